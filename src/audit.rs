@@ -0,0 +1,231 @@
+//! An append-only, tamper-evident audit log of the irreversible operations (flashing, eFuse burning)
+//! done on the PCB being provisioned
+//!
+//! Each entry contains the hash of the previous entry, so that removing, re-ordering or modifying entries
+//! breaks the chain. The head of the chain (the number of entries and the hash of the last one) is recorded
+//! in the summary of the PCB logs, so that dropping entries from the end is detected too.
+//! Optionally, the hash of each entry and the head are also signed with an Ed25519 station key.
+
+use core::fmt;
+
+use anyhow::Context;
+
+use ed25519_dalek::{Signer, SigningKey};
+
+use serde::Serialize;
+
+use zeroize::Zeroizing;
+
+use crate::utils::hash::sha256_hex;
+use crate::utils::hex;
+use crate::Locale;
+
+/// The `prev_hash` of the first entry in the chain
+const GENESIS_HASH: &str = "0000000000000000000000000000000000000000000000000000000000000000";
+
+/// An entry in the audit log
+#[derive(Clone, Debug, Serialize)]
+pub struct AuditEntry {
+    /// The sequence number of the entry, starting from 0
+    pub seq: usize,
+    /// The UTC date and time of the operation
+    pub timestamp: String,
+    /// The operation (e.g. `flash`, `burn-keys`)
+    pub operation: String,
+    /// The SHA-256 hash of the operation parameters
+    pub params_hash: String,
+    /// The result of the operation (`ok` or `error: <message>`)
+    pub result: String,
+    /// The hash of the previous entry
+    pub prev_hash: String,
+    /// The SHA-256 hash of this entry, computed over the JSON encoding of all of the above fields
+    pub hash: String,
+    /// The hex-encoded Ed25519 signature of `hash`, if a station key is configured
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub signature: Option<String>,
+}
+
+/// The fields of an entry covered by its hash
+#[derive(Serialize)]
+struct HashedEntry<'a> {
+    seq: usize,
+    timestamp: &'a str,
+    operation: &'a str,
+    params_hash: &'a str,
+    result: &'a str,
+    prev_hash: &'a str,
+}
+
+/// The head of the chain, covered by the signature of the head
+#[derive(Serialize)]
+struct HashedHead<'a> {
+    count: usize,
+    hash: &'a str,
+}
+
+/// The audit log of the PCB being provisioned
+pub struct AuditLog {
+    entries: Vec<AuditEntry>,
+    key: Option<SigningKey>,
+}
+
+impl AuditLog {
+    /// Create a new, empty audit log without a station key
+    pub const fn new() -> Self {
+        Self {
+            entries: Vec::new(),
+            key: None,
+        }
+    }
+
+    /// Set the station key used for signing the entries
+    pub fn set_key(&mut self, key: Option<SigningKey>) {
+        self.key = key;
+    }
+
+    /// Clear the audit log
+    ///
+    /// To be called when a new PCB is to be provisioned
+    pub fn clear(&mut self) {
+        self.entries.clear();
+    }
+
+    /// Append an entry for the given operation to the audit log
+    ///
+    /// # Arguments
+    /// - `operation` - the name of the operation
+    /// - `params` - the parameters of the operation; only their hash is recorded
+    /// - `result` - the result of the operation
+    pub fn record<T>(&mut self, operation: &str, params: &str, result: &anyhow::Result<T>) {
+        let seq = self.entries.len();
+        let prev_hash = self
+            .entries
+            .last()
+            .map(|entry| entry.hash.clone())
+            .unwrap_or_else(|| GENESIS_HASH.to_string());
+
+        let timestamp = Locale::ISO.format_now();
+        let params_hash = sha256_hex(params);
+        let result = match result {
+            Ok(_) => "ok".to_string(),
+            Err(err) => format!("error: {err}"),
+        };
+
+        // The JSON encoding delimits the fields unambiguously, whatever text the operation or the result contain
+        let hash = sha256_hex(
+            serde_json::to_string(&HashedEntry {
+                seq,
+                timestamp: &timestamp,
+                operation,
+                params_hash: &params_hash,
+                result: &result,
+                prev_hash: &prev_hash,
+            })
+            .unwrap(),
+        );
+
+        let signature = self.sign(&hash);
+
+        self.entries.push(AuditEntry {
+            seq,
+            timestamp,
+            operation: operation.to_string(),
+            params_hash,
+            result,
+            prev_hash,
+            hash,
+            signature,
+        });
+    }
+
+    /// Return the head of the chain - the number of entries and the hash of the last entry - as PCB summary entries,
+    /// so that truncating the audit log is detected too
+    ///
+    /// If a station key is configured, the head is signed as well
+    pub fn head(&self) -> Vec<(String, String)> {
+        let count = self.entries.len();
+        let hash = self
+            .entries
+            .last()
+            .map(|entry| entry.hash.as_str())
+            .unwrap_or(GENESIS_HASH);
+
+        let mut head = vec![
+            ("Audit Entries".to_string(), count.to_string()),
+            ("Audit Head".to_string(), hash.to_string()),
+        ];
+
+        let signed = sha256_hex(serde_json::to_string(&HashedHead { count, hash }).unwrap());
+
+        if let Some(signature) = self.sign(&signed) {
+            head.push(("Audit Head Signature".to_string(), signature));
+        }
+
+        head
+    }
+
+    /// Sign the given hash with the station key, if one is configured
+    fn sign(&self, hash: &str) -> Option<String> {
+        self.key
+            .as_ref()
+            .map(|key| hex::encode(key.sign(hash.as_bytes()).to_bytes()))
+    }
+
+    /// Render the audit log as JSON lines (one entry per line)
+    pub fn to_json_lines(&self) -> anyhow::Result<String> {
+        let mut lines = String::new();
+
+        for entry in &self.entries {
+            lines.push_str(&serde_json::to_string(entry)?);
+            lines.push('\n');
+        }
+
+        Ok(lines)
+    }
+}
+
+impl Default for AuditLog {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for AuditLog {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("AuditLog")
+            .field("entries", &self.entries)
+            .field("signed", &self.key.is_some())
+            .finish()
+    }
+}
+
+/// Load an Ed25519 station key from a file
+///
+/// The file should contain the 32-byte secret key, either raw or hex-encoded
+pub fn load_key(path: &str) -> anyhow::Result<SigningKey> {
+    let data = Zeroizing::new(
+        std::fs::read(path).with_context(|| format!("Reading the station key `{path}` failed"))?,
+    );
+
+    let bytes = if data.len() == 32 {
+        data
+    } else {
+        let encoded = core::str::from_utf8(&data)
+            .ok()
+            .map(str::trim)
+            .filter(|encoded| encoded.len() == 64)
+            .ok_or_else(|| anyhow::anyhow!("Station key `{path}` is not a 32-byte Ed25519 key"))?;
+
+        Zeroizing::new(
+            hex::decode(encoded)
+                .with_context(|| format!("Station key `{path}` is not a valid hex string"))?,
+        )
+    };
+
+    let bytes: &[u8; 32] = bytes
+        .as_slice()
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("Station key `{path}` is not a 32-byte Ed25519 key"))?;
+
+    Ok(SigningKey::from_bytes(bytes))
+}
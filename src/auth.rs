@@ -0,0 +1,149 @@
+//! Authorization of the requests of the HTTP(S) bundle loaders and logs uploaders
+
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use log::info;
+
+use serde::{Deserialize, Serialize};
+
+use crate::Error;
+
+/// Renew the OAuth2 access token that long before it expires, so that a request does not race its expiration
+const TOKEN_EXPIRY_MARGIN: Duration = Duration::from_secs(60);
+
+/// The lifetime assumed for OAuth2 access tokens whose token response does not specify one
+const DEFAULT_TOKEN_LIFETIME: Duration = Duration::from_secs(3600);
+
+/// The settings of the OAuth2 client credentials flow
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct OAuth2 {
+    /// The URL of the token endpoint of the identity provider
+    pub token_url: String,
+    /// The client ID
+    pub client_id: String,
+    /// The client secret
+    pub client_secret: String,
+    /// An optional (space-separated) scope of the requested access token
+    #[serde(default)]
+    pub scope: Option<String>,
+}
+
+/// The authorization of the HTTP(S) requests
+///
+/// Cloning shares the cached OAuth2 access token (if any), so that all loaders and uploaders
+/// cloned from the same `HttpAuth` fetch a new token only once
+#[derive(Debug, Clone)]
+pub enum HttpAuth {
+    /// A static value of the `Authorization` header (e.g. `Bearer <token>`)
+    Header(String),
+    /// An access token obtained with the OAuth2 client credentials flow, and renewed automatically before it expires
+    OAuth2(OAuth2Client),
+}
+
+impl HttpAuth {
+    /// Create an `HttpAuth` obtaining its access tokens with the OAuth2 client credentials flow
+    pub fn oauth2(conf: OAuth2) -> Self {
+        Self::OAuth2(OAuth2Client::new(conf))
+    }
+
+    /// Return the value of the `Authorization` header, fetching a new OAuth2 access token if necessary
+    pub async fn header(&self) -> Result<String, Error> {
+        match self {
+            Self::Header(header) => Ok(header.clone()),
+            Self::OAuth2(client) => client.header().await,
+        }
+    }
+}
+
+/// An OAuth2 client credentials flow client, with a cached access token
+#[derive(Debug, Clone)]
+pub struct OAuth2Client {
+    conf: OAuth2,
+    token: Arc<Mutex<Option<(String, Instant)>>>,
+}
+
+impl OAuth2Client {
+    /// Create a new client with the given settings
+    pub fn new(conf: OAuth2) -> Self {
+        Self {
+            conf,
+            token: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Return the value of the `Authorization` header, fetching a new access token if there is none yet,
+    /// or if the current one is about to expire
+    pub async fn header(&self) -> Result<String, Error> {
+        {
+            let token = self.token.lock().unwrap();
+
+            if let Some((token, expires)) = token.as_ref() {
+                if Instant::now() + TOKEN_EXPIRY_MARGIN < *expires {
+                    return Ok(format!("Bearer {token}"));
+                }
+            }
+        }
+
+        let (token, lifetime) = self.fetch().await?;
+
+        *self.token.lock().unwrap() = Some((token.clone(), Instant::now() + lifetime));
+
+        Ok(format!("Bearer {token}"))
+    }
+
+    /// Fetch a new access token from the token endpoint
+    async fn fetch(&self) -> anyhow::Result<(String, Duration)> {
+        info!(
+            "About to fetch an OAuth2 access token from URL `{}`...",
+            self.conf.token_url
+        );
+
+        let mut form = vec![
+            ("grant_type", "client_credentials"),
+            ("client_id", self.conf.client_id.as_str()),
+            ("client_secret", self.conf.client_secret.as_str()),
+        ];
+
+        if let Some(scope) = self.conf.scope.as_deref() {
+            form.push(("scope", scope));
+        }
+
+        let response = reqwest::Client::new()
+            .post(&self.conf.token_url)
+            .form(&form)
+            .send()
+            .await
+            .context("Token request failed")?
+            .error_for_status()
+            .context("Token request returned an error status")?
+            .text()
+            .await
+            .context("Reading the token response failed")?;
+
+        let response: TokenResponse =
+            serde_json::from_str(&response).context("Parsing the token response failed")?;
+
+        let lifetime = response
+            .expires_in
+            .map(Duration::from_secs)
+            .unwrap_or(DEFAULT_TOKEN_LIFETIME);
+
+        info!(
+            "OAuth2 access token fetched, valid for {}s",
+            lifetime.as_secs()
+        );
+
+        Ok((response.access_token, lifetime))
+    }
+}
+
+/// The (successful) response of an OAuth2 token endpoint
+#[derive(Debug, Deserialize)]
+struct TokenResponse {
+    access_token: String,
+    #[serde(default)]
+    expires_in: Option<u64>,
+}
@@ -0,0 +1,711 @@
+//! The provisioning backends, i.e. the means by which the chip being provisioned is flashed and its eFuses are burned
+//!
+//! - `Native` - flashing with the native flasher (`espflash`) over the serial port
+//! - `Esptool` - flashing with `esptool.py` over the serial port
+//! - `Jtag` - flashing with `probe-rs` or OpenOCD over JTAG
+//! - `Simulated` - a simulated device, without any hardware (see `Config::simulate`)
+//!
+//! With all hardware backends the eFuses are read and burned with `espefuse.py`, and the app is run and monitored
+//! over the serial port
+
+use std::collections::{BTreeMap, HashMap};
+use std::io::Write;
+use std::sync::atomic::AtomicBool;
+use std::sync::{mpsc, Arc};
+
+use espflash::cli::monitor::LogFormat;
+use espflash::flasher::{FlashSize, ProgressCallbacks};
+
+use log::info;
+
+use crate::bundle::{Chip, FlashData};
+use crate::efuse::{self, EfuseBurn, EfuseValue, KeyProtection};
+use crate::flash::{self, ChipInfo};
+use crate::jtag;
+use crate::monitor;
+use crate::permissions::ToolRunner;
+use crate::simulate::Simulated;
+use crate::{Config, FlashJtag};
+
+/// The serial connection to the chip, shared by all backends
+#[derive(Clone, Debug)]
+pub struct Connection {
+    /// The serial port; if not provided, the port is auto-detected
+    pub port: Option<String>,
+    /// Whether to use the flasher stub
+    pub use_stub: bool,
+    /// The speed of the serial port when flashing; if not provided, the default speed is used
+    pub speed: Option<u32>,
+    /// The speed of the serial port when reading and burning the eFuses; if not provided, the default speed is used
+    pub efuse_baud: Option<String>,
+    /// The runner of the tools connecting over the serial port
+    pub tools: Arc<ToolRunner>,
+}
+
+impl Connection {
+    /// Create the serial connection to the chip from the factory configuration
+    pub fn new(conf: &Config, tools: Arc<ToolRunner>) -> Self {
+        Self {
+            port: conf.port.clone(),
+            use_stub: !conf.flash_no_stub,
+            speed: conf.flash_speed,
+            efuse_baud: conf.efuse_speed.map(|speed| speed.to_string()),
+            tools,
+        }
+    }
+}
+
+/// A provisioning backend
+///
+/// The `Task` talks to the chip only via its backend, so that it can be run against a simulated (or a fake) chip
+pub trait ProvisioningBackend {
+    /// The name of the backend, for logging purposes
+    fn name(&self) -> &'static str;
+
+    /// The serial connection to the chip
+    fn connection(&self) -> &Connection;
+
+    /// Return `true` if the backend flashes with external tools (`esptool.py` or the JTAG tools),
+    /// rather than with the native flasher
+    fn tools(&self) -> bool;
+
+    /// Update the backend for the configuration in effect for the PCB being provisioned,
+    /// i.e. with the configuration overrides applied
+    fn configure(&mut self, _conf: &Config) {}
+
+    /// Connect to the chip, check that it is the expected one and detect its silicon revision and flash size
+    ///
+    /// Returns `None` if the backend cannot detect the chip
+    fn connect(&self, chip: Chip) -> anyhow::Result<Option<ChipInfo>>;
+
+    /// Erase the whole flash of the chip
+    fn erase(&self, chip: Chip, flash_size: Option<FlashSize>, dry_run: bool)
+        -> anyhow::Result<()>;
+
+    /// Write the images to the flash of the chip
+    ///
+    /// # Arguments
+    /// - `chip` - the chip being flashed
+    /// - `flash_size` - the flash size of the chip, if known
+    /// - `flash_data` - the images to be flashed
+    /// - `diff` - whether to only flash the images which differ from the flash content, if supported by the backend
+    /// - `dry_run` - if `true`, the flashing is skipped
+    /// - `progress` - the progress callbacks
+    ///
+    /// Returns the MD5 checksums of the flashed images as computed by the chip, by image offset,
+    /// if the backend verifies the flashing (see `Config::flash_verify`); empty otherwise
+    fn write<P>(
+        &self,
+        chip: Chip,
+        flash_size: Option<FlashSize>,
+        flash_data: impl IntoIterator<Item = anyhow::Result<FlashData>>,
+        diff: bool,
+        dry_run: bool,
+        progress: &mut P,
+    ) -> anyhow::Result<Vec<(u32, u128)>>
+    where
+        P: ProgressCallbacks + Send + Sync + 'static;
+
+    /// Read a region of the flash of the chip
+    fn read(&self, chip: Chip, offset: u32, size: u32) -> anyhow::Result<Vec<u8>> {
+        let connection = self.connection();
+
+        flash::read_flash_esptool(
+            &connection.tools,
+            connection.port.as_deref(),
+            chip,
+            connection.use_stub,
+            connection.speed,
+            offset,
+            size,
+        )
+    }
+
+    /// Read the given eFuse values of the chip, or all values if `values` is empty
+    ///
+    /// The values come from the eFuse summary cached for the provisioning cycle, if any (see `efuse::cached_summary`)
+    fn read_efuses<'a, I>(
+        &self,
+        chip: Option<Chip>,
+        values: I,
+    ) -> anyhow::Result<HashMap<String, EfuseValue>>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        let connection = self.connection();
+
+        efuse::cached_summary(
+            &connection.tools,
+            chip,
+            connection.port.as_deref(),
+            connection.efuse_baud.as_deref(),
+            values,
+        )
+    }
+
+    /// Get the full eFuse summary of the chip as pretty-printed JSON, in the format of `espefuse summary --format json`,
+    /// with the eFuses sorted by name
+    fn efuse_summary_json(&self, chip: Chip) -> anyhow::Result<String> {
+        let summary = self
+            .read_efuses(Some(chip), core::iter::empty())?
+            .into_iter()
+            .collect::<BTreeMap<_, _>>();
+
+        Ok(serde_json::to_string_pretty(&summary)?)
+    }
+
+    /// Burn the given eFuse values, as (eFuse, value) pairs
+    fn burn_efuses<'a, I>(&self, chip: Chip, dry_run: bool, values: I) -> anyhow::Result<String>
+    where
+        I: Iterator<Item = (&'a str, u32)>,
+    {
+        let connection = self.connection();
+
+        efuse::burn_efuses(
+            &connection.tools,
+            chip,
+            connection.port.as_deref(),
+            connection.efuse_baud.as_deref(),
+            dry_run,
+            values,
+        )
+    }
+
+    /// Burn the given keys, as (block, key, purpose) triples
+    fn burn_keys<'a, I>(
+        &self,
+        protection: KeyProtection,
+        chip: Chip,
+        dry_run: bool,
+        values: I,
+    ) -> anyhow::Result<String>
+    where
+        I: Iterator<Item = (&'a str, &'a [u8], &'a str)>,
+    {
+        let connection = self.connection();
+
+        efuse::burn_keys(
+            &connection.tools,
+            protection,
+            chip,
+            connection.port.as_deref(),
+            connection.efuse_baud.as_deref(),
+            dry_run,
+            values,
+        )
+    }
+
+    /// Burn the given key digests, as (block, key, purpose) triples
+    fn burn_key_digests<'a, I>(
+        &self,
+        protection: KeyProtection,
+        chip: Chip,
+        dry_run: bool,
+        values: I,
+    ) -> anyhow::Result<String>
+    where
+        I: Iterator<Item = (&'a str, &'a [u8], &'a str)>,
+    {
+        let connection = self.connection();
+
+        efuse::burn_key_digests(
+            &connection.tools,
+            protection,
+            chip,
+            connection.port.as_deref(),
+            connection.efuse_baud.as_deref(),
+            dry_run,
+            values,
+        )
+    }
+
+    /// Burn a batch of keys, key digests, the custom MAC and eFuse params at once
+    fn burn_batch(
+        &self,
+        chip: Chip,
+        dry_run: bool,
+        burns: &[EfuseBurn<'_, [u8]>],
+    ) -> anyhow::Result<String> {
+        let connection = self.connection();
+
+        efuse::burn_batch(
+            &connection.tools,
+            chip,
+            connection.port.as_deref(),
+            connection.efuse_baud.as_deref(),
+            dry_run,
+            burns,
+        )
+    }
+
+    /// Burn the custom MAC address
+    fn burn_custom_mac(&self, chip: Chip, dry_run: bool, mac: &[u8; 6]) -> anyhow::Result<String> {
+        let connection = self.connection();
+
+        efuse::burn_custom_mac(
+            &connection.tools,
+            chip,
+            connection.port.as_deref(),
+            connection.efuse_baud.as_deref(),
+            dry_run,
+            mac,
+        )
+    }
+
+    /// Reset the chip so that it runs the flashed app
+    fn run_app(&self, chip: Chip) -> anyhow::Result<()> {
+        let connection = self.connection();
+
+        flash::run_app_esptool(
+            &connection.tools,
+            connection.port.as_deref(),
+            chip,
+            connection.use_stub,
+            connection.speed,
+        )
+    }
+
+    /// Monitor the app running on the chip until `stop` is raised (see `monitor::monitor`)
+    #[allow(clippy::too_many_arguments)]
+    fn monitor<W>(
+        &self,
+        elf: Option<&[u8]>,
+        baud: u32,
+        log_format: LogFormat,
+        stop: Arc<AtomicBool>,
+        input: Option<mpsc::Receiver<Vec<u8>>>,
+        out: W,
+    ) -> anyhow::Result<()>
+    where
+        W: Write,
+    {
+        monitor::monitor(
+            self.connection().port.as_deref(),
+            elf,
+            baud,
+            log_format,
+            false,
+            stop,
+            input,
+            out,
+        )
+    }
+}
+
+/// Flashing with the native flasher (`espflash`) over the serial port
+#[derive(Clone, Debug)]
+pub struct Native {
+    pub connection: Connection,
+    /// Whether to read back the MD5 checksums of the flashed images (see `Config::flash_verify`)
+    pub verify: bool,
+}
+
+impl ProvisioningBackend for Native {
+    fn name(&self) -> &'static str {
+        "native"
+    }
+
+    fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    fn tools(&self) -> bool {
+        false
+    }
+
+    fn connect(&self, chip: Chip) -> anyhow::Result<Option<ChipInfo>> {
+        let connection = &self.connection;
+
+        flash::detect(
+            &connection.tools,
+            connection.port.as_deref(),
+            chip,
+            connection.use_stub,
+            connection.speed,
+        )
+        .map(Some)
+    }
+
+    fn erase(
+        &self,
+        chip: Chip,
+        flash_size: Option<FlashSize>,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        let connection = &self.connection;
+
+        flash::erase(
+            &connection.tools,
+            connection.port.as_deref(),
+            chip,
+            connection.use_stub,
+            connection.speed,
+            flash_size,
+            dry_run,
+        )
+    }
+
+    fn write<P>(
+        &self,
+        chip: Chip,
+        flash_size: Option<FlashSize>,
+        flash_data: impl IntoIterator<Item = anyhow::Result<FlashData>>,
+        diff: bool,
+        dry_run: bool,
+        progress: &mut P,
+    ) -> anyhow::Result<Vec<(u32, u128)>>
+    where
+        P: ProgressCallbacks + Send + Sync + 'static,
+    {
+        let connection = &self.connection;
+
+        flash::flash(
+            &connection.tools,
+            connection.port.as_deref(),
+            chip,
+            connection.use_stub,
+            connection.speed,
+            flash_size,
+            flash_data,
+            diff,
+            self.verify,
+            dry_run,
+            progress,
+        )
+    }
+}
+
+/// Flashing with `esptool.py` over the serial port
+#[derive(Clone, Debug)]
+pub struct Esptool {
+    pub connection: Connection,
+    /// Whether to compress the images (`--compress` / `--no-compress`); if not provided, the `esptool.py` default is used
+    pub compress: Option<bool>,
+}
+
+impl ProvisioningBackend for Esptool {
+    fn name(&self) -> &'static str {
+        "esptool.py"
+    }
+
+    fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    fn tools(&self) -> bool {
+        true
+    }
+
+    fn connect(&self, chip: Chip) -> anyhow::Result<Option<ChipInfo>> {
+        let connection = &self.connection;
+
+        flash::detect_esptool(
+            &connection.tools,
+            connection.port.as_deref(),
+            chip,
+            connection.use_stub,
+            connection.speed,
+        )
+        .map(Some)
+    }
+
+    fn erase(
+        &self,
+        chip: Chip,
+        flash_size: Option<FlashSize>,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        let connection = &self.connection;
+
+        flash::erase_esptool(
+            &connection.tools,
+            connection.port.as_deref(),
+            chip,
+            connection.use_stub,
+            connection.speed,
+            flash_size,
+            dry_run,
+        )
+    }
+
+    fn write<P>(
+        &self,
+        chip: Chip,
+        flash_size: Option<FlashSize>,
+        flash_data: impl IntoIterator<Item = anyhow::Result<FlashData>>,
+        _diff: bool,
+        dry_run: bool,
+        progress: &mut P,
+    ) -> anyhow::Result<Vec<(u32, u128)>>
+    where
+        P: ProgressCallbacks + Send + Sync + 'static,
+    {
+        let connection = &self.connection;
+
+        flash::flash_esptool(
+            &connection.tools,
+            connection.port.as_deref(),
+            chip,
+            connection.use_stub,
+            connection.speed,
+            flash_size,
+            self.compress,
+            flash_data,
+            dry_run,
+            progress,
+        )?;
+
+        Ok(Vec::new())
+    }
+}
+
+/// Flashing with `probe-rs` or OpenOCD over JTAG
+///
+/// The flash content can still be read, and the app is still run and monitored, over the serial port
+#[derive(Clone, Debug)]
+pub struct Jtag {
+    pub connection: Connection,
+    /// The JTAG flasher
+    pub jtag: FlashJtag,
+}
+
+impl ProvisioningBackend for Jtag {
+    fn name(&self) -> &'static str {
+        "JTAG"
+    }
+
+    fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    fn tools(&self) -> bool {
+        true
+    }
+
+    fn connect(&self, _chip: Chip) -> anyhow::Result<Option<ChipInfo>> {
+        info!("Flashing over JTAG, chip detection skipped");
+
+        Ok(None)
+    }
+
+    fn erase(
+        &self,
+        chip: Chip,
+        _flash_size: Option<FlashSize>,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        jtag::erase(&self.connection.tools, &self.jtag, chip, dry_run)
+    }
+
+    fn write<P>(
+        &self,
+        chip: Chip,
+        _flash_size: Option<FlashSize>,
+        flash_data: impl IntoIterator<Item = anyhow::Result<FlashData>>,
+        _diff: bool,
+        dry_run: bool,
+        progress: &mut P,
+    ) -> anyhow::Result<Vec<(u32, u128)>>
+    where
+        P: ProgressCallbacks + Send + Sync + 'static,
+    {
+        jtag::flash(
+            &self.connection.tools,
+            &self.jtag,
+            chip,
+            flash_data,
+            dry_run,
+            progress,
+        )?;
+
+        Ok(Vec::new())
+    }
+}
+
+/// The provisioning backend selected by the factory configuration
+#[derive(Clone, Debug)]
+pub enum Backend {
+    Native(Native),
+    Esptool(Esptool),
+    Jtag(Jtag),
+    Simulated(Simulated),
+}
+
+impl Backend {
+    /// Select the provisioning backend from the factory configuration
+    ///
+    /// The simulated device (`simulate`) takes precedence over JTAG (`flash_jtag`), which takes precedence
+    /// over `esptool.py` (`flash_esptool`), which takes precedence over the native flasher
+    pub fn new(conf: &Config, tools: Arc<ToolRunner>) -> anyhow::Result<Self> {
+        if let Some(simulation) = conf.simulate.as_ref() {
+            Ok(Self::Simulated(Simulated::new(
+                simulation,
+                Connection::new(conf, tools),
+            )?))
+        } else {
+            Ok(Self::select(conf, tools))
+        }
+    }
+
+    /// Select one of the hardware backends from the factory configuration
+    fn select(conf: &Config, tools: Arc<ToolRunner>) -> Self {
+        let connection = Connection::new(conf, tools);
+
+        if let Some(jtag) = conf.flash_jtag.clone() {
+            Self::Jtag(Jtag { connection, jtag })
+        } else if conf.flash_esptool {
+            Self::Esptool(Esptool {
+                connection,
+                compress: conf.flash_compressed(),
+            })
+        } else {
+            Self::Native(Native {
+                connection,
+                verify: conf.flash_verify,
+            })
+        }
+    }
+}
+
+/// Dispatch a method call to the selected backend
+macro_rules! dispatch {
+    ($self:ident, $backend:ident => $call:expr) => {
+        match $self {
+            Self::Native($backend) => $call,
+            Self::Esptool($backend) => $call,
+            Self::Jtag($backend) => $call,
+            Self::Simulated($backend) => $call,
+        }
+    };
+}
+
+impl ProvisioningBackend for Backend {
+    fn name(&self) -> &'static str {
+        dispatch!(self, backend => backend.name())
+    }
+
+    fn connection(&self) -> &Connection {
+        dispatch!(self, backend => backend.connection())
+    }
+
+    fn tools(&self) -> bool {
+        dispatch!(self, backend => backend.tools())
+    }
+
+    fn configure(&mut self, conf: &Config) {
+        // The device stays simulated for the whole session
+        if let Self::Simulated(backend) = self {
+            backend.configure(conf);
+        } else {
+            *self = Self::select(conf, self.connection().tools.clone());
+        }
+    }
+
+    fn connect(&self, chip: Chip) -> anyhow::Result<Option<ChipInfo>> {
+        dispatch!(self, backend => backend.connect(chip))
+    }
+
+    fn erase(
+        &self,
+        chip: Chip,
+        flash_size: Option<FlashSize>,
+        dry_run: bool,
+    ) -> anyhow::Result<()> {
+        dispatch!(self, backend => backend.erase(chip, flash_size, dry_run))
+    }
+
+    fn write<P>(
+        &self,
+        chip: Chip,
+        flash_size: Option<FlashSize>,
+        flash_data: impl IntoIterator<Item = anyhow::Result<FlashData>>,
+        diff: bool,
+        dry_run: bool,
+        progress: &mut P,
+    ) -> anyhow::Result<Vec<(u32, u128)>>
+    where
+        P: ProgressCallbacks + Send + Sync + 'static,
+    {
+        dispatch!(self, backend => backend.write(chip, flash_size, flash_data, diff, dry_run, progress))
+    }
+
+    fn read(&self, chip: Chip, offset: u32, size: u32) -> anyhow::Result<Vec<u8>> {
+        dispatch!(self, backend => backend.read(chip, offset, size))
+    }
+
+    fn read_efuses<'a, I>(
+        &self,
+        chip: Option<Chip>,
+        values: I,
+    ) -> anyhow::Result<HashMap<String, EfuseValue>>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        dispatch!(self, backend => backend.read_efuses(chip, values))
+    }
+
+    fn burn_efuses<'a, I>(&self, chip: Chip, dry_run: bool, values: I) -> anyhow::Result<String>
+    where
+        I: Iterator<Item = (&'a str, u32)>,
+    {
+        dispatch!(self, backend => backend.burn_efuses(chip, dry_run, values))
+    }
+
+    fn burn_keys<'a, I>(
+        &self,
+        protection: KeyProtection,
+        chip: Chip,
+        dry_run: bool,
+        values: I,
+    ) -> anyhow::Result<String>
+    where
+        I: Iterator<Item = (&'a str, &'a [u8], &'a str)>,
+    {
+        dispatch!(self, backend => backend.burn_keys(protection, chip, dry_run, values))
+    }
+
+    fn burn_key_digests<'a, I>(
+        &self,
+        protection: KeyProtection,
+        chip: Chip,
+        dry_run: bool,
+        values: I,
+    ) -> anyhow::Result<String>
+    where
+        I: Iterator<Item = (&'a str, &'a [u8], &'a str)>,
+    {
+        dispatch!(self, backend => backend.burn_key_digests(protection, chip, dry_run, values))
+    }
+
+    fn burn_batch(
+        &self,
+        chip: Chip,
+        dry_run: bool,
+        burns: &[EfuseBurn<'_, [u8]>],
+    ) -> anyhow::Result<String> {
+        dispatch!(self, backend => backend.burn_batch(chip, dry_run, burns))
+    }
+
+    fn burn_custom_mac(&self, chip: Chip, dry_run: bool, mac: &[u8; 6]) -> anyhow::Result<String> {
+        dispatch!(self, backend => backend.burn_custom_mac(chip, dry_run, mac))
+    }
+
+    fn run_app(&self, chip: Chip) -> anyhow::Result<()> {
+        dispatch!(self, backend => backend.run_app(chip))
+    }
+
+    fn monitor<W>(
+        &self,
+        elf: Option<&[u8]>,
+        baud: u32,
+        log_format: LogFormat,
+        stop: Arc<AtomicBool>,
+        input: Option<mpsc::Receiver<Vec<u8>>>,
+        out: W,
+    ) -> anyhow::Result<()>
+    where
+        W: Write,
+    {
+        dispatch!(self, backend => backend.monitor(elf, baud, log_format, stop, input, out))
+    }
+}
@@ -3,23 +3,28 @@ use core::iter::once;
 
 use std::collections::hash_map::Entry;
 use std::collections::HashMap;
-use std::io::{Read, Seek};
+use std::io::{self, Cursor, Read, Seek};
 
 use alloc::string::String;
 use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use anyhow::Context;
-use esp_idf_part::{Partition, PartitionTable, SubType, Type};
+use esp_idf_part::{AppType, DataType, Partition, PartitionTable, SubType, Type};
 
 use espflash::flasher::FlashSize;
 use log::{info, warn};
+use md5::{Digest, Md5};
 use serde::Deserialize;
 
-use zip::ZipArchive;
+use zip::write::SimpleFileOptions;
+use zip::{CompressionMethod, ZipArchive, ZipWriter};
 
 use crate::flash::{self, empty_space};
 use crate::loader::BundleType;
+use crate::utils::hex;
+use crate::utils::secret::Secret;
+use crate::ConfigOverride;
 
 extern crate alloc;
 
@@ -34,6 +39,11 @@ pub struct Bundle {
     pub parts_mapping: Vec<PartitionMapping>,
     /// The mapping of efuses to efuse regions
     pub efuse_mapping: Vec<EfuseMapping>,
+    /// An optional override of the factory configuration for this bundle
+    pub config_override: Option<ConfigOverride>,
+    /// The alternative partition tables shipped with the bundle (`partition-table-<variant>.csv`),
+    /// as (variant, CSV) pairs, sorted by variant
+    pub part_table_variants: Vec<(String, String)>,
 }
 
 impl Bundle {
@@ -53,6 +63,16 @@ impl Bundle {
     const BOOTLOADER_FILE_NAME: &str = "bootloader.bin";
     /// The name of the partition table file when loaded from a ZIP bundle (.bundle)
     const PART_TABLE_FILE_NAME: &str = "partition-table.csv";
+    /// The prefix of the alternative partition table files when loaded from a ZIP bundle (.bundle)
+    const PART_TABLE_VARIANT_PREFIX: &str = "partition-table-";
+    /// The suffix of the alternative partition table files when loaded from a ZIP bundle (.bundle)
+    const PART_TABLE_VARIANT_SUFFIX: &str = ".csv";
+    /// The name of the configuration override file when loaded from a ZIP bundle (.bundle)
+    const CONFIG_OVERRIDE_FILE_NAME: &str = "config-override.toml";
+    /// The name of the eFuse plan file when loaded from a ZIP bundle (.bundle)
+    const EFUSE_PLAN_FILE_NAME: &str = "efuse-plan.json";
+    /// The name of the eFuse manifest file when loaded from a ZIP bundle (.bundle)
+    const EFUSE_MANIFEST_FILE_NAME: &str = "efuses.toml";
 
     /// The suffix of the binary image files when loaded from a ZIP bundle (.bundle)
     const BIN_SUFFIX: &str = ".bin";
@@ -87,14 +107,21 @@ otadata,  data, ota,             ,    8K,
 extra_1,  data, 0x06,            ,   20K,
 "#;
 
+    /// The offset of the first app partition in the generated app partition tables
+    const APP_PART_TABLE_START_KB: u32 = 64;
+    /// The total size of the data partitions following each app partition in the generated app partition tables
+    const APP_PART_TABLE_DATA_KB: u32 = 60;
+
     /// Create a new `Bundle` from a bundle content
     ///
     /// # Arguments
     /// - `name`: The name of the bundle
     ///   Used to identify the type of the provided `bundle_content` by examining the suffix in the name as
-    ///   well as for display purposes
+    ///   well as for display purposes. The container of a real bundle (ZIP, or TAR compressed with gzip or zstd)
+    ///   is additionally detected by its magic bytes, so that a misnamed bundle is still loaded
     /// - `default_params`: The default parameters to use when the parameters are not provided in the bundle
-    /// - `bundle_content`: The content of the bundle (a ZIP archive, a binary image, or an ELF image)
+    /// - `bundle_content`: The content of the bundle (a ZIP archive, a gzip/zstd-compressed TAR archive, a binary image,
+    ///   or an ELF image)
     /// - `supply_default_part_table`: Whether to supply the default partition table if the partition table is not provided in the bundle
     /// - `supply_default_bootloader`: Whether to supply the default bootloader if the bootloader is not provided in the bundle
     pub fn create<R>(
@@ -113,6 +140,12 @@ extra_1,  data, 0x06,            ,   20K,
                 anyhow::anyhow!("Bundle name `{}` does not end with a known suffix", name)
             })?;
 
+        let bundle_type = if bundle_type.is_complete() {
+            Self::detect_container(&mut bundle_content)?.unwrap_or(bundle_type)
+        } else {
+            bundle_type
+        };
+
         match bundle_type {
             BundleType::Complete => {
                 info!("Bundle `{name}` is a ZIP file");
@@ -123,6 +156,27 @@ extra_1,  data, 0x06,            ,   20K,
                     supply_default_bootloader,
                 )
             }
+            BundleType::CompleteTarGz => {
+                info!("Bundle `{name}` is a gzip-compressed TAR file");
+                Self::from_zip_bundle(
+                    name,
+                    &mut zip_from_tar(flate2::read::GzDecoder::new(bundle_content))?,
+                    supply_default_part_table,
+                    supply_default_bootloader,
+                )
+            }
+            BundleType::CompleteTarZst => {
+                info!("Bundle `{name}` is a zstd-compressed TAR file");
+                Self::from_zip_bundle(
+                    name,
+                    &mut zip_from_tar(
+                        zstd::stream::read::Decoder::new(bundle_content)
+                            .context("Decompressing the bundle failed")?,
+                    )?,
+                    supply_default_part_table,
+                    supply_default_bootloader,
+                )
+            }
             BundleType::BinAppImage => {
                 info!("Bundle `{name}` is a binary App image");
                 let mut bytes = Vec::new();
@@ -152,6 +206,25 @@ extra_1,  data, 0x06,            ,   20K,
         }
     }
 
+    /// Detect the container of a real bundle by its magic bytes
+    ///
+    /// Return `None` if the container is not recognized
+    fn detect_container<R>(bundle_content: &mut R) -> anyhow::Result<Option<BundleType>>
+    where
+        R: Read + Seek,
+    {
+        let mut magic = Vec::with_capacity(4);
+        bundle_content.by_ref().take(4).read_to_end(&mut magic)?;
+        bundle_content.rewind()?;
+
+        Ok(match magic.as_slice() {
+            [0x50, 0x4b, 0x03, 0x04] => Some(BundleType::Complete),
+            [0x1f, 0x8b, ..] => Some(BundleType::CompleteTarGz),
+            [0x28, 0xb5, 0x2f, 0xfd] => Some(BundleType::CompleteTarZst),
+            _ => None,
+        })
+    }
+
     /// Create a new `Bundle` from an ELF application image
     ///
     /// # Arguments
@@ -169,13 +242,20 @@ extra_1,  data, 0x06,            ,   20K,
     ) -> anyhow::Result<Self> {
         info!("About to prep the ELF App image bundle `{name}`");
 
-        let app_image =
-            Image::new_elf("ota_1".to_string(), flash::elf2bin(app_image, params.chip)?);
+        let app_image = Image::new_elf(
+            "ota_1".to_string(),
+            flash::elf2bin(app_image, params.chip, params.flash_size)?,
+            app_image.to_vec(),
+        );
+
+        let part_table = supply_default_part_table
+            .then(|| Self::app_part_table(params.flash_size, app_image.data.len()))
+            .transpose()?;
 
         Self::from_parts(
             name,
             params,
-            Payload::new(None, supply_default_part_table),
+            Payload::new(part_table.as_deref(), false),
             Payload::new(None, supply_default_bootloader),
             once(app_image),
             Vec::new().into_iter(),
@@ -201,10 +281,14 @@ extra_1,  data, 0x06,            ,   20K,
 
         let app_image = Image::new("ota_1".to_string(), app_image.to_vec());
 
+        let part_table = supply_default_part_table
+            .then(|| Self::app_part_table(params.flash_size, app_image.data.len()))
+            .transpose()?;
+
         Self::from_parts(
             name,
             params,
-            Payload::new(None, supply_default_part_table),
+            Payload::new(part_table.as_deref(), false),
             Payload::new(None, supply_default_bootloader),
             once(app_image),
             Vec::new().into_iter(),
@@ -229,6 +313,8 @@ extra_1,  data, 0x06,            ,   20K,
     {
         info!("About to prep the ZIP image bundle `{name}`");
 
+        validate(&name, zip, supply_default_part_table)?;
+
         let mut params_str = String::new();
         zip.by_name(Self::PARAMS_FILE_NAME)?
             .read_to_string(&mut params_str)
@@ -241,6 +327,36 @@ extra_1,  data, 0x06,            ,   20K,
 
         let params: Params = toml::from_str(&params_str)?;
 
+        let config_override = zip
+            .index_for_name(Self::CONFIG_OVERRIDE_FILE_NAME)
+            .map(|index| {
+                let mut zip_file = zip.by_index(index).with_context(|| {
+                    format!(
+                        "Loading `{}` from the ZIP file failed",
+                        Self::CONFIG_OVERRIDE_FILE_NAME
+                    )
+                })?;
+
+                let mut config_override_str = String::new();
+
+                zip_file
+                    .read_to_string(&mut config_override_str)
+                    .with_context(|| {
+                        format!(
+                            "Loading `{}` from the ZIP file failed",
+                            Self::CONFIG_OVERRIDE_FILE_NAME
+                        )
+                    })?;
+
+                toml::from_str::<ConfigOverride>(&config_override_str).with_context(|| {
+                    format!(
+                        "Parsing `{}` from the ZIP file failed (only a subset of the configuration can be overridden)",
+                        Self::CONFIG_OVERRIDE_FILE_NAME
+                    )
+                })
+            })
+            .transpose()?;
+
         let part_table_str = zip
             .index_for_name(Self::PART_TABLE_FILE_NAME)
             .map(|index| {
@@ -266,6 +382,30 @@ extra_1,  data, 0x06,            ,   20K,
             })
             .transpose()?;
 
+        let variant_names = zip
+            .file_names()
+            .filter(|file_name| Self::part_table_variant(file_name).is_some())
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        let mut part_table_variants = variant_names
+            .into_iter()
+            .map(|file_name| {
+                let mut part_table_str = String::new();
+
+                zip.by_name(&file_name)
+                    .with_context(|| format!("Loading `{file_name}` from the ZIP file failed"))?
+                    .read_to_string(&mut part_table_str)
+                    .with_context(|| format!("Loading `{file_name}` from the ZIP file failed"))?;
+
+                let variant = Self::part_table_variant(&file_name).unwrap().to_string();
+
+                Ok::<_, anyhow::Error>((variant, part_table_str))
+            })
+            .collect::<anyhow::Result<Vec<_>>>()?;
+
+        part_table_variants.sort();
+
         let bootloader_image = zip
             .index_for_name(Self::BOOTLOADER_FILE_NAME)
             .map(|index| {
@@ -314,7 +454,11 @@ extra_1,  data, 0x06,            ,   20K,
                 let elf = !file_name.ends_with(Self::BIN_SUFFIX);
 
                 let image = if elf {
-                    Image::new(name.to_string(), flash::elf2bin(&data, params.chip)?)
+                    Image::new_elf(
+                        name.to_string(),
+                        flash::elf2bin(&data, params.chip, params.flash_size)?,
+                        data,
+                    )
                 } else {
                     Image::new(name.to_string(), data)
                 };
@@ -325,9 +469,46 @@ extra_1,  data, 0x06,            ,   20K,
 
         let images = images?;
 
+        let efuse_manifest = zip
+            .index_for_name(Self::EFUSE_MANIFEST_FILE_NAME)
+            .map(|index| {
+                let mut zip_file = zip.by_index(index).with_context(|| {
+                    format!(
+                        "Loading `{}` from the ZIP file failed",
+                        Self::EFUSE_MANIFEST_FILE_NAME
+                    )
+                })?;
+
+                let mut efuse_manifest_str = String::new();
+
+                zip_file
+                    .read_to_string(&mut efuse_manifest_str)
+                    .with_context(|| {
+                        format!(
+                            "Loading `{}` from the ZIP file failed",
+                            Self::EFUSE_MANIFEST_FILE_NAME
+                        )
+                    })?;
+
+                EfuseManifest::parse(&efuse_manifest_str).with_context(|| {
+                    format!(
+                        "Parsing `{}` from the ZIP file failed",
+                        Self::EFUSE_MANIFEST_FILE_NAME
+                    )
+                })
+            })
+            .transpose()?;
+
+        // The files referenced by the eFuse manifest are not named after the legacy file name convention
+        let efuse_manifest_files = efuse_manifest
+            .as_ref()
+            .map(EfuseManifest::files)
+            .unwrap_or_default();
+
         let efuse_names = zip
             .file_names()
             .filter(|file_name| file_name.starts_with(Self::EFUSES_PREFIX))
+            .filter(|file_name| !efuse_manifest_files.contains(file_name))
             .map(|file_name| file_name.to_string())
             .collect::<Vec<_>>();
 
@@ -343,23 +524,156 @@ extra_1,  data, 0x06,            ,   20K,
                     .read_to_end(&mut data)
                     .with_context(|| format!("Loading `{}` from the ZIP file failed", file_name))?;
 
-                Efuse::new(
-                    file_name.strip_prefix(Self::EFUSES_PREFIX).unwrap(),
-                    Arc::new(data),
-                )
+                Efuse::new(file_name.strip_prefix(Self::EFUSES_PREFIX).unwrap(), data)
             })
             .collect();
 
-        let efuses = efuses?;
+        let mut efuses = efuses?;
 
-        Self::from_parts(
+        if let Some(efuse_manifest) = efuse_manifest {
+            let manifest_efuses = efuse_manifest
+                .efuses(|file_name| {
+                    let mut zip_file = zip.by_name(file_name).with_context(|| {
+                        format!("Loading `{}` from the ZIP file failed", file_name)
+                    })?;
+
+                    let mut data = Vec::new();
+                    zip_file.read_to_end(&mut data).with_context(|| {
+                        format!("Loading `{}` from the ZIP file failed", file_name)
+                    })?;
+
+                    Ok(data)
+                })
+                .with_context(|| {
+                    format!(
+                        "Loading the efuses of `{}` failed",
+                        Self::EFUSE_MANIFEST_FILE_NAME
+                    )
+                })?;
+
+            for efuse in manifest_efuses {
+                if efuses.iter().any(|existing| existing.is_same(&efuse)) {
+                    anyhow::bail!(
+                        "Efuse `{efuse}` from `{}` already exists",
+                        Self::EFUSE_MANIFEST_FILE_NAME
+                    );
+                }
+
+                efuses.push(efuse);
+            }
+        }
+
+        if let Some(index) = zip.index_for_name(Self::EFUSE_PLAN_FILE_NAME) {
+            let mut zip_file = zip.by_index(index).with_context(|| {
+                format!(
+                    "Loading `{}` from the ZIP file failed",
+                    Self::EFUSE_PLAN_FILE_NAME
+                )
+            })?;
+
+            let mut efuse_plan_str = String::new();
+
+            zip_file
+                .read_to_string(&mut efuse_plan_str)
+                .with_context(|| {
+                    format!(
+                        "Loading `{}` from the ZIP file failed",
+                        Self::EFUSE_PLAN_FILE_NAME
+                    )
+                })?;
+
+            for efuse in Efuse::from_plan(&efuse_plan_str).with_context(|| {
+                format!(
+                    "Parsing `{}` from the ZIP file failed",
+                    Self::EFUSE_PLAN_FILE_NAME
+                )
+            })? {
+                if efuses.iter().any(|existing| existing.is_same(&efuse)) {
+                    anyhow::bail!(
+                        "Efuse `{efuse}` from `{}` already exists",
+                        Self::EFUSE_PLAN_FILE_NAME
+                    );
+                }
+
+                efuses.push(efuse);
+            }
+        }
+
+        let mut this = Self::from_parts(
             name,
             params,
             Payload::new(part_table_str.as_deref(), supply_default_part_table),
             Payload::new(bootloader_image, supply_default_bootloader),
             images.into_iter(),
             efuses.into_iter(),
+        )?;
+
+        this.config_override = config_override;
+        this.part_table_variants = part_table_variants;
+
+        Ok(this)
+    }
+
+    /// Return a copy of the bundle, where the partition table is replaced with the given alternative partition table
+    /// shipped with the bundle (`partition-table-<variant>.csv`)
+    ///
+    /// The images are re-mapped to the partitions of the alternative partition table by partition name
+    pub fn with_part_table_variant(&self, variant: &str) -> anyhow::Result<Self> {
+        let (_, part_table_str) = self
+            .part_table_variants
+            .iter()
+            .find(|(name, _)| name == variant)
+            .with_context(|| {
+                format!(
+                    "Partition table variant `{variant}` not found in bundle `{}`",
+                    self.name
+                )
+            })?;
+
+        let mut bootloader = None;
+        let mut images = Vec::new();
+
+        for mapping in &self.parts_mapping {
+            let Some(image) = mapping.image.clone() else {
+                continue;
+            };
+
+            match mapping.partition.as_ref().map(|partition| partition.name()) {
+                Some(name) if name == Self::BOOTLOADER_NAME => bootloader = Some(image),
+                Some(name) if name == Self::PART_TABLE_NAME => (),
+                Some(name) => images.push(Image { name, ..image }),
+                None => images.push(image),
+            }
+        }
+
+        let mut this = Self::from_parts(
+            self.name.clone(),
+            self.params.clone(),
+            Payload::Provided(part_table_str.as_str()),
+            Payload::new(bootloader, false),
+            images.into_iter(),
+            core::iter::empty(),
         )
+        .with_context(|| format!("Applying partition table variant `{variant}` failed"))?;
+
+        this.efuse_mapping = self.efuse_mapping.clone();
+        this.config_override = self.config_override.clone();
+        this.part_table_variants = self.part_table_variants.clone();
+
+        info!(
+            "Partition table variant `{variant}` applied to bundle `{}`",
+            self.name
+        );
+
+        Ok(this)
+    }
+
+    /// Return the variant of an alternative partition table file name (`partition-table-<variant>.csv`), if it is one
+    fn part_table_variant(file_name: &str) -> Option<&str> {
+        file_name
+            .strip_prefix(Self::PART_TABLE_VARIANT_PREFIX)
+            .and_then(|variant| variant.strip_suffix(Self::PART_TABLE_VARIANT_SUFFIX))
+            .filter(|variant| !variant.is_empty() && !variant.contains('/'))
     }
 
     /// Create a new `Bundle` from the parts of the bundle
@@ -387,6 +701,8 @@ extra_1,  data, 0x06,            ,   20K,
             offset: u32,
             /// The image of the partition table
             image: Image,
+            /// The flags of the partitions not handled by `esp-idf-part`, by partition name
+            flags: HashMap<String, PartitionFlags>,
         }
 
         impl PartTableData {
@@ -396,22 +712,20 @@ extra_1,  data, 0x06,            ,   20K,
                     part_table_str.into_option(|| Ok(Bundle::DEFAULT_PART_TABLE))?;
 
                 if let Some(part_table_str) = part_table_str {
-                    let table = esp_idf_part::PartitionTable::try_from_str(part_table_str)
-                        .context("Parsing CSV partition table failed")?;
+                    let (table, flags) = Bundle::parse_part_table(part_table_str)?;
 
                     let offset = table.partitions()[0].offset() - Bundle::PART_TABLE_SIZE as u32;
 
                     let image = Image::new(
                         Bundle::PART_TABLE_NAME.to_string(),
-                        table
-                            .to_bin()
-                            .context("Converting CSV partition table to binary failed")?,
+                        Bundle::part_table_bin(&table, &flags)?,
                     );
 
                     Ok(Some(PartTableData {
                         table,
                         offset,
                         image,
+                        flags,
                     }))
                 } else {
                     Ok(None)
@@ -445,6 +759,7 @@ extra_1,  data, 0x06,            ,   20K,
                         false,
                     )
                 }),
+                flags: PartitionFlags::default(),
                 image: Some(image),
             });
         }
@@ -459,6 +774,7 @@ extra_1,  data, 0x06,            ,   20K,
                     Self::PART_TABLE_SIZE as u32,
                     false,
                 )),
+                flags: PartitionFlags::default(),
                 image: Some(pt.image.clone()),
             });
 
@@ -481,6 +797,7 @@ extra_1,  data, 0x06,            ,   20K,
 
                 parts_mapping.push(PartitionMapping {
                     partition: Some(partition.clone()),
+                    flags: pt.flags.get(&partition.name()).cloned().unwrap_or_default(),
                     image,
                 });
             }
@@ -489,6 +806,7 @@ extra_1,  data, 0x06,            ,   20K,
         for image in images.into_values() {
             parts_mapping.push(PartitionMapping {
                 partition: None,
+                flags: PartitionFlags::default(),
                 image: Some(image),
             });
         }
@@ -505,6 +823,8 @@ extra_1,  data, 0x06,            ,   20K,
                     status: ProvisioningStatus::NotStarted,
                 })
                 .collect(),
+            config_override: None,
+            part_table_variants: Vec::new(),
         };
 
         this.check_part_sizes()?;
@@ -512,6 +832,69 @@ extra_1,  data, 0x06,            ,   20K,
         Ok(this)
     }
 
+    /// Add the param efuses of an eFuse plan into the current bundle
+    ///
+    /// Efuses already in the bundle are only allowed if they have the same value
+    pub fn add_efuses(&mut self, efuses: impl IntoIterator<Item = Efuse>) -> anyhow::Result<()> {
+        for efuse in efuses {
+            if let Some(existing_efuse) = self
+                .efuse_mapping
+                .iter()
+                .find(|existing_efuse| efuse.is_same(&existing_efuse.efuse))
+            {
+                let same_value = matches!(
+                    (&existing_efuse.efuse, &efuse),
+                    (Efuse::Param { value: value1, .. }, Efuse::Param { value: value2, .. })
+                        if value1 == value2
+                );
+
+                if !same_value {
+                    anyhow::bail!(
+                        "Efuse `{efuse}` conflicts with efuse `{}` of the bundle",
+                        existing_efuse.efuse
+                    );
+                }
+            } else {
+                self.efuse_mapping.push(EfuseMapping {
+                    efuse,
+                    status: ProvisioningStatus::NotStarted,
+                });
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Set the read- and write-protection of the key and the key digest in the given eFuse block,
+    /// unless already declared by the bundle itself
+    pub fn protect_efuse(
+        &mut self,
+        block: &str,
+        read_protect: Option<bool>,
+        write_protect: Option<bool>,
+    ) {
+        for mapping in &mut self.efuse_mapping {
+            match &mut mapping.efuse {
+                Efuse::Key {
+                    block: efuse_block,
+                    read_protect: efuse_read_protect,
+                    write_protect: efuse_write_protect,
+                    ..
+                }
+                | Efuse::KeyDigest {
+                    block: efuse_block,
+                    read_protect: efuse_read_protect,
+                    write_protect: efuse_write_protect,
+                    ..
+                } if efuse_block == block => {
+                    *efuse_read_protect = efuse_read_protect.or(read_protect);
+                    *efuse_write_protect = efuse_write_protect.or(write_protect);
+                }
+                _ => (),
+            }
+        }
+    }
+
     /// Add the images and efuses of another bundle into the current bundle
     ///
     /// # Arguments
@@ -561,6 +944,7 @@ extra_1,  data, 0x06,            ,   20K,
         for image in other_images.into_values() {
             self.parts_mapping.push(PartitionMapping {
                 partition: None,
+                flags: PartitionFlags::default(),
                 image: Some(image),
             });
         }
@@ -577,6 +961,12 @@ extra_1,  data, 0x06,            ,   20K,
             }
         }
 
+        if let Some(other_override) = other.config_override {
+            self.config_override
+                .get_or_insert_with(Default::default)
+                .merge(&other_override);
+        }
+
         self.name = format!("{}+{}", self.name, other.name);
 
         self.check_part_sizes()?;
@@ -584,16 +974,54 @@ extra_1,  data, 0x06,            ,   20K,
         Ok(())
     }
 
-    pub fn add_empty(&mut self) {
+    /// Add 0xff images for all partitions which do not have an image
+    ///
+    /// # Arguments
+    /// - `readonly`: Whether to add 0xff images for the partitions marked as `readonly` too
+    pub fn add_empty(&mut self, readonly: bool) {
         for mapping in &mut self.parts_mapping {
             if let Some(partition) = mapping.partition.as_ref() {
-                if mapping.image.is_none() {
+                if mapping.image.is_none() && (readonly || !mapping.flags.readonly) {
                     mapping.image = Some(Image::new_empty(partition.size() as _));
                 }
             }
         }
     }
 
+    /// Only keep 0xff images for the data partitions and no images for the app partitions,
+    /// as well as for the bootloader and the partition table, so that only the data partitions are reset
+    ///
+    /// # Arguments
+    /// - `readonly`: Whether to reset the data partitions marked as `readonly` too
+    pub fn data_reset(&mut self, readonly: bool) {
+        for mapping in &mut self.parts_mapping {
+            if let Some(partition) = mapping.partition.as_ref() {
+                mapping.image = (matches!(partition.ty(), Type::Data)
+                    && (readonly || !mapping.flags.readonly))
+                    .then(|| Image::new_empty(partition.size() as _));
+            }
+        }
+    }
+
+    /// Set (or replace) the image to be flashed to the partition with the given name
+    pub fn set_image(&mut self, part_name: &str, image: Image) -> anyhow::Result<()> {
+        let mapping = self
+            .parts_mapping
+            .iter_mut()
+            .find(|mapping| {
+                mapping
+                    .partition
+                    .as_ref()
+                    .map(|partition| partition.name() == part_name)
+                    .unwrap_or(false)
+            })
+            .ok_or_else(|| anyhow::anyhow!("Partition `{part_name}` not found"))?;
+
+        mapping.image = Some(image);
+
+        self.check_part_sizes()
+    }
+
     /// Return `true` if the bundle is bootable, i.e. has a partition table, a bootloader, and an app image
     pub fn is_bootable(&self) -> bool {
         self.has_part_table() && self.has_bootloader() && self.has_app_image()
@@ -628,13 +1056,98 @@ extra_1,  data, 0x06,            ,   20K,
         })
     }
 
+    /// Get the ELF file of the app image (if any) to be used for decoding the app logs
+    ///
+    /// The first app partition (in partition table order) having an image extracted from an ELF file is used
+    pub fn app_elf(&self) -> Option<Arc<Vec<u8>>> {
+        self.parts_mapping.iter().find_map(|mapping| {
+            mapping
+                .partition
+                .as_ref()
+                .filter(|partition| matches!(partition.ty(), Type::App))
+                .and(mapping.image.as_ref())
+                .and_then(|image| image.elf.clone())
+        })
+    }
+
+    /// Get the name of the first `nvs_keys` partition of the partition table, if any
+    pub fn nvs_keys_partition(&self) -> Option<&str> {
+        self.parts_mapping
+            .iter()
+            .filter_map(|mapping| mapping.partition.as_ref())
+            .find(|partition| matches!(partition.subtype(), SubType::Data(DataType::NvsKeys)))
+            .map(|partition| partition.name())
+    }
+
+    /// Get the name of the `otadata` partition of the partition table, if any
+    pub fn otadata_partition(&self) -> Option<&str> {
+        self.parts_mapping
+            .iter()
+            .filter_map(|mapping| mapping.partition.as_ref())
+            .find(|partition| matches!(partition.subtype(), SubType::Data(DataType::Ota)))
+            .map(|partition| partition.name())
+    }
+
+    /// Get the offset and size of the `coredump` partition of the partition table, if any
+    pub fn coredump_partition(&self) -> Option<(u32, u32)> {
+        self.parts_mapping
+            .iter()
+            .filter_map(|mapping| mapping.partition.as_ref())
+            .find(|partition| matches!(partition.subtype(), SubType::Data(DataType::CoreDump)))
+            .map(|partition| (partition.offset(), partition.size()))
+    }
+
+    /// Return `true` if the partition table has a `factory` app partition
+    pub fn has_factory_partition(&self) -> bool {
+        self.parts_mapping
+            .iter()
+            .filter_map(|mapping| mapping.partition.as_ref())
+            .any(|partition| matches!(partition.subtype(), SubType::App(AppType::Factory)))
+    }
+
+    /// Get the OTA layout of the partition table, if the partition table has an `otadata` partition
+    pub fn ota_layout(&self) -> Option<OtaLayout> {
+        let partitions = self
+            .parts_mapping
+            .iter()
+            .filter_map(|mapping| mapping.partition.as_ref());
+
+        let otadata = partitions
+            .clone()
+            .find(|partition| matches!(partition.subtype(), SubType::Data(DataType::Ota)))?;
+
+        let mut slots = partitions
+            .filter_map(|partition| match partition.subtype() {
+                SubType::App(app_type) => (app_type as u8)
+                    .checked_sub(AppType::Ota_0 as u8)
+                    .filter(|index| *index < 16)
+                    .map(|index| (index, partition)),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        slots.sort_by_key(|(index, _)| *index);
+
+        Some(OtaLayout {
+            otadata: (otadata.offset(), otadata.size()),
+            slots: slots
+                .into_iter()
+                .map(|(_, partition)| {
+                    (
+                        partition.name().to_string(),
+                        partition.offset(),
+                        partition.size(),
+                    )
+                })
+                .collect(),
+        })
+    }
+
     /// Get all flash encryption keys (if any)
     pub(crate) fn get_flash_encrypt_keys(&self) -> impl Iterator<Item = &'_ [u8]> + '_ {
         self.efuse_mapping.iter().filter_map(|mapping| {
             if let Efuse::Key {
-                block: _,
-                key_value,
-                purpose,
+                key_value, purpose, ..
             } = &mapping.efuse
             {
                 (purpose == "XTS_AES_128_KEY").then_some(key_value.as_slice())
@@ -697,6 +1210,257 @@ extra_1,  data, 0x06,            ,   20K,
         modified
     }
 
+    /// Return the name of the partition at the given offset, if any
+    pub(crate) fn partition_name(&self, part_offset: u32) -> Option<&str> {
+        self.parts_mapping
+            .iter()
+            .filter_map(|mapping| mapping.partition.as_ref())
+            .find(|partition| partition.offset() == part_offset)
+            .map(|partition| partition.name())
+    }
+
+    /// Return the partition table to be used for a bundle consisting of a single (binary or ELF) app image
+    ///
+    /// For 4MB flash (or no flash size specified), this is the default partition table.
+    /// For other flash sizes, a table with the same layout is generated, with the two app partitions
+    /// scaled so that they use all of the flash. As with the default table, the app partitions start
+    /// at a 64K boundary and their size (excluding the potential 4K signature at the end) is divisible by 64K
+    ///
+    /// # Arguments
+    /// - `flash_size`: The flash size of the target device
+    /// - `app_len`: The size of the app image, used to check early that the app fits in the table
+    fn app_part_table(flash_size: Option<FlashSize>, app_len: usize) -> anyhow::Result<String> {
+        let flash_kb = flash_size.map(|size| size.size() / 1024).unwrap_or(4096);
+
+        let (table, app_kb) = if flash_kb == 4096 {
+            (Self::DEFAULT_PART_TABLE.to_string(), 1956)
+        } else {
+            let start = Self::APP_PART_TABLE_START_KB;
+            let data = Self::APP_PART_TABLE_DATA_KB;
+
+            // Each app partition is followed by the data partitions, so that the next app partition
+            // is again 64K-aligned: `start + 2 * (64 * n + 4 + data) <= flash`
+            let slots = flash_kb
+                .checked_sub(start + 2 * (4 + data))
+                .map(|free| free / 128)
+                .filter(|slots| *slots > 0)
+                .ok_or_else(|| {
+                    anyhow::anyhow!(
+                        "Flash size {flash_kb}K is too small for a default partition table"
+                    )
+                })?;
+
+            let app_kb = 64 * slots + 4;
+            let ota_1_offset = start + app_kb + data;
+
+            let table = format!(
+                r#"
+# Name,   Type, SubType,   Offset,  Size,  Flags
+ota_0,    app,  ota_0,    {:#x}, {app_kb}K,
+nvs,      data, nvs,             ,   32K,
+nvs_keys, data, 0x04,            ,    4K,
+phy_init, data, phy,             ,    4K,
+extra_0,  data, 0x06,            ,   20K,
+ota_1,    app,  ota_1,    {:#x}, {app_kb}K,
+nvs_bm,   data, 0x06,            ,   32K,
+otadata,  data, ota,             ,    8K,
+extra_1,  data, 0x06,            ,   20K,
+"#,
+                start * 1024,
+                ota_1_offset * 1024,
+            );
+
+            (table, app_kb)
+        };
+
+        let app_max_len = app_kb as usize * 1024;
+
+        if app_len > app_max_len {
+            anyhow::bail!(
+                "App image is too large for the default partition table of a {flash_kb}K flash ({app_len}B > {app_max_len}B); use a larger flash size or a ZIP bundle with a custom partition table"
+            );
+        }
+
+        info!(
+            "Using a default partition table for a {flash_kb}K flash with {app_kb}K app partitions"
+        );
+
+        Ok(table)
+    }
+
+    /// Check that no images are to be flashed to partitions marked as `readonly`
+    pub fn check_readonly(&self) -> anyhow::Result<()> {
+        let readonly = self
+            .parts_mapping
+            .iter()
+            .filter(|mapping| mapping.flags.readonly && mapping.image.is_some())
+            .filter_map(|mapping| mapping.partition.as_ref())
+            .map(|partition| format!("`{}`", partition.name()))
+            .collect::<Vec<_>>();
+
+        if !readonly.is_empty() {
+            anyhow::bail!(
+                "Refusing to flash partitions marked as readonly: {}",
+                readonly.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Check that the bundle fits the flash size detected on the chip, i.e. that the flash size of the bundle
+    /// (if any) is not larger than the detected one, and that all partitions end within the detected flash
+    ///
+    /// A bundle for a smaller flash than the detected one is only warned about, as it does work, albeit
+    /// without using the whole flash
+    pub fn check_flash_size(&self, detected: FlashSize) -> anyhow::Result<()> {
+        let mut problems = Vec::new();
+
+        if let Some(flash_size) = self.params.flash_size {
+            if flash_size.size() > detected.size() {
+                problems.push(format!("the bundle is for a {flash_size} flash"));
+            } else if flash_size != detected {
+                warn!("The bundle is for a {flash_size} flash, but the chip has a {detected} flash; the rest of the flash will not be used");
+            }
+        }
+
+        for partition in self
+            .parts_mapping
+            .iter()
+            .filter_map(|mapping| mapping.partition.as_ref())
+        {
+            let end = partition.offset() as usize + partition.size() as usize;
+
+            if end > detected.size() as usize {
+                problems.push(format!(
+                    "partition `{}` ends at 0x{end:x}",
+                    partition.name()
+                ));
+            }
+        }
+
+        if !problems.is_empty() {
+            anyhow::bail!(
+                "The chip has a {detected} flash, but {}",
+                problems.join(", ")
+            );
+        }
+
+        info!("Bundle fits the detected {detected} flash");
+
+        Ok(())
+    }
+
+    /// Parse a CSV partition table
+    ///
+    /// The flags `esp-idf-part` does not know about (`readonly`, as well as any custom ones) are removed
+    /// from the table before parsing it, and are returned separately, by partition name
+    fn parse_part_table(
+        part_table_str: &str,
+    ) -> anyhow::Result<(PartitionTable, HashMap<String, PartitionFlags>)> {
+        let mut flags = HashMap::new();
+
+        let part_table_str = part_table_str
+            .lines()
+            .map(|line| {
+                let mut columns = line.split(',').collect::<Vec<_>>();
+
+                if line.trim_start().starts_with('#') || columns.len() < 6 {
+                    return line.to_string();
+                }
+
+                let mut part_flags = PartitionFlags::default();
+                let mut known = Vec::new();
+
+                for flag in columns[5]
+                    .split(':')
+                    .map(str::trim)
+                    .filter(|flag| !flag.is_empty())
+                {
+                    match flag {
+                        "encrypted" => known.push(flag),
+                        "readonly" => part_flags.readonly = true,
+                        other => part_flags.custom.push(other.to_string()),
+                    }
+                }
+
+                if part_flags != PartitionFlags::default() {
+                    flags.insert(columns[0].trim().to_string(), part_flags);
+                }
+
+                let known = known.join(":");
+                columns[5] = &known;
+
+                columns.join(",")
+            })
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let table = PartitionTable::try_from_str(part_table_str)
+            .context("Parsing CSV partition table failed")?;
+
+        for (name, part_flags) in &flags {
+            if !part_flags.custom.is_empty() {
+                warn!(
+                    "Partition `{name}` has unknown flags: {}",
+                    part_flags.custom.join(", ")
+                );
+            }
+        }
+
+        Ok((table, flags))
+    }
+
+    /// Convert the partition table to binary, setting the `readonly` flags which are not known to `esp-idf-part`
+    fn part_table_bin(
+        table: &PartitionTable,
+        flags: &HashMap<String, PartitionFlags>,
+    ) -> anyhow::Result<Vec<u8>> {
+        /// The size of a partition table entry
+        const ENTRY_SIZE: usize = 32;
+        /// The magic bytes of a partition entry
+        const ENTRY_MAGIC: [u8; 2] = [0xaa, 0x50];
+        /// The magic bytes of the MD5 checksum entry
+        const MD5_MAGIC: [u8; 2] = [0xeb, 0xeb];
+        /// The `readonly` bit of the flags of a partition entry
+        const READONLY_FLAG: u32 = 1 << 1;
+
+        let mut bin = table
+            .to_bin()
+            .context("Converting CSV partition table to binary failed")?;
+
+        for offset in (0..bin.len()).step_by(ENTRY_SIZE) {
+            let Some(entry) = bin.get_mut(offset..offset + ENTRY_SIZE) else {
+                break;
+            };
+
+            if entry[..2] == ENTRY_MAGIC {
+                let label = &entry[12..28];
+                let label = &label[..label.iter().position(|b| *b == 0).unwrap_or(label.len())];
+
+                let readonly = core::str::from_utf8(label)
+                    .ok()
+                    .and_then(|label| flags.get(label))
+                    .map(|flags| flags.readonly)
+                    .unwrap_or(false);
+
+                if readonly {
+                    let part_flags = u32::from_le_bytes(entry[28..32].try_into().unwrap());
+                    entry[28..32].copy_from_slice(&(part_flags | READONLY_FLAG).to_le_bytes());
+                }
+            } else if entry[..2] == MD5_MAGIC {
+                let digest = Md5::digest(&bin[..offset]);
+                bin[offset + 16..offset + ENTRY_SIZE].copy_from_slice(&digest);
+
+                break;
+            } else {
+                break;
+            }
+        }
+
+        Ok(bin)
+    }
+
     fn check_part_sizes(&self) -> anyhow::Result<()> {
         for mapping in &self.parts_mapping {
             if let Some(partition) = mapping.partition.as_ref() {
@@ -737,12 +1501,360 @@ impl Display for Bundle {
         }
         writeln!(f, "  }}")?;
 
+        if let Some(config_override) = &self.config_override {
+            writeln!(f, "  Config override: {config_override:?}")?;
+        }
+
         writeln!(f, "}}")?;
 
         Ok(())
     }
 }
 
+/// Repack the content of a TAR bundle into an in-memory ZIP archive, so that it is loaded
+/// in the same way as a ZIP bundle
+///
+/// The files are stored uncompressed, as the archive only lives for the duration of the loading
+fn zip_from_tar<R>(read: R) -> anyhow::Result<ZipArchive<Cursor<Vec<u8>>>>
+where
+    R: Read,
+{
+    let mut tar = tar::Archive::new(read);
+    let mut zip = ZipWriter::new(Cursor::new(Vec::new()));
+
+    let options = SimpleFileOptions::default()
+        .compression_method(CompressionMethod::Stored)
+        .large_file(true);
+
+    for entry in tar.entries().context("Reading the TAR bundle failed")? {
+        let mut entry = entry.context("Reading the TAR bundle failed")?;
+
+        if !entry.header().entry_type().is_file() {
+            continue;
+        }
+
+        // Archives created with e.g. `tar -C <dir> .` prefix all files with `./`
+        let file_name = String::from_utf8_lossy(&entry.path_bytes())
+            .trim_start_matches("./")
+            .to_string();
+
+        zip.start_file(file_name.as_str(), options)?;
+        io::copy(&mut entry, &mut zip)
+            .with_context(|| format!("Reading file `{file_name}` from the TAR bundle failed"))?;
+    }
+
+    Ok(ZipArchive::new(zip.finish()?)?)
+}
+
+/// Check a ZIP bundle (.bundle) for common mistakes before loading it
+///
+/// Unlike loading the bundle - which fails on the first error - all problems are collected and reported at once,
+/// each one prefixed with the file of the bundle it relates to. Checked are:
+/// - `params.toml` being present and complete
+/// - `partition-table.csv`, `config-override.toml`, `efuse-plan.json` and `efuses.toml` being parseable
+/// - Misplaced or misnamed files (e.g. `image/` instead of `images/`)
+/// - The names of the eFuse files, and the files referenced by `efuses.toml` being present
+/// - Image names not matching any partition, ELF images for non-app partitions and images larger than their partitions
+/// - Partitions and images not fitting in the flash size of the bundle
+///
+/// # Arguments
+/// - `name`: The name of the bundle
+/// - `zip`: The ZIP archive containing the bundle content
+/// - `supply_default_part_table`: Whether the default partition table is supplied if the partition table is not provided in the bundle
+pub fn validate<T>(
+    name: &str,
+    zip: &mut ZipArchive<T>,
+    supply_default_part_table: bool,
+) -> anyhow::Result<()>
+where
+    T: Read + Seek,
+{
+    /// Read a file from the ZIP archive, if present
+    fn read<T>(zip: &mut ZipArchive<T>, file_name: &str) -> anyhow::Result<Option<Vec<u8>>>
+    where
+        T: Read + Seek,
+    {
+        let Some(index) = zip.index_for_name(file_name) else {
+            return Ok(None);
+        };
+
+        let mut data = Vec::new();
+        zip.by_index(index)?.read_to_end(&mut data)?;
+
+        Ok(Some(data))
+    }
+
+    /// Read a UTF-8 file from the ZIP archive, if present
+    fn read_str<T>(zip: &mut ZipArchive<T>, file_name: &str) -> anyhow::Result<Option<String>>
+    where
+        T: Read + Seek,
+    {
+        read(zip, file_name)?
+            .map(|data| String::from_utf8(data).context("Not a UTF-8 file"))
+            .transpose()
+    }
+
+    info!("About to validate the ZIP image bundle `{name}`");
+
+    let mut problems = Vec::new();
+
+    let params = match read_str(zip, Bundle::PARAMS_FILE_NAME) {
+        Ok(Some(params_str)) => match toml::from_str::<Params>(&params_str) {
+            Ok(params) => Some(params),
+            Err(err) => {
+                problems.push(format!("`{}`: {}", Bundle::PARAMS_FILE_NAME, err.message()));
+                None
+            }
+        },
+        Ok(None) => {
+            problems.push(format!(
+                "`{}`: missing (the chip of the bundle needs to be specified)",
+                Bundle::PARAMS_FILE_NAME
+            ));
+            None
+        }
+        Err(err) => {
+            problems.push(format!("`{}`: {err:#}", Bundle::PARAMS_FILE_NAME));
+            None
+        }
+    };
+
+    match read_str(zip, Bundle::CONFIG_OVERRIDE_FILE_NAME) {
+        Ok(Some(config_override_str)) => {
+            if let Err(err) = toml::from_str::<ConfigOverride>(&config_override_str) {
+                problems.push(format!(
+                    "`{}`: {} (only a subset of the configuration can be overridden)",
+                    Bundle::CONFIG_OVERRIDE_FILE_NAME,
+                    err.message()
+                ));
+            }
+        }
+        Ok(None) => (),
+        Err(err) => problems.push(format!("`{}`: {err:#}", Bundle::CONFIG_OVERRIDE_FILE_NAME)),
+    }
+
+    match read_str(zip, Bundle::EFUSE_PLAN_FILE_NAME) {
+        Ok(Some(efuse_plan_str)) => {
+            if let Err(err) = Efuse::from_plan(&efuse_plan_str) {
+                problems.push(format!("`{}`: {err:#}", Bundle::EFUSE_PLAN_FILE_NAME));
+            }
+        }
+        Ok(None) => (),
+        Err(err) => problems.push(format!("`{}`: {err:#}", Bundle::EFUSE_PLAN_FILE_NAME)),
+    }
+
+    let efuse_manifest_files = match read_str(zip, Bundle::EFUSE_MANIFEST_FILE_NAME) {
+        Ok(Some(efuse_manifest_str)) => match EfuseManifest::parse(&efuse_manifest_str) {
+            Ok(efuse_manifest) => {
+                if let Err(err) = efuse_manifest.efuses(|file_name| {
+                    read(zip, file_name)?
+                        .with_context(|| format!("File `{file_name}` not found in the bundle"))
+                }) {
+                    problems.push(format!("`{}`: {err:#}", Bundle::EFUSE_MANIFEST_FILE_NAME));
+                }
+
+                efuse_manifest
+                    .files()
+                    .into_iter()
+                    .map(str::to_string)
+                    .collect()
+            }
+            Err(err) => {
+                problems.push(format!("`{}`: {err:#}", Bundle::EFUSE_MANIFEST_FILE_NAME));
+                Vec::new()
+            }
+        },
+        Ok(None) => Vec::new(),
+        Err(err) => {
+            problems.push(format!("`{}`: {err:#}", Bundle::EFUSE_MANIFEST_FILE_NAME));
+            Vec::new()
+        }
+    };
+
+    let part_table = match read_str(zip, Bundle::PART_TABLE_FILE_NAME) {
+        Ok(Some(part_table_str)) => match Bundle::parse_part_table(&part_table_str) {
+            Ok((table, _)) if table.partitions().is_empty() => {
+                problems.push(format!("`{}`: no partitions", Bundle::PART_TABLE_FILE_NAME));
+                None
+            }
+            Ok((table, _)) => Some(table),
+            Err(err) => {
+                problems.push(format!("`{}`: {err:#}", Bundle::PART_TABLE_FILE_NAME));
+                None
+            }
+        },
+        Ok(None) => supply_default_part_table
+            .then(|| PartitionTable::try_from_str(Bundle::DEFAULT_PART_TABLE).ok())
+            .flatten(),
+        Err(err) => {
+            problems.push(format!("`{}`: {err:#}", Bundle::PART_TABLE_FILE_NAME));
+            None
+        }
+    };
+
+    let flash_len = params
+        .as_ref()
+        .and_then(|params| params.flash_size)
+        .map(|size| size.size() as usize)
+        .unwrap_or(4 * 1024 * 1024);
+
+    if let Some(part_table) = part_table.as_ref() {
+        for partition in part_table.partitions() {
+            let end = partition.offset() as usize + partition.size() as usize;
+
+            if end > flash_len {
+                problems.push(format!(
+                    "`{}`: partition `{}` ends at 0x{end:x}, beyond the flash size ({flash_len}B)",
+                    Bundle::PART_TABLE_FILE_NAME,
+                    partition.name()
+                ));
+            }
+        }
+    }
+
+    let file_names = zip
+        .file_names()
+        .filter(|file_name| !file_name.ends_with('/'))
+        .map(str::to_string)
+        .collect::<Vec<_>>();
+
+    let mut images_len = 0;
+
+    for file_name in file_names {
+        if efuse_manifest_files.contains(&file_name) {
+            continue;
+        }
+
+        if let Some(image_name) = file_name.strip_prefix(Bundle::IMAGES_PREFIX) {
+            let elf = !file_name.ends_with(Bundle::BIN_SUFFIX);
+            let image_name = image_name.trim_end_matches(Bundle::BIN_SUFFIX);
+
+            let data = match read(zip, &file_name) {
+                Ok(data) => data.unwrap_or_default(),
+                Err(err) => {
+                    problems.push(format!("`{file_name}`: {err:#}"));
+                    continue;
+                }
+            };
+
+            // The size of ELF images is only known after conversion, so they are not counted
+            if !elf {
+                images_len += data.len();
+            }
+
+            let Some(part_table) = part_table.as_ref() else {
+                continue;
+            };
+
+            let Some(partition) = part_table.find(image_name) else {
+                problems.push(format!(
+                    "`{file_name}`: no partition `{image_name}` in the partition table (partitions: {})",
+                    part_table
+                        .partitions()
+                        .iter()
+                        .map(|partition| partition.name())
+                        .collect::<Vec<_>>()
+                        .join(", ")
+                ));
+                continue;
+            };
+
+            if elf && !matches!(partition.ty(), Type::App) {
+                problems.push(format!(
+                    "`{file_name}`: ELF image for partition `{image_name}` which is not of type 'App'"
+                ));
+            }
+
+            if !elf && data.len() > partition.size() as usize {
+                problems.push(format!(
+                    "`{file_name}`: image is too large for partition `{image_name}` ({}B > {}B)",
+                    data.len(),
+                    partition.size()
+                ));
+            }
+        } else if Bundle::part_table_variant(&file_name).is_some() {
+            match read_str(zip, &file_name) {
+                Ok(Some(part_table_str)) => match Bundle::parse_part_table(&part_table_str) {
+                    Ok((table, _)) if table.partitions().is_empty() => {
+                        problems.push(format!("`{file_name}`: no partitions"));
+                    }
+                    Ok(_) => (),
+                    Err(err) => problems.push(format!("`{file_name}`: {err:#}")),
+                },
+                Ok(None) => (),
+                Err(err) => problems.push(format!("`{file_name}`: {err:#}")),
+            }
+        } else if let Some(efuse_name) = file_name.strip_prefix(Bundle::EFUSES_PREFIX) {
+            let result = read(zip, &file_name)
+                .and_then(|data| Efuse::new(efuse_name, data.unwrap_or_default()));
+
+            if let Err(err) = result {
+                problems.push(format!("`{file_name}`: {err:#}"));
+            }
+        } else if ![
+            Bundle::PARAMS_FILE_NAME,
+            Bundle::BOOTLOADER_FILE_NAME,
+            Bundle::PART_TABLE_FILE_NAME,
+            Bundle::CONFIG_OVERRIDE_FILE_NAME,
+            Bundle::EFUSE_PLAN_FILE_NAME,
+            Bundle::EFUSE_MANIFEST_FILE_NAME,
+        ]
+        .contains(&file_name.as_str())
+        {
+            let lowercase = file_name.to_ascii_lowercase();
+
+            // Most likely a typo in the name of a known file or directory
+            if lowercase.starts_with("image")
+                || lowercase.starts_with("efuse")
+                || lowercase.starts_with("param")
+                || lowercase.starts_with("partition")
+                || lowercase.starts_with("bootloader")
+                || lowercase.starts_with("config")
+            {
+                problems.push(format!(
+                    "`{file_name}`: unknown file (expected `{}`, `{}`, `{}`, `{}`, `{}`, `{}`, `{}*` or `{}*`)",
+                    Bundle::PARAMS_FILE_NAME,
+                    Bundle::BOOTLOADER_FILE_NAME,
+                    Bundle::PART_TABLE_FILE_NAME,
+                    Bundle::CONFIG_OVERRIDE_FILE_NAME,
+                    Bundle::EFUSE_PLAN_FILE_NAME,
+                    Bundle::EFUSE_MANIFEST_FILE_NAME,
+                    Bundle::IMAGES_PREFIX,
+                    Bundle::EFUSES_PREFIX
+                ));
+            } else {
+                warn!("Ignoring unknown file `{file_name}` in bundle `{name}`");
+            }
+        }
+    }
+
+    if images_len > flash_len {
+        problems.push(format!(
+            "`{}*`: the images take {images_len}B, more than the flash size ({flash_len}B)",
+            Bundle::IMAGES_PREFIX
+        ));
+    }
+
+    if !problems.is_empty() {
+        anyhow::bail!(
+            "Bundle `{name}` has {} problem(s):\n- {}",
+            problems.len(),
+            problems.join("\n- ")
+        );
+    }
+
+    Ok(())
+}
+
+/// The OTA layout of a partition table
+#[derive(Clone, Debug)]
+pub struct OtaLayout {
+    /// The offset and size of the `otadata` partition
+    pub otadata: (u32, u32),
+    /// The name, offset and size of each OTA app partition, ordered by OTA slot index
+    pub slots: Vec<(String, u32, u32)>,
+}
+
 /// A type for a payload that can be either provided, not providced,
 /// or requested to be the default payload for that payload type
 ///
@@ -794,6 +1906,10 @@ pub struct Params {
     /// Flash size of the target device
     /// If not provided, 4MB flash size is assumed
     pub flash_size: Option<FlashSize>,
+    /// The minimum silicon revision of the chip (e.g. `v0.2`) the bundle can be flashed to
+    /// If not provided, any revision is accepted
+    #[serde(default)]
+    pub min_chip_revision: Option<ChipRevision>,
 }
 
 impl Params {
@@ -802,6 +1918,7 @@ impl Params {
         Self {
             chip: Chip::Esp32,
             flash_size: None,
+            min_chip_revision: None,
         }
     }
 }
@@ -820,10 +1937,64 @@ impl Display for Params {
             write!(f, ", Flash size: {:?}", flash_size)?;
         }
 
+        if let Some(min_chip_revision) = self.min_chip_revision {
+            write!(f, ", Min chip revision: {min_chip_revision}")?;
+        }
+
         Ok(())
     }
 }
 
+/// The silicon revision of a chip, in the `v<major>.<minor>` format used by ESP-IDF and `esptool.py`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Ord, PartialOrd, Hash, Deserialize)]
+#[serde(try_from = "String")]
+pub struct ChipRevision {
+    /// The major revision
+    pub major: u32,
+    /// The minor revision
+    pub minor: u32,
+}
+
+impl ChipRevision {
+    /// Create a new `ChipRevision`
+    pub const fn new(major: u32, minor: u32) -> Self {
+        Self { major, minor }
+    }
+}
+
+impl core::str::FromStr for ChipRevision {
+    type Err = anyhow::Error;
+
+    /// Parse a revision like `v0.2`, `0.2`, or - as reported by older `esptool.py` versions for the ESP32 - `3`
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let revision = s.trim();
+        let revision = revision.strip_prefix(['v', 'V']).unwrap_or(revision);
+
+        let (major, minor) = revision.split_once('.').unwrap_or((revision, "0"));
+
+        let parse = |part: &str| {
+            part.parse::<u32>()
+                .with_context(|| format!("Invalid chip revision `{s}`, expected e.g. `v0.2`"))
+        };
+
+        Ok(Self::new(parse(major)?, parse(minor)?))
+    }
+}
+
+impl TryFrom<String> for ChipRevision {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl Display for ChipRevision {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "v{}.{}", self.major, self.minor)
+    }
+}
+
 /// The type of the chip to be flashed
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Hash, Deserialize)]
 #[non_exhaustive]
@@ -871,17 +2042,33 @@ impl Chip {
         }
     }
 
-    /// Convert the `Chip` to a `espflash::targets::Chip` instance
-    pub const fn to_flash_chip(self) -> espflash::targets::Chip {
+    /// Parse a `Chip` from its representation used by the Espressif tools (e.g. `esp32c3`)
+    pub fn from_tools_str(chip: &str) -> Option<Self> {
+        [
+            Self::Esp32,
+            Self::Esp32c2,
+            Self::Esp32c3,
+            Self::Esp32c6,
+            Self::Esp32h2,
+            Self::Esp32p4,
+            Self::Esp32s2,
+            Self::Esp32s3,
+        ]
+        .into_iter()
+        .find(|candidate| candidate.as_tools_str().eq_ignore_ascii_case(chip.trim()))
+    }
+
+    /// Convert the `Chip` to a `espflash::target::Chip` instance
+    pub const fn to_flash_chip(self) -> espflash::target::Chip {
         match self {
-            Self::Esp32 => espflash::targets::Chip::Esp32,
-            Self::Esp32c2 => espflash::targets::Chip::Esp32c2,
-            Self::Esp32c3 => espflash::targets::Chip::Esp32c3,
-            Self::Esp32c6 => espflash::targets::Chip::Esp32c6,
-            Self::Esp32h2 => espflash::targets::Chip::Esp32h2,
-            Self::Esp32p4 => espflash::targets::Chip::Esp32p4,
-            Self::Esp32s2 => espflash::targets::Chip::Esp32s2,
-            Self::Esp32s3 => espflash::targets::Chip::Esp32s3,
+            Self::Esp32 => espflash::target::Chip::Esp32,
+            Self::Esp32c2 => espflash::target::Chip::Esp32c2,
+            Self::Esp32c3 => espflash::target::Chip::Esp32c3,
+            Self::Esp32c6 => espflash::target::Chip::Esp32c6,
+            Self::Esp32h2 => espflash::target::Chip::Esp32h2,
+            Self::Esp32p4 => espflash::target::Chip::Esp32p4,
+            Self::Esp32s2 => espflash::target::Chip::Esp32s2,
+            Self::Esp32s3 => espflash::target::Chip::Esp32s3,
         }
     }
 }
@@ -928,6 +2115,8 @@ impl Display for FlashData {
 pub struct PartitionMapping {
     /// The partition
     pub partition: Option<Partition>,
+    /// The flags of the partition which are not handled by `esp-idf-part`
+    pub flags: PartitionFlags,
     /// The image to be flashed to the partition; if `None`, the partition will be left empty
     pub image: Option<Image>,
 }
@@ -959,6 +2148,14 @@ impl Display for PartitionMapping {
                 write!(f, " encrypted")?;
             }
 
+            if self.flags.readonly {
+                write!(f, " readonly")?;
+            }
+
+            for flag in &self.flags.custom {
+                write!(f, " {flag}")?;
+            }
+
             write!(f, ")")?;
         } else {
             write!(f, "(none)")?;
@@ -974,6 +2171,16 @@ impl Display for PartitionMapping {
     }
 }
 
+/// The flags of a partition - as specified in the last column of the CSV partition table - which are not handled
+/// by `esp-idf-part` (i.e. all but `encrypted`)
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash)]
+pub struct PartitionFlags {
+    /// The partition is marked as `readonly` (newer ESP-IDF versions)
+    pub readonly: bool,
+    /// Flags unknown to this tool, preserved for display purposes
+    pub custom: Vec<String>,
+}
+
 /// An efuse to be programmed
 #[derive(Debug, Clone)]
 pub enum Efuse {
@@ -1002,10 +2209,16 @@ pub enum Efuse {
         block: String,
         /// The key value to be programmed, as documented here:
         /// https://docs.espressif.com/projects/esptool/en/latest/esp32s3/espefuse/burn-key-cmd.html
-        key_value: Arc<Vec<u8>>,
+        key_value: Arc<Secret>,
         /// The key purpose, as documented here:
         /// https://docs.espressif.com/projects/esptool/en/latest/esp32s3/espefuse/burn-key-cmd.html
         purpose: String,
+        /// Whether to read-protect the key; if `None`, `Config::efuse_key_protection` or
+        /// `Config::efuse_protect_keys` applies
+        read_protect: Option<bool>,
+        /// Whether to write-protect the key; if `None`, `Config::efuse_key_protection` or
+        /// `Config::efuse_protect_keys` applies
+        write_protect: Option<bool>,
     },
     /// A key digest efuse - a digest value to be programmed
     ///
@@ -1022,12 +2235,28 @@ pub enum Efuse {
         /// The key digest purpose, as documented here:
         /// https://docs.espressif.com/projects/esptool/en/latest/esp32s3/espefuse/burn-key-digest-cmd.html
         purpose: String,
+        /// Whether to read-protect the key digest; if `None`, `Config::efuse_key_protection` or
+        /// `Config::efuse_protect_digests` applies
+        read_protect: Option<bool>,
+        /// Whether to write-protect the key digest; if `None`, `Config::efuse_key_protection` or
+        /// `Config::efuse_protect_digests` applies
+        write_protect: Option<bool>,
+    },
+    /// The custom MAC address of the chip
+    ///
+    /// For burning it, the equivalent of `espefuse.py burn_custom_mac` command is used
+    CustomMac {
+        /// The MAC address, as documented here:
+        /// https://docs.espressif.com/projects/esptool/en/latest/esp32s3/espefuse/burn-custom-mac-cmd.html
+        mac: [u8; 6],
     },
 }
 
 impl Efuse {
     /// Create a new `Efuse` from the given file name and file data
-    pub fn new(name: &str, data: Arc<Vec<u8>>) -> anyhow::Result<Self> {
+    ///
+    /// The data is taken over, so that the data of a key is never copied before being wrapped as a `Secret`
+    pub fn new(name: &str, data: Vec<u8>) -> anyhow::Result<Self> {
         let mut parts = name.split('-');
 
         let ty = parts
@@ -1082,14 +2311,18 @@ impl Efuse {
                 if ty == "key" {
                     Ok(Self::Key {
                         block: block.to_string(),
-                        key_value: data,
+                        key_value: Arc::new(Secret::new(data)),
                         purpose: purpose.to_string(),
+                        read_protect: None,
+                        write_protect: None,
                     })
                 } else {
                     Ok(Self::KeyDigest {
                         block: block.to_string(),
-                        digest_value: data,
+                        digest_value: Arc::new(data),
                         purpose: purpose.to_string(),
+                        read_protect: None,
+                        write_protect: None,
                     })
                 }
             }
@@ -1097,11 +2330,61 @@ impl Efuse {
         }
     }
 
+    /// Create the param efuses of an eFuse plan
+    ///
+    /// The eFuse plan is a JSON object of eFuse names and their values, where a value is either a number,
+    /// a boolean, a decimal or `0x`-prefixed hex string, or an object with a `value` member of one of these types
+    /// (i.e. a subset of the output of `espefuse.py summary --format json`)
+    pub fn from_plan(plan: &str) -> anyhow::Result<Vec<Self>> {
+        let plan = serde_json::from_str::<serde_json::Map<String, serde_json::Value>>(plan)
+            .context("Invalid eFuse plan: not a JSON object")?;
+
+        plan.into_iter()
+            .map(|(name, value)| {
+                if name.is_empty()
+                    || !name
+                        .chars()
+                        .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+                {
+                    anyhow::bail!("Invalid efuse name `{name}` in the eFuse plan");
+                }
+
+                let value = match value {
+                    serde_json::Value::Object(mut object) => object
+                        .remove("value")
+                        .ok_or_else(|| anyhow::anyhow!("No value for efuse `{name}`"))?,
+                    value => value,
+                };
+
+                let value = match &value {
+                    serde_json::Value::Bool(value) => Some(*value as u32),
+                    serde_json::Value::Number(value) => {
+                        value.as_u64().and_then(|value| u32::try_from(value).ok())
+                    }
+                    serde_json::Value::String(value) => {
+                        if let Some(hex) = value.strip_prefix("0x") {
+                            u32::from_str_radix(hex, 16).ok()
+                        } else {
+                            value.parse::<u32>().ok()
+                        }
+                    }
+                    _ => None,
+                }
+                .ok_or_else(|| {
+                    anyhow::anyhow!("Invalid efuse value `{value}` for name `{name}`")
+                })?;
+
+                Ok(Self::Param { name, value })
+            })
+            .collect()
+    }
+
     pub fn name(&self) -> &str {
         match self {
             Self::Param { name, .. } => name,
             Self::Key { block, .. } => block,
             Self::KeyDigest { block, .. } => block,
+            Self::CustomMac { .. } => "CUSTOM_MAC",
         }
     }
 
@@ -1112,6 +2395,7 @@ impl Efuse {
             (Self::KeyDigest { block: block1, .. }, Self::KeyDigest { block: block2, .. }) => {
                 block1 == block2
             }
+            (Self::CustomMac { .. }, Self::CustomMac { .. }) => true,
             _ => false,
         }
     }
@@ -1123,10 +2407,233 @@ impl Display for Efuse {
             Self::Param { name, value } => write!(f, "param-{}-{:08x}", name, value),
             Self::Key { block, purpose, .. } => write!(f, "key-{}-{}", block, purpose),
             Self::KeyDigest { block, purpose, .. } => write!(f, "keydigest-{}-{}", block, purpose),
+            Self::CustomMac { mac } => write!(f, "custommac-{}", mac_str(mac)),
         }
     }
 }
 
+/// The eFuse manifest of a ZIP bundle (`efuses.toml`)
+///
+/// A structured alternative to encoding the efuses in the names of the files in `efuses/`. Each `[[efuse]]` entry
+/// declares one efuse, where the values of the keys and the key digests are read from the given files of the bundle, e.g.:
+/// ```toml
+/// [[efuse]]
+/// type = "key"
+/// block = "BLOCK_KEY0"
+/// purpose = "XTS_AES_128_KEY"
+/// file = "keys/flash-encryption-key.bin"
+/// read_protect = true
+/// write_protect = true
+///
+/// [[efuse]]
+/// type = "digest"
+/// block = "BLOCK_KEY1"
+/// purpose = "SECURE_BOOT_DIGEST0"
+/// file = "keys/secure-boot-signing-key.pem"
+/// protect = false
+///
+/// [[efuse]]
+/// type = "param"
+/// name = "SPI_BOOT_CRYPT_CNT"
+/// value = "0x7"
+///
+/// [[efuse]]
+/// type = "custom-mac"
+/// mac = "aa:bb:cc:dd:ee:ff"
+/// ```
+#[derive(Clone, Debug, Deserialize)]
+struct EfuseManifest {
+    #[serde(default)]
+    efuse: Vec<EfuseManifestEntry>,
+}
+
+impl EfuseManifest {
+    /// Parse the eFuse manifest
+    fn parse(manifest: &str) -> anyhow::Result<Self> {
+        toml::from_str(manifest).map_err(|err| anyhow::anyhow!("{}", err.message()))
+    }
+
+    /// Return the files of the bundle referenced by the manifest
+    fn files(&self) -> Vec<&str> {
+        self.efuse
+            .iter()
+            .filter_map(|entry| match entry {
+                EfuseManifestEntry::Key { file, .. } | EfuseManifestEntry::Digest { file, .. } => {
+                    Some(file.as_str())
+                }
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Create the efuses of the manifest, reading the values of the keys and the key digests with the given closure
+    fn efuses<F>(&self, mut read: F) -> anyhow::Result<Vec<Efuse>>
+    where
+        F: FnMut(&str) -> anyhow::Result<Vec<u8>>,
+    {
+        let mut efuses: Vec<Efuse> = Vec::new();
+
+        for entry in &self.efuse {
+            let efuse = match entry {
+                EfuseManifestEntry::Param { name, value } => Efuse::Param {
+                    name: name.clone(),
+                    value: value.to_u32().ok_or_else(|| {
+                        anyhow::anyhow!("Invalid efuse value `{value:?}` for name `{name}`")
+                    })?,
+                },
+                EfuseManifestEntry::Key {
+                    block,
+                    purpose,
+                    file,
+                    protection,
+                } => Efuse::Key {
+                    block: block.clone(),
+                    key_value: Arc::new(Secret::new(Self::read_value(&mut read, file)?)),
+                    purpose: purpose.clone(),
+                    read_protect: protection.read_protect(),
+                    write_protect: protection.write_protect(),
+                },
+                EfuseManifestEntry::Digest {
+                    block,
+                    purpose,
+                    file,
+                    protection,
+                } => Efuse::KeyDigest {
+                    block: block.clone(),
+                    digest_value: Arc::new(Self::read_value(&mut read, file)?),
+                    purpose: purpose.clone(),
+                    read_protect: protection.read_protect(),
+                    write_protect: protection.write_protect(),
+                },
+                EfuseManifestEntry::CustomMac { mac } => Efuse::CustomMac {
+                    mac: parse_mac(mac)?,
+                },
+            };
+
+            if efuses.iter().any(|existing| existing.is_same(&efuse)) {
+                anyhow::bail!("Efuse `{efuse}` is declared more than once");
+            }
+
+            efuses.push(efuse);
+        }
+
+        Ok(efuses)
+    }
+
+    fn read_value<F>(read: &mut F, file: &str) -> anyhow::Result<Vec<u8>>
+    where
+        F: FnMut(&str) -> anyhow::Result<Vec<u8>>,
+    {
+        let data = read(file)?;
+
+        if data.is_empty() {
+            anyhow::bail!("Invalid efuse data in file `{file}`: empty");
+        }
+
+        Ok(data)
+    }
+}
+
+/// An entry of the eFuse manifest
+#[derive(Clone, Debug, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum EfuseManifestEntry {
+    /// A parameter efuse (see `Efuse::Param`)
+    Param {
+        name: String,
+        value: EfuseManifestValue,
+    },
+    /// A key efuse (see `Efuse::Key`), with the key value in the given file of the bundle
+    Key {
+        block: String,
+        purpose: String,
+        file: String,
+        #[serde(flatten)]
+        protection: EfuseManifestProtection,
+    },
+    /// A key digest efuse (see `Efuse::KeyDigest`), with the digest value in the given file of the bundle
+    #[serde(alias = "keydigest")]
+    Digest {
+        block: String,
+        purpose: String,
+        file: String,
+        #[serde(flatten)]
+        protection: EfuseManifestProtection,
+    },
+    /// The custom MAC address (see `Efuse::CustomMac`), as six hex bytes separated with `:` or `-`
+    CustomMac { mac: String },
+}
+
+/// The protection of a key or a key digest in the eFuse manifest
+///
+/// `protect` sets both the read- and the write-protection, while `read_protect` and `write_protect` - which take
+/// precedence - set each one of them
+#[derive(Clone, Debug, Default, Deserialize)]
+struct EfuseManifestProtection {
+    #[serde(default)]
+    protect: Option<bool>,
+    #[serde(default)]
+    read_protect: Option<bool>,
+    #[serde(default)]
+    write_protect: Option<bool>,
+}
+
+impl EfuseManifestProtection {
+    fn read_protect(&self) -> Option<bool> {
+        self.read_protect.or(self.protect)
+    }
+
+    fn write_protect(&self) -> Option<bool> {
+        self.write_protect.or(self.protect)
+    }
+}
+
+/// The value of a parameter efuse in the eFuse manifest: a number, a boolean, or a decimal or `0x`-prefixed hex string
+#[derive(Clone, Debug, Deserialize)]
+#[serde(untagged)]
+enum EfuseManifestValue {
+    Bool(bool),
+    Number(u32),
+    String(String),
+}
+
+impl EfuseManifestValue {
+    fn to_u32(&self) -> Option<u32> {
+        match self {
+            Self::Bool(value) => Some(*value as u32),
+            Self::Number(value) => Some(*value),
+            Self::String(value) => {
+                if let Some(hex) = value.strip_prefix("0x") {
+                    u32::from_str_radix(hex, 16).ok()
+                } else {
+                    value.parse::<u32>().ok()
+                }
+            }
+        }
+    }
+}
+
+/// Parse a MAC address given as six hex bytes separated with `:` or `-`
+fn parse_mac(mac: &str) -> anyhow::Result<[u8; 6]> {
+    let bytes = mac
+        .split([':', '-'])
+        .map(|byte| u8::from_str_radix(byte, 16))
+        .collect::<Result<Vec<_>, _>>()
+        .ok()
+        .filter(|bytes| bytes.len() == 6)
+        .ok_or_else(|| anyhow::anyhow!("Invalid MAC address `{mac}`"))?;
+
+    let mut result = [0; 6];
+    result.copy_from_slice(&bytes);
+
+    Ok(result)
+}
+
+/// Format a MAC address as six hex bytes separated with `:`, as expected by `espefuse.py`
+pub(crate) fn mac_str(mac: &[u8; 6]) -> String {
+    hex::encode_separated(mac, ":")
+}
+
 #[derive(Clone, Debug)]
 pub struct EfuseMapping {
     /// The efuse
@@ -1173,6 +2680,10 @@ pub struct Image {
     pub ty: ImageType,
     /// The data of the image
     pub data: Arc<Vec<u8>>,
+    /// The original ELF file the data was extracted from, if the image is of type `ImageType::Elf`
+    ///
+    /// Necessary for decoding `defmt` logs when running the app
+    pub elf: Option<Arc<Vec<u8>>>,
     /// The status of the image flashing
     pub status: ProvisioningStatus,
 }
@@ -1185,17 +2696,19 @@ impl Image {
             name,
             ty: ImageType::Binary,
             data: Arc::new(data),
+            elf: None,
             status: ProvisioningStatus::NotStarted,
         }
     }
 
     /// Create a new `Image` from the given binary data, where the binary data
-    /// was extracted from an ELF file
-    pub fn new_elf(name: String, data: Vec<u8>) -> Self {
+    /// was extracted from the given ELF file
+    pub fn new_elf(name: String, data: Vec<u8>, elf: Vec<u8>) -> Self {
         Self {
             name,
             ty: ImageType::Elf,
             data: Arc::new(data),
+            elf: Some(Arc::new(elf)),
             status: ProvisioningStatus::NotStarted,
         }
     }
@@ -1206,6 +2719,7 @@ impl Image {
             name: "(Empty)".into(),
             ty: ImageType::Empty,
             data: Arc::new(empty_space(size)),
+            elf: None,
             status: ProvisioningStatus::NotStarted,
         }
     }
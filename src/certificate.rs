@@ -0,0 +1,59 @@
+//! The provisioning record ("birth certificate") of the device
+//!
+//! The certificate is a small NUL-terminated JSON blob flashed into a designated data partition,
+//! so that field diagnostics can read back the provisioning provenance from the device itself
+
+use serde::Serialize;
+
+use crate::utils::hash::sha256_hex;
+use crate::Locale;
+
+/// The version of the certificate format
+const VERSION: u32 = 1;
+
+/// The content of the certificate
+#[derive(Serialize)]
+struct Record<'a> {
+    /// The version of the certificate format
+    version: u32,
+    /// The UTC date and time of the provisioning
+    date: String,
+    /// The ID of the provisioning station (Test JIG)
+    station_id: &'a str,
+    /// The name (version) of the provisioned bundle
+    bundle: &'a str,
+    /// The SHA-256 hash of the provisioning report (the readouts of the PCB)
+    report_hash: String,
+}
+
+/// Create the certificate blob
+///
+/// # Arguments
+/// - `station_id` - the ID of the provisioning station
+/// - `bundle_name` - the name of the provisioned bundle
+/// - `readouts` - the readouts of the PCB, as recorded in the summary of the PCB logs
+pub fn create(
+    station_id: &str,
+    bundle_name: &str,
+    readouts: &[(String, String)],
+) -> anyhow::Result<Vec<u8>> {
+    let report = readouts
+        .iter()
+        .map(|(name, value)| format!("{name}={value}\n"))
+        .collect::<String>();
+
+    let report_hash = sha256_hex(report);
+
+    let record = Record {
+        version: VERSION,
+        date: Locale::ISO.format_now(),
+        station_id,
+        bundle: bundle_name,
+        report_hash,
+    };
+
+    let mut blob = serde_json::to_vec(&record)?;
+    blob.push(0);
+
+    Ok(blob)
+}
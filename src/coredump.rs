@@ -0,0 +1,96 @@
+//! Retrieval - and optional decoding - of the ESP-IDF coredump saved to the flash by a crashed app
+
+use std::fs;
+use std::process::Command;
+
+use anyhow::Context;
+
+use log::info;
+
+use crate::backend::ProvisioningBackend;
+use crate::bundle::Chip;
+use crate::permissions::ToolRunner;
+
+/// Read the coredump from the coredump partition at the given offset and with the given size
+///
+/// Returns `None` if the partition does not contain a coredump (i.e. it is erased)
+pub fn read(
+    backend: &impl ProvisioningBackend,
+    chip: Chip,
+    offset: u32,
+    size: u32,
+) -> anyhow::Result<Option<Vec<u8>>> {
+    let mut data = backend.read(chip, offset, size)?;
+
+    // The coredump starts with its total length (little endian), which is 0xffffffff if the partition is erased
+    let Some(len) = data
+        .get(..4)
+        .map(|len| u32::from_le_bytes([len[0], len[1], len[2], len[3]]))
+    else {
+        return Ok(None);
+    };
+
+    if len == u32::MAX || len == 0 {
+        info!("No coredump found in the coredump partition");
+        return Ok(None);
+    }
+
+    if len as usize <= data.len() {
+        data.truncate(len as _);
+    }
+
+    info!(
+        "Coredump of {}B found in the coredump partition",
+        data.len()
+    );
+
+    Ok(Some(data))
+}
+
+/// Decode the (raw) coredump with the given decoder command (e.g. `espcoredump.py` of ESP-IDF)
+/// and the ELF file of the crashed app
+///
+/// Returns the output of the decoder, i.e. the backtraces, the registers and the memory of the crashed tasks
+pub fn decode(
+    tools: &ToolRunner,
+    decoder: &str,
+    chip: Chip,
+    coredump: &[u8],
+    elf: &[u8],
+) -> anyhow::Result<String> {
+    let coredump_file = tools.temp_file()?;
+    fs::write(coredump_file.path(), coredump).context("Writing the coredump file failed")?;
+
+    let elf_file = tools.temp_file()?;
+    fs::write(elf_file.path(), elf).context("Writing the ELF file failed")?;
+
+    let mut command = Command::new(decoder);
+
+    command
+        .arg("--chip")
+        .arg(chip.as_tools_str())
+        .arg("info_corefile")
+        .arg("--core")
+        .arg(coredump_file.path())
+        .arg("--core-format")
+        .arg("raw")
+        .arg(elf_file.path());
+
+    info!("About to decode the coredump with command `{command:?}`...");
+
+    let output = command
+        .output()
+        .with_context(|| format!("Executing the coredump decoder `{command:?}` failed"))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "`{command:?}` command failed with status: {}.\nStderr output:\n{}",
+            output.status,
+            core::str::from_utf8(&output.stderr).unwrap_or("???")
+        );
+    }
+
+    info!("Coredump decoded");
+
+    Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
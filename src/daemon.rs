@@ -0,0 +1,627 @@
+//! A headless daemon mode, where the provisioning is driven by a custom shop-floor UI over a small REST API
+//!
+//! The API replaces the operator input of the interactive console UI (see `Api`) and is served over plain HTTP:
+//! - `GET /status` - the state of the factory and the pending prompt (if any), as a JSON object
+//! - `POST /provision` - confirm the pending confirmation prompt (e.g. `Provision?`)
+//! - `POST /skip` - skip the current step (only where skipping is offered)
+//! - `POST /cancel` - cancel the pending prompt or the running step (go back / start over)
+//! - `POST /quit` - quit the factory
+//! - `POST /input` - answer the pending input prompt (e.g. the PCB ID) with the request body
+//! - `GET /logs` - stream the log lines as they are logged (a chunked `text/plain` response)
+//!
+//! A command which does not fit the pending prompt is rejected with `409 Conflict`
+//!
+//! If a token is configured, each request must carry it as an `Authorization: Bearer <token>` header,
+//! or it is rejected with `401 Unauthorized`
+//!
+//! The size of the request line and the headers, the number of the headers and the number of the connections served
+//! at the same time are bounded; requests over the bounds are rejected with `431 Request Header Fields Too Large`,
+//! and connections over the bound with `503 Service Unavailable`
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpListener, TcpStream};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread;
+
+use anyhow::Context;
+
+use embassy_time::{Duration, Timer};
+
+use log::{info, warn};
+
+use crate::input::{
+    LogInput, LogInputOutcome, TaskConfirmationOutcome, TaskInput, TaskInputOutcome,
+};
+use crate::model::{Model, State};
+
+/// The maximum size of a request body, as the only request with a body is `POST /input`
+const MAX_BODY_LEN: usize = 4096;
+
+/// The maximum size of the request line and of each header line
+const MAX_LINE_LEN: usize = 4096;
+
+/// The maximum total size of the request line and the headers
+const MAX_HEAD_LEN: usize = 16384;
+
+/// The maximum number of headers of a request
+const MAX_HEADERS: usize = 64;
+
+/// The maximum number of connections served at the same time (including the long-running `/logs` ones)
+const MAX_CONNECTIONS: usize = 16;
+
+/// A prompt of the task awaiting a command over the API
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub(crate) enum Prompt {
+    /// A running step which can be canceled
+    Cancelable,
+    /// A confirmation prompt
+    Confirm { label: String, skip: bool },
+    /// An input prompt
+    Input { label: String, current: String },
+}
+
+/// A command received over the API
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub(crate) enum Command {
+    Confirm,
+    Skip,
+    Cancel,
+    Quit,
+    Input(String),
+}
+
+/// The state shared between the task input and the HTTP server
+struct Shared {
+    prompt: Mutex<Option<Prompt>>,
+    commands: Mutex<Receiver<Command>>,
+    sender: Mutex<Sender<Command>>,
+}
+
+impl Shared {
+    /// Accept a command if it fits the pending prompt
+    ///
+    /// Returns `false` if there is no pending prompt, or if the command does not fit it
+    fn accept(&self, command: Command) -> bool {
+        let mut prompt = self.prompt.lock().unwrap();
+
+        let accepted = matches!(
+            (prompt.as_ref(), &command),
+            (Some(_), Command::Cancel | Command::Quit)
+                | (Some(Prompt::Confirm { .. }), Command::Confirm)
+                | (Some(Prompt::Confirm { skip: true, .. }), Command::Skip)
+                | (Some(Prompt::Input { .. }), Command::Input(_))
+        );
+
+        if accepted {
+            *prompt = None;
+            self.sender.lock().unwrap().send(command).unwrap();
+        }
+
+        accepted
+    }
+}
+
+/// The task input of the daemon mode, where the prompts are answered over the REST API
+#[derive(Clone)]
+pub struct Api {
+    shared: Arc<Shared>,
+}
+
+impl Api {
+    /// Create the task input, without serving it over the REST API yet
+    ///
+    /// The prompts can be answered by other frontends as well (see `Api::pending` and `Api::send`)
+    pub fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        Self {
+            shared: Arc::new(Shared {
+                prompt: Mutex::new(None),
+                commands: Mutex::new(receiver),
+                sender: Mutex::new(sender),
+            }),
+        }
+    }
+
+    /// Serve the task input over the REST API
+    ///
+    /// # Arguments
+    /// - `listen` - the address the server listens on, e.g. `127.0.0.1:8080`
+    /// - `token` - the bearer token each request must carry, if any;
+    ///   required when the server listens on a non-loopback address
+    /// - `model` - the model of the factory, for reporting its state
+    pub fn serve(
+        &self,
+        listen: &str,
+        token: Option<&str>,
+        model: Arc<Model>,
+    ) -> anyhow::Result<()> {
+        let shared = self.shared.clone();
+
+        let listener = TcpListener::bind(listen)
+            .with_context(|| format!("Binding the daemon API to `{listen}` failed"))?;
+
+        if token.is_none() && !listener.local_addr()?.ip().is_loopback() {
+            anyhow::bail!(
+                "The daemon API listens on the non-loopback address `{listen}` but no token is configured"
+            );
+        }
+
+        let token: Option<Arc<str>> = token.map(Into::into);
+
+        info!("Daemon API listening on `http://{listen}`");
+
+        let server_shared = shared.clone();
+
+        let connections = Arc::new(AtomicUsize::new(0));
+
+        thread::Builder::new()
+            .name("daemon".into())
+            .spawn(move || {
+                for stream in listener.incoming() {
+                    match stream {
+                        Ok(mut stream) => {
+                            if connections.fetch_add(1, Ordering::SeqCst) >= MAX_CONNECTIONS {
+                                connections.fetch_sub(1, Ordering::SeqCst);
+
+                                warn!("Too many daemon API connections, rejecting a new one");
+
+                                let _ = respond(
+                                    &mut stream,
+                                    "503 Service Unavailable",
+                                    "text/plain",
+                                    "Too many connections\n",
+                                );
+
+                                continue;
+                            }
+
+                            let shared = server_shared.clone();
+                            let model = model.clone();
+                            let token = token.clone();
+                            let connections = connections.clone();
+
+                            // Each connection is served on its own thread, as the `/logs` endpoint is long-running;
+                            // the number of the connection threads is bounded by `MAX_CONNECTIONS`
+                            let spawned = thread::Builder::new().name("daemon-conn".into()).spawn(
+                                move || {
+                                    let _guard = scopeguard::guard((), |_| {
+                                        connections.fetch_sub(1, Ordering::SeqCst);
+                                    });
+
+                                    if let Err(err) =
+                                        serve(stream, &shared, &model, token.as_deref())
+                                    {
+                                        warn!("Serving a daemon API request failed: {err}");
+                                    }
+                                },
+                            );
+
+                            if let Err(err) = spawned {
+                                warn!("Spawning a daemon API connection thread failed: {err}");
+                            }
+                        }
+                        Err(err) => warn!("Accepting a daemon API connection failed: {err}"),
+                    }
+                }
+            })
+            .context("Spawning the daemon API thread failed")?;
+
+        Ok(())
+    }
+
+    /// Return the pending prompt, if any
+    pub(crate) fn pending(&self) -> Option<Prompt> {
+        self.shared.prompt.lock().unwrap().clone()
+    }
+
+    /// Answer the pending prompt with the command
+    ///
+    /// Returns `false` if there is no pending prompt, or if the command does not fit it
+    pub(crate) fn send(&self, command: Command) -> bool {
+        self.shared.accept(command)
+    }
+
+    /// Publish the prompt and wait for a command fitting it
+    async fn prompt(&mut self, prompt: Prompt) -> Command {
+        let shared = self.shared.clone();
+
+        // Drop the commands accepted for a previous prompt but never consumed (e.g. because its step completed
+        // in the meantime), so that they are not taken as an answer to this one
+        {
+            let commands = shared.commands.lock().unwrap();
+            while commands.try_recv().is_ok() {}
+        }
+
+        *shared.prompt.lock().unwrap() = Some(prompt);
+
+        // The prompt futures are dropped when the task moves on (e.g. a cancelable step completes),
+        // so the prompt is withdrawn on drop
+        let _guard = scopeguard::guard((), |_| {
+            *shared.prompt.lock().unwrap() = None;
+        });
+
+        loop {
+            if let Ok(command) = self.shared.commands.lock().unwrap().try_recv() {
+                break command;
+            }
+
+            Timer::after(Duration::from_millis(100)).await;
+        }
+    }
+
+    async fn confirmation(&mut self, label: &str, skip: bool) -> TaskConfirmationOutcome {
+        match self
+            .prompt(Prompt::Confirm {
+                label: label.to_string(),
+                skip,
+            })
+            .await
+        {
+            Command::Confirm => TaskConfirmationOutcome::Confirmed,
+            Command::Skip => TaskConfirmationOutcome::Skipped,
+            Command::Quit => TaskConfirmationOutcome::Quit,
+            Command::Cancel | Command::Input(_) => TaskConfirmationOutcome::Canceled,
+        }
+    }
+}
+
+impl TaskInput for Api {
+    async fn wait_cancel(&mut self) -> TaskConfirmationOutcome {
+        match self.prompt(Prompt::Cancelable).await {
+            Command::Quit => TaskConfirmationOutcome::Quit,
+            _ => TaskConfirmationOutcome::Canceled,
+        }
+    }
+
+    async fn confirm(&mut self, label: &str) -> TaskConfirmationOutcome {
+        self.confirmation(label, false).await
+    }
+
+    async fn confirm_or_skip(&mut self, label: &str) -> TaskConfirmationOutcome {
+        self.confirmation(label, true).await
+    }
+
+    async fn input(&mut self, label: &str, current: &str) -> TaskInputOutcome {
+        match self
+            .prompt(Prompt::Input {
+                label: label.to_string(),
+                current: current.to_string(),
+            })
+            .await
+        {
+            Command::Input(value) => TaskInputOutcome::Done(value),
+            Command::Quit => TaskInputOutcome::Quit,
+            _ => TaskInputOutcome::StartOver,
+        }
+    }
+
+    async fn swallow(&mut self) -> ! {
+        core::future::pending().await
+    }
+}
+
+impl LogInput for Api {
+    async fn get(&mut self) -> LogInputOutcome {
+        core::future::pending().await
+    }
+}
+
+/// Serve a single HTTP request of the daemon API
+fn serve(
+    mut stream: TcpStream,
+    shared: &Shared,
+    model: &Model,
+    token: Option<&str>,
+) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(core::time::Duration::from_secs(5)))?;
+
+    let mut reader = BufReader::new(stream.try_clone()?);
+
+    // The request line and the headers are read before the request is authorized, so their size is bounded
+    let mut head_len = 0;
+
+    let Some(request) = read_line(&mut reader, &mut head_len)? else {
+        return respond_too_large(&mut stream);
+    };
+
+    let mut content_len = 0;
+    let mut authorized = token.is_none();
+    let mut headers = 0;
+
+    loop {
+        let Some(header) = read_line(&mut reader, &mut head_len)? else {
+            return respond_too_large(&mut stream);
+        };
+
+        let header = header.trim();
+
+        if header.is_empty() {
+            break;
+        }
+
+        headers += 1;
+
+        if headers > MAX_HEADERS {
+            return respond_too_large(&mut stream);
+        }
+
+        if let Some((name, value)) = header.split_once(':') {
+            if name.trim().eq_ignore_ascii_case("content-length") {
+                content_len = value.trim().parse()?;
+            } else if name.trim().eq_ignore_ascii_case("authorization") {
+                if let (Some(token), Some(bearer)) = (token, value.trim().strip_prefix("Bearer ")) {
+                    authorized = tokens_equal(token.as_bytes(), bearer.trim().as_bytes());
+                }
+            }
+        }
+    }
+
+    if !authorized {
+        return respond(
+            &mut stream,
+            "401 Unauthorized",
+            "text/plain",
+            "Unauthorized\n",
+        );
+    }
+
+    if content_len > MAX_BODY_LEN {
+        return respond(
+            &mut stream,
+            "413 Payload Too Large",
+            "text/plain",
+            "Payload Too Large\n",
+        );
+    }
+
+    let mut body = vec![0; content_len];
+    reader.read_exact(&mut body)?;
+
+    let mut parts = request.split_whitespace();
+    let method = parts.next().unwrap_or_default();
+    let path = parts.next().unwrap_or_default();
+    let path = path.split('?').next().unwrap_or_default();
+
+    let command = match (method, path) {
+        ("GET", "/status") => {
+            return respond(
+                &mut stream,
+                "200 OK",
+                "application/json",
+                &status(shared, model),
+            );
+        }
+        ("GET", "/logs") => return stream_logs(stream, model),
+        ("POST", "/provision") => Command::Confirm,
+        ("POST", "/skip") => Command::Skip,
+        ("POST", "/cancel") => Command::Cancel,
+        ("POST", "/quit") => Command::Quit,
+        ("POST", "/input") => Command::Input(String::from_utf8(body)?.trim().to_string()),
+        _ => return respond(&mut stream, "404 Not Found", "text/plain", "Not Found\n"),
+    };
+
+    if shared.accept(command) {
+        respond(&mut stream, "200 OK", "text/plain", "OK\n")
+    } else {
+        respond(
+            &mut stream,
+            "409 Conflict",
+            "text/plain",
+            "The command does not fit the pending prompt\n",
+        )
+    }
+}
+
+/// Read a request or a header line of at most `MAX_LINE_LEN` bytes, adding its size to `head_len`
+///
+/// Returns `None` if the line is longer than `MAX_LINE_LEN`, or if `head_len` would exceed `MAX_HEAD_LEN`
+fn read_line<R: BufRead>(reader: &mut R, head_len: &mut usize) -> anyhow::Result<Option<String>> {
+    let limit = MAX_LINE_LEN.min(MAX_HEAD_LEN.saturating_sub(*head_len));
+
+    let mut line = Vec::new();
+    reader
+        .by_ref()
+        .take(limit as u64 + 1)
+        .read_until(b'\n', &mut line)?;
+
+    if line.len() > limit {
+        return Ok(None);
+    }
+
+    *head_len += line.len();
+
+    Ok(Some(
+        String::from_utf8(line).context("The request is not valid UTF-8")?,
+    ))
+}
+
+/// Render the state of the factory and the pending prompt as a JSON object
+fn status(shared: &Shared, model: &Model) -> String {
+    let prompt = match shared.prompt.lock().unwrap().clone() {
+        None => serde_json::Value::Null,
+        Some(Prompt::Cancelable) => serde_json::json!({ "kind": "cancel" }),
+        Some(Prompt::Confirm { label, skip }) => serde_json::json!({
+            "kind": if skip { "confirm-or-skip" } else { "confirm" },
+            "label": label,
+        }),
+        Some(Prompt::Input { label, current }) => serde_json::json!({
+            "kind": "input",
+            "label": label,
+            "current": current,
+        }),
+    };
+
+    let mut status = model.access(|inner| {
+        let state = match &inner.state {
+            State::Readout(readout) => serde_json::json!({
+                "state": "readout",
+                "readouts": readout.readouts,
+                "error": readout.error,
+            }),
+            State::Provision(provision) => serde_json::json!({
+                "state": if provision.provisioning { "provisioning" } else { "provision" },
+                "bundle": provision.bundle.name,
+                "readouts": provision.readouts,
+            }),
+            State::Preview(preview) => serde_json::json!({
+                "state": "preview",
+                "bundle": preview.provision.bundle.name,
+                "readouts": preview.provision.readouts,
+                "details": preview.details,
+            }),
+            State::AppRun(_) => serde_json::json!({ "state": "app-run" }),
+            State::Processing(processing) => serde_json::json!({
+                "state": "processing",
+                "title": processing.title.trim(),
+                "message": processing.status,
+            }),
+            State::Status(status) => serde_json::json!({
+                "state": if status.error { "error" } else { "success" },
+                "title": status.title.trim(),
+                "message": status.message,
+            }),
+            State::PortPick(port_pick) => serde_json::json!({
+                "state": "port-pick",
+                "ports": port_pick.ports.iter().map(|port| port.name.clone()).collect::<Vec<_>>(),
+            }),
+            State::PartTablePick(part_table_pick) => serde_json::json!({
+                "state": "part-table-pick",
+                "problem": part_table_pick.problem,
+                "variants": part_table_pick.variants.iter().map(|variant| variant.variant.clone()).collect::<Vec<_>>(),
+            }),
+        };
+
+        let mut status = state;
+        status["operator"] = serde_json::json!(inner.operator);
+        status["provisioned"] = serde_json::json!(inner.stats.provisioned);
+        status["steps"] = inner
+            .cycle
+            .steps
+            .iter()
+            .map(|(step, status)| serde_json::json!({ "step": step, "status": status }))
+            .collect();
+        status["work_order"] = inner
+            .work_order
+            .as_ref()
+            .map(|work_order| {
+                serde_json::json!({
+                    "id": work_order.id,
+                    "quantity": work_order.quantity,
+                    "remaining": work_order.remaining(),
+                })
+            })
+            .unwrap_or_default();
+
+        status
+    });
+
+    status["prompt"] = prompt;
+
+    status.to_string()
+}
+
+/// Stream the log lines to the client until it disconnects
+fn stream_logs(mut stream: TcpStream, model: &Model) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 200 OK\r\nContent-Type: text/plain; charset=utf-8\r\nTransfer-Encoding: chunked\r\nConnection: close\r\n\r\n"
+    )?;
+    stream.flush()?;
+
+    let receiver = model.access_mut(|inner| (inner.logs.streamed.subscribe(), false));
+
+    // Dropping the receiver on a write error unsubscribes the client with the next published log line
+    for line in receiver {
+        write!(stream, "{:x}\r\n{line}\r\n", line.len())?;
+        stream.flush()?;
+    }
+
+    Ok(())
+}
+
+/// Compare the tokens in a constant time, so that the configured token cannot be guessed byte by byte
+fn tokens_equal(expected: &[u8], actual: &[u8]) -> bool {
+    expected.len() == actual.len()
+        && expected
+            .iter()
+            .zip(actual)
+            .fold(0, |diff, (a, b)| diff | (a ^ b))
+            == 0
+}
+
+fn respond_too_large(stream: &mut TcpStream) -> anyhow::Result<()> {
+    respond(
+        stream,
+        "431 Request Header Fields Too Large",
+        "text/plain",
+        "Request Header Fields Too Large\n",
+    )
+}
+
+fn respond(
+    stream: &mut TcpStream,
+    status: &str,
+    content_type: &str,
+    body: &str,
+) -> anyhow::Result<()> {
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: {content_type}\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+
+    stream.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Cursor;
+
+    use super::*;
+
+    #[test]
+    fn read_line_within_limits() {
+        let mut reader = Cursor::new(b"GET /status HTTP/1.1\r\nHost: localhost\r\n\r\n".to_vec());
+        let mut head_len = 0;
+
+        assert_eq!(
+            read_line(&mut reader, &mut head_len).unwrap().as_deref(),
+            Some("GET /status HTTP/1.1\r\n")
+        );
+        assert_eq!(
+            read_line(&mut reader, &mut head_len).unwrap().as_deref(),
+            Some("Host: localhost\r\n")
+        );
+        assert_eq!(
+            read_line(&mut reader, &mut head_len).unwrap().as_deref(),
+            Some("\r\n")
+        );
+        assert_eq!(head_len, 41);
+    }
+
+    #[test]
+    fn read_line_rejects_long_lines() {
+        let mut reader = Cursor::new(vec![b'a'; MAX_LINE_LEN * 2]);
+        let mut head_len = 0;
+
+        assert!(read_line(&mut reader, &mut head_len).unwrap().is_none());
+    }
+
+    #[test]
+    fn read_line_rejects_long_heads() {
+        let line = format!("X-Header: {}\r\n", "a".repeat(MAX_LINE_LEN - 12));
+        let mut reader = Cursor::new(line.repeat(MAX_HEAD_LEN / MAX_LINE_LEN + 1).into_bytes());
+        let mut head_len = 0;
+
+        for _ in 0..MAX_HEAD_LEN / MAX_LINE_LEN {
+            assert!(read_line(&mut reader, &mut head_len).unwrap().is_some());
+        }
+
+        assert!(read_line(&mut reader, &mut head_len).unwrap().is_none());
+    }
+}
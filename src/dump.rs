@@ -0,0 +1,98 @@
+//! Reading back the flash of a (failed) PCB into files, so that it can be archived for failure analysis before rework
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+
+use esp_idf_part::PartitionTable;
+
+use log::info;
+
+use crate::bundle::Chip;
+use crate::permissions::ToolRunner;
+use crate::{flash, Config};
+
+/// The size of the partition table area of the flash
+const PART_TABLE_SIZE: u32 = 0xc00;
+
+/// Read back the given partitions, or - if none are given - the whole flash of the chip into files in the given directory
+///
+/// The partitions are looked up in the partition table read from the flash of the chip, and are saved as `<partition-name>.bin`.
+/// The whole flash is saved as `flash.bin`
+///
+/// # Arguments
+/// - `conf` - The configuration of the factory (the serial port, the flash speed, etc.)
+/// - `chip` - The chip to read from, as known to `esptool.py` (e.g. `esp32c3`)
+/// - `partitions` - The names of the partitions to read back; if empty, the whole flash is read back
+/// - `part_table_offset` - The offset of the partition table in the flash (usually `0x8000`)
+/// - `output_dir` - The directory where the files are saved; created if it does not exist
+pub fn dump(
+    conf: &Config,
+    chip: &str,
+    partitions: &[String],
+    part_table_offset: u32,
+    output_dir: &Path,
+) -> anyhow::Result<()> {
+    let chip = Chip::from_tools_str(chip).with_context(|| format!("Unknown chip `{chip}`"))?;
+
+    let tools = ToolRunner::new(conf)?;
+
+    let port = conf.port.as_deref();
+    let use_stub = !conf.flash_no_stub;
+    let speed = conf.flash_speed;
+
+    fs::create_dir_all(output_dir).with_context(|| {
+        format!(
+            "Creating the dump directory `{}` failed",
+            output_dir.display()
+        )
+    })?;
+
+    let mut regions = Vec::new();
+
+    if partitions.is_empty() {
+        let flash_size = flash::detect_esptool(&tools, port, chip, use_stub, speed)?
+            .flash_size
+            .context("Detecting the flash size failed")?;
+
+        regions.push(("flash".to_string(), 0, flash_size.size()));
+    } else {
+        let table = flash::read_flash_esptool(
+            &tools,
+            port,
+            chip,
+            use_stub,
+            speed,
+            part_table_offset,
+            PART_TABLE_SIZE,
+        )?;
+
+        let table = PartitionTable::try_from_bytes(table).with_context(|| {
+            format!("Parsing the partition table at offset 0x{part_table_offset:x} failed")
+        })?;
+
+        for name in partitions {
+            let partition = table
+                .find(name)
+                .with_context(|| format!("Partition `{name}` not found in the partition table"))?;
+
+            regions.push((name.clone(), partition.offset(), partition.size()));
+        }
+    }
+
+    for (name, offset, size) in regions {
+        info!("Reading back `{name}` (0x{offset:x}, 0x{size:x} bytes)...");
+
+        let data = flash::read_flash_esptool(&tools, port, chip, use_stub, speed, offset, size)?;
+
+        let path = output_dir.join(format!("{name}.bin"));
+
+        fs::write(&path, data)
+            .with_context(|| format!("Writing the dump `{}` failed", path.display()))?;
+
+        info!("`{name}` saved as `{}`", path.display());
+    }
+
+    Ok(())
+}
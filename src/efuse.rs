@@ -1,15 +1,26 @@
+use core::fmt;
+
 use std::collections::HashMap;
 use std::fs;
-use std::io::Write;
+use std::path::Path;
 use std::process::Command;
 
 use anyhow::Context;
 
-use log::{info, warn};
+use log::{debug, info, warn};
 
 use serde::{Deserialize, Serialize};
 
-use crate::bundle::Chip;
+use crate::bundle::{mac_str, Chip};
+use crate::permissions::{self, ToolRunner};
+use crate::remote;
+use crate::session;
+use crate::utils::secret::SecretFile;
+
+mod fields;
+mod table;
+
+pub(crate) use fields::{docs_url, known_field, known_fields};
 
 /// An eFuse value as returned by the Espressif eFuse tool when the command `espefuse summary --format json` is used
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -27,15 +38,50 @@ pub struct EfuseValue {
     pub writeable: bool,
 }
 
+/// The eFuse tool failed to connect to the chip, e.g. because the chip left the download mode and the adapter
+/// cannot strap it back (a manual boot-strap is needed)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct ConnectFailed;
+
+impl fmt::Display for ConnectFailed {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "The eFuse tool failed to connect to the chip")
+    }
+}
+
+impl std::error::Error for ConnectFailed {}
+
+/// The eFuse tool does not support burning a batch of eFuses with a single invocation (see `burn_batch`)
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct BatchUnsupported;
+
+impl fmt::Display for BatchUnsupported {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The eFuse tool does not support chaining several commands in one invocation"
+        )
+    }
+}
+
+impl std::error::Error for BatchUnsupported {}
+
+/// Return the names of the eFuse fields of the chip, as described in the ESP-IDF eFuse table
+/// (`<chip>.csv`) in the given directory
+pub(crate) fn field_names(tables: &str, chip: Chip) -> anyhow::Result<Vec<String>> {
+    table::field_names(tables, chip)
+}
+
 /// Get the eFuse summary for the given values
 ///
 /// # Arguments
-/// - `tools`: The tools to use for the eFuse tool
+/// - `tools`: The runner of the eFuse tool
 /// - `values`: The eFuse values to get the summary for. If empty, all values are returned
 ///
 /// # Returns
 /// A map of eFuse values by name
 pub fn summary<'a, I>(
+    tools: &ToolRunner,
     chip: Option<Chip>,
     port: Option<&str>,
     baud: Option<&str>,
@@ -44,23 +90,28 @@ pub fn summary<'a, I>(
 where
     I: Iterator<Item = &'a str>,
 {
-    let tempfile =
-        tempfile::NamedTempFile::new().context("Creation of eFuse temp out file failed")?;
+    let tempfile = tools
+        .temp_file()
+        .context("Creation of eFuse temp out file failed")?;
 
-    let mut command = Command::new(esptools::Tool::EspEfuse.mount()?.path());
+    let mut command = tools.command(esptools::Tool::EspEfuse)?;
 
     if let Some(chip) = chip {
         command.arg("--chip").arg(chip.as_tools_str());
     }
 
     if let Some(port) = port {
-        command.arg("--port").arg(port);
+        command.arg("--port").arg(remote::tools_port(port));
     }
 
     if let Some(baud) = baud {
         command.arg("--baud").arg(baud);
     }
 
+    if !tools.auto_reset() {
+        command.arg("--before").arg("no_reset");
+    }
+
     command
         .arg("summary")
         .arg("--format")
@@ -72,8 +123,7 @@ where
         command.arg(value);
     }
 
-    let output = command
-        .output()
+    let output = session::tool_output(tools, &mut command, &[tempfile.path()])
         .with_context(|| format!("Executing the eFuse tool with command `{command:?}` failed"))?;
 
     if !output.status.success() {
@@ -95,7 +145,66 @@ where
     Ok(summary)
 }
 
+/// Get the eFuse summary for the given values like `summary` does, but from the full eFuse summary of the chip
+/// cached for the provisioning cycle in the tool runner, so that the chip is connected to (and reset) only once
+///
+/// The cache is cleared with `clear_summary_cache` when a new provisioning cycle starts, and when eFuses are burned
+pub(crate) fn cached_summary<'a, I>(
+    tools: &ToolRunner,
+    chip: Option<Chip>,
+    port: Option<&str>,
+    baud: Option<&str>,
+    values: I,
+) -> anyhow::Result<HashMap<String, EfuseValue>>
+where
+    I: Iterator<Item = &'a str>,
+{
+    cached(
+        tools,
+        || summary(tools, chip, port, baud, core::iter::empty()),
+        values,
+    )
+}
+
+/// Get the given values from the eFuse summary cached for the provisioning cycle (see `cached_summary`),
+/// loading the full summary with `load` if it is not cached yet
+pub(crate) fn cached<'a, F, I>(
+    tools: &ToolRunner,
+    load: F,
+    values: I,
+) -> anyhow::Result<HashMap<String, EfuseValue>>
+where
+    F: FnOnce() -> anyhow::Result<HashMap<String, EfuseValue>>,
+    I: Iterator<Item = &'a str>,
+{
+    let mut cache = tools.efuse_summary().lock().unwrap();
+
+    let summary = match cache.as_ref() {
+        Some(summary) => {
+            debug!("Using the cached eFuse summary");
+            summary
+        }
+        None => cache.insert(load()?),
+    };
+
+    let mut values = values.peekable();
+
+    if values.peek().is_none() {
+        return Ok(summary.clone());
+    }
+
+    Ok(values
+        .filter_map(|value| Some((value.to_string(), summary.get(value)?.clone())))
+        .collect())
+}
+
+/// Clear the eFuse summary cached for the provisioning cycle (see `cached_summary`)
+pub(crate) fn clear_summary_cache(tools: &ToolRunner) {
+    *tools.efuse_summary().lock().unwrap() = None;
+}
+
 pub fn burn_efuses<'a, I>(
+    tools: &ToolRunner,
     chip: Chip,
     port: Option<&str>,
     baud: Option<&str>,
@@ -105,32 +214,46 @@ pub fn burn_efuses<'a, I>(
 where
     I: Iterator<Item = (&'a str, u32)>,
 {
-    let mut command = Command::new(esptools::Tool::EspEfuse.mount()?.path());
+    let mut command = burn_efuses_command(tools, chip, port, baud, values)?;
 
-    command.arg("--chip").arg(chip.as_tools_str());
+    burn_exec(tools, dry_run, &mut command)
+}
 
-    if let Some(port) = port {
-        command.arg("--port").arg(port);
-    }
+/// The protection of keys or key digests burned in the eFuse
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct KeyProtection {
+    /// Whether to read-protect the eFuse blocks
+    pub read: bool,
+    /// Whether to write-protect the eFuse blocks
+    pub write: bool,
+}
 
-    if let Some(baud) = baud {
-        command.arg("--baud").arg(baud);
+impl KeyProtection {
+    /// All combinations of the protection, in the order the keys or key digests are burned
+    ///
+    /// The read-protected groups go first, as the write protection of a group might also lock the read protection bits
+    pub const ALL: [Self; 4] = [
+        Self::new(true, true),
+        Self::new(true, false),
+        Self::new(false, true),
+        Self::new(false, false),
+    ];
+
+    /// Create a new `KeyProtection`
+    pub const fn new(read: bool, write: bool) -> Self {
+        Self { read, write }
     }
+}
 
-    command.arg("--do-not-confirm");
-
-    command.arg("burn_efuse");
-
-    for (key, value) in values {
-        command.arg(key);
-        command.arg(value.to_string());
+impl fmt::Display for KeyProtection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "read_protect={};write_protect={}", self.read, self.write)
     }
-
-    burn_exec(dry_run, &mut command)
 }
 
 pub fn burn_keys<'a, I>(
-    protect_keys: bool,
+    tools: &ToolRunner,
+    protection: KeyProtection,
     chip: Chip,
     port: Option<&str>,
     baud: Option<&str>,
@@ -140,11 +263,14 @@ pub fn burn_keys<'a, I>(
 where
     I: Iterator<Item = (&'a str, &'a [u8], &'a str)>,
 {
-    burn_keys_or_digests(protect_keys, "burn_key", chip, port, baud, dry_run, values)
+    burn_keys_or_digests(
+        tools, protection, "burn_key", chip, port, baud, dry_run, values,
+    )
 }
 
 pub fn burn_key_digests<'a, I>(
-    protect_digests: bool,
+    tools: &ToolRunner,
+    protection: KeyProtection,
     chip: Chip,
     port: Option<&str>,
     baud: Option<&str>,
@@ -155,7 +281,8 @@ where
     I: Iterator<Item = (&'a str, &'a [u8], &'a str)>,
 {
     burn_keys_or_digests(
-        protect_digests,
+        tools,
+        protection,
         "burn_key_digest",
         chip,
         port,
@@ -165,8 +292,196 @@ where
     )
 }
 
+pub fn burn_custom_mac(
+    tools: &ToolRunner,
+    chip: Chip,
+    port: Option<&str>,
+    baud: Option<&str>,
+    dry_run: bool,
+    mac: &[u8; 6],
+) -> anyhow::Result<String> {
+    let mut command = burn_custom_mac_command(tools, chip, port, baud, mac)?;
+
+    burn_exec(tools, dry_run, &mut command)
+}
+
+/// A burn of eFuses of the same kind, as part of a batch burned with a single eFuse tool invocation
+/// (see `burn_batch`)
+///
+/// `K` is the content of the keys and the key digests: either the data itself, or the file with the data
+pub enum EfuseBurn<'a, K: ?Sized> {
+    /// Keys, as (block, key, purpose) triples
+    Keys(KeyProtection, Vec<(&'a str, &'a K, &'a str)>),
+    /// Key digests, as (block, digest, purpose) triples
+    KeyDigests(KeyProtection, Vec<(&'a str, &'a K, &'a str)>),
+    /// The custom MAC address
+    CustomMac(&'a [u8; 6]),
+    /// eFuse params, as (eFuse, value) pairs
+    Params(Vec<(&'a str, u32)>),
+}
+
+/// Burn a batch of keys, key digests, the custom MAC and eFuse params with a single eFuse tool invocation,
+/// so that the chip is connected to (and reset) only once
+///
+/// The burns are chained as commands of the one invocation (supported by `espefuse.py` v3.1+),
+/// in the given order
+pub fn burn_batch(
+    tools: &ToolRunner,
+    chip: Chip,
+    port: Option<&str>,
+    baud: Option<&str>,
+    dry_run: bool,
+    burns: &[EfuseBurn<'_, [u8]>],
+) -> anyhow::Result<String> {
+    let mut temp_files = Vec::new();
+
+    for burn in burns {
+        if let EfuseBurn::Keys(_, values) | EfuseBurn::KeyDigests(_, values) = burn {
+            for (_, value, _) in values {
+                temp_files.push(key_temp_file(tools, value)?);
+            }
+        }
+    }
+
+    let mut paths = temp_files.iter().map(|temp_file| temp_file.path());
+
+    let burns = burns
+        .iter()
+        .map(|burn| match burn {
+            EfuseBurn::Keys(protection, values) => EfuseBurn::Keys(
+                *protection,
+                values
+                    .iter()
+                    .map(|(key, _, purpose)| (*key, paths.next().unwrap(), *purpose))
+                    .collect(),
+            ),
+            EfuseBurn::KeyDigests(protection, values) => EfuseBurn::KeyDigests(
+                *protection,
+                values
+                    .iter()
+                    .map(|(key, _, purpose)| (*key, paths.next().unwrap(), *purpose))
+                    .collect(),
+            ),
+            EfuseBurn::CustomMac(mac) => EfuseBurn::CustomMac(*mac),
+            EfuseBurn::Params(values) => EfuseBurn::Params(values.clone()),
+        })
+        .collect::<Vec<_>>();
+
+    let mut command = burn_batch_command(tools, chip, port, baud, &burns)?;
+
+    // Older eFuse tools reject the chained commands before connecting to the chip, so nothing is burned
+    burn_exec(tools, dry_run, &mut command).map_err(|err| {
+        if format!("{err:#}").contains("unrecognized arguments") {
+            err.context(BatchUnsupported)
+        } else {
+            err
+        }
+    })
+}
+
+/// Build - but do not execute - the eFuse tool command for burning the given batch of eFuses,
+/// with the keys and the key digests stored in the given files
+pub fn burn_batch_command(
+    tools: &ToolRunner,
+    chip: Chip,
+    port: Option<&str>,
+    baud: Option<&str>,
+    burns: &[EfuseBurn<'_, Path>],
+) -> anyhow::Result<Command> {
+    let mut command = burn_command(tools, chip, port, baud)?;
+
+    for burn in burns {
+        match burn {
+            EfuseBurn::Keys(protection, values) => {
+                keys_or_digests_args(&mut command, *protection, "burn_key", chip, values)?
+            }
+            EfuseBurn::KeyDigests(protection, values) => {
+                keys_or_digests_args(&mut command, *protection, "burn_key_digest", chip, values)?
+            }
+            EfuseBurn::CustomMac(mac) => {
+                command.arg("burn_custom_mac").arg(mac_str(mac));
+            }
+            EfuseBurn::Params(values) => params_args(&mut command, values.iter().copied()),
+        }
+    }
+
+    Ok(command)
+}
+
+/// Build - but do not execute - the eFuse tool command for burning the given eFuse params
+pub fn burn_efuses_command<'a, I>(
+    tools: &ToolRunner,
+    chip: Chip,
+    port: Option<&str>,
+    baud: Option<&str>,
+    values: I,
+) -> anyhow::Result<Command>
+where
+    I: Iterator<Item = (&'a str, u32)>,
+{
+    let mut command = burn_command(tools, chip, port, baud)?;
+
+    params_args(&mut command, values);
+
+    Ok(command)
+}
+
+/// Build - but do not execute - the eFuse tool command for burning the given custom MAC
+pub fn burn_custom_mac_command(
+    tools: &ToolRunner,
+    chip: Chip,
+    port: Option<&str>,
+    baud: Option<&str>,
+    mac: &[u8; 6],
+) -> anyhow::Result<Command> {
+    let mut command = burn_command(tools, chip, port, baud)?;
+
+    command.arg("burn_custom_mac").arg(mac_str(mac));
+
+    Ok(command)
+}
+
+/// Build - but do not execute - the eFuse tool command for burning the keys stored in the given files
+pub fn burn_keys_command<'a, I>(
+    tools: &ToolRunner,
+    protection: KeyProtection,
+    chip: Chip,
+    port: Option<&str>,
+    baud: Option<&str>,
+    values: I,
+) -> anyhow::Result<Command>
+where
+    I: Iterator<Item = (&'a str, &'a Path, &'a str)>,
+{
+    burn_keys_or_digests_command(tools, protection, "burn_key", chip, port, baud, values)
+}
+
+/// Build - but do not execute - the eFuse tool command for burning the key digests stored in the given files
+pub fn burn_key_digests_command<'a, I>(
+    tools: &ToolRunner,
+    protection: KeyProtection,
+    chip: Chip,
+    port: Option<&str>,
+    baud: Option<&str>,
+    values: I,
+) -> anyhow::Result<Command>
+where
+    I: Iterator<Item = (&'a str, &'a Path, &'a str)>,
+{
+    burn_keys_or_digests_command(
+        tools,
+        protection,
+        "burn_key_digest",
+        chip,
+        port,
+        baud,
+        values,
+    )
+}
+
 fn burn_keys_or_digests<'a, I>(
-    protect_keys: bool,
+    tools: &ToolRunner,
+    protection: KeyProtection,
     cmd: &str,
     chip: Chip,
     port: Option<&str>,
@@ -177,24 +492,104 @@ fn burn_keys_or_digests<'a, I>(
 where
     I: Iterator<Item = (&'a str, &'a [u8], &'a str)>,
 {
-    let mut command = Command::new(esptools::Tool::EspEfuse.mount()?.path());
+    let mut temp_files = Vec::new();
+
+    for (key, value, purpose) in values {
+        temp_files.push((key, key_temp_file(tools, value)?, purpose));
+    }
+
+    let mut command = burn_keys_or_digests_command(
+        tools,
+        protection,
+        cmd,
+        chip,
+        port,
+        baud,
+        temp_files
+            .iter()
+            .map(|(key, temp_file, purpose)| (*key, temp_file.path(), *purpose)),
+    )?;
+
+    burn_exec(tools, dry_run, &mut command)
+}
+
+fn burn_keys_or_digests_command<'a, I>(
+    tools: &ToolRunner,
+    protection: KeyProtection,
+    cmd: &str,
+    chip: Chip,
+    port: Option<&str>,
+    baud: Option<&str>,
+    values: I,
+) -> anyhow::Result<Command>
+where
+    I: Iterator<Item = (&'a str, &'a Path, &'a str)>,
+{
+    let mut command = burn_command(tools, chip, port, baud)?;
+
+    keys_or_digests_args(
+        &mut command,
+        protection,
+        cmd,
+        chip,
+        &values.collect::<Vec<_>>(),
+    )?;
+
+    Ok(command)
+}
+
+/// Build the eFuse tool command with the connection options common to all burns, but without any burn commands
+fn burn_command(
+    tools: &ToolRunner,
+    chip: Chip,
+    port: Option<&str>,
+    baud: Option<&str>,
+) -> anyhow::Result<Command> {
+    let mut command = tools.command(esptools::Tool::EspEfuse)?;
 
     command.arg("--chip").arg(chip.as_tools_str());
 
     if let Some(port) = port {
-        command.arg("--port").arg(port);
+        command.arg("--port").arg(remote::tools_port(port));
     }
 
     if let Some(baud) = baud {
         command.arg("--baud").arg(baud);
     }
 
+    if !tools.auto_reset() {
+        command.arg("--before").arg("no_reset");
+    }
+
     // ... or else we need to type "BURN" in the terminal which is impossible
     // as the provisioning process is not interactive
     command.arg("--do-not-confirm");
 
-    command.arg(cmd);
+    Ok(command)
+}
+
+/// Append the `burn_efuse` command for the given eFuse params
+fn params_args<'a, I>(command: &mut Command, values: I)
+where
+    I: Iterator<Item = (&'a str, u32)>,
+{
+    command.arg("burn_efuse");
 
+    for (key, value) in values {
+        command.arg(key);
+        command.arg(value.to_string());
+    }
+}
+
+/// Append the `burn_key` or `burn_key_digest` command for the keys or key digests stored in the given files
+fn keys_or_digests_args(
+    command: &mut Command,
+    protection: KeyProtection,
+    cmd: &str,
+    chip: Chip,
+    values: &[(&str, &Path, &str)],
+) -> anyhow::Result<()> {
+    command.arg(cmd);
     // NOTE: VERY, VERY IMPORTANT
     // As mentoned here:
     // https://docs.espressif.com/projects/esp-idf/en/v5.4/esp32s3/security/security-features-enablement-workflows.html#enable-flash-encryption-and-secure-boot-v2-externally
@@ -217,66 +612,81 @@ where
     //
     // See also:
     // https://github.com/espressif/esp-idf/issues/11888
-    if !protect_keys {
-        if matches!(chip, Chip::Esp32) {
+    if matches!(chip, Chip::Esp32) {
+        // The ESP32 eFuse tool only supports disabling both protections at once
+        if protection.read != protection.write {
+            anyhow::bail!(
+                "Different read- and write-protection of the keys is not supported on {chip}"
+            );
+        }
+
+        if !protection.read {
             command.arg("--no-protect-key");
-        } else {
-            command.arg("--no-read-protect").arg("--no-write-protect");
         }
-    }
+    } else {
+        if !protection.read {
+            command.arg("--no-read-protect");
+        }
 
-    let mut temp_files = Vec::new();
+        if !protection.write {
+            command.arg("--no-write-protect");
+        }
+    }
 
-    for (key, value, purpose) in values {
+    for (key, path, purpose) in values {
         command.arg(key);
-
-        let mut temp_file = tempfile::NamedTempFile::new()
-            .context("Creation of eFuse temp key/digest file failed")?;
-
-        temp_file
-            .write_all(value)
-            .context("Writing eFuse temp key/digest file failed")?;
-
-        temp_file
-            .flush()
-            .context("Flushing eFuse temp key/digest file failed")?;
-
-        command.arg(temp_file.path().to_string_lossy().into_owned());
-
-        temp_files.push(temp_file);
-
+        command.arg(path.to_string_lossy().into_owned());
         command.arg(purpose);
     }
 
-    burn_exec(dry_run, &mut command)
+    Ok(())
 }
 
-fn burn_exec(dry_run: bool, command: &mut Command) -> anyhow::Result<String> {
+/// Write the key or key digest into a secret file, for passing it to the eFuse tool
+fn key_temp_file(tools: &ToolRunner, value: &[u8]) -> anyhow::Result<SecretFile> {
+    tools
+        .secret_file(value)
+        .context("Creation of eFuse temp key/digest file failed")
+}
+
+fn burn_exec(tools: &ToolRunner, dry_run: bool, command: &mut Command) -> anyhow::Result<String> {
+    // The paths of the key files are not logged
+    let command_str = permissions::tool_display(command);
+
     if dry_run {
-        warn!("eFuse dry run mode: NOT executing eFuse tool command `{command:?}`");
+        warn!("eFuse dry run mode: NOT executing eFuse tool command `{command_str}`");
         return Ok("".to_string());
     }
 
-    warn!("About to execute eFuse tool command `{command:?}`...");
+    warn!("About to execute eFuse tool command `{command_str}`...");
 
-    let output = command
-        .output()
-        .with_context(|| format!("Executing the eFuse tool with command `{command:?}` failed"))?;
+    // The eFuses change (or might have changed, if the burn fails midway)
+    clear_summary_cache(tools);
+
+    let output = session::tool_output(tools, command, &[])
+        .with_context(|| format!("Executing the eFuse tool with command `{command_str}` failed"))?;
 
     if !output.status.success() {
-        anyhow::bail!(
-            "eFuse tool `{command:?}` command failed with status: {}. Is the PCB connected?\nStderr output:\n{}\nStdout output:\n{}",
+        let stderr = core::str::from_utf8(&output.stderr).unwrap_or("???");
+        let stdout = core::str::from_utf8(&output.stdout).unwrap_or("???");
+
+        let err = anyhow::anyhow!(
+            "eFuse tool `{command_str}` command failed with status: {}. Is the PCB connected?\nStderr output:\n{stderr}\nStdout output:\n{stdout}",
             output.status,
-            core::str::from_utf8(&output.stderr).unwrap_or("???"),
-            core::str::from_utf8(&output.stdout).unwrap_or("???")
         );
+
+        if stderr.contains("Failed to connect") || stdout.contains("Failed to connect") {
+            return Err(err.context(ConnectFailed));
+        }
+
+        return Err(err);
     }
 
     let output = core::str::from_utf8(&output.stdout)
-        .with_context(|| format!("Loading the eFuse tool `{command:?}` command output failed"))
+        .with_context(|| format!("Loading the eFuse tool `{command_str}` command output failed"))
         .map(str::to_string)?;
 
-    info!("eFuse tool command `{command:?}` executed. Output:\n{output}");
+    info!("eFuse tool command `{command_str}` executed. Output:\n{output}");
 
     Ok(output)
 }
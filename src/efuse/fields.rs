@@ -0,0 +1,485 @@
+//! The known eFuse fields of each chip, with a short description
+//!
+//! Derived from the ESP-IDF eFuse tables (`components/efuse/<chip>/esp_efuse_table.csv`) as used by `espefuse.py`.
+//! Only the fields which might be burned or read during provisioning are listed - i.e. the BLOCK0 params,
+//! the system data fields and the user/key blocks - not the factory calibration data.
+//!
+//! The tables are not exhaustive, so the names which are not listed are only warned about by default
+//! (see `Config::efuse_allow_unknown`), and `Config::efuse_native_tables` is the authoritative source.
+
+use crate::bundle::Chip;
+
+/// A known eFuse field of a chip
+#[derive(Debug, Copy, Clone)]
+pub struct KnownField {
+    /// The name of the field, as used by `espefuse.py`
+    pub name: &'static str,
+    /// A short description of the field
+    pub description: &'static str,
+}
+
+const fn field(name: &'static str, description: &'static str) -> KnownField {
+    KnownField { name, description }
+}
+
+/// Return the known eFuse fields of the given chip
+pub fn known_fields(chip: Chip) -> impl Iterator<Item = &'static KnownField> {
+    let (common, specific): (&[KnownField], &[KnownField]) = match chip {
+        Chip::Esp32 => (&[], ESP32),
+        Chip::Esp32c2 => (&[], ESP32C2),
+        Chip::Esp32c3 => (COMMON, ESP32C3),
+        Chip::Esp32c6 => (COMMON, ESP32C6),
+        Chip::Esp32h2 => (COMMON, ESP32H2),
+        Chip::Esp32p4 => (COMMON, ESP32P4),
+        Chip::Esp32s2 => (COMMON, ESP32S2),
+        Chip::Esp32s3 => (COMMON, ESP32S3),
+    };
+
+    common.iter().chain(specific.iter())
+}
+
+/// Return the known eFuse field of the given chip with the given name, if any
+pub fn known_field(chip: Chip, name: &str) -> Option<&'static KnownField> {
+    known_fields(chip).find(|field| field.name == name)
+}
+
+/// Return the URL of the eFuse documentation of the given chip
+pub fn docs_url(chip: Chip) -> String {
+    format!(
+        "https://docs.espressif.com/projects/esp-idf/en/latest/{}/api-reference/system/efuse.html",
+        chip.as_tools_str()
+    )
+}
+
+/// The fields common to the chips with the ESP32-S2/ESP32-C3 style eFuse controller
+const COMMON: &[KnownField] = &[
+    field("WR_DIS", "Disable programming of individual eFuses"),
+    field("RD_DIS", "Disable reading from BLOCK_KEY0-5"),
+    field(
+        "DIS_FORCE_DOWNLOAD",
+        "Disable forcing the chip into download mode",
+    ),
+    field("DIS_TWAI", "Disable the TWAI controller"),
+    field(
+        "SOFT_DIS_JTAG",
+        "Disable JTAG by software (can be re-enabled with HMAC)",
+    ),
+    field("DIS_PAD_JTAG", "Permanently disable the JTAG pads"),
+    field(
+        "DIS_DOWNLOAD_MANUAL_ENCRYPT",
+        "Disable flash encryption in download boot modes",
+    ),
+    field(
+        "SPI_BOOT_CRYPT_CNT",
+        "Enable flash encryption when 1 or 3 bits are set",
+    ),
+    field(
+        "SECURE_BOOT_KEY_REVOKE0",
+        "Revoke the first secure boot key",
+    ),
+    field(
+        "SECURE_BOOT_KEY_REVOKE1",
+        "Revoke the second secure boot key",
+    ),
+    field(
+        "SECURE_BOOT_KEY_REVOKE2",
+        "Revoke the third secure boot key",
+    ),
+    field("KEY_PURPOSE_0", "Purpose of BLOCK_KEY0"),
+    field("KEY_PURPOSE_1", "Purpose of BLOCK_KEY1"),
+    field("KEY_PURPOSE_2", "Purpose of BLOCK_KEY2"),
+    field("KEY_PURPOSE_3", "Purpose of BLOCK_KEY3"),
+    field("KEY_PURPOSE_4", "Purpose of BLOCK_KEY4"),
+    field("KEY_PURPOSE_5", "Purpose of BLOCK_KEY5"),
+    field("SECURE_BOOT_EN", "Enable secure boot"),
+    field(
+        "SECURE_BOOT_AGGRESSIVE_REVOKE",
+        "Enable aggressive secure boot key revocation",
+    ),
+    field("FLASH_TPUW", "Flash power-up wait time after a reset"),
+    field("DIS_DOWNLOAD_MODE", "Disable all download boot modes"),
+    field("ENABLE_SECURITY_DOWNLOAD", "Enable Secure Download mode"),
+    field("UART_PRINT_CONTROL", "Control of the ROM UART printing"),
+    field(
+        "FORCE_SEND_RESUME",
+        "Force the ROM to send a resume command during SPI boot",
+    ),
+    field(
+        "SECURE_VERSION",
+        "Secure version for the anti-rollback feature",
+    ),
+    field(
+        "DISABLE_WAFER_VERSION_MAJOR",
+        "Disable the check of the wafer version major",
+    ),
+    field(
+        "DISABLE_BLK_VERSION_MAJOR",
+        "Disable the check of the eFuse block version major",
+    ),
+    field("MAC", "The factory MAC address"),
+    field("WAFER_VERSION_MINOR", "Minor chip revision"),
+    field("WAFER_VERSION_MAJOR", "Major chip revision"),
+    field("PKG_VERSION", "Package version"),
+    field(
+        "BLK_VERSION_MINOR",
+        "Minor version of the system data block",
+    ),
+    field(
+        "BLK_VERSION_MAJOR",
+        "Major version of the system data block",
+    ),
+    field("OPTIONAL_UNIQUE_ID", "Optional unique 128-bit ID"),
+    field("BLOCK_USR_DATA", "User data (BLOCK3)"),
+    field("CUSTOM_MAC", "Custom MAC address (in BLOCK3)"),
+    field("BLOCK_KEY0", "Key 0 or user data (BLOCK4)"),
+    field("BLOCK_KEY1", "Key 1 or user data (BLOCK5)"),
+    field("BLOCK_KEY2", "Key 2 or user data (BLOCK6)"),
+    field("BLOCK_KEY3", "Key 3 or user data (BLOCK7)"),
+    field("BLOCK_KEY4", "Key 4 or user data (BLOCK8)"),
+    field("BLOCK_KEY5", "Key 5 or user data (BLOCK9)"),
+    field(
+        "BLOCK_SYS_DATA2",
+        "System data part 2 or user data (BLOCK10)",
+    ),
+];
+
+const ESP32: &[KnownField] = &[
+    field("WR_DIS", "Disable programming of individual eFuses"),
+    field("RD_DIS", "Disable reading from BLOCK1-3"),
+    field(
+        "FLASH_CRYPT_CNT",
+        "Enable flash encryption when an odd number of bits are set",
+    ),
+    field(
+        "UART_DOWNLOAD_DIS",
+        "Disable the UART download mode (ECO V3+)",
+    ),
+    field("MAC", "The factory MAC address"),
+    field("MAC_CRC", "CRC8 of the factory MAC address"),
+    field("DISABLE_APP_CPU", "Disable the APP CPU"),
+    field("DISABLE_BT", "Disable Bluetooth"),
+    field("CHIP_PACKAGE", "Chip package identifier"),
+    field("CHIP_VER_REV1", "Chip revision bit 1"),
+    field("CHIP_VER_REV2", "Chip revision bit 2"),
+    field("WAFER_VERSION_MINOR", "Minor chip revision"),
+    field("XPD_SDIO_REG", "Power up the SDIO/flash LDO at reset"),
+    field("XPD_SDIO_TIEH", "SDIO/flash LDO voltage (0: 1.8V, 1: 3.3V)"),
+    field(
+        "XPD_SDIO_FORCE",
+        "Use the eFuses to configure the SDIO/flash LDO instead of MTDI",
+    ),
+    field("SPI_PAD_CONFIG_CLK", "Override the SD_CLK pad"),
+    field("SPI_PAD_CONFIG_Q", "Override the SD_DATA_0 pad"),
+    field("SPI_PAD_CONFIG_D", "Override the SD_DATA_1 pad"),
+    field("SPI_PAD_CONFIG_HD", "Override the SD_DATA_2 pad"),
+    field("SPI_PAD_CONFIG_CS0", "Override the SD_CMD pad"),
+    field(
+        "FLASH_CRYPT_CONFIG",
+        "Flash encryption key tweak configuration",
+    ),
+    field("CODING_SCHEME", "Coding scheme of BLOCK1-3"),
+    field(
+        "CONSOLE_DEBUG_DISABLE",
+        "Disable the ROM BASIC interpreter fallback",
+    ),
+    field("DISABLE_SDIO_HOST", "Disable the SDIO host"),
+    field("ABS_DONE_0", "Enable secure boot V1"),
+    field("ABS_DONE_1", "Enable secure boot V2"),
+    field("JTAG_DISABLE", "Disable JTAG"),
+    field(
+        "DISABLE_DL_ENCRYPT",
+        "Disable flash encryption in the UART bootloader",
+    ),
+    field(
+        "DISABLE_DL_DECRYPT",
+        "Disable flash decryption in the UART bootloader",
+    ),
+    field(
+        "DISABLE_DL_CACHE",
+        "Disable the flash cache in the UART bootloader",
+    ),
+    field("KEY_STATUS", "Use BLOCK3 as user data rather than as a key"),
+    field("CUSTOM_MAC_CRC", "CRC8 of the custom MAC address"),
+    field("CUSTOM_MAC", "Custom MAC address (in BLOCK3)"),
+    field("MAC_VERSION", "Version of the custom MAC address"),
+    field(
+        "BLK3_PART_RESERVE",
+        "BLOCK3 partially served for ADC calibration data",
+    ),
+    field("ADC_VREF", "ADC reference voltage calibration"),
+    field("BLOCK1", "Flash encryption key"),
+    field("BLOCK2", "Secure boot key"),
+    field("BLOCK3", "Variable block 3 (user data)"),
+];
+
+const ESP32C2: &[KnownField] = &[
+    field("WR_DIS", "Disable programming of individual eFuses"),
+    field("RD_DIS", "Disable reading from BLOCK_KEY0"),
+    field("WDT_DELAY_SEL", "RTC watchdog timeout threshold selection"),
+    field("DIS_PAD_JTAG", "Permanently disable the JTAG pads"),
+    field("DIS_DOWNLOAD_ICACHE", "Disable the ICache in download mode"),
+    field(
+        "DIS_DOWNLOAD_MANUAL_ENCRYPT",
+        "Disable flash encryption in download boot modes",
+    ),
+    field(
+        "SPI_BOOT_CRYPT_CNT",
+        "Enable flash encryption when 1 or 3 bits are set",
+    ),
+    field(
+        "XTS_KEY_LENGTH_256",
+        "Use the whole BLOCK_KEY0 as a 256-bit XTS-AES key",
+    ),
+    field("UART_PRINT_CONTROL", "Control of the ROM UART printing"),
+    field(
+        "FORCE_SEND_RESUME",
+        "Force the ROM to send a resume command during SPI boot",
+    ),
+    field("DIS_DOWNLOAD_MODE", "Disable all download boot modes"),
+    field("DIS_DIRECT_BOOT", "Disable direct boot mode"),
+    field("ENABLE_SECURITY_DOWNLOAD", "Enable Secure Download mode"),
+    field("FLASH_TPUW", "Flash power-up wait time after a reset"),
+    field("SECURE_BOOT_EN", "Enable secure boot"),
+    field(
+        "SECURE_VERSION",
+        "Secure version for the anti-rollback feature",
+    ),
+    field("CUSTOM_MAC_USED", "Use the custom MAC address"),
+    field(
+        "DISABLE_WAFER_VERSION_MAJOR",
+        "Disable the check of the wafer version major",
+    ),
+    field(
+        "DISABLE_BLK_VERSION_MAJOR",
+        "Disable the check of the eFuse block version major",
+    ),
+    field("USER_DATA", "User data (BLOCK1)"),
+    field("CUSTOM_MAC", "Custom MAC address (in BLOCK1)"),
+    field("MAC", "The factory MAC address"),
+    field("WAFER_VERSION_MINOR", "Minor chip revision"),
+    field("WAFER_VERSION_MAJOR", "Major chip revision"),
+    field("PKG_VERSION", "Package version"),
+    field(
+        "BLK_VERSION_MINOR",
+        "Minor version of the system data block",
+    ),
+    field(
+        "BLK_VERSION_MAJOR",
+        "Major version of the system data block",
+    ),
+    field("BLOCK_KEY0", "Key 0 or user data (BLOCK3)"),
+];
+
+const ESP32C3: &[KnownField] = &[
+    field("DIS_ICACHE", "Disable the ICache"),
+    field(
+        "DIS_USB_JTAG",
+        "Disable the USB-JTAG function of the USB-Serial-JTAG peripheral",
+    ),
+    field("DIS_DOWNLOAD_ICACHE", "Disable the ICache in download mode"),
+    field(
+        "DIS_USB_SERIAL_JTAG",
+        "Disable the USB-Serial-JTAG peripheral",
+    ),
+    field("USB_EXCHG_PINS", "Exchange the USB D+ and D- pins"),
+    field("VDD_SPI_AS_GPIO", "Use the VDD_SPI pin as a GPIO"),
+    field("WDT_DELAY_SEL", "RTC watchdog timeout threshold selection"),
+    field("DIS_DIRECT_BOOT", "Disable direct boot mode"),
+    field(
+        "DIS_USB_SERIAL_JTAG_ROM_PRINT",
+        "Disable the ROM printing over USB-Serial-JTAG",
+    ),
+    field(
+        "DIS_USB_SERIAL_JTAG_DOWNLOAD_MODE",
+        "Disable the UART download mode over USB-Serial-JTAG",
+    ),
+    field("ERR_RST_ENABLE", "Use the BLOCK0 check for reset on errors"),
+];
+
+const ESP32C6: &[KnownField] = &[
+    field("SWAP_UART_SDIO_EN", "Swap the UART and SDIO pads"),
+    field("DIS_ICACHE", "Disable the ICache"),
+    field(
+        "DIS_USB_JTAG",
+        "Disable the USB-JTAG function of the USB-Serial-JTAG peripheral",
+    ),
+    field("DIS_DOWNLOAD_ICACHE", "Disable the ICache in download mode"),
+    field(
+        "DIS_USB_SERIAL_JTAG",
+        "Disable the USB-Serial-JTAG peripheral",
+    ),
+    field("USB_EXCHG_PINS", "Exchange the USB D+ and D- pins"),
+    field("VDD_SPI_AS_GPIO", "Use the VDD_SPI pin as a GPIO"),
+    field("WDT_DELAY_SEL", "RTC watchdog timeout threshold selection"),
+    field("DIS_DIRECT_BOOT", "Disable direct boot mode"),
+    field(
+        "DIS_USB_SERIAL_JTAG_ROM_PRINT",
+        "Disable the ROM printing over USB-Serial-JTAG",
+    ),
+    field(
+        "DIS_USB_SERIAL_JTAG_DOWNLOAD_MODE",
+        "Disable the UART download mode over USB-Serial-JTAG",
+    ),
+    field(
+        "CRYPT_DPA_ENABLE",
+        "Enable the anti-DPA protection of the crypto peripherals",
+    ),
+];
+
+const ESP32H2: &[KnownField] = &[
+    field("DIS_ICACHE", "Disable the ICache"),
+    field(
+        "DIS_USB_JTAG",
+        "Disable the USB-JTAG function of the USB-Serial-JTAG peripheral",
+    ),
+    field(
+        "DIS_USB_SERIAL_JTAG",
+        "Disable the USB-Serial-JTAG peripheral",
+    ),
+    field("USB_EXCHG_PINS", "Exchange the USB D+ and D- pins"),
+    field("VDD_SPI_AS_GPIO", "Use the VDD_SPI pin as a GPIO"),
+    field("WDT_DELAY_SEL", "RTC watchdog timeout threshold selection"),
+    field("DIS_DIRECT_BOOT", "Disable direct boot mode"),
+    field(
+        "DIS_USB_SERIAL_JTAG_ROM_PRINT",
+        "Disable the ROM printing over USB-Serial-JTAG",
+    ),
+    field(
+        "DIS_USB_SERIAL_JTAG_DOWNLOAD_MODE",
+        "Disable the UART download mode over USB-Serial-JTAG",
+    ),
+    field(
+        "ECDSA_FORCE_USE_HARDWARE_K",
+        "Force the ECDSA peripheral to use a hardware generated k",
+    ),
+    field(
+        "CRYPT_DPA_ENABLE",
+        "Enable the anti-DPA protection of the crypto peripherals",
+    ),
+];
+
+const ESP32P4: &[KnownField] = &[
+    field(
+        "DIS_USB_JTAG",
+        "Disable the USB-JTAG function of the USB-Serial-JTAG peripheral",
+    ),
+    field(
+        "DIS_USB_SERIAL_JTAG",
+        "Disable the USB-Serial-JTAG peripheral",
+    ),
+    field(
+        "USB_PHY_SEL",
+        "Exchange the USB OTG and USB-Serial-JTAG PHYs",
+    ),
+    field("WDT_DELAY_SEL", "RTC watchdog timeout threshold selection"),
+    field("DIS_DIRECT_BOOT", "Disable direct boot mode"),
+    field(
+        "DIS_USB_SERIAL_JTAG_ROM_PRINT",
+        "Disable the ROM printing over USB-Serial-JTAG",
+    ),
+    field(
+        "DIS_USB_SERIAL_JTAG_DOWNLOAD_MODE",
+        "Disable the UART download mode over USB-Serial-JTAG",
+    ),
+    field(
+        "DIS_USB_OTG_DOWNLOAD_MODE",
+        "Disable the download mode over USB OTG",
+    ),
+    field(
+        "FLASH_TYPE",
+        "Flash type (0: 4 data lines, 1: 8 data lines)",
+    ),
+];
+
+const ESP32S2: &[KnownField] = &[
+    field("DIS_ICACHE", "Disable the ICache"),
+    field("DIS_DCACHE", "Disable the DCache"),
+    field("DIS_DOWNLOAD_ICACHE", "Disable the ICache in download mode"),
+    field("DIS_DOWNLOAD_DCACHE", "Disable the DCache in download mode"),
+    field("DIS_USB", "Disable the USB OTG peripheral"),
+    field("DIS_BOOT_REMAP", "Disable the boot ROM remapping"),
+    field("USB_EXCHG_PINS", "Exchange the USB D+ and D- pins"),
+    field("USB_EXT_PHY_ENABLE", "Use an external USB PHY"),
+    field(
+        "USB_FORCE_NOPERSIST",
+        "Do not persist the USB OTG download mode",
+    ),
+    field("VDD_SPI_XPD", "Power up the VDD_SPI LDO at reset"),
+    field("VDD_SPI_TIEH", "VDD_SPI LDO voltage (0: 1.8V, 1: 3.3V)"),
+    field(
+        "VDD_SPI_FORCE",
+        "Use the eFuses to configure the VDD_SPI LDO instead of GPIO45",
+    ),
+    field("WDT_DELAY_SEL", "RTC watchdog timeout threshold selection"),
+    field("DIS_LEGACY_SPI_BOOT", "Disable the legacy SPI boot mode"),
+    field(
+        "FLASH_TYPE",
+        "Flash type (0: 4 data lines, 1: 8 data lines)",
+    ),
+    field(
+        "DIS_USB_DOWNLOAD_MODE",
+        "Disable the download mode over USB OTG",
+    ),
+    field(
+        "PIN_POWER_SELECTION",
+        "Power supply of the GPIO33-37 pins (0: VDD3P3_CPU, 1: VDD_SPI)",
+    ),
+];
+
+const ESP32S3: &[KnownField] = &[
+    field("DIS_ICACHE", "Disable the ICache"),
+    field("DIS_DCACHE", "Disable the DCache"),
+    field("DIS_DOWNLOAD_ICACHE", "Disable the ICache in download mode"),
+    field("DIS_DOWNLOAD_DCACHE", "Disable the DCache in download mode"),
+    field("DIS_USB_OTG", "Disable the USB OTG peripheral"),
+    field("DIS_APP_CPU", "Disable the APP CPU"),
+    field("USB_EXCHG_PINS", "Exchange the USB D+ and D- pins"),
+    field("USB_EXT_PHY_EN", "Use an external USB PHY"),
+    field("VDD_SPI_XPD", "Power up the VDD_SPI LDO at reset"),
+    field("VDD_SPI_TIEH", "VDD_SPI LDO voltage (0: 1.8V, 1: 3.3V)"),
+    field(
+        "VDD_SPI_FORCE",
+        "Use the eFuses to configure the VDD_SPI LDO instead of GPIO45",
+    ),
+    field("WDT_DELAY_SEL", "RTC watchdog timeout threshold selection"),
+    field(
+        "DIS_USB_JTAG",
+        "Disable the USB-JTAG function of the USB-Serial-JTAG peripheral",
+    ),
+    field(
+        "DIS_USB_SERIAL_JTAG",
+        "Disable the USB-Serial-JTAG peripheral",
+    ),
+    field(
+        "STRAP_JTAG_SEL",
+        "Select the JTAG interface by the GPIO3 strapping pin",
+    ),
+    field(
+        "USB_PHY_SEL",
+        "Exchange the USB OTG and USB-Serial-JTAG PHYs",
+    ),
+    field("DIS_DIRECT_BOOT", "Disable direct boot mode"),
+    field(
+        "DIS_USB_SERIAL_JTAG_ROM_PRINT",
+        "Disable the ROM printing over USB-Serial-JTAG",
+    ),
+    field("FLASH_ECC_MODE", "Flash ECC mode in the ROM"),
+    field(
+        "DIS_USB_SERIAL_JTAG_DOWNLOAD_MODE",
+        "Disable the UART download mode over USB-Serial-JTAG",
+    ),
+    field(
+        "PIN_POWER_SELECTION",
+        "Power supply of the GPIO33-37 pins (0: VDD3P3_CPU, 1: VDD_SPI)",
+    ),
+    field(
+        "FLASH_TYPE",
+        "Flash type (0: 4 data lines, 1: 8 data lines)",
+    ),
+    field("FLASH_PAGE_SIZE", "Flash page size"),
+    field("FLASH_ECC_EN", "Enable the flash ECC"),
+    field(
+        "DIS_USB_OTG_DOWNLOAD_MODE",
+        "Disable the download mode over USB OTG",
+    ),
+];
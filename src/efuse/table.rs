@@ -0,0 +1,77 @@
+//! The ESP-IDF eFuse tables, for validating the eFuse names of the bundles and eFuse plans
+//!
+//! The eFuse fields are described by the ESP-IDF eFuse tables (`components/efuse/<chip>/esp_efuse_table.csv`),
+//! which need to be supplied in a directory as `<chip>.csv` files (e.g. `esp32s3.csv`).
+
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+
+use crate::bundle::Chip;
+
+/// The length of BLOCK0 to BLOCK10, in words
+const BLOCK_LENS: [u32; 11] = [6, 6, 8, 8, 8, 8, 8, 8, 8, 8, 8];
+
+/// Return the names of the eFuse fields described in the ESP-IDF eFuse table of the chip
+pub fn field_names(tables: &str, chip: Chip) -> anyhow::Result<Vec<String>> {
+    let path = Path::new(tables).join(format!("{}.csv", chip.as_tools_str()));
+
+    let table = fs::read_to_string(&path)
+        .with_context(|| format!("Reading the eFuse table `{}` failed", path.display()))?;
+
+    let mut names = Vec::new();
+
+    // Rows without a field name continue the field of the preceding row
+    for (index, line) in table.lines().enumerate() {
+        let line = line.trim();
+
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+
+        let invalid = || {
+            format!(
+                "Invalid row {} in the eFuse table `{}`",
+                index + 1,
+                path.display()
+            )
+        };
+
+        let mut columns = line.splitn(5, ',').map(str::trim);
+
+        let name = columns.next().unwrap_or_default();
+        let block_len = columns
+            .next()
+            .and_then(|block| block.strip_prefix("EFUSE_BLK"))
+            .and_then(|block| block.parse::<usize>().ok())
+            .and_then(|block| BLOCK_LENS.get(block))
+            .with_context(invalid)?
+            * 32;
+        let start = columns
+            .next()
+            .and_then(|start| start.parse::<u32>().ok())
+            .with_context(invalid)?;
+        columns
+            .next()
+            .and_then(|count| {
+                if count == "MAX_BLK_LEN" {
+                    block_len.checked_sub(start)
+                } else {
+                    count.parse::<u32>().ok()
+                }
+            })
+            .filter(|count| start + count <= block_len)
+            .with_context(invalid)?;
+
+        if name.is_empty() {
+            if names.is_empty() {
+                anyhow::bail!(invalid());
+            }
+        } else {
+            names.push(name.to_string());
+        }
+    }
+
+    Ok(names)
+}
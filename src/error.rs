@@ -0,0 +1,169 @@
+//! The error type of the library API (`run`, `plan`, the bundle loaders and the bundle logs uploaders)
+
+use core::fmt::{self, Debug, Display};
+
+use crate::{efuse, Failure};
+
+/// The error returned by the library API, categorized so that embedders can tell
+/// e.g. a transient connection problem worth retrying from a broken bundle
+#[derive(Debug)]
+#[non_exhaustive]
+pub enum Error {
+    /// Loading or preparing the bundle failed (e.g. no bundle was found for the PCB, or the bundle is invalid)
+    Bundle(ErrorSource),
+    /// Connecting to the chip over the serial port failed (e.g. no chip on the port, or the port is busy)
+    Connection(ErrorSource),
+    /// Flashing the chip failed
+    Flash(ErrorSource),
+    /// Reading or burning the eFuses failed
+    Efuse(ErrorSource),
+    /// An I/O error (e.g. reading a bundle file or writing the logs)
+    Io(ErrorSource),
+    /// The run was canceled (quit) by the operator before it was complete
+    Canceled,
+    /// Any other error (e.g. a plugin, the app run or a measurement failed)
+    Other(ErrorSource),
+}
+
+impl Error {
+    /// Create a `Bundle` error
+    pub(crate) fn bundle(err: anyhow::Error) -> Self {
+        Self::Bundle(ErrorSource(err))
+    }
+
+    /// Return the cause of the error, if any
+    pub fn cause(&self) -> Option<&ErrorSource> {
+        match self {
+            Self::Bundle(source)
+            | Self::Connection(source)
+            | Self::Flash(source)
+            | Self::Efuse(source)
+            | Self::Io(source)
+            | Self::Other(source) => Some(source),
+            Self::Canceled => None,
+        }
+    }
+
+    /// Return the `Failure` of the factory run the error is about, if any
+    pub fn failure(&self) -> Option<Failure> {
+        match self {
+            Self::Canceled => Some(Failure::Incomplete),
+            _ => self
+                .cause()
+                .and_then(|source| source.0.downcast_ref::<Failure>().copied()),
+        }
+    }
+
+    /// Return `true` if the error is likely transient, so that retrying the operation might succeed
+    /// (e.g. a connection or an I/O error)
+    pub fn is_transient(&self) -> bool {
+        matches!(self, Self::Connection(_) | Self::Io(_))
+    }
+}
+
+impl Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.cause() {
+            Some(source) => Display::fmt(source, f),
+            None => write!(f, "Canceled by the operator"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.cause()
+            .map(|source| source as &(dyn std::error::Error + 'static))
+    }
+}
+
+impl From<anyhow::Error> for Error {
+    fn from(err: anyhow::Error) -> Self {
+        let err = match err.downcast::<Error>() {
+            Ok(err) => return err,
+            Err(err) => err,
+        };
+
+        if connection_failed(&err) {
+            return Self::Connection(ErrorSource(err));
+        }
+
+        match err.downcast_ref::<Failure>() {
+            Some(Failure::BundleLoad | Failure::Bundle) => Self::Bundle(ErrorSource(err)),
+            Some(Failure::Flash) => Self::Flash(ErrorSource(err)),
+            Some(Failure::Efuse | Failure::EfuseReadout) => Self::Efuse(ErrorSource(err)),
+            Some(Failure::Incomplete) => Self::Canceled,
+            Some(_) => Self::Other(ErrorSource(err)),
+            None if err.downcast_ref::<std::io::Error>().is_some() => Self::Io(ErrorSource(err)),
+            None => Self::Other(ErrorSource(err)),
+        }
+    }
+}
+
+impl From<std::io::Error> for Error {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(ErrorSource::new(err))
+    }
+}
+
+/// The cause of an `Error`
+///
+/// Displaying it with `{:#}` renders the whole chain of causes, which is also available
+/// via `std::error::Error::source`, starting from the `Error`
+pub struct ErrorSource(anyhow::Error);
+
+impl ErrorSource {
+    /// Create an error cause from an error
+    pub fn new<E>(err: E) -> Self
+    where
+        E: std::error::Error + Send + Sync + 'static,
+    {
+        Self(anyhow::Error::new(err))
+    }
+
+    /// Create an error cause from a message
+    pub fn msg<M>(msg: M) -> Self
+    where
+        M: Display + Debug + Send + Sync + 'static,
+    {
+        Self(anyhow::Error::msg(msg))
+    }
+
+    /// Return the error of the given type in the chain of causes, if any
+    pub fn downcast_ref<E>(&self) -> Option<&E>
+    where
+        E: Display + Debug + Send + Sync + 'static,
+    {
+        self.0.downcast_ref()
+    }
+}
+
+impl Debug for ErrorSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl Display for ErrorSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+impl std::error::Error for ErrorSource {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Return `true` if the error is about connecting to the chip, rather than about what was done with it
+fn connection_failed(err: &anyhow::Error) -> bool {
+    err.downcast_ref::<efuse::ConnectFailed>().is_some()
+        || err.chain().any(|cause| {
+            cause.downcast_ref::<serialport::Error>().is_some()
+                || matches!(
+                    cause.downcast_ref::<espflash::error::Error>(),
+                    Some(espflash::error::Error::Connection(_))
+                )
+        })
+}
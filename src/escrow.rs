@@ -0,0 +1,156 @@
+//! Per-device flash encryption keys generated on the host, and their escrow
+//!
+//! The escrow is also used for the generated NVS encryption keys (see `NvsKeys::escrow`).
+//! The keys are encrypted with the configured escrow public key (see `EscrowKey`) and uploaded with the PCB logs,
+//! so that only the holder of the private key can recover the key of a PCB (e.g. for analyzing a field return).
+//! Before a key is burned, its escrow is also persisted in a local directory (see `persist`), so that the key
+//! of a PCB whose provisioning fails after the burning (and whose logs are therefore not uploaded) is not lost
+
+use std::fs;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use chrono::Utc;
+
+use zeroize::Zeroizing;
+
+use crate::utils::private_dir;
+use crate::utils::secret::Secret;
+use crate::EscrowKey;
+
+/// The eFuse key purpose of the generated keys
+pub const PURPOSE: &str = "XTS_AES_128_KEY";
+
+/// The eFuse block where the keys are burned, unless configured otherwise
+pub const DEFAULT_BLOCK: &str = "BLOCK_KEY0";
+
+/// The size of the generated keys
+const KEY_SIZE: usize = 32;
+
+/// Generate a new random flash encryption key
+pub fn generate() -> anyhow::Result<Secret> {
+    let mut key = Zeroizing::new([0; KEY_SIZE]);
+
+    getrandom::getrandom(key.as_mut())
+        .map_err(|err| anyhow::anyhow!("{err}"))
+        .context("Generating the flash encryption key failed")?;
+
+    Ok(Secret::new(key.to_vec()))
+}
+
+/// The name (without an extension) of the PCB logs file with an escrowed flash encryption key
+pub const FLASH_KEY_FILE: &str = "flash-encryption-key";
+
+/// The name (without an extension) of the PCB logs file with escrowed NVS keys
+pub const NVS_KEYS_FILE: &str = "nvs-keys";
+
+/// Encrypt the key with the escrow public key
+///
+/// Return the name of the PCB logs file with the encrypted key (`name` with an extension of the encryption),
+/// and its content
+pub fn escrow(escrow_key: &EscrowKey, name: &str, key: &[u8]) -> anyhow::Result<(String, String)> {
+    match escrow_key {
+        EscrowKey::Rsa { path } => Ok((
+            format!("{name}.rsa"),
+            encrypt_rsa(path, key)
+                .with_context(|| format!("Encrypting the key with RSA key `{path}` failed"))?,
+        )),
+        EscrowKey::Age { recipient } => Ok((
+            format!("{name}.age"),
+            encrypt_age(recipient, key).with_context(|| {
+                format!("Encrypting the key for age recipient `{recipient}` failed")
+            })?,
+        )),
+    }
+}
+
+/// Return the default directory where the escrowed keys are persisted (`espfactory/escrow` in the per-user
+/// cache directory of the host)
+pub fn default_dir() -> PathBuf {
+    private_dir::user_dir("escrow")
+}
+
+/// Persist the escrowed key (as returned by `escrow`) of the bundle in the directory, and sync it to the disk
+///
+/// Return the path of the persisted file
+pub fn persist(
+    dir: &Path,
+    bundle_name: &str,
+    file: &str,
+    content: &str,
+) -> anyhow::Result<PathBuf> {
+    private_dir::ensure(dir).context("Creating the escrow directory failed")?;
+
+    let stem = Utc::now().format("%Y%m%dT%H%M%S%.6fZ");
+
+    let path = dir.join(format!("{bundle_name}_{stem}_{file}"));
+    let tmp_path = dir.join(format!("{bundle_name}_{stem}_{file}.tmp"));
+
+    let mut tmp = fs::File::create(&tmp_path).context("Persisting the escrowed key failed")?;
+    tmp.write_all(content.as_bytes())
+        .and_then(|_| tmp.sync_all())
+        .context("Persisting the escrowed key failed")?;
+    drop(tmp);
+
+    fs::rename(&tmp_path, &path).context("Persisting the escrowed key failed")?;
+
+    Ok(path)
+}
+
+/// Encrypt the data with RSA-OAEP (SHA-256) and the RSA public key in the PEM file, returning it base64-encoded
+#[cfg(feature = "escrow")]
+fn encrypt_rsa(path: &str, data: &[u8]) -> anyhow::Result<String> {
+    use base64::Engine;
+
+    use rsa::pkcs1::DecodeRsaPublicKey;
+    use rsa::pkcs8::DecodePublicKey;
+
+    let pem = std::fs::read_to_string(path).context("Reading the RSA public key failed")?;
+
+    let key = rsa::RsaPublicKey::from_public_key_pem(&pem)
+        .or_else(|_| rsa::RsaPublicKey::from_pkcs1_pem(&pem))
+        .context("Parsing the RSA public key failed")?;
+
+    let encrypted = key.encrypt(
+        &mut rsa::rand_core::OsRng,
+        rsa::Oaep::new::<sha2::Sha256>(),
+        data,
+    )?;
+
+    Ok(base64::engine::general_purpose::STANDARD.encode(encrypted))
+}
+
+/// Encrypt the data for the age X25519 recipient, returning it as an ASCII-armored age file
+#[cfg(feature = "escrow")]
+fn encrypt_age(recipient: &str, data: &[u8]) -> anyhow::Result<String> {
+    let recipient = recipient
+        .parse::<age::x25519::Recipient>()
+        .map_err(|err| anyhow::anyhow!("{err}"))
+        .context("Parsing the age recipient failed")?;
+
+    let encryptor =
+        age::Encryptor::with_recipients(core::iter::once(&recipient as &dyn age::Recipient))?;
+
+    let mut encrypted = Vec::new();
+
+    let armored =
+        age::armor::ArmoredWriter::wrap_output(&mut encrypted, age::armor::Format::AsciiArmor)?;
+
+    let mut writer = encryptor.wrap_output(armored)?;
+    writer.write_all(data)?;
+    writer.finish()?.finish()?;
+
+    Ok(String::from_utf8(encrypted)?)
+}
+
+#[cfg(not(feature = "escrow"))]
+fn encrypt_rsa(_path: &str, _data: &[u8]) -> anyhow::Result<String> {
+    anyhow::bail!("Escrowing the keys requires the `escrow` feature")
+}
+
+#[cfg(not(feature = "escrow"))]
+fn encrypt_age(_recipient: &str, _data: &[u8]) -> anyhow::Result<String> {
+    anyhow::bail!("Escrowing the keys requires the `escrow` feature")
+}
@@ -0,0 +1,136 @@
+//! A stream of typed provisioning events, for embedders rendering their own progress (e.g. GUI frontends)
+//! without depending on the internals of the console UI
+
+use std::sync::{mpsc, Arc, Mutex};
+use std::time::Duration;
+
+use serde::Serialize;
+
+/// A provisioning event
+///
+/// New events might be added in future versions, so embedders should ignore the events they do not handle
+#[derive(Clone, Debug, PartialEq, Serialize)]
+#[serde(tag = "type")]
+#[non_exhaustive]
+pub enum ProvisioningEvent {
+    /// The provisioning of a PCB started, also when the operator cancels a PCB and starts over with the next one
+    PcbStarted,
+    /// A provisioning step started (e.g. `readout`, `flash` or `app-run`)
+    StepStarted { step: String },
+    /// A provisioning step - or a sub-step like `flash-<partition>` - finished
+    ///
+    /// `error` is the error message if the step failed
+    StepFinished {
+        step: String,
+        duration: Duration,
+        error: Option<String>,
+    },
+    /// Flashing an image progressed; `current` of `total` bytes of the image at address `addr` are flashed
+    FlashProgress {
+        addr: u32,
+        current: usize,
+        total: usize,
+    },
+    /// An eFuse was burned; `name` is the eFuse without its value (e.g. `key-BLOCK_KEY0-XTS_AES_128_KEY`)
+    EfuseBurned { name: String },
+    /// The PCB was provisioned successfully with the bundle
+    Provisioned { bundle: String },
+    /// An error was shown to the operator
+    Error { title: String, message: String },
+    /// No bundle is left in the bundle pool (e.g. the bundles' directory or the S3 bucket) to load a random bundle from
+    BundlePoolEmpty { message: String },
+    /// Uploading the PCB logs failed
+    ///
+    /// With a spool directory the provisioning continues, and the logs are uploaded on a later retry
+    UploadFailed { message: String },
+}
+
+/// The subscribers of the provisioning events of a factory run (see `run` and `Factory::events`)
+///
+/// The clones of an `Events` instance share the subscribers
+#[derive(Clone, Debug, Default)]
+pub struct Events(Arc<Mutex<Vec<mpsc::SyncSender<ProvisioningEvent>>>>);
+
+impl Events {
+    /// The number of events buffered for a subscriber which is not receiving them
+    const SUBSCRIBER_BOUND: usize = 64;
+
+    /// Create a new instance without subscribers
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Subscribe to the provisioning events
+    ///
+    /// The events are sent to all subscribers until their receiver is dropped. The receivers are
+    /// disconnected once all clones of the `Events` instance are dropped, i.e. after the run is complete.
+    /// Subscribe before calling `run` (or `Factory::run`) so that no events are missed
+    ///
+    /// Only a few events are buffered for each subscriber: the `FlashProgress` events are dropped while
+    /// the buffer is full, and the other events wait for the subscriber to receive them, so the receiver
+    /// needs to be drained for as long as it is not dropped
+    pub fn subscribe(&self) -> mpsc::Receiver<ProvisioningEvent> {
+        let (sender, receiver) = mpsc::sync_channel(Self::SUBSCRIBER_BOUND);
+
+        self.0.lock().unwrap().push(sender);
+
+        receiver
+    }
+
+    /// Send the event to all subscribers, forgetting those whose receiver is dropped
+    ///
+    /// A `FlashProgress` event is dropped for the subscribers whose buffer is full, as it is superseded
+    /// by the next one anyway
+    pub(crate) fn emit(&self, event: ProvisioningEvent) {
+        let progress = matches!(event, ProvisioningEvent::FlashProgress { .. });
+
+        self.0.lock().unwrap().retain(|sender| {
+            if progress {
+                !matches!(
+                    sender.try_send(event.clone()),
+                    Err(mpsc::TrySendError::Disconnected(_))
+                )
+            } else {
+                sender.send(event.clone()).is_ok()
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn emit_drops_progress_while_full() {
+        let events = Events::new();
+        let received = events.subscribe();
+
+        drop(events.subscribe());
+
+        let total = Events::SUBSCRIBER_BOUND * 2;
+
+        for current in 0..total {
+            events.emit(ProvisioningEvent::FlashProgress {
+                addr: 0x10000,
+                current,
+                total,
+            });
+        }
+
+        // The subscriber whose receiver is dropped is forgotten, the one lagging behind is kept
+        assert_eq!(events.0.lock().unwrap().len(), 1);
+        assert_eq!(received.try_iter().count(), Events::SUBSCRIBER_BOUND);
+
+        events.emit(ProvisioningEvent::Provisioned {
+            bundle: "PCB1.bundle".into(),
+        });
+
+        assert_eq!(
+            received.try_iter().collect::<Vec<_>>(),
+            [ProvisioningEvent::Provisioned {
+                bundle: "PCB1.bundle".into(),
+            }]
+        );
+    }
+}
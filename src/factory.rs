@@ -0,0 +1,158 @@
+//! A builder-style API for embedding the factory in other applications
+
+use crate::events::{Events, ProvisioningEvent};
+use crate::loader::{BundleLoader, Loader};
+use crate::uploader::BundleLogsUploader;
+use crate::{Config, Error};
+
+/// A builder for running the factory from another application
+///
+/// A convenience over `run` and `plan`, e.g.:
+/// ```ignore
+/// Factory::new(config)
+///     .with_base_loader(Loader::new(&base_url, false, None, &[])?)
+///     .with_loader(Loader::new(&url, true, None, &[])?)
+///     .with_logs_uploader(LogsUploader::new(&logs_url, None)?)
+///     .with_ui(false)
+///     .run()
+///     .await?;
+/// ```
+///
+/// Any `BundleLoader` and `BundleLogsUploader` implementation can be plugged in. The base loaders default to
+/// the loaders supported OOTB (`Loader`), use `with_base_loaders` for custom ones. Without a loader, loading a bundle
+/// fails, and without a logs uploader, the logs are discarded.
+///
+/// Note that the factory logs are shown in the UI and attached to the PCB logs only if `LOGGER`
+/// is installed as the logger (i.e. with `log::set_logger`) by the embedding application.
+pub struct Factory<B = Loader, L = (), U = ()> {
+    conf: Config,
+    log_level: log::LevelFilter,
+    events: Events,
+    base_loaders: Vec<B>,
+    loader: L,
+    logs_uploader: U,
+}
+
+impl Factory {
+    /// Create a factory with the given configuration
+    pub fn new(conf: Config) -> Self {
+        Self {
+            conf,
+            log_level: log::LevelFilter::Info,
+            events: Events::new(),
+            base_loaders: Vec::new(),
+            loader: (),
+            logs_uploader: (),
+        }
+    }
+}
+
+impl<B, L, U> Factory<B, L, U>
+where
+    B: BundleLoader,
+    L: BundleLoader,
+    U: BundleLogsUploader,
+{
+    /// Add a loader of a base bundle layer, on top of the layers added so far (see `run`)
+    pub fn with_base_loader(mut self, loader: B) -> Self {
+        self.base_loaders.push(loader);
+        self
+    }
+
+    /// Replace the loaders of the base bundle layers, from the bottom layer upwards (see `run`)
+    pub fn with_base_loaders<B2, I>(self, loaders: I) -> Factory<B2, L, U>
+    where
+        B2: BundleLoader,
+        I: IntoIterator<Item = B2>,
+    {
+        Factory {
+            conf: self.conf,
+            log_level: self.log_level,
+            events: self.events,
+            base_loaders: loaders.into_iter().collect(),
+            loader: self.loader,
+            logs_uploader: self.logs_uploader,
+        }
+    }
+
+    /// Set the loader of the bundle (see `run`)
+    pub fn with_loader<L2>(self, loader: L2) -> Factory<B, L2, U>
+    where
+        L2: BundleLoader,
+    {
+        Factory {
+            conf: self.conf,
+            log_level: self.log_level,
+            events: self.events,
+            base_loaders: self.base_loaders,
+            loader,
+            logs_uploader: self.logs_uploader,
+        }
+    }
+
+    /// Set the uploader of the PCB logs (see `run`)
+    pub fn with_logs_uploader<U2>(self, logs_uploader: U2) -> Factory<B, L, U2>
+    where
+        U2: BundleLogsUploader,
+    {
+        Factory {
+            conf: self.conf,
+            log_level: self.log_level,
+            events: self.events,
+            base_loaders: self.base_loaders,
+            loader: self.loader,
+            logs_uploader,
+        }
+    }
+
+    /// Set whether to run with the interactive console UI (see `Config::set_no_ui`)
+    pub fn with_ui(mut self, ui: bool) -> Self {
+        self.conf.set_no_ui(!ui);
+        self
+    }
+
+    /// Set the length of the log buffer of the interactive console UI (see `Config::set_log_buffer_len`)
+    pub fn with_log_buffer_len(mut self, len: usize) -> Self {
+        self.conf.set_log_buffer_len(len);
+        self
+    }
+
+    /// Set the log level; `Info` by default
+    pub fn with_log_level(mut self, log_level: log::LevelFilter) -> Self {
+        self.log_level = log_level;
+        self
+    }
+
+    /// Subscribe to the provisioning events of the factory (see `Events::subscribe`)
+    pub fn events(&self) -> std::sync::mpsc::Receiver<ProvisioningEvent> {
+        self.events.subscribe()
+    }
+
+    /// Return the configuration of the factory
+    pub fn config(&self) -> &Config {
+        &self.conf
+    }
+
+    /// Return the configuration of the factory for modification
+    pub fn config_mut(&mut self) -> &mut Config {
+        &mut self.conf
+    }
+
+    /// Run the factory (see `run`)
+    pub async fn run(self) -> Result<(), Error> {
+        crate::run(
+            &self.conf,
+            self.log_level,
+            &self.events,
+            self.base_loaders,
+            self.loader,
+            self.logs_uploader,
+        )
+        .await
+    }
+
+    /// Return a plan of what would be flashed and burned for the bundle with the given ID (see `plan`)
+    pub async fn plan(self, bundle_id: Option<&str>) -> Result<String, Error> {
+        crate::plan(&self.conf, self.base_loaders, self.loader, bundle_id).await
+    }
+}
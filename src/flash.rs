@@ -1,28 +1,41 @@
+use std::collections::{BTreeMap, VecDeque};
 use std::fs;
 use std::io::Write;
+use std::path::Path;
 use std::process::Command;
+use std::sync::{mpsc, Mutex};
 
-use alloc::borrow::Cow;
+use alloc::sync::Arc;
 use alloc::vec::Vec;
 
 use anyhow::Context;
 
-use espflash::connection::reset::{ResetAfterOperation, ResetBeforeOperation};
-use espflash::elf::{ElfFirmwareImage, RomSegment};
-use espflash::flasher::{FlashSettings, FlashSize, Flasher, ProgressCallbacks};
-use espflash::image_format::IdfBootloaderFormat;
-use espflash::targets::XtalFrequency;
+use espflash::connection::reset::soft_reset;
+use espflash::connection::{Connection, ResetAfterOperation, ResetBeforeOperation};
+use espflash::flasher::{
+    FlashData as ImageFlashData, FlashSettings, FlashSize, Flasher, ProgressCallbacks,
+};
+use espflash::image_format::idf::IdfBootloaderFormat;
+use espflash::image_format::Segment;
+use espflash::target::XtalFrequency;
 
 use log::{info, warn};
 
+use md5::{Digest, Md5};
+
 use serialport::{FlowControl, SerialPortInfo, SerialPortType, UsbPortInfo};
-use tempfile::NamedTempFile;
 
-use crate::bundle::{Chip, FlashData};
+use crate::bundle::{Chip, ChipRevision, FlashData};
+use crate::permissions::{self, serial_open_error, ToolRunner};
+use crate::remote;
+use crate::session;
+use crate::utils::secret::Secret;
+use crate::{FlashResetAfter, FlashResetBefore};
 
 extern crate alloc;
 
-pub(crate) const DEFAULT_BAUD_RATE: u32 = 112500;
+/// The baud rate of the ROM bootloader, used for connecting to the chip and - by default - for the app logs
+pub(crate) const DEFAULT_BAUD_RATE: u32 = 115_200;
 
 /// Return the default bootloader image for the given chip
 ///
@@ -35,15 +48,13 @@ pub(crate) const DEFAULT_BAUD_RATE: u32 = 112500;
 pub fn default_bootloader(chip: Chip, flash_size: Option<FlashSize>) -> anyhow::Result<Vec<u8>> {
     let elf_data: &[u8] = &[];
 
-    let image = ElfFirmwareImage::try_from(elf_data)?;
-
-    let image = bootloader_format(&image, chip, flash_size)?;
+    let image = bootloader_format(elf_data, chip, flash_size)?;
 
     let mut file = Vec::new();
 
     // There should always be a bootloader segment and it is always the first one
     // TODO: Internal `espflash` detail, maybe ask them to expose this in a more user-friendly way
-    file.write_all(&image.flash_segments().next().unwrap().data)
+    file.write_all(image.flash_segments().next().unwrap().data())
         .context("Loading default bootloader failed")?;
 
     Ok(file)
@@ -54,16 +65,19 @@ pub fn default_bootloader(chip: Chip, flash_size: Option<FlashSize>) -> anyhow::
 /// Arguments:
 /// - `elf_data` - the ELF file data
 /// - `chip` - the chip for which the binary image is needed
-pub fn elf2bin(elf_data: &[u8], chip: Chip) -> anyhow::Result<Vec<u8>> {
-    let image = ElfFirmwareImage::try_from(elf_data)?;
-
-    let image = bootloader_format(&image, chip, None)?;
+/// - `flash_size` - the flash size recorded in the image header. If not provided, 4MB is assumed
+pub fn elf2bin(
+    elf_data: &[u8],
+    chip: Chip,
+    flash_size: Option<FlashSize>,
+) -> anyhow::Result<Vec<u8>> {
+    let image = bootloader_format(elf_data, chip, flash_size)?;
 
     let mut file = Vec::new();
 
     for segment in image.ota_segments() {
         if file.is_empty() {
-            file.write_all(&segment.data)?;
+            file.write_all(segment.data())?;
         } else {
             unreachable!("Found multiple segments in an App image");
         }
@@ -75,58 +89,132 @@ pub fn elf2bin(elf_data: &[u8], chip: Chip) -> anyhow::Result<Vec<u8>> {
 /// Flash a binary image to the device
 ///
 /// Arguments:
+/// - `tools` - the runner of the tools, with the fallback speeds for connecting to the chip
 /// - `port` - the serial port to use for flashing. If not provided, the first available port where an ESP chip is detected will be used
 /// - `chip` - the chip which is expected to be flashed. Used for double-checking
 /// - `speed` - the baud rate to use for flashing. If not provided, the default baud rate (115_200) will be used
 /// - `flash_size` - the flash size to be used for flashing. If not provided, the default flash size (4MB) will be used
-/// - `flash_data` - the binary image data to be flashed
+/// - `flash_data` - the binary image data to be flashed; each image is flashed as soon as it is available
+///   (e.g. with an `EncryptPipeline`, once it is encrypted)
+/// - `diff` - if `true`, images whose MD5 checksum matches the one of their flash region on the chip are not flashed
+/// - `verify` - if `true`, the MD5 checksums of the flashed images are read back from the chip (see `Config::flash_verify`)
 /// - `progress` - the progress callbacks to be used during flashing
+///
+/// Returns the MD5 checksums of the flashed images as computed by the chip, by image offset;
+/// empty if the flashing was not verified
 #[allow(clippy::too_many_arguments)]
 pub fn flash<P>(
+    tools: &ToolRunner,
     port: Option<&str>,
     chip: Chip,
     use_stub: bool,
     speed: Option<u32>,
     flash_size: Option<FlashSize>,
-    flash_data: Vec<FlashData>,
+    flash_data: impl IntoIterator<Item = anyhow::Result<FlashData>>,
+    diff: bool,
+    verify: bool,
     dry_run: bool,
     progress: &mut P,
-) -> anyhow::Result<()>
+) -> anyhow::Result<Vec<(u32, u128)>>
 where
     P: ProgressCallbacks + Send + Sync + 'static,
 {
-    let mut flasher = new(port, chip, use_stub, speed)?;
+    let mut flasher = connect(tools, port, Some(chip), use_stub, speed)?;
 
     if let Some(flash_size) = flash_size {
         flasher.set_flash_size(flash_size);
     }
 
-    let segments = flash_data
-        .iter()
-        .map(|data| RomSegment {
-            addr: data.offset,
-            data: Cow::Borrowed(data.data.as_ref()),
-        })
-        .collect::<Vec<_>>();
+    if dry_run {
+        warn!("Flash dry run mode: flashing skipped");
+    }
+
+    let verify = verify && !dry_run;
+
+    let mut digests = Vec::new();
+
+    for flash_data in flash_data {
+        let flash_data = flash_data?;
+
+        if diff && unchanged(&mut flasher, &flash_data)? {
+            info!(
+                "Flash for addr `0x{:08x}` unchanged, skipped",
+                flash_data.offset
+            );
+
+            if verify {
+                // Already verified by the diffing
+                digests.push((flash_data.offset, md5(flash_data.data.as_slice())));
+            }
 
+            progress.init(flash_data.offset, flash_data.data.len());
+            progress.finish(true);
+
+            continue;
+        }
+
+        let segment = Segment::new(flash_data.offset, flash_data.data.as_ref());
+
+        if !dry_run {
+            flasher
+                .write_bins_to_flash(&[segment], progress)
+                .context("Flashing failed")?;
+        }
+
+        if verify {
+            let digest = checksum(&mut flasher, &flash_data)?;
+
+            digests.push((flash_data.offset, digest));
+        }
+    }
+
+    // The chip is connected to without a reset after each flashed image (see `connect_at`),
+    // so that it is reset only once, after all images are flashed
     if !dry_run {
-        flasher
-            .write_bins_to_flash(&segments, Some(progress))
-            .context("Flashing failed")?;
-    } else {
-        warn!("Flash dry run mode: flashing skipped");
+        match reset_after(tools) {
+            ResetAfterOperation::HardReset => flasher.connection().reset(),
+            ResetAfterOperation::NoResetNoStub => soft_reset(flasher.connection(), true, use_stub),
+            ResetAfterOperation::NoReset => Ok(()),
+        }
+        .context("Resetting the chip after flashing failed")?;
     }
 
-    Ok(())
+    Ok(digests)
+}
+
+/// Return `true` if the content of the flash region of the given image matches the image,
+/// by comparing the MD5 checksum of the region as computed by the chip with the one of the image
+fn unchanged(flasher: &mut Flasher, flash_data: &FlashData) -> anyhow::Result<bool> {
+    let expected = md5(flash_data.data.as_slice());
+
+    Ok(checksum(flasher, flash_data)? == expected)
+}
+
+/// Return the MD5 checksum of the given data, in the form returned by the chip
+pub fn md5(data: &[u8]) -> u128 {
+    u128::from_be_bytes(Md5::digest(data).into())
+}
+
+/// Return the MD5 checksum of the flash region of the given image, as computed by the chip
+fn checksum(flasher: &mut Flasher, flash_data: &FlashData) -> anyhow::Result<u128> {
+    flasher
+        .checksum_md5(flash_data.offset, flash_data.data.len() as _)
+        .with_context(|| {
+            format!(
+                "Reading back the MD5 checksum of the flash for addr `0x{:08x}` failed",
+                flash_data.offset
+            )
+        })
 }
 
 pub fn run_app_esptool(
+    tools: &ToolRunner,
     port: Option<&str>,
     chip: Chip,
     use_stub: bool,
     speed: Option<u32>,
 ) -> anyhow::Result<()> {
-    let mut command = Command::new(esptools::Tool::EspTool.mount()?.path());
+    let mut command = tools.command(esptools::Tool::EspTool)?;
 
     command.arg("--chip").arg(chip.as_tools_str());
 
@@ -135,7 +223,7 @@ pub fn run_app_esptool(
     }
 
     if let Some(port) = port {
-        command.arg("--port").arg(port);
+        command.arg("--port").arg(remote::tools_port(port));
     }
 
     if let Some(speed) = speed {
@@ -144,8 +232,7 @@ pub fn run_app_esptool(
 
     command.arg("run");
 
-    let output = command
-        .output()
+    let output = session::tool_output(tools, &mut command, &[])
         .with_context(|| format!("Executing `esptool.py` with command `{command:?}` failed"))?;
 
     if !output.status.success() {
@@ -163,6 +250,7 @@ pub fn run_app_esptool(
 
 #[allow(clippy::too_many_arguments)]
 pub fn erase(
+    tools: &ToolRunner,
     port: Option<&str>,
     chip: Chip,
     use_stub: bool,
@@ -170,7 +258,7 @@ pub fn erase(
     flash_size: Option<FlashSize>,
     dry_run: bool,
 ) -> anyhow::Result<()> {
-    let mut flasher = new(port, chip, use_stub, speed)?;
+    let mut flasher = connect(tools, port, Some(chip), use_stub, speed)?;
 
     if let Some(flash_size) = flash_size {
         flasher.set_flash_size(flash_size);
@@ -187,21 +275,26 @@ pub fn erase(
 
 #[allow(clippy::too_many_arguments)]
 pub fn flash_esptool<P>(
+    tools: &ToolRunner,
     port: Option<&str>,
     chip: Chip,
     use_stub: bool,
     speed: Option<u32>,
     flash_size: Option<FlashSize>,
-    flash_data: Vec<FlashData>,
+    compress: Option<bool>,
+    flash_data: impl IntoIterator<Item = anyhow::Result<FlashData>>,
     dry_run: bool,
     progress: &mut P,
 ) -> anyhow::Result<()>
 where
     P: ProgressCallbacks + Send + Sync + 'static,
 {
-    for flash_data in &flash_data {
-        let mut data_temp_file =
-            NamedTempFile::new().context("Creating a temporary file failed")?;
+    check_chip_esptool(tools, port, chip, speed)?;
+
+    for flash_data in flash_data {
+        let flash_data = flash_data?;
+
+        let mut data_temp_file = tools.temp_file()?;
 
         data_temp_file
             .write_all(&flash_data.data)
@@ -213,40 +306,22 @@ where
 
         progress.init(flash_data.offset, flash_data.data.len());
 
-        let mut command = Command::new(esptools::Tool::EspTool.mount()?.path());
-
-        command.arg("--chip").arg(chip.as_tools_str());
-
-        if !use_stub {
-            command.arg("--no-stub");
-        }
-
-        if let Some(port) = port {
-            command.arg("--port").arg(port);
-        }
-
-        if let Some(speed) = speed {
-            command.arg("--baud").arg(speed.to_string());
-        }
-
-        command.arg("--after").arg("no_reset");
-
-        command
-            .arg("write_flash")
-            .arg(format!("0x{:x}", flash_data.offset))
-            .arg(data_temp_file.path());
-
-        if let Some(flash_size) = flash_size {
-            command.arg("--flash_size").arg(format!("{flash_size}"));
-        }
-
-        // Necessary for chips in Secure Download Mode
-        command.arg("--force");
+        let mut command = flash_esptool_command(
+            tools,
+            port,
+            chip,
+            use_stub,
+            speed,
+            flash_size,
+            compress,
+            flash_data.offset,
+            data_temp_file.path(),
+        )?;
 
         if !dry_run {
             warn!("About to execute `esptool.py` command `{command:?}`...");
 
-            let output = command.output().with_context(|| {
+            let output = session::tool_output(tools, &mut command, &[]).with_context(|| {
                 format!("Executing `esptool.py` with command `{command:?}` failed")
             })?;
 
@@ -263,7 +338,7 @@ where
             warn!("Flash dry run mode: flashing skipped");
         }
 
-        progress.finish();
+        progress.finish(false);
     }
 
     Ok(())
@@ -271,6 +346,7 @@ where
 
 #[allow(clippy::too_many_arguments)]
 pub fn erase_esptool(
+    tools: &ToolRunner,
     port: Option<&str>,
     chip: Chip,
     use_stub: bool,
@@ -278,34 +354,14 @@ pub fn erase_esptool(
     _flash_size: Option<FlashSize>,
     dry_run: bool,
 ) -> anyhow::Result<()> {
-    let mut command = Command::new(esptools::Tool::EspTool.mount()?.path());
+    check_chip_esptool(tools, port, chip, speed)?;
 
-    command.arg("--chip").arg(chip.as_tools_str());
-
-    if !use_stub {
-        command.arg("--no-stub");
-    }
-
-    if let Some(port) = port {
-        command.arg("--port").arg(port);
-    }
-
-    if let Some(speed) = speed {
-        command.arg("--baud").arg(speed.to_string());
-    }
-
-    command.arg("--after").arg("no_reset");
-
-    command.arg("erase_flash");
-
-    // Necessary for chips in Secure Download Mode
-    command.arg("--force");
+    let mut command = erase_esptool_command(tools, port, chip, use_stub, speed)?;
 
     if !dry_run {
         warn!("About to execute `esptool.py` command `{command:?}`...");
 
-        let output = command
-            .output()
+        let output = session::tool_output(tools, &mut command, &[])
             .with_context(|| format!("Executing `esptool.py` with command `{command:?}` failed"))?;
 
         if !output.status.success() {
@@ -325,18 +381,386 @@ pub fn erase_esptool(
     Ok(())
 }
 
-pub fn encrypt(offset: usize, raw_data: &[u8], key: &[u8]) -> anyhow::Result<Vec<u8>> {
-    let key_file = NamedTempFile::new().context("Creating temp key file failed")?;
-    fs::write(key_file.path(), key).context("Creating temp key file failed")?;
+/// The information detected from the connected chip before flashing it
+#[derive(Debug, Clone, Default)]
+pub struct ChipInfo {
+    /// The silicon revision of the chip, if detected
+    pub revision: Option<ChipRevision>,
+    /// The size of the flash of the chip, if detected
+    pub flash_size: Option<FlashSize>,
+}
+
+/// Connect to the chip, check that it is the expected one and detect its silicon revision and flash size
+///
+/// The revision and the flash size are `None` if they could not be detected
+/// (e.g. because the chip is in Secure Download mode)
+pub fn detect(
+    tools: &ToolRunner,
+    port: Option<&str>,
+    chip: Chip,
+    use_stub: bool,
+    speed: Option<u32>,
+) -> anyhow::Result<ChipInfo> {
+    let mut flasher = connect(tools, port, Some(chip), use_stub, speed)?;
+
+    let flash_size = match flasher.flash_detect() {
+        Ok(flash_size) => flash_size,
+        Err(err) => {
+            warn!("Detecting the flash size failed: {err}");
+            None
+        }
+    };
+
+    let revision = match flasher.device_info() {
+        Ok(info) => info
+            .revision
+            .map(|(major, minor)| ChipRevision::new(major, minor)),
+        Err(err) => {
+            warn!("Detecting the chip revision failed: {err}");
+            None
+        }
+    };
+
+    Ok(ChipInfo {
+        revision,
+        flash_size,
+    })
+}
+
+/// Check that the connected chip is the expected one and detect its silicon revision and flash size
+/// using `esptool.py`
+///
+/// The revision and the flash size are `None` if they could not be detected
+/// (e.g. because the chip is in Secure Download mode)
+pub fn detect_esptool(
+    tools: &ToolRunner,
+    port: Option<&str>,
+    chip: Chip,
+    use_stub: bool,
+    speed: Option<u32>,
+) -> anyhow::Result<ChipInfo> {
+    let revision = check_chip_esptool(tools, port, chip, speed)?;
+
+    let mut command = esptool_command(tools, port, chip, use_stub, speed)?;
+
+    command.arg("flash_id");
+
+    let output = session::tool_output(tools, &mut command, &[])
+        .with_context(|| format!("Executing `esptool.py` with command `{command:?}` failed"))?;
+
+    let stdout = core::str::from_utf8(&output.stdout).unwrap_or("???");
+
+    if !output.status.success() {
+        warn!(
+            "Detecting the flash size with command `{command:?}` failed with status: {}.\nStderr output:\n{}",
+            output.status,
+            core::str::from_utf8(&output.stderr).unwrap_or("???")
+        );
+
+        return Ok(ChipInfo {
+            revision,
+            flash_size: None,
+        });
+    }
+
+    let detected = stdout
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Detected flash size:"))
+        .map(str::trim)
+        .last();
+
+    let flash_size = detected.and_then(|detected| {
+        serde_json::from_value::<FlashSize>(serde_json::Value::String(detected.to_string())).ok()
+    });
+
+    if flash_size.is_none() {
+        warn!(
+            "Unknown flash size `{}` detected",
+            detected.unwrap_or("???")
+        );
+    }
+
+    Ok(ChipInfo {
+        revision,
+        flash_size,
+    })
+}
+
+/// Read `size` bytes of the flash, starting at `offset`, using `esptool.py`
+pub fn read_flash_esptool(
+    tools: &ToolRunner,
+    port: Option<&str>,
+    chip: Chip,
+    use_stub: bool,
+    speed: Option<u32>,
+    offset: u32,
+    size: u32,
+) -> anyhow::Result<Vec<u8>> {
+    let data_temp_file = tools.temp_file()?;
+
+    let mut command = esptool_command(tools, port, chip, use_stub, speed)?;
+
+    command
+        .arg("read_flash")
+        .arg(format!("0x{offset:x}"))
+        .arg(format!("0x{size:x}"))
+        .arg(data_temp_file.path());
+
+    info!("About to execute `esptool.py` command `{command:?}`...");
+
+    let output = session::tool_output(tools, &mut command, &[data_temp_file.path()])
+        .with_context(|| format!("Executing `esptool.py` with command `{command:?}` failed"))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "`{command:?}` command failed with status: {}.\nStderr output:\n{}",
+            output.status,
+            core::str::from_utf8(&output.stderr).unwrap_or("???")
+        );
+    }
+
+    info!("`esptool.py` command `{command:?}` executed.");
+
+    fs::read(data_temp_file.path()).context("Reading the flash content failed")
+}
+
+/// Build - but do not execute - the `esptool.py` command for flashing the given image file at the given offset
+///
+/// If `compress` is not provided, the `esptool.py` default applies
+#[allow(clippy::too_many_arguments)]
+pub fn flash_esptool_command(
+    tools: &ToolRunner,
+    port: Option<&str>,
+    chip: Chip,
+    use_stub: bool,
+    speed: Option<u32>,
+    flash_size: Option<FlashSize>,
+    compress: Option<bool>,
+    offset: u32,
+    image: &Path,
+) -> anyhow::Result<Command> {
+    let mut command = esptool_command(tools, port, chip, use_stub, speed)?;
 
-    let espsecure = esptools::Tool::EspSecure.mount()?;
+    command
+        .arg("write_flash")
+        .arg(format!("0x{offset:x}"))
+        .arg(image);
 
-    let input_file = NamedTempFile::new().context("Creating temp input file failed")?;
+    if let Some(flash_size) = flash_size {
+        command.arg("--flash_size").arg(format!("{flash_size}"));
+    }
+
+    match compress {
+        Some(true) => {
+            command.arg("--compress");
+        }
+        Some(false) => {
+            command.arg("--no-compress");
+        }
+        None => (),
+    }
+
+    // Necessary for chips in Secure Download Mode
+    command.arg("--force");
+
+    Ok(command)
+}
+
+/// Build - but do not execute - the `esptool.py` command for erasing the whole flash
+pub fn erase_esptool_command(
+    tools: &ToolRunner,
+    port: Option<&str>,
+    chip: Chip,
+    use_stub: bool,
+    speed: Option<u32>,
+) -> anyhow::Result<Command> {
+    let mut command = esptool_command(tools, port, chip, use_stub, speed)?;
+
+    command.arg("erase_flash");
+
+    // Necessary for chips in Secure Download Mode
+    command.arg("--force");
+
+    Ok(command)
+}
+
+fn esptool_command(
+    tools: &ToolRunner,
+    port: Option<&str>,
+    chip: Chip,
+    use_stub: bool,
+    speed: Option<u32>,
+) -> anyhow::Result<Command> {
+    let mut command = tools.command(esptools::Tool::EspTool)?;
+
+    command.arg("--chip").arg(chip.as_tools_str());
+
+    if !use_stub {
+        command.arg("--no-stub");
+    }
+
+    if let Some(port) = port {
+        command.arg("--port").arg(remote::tools_port(port));
+    }
+
+    if let Some(speed) = speed {
+        command.arg("--baud").arg(speed.to_string());
+    }
+
+    if !tools.auto_reset() {
+        command.arg("--before").arg("no_reset");
+    }
+
+    command.arg("--after").arg("no_reset");
+
+    Ok(command)
+}
+
+/// A pipeline encrypting all flash data destined to partitions marked as encrypted
+///
+/// The encryptions are independent of each other, so up to `threads` of them are run in parallel.
+/// The flash data is yielded in its original order, as soon as it is ready, so that the flashing
+/// of the already encrypted images can start while the remaining images are still being encrypted
+pub struct EncryptPipeline {
+    /// The flash data which is ready (encrypted or not needing encryption), by index
+    ready: BTreeMap<usize, anyhow::Result<FlashData>>,
+    /// The receiver of the flash data encrypted by the workers
+    receiver: Option<mpsc::Receiver<(usize, anyhow::Result<FlashData>)>>,
+    /// The index of the next flash data to be yielded
+    next: usize,
+    /// The total number of flash data
+    len: usize,
+}
+
+impl EncryptPipeline {
+    /// Create a new pipeline and start encrypting
+    ///
+    /// Arguments:
+    /// - `flash_data` - the flash data to be encrypted
+    /// - `key` - the `XTS_AES_128_KEY` flash encryption key; if not provided, the flash data is yielded as-is
+    /// - `threads` - the maximum number of parallel encryptions
+    /// - `tools` - the runner of the encryption tool
+    pub fn new(
+        flash_data: Vec<FlashData>,
+        key: Option<Secret>,
+        threads: usize,
+        tools: Arc<ToolRunner>,
+    ) -> Self {
+        let len = flash_data.len();
+
+        let mut ready = BTreeMap::new();
+        let mut pending = VecDeque::new();
+
+        for (index, flash_data) in flash_data.into_iter().enumerate() {
+            if key.is_some() && flash_data.encrypted_partition {
+                pending.push_back((index, flash_data));
+            } else {
+                ready.insert(index, Ok(flash_data));
+            }
+        }
+
+        let receiver = key.filter(|_| !pending.is_empty()).map(|key| {
+            let threads = threads.clamp(1, pending.len());
+
+            let pending = Arc::new(Mutex::new(pending));
+            let key = Arc::new(key);
+
+            let (sender, receiver) = mpsc::channel();
+
+            for _ in 0..threads {
+                let pending = pending.clone();
+                let key = key.clone();
+                let sender = sender.clone();
+                let tools = tools.clone();
+
+                std::thread::spawn(move || loop {
+                    let Some((index, mut flash_data)) = pending.lock().unwrap().pop_front() else {
+                        break;
+                    };
+
+                    info!(
+                        "Encrypting image for addr `0x{:08x}`, {}KB",
+                        flash_data.offset,
+                        flash_data.data.len() / 1024
+                    );
+
+                    let result = encrypt(&tools, flash_data.offset as _, &flash_data.data, &key)
+                        .map(|encrypted_data| {
+                            flash_data.data = Arc::new(encrypted_data);
+                            flash_data
+                        });
+
+                    // The pipeline is gone if sending fails, i.e. the flashing was aborted
+                    if sender.send((index, result)).is_err() {
+                        break;
+                    }
+                });
+            }
+
+            receiver
+        });
+
+        Self {
+            ready,
+            receiver,
+            next: 0,
+            len,
+        }
+    }
+}
+
+impl Iterator for EncryptPipeline {
+    type Item = anyhow::Result<FlashData>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.next >= self.len {
+            return None;
+        }
+
+        loop {
+            if let Some(flash_data) = self.ready.remove(&self.next) {
+                self.next += 1;
+
+                break Some(flash_data);
+            }
+
+            let Some((index, flash_data)) = self
+                .receiver
+                .as_ref()
+                .and_then(|receiver| receiver.recv().ok())
+            else {
+                self.next = self.len;
+
+                break Some(Err(anyhow::anyhow!(
+                    "Encryption workers terminated unexpectedly"
+                )));
+            };
+
+            self.ready.insert(index, flash_data);
+        }
+    }
+}
+
+pub fn encrypt(
+    tools: &ToolRunner,
+    offset: usize,
+    raw_data: &[u8],
+    key: &[u8],
+) -> anyhow::Result<Vec<u8>> {
+    let key_file = tools
+        .secret_file(key)
+        .context("Creating temp key file failed")?;
+
+    let input_file = tools
+        .temp_file()
+        .context("Creating temp input file failed")?;
     fs::write(input_file.path(), raw_data).context("Creating temp input file failed")?;
 
-    let output_file = NamedTempFile::new().context("Creating temp output file failed")?;
+    let output_file = tools
+        .temp_file()
+        .context("Creating temp output file failed")?;
 
-    let mut command = Command::new(espsecure.path());
+    let mut command = tools.command(esptools::Tool::EspSecure)?;
 
     command
         .arg("encrypt_flash_data")
@@ -349,7 +773,7 @@ pub fn encrypt(offset: usize, raw_data: &[u8], key: &[u8]) -> anyhow::Result<Vec
         .arg(output_file.path())
         .arg(input_file.path());
 
-    let output = command.output().with_context(|| {
+    let output = permissions::tool_run(&mut command).with_context(|| {
         "Executing the espsecure tool with command `encrypt_flash_data` failed".to_string()
     })?;
 
@@ -357,7 +781,7 @@ pub fn encrypt(offset: usize, raw_data: &[u8], key: &[u8]) -> anyhow::Result<Vec
         anyhow::bail!(
             "espsecure tool `encrypt_flash_data` command failed with status: {}. Stderr output:\n{}",
             output.status,
-            core::str::from_utf8(&output.stderr).unwrap_or("???")
+            String::from_utf8_lossy(&output.stderr)
         );
     }
 
@@ -373,23 +797,74 @@ pub fn empty_space(size: usize) -> Vec<u8> {
     chunk
 }
 
-fn new(
+/// Connect to the chip on the given serial port
+///
+/// If the chip is not provided, it is detected.
+///
+/// If connecting fails at the given speed, the configured fallback speeds are tried in order
+pub(crate) fn connect(
+    tools: &ToolRunner,
     port: Option<&str>,
-    chip: Chip,
+    chip: Option<Chip>,
     use_stub: bool,
     speed: Option<u32>,
 ) -> anyhow::Result<Flasher> {
     let port_info = get_serial_port_info(port)?;
 
-    let serial_port = serialport::new(port_info.port_name, DEFAULT_BAUD_RATE)
+    let reset_before = reset_before(tools);
+
+    let mut result = connect_at(&port_info, chip, use_stub, speed, reset_before);
+    let mut current = speed;
+
+    for &fallback in tools.speed_fallbacks() {
+        let err = match &result {
+            Err(err) if permissions::connection_failed(&format!("{err:#}")) => err,
+            _ => break,
+        };
+
+        warn!(
+            "Connecting at speed {} failed ({err:#}), retrying at speed {fallback}...",
+            current
+                .map(|speed| speed.to_string())
+                .as_deref()
+                .unwrap_or("default")
+        );
+
+        result = connect_at(&port_info, chip, use_stub, Some(fallback), reset_before);
+        current = Some(fallback);
+
+        if result.is_ok() {
+            info!("Connected at fallback speed {fallback}");
+        }
+    }
+
+    let flasher = result?;
+
+    if let Some(chip) = chip {
+        // Do not rely on `espflash` only, as writing to the wrong chip might brick it
+        check_chip(chip, &flasher.chip().to_string())?;
+    }
+
+    Ok(flasher)
+}
+
+/// Connect to the chip on the given serial port at the given speed, doing the given reset before connecting
+fn connect_at(
+    port_info: &SerialPortInfo,
+    chip: Option<Chip>,
+    use_stub: bool,
+    speed: Option<u32>,
+    reset_before: ResetBeforeOperation,
+) -> anyhow::Result<Flasher> {
+    let serial_port = serialport::new(&port_info.port_name, DEFAULT_BAUD_RATE)
         .flow_control(FlowControl::None)
         .open_native()
-        .context("Opening serial port failed")?;
+        .map_err(|err| serial_open_error(port_info, err))?;
 
     // NOTE: since `get_serial_port_info` filters out all PCI Port and Bluetooth
     //       serial ports, we can just pretend these types don't exist here.
-    let port_info = match port_info.port_type {
-        SerialPortType::UsbPort(info) => info,
+    let port_info = match &port_info.port_type {
+        SerialPortType::UsbPort(info) => info.clone(),
         SerialPortType::PciPort | SerialPortType::Unknown => {
             warn!("Matched `SerialPortType::PciPort or ::Unknown`");
             UsbPortInfo {
@@ -403,27 +878,135 @@ fn new(
         _ => unreachable!(),
     };
 
-    let flasher = espflash::flasher::Flasher::connect(
+    let connection = Connection::new(
         *Box::new(serial_port),
         port_info.clone(),
-        speed,
+        ResetAfterOperation::NoReset,
+        reset_before,
+        DEFAULT_BAUD_RATE,
+    );
+
+    Flasher::connect(
+        connection,
         use_stub,
         true,
         false,
-        Some(chip.to_flash_chip()),
-        ResetAfterOperation::NoReset,
-        ResetBeforeOperation::default(),
+        chip.map(Chip::to_flash_chip),
+        speed,
     )
-    .with_context(|| format!("Connecting to serial port {port_info:?} failed"))?;
+    .with_context(|| format!("Connecting to serial port {port_info:?} failed"))
+}
 
-    Ok(flasher)
+/// Return the reset to be done by the native flasher before connecting to the chip
+fn reset_before(tools: &ToolRunner) -> ResetBeforeOperation {
+    if !tools.auto_reset() {
+        return ResetBeforeOperation::NoReset;
+    }
+
+    match tools.reset_before() {
+        None => ResetBeforeOperation::default(),
+        Some(FlashResetBefore::DefaultReset) => ResetBeforeOperation::DefaultReset,
+        Some(FlashResetBefore::UsbReset) => ResetBeforeOperation::UsbReset,
+        Some(FlashResetBefore::NoReset) => ResetBeforeOperation::NoReset,
+        Some(FlashResetBefore::NoResetNoSync) => ResetBeforeOperation::NoResetNoSync,
+    }
+}
+
+/// Return the reset to be done by the native flasher once all images are flashed
+fn reset_after(tools: &ToolRunner) -> ResetAfterOperation {
+    match tools.reset_after() {
+        FlashResetAfter::NoReset => ResetAfterOperation::NoReset,
+        FlashResetAfter::NoResetNoStub => ResetAfterOperation::NoResetNoStub,
+        FlashResetAfter::HardReset => ResetAfterOperation::HardReset,
+    }
 }
 
-fn bootloader_format<'a>(
-    image: &'a ElfFirmwareImage,
+/// Detect the connected chip with `esptool.py` and check that it is the expected one,
+/// before anything is written to it with `esptool.py`
+///
+/// The chip type is parsed from the output of `esptool.py` even if the command itself fails
+/// (e.g. because the chip is in Secure Download mode), as the chip type is detected before executing the command
+///
+/// Returns the silicon revision of the chip, if reported by `esptool.py`
+fn check_chip_esptool(
+    tools: &ToolRunner,
+    port: Option<&str>,
+    chip: Chip,
+    speed: Option<u32>,
+) -> anyhow::Result<Option<ChipRevision>> {
+    let mut command = tools.command(esptools::Tool::EspTool)?;
+
+    if let Some(port) = port {
+        command.arg("--port").arg(remote::tools_port(port));
+    }
+
+    if let Some(speed) = speed {
+        command.arg("--baud").arg(speed.to_string());
+    }
+
+    if !tools.auto_reset() {
+        command.arg("--before").arg("no_reset");
+    }
+
+    command.arg("--after").arg("no_reset").arg("chip_id");
+
+    let output = session::tool_output(tools, &mut command, &[])
+        .with_context(|| format!("Executing `esptool.py` with command `{command:?}` failed"))?;
+
+    let stdout = core::str::from_utf8(&output.stdout).unwrap_or("???");
+
+    let detected = stdout
+        .lines()
+        .filter_map(|line| line.trim().strip_prefix("Detecting chip type..."))
+        .map(str::trim)
+        .filter(|detected| detected.to_ascii_lowercase().starts_with("esp"))
+        .last()
+        .with_context(|| {
+            format!(
+                "Detecting the chip type with command `{command:?}` failed. Is the PCB connected?\nStderr output:\n{}\nStdout output:\n{}",
+                core::str::from_utf8(&output.stderr).unwrap_or("???"),
+                stdout
+            )
+        })?;
+
+    check_chip(chip, detected)?;
+
+    // E.g. `Chip is ESP32-S3 (QFN56) (revision v0.2)`
+    let revision = stdout
+        .lines()
+        .filter_map(|line| line.split_once("(revision "))
+        .filter_map(|(_, revision)| revision.split_once(')'))
+        .filter_map(|(revision, _)| revision.parse::<ChipRevision>().ok())
+        .last();
+
+    Ok(revision)
+}
+
+/// Check that the detected chip (e.g. `ESP32-S3` or `esp32s3`) is the one the bundle is built for
+fn check_chip(chip: Chip, detected: &str) -> anyhow::Result<()> {
+    let normalized = detected
+        .chars()
+        .filter(char::is_ascii_alphanumeric)
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    if normalized != chip.as_tools_str() {
+        anyhow::bail!(
+            "Wrong chip: expected {}, found {normalized} ({detected}). Refusing to write to the chip",
+            chip.as_tools_str()
+        );
+    }
+
+    info!("Connected chip {detected} matches the bundle chip");
+
+    Ok(())
+}
+
+fn bootloader_format(
+    elf_data: &[u8],
     chip: Chip,
     flash_size: Option<FlashSize>,
-) -> anyhow::Result<IdfBootloaderFormat<'a>> {
+) -> anyhow::Result<IdfBootloaderFormat<'_>> {
     let chip = chip.to_flash_chip();
 
     let mut flash_settings = FlashSettings::default();
@@ -431,16 +1014,12 @@ fn bootloader_format<'a>(
         flash_settings.size = Some(flash_size);
     }
 
-    let flash_data = espflash::flasher::FlashData::new(None, None, None, None, flash_settings, 0)?;
-
     // To get a chip revision, the connection is needed
-    // For simplicity, the revision None is used
-    let image = chip.into_target().get_flash_image(
-        image,
-        flash_data.clone(),
-        None,
-        XtalFrequency::default(chip),
-    )?;
+    // For simplicity, the minimum revision 0 is used
+    let flash_data =
+        ImageFlashData::new(flash_settings, 0, None, chip, XtalFrequency::default(chip));
+
+    let image = IdfBootloaderFormat::new(elf_data, &flash_data, None, None, None, None)?;
 
     Ok(image)
 }
@@ -452,6 +1031,52 @@ pub(crate) fn get_serial_port_info(serial: Option<&str>) -> anyhow::Result<Seria
     find_serial_port(&ports, serial)
 }
 
+/// Wait until a new USB serial port shows up (e.g. because a PCB with a USB-JTAG-serial peripheral was inserted)
+///
+/// Blocks until a new port is detected, in which case `true` is returned, or until `cancel` is set,
+/// in which case `false` is returned
+pub(crate) fn wait_usb_serial_port_arrival(cancel: &AtomicBool) -> anyhow::Result<bool> {
+    let port_names = || {
+        detect_usb_serial_ports(false).map(|ports| {
+            ports
+                .into_iter()
+                .map(|port| port.port_name)
+                .collect::<Vec<_>>()
+        })
+    };
+
+    let mut known = port_names()?;
+
+    while !cancel.load(Ordering::SeqCst) {
+        std::thread::sleep(core::time::Duration::from_millis(200));
+
+        let current = port_names()?;
+
+        if let Some(port) = current.iter().find(|port| !known.contains(port)) {
+            info!("USB serial port {port} arrived");
+            return Ok(true);
+        }
+
+        // Ports which disappeared (i.e. the PCB was removed) count as new once they show up again
+        known = current;
+    }
+
+    Ok(false)
+}
+
+/// Return the names and the USB information of the detected USB serial ports
+pub(crate) fn usb_serial_ports() -> anyhow::Result<Vec<(String, UsbPortInfo)>> {
+    let ports = detect_usb_serial_ports(false)?
+        .into_iter()
+        .filter_map(|port| match port.port_type {
+            SerialPortType::UsbPort(info) => Some((port.port_name, info)),
+            _ => None,
+        })
+        .collect();
+
+    Ok(ports)
+}
+
 // TODO: musl
 fn detect_usb_serial_ports(list_all_ports: bool) -> anyhow::Result<Vec<SerialPortInfo>> {
     let ports = serialport::available_ports()?;
@@ -486,6 +1111,10 @@ fn find_serial_port(
     if let Some(name) = name {
         info!("Finding serial port {name}");
 
+        // Accept the device namespace form of the COM ports (e.g. `\\.\COM10`) as well
+        #[cfg(target_os = "windows")]
+        let name = name.strip_prefix(r"\\.\").unwrap_or(name);
+
         #[cfg(not(target_os = "windows"))]
         let name = std::fs::canonicalize(name).with_context(|| format!("Port {name} not found"))?;
         #[cfg(not(target_os = "windows"))]
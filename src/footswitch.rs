@@ -0,0 +1,251 @@
+//! Keyboard-free operation with a footswitch (or another single-button device)
+//!
+//! A press of the footswitch - a single configured key of the console UI, and/or a switch wired to
+//! the modem status lines of a serial port - confirms the current prompt, so that gloved operators
+//! can run the happy path without touching the keyboard
+
+use std::sync::{Arc, Weak};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
+
+use embassy_futures::select::{select, Either};
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+
+use log::{error, info};
+
+use serialport::SerialPort;
+
+use crate::input::{TaskConfirmationOutcome, TaskInput, TaskInputOutcome};
+use crate::{Footswitch, FootswitchLine};
+
+/// The polling period of the footswitch serial port
+const POLL: Duration = Duration::from_millis(20);
+
+/// The number of consecutive polls the footswitch line has to be active for a press to be registered
+const DEBOUNCE_POLLS: usize = 3;
+
+/// The footswitch of a run, pressed with its key of the console UI and/or with its serial port line
+pub(crate) struct Switch {
+    /// The key of the console UI acting as the footswitch, if any
+    key: Option<KeyCode>,
+    /// Signaled on each press of the footswitch
+    pressed: Signal<CriticalSectionRawMutex, ()>,
+}
+
+impl Switch {
+    /// Create a footswitch without a key and without a serial port, i.e. one which is never pressed
+    pub(crate) const fn new() -> Self {
+        Self {
+            key: None,
+            pressed: Signal::new(),
+        }
+    }
+
+    /// Start listening for the presses of the footswitch
+    ///
+    /// The serial port of the footswitch (if any) is closed once the returned footswitch is dropped
+    ///
+    /// # Arguments
+    /// - `footswitch` - the footswitch configuration
+    pub(crate) fn start(footswitch: &Footswitch) -> anyhow::Result<Arc<Self>> {
+        let switch = Arc::new(Self {
+            key: footswitch.key.as_deref().map(parse_key).transpose()?,
+            pressed: Signal::new(),
+        });
+
+        if let Some(port) = footswitch.port.as_deref() {
+            let mut serial = serialport::new(port, 9600)
+                .timeout(POLL)
+                .open()
+                .with_context(|| format!("Opening footswitch port `{port}` failed"))?;
+
+            // The footswitch is expected to close the DTR line onto the monitored line
+            serial
+                .write_data_terminal_ready(true)
+                .context("Setting the DTR line of the footswitch port failed")?;
+
+            let port = port.to_string();
+            let line = footswitch.line.clone();
+            let weak = Arc::downgrade(&switch);
+
+            thread::Builder::new()
+                .name("footswitch".into())
+                .spawn(move || {
+                    if let Err(err) = poll(serial.as_mut(), &line, &weak) {
+                        error!("Footswitch port `{port}` failed: {err:#}");
+                    }
+                })
+                .unwrap();
+
+            info!("Footswitch on port `{port}`, line {:?}", footswitch.line);
+        }
+
+        Ok(switch)
+    }
+
+    /// Return `true` if the key event is a press of the footswitch key
+    pub(crate) fn is_key(&self, key: &KeyEvent) -> bool {
+        self.key
+            .is_some_and(|code| key.code == code && key.modifiers == KeyModifiers::empty())
+    }
+
+    /// Register a press of the footswitch
+    pub(crate) fn press(&self) {
+        info!("Footswitch pressed");
+
+        self.pressed.signal(());
+    }
+
+    /// Wait for a press of the footswitch
+    ///
+    /// Presses which happened before the call (e.g. while the device was being flashed) are ignored
+    async fn pressed(&self) {
+        self.pressed.reset();
+        self.pressed.wait().await;
+    }
+}
+
+/// Poll the footswitch line of the serial port, registering a press on each (debounced) activation
+///
+/// Polling stops once the footswitch is dropped
+fn poll(
+    serial: &mut dyn SerialPort,
+    line: &FootswitchLine,
+    switch: &Weak<Switch>,
+) -> anyhow::Result<()> {
+    let mut active = 0;
+
+    loop {
+        let Some(switch) = switch.upgrade() else {
+            return Ok(());
+        };
+
+        let level = match line {
+            FootswitchLine::Cts => serial.read_clear_to_send(),
+            FootswitchLine::Dsr => serial.read_data_set_ready(),
+            FootswitchLine::Cd => serial.read_carrier_detect(),
+            FootswitchLine::Ri => serial.read_ring_indicator(),
+        }
+        .context("Reading the footswitch line failed")?;
+
+        if level {
+            active += 1;
+
+            if active == DEBOUNCE_POLLS {
+                switch.press();
+            }
+        } else {
+            active = 0;
+        }
+
+        drop(switch);
+
+        thread::sleep(POLL);
+    }
+}
+
+/// Parse the footswitch key (e.g. `Space`, `F5` or a single character)
+fn parse_key(key: &str) -> anyhow::Result<KeyCode> {
+    let lower = key.to_ascii_lowercase();
+
+    let code = match lower.as_str() {
+        "space" => KeyCode::Char(' '),
+        "tab" => KeyCode::Tab,
+        "insert" => KeyCode::Insert,
+        "delete" => KeyCode::Delete,
+        "pause" => KeyCode::Pause,
+        _ => {
+            if let Some(n) = lower
+                .strip_prefix('f')
+                .and_then(|n| n.parse::<u8>().ok())
+                .filter(|n| (1..=24).contains(n))
+            {
+                KeyCode::F(n)
+            } else {
+                let mut chars = key.chars();
+
+                match (chars.next(), chars.next()) {
+                    (Some(ch), None) => KeyCode::Char(ch),
+                    _ => anyhow::bail!("Invalid footswitch key `{key}`"),
+                }
+            }
+        }
+    };
+
+    Ok(code)
+}
+
+/// A `TaskInput` which answers the prompts on a press of the footswitch, in addition to the
+/// answers of the wrapped input
+///
+/// A press:
+/// - confirms the confirmation prompts
+/// - retries - or ignores, with `ignore_errors` - on the error prompts offering to ignore the error
+/// - accepts the preselected value of the input prompts (e.g. the serial port pick); input prompts
+///   without a preselected value (e.g. the readouts) still need the wrapped input
+#[derive(Clone)]
+pub struct FootswitchInput<T> {
+    input: T,
+    switch: Arc<Switch>,
+    ignore_errors: bool,
+}
+
+impl<T> FootswitchInput<T> {
+    /// Create a new footswitch input
+    ///
+    /// # Arguments
+    /// - `input` - the wrapped input
+    /// - `switch` - the footswitch
+    /// - `ignore_errors` - whether a press ignores rather than retries the errors which can be ignored
+    pub(crate) const fn new(input: T, switch: Arc<Switch>, ignore_errors: bool) -> Self {
+        Self {
+            input,
+            switch,
+            ignore_errors,
+        }
+    }
+}
+
+impl<T> TaskInput for FootswitchInput<T>
+where
+    T: TaskInput,
+{
+    async fn wait_cancel(&mut self) -> TaskConfirmationOutcome {
+        self.input.wait_cancel().await
+    }
+
+    async fn confirm(&mut self, label: &str) -> TaskConfirmationOutcome {
+        match select(self.input.confirm(label), self.switch.pressed()).await {
+            Either::First(outcome) => outcome,
+            Either::Second(_) => TaskConfirmationOutcome::Confirmed,
+        }
+    }
+
+    async fn confirm_or_skip(&mut self, label: &str) -> TaskConfirmationOutcome {
+        match select(self.input.confirm_or_skip(label), self.switch.pressed()).await {
+            Either::First(outcome) => outcome,
+            Either::Second(_) if self.ignore_errors => TaskConfirmationOutcome::Skipped,
+            Either::Second(_) => TaskConfirmationOutcome::Confirmed,
+        }
+    }
+
+    async fn input(&mut self, label: &str, current: &str) -> TaskInputOutcome {
+        if current.is_empty() {
+            return self.input.input(label, current).await;
+        }
+
+        match select(self.input.input(label, current), self.switch.pressed()).await {
+            Either::First(outcome) => outcome,
+            Either::Second(_) => TaskInputOutcome::Done(current.to_string()),
+        }
+    }
+
+    async fn swallow(&mut self) -> ! {
+        self.input.swallow().await
+    }
+}
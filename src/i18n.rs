@@ -0,0 +1,105 @@
+//! Localization of the operator-facing UI strings (prompts, key hints and titles)
+//!
+//! A message catalog is a flat TOML map of the English UI strings (without their leading and trailing spaces)
+//! to their translations. Strings not found in the catalog are displayed in English.
+//! The logs are always in English
+
+use std::collections::HashMap;
+use std::fs;
+
+use anyhow::Context;
+
+use log::info;
+
+/// The built-in message catalogs, by language
+const BUILTIN_CATALOGS: &[(&str, &str)] = &[
+    ("es", include_str!("i18n/es.toml")),
+    ("zh", include_str!("i18n/zh.toml")),
+];
+
+/// The message catalog of the UI language
+///
+/// Empty if the UI language is English
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Catalog(Option<HashMap<String, String>>);
+
+impl Catalog {
+    /// Create an empty message catalog, i.e. the UI strings are displayed in English
+    pub(crate) const fn new() -> Self {
+        Self(None)
+    }
+
+    /// Load the message catalog of the operator-facing UI strings
+    ///
+    /// # Arguments
+    /// - `language` - the language (e.g. `zh` or `es`) of the built-in catalog to use, or `None` for English
+    /// - `messages` - an optional message catalog file, overriding - or extending - the built-in catalog
+    pub(crate) fn load(language: Option<&str>, messages: Option<&str>) -> anyhow::Result<Self> {
+        let builtin = language
+            .map(|language| {
+                BUILTIN_CATALOGS
+                    .iter()
+                    .find(|(name, _)| name.eq_ignore_ascii_case(language.trim()))
+                    .map(|(_, catalog)| *catalog)
+                    .with_context(|| {
+                        format!(
+                            "No built-in UI message catalog for language `{language}` (available: {}); provide one with `ui_messages`",
+                            BUILTIN_CATALOGS
+                                .iter()
+                                .map(|(name, _)| *name)
+                                .collect::<Vec<_>>()
+                                .join(", ")
+                        )
+                    })
+            })
+            .transpose();
+
+        // A custom catalog might be provided for a language without a built-in catalog
+        let builtin = if messages.is_some() {
+            builtin.unwrap_or_default()
+        } else {
+            builtin?
+        };
+
+        let mut catalog = builtin
+            .map(|builtin| {
+                toml::from_str::<HashMap<String, String>>(builtin)
+                    .context("Parsing the built-in UI message catalog failed")
+            })
+            .transpose()?;
+
+        if let Some(messages) = messages {
+            let messages_str = fs::read_to_string(messages)
+                .with_context(|| format!("Loading UI message catalog `{messages}` failed"))?;
+
+            let messages = toml::from_str::<HashMap<String, String>>(&messages_str)
+                .with_context(|| format!("Parsing UI message catalog `{messages}` failed"))?;
+
+            catalog.get_or_insert_with(HashMap::new).extend(messages);
+        }
+
+        if let Some(catalog) = catalog.as_ref() {
+            info!(
+                "UI language `{}` with {} messages",
+                language.unwrap_or("custom"),
+                catalog.len()
+            );
+        }
+
+        Ok(Self(catalog))
+    }
+
+    /// Translate an operator-facing UI string to the UI language
+    ///
+    /// The leading and trailing spaces of the string (used for padding in the UI) are kept as they are
+    pub(crate) fn tr(&self, text: &str) -> String {
+        let Some(translated) = self.0.as_ref().and_then(|catalog| catalog.get(text.trim())) else {
+            return text.to_string();
+        };
+
+        let start = &text[..text.len() - text.trim_start().len()];
+        let end = &text[text.trim_end().len()..];
+
+        format!("{start}{translated}{end}")
+    }
+}
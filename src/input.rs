@@ -1,5 +1,16 @@
+use std::collections::{HashMap, VecDeque};
 use std::io::Write;
+use std::sync::mpsc::{self, Receiver};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
+use anyhow::Context;
+
+use log::{error, info};
+
+use serde::Deserialize;
+
+use crate::i18n::Catalog;
 use crate::utils::futures::unblock;
 
 /// The outcome of a user confirmation of a step in the task workflow
@@ -82,6 +93,22 @@ pub enum LogInputOutcome {
     PgDown,
     LogHome,
     LogEnd,
+    /// Start typing a search pattern
+    Search,
+    /// Append a character to the search pattern being typed
+    SearchChar(char),
+    /// Remove the last character of the search pattern being typed
+    SearchBackspace,
+    /// Complete typing the search pattern
+    SearchDone,
+    /// End the search
+    SearchCancel,
+    /// Move to the next match
+    SearchNext,
+    /// Move to the previous match
+    SearchPrev,
+    /// Toggle displaying only the warning and error log lines
+    Filter,
 }
 
 pub trait LogInput {
@@ -97,20 +124,121 @@ where
     }
 }
 
+/// A line protocol on top of the standard input, for running without the interactive console UI
+///
+/// Suitable for driving the provisioning via `expect` scripts or SSH sessions. The protocol is as follows:
+/// - Confirmation prompts accept (case-insensitive):
+///   - `y`, `yes` or an empty line - confirm
+///   - `n`, `no`, `c`, `cancel` - cancel (go back to the previous step)
+///   - `i`, `ignore`, `s`, `skip` - skip the current step (only where skipping is offered)
+///   - `q`, `quit` - quit
+///   - `h`, `help`, `?` - print the accepted commands
+/// - Input prompts accept any value, except:
+///   - an empty line, `!c`, `!cancel` - cancel (start over)
+///   - `!q`, `!quit` - quit
+///   - `!h`, `!help`, `!?` - print the accepted commands
+///
+/// Invalid input is reported and the prompt is repeated. If a timeout is set and no line is received in time,
+/// or if the standard input is closed, the outcome is `Quit`
 #[derive(Clone)]
-pub struct Stdin;
+pub struct Stdin {
+    lines: Arc<Mutex<Receiver<String>>>,
+    timeout: Option<Duration>,
+    catalog: Catalog,
+}
 
 impl Stdin {
-    async fn read_line(&mut self) -> String {
-        unblock("read-line", || {
-            let mut line = String::new();
+    /// Create a new standard input line protocol
+    ///
+    /// # Arguments
+    /// - `timeout` - an optional timeout for each prompt
+    /// - `catalog` - the message catalog the prompts are translated with
+    pub fn new(timeout: Option<Duration>, catalog: Catalog) -> Self {
+        let (sender, receiver) = mpsc::channel();
+
+        // Reading from the standard input cannot be interrupted, hence the lines are read by a detached thread
+        // and the prompts wait on the channel (with a timeout) instead
+        std::thread::Builder::new()
+            .name("stdin".into())
+            .spawn(move || {
+                for line in std::io::stdin().lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+
+                    if sender.send(line.trim().to_string()).is_err() {
+                        break;
+                    }
+                }
+            })
+            .unwrap();
+
+        Self {
+            lines: Arc::new(Mutex::new(receiver)),
+            timeout,
+            catalog,
+        }
+    }
+
+    /// Prompt the operator and read a line
+    ///
+    /// Returns `None` if the prompt timed out or if the standard input is closed
+    async fn prompt(&mut self, label: &str) -> Option<String> {
+        print!("{}: ", self.catalog.tr(label));
+        std::io::stdout().flush().unwrap();
 
-            std::io::stdin().read_line(&mut line)?;
+        let lines = self.lines.clone();
+        let timeout = self.timeout;
 
-            Ok(line.trim().to_string())
+        let line = unblock("read-line", move || {
+            let lines = lines.lock().unwrap();
+
+            Ok(match timeout {
+                Some(timeout) => lines.recv_timeout(timeout).ok(),
+                None => lines.recv().ok(),
+            })
         })
         .await
-        .unwrap()
+        .unwrap();
+
+        if line.is_none() {
+            println!();
+
+            if let Some(timeout) = self.timeout {
+                println!("No input within {}s, quitting", timeout.as_secs());
+            } else {
+                println!("Input closed, quitting");
+            }
+        }
+
+        line
+    }
+
+    /// Prompt the operator until a valid confirmation command is received
+    async fn confirmation(&mut self, label: &str, skip: bool) -> TaskConfirmationOutcome {
+        loop {
+            let Some(line) = self.prompt(label).await else {
+                return TaskConfirmationOutcome::Quit;
+            };
+
+            match line.to_ascii_lowercase().as_str() {
+                "" | "y" | "yes" => return TaskConfirmationOutcome::Confirmed,
+                "n" | "no" | "c" | "cancel" => return TaskConfirmationOutcome::Canceled,
+                "i" | "ignore" | "s" | "skip" if skip => return TaskConfirmationOutcome::Skipped,
+                "q" | "quit" => return TaskConfirmationOutcome::Quit,
+                "h" | "help" | "?" => {
+                    println!("  y, yes, ENTER      - confirm");
+                    println!("  n, no, c, cancel   - cancel");
+
+                    if skip {
+                        println!("  i, ignore, s, skip - skip");
+                    }
+
+                    println!("  q, quit            - quit");
+                }
+                other => println!("Invalid input `{other}`, type `help` for the accepted commands"),
+            }
+        }
     }
 }
 
@@ -120,40 +248,205 @@ impl TaskInput for Stdin {
     }
 
     async fn confirm(&mut self, label: &str) -> TaskConfirmationOutcome {
-        print!("{label}: ");
-        std::io::stdout().flush().unwrap();
+        self.confirmation(label, false).await
+    }
 
-        match self.read_line().await.to_ascii_lowercase().as_str() {
-            "" | "y" | "yes" => TaskConfirmationOutcome::Confirmed,
-            "c" | "cancel" | "n" | "no" => TaskConfirmationOutcome::Canceled,
-            "q" | "quit" => TaskConfirmationOutcome::Quit,
-            _ => unreachable!(),
+    async fn confirm_or_skip(&mut self, label: &str) -> TaskConfirmationOutcome {
+        self.confirmation(label, true).await
+    }
+
+    async fn input(&mut self, label: &str, _current: &str) -> TaskInputOutcome {
+        loop {
+            let Some(line) = self.prompt(label).await else {
+                return TaskInputOutcome::Quit;
+            };
+
+            match line.to_ascii_lowercase().as_str() {
+                "" | "!c" | "!cancel" => return TaskInputOutcome::StartOver,
+                "!q" | "!quit" => return TaskInputOutcome::Quit,
+                "!h" | "!help" | "!?" => {
+                    println!("  <value>            - enter the value");
+                    println!("  ENTER, !c, !cancel - cancel");
+                    println!("  !q, !quit          - quit");
+                }
+                _ => return TaskInputOutcome::Done(line),
+            }
         }
     }
 
-    async fn confirm_or_skip(&mut self, label: &str) -> TaskConfirmationOutcome {
-        print!("{label}: ");
-        std::io::stdout().flush().unwrap();
+    async fn swallow(&mut self) -> ! {
+        core::future::pending().await
+    }
+}
+
+impl LogInput for Stdin {
+    async fn get(&mut self) -> LogInputOutcome {
+        core::future::pending().await
+    }
+}
 
-        match self.read_line().await.to_ascii_lowercase().as_str() {
-            "" | "y" | "yes" => TaskConfirmationOutcome::Confirmed,
-            "c" | "cancel" | "n" | "no" => TaskConfirmationOutcome::Canceled,
-            "s" | "skip" | "i" | "ignore" => TaskConfirmationOutcome::Skipped,
+/// The prefix of the environment variables answering the prompts (see `Answers`)
+const ANSWERS_ENV_PREFIX: &str = "ESPFACTORY_";
+
+/// An answer file, as loaded from TOML
+#[derive(Debug, Default, Deserialize)]
+struct AnswerFile {
+    /// The answers to the confirmation prompts, by prompt
+    #[serde(default)]
+    confirmations: HashMap<String, String>,
+    /// The answers to the input prompts, by prompt
+    #[serde(default)]
+    inputs: HashMap<String, AnswerValues>,
+}
+
+/// The answers to an input prompt
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum AnswerValues {
+    /// The same value for all PCBs
+    One(String),
+    /// One value per PCB, in the order of the PCBs
+    Many(VecDeque<String>),
+}
+
+/// Unattended task input, answering the prompts from an answer file and/or from environment variables,
+/// for fixture scripts driving the provisioning without the interactive console UI
+///
+/// The prompts are identified by their label without the key hints, e.g. `Provision?`, `Retry?`, `Device ID`
+/// or `PCB ID`. The answer file is a TOML file as follows:
+/// ```toml
+/// [confirmations]
+/// # `yes`, `no` (go back / start over), `skip` (only where skipping is offered) or `quit`
+/// "Provision?" = "yes"
+/// "Retry?" = "quit"
+/// # The answer to all other confirmations; `yes` if not provided
+/// "*" = "yes"
+///
+/// [inputs]
+/// # The same value for all PCBs...
+/// "Test JIG ID" = "JIG-7"
+/// # ... or one value per PCB, consumed in order
+/// "PCB ID" = ["PCB-0001", "PCB-0002"]
+/// ```
+///
+/// An environment variable named after the prompt (`ESPFACTORY_` followed by the label in upper case,
+/// with all non-alphanumeric characters replaced by `_`, e.g. `ESPFACTORY_DEVICE_ID` or `ESPFACTORY_RETRY`)
+/// takes precedence over the answer file.
+///
+/// An input prompt without an answer (or whose answers are exhausted) quits the run
+#[derive(Clone)]
+pub struct Answers {
+    answers: Arc<Mutex<AnswerFile>>,
+    env: bool,
+}
+
+impl Answers {
+    /// Create the unattended task input
+    ///
+    /// # Arguments
+    /// - `file` - the answer file, if any
+    /// - `env` - whether to answer the prompts from the `ESPFACTORY_*` environment variables
+    pub fn new(file: Option<&str>, env: bool) -> anyhow::Result<Self> {
+        let answers = if let Some(file) = file {
+            let content = std::fs::read_to_string(file)
+                .with_context(|| format!("Reading the answer file `{file}` failed"))?;
+
+            toml::from_str(&content)
+                .with_context(|| format!("Parsing the answer file `{file}` failed"))?
+        } else {
+            AnswerFile::default()
+        };
+
+        Ok(Self {
+            answers: Arc::new(Mutex::new(answers)),
+            env,
+        })
+    }
+
+    /// Return the prompt of the label, i.e. the label without the key hints (e.g. ` <[Y]es/ENTER, [Q]uit>`)
+    fn prompt(label: &str) -> &str {
+        label.split(" <").next().unwrap_or(label).trim()
+    }
+
+    /// Return the answer from the environment variable of the prompt, if any
+    fn env_answer(&self, prompt: &str) -> Option<String> {
+        if !self.env {
+            return None;
+        }
+
+        let name = prompt
+            .split(|c: char| !c.is_ascii_alphanumeric())
+            .filter(|part| !part.is_empty())
+            .map(str::to_ascii_uppercase)
+            .collect::<Vec<_>>()
+            .join("_");
+
+        std::env::var(format!("{ANSWERS_ENV_PREFIX}{name}")).ok()
+    }
+
+    fn confirmation(&mut self, label: &str, skip: bool) -> TaskConfirmationOutcome {
+        let prompt = Self::prompt(label);
+
+        let answer = self.env_answer(prompt).unwrap_or_else(|| {
+            let answers = self.answers.lock().unwrap();
+
+            answers
+                .confirmations
+                .get(prompt)
+                .or_else(|| answers.confirmations.get("*"))
+                .cloned()
+                .unwrap_or_else(|| "yes".to_string())
+        });
+
+        info!("Answering `{prompt}` with `{answer}`");
+
+        match answer.to_ascii_lowercase().as_str() {
+            "y" | "yes" => TaskConfirmationOutcome::Confirmed,
+            "n" | "no" | "c" | "cancel" => TaskConfirmationOutcome::Canceled,
+            "i" | "ignore" | "s" | "skip" if skip => TaskConfirmationOutcome::Skipped,
             "q" | "quit" => TaskConfirmationOutcome::Quit,
-            _ => unreachable!(),
+            other => {
+                error!("Invalid answer `{other}` to `{prompt}`, quitting");
+
+                TaskConfirmationOutcome::Quit
+            }
         }
     }
+}
+
+impl TaskInput for Answers {
+    async fn wait_cancel(&mut self) -> TaskConfirmationOutcome {
+        core::future::pending().await
+    }
+
+    async fn confirm(&mut self, label: &str) -> TaskConfirmationOutcome {
+        self.confirmation(label, false)
+    }
+
+    async fn confirm_or_skip(&mut self, label: &str) -> TaskConfirmationOutcome {
+        self.confirmation(label, true)
+    }
 
     async fn input(&mut self, label: &str, _current: &str) -> TaskInputOutcome {
-        print!("{label}: ");
-        std::io::stdout().flush().unwrap();
+        let prompt = Self::prompt(label);
+
+        let answer = self.env_answer(prompt).or_else(|| {
+            let mut answers = self.answers.lock().unwrap();
+
+            match answers.inputs.get_mut(prompt)? {
+                AnswerValues::One(value) => Some(value.clone()),
+                AnswerValues::Many(values) => values.pop_front(),
+            }
+        });
 
-        let line = self.read_line().await;
+        if let Some(answer) = answer {
+            info!("Answering `{prompt}` with `{answer}`");
 
-        if line.is_empty() {
-            TaskInputOutcome::StartOver
+            TaskInputOutcome::Done(answer)
         } else {
-            TaskInputOutcome::Done(line)
+            error!("No answer to `{prompt}`, quitting");
+
+            TaskInputOutcome::Quit
         }
     }
 
@@ -162,7 +455,7 @@ impl TaskInput for Stdin {
     }
 }
 
-impl LogInput for Stdin {
+impl LogInput for Answers {
     async fn get(&mut self) -> LogInputOutcome {
         core::future::pending().await
     }
@@ -0,0 +1,142 @@
+//! Control of the test JIG strapping (boot mode and reset) via the DTR/RTS lines of a serial adapter,
+//! a serial relay board or an external command (e.g. a USB HID relay CLI)
+
+use core::fmt::{self, Display};
+
+use std::io::Write;
+use std::process::Command;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use log::info;
+
+use serialport::SerialPort;
+
+use crate::remote;
+use crate::utils::hex;
+use crate::{Jig, JigAction};
+
+/// The sequences of a test JIG
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Sequence {
+    /// The actions run before each connection to the ROM bootloader of the chip
+    PreFlash,
+    /// The actions run after the flashing and the eFuse burning of the PCB
+    PostFlash,
+}
+
+impl Sequence {
+    /// Return the actions of the sequence in the given test JIG configuration
+    pub fn actions(self, jig: &Jig) -> &[JigAction] {
+        match self {
+            Self::PreFlash => &jig.pre_flash,
+            Self::PostFlash => &jig.post_flash,
+        }
+    }
+}
+
+impl Display for Sequence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PreFlash => write!(f, "pre-flash"),
+            Self::PostFlash => write!(f, "post-flash"),
+        }
+    }
+}
+
+/// Run a sequence of test JIG actions
+///
+/// # Arguments
+/// - `jig` - the test JIG configuration
+/// - `flash_port` - the serial port used for flashing; used for the `Write` actions when the JIG has no port of its own
+/// - `sequence` - the sequence to run
+pub fn run(jig: &Jig, flash_port: Option<&str>, sequence: Sequence) -> anyhow::Result<()> {
+    let actions = sequence.actions(jig);
+
+    if actions.is_empty() {
+        return Ok(());
+    }
+
+    // The JIG port is closed at the end of the sequence, and closing a port deasserts its DTR/RTS lines,
+    // so with the flashing port the strapping would be undone before the flashing tool connects
+    if jig.port.is_none()
+        && actions
+            .iter()
+            .any(|action| matches!(action, JigAction::Dtr { .. } | JigAction::Rts { .. }))
+    {
+        anyhow::bail!(
+            "JIG sequence `{sequence}` has DTR/RTS actions, which need a dedicated JIG port (`jig.port`)"
+        );
+    }
+
+    info!("Running JIG sequence `{sequence}`");
+
+    let mut serial: Option<Box<dyn SerialPort>> = None;
+
+    for action in actions {
+        match action {
+            JigAction::Dtr { level } => {
+                open(&mut serial, jig, flash_port)?
+                    .write_data_terminal_ready(*level)
+                    .context("Setting the DTR line of the JIG port failed")?;
+            }
+            JigAction::Rts { level } => {
+                open(&mut serial, jig, flash_port)?
+                    .write_request_to_send(*level)
+                    .context("Setting the RTS line of the JIG port failed")?;
+            }
+            JigAction::Write { data } => {
+                let bytes = hex::decode(data)
+                    .with_context(|| format!("JIG data `{data}` is not a valid hex string"))?;
+
+                let serial = open(&mut serial, jig, flash_port)?;
+
+                serial
+                    .write_all(&bytes)
+                    .and_then(|_| serial.flush())
+                    .context("Writing to the JIG port failed")?;
+            }
+            JigAction::Command { command, args } => {
+                let status = Command::new(command)
+                    .args(args)
+                    .status()
+                    .with_context(|| format!("Executing JIG command `{command}` failed"))?;
+
+                if !status.success() {
+                    anyhow::bail!("JIG command `{command}` failed with status: {status}");
+                }
+            }
+            JigAction::Delay { ms } => thread::sleep(Duration::from_millis(*ms)),
+        }
+    }
+
+    info!("JIG sequence `{sequence}` complete");
+
+    Ok(())
+}
+
+/// Open the JIG serial port, if not opened already
+fn open<'a>(
+    serial: &'a mut Option<Box<dyn SerialPort>>,
+    jig: &Jig,
+    flash_port: Option<&str>,
+) -> anyhow::Result<&'a mut Box<dyn SerialPort>> {
+    if serial.is_none() {
+        let port = jig
+            .port
+            .as_deref()
+            .or(flash_port.filter(|port| !remote::is_remote(Some(port))))
+            .context("No JIG port configured and no local flashing port to fall back to")?;
+
+        *serial = Some(
+            serialport::new(port, jig.baud)
+                .timeout(Duration::from_secs(1))
+                .open()
+                .with_context(|| format!("Opening JIG port `{port}` failed"))?,
+        );
+    }
+
+    Ok(serial.as_mut().unwrap())
+}
@@ -0,0 +1,216 @@
+//! Flashing over JTAG (e.g. the USB-JTAG-serial peripheral of the newer chips) with `probe-rs` or OpenOCD,
+//! for PCBs where flashing over UART0 is not possible
+
+use std::io::Write;
+use std::path::Path;
+use std::process::Command;
+
+use anyhow::Context;
+
+use espflash::flasher::ProgressCallbacks;
+
+use log::{info, warn};
+
+use crate::bundle::{Chip, FlashData};
+use crate::permissions::ToolRunner;
+use crate::session;
+use crate::FlashJtag;
+
+/// Flash the given data over JTAG and reset the chip afterwards
+///
+/// # Arguments
+/// - `tools` - the runner of the JTAG tool
+/// - `jtag` - the JTAG flasher configuration
+/// - `chip` - the chip being flashed
+/// - `flash_data` - the data to be flashed
+/// - `dry_run` - if `true`, the flashing is skipped
+/// - `progress` - the progress callbacks to be used during flashing
+pub fn flash<P>(
+    tools: &ToolRunner,
+    jtag: &FlashJtag,
+    chip: Chip,
+    flash_data: impl IntoIterator<Item = anyhow::Result<FlashData>>,
+    dry_run: bool,
+    progress: &mut P,
+) -> anyhow::Result<()>
+where
+    P: ProgressCallbacks,
+{
+    for flash_data in flash_data {
+        let flash_data = flash_data?;
+
+        let mut data_temp_file = tools.temp_file()?;
+
+        data_temp_file
+            .write_all(&flash_data.data)
+            .context("Writing the binary image to a temporary file failed")?;
+
+        data_temp_file
+            .flush()
+            .context("Flushing the temporary file failed")?;
+
+        progress.init(flash_data.offset, flash_data.data.len());
+
+        let mut command = flash_command(jtag, chip, flash_data.offset, data_temp_file.path());
+
+        if !dry_run {
+            exec(tools, &mut command)?;
+        } else {
+            warn!("Flash dry run mode: flashing skipped");
+        }
+
+        progress.update(flash_data.data.len());
+        progress.finish(false);
+    }
+
+    if !dry_run {
+        exec(tools, &mut reset_command(jtag, chip))?;
+    }
+
+    Ok(())
+}
+
+/// Erase the whole flash over JTAG
+///
+/// # Arguments
+/// - `tools` - the runner of the JTAG tool
+/// - `jtag` - the JTAG flasher configuration
+/// - `chip` - the chip being erased
+/// - `dry_run` - if `true`, the erasing is skipped
+pub fn erase(
+    tools: &ToolRunner,
+    jtag: &FlashJtag,
+    chip: Chip,
+    dry_run: bool,
+) -> anyhow::Result<()> {
+    let mut command = erase_command(jtag, chip);
+
+    if !dry_run {
+        exec(tools, &mut command)?;
+    } else {
+        warn!("Flash dry run mode: erasing flash skipped");
+    }
+
+    Ok(())
+}
+
+/// Build - but do not execute - the command for flashing the given image file at the given offset over JTAG
+pub fn flash_command(jtag: &FlashJtag, chip: Chip, offset: u32, image: &Path) -> Command {
+    match jtag {
+        FlashJtag::ProbeRs { .. } => {
+            let mut command = probe_rs_command(jtag, chip, "download");
+
+            command
+                .arg("--binary-format")
+                .arg("bin")
+                .arg("--base-address")
+                .arg(format!("0x{offset:x}"))
+                .arg(image);
+
+            command
+        }
+        FlashJtag::OpenOcd { .. } => openocd_command(
+            jtag,
+            chip,
+            &format!(
+                "program_esp {} 0x{offset:x} verify exit",
+                image.to_string_lossy().replace('\\', "/")
+            ),
+        ),
+    }
+}
+
+/// Build - but do not execute - the command for erasing the whole flash over JTAG
+pub fn erase_command(jtag: &FlashJtag, chip: Chip) -> Command {
+    match jtag {
+        FlashJtag::ProbeRs { .. } => probe_rs_command(jtag, chip, "erase"),
+        FlashJtag::OpenOcd { .. } => openocd_command(
+            jtag,
+            chip,
+            "init; reset halt; flash erase_sector 0 0 last; shutdown",
+        ),
+    }
+}
+
+/// Build - but do not execute - the command for resetting the chip over JTAG
+pub fn reset_command(jtag: &FlashJtag, chip: Chip) -> Command {
+    match jtag {
+        FlashJtag::ProbeRs { .. } => probe_rs_command(jtag, chip, "reset"),
+        FlashJtag::OpenOcd { .. } => openocd_command(jtag, chip, "init; reset run; shutdown"),
+    }
+}
+
+fn probe_rs_command(jtag: &FlashJtag, chip: Chip, cmd: &str) -> Command {
+    let FlashJtag::ProbeRs {
+        command: program,
+        target,
+        probe,
+        speed_khz,
+    } = jtag
+    else {
+        unreachable!();
+    };
+
+    let mut command = Command::new(program.as_deref().unwrap_or("probe-rs"));
+
+    command
+        .arg(cmd)
+        .arg("--chip")
+        .arg(target.as_deref().unwrap_or(chip.as_tools_str()));
+
+    if let Some(probe) = probe {
+        command.arg("--probe").arg(probe);
+    }
+
+    if let Some(speed_khz) = speed_khz {
+        command.arg("--speed").arg(speed_khz.to_string());
+    }
+
+    command
+}
+
+fn openocd_command(jtag: &FlashJtag, chip: Chip, cmd: &str) -> Command {
+    let FlashJtag::OpenOcd {
+        command: program,
+        configs,
+    } = jtag
+    else {
+        unreachable!();
+    };
+
+    let mut command = Command::new(program.as_deref().unwrap_or("openocd"));
+
+    if configs.is_empty() {
+        command
+            .arg("-f")
+            .arg(format!("board/{}-builtin.cfg", chip.as_tools_str()));
+    } else {
+        for config in configs {
+            command.arg("-f").arg(config);
+        }
+    }
+
+    command.arg("-c").arg(cmd);
+
+    command
+}
+
+fn exec(tools: &ToolRunner, command: &mut Command) -> anyhow::Result<()> {
+    warn!("About to execute JTAG command `{command:?}`...");
+
+    let output = session::tool_output(tools, command, &[])
+        .with_context(|| format!("Executing JTAG command `{command:?}` failed"))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "JTAG command `{command:?}` failed with status: {}. Is the PCB connected?\nStderr output:\n{}\nStdout output:\n{}",
+            output.status,
+            core::str::from_utf8(&output.stderr).unwrap_or("???"),
+            core::str::from_utf8(&output.stdout).unwrap_or("???")
+        );
+    }
+
+    info!("JTAG command `{command:?}` executed.");
+
+    Ok(())
+}
@@ -0,0 +1,121 @@
+//! Printing of labels (ZPL, EPL or any other raw printer language) for the provisioned PCBs
+
+use std::fs::OpenOptions;
+use std::io::Write;
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::Duration;
+
+use anyhow::Context;
+
+use log::info;
+
+use crate::{Label, LabelPrinter};
+
+/// Render the label template and send it to the label printer
+///
+/// # Arguments
+/// - `label` - the label configuration
+/// - `bundle_name` - the name of the provisioned bundle
+/// - `readouts` - the readouts of the PCB, as recorded in the summary of the PCB logs
+pub fn print(
+    label: &Label,
+    bundle_name: &str,
+    readouts: &[(String, String)],
+) -> anyhow::Result<()> {
+    let template = if let Some(template_file) = &label.template_file {
+        std::fs::read_to_string(template_file)
+            .with_context(|| format!("Reading the label template `{template_file}` failed"))?
+    } else {
+        label.template.clone()
+    };
+
+    let data = render(&template, bundle_name, readouts);
+
+    send(&label.printer, data.as_bytes())?;
+
+    info!("Label printed");
+
+    Ok(())
+}
+
+/// Render the label template by replacing the `${Name}` placeholders with the values
+/// of the readouts with that name, and `${Bundle}` with the name of the bundle
+///
+/// Placeholders without a matching readout are replaced with an empty string
+pub fn render(template: &str, bundle_name: &str, readouts: &[(String, String)]) -> String {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+
+    while let Some(start) = rest.find("${") {
+        rendered.push_str(&rest[..start]);
+
+        let Some(end) = rest[start..].find('}') else {
+            rest = &rest[start..];
+            break;
+        };
+
+        let name = &rest[start + 2..start + end];
+
+        let value = if name == "Bundle" {
+            Some(bundle_name)
+        } else {
+            readouts
+                .iter()
+                .find(|(readout, _)| readout == name)
+                .map(|(_, value)| value.as_str())
+        };
+
+        rendered.push_str(value.unwrap_or_default());
+
+        rest = &rest[start + end + 1..];
+    }
+
+    rendered.push_str(rest);
+
+    rendered
+}
+
+/// Send the raw label data to the label printer
+fn send(printer: &LabelPrinter, data: &[u8]) -> anyhow::Result<()> {
+    match printer {
+        LabelPrinter::Network { address } => {
+            let addr = address
+                .to_socket_addrs()
+                .with_context(|| format!("Resolving label printer address `{address}` failed"))?
+                .next()
+                .with_context(|| format!("Label printer address `{address}` did not resolve"))?;
+
+            let mut stream = TcpStream::connect_timeout(&addr, Duration::from_secs(5))
+                .with_context(|| format!("Connecting to label printer `{address}` failed"))?;
+
+            stream
+                .write_all(data)
+                .and_then(|_| stream.flush())
+                .with_context(|| format!("Sending the label to printer `{address}` failed"))?;
+        }
+        LabelPrinter::Serial { port, baud } => {
+            let mut serial = serialport::new(port, *baud)
+                .timeout(Duration::from_secs(5))
+                .open()
+                .with_context(|| format!("Opening label printer serial port `{port}` failed"))?;
+
+            serial
+                .write_all(data)
+                .and_then(|_| serial.flush())
+                .with_context(|| format!("Sending the label to printer `{port}` failed"))?;
+        }
+        LabelPrinter::Device { path } => {
+            let mut device = OpenOptions::new()
+                .write(true)
+                .open(path)
+                .with_context(|| format!("Opening label printer device `{path}` failed"))?;
+
+            device
+                .write_all(data)
+                .and_then(|_| device.flush())
+                .with_context(|| format!("Sending the label to printer `{path}` failed"))?;
+        }
+    }
+
+    Ok(())
+}
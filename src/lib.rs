@@ -1,36 +1,79 @@
 #![allow(async_fn_in_trait)]
 
+use core::fmt;
+
 use alloc::sync::Arc;
 
 use anyhow::Context;
 
-use embassy_futures::select::select3;
+use embassy_futures::select::{select, select3};
 
+use backend::Backend;
+use events::Events;
+use footswitch::{FootswitchInput, Switch};
 use input::{LogInput, LogInputOutcome};
 use model::Model;
+use permissions::ToolRunner;
 use serde::{Deserialize, Serialize};
+use session::Session;
 use task::Task;
 use ui::input::Input;
 use ui::view::View;
 use utils::futures::Coalesce;
 
+pub use dump::dump;
+pub use error::{Error, ErrorSource};
+pub use factory::Factory;
 pub use logger::LOGGER;
+pub use permissions::udev_rules;
 
 extern crate alloc;
 
+pub mod auth;
+pub mod events;
 pub mod loader;
 pub mod uploader;
 
+mod audit;
+mod backend;
 mod bundle;
+mod certificate;
+mod coredump;
+mod daemon;
+mod dump;
 mod efuse;
+mod error;
+mod escrow;
+mod factory;
 mod flash;
+mod footswitch;
+mod i18n;
 mod input;
+mod jig;
+mod jtag;
+mod label;
 mod logger;
+mod lookup;
+mod measure;
+mod metrics;
 mod model;
 mod monitor;
+mod notifications;
+mod nvs_keys;
+mod ota;
+mod permissions;
+mod plugin;
+mod readout;
+mod remote;
+mod report;
+mod sensor;
+mod session;
+mod simulate;
+mod sound;
 mod task;
 mod ui;
 mod utils;
+mod work_order;
 
 /// The configuration of the factory
 #[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
@@ -42,39 +85,154 @@ pub struct Config {
     #[serde(default = "default_bool::<true>")]
     pub efuse_dry_run: bool,
     /// Whether to protect the keys to be burned in the eFuse
+    ///
+    /// Can be overridden per eFuse block with `efuse_key_protection`, as well as per key in the eFuse manifest
+    /// of the bundle (`efuses.toml`)
     #[serde(default)]
     pub efuse_protect_keys: bool,
     /// Whether to protect the digests to be burned in the eFuse
+    ///
+    /// Can be overridden per eFuse block with `efuse_key_protection`, as well as per digest in the eFuse manifest
+    /// of the bundle (`efuses.toml`)
     #[serde(default)]
     pub efuse_protect_digests: bool,
+    /// The read- and write-protection of individual key and key digest eFuse blocks, overriding
+    /// `efuse_protect_keys` and `efuse_protect_digests` (e.g. a read-protected flash encryption key
+    /// next to a readable Secure Boot digest)
+    ///
+    /// The protection declared for a key or a key digest in the eFuse manifest of the bundle takes precedence
+    #[serde(default)]
+    pub efuse_key_protection: Vec<EfuseKeyProtection>,
+    /// Whether to include a snapshot of the full eFuse summary of the chip - as taken before and after burning
+    /// the eFuses - in the PCB logs (`efuse-before.json` and `efuse-after.json`)
+    ///
+    /// Useful when debugging field returns, as the exact eFuse state of the PCB when leaving the factory is known
+    #[serde(default = "default_bool::<true>")]
+    pub efuse_snapshots: bool,
+    /// Whether to burn the keys, the key digests, the custom MAC and the eFuse params with a single `espefuse.py`
+    /// invocation, rather than with one invocation per kind (and per key protection), each connecting to
+    /// and resetting the chip
+    ///
+    /// Requires `espefuse.py` v3.1+
+    #[serde(default = "default_bool::<true>")]
+    pub efuse_batch: bool,
     /// Whether to in-place encrypt the bootloader, partition-table
     /// and all images going to partitions marked as encrypted.
     /// Requires exactly one key with purpose `XTS_AES_128_KEY`
-    /// to be present in the bundle
+    /// to be present in the bundle, or generated with `flash_encrypt_keygen`
     #[serde(default)]
     pub flash_encrypt: bool,
+    /// The maximum number of images to be encrypted in parallel when `flash_encrypt` is enabled
+    ///
+    /// The encryption is pipelined with the flashing, i.e. each image is flashed as soon as it is encrypted
+    #[serde(default = "default_usize::<4>")]
+    pub flash_encrypt_threads: usize,
     /// The serial port to use for communication with the device
     ///
     /// If not provided, the first available port where an ESP chip is
     /// detected will be used
+    ///
+    /// Can also be a networked serial port (e.g. `ser2net`) in the form of `rfc2217://<host>:<port>`
+    /// or `tcp://<host>:<port>`, in which case the images are always flashed with `esptool.py`
+    /// (or over JTAG) and the eFuses are always accessed with `espefuse.py`
     #[serde(default)]
     pub port: Option<String>,
+    /// Whether to let the operator pick the serial port at startup from a list of the detected USB serial ports,
+    /// when `port` is not provided and more than one USB serial port is detected
+    ///
+    /// The picked port is used for the whole session. If `false`, the first detected port is used
+    #[serde(default = "default_bool::<true>")]
+    pub port_pick: bool,
     /// Do not use a stub when flashing
     #[serde(default)]
     pub flash_no_stub: bool,
+    /// The reset done by the native flasher when connecting to the chip, to put it into the ROM bootloader
+    ///
+    /// If not provided, the classic DTR/RTS reset sequence is used. Boards with nonstandard auto-reset circuits
+    /// (e.g. some CH340K adapters) might need a different one.
+    /// Has no effect when the test JIG does the strapping itself (see `Jig::auto_reset`)
+    #[serde(default)]
+    pub flash_reset_before: Option<FlashResetBefore>,
+    /// The reset done by the native flasher once all images are flashed
+    ///
+    /// The chip is never reset between the flashed images
+    #[serde(default)]
+    pub flash_reset_after: FlashResetAfter,
+    /// Only warn - rather than refuse to flash - when the bundle does not fit the flash size detected on the chip
+    /// (i.e. the flash size of the bundle is larger than the detected one, or some of its partitions end beyond it)
+    ///
+    /// The flash size is not detected when flashing over JTAG
+    #[serde(default)]
+    pub flash_size_mismatch_ignore: bool,
+    /// Whether to let the operator pick one of the alternative partition tables shipped in the bundle
+    /// (`partition-table-<variant>.csv`) when the partition table of the bundle does not fit the flash
+    /// detected on the chip
+    ///
+    /// The images of the bundle are re-mapped by partition name to the partitions of the picked partition table
+    #[serde(default)]
+    pub part_table_variant_pick: bool,
     /// Erase flash prior to flashing
     /// Only works if Secure Download mode is not enabled
     #[serde(default)]
     pub flash_erase: bool,
+    /// Only flash the images whose content differs from the content already in the flash of the chip
+    ///
+    /// Before flashing an image, the MD5 checksum of its flash region is read back from the chip and the image
+    /// is skipped if the checksum matches. Speeds up the re-provisioning of mostly-identical firmware at rework stations
+    ///
+    /// Only supported with the native flasher (i.e. not with `flash_esptool` or `flash_jtag`),
+    /// and has no effect with `flash_erase`
+    #[serde(default)]
+    pub flash_diff: bool,
+    /// Once an image is flashed, read back the MD5 checksum of its flash region as computed by the chip
+    /// and fail the flashing if it does not match the MD5 checksum of the image
+    ///
+    /// The checksums are recorded in the `checksums.csv` file of the PCB logs and in the test reports
+    ///
+    /// Only supported with the native flasher (i.e. not with `flash_esptool` or `flash_jtag`)
+    #[serde(default)]
+    pub flash_verify: bool,
     /// Reset empty partitions by writing 0xff to the entire partition
     /// Works also when Secure Download mode is enabled
     /// For encrypted partitions, will write pre-encrypted 0xff sequences
     /// (Else ESP-IDF might complain for reading bogus data from those)
     #[serde(default)]
     pub reset_empty_partitions: bool,
+    /// Only reset the data partitions (NVS, `otadata`, coredump, FAT, SPIFFS, ...) by writing 0xff to them,
+    /// leaving the bootloader, the partition table and the app partitions intact
+    ///
+    /// Useful for refurbishing returned units without re-flashing the identical firmware (see `Config::data_reset`).
+    /// The readonly data partitions are only reset with `flash_readonly`
+    #[serde(default)]
+    pub flash_data_reset: bool,
+    /// Allow flashing images to partitions marked as `readonly` in the partition table
+    ///
+    /// If not enabled, bundles with images for readonly partitions are refused, and readonly partitions
+    /// are not reset with `reset_empty_partitions`
+    #[serde(default)]
+    pub flash_readonly: bool,
+    /// If provided, the `otadata` partition is flashed with a generated image selecting the given boot slot
+    /// (`factory`, `ota_0`, `ota_1`, ...), rather than being left to the flash erase or to `reset_empty_partitions`
+    ///
+    /// Useful when re-provisioning, where a stale `otadata` might otherwise make the bootloader boot a stale app slot
+    #[serde(default)]
+    pub otadata_boot: Option<OtaBoot>,
     /// Use `esptool.py` for flashing the device
     #[serde(default)]
     pub flash_esptool: bool,
+    /// Whether to compress (deflate) the images when flashing them with `esptool.py` (`write_flash --compress`),
+    /// which considerably reduces the flashing time at the lower speeds
+    ///
+    /// If not provided, the images are compressed when the flasher stub is used, and the `esptool.py` default
+    /// applies otherwise. The native flasher always compresses the images
+    #[serde(default)]
+    pub flash_compress: Option<bool>,
+    /// If provided, the device is flashed over JTAG (e.g. the USB-JTAG-serial peripheral) with `probe-rs` or OpenOCD,
+    /// rather than over the serial port
+    ///
+    /// For PCBs where flashing over UART0 is not possible. Takes precedence over `flash_esptool`
+    #[serde(default)]
+    pub flash_jtag: Option<FlashJtag>,
     /// The flash speed to use for flashing the device
     ///
     /// If not provided, the default speed will be used
@@ -85,16 +243,90 @@ pub struct Config {
     /// If not provided, the default speed will be used
     #[serde(default)]
     pub efuse_speed: Option<u32>,
+    /// The speed of the serial port when monitoring the app logs during the device app run
+    ///
+    /// Should match the console speed of the app. If not provided, 115200 is used
+    #[serde(default)]
+    pub monitor_speed: Option<u32>,
+    /// The speeds (e.g. `[460800, 115200]`) tried in order when connecting to the device for flashing
+    /// or reading the eFuses fails at the configured (or the default) speed
+    ///
+    /// Burning the eFuses is never retried at a fallback speed.
+    /// Useful with serial adapters which are unreliable at the higher speeds. If empty, no fallback is attempted
+    #[serde(default)]
+    pub speed_fallbacks: Vec<u32>,
     /// Whether to ignore failed readouts of the eFuses
     /// (eFuse reading will fail if the device has a Secure Download enabled)
     #[serde(default)]
     pub efuse_ignore_failed_readouts: bool,
+    /// The eFuse fields read from the chip in the eFuse readouts step and recorded in the summary of the PCB logs
+    /// (e.g. `MAC`, ADC calibration fields or `BLOCK_SYS_DATA`)
+    ///
+    /// If empty, the chip IDs are read: `MAC`, the wafer version, `OPTIONAL_UNIQUE_ID`, and the flash and PSRAM
+    /// capacity, type and vendor
+    #[serde(default)]
+    pub efuse_readouts: Vec<EfuseReadout>,
+    /// The directory with the ESP-IDF eFuse tables used for validating the eFuse names of the bundles
+    /// and eFuse plans, as `<chip>.csv` files
+    /// (i.e. copies of `components/efuse/<chip>/esp_efuse_table.csv` from ESP-IDF, e.g. `esp32s3.csv`)
+    #[serde(default)]
+    pub efuse_native_tables: Option<String>,
+    /// An optional eFuse plan (a JSON object of eFuse names and values, e.g. as approved by a security team)
+    /// whose eFuses are burned in addition to the eFuses of the bundle
+    ///
+    /// Bundles might also contain an eFuse plan as an `efuse-plan.json` file.
+    /// The eFuse names of the plans (and of the bundles) are validated against the eFuse table of the chip
+    /// in `efuse_native_tables` if provided, or against the built-in table of the known eFuse fields of the chip otherwise
+    #[serde(default)]
+    pub efuse_plan: Option<String>,
+    /// Whether to allow eFuse names which are not in the built-in table of the known eFuse fields of the chip,
+    /// with a warning; if `false`, such names fail the bundle
+    ///
+    /// `true` by default, as the built-in table only lists the fields commonly burned during provisioning
+    /// and might lag behind the newer chip revisions. Not used if `efuse_native_tables` is provided,
+    /// in which case the names which are not in the native tables always fail the bundle
+    #[serde(default = "default_bool::<true>")]
+    pub efuse_allow_unknown: bool,
     /// The type of device app run to perform
     #[serde(default)]
     pub app_run: AppRun,
+    /// An optional verification - done after the device app run - that the app slot activated by an on-device
+    /// OTA update of the factory app is the expected one and (optionally) contains the expected app image
+    ///
+    /// Requires the flash to be readable, i.e. unencrypted and not in Secure Download Mode
+    #[serde(default)]
+    pub app_run_ota_verify: Option<OtaVerify>,
+    /// An optional retrieval of the ESP-IDF coredump when the device app run fails (e.g. because the app panicked)
+    ///
+    /// The coredump partition is read back and attached to the PCB logs as `coredump.b64`, and - if a decoder
+    /// is configured and the app image of the bundle is an ELF file - decoded into `coredump.txt`
+    ///
+    /// Requires the flash to be readable, i.e. unencrypted and not in Secure Download Mode
+    #[serde(default)]
+    pub app_run_coredump: Option<AppRunCoredump>,
+    /// The log format of the app during the device app run
+    ///
+    /// With `AppLogFormat::Defmt`, the app image of the bundle should be provided as an ELF file
+    /// so that the `defmt` frames can be decoded
+    #[serde(default)]
+    pub app_run_log_format: AppLogFormat,
+    /// Only relevant with the interactive console UI:
+    /// How the app logs are colored during the device app run
+    ///
+    /// The app logs in the PCB logs are always plain text
+    #[serde(default)]
+    pub app_run_log_colors: AppLogColors,
     /// The method used to identify the bundle to be loaded
     #[serde(default)]
     pub bundle_identification: BundleIdentification,
+    /// Fetch the bundle of the next PCB while the current PCB is being provisioned, so that the bundle preparation
+    /// of the next PCB is nearly instant on slow links
+    ///
+    /// Only used with `BundleIdentification::None`, as the bundle of the next PCB is not known otherwise.
+    /// NOTE: As with any bundle loaded without an ID, the prefetched bundle is removed from the bundle source,
+    /// so it is lost if the provisioning is quit before the next PCB
+    #[serde(default)]
+    pub bundle_prefetch: bool,
     /// Instead of reading the Test JIG ID, hard-code its value here.
     ///
     /// The test JIG ID is used for logging purposes.
@@ -107,21 +339,79 @@ pub struct Config {
     /// The test JIG ID is only read and used for logging purposes
     #[serde(default)]
     pub test_jig_id_readout: bool,
+    /// The source of the Test JIG ID readout
+    #[serde(default)]
+    pub test_jig_id_source: ReadoutSource,
+    /// Optional validation rules of the Test JIG ID readout
+    ///
+    /// A value violating the rules is rejected and has to be read again
+    #[serde(default)]
+    pub test_jig_id_validation: Option<ReadoutValidation>,
+    /// Whether to render a UI for the operator login (i.e. reading the operator ID, e.g. by a badge scan)
+    /// before the readouts of the first PCB
+    ///
+    /// The operator stays logged in for all subsequent PCBs, and the operator ID is recorded
+    /// in the summary of the PCB logs and in the PCB reports, for traceability
+    #[serde(default)]
+    pub operator_id_readout: bool,
     /// Whether to render a UI for reading the PCB ID
     ///
     /// The PCB ID is used for logging purposes, but also and if the `BundleIdentification::PcbId` is used
     /// it is used to identify the bundle to be loaded
     #[serde(default)]
     pub pcb_id_readout: bool,
+    /// The source of the PCB ID readout
+    #[serde(default)]
+    pub pcb_id_source: ReadoutSource,
+    /// Optional validation rules of the PCB ID readout
+    ///
+    /// A value violating the rules is rejected and has to be read again
+    #[serde(default)]
+    pub pcb_id_validation: Option<ReadoutValidation>,
     /// Whether to render a UI for reading the Device ID
     ///
     /// The Device ID is used for logging purposes, but also and if the `BundleIdentification::DeviceId` is used
     /// it is used to identify the bundle to be loaded
     #[serde(default)]
     pub device_id_readout: bool,
+    /// The source of the Device ID readout
+    #[serde(default)]
+    pub device_id_source: ReadoutSource,
+    /// Optional validation rules of the Device ID readout
+    ///
+    /// A value violating the rules is rejected and has to be read again
+    #[serde(default)]
+    pub device_id_validation: Option<ReadoutValidation>,
+    /// Additional readouts to be read after the Device ID, PCB ID and Test JIG ID readouts
+    /// (e.g. the fixture slot or the work order number)
+    ///
+    /// The readouts are recorded in the summary of the PCB logs under their names
+    #[serde(default)]
+    pub extra_readouts: Vec<ExtraReadout>,
     /// Whether to skip all confirmation screens
     #[serde(default)]
     pub skip_confirmations: bool,
+    /// Whether to show a preview of the prepared bundle (its parameters, images, eFuse operations and
+    /// the estimated flashing duration) with the confirmation of the provisioning, rather than the bundle
+    /// provisioning screen
+    ///
+    /// Has no effect when `skip_confirmations` is `true`
+    #[serde(default = "default_bool::<true>")]
+    pub provision_preview: bool,
+    /// If provided, the prompts can also be answered with a footswitch (see `Footswitch`),
+    /// so that the happy path can be run without a keyboard
+    #[serde(default)]
+    pub footswitch: Option<Footswitch>,
+    /// Whether the destructive settings (see `Config::destructive_settings`) are acknowledged upfront
+    ///
+    /// If `false` and the configuration has destructive settings, an explicit acknowledgment is requested
+    /// from the operator at startup, or - when `skip_confirmations` is `true` - the factory refuses to start.
+    /// Destructive settings introduced by a configuration override (see `ConfigOverride` and `config_override_file`)
+    /// fail the PCB.
+    ///
+    /// Only set from the command line (`--yes-i-know`), so that a configuration file can never acknowledge itself
+    #[serde(skip)]
+    pub destructive_ack: bool,
     /// Whether to supply the default partition table if the loaded bundle does not contain one
     #[serde(default = "default_bool::<true>")]
     pub supply_default_partition_table: bool,
@@ -132,12 +422,236 @@ pub struct Config {
     /// during the bundles' merge operation
     #[serde(default)]
     pub overwrite_on_merge: bool,
+    /// An optional TOML file with a configuration override (see `ConfigOverride`) applied to each PCB
+    ///
+    /// The file is re-read whenever it changes, so that the whitelisted settings can be changed without restarting
+    /// the factory (the changes take effect with the next PCB). The configuration override of a bundle takes precedence
+    #[serde(default)]
+    pub config_override_file: Option<String>,
+    /// When a base bundle is used: whether to cache the base bundle in a local cache directory,
+    /// so that it is not re-downloaded for every PCB
+    ///
+    /// The cached base bundle is re-used for as long as its fingerprint (e.g. the ETag of the remote bundle) does not change
+    #[serde(default = "default_bool::<true>")]
+    pub base_bundle_cache: bool,
+    /// The directory of the base bundle cache
+    ///
+    /// If not provided, `espfactory/bundles` in the per-user cache directory of the host is used
+    #[serde(default)]
+    pub base_bundle_cache_dir: Option<String>,
+    /// Whether to persist the PCB logs in a local spool directory before uploading them, so that logs
+    /// whose upload failed (e.g. because the network is down) are not lost, and their upload is retried
+    /// with the upload of the next PCB logs, as well as on the next start of the factory
+    #[serde(default = "default_bool::<true>")]
+    pub logs_spool: bool,
+    /// The directory of the logs spool
+    ///
+    /// If not provided, `espfactory/spool` in the per-user cache directory of the host is used
+    #[serde(default)]
+    pub logs_spool_dir: Option<String>,
+    /// Only relevant with the interactive console UI:
+    /// The directory where the log of the PCB being provisioned is saved on demand with `Alt-S`
+    /// (e.g. for attaching it to a ticket when the logs upload is down)
+    ///
+    /// If not provided, the current directory is used
+    #[serde(default)]
+    pub logs_save_dir: Option<String>,
+    /// The maximum size of the log of a PCB in KB; 0 means no limit
+    ///
+    /// Long app runs of misbehaving PCBs (e.g. a boot-looping PCB with a long `AppRun::MatchPattern` timeout)
+    /// can otherwise produce enormous logs. Once exceeded, the head and the most recent tail of the log are kept,
+    /// and the truncation is noted in the uploaded log
+    #[serde(default = "default_u32::<65536>")]
+    pub logs_max_size_kb: u32,
+    /// An optional path to an Ed25519 station key (32 bytes, raw or hex-encoded) used for signing the entries
+    /// of the audit log of the irreversible operations (flashing, eFuse burning), which is included in the PCB logs,
+    /// and the head of its chain, which is recorded in the PCB summary
+    ///
+    /// If not provided, the audit log entries are only hash-chained, but not signed
+    #[serde(default)]
+    pub audit_signing_key: Option<String>,
+    /// The formats of the per-board test report (the provisioning steps and the app-run expectations as test cases),
+    /// which is included in the PCB logs next to the log itself
+    ///
+    /// If empty, no test report is emitted
+    #[serde(default)]
+    pub report_formats: Vec<ReportFormat>,
+    /// An optional target cycle time per PCB, in seconds (from the start of the PCB provisioning until
+    /// the provisioning is complete, including the operator readouts and retries)
+    ///
+    /// PCBs exceeding it are reported with a warning (with a breakdown of the step durations) and their summary
+    /// is highlighted - in yellow, or in red if the budget is exceeded by more than 50%.
+    /// The number of PCBs exceeding it is tracked for the provisioning session
+    #[serde(default)]
+    pub cycle_time_budget_secs: Option<u32>,
+    /// Whether to present the result of the PCB provisioning as a full-screen PASS (green) / FAIL (red) banner
+    /// with the PCB identity (its readouts) in large text, rather than as a regular status message
+    #[serde(default)]
+    pub result_banner: bool,
+    /// If set, the PASS banner is dismissed automatically after that many seconds, as if the operator
+    /// confirmed to continue with the next PCB
+    ///
+    /// FAIL banners are never dismissed automatically after a delay
+    #[serde(default)]
+    pub result_banner_dismiss_secs: Option<u32>,
+    /// Whether the PASS banner is dismissed automatically once the next PCB is inserted
+    ///
+    /// The insertion is detected as a new USB serial port showing up, hence this only works
+    /// for PCBs bringing their own USB serial port (e.g. the USB-JTAG-serial peripheral of the newer chips)
+    #[serde(default)]
+    pub result_banner_dismiss_on_insert: bool,
+    /// The locale of the dates and numbers in the PCB log summary (CSV) and reports
+    ///
+    /// Defaults to ISO 8601 UTC dates and dot decimal separators, regardless of the locale of the station
+    #[serde(default)]
+    pub summary_locale: Locale,
+    /// The locale of the dates and numbers displayed to the operator
+    #[serde(default)]
+    pub ui_locale: Locale,
+    /// The colors and the branding of the interactive console UI
+    #[serde(default)]
+    pub ui_theme: UiTheme,
+    /// Whether to run with a desktop GUI with big, touch-friendly buttons rather than with the interactive console UI
+    ///
+    /// Requires the `gui` feature and is not supported on macOS. Can be combined with `daemon`, in which case
+    /// the prompts can be answered both in the GUI and over the REST API
+    #[serde(default)]
+    pub gui: bool,
+    /// The language of the operator-facing UI strings (prompts, key hints and titles), e.g. `zh` or `es`
+    ///
+    /// The logs are always in English. If not provided, the UI is in English too
+    #[serde(default)]
+    pub ui_language: Option<String>,
+    /// An optional UI message catalog file (a TOML map of the English UI strings to their translations),
+    /// overriding - or extending - the built-in catalog of `ui_language`
+    #[serde(default)]
+    pub ui_messages: Option<String>,
     /// Whether to print stack backtraces in the error messages and in the logs
     #[serde(default)]
     pub print_backtraces: bool,
+    /// An optional user under which the tool subprocesses (`esptool.py`, `espefuse.py`, `espsecure.py`) are run
+    ///
+    /// Unix only. Requires `espfactory` to have the privileges to switch users (root or `CAP_SETUID` + `CAP_SETGID`).
+    /// The serial devices should be owned by that user or be accessible by its primary group
+    /// (see the `espfactory udev-rules` command)
+    #[serde(default)]
+    pub tools_user: Option<String>,
+    /// The minimum delay between the tool invocations (`esptool.py`, `espefuse.py`) in milliseconds,
+    /// so that the serial port closed by the previous invocation settles before the next one opens it
+    ///
+    /// Useful with USB hubs which re-enumerate the adapters when the port is reset
+    #[serde(default)]
+    pub tool_port_settle_ms: u32,
+    /// The number of times a tool invocation is retried when it fails because the serial port is busy
+    /// or not (yet) available
+    ///
+    /// eFuse burns are never retried
+    #[serde(default = "default_u32::<3>")]
+    pub tool_port_busy_retries: u32,
+    /// External plugins to be invoked at the hook points of the provisioning cycle
+    #[serde(default)]
+    pub plugins: Vec<Plugin>,
+    /// Auxiliary fixture sensors (e.g. temperature, humidity) to be sampled during provisioning
+    ///
+    /// The sensors are sampled before and after flashing, as well as after the app run,
+    /// and the samples are recorded in the summary of the PCB logs
+    #[serde(default)]
+    pub sensors: Vec<Sensor>,
+    /// Test-point measurements to be taken with external instruments (e.g. the current draw from a programmable PSU)
+    ///
+    /// The measurements are taken after the app run and are recorded in the summary and the report of the PCB logs;
+    /// a measurement outside of its thresholds fails the PCB
+    #[serde(default)]
+    pub measurements: Vec<Measurement>,
+    /// If provided, a provisioning record ("birth certificate") is generated and flashed
+    /// into a designated data partition as part of the PCB provisioning
+    #[serde(default)]
+    pub birth_certificate: Option<BirthCertificate>,
+    /// If provided, unique NVS encryption keys are generated for each PCB and flashed into its `nvs_keys` partition
+    #[serde(default)]
+    pub nvs_keys: Option<NvsKeys>,
+    /// If provided, a random flash encryption key is generated on the host for each PCB, burned into its eFuses,
+    /// used for the in-place encryption of the flash (see `flash_encrypt`) and escrowed with the PCB logs,
+    /// rather than being shipped in per-device bundles
+    #[serde(default)]
+    pub flash_encrypt_keygen: Option<FlashEncryptKeygen>,
+    /// If provided, a label is printed for each successfully provisioned PCB
+    #[serde(default)]
+    pub label: Option<Label>,
+    /// If provided, the boot mode strapping and the reset of the PCB are done by the test JIG
+    /// (e.g. an FTDI adapter or a relay board pulling IO0 and EN), so that the operator never touches the PCB
+    #[serde(default)]
+    pub jig: Option<Jig>,
+    /// If provided, line-rate metrics of the station (provisioned PCBs, failures by step, cycle times
+    /// and flash throughput) are exposed on an HTTP endpoint for Prometheus and/or pushed to a StatsD server
+    #[serde(default)]
+    pub metrics: Option<Metrics>,
+    /// If provided, audible feedback is given on the outcome of each PCB (see `Sound`),
+    /// so that the operator does not have to watch the screen
+    #[serde(default)]
+    pub sound: Option<Sound>,
+    /// If provided, a webhook (e.g. a Slack or Teams incoming webhook) is notified when the station stalls
+    /// (consecutive failures, an empty bundle pool or failing logs uploads), so that the production supervisors are paged
+    #[serde(default)]
+    pub notifications: Option<Notifications>,
+    /// If provided, the factory runs as a headless daemon without the interactive console UI,
+    /// and is driven by a custom shop-floor UI over a small REST API (see `Daemon`)
+    #[serde(default)]
+    pub daemon: Option<Daemon>,
+    /// An optional file where the operator session (the key presses and their timings, the hashes of the loaded bundles,
+    /// as well as the tool invocations and the other responses of the device) is recorded,
+    /// so that it can later be replayed with `session_replay`
+    #[serde(default)]
+    pub session_record: Option<String>,
+    /// An optional recorded session file to be replayed in the interactive console UI, for operator training,
+    /// documentation screencasts, or for reproducing a reported failure deterministically on a developer machine
+    ///
+    /// The bundles are loaded as usual, but have to be the same as the recorded ones
+    ///
+    /// When replaying, no device is needed and the configuration is adjusted with `Config::demo`
+    #[serde(default)]
+    pub session_replay: Option<String>,
+    /// If provided, the factory runs against a simulated device (see `Simulation`) rather than a real one,
+    /// for operator training and for exercising the whole provisioning in CI without hardware
+    ///
+    /// When simulating, the configuration is adjusted with `Config::demo`, except that the app run
+    /// is kept (with scripted app logs) and that the operator can still use the daemon or run without the UI
+    #[serde(default)]
+    pub simulate: Option<Simulation>,
+    /// If provided, provision exactly that many PCBs and then exit, rather than looping forever
+    ///
+    /// Meant for driving the factory from CI or from fixture scripts (usually together with `no_ui` and
+    /// `skip_confirmations`): a failed step fails the run (see `Failure` and `exit_code`) rather than offering a retry,
+    /// and quitting before all PCBs are provisioned fails the run with `Failure::Incomplete`
+    #[serde(default)]
+    pub batch_count: Option<u32>,
+    /// If provided, the PCBs are provisioned against a work order with a target quantity (e.g. a purchase order),
+    /// and the factory exits once the quantity is exhausted
+    #[serde(default)]
+    pub work_order: Option<WorkOrder>,
     /// Whether to run the app without the interactive console UI
     #[serde(default)]
     no_ui: bool,
+    /// Only relevant without the interactive console UI:
+    /// An optional timeout in seconds for each prompt, after which the app quits
+    ///
+    /// Useful when the app is driven via `expect` scripts or SSH sessions which might hang or drop
+    #[serde(default)]
+    stdin_timeout_secs: Option<u32>,
+    /// Only relevant without the interactive console UI:
+    /// An optional answer file (TOML) with the answers to the confirmation and input prompts, for fully unattended runs
+    /// driven by fixture scripts, rather than reading the answers from the standard input
+    ///
+    /// See `answer_env` for answering the prompts from environment variables instead
+    #[serde(default)]
+    pub answer_file: Option<String>,
+    /// Only relevant without the interactive console UI:
+    /// Whether to answer the prompts from `ESPFACTORY_*` environment variables named after the prompts
+    /// (e.g. `ESPFACTORY_DEVICE_ID` or `ESPFACTORY_PROVISION`), rather than reading the answers from the standard input
+    ///
+    /// The environment variables take precedence over the answer file, if both are used
+    #[serde(default)]
+    pub answer_env: bool,
     /// Only relevant with the interactive console UI:
     /// The length of the log buffer
     #[serde(default = "default_usize::<1000>")]
@@ -151,28 +665,108 @@ impl Config {
             flash_dry_run: false,
             efuse_dry_run: true,
             efuse_ignore_failed_readouts: false,
+            efuse_readouts: Vec::new(),
+            efuse_native_tables: None,
+            efuse_plan: None,
+            efuse_allow_unknown: true,
             efuse_protect_keys: false,
             efuse_protect_digests: false,
+            efuse_key_protection: Vec::new(),
+            efuse_snapshots: true,
+            efuse_batch: true,
             port: None,
+            port_pick: true,
             flash_no_stub: false,
+            flash_reset_before: None,
+            flash_reset_after: FlashResetAfter::NoReset,
+            flash_size_mismatch_ignore: false,
+            part_table_variant_pick: false,
             flash_erase: false,
+            flash_diff: false,
+            flash_verify: false,
             reset_empty_partitions: false,
+            flash_data_reset: false,
+            flash_readonly: false,
+            otadata_boot: None,
             flash_esptool: false,
+            flash_compress: None,
+            flash_jtag: None,
             flash_encrypt: false,
+            flash_encrypt_threads: 4,
             flash_speed: None,
             efuse_speed: None,
+            monitor_speed: None,
+            speed_fallbacks: Vec::new(),
             app_run: AppRun::Disabled,
+            app_run_ota_verify: None,
+            app_run_coredump: None,
+            app_run_log_format: AppLogFormat::Serial,
+            app_run_log_colors: AppLogColors::Plain,
             bundle_identification: BundleIdentification::None,
+            bundle_prefetch: false,
             test_jig_id: String::new(),
             test_jig_id_readout: false,
+            test_jig_id_source: ReadoutSource::Keyboard,
+            test_jig_id_validation: None,
+            operator_id_readout: false,
             pcb_id_readout: false,
+            pcb_id_source: ReadoutSource::Keyboard,
+            pcb_id_validation: None,
             device_id_readout: false,
+            device_id_source: ReadoutSource::Keyboard,
+            device_id_validation: None,
+            extra_readouts: Vec::new(),
             skip_confirmations: false,
+            provision_preview: true,
+            footswitch: None,
+            destructive_ack: false,
             supply_default_partition_table: true,
             supply_default_bootloader: true,
             overwrite_on_merge: false,
+            config_override_file: None,
+            base_bundle_cache: true,
+            base_bundle_cache_dir: None,
+            logs_spool: true,
+            logs_spool_dir: None,
+            logs_save_dir: None,
+            logs_max_size_kb: 65536,
+            audit_signing_key: None,
+            report_formats: Vec::new(),
+            cycle_time_budget_secs: None,
+            result_banner: false,
+            result_banner_dismiss_secs: None,
+            result_banner_dismiss_on_insert: false,
+            summary_locale: Locale::ISO,
+            ui_locale: Locale::ISO,
+            ui_theme: UiTheme::DEFAULT,
+            gui: false,
+            ui_language: None,
+            ui_messages: None,
             print_backtraces: false,
+            tools_user: None,
+            tool_port_settle_ms: 0,
+            tool_port_busy_retries: 3,
+            plugins: Vec::new(),
+            sensors: Vec::new(),
+            measurements: Vec::new(),
+            birth_certificate: None,
+            nvs_keys: None,
+            flash_encrypt_keygen: None,
+            label: None,
+            jig: None,
+            metrics: None,
+            sound: None,
+            notifications: None,
+            daemon: None,
+            session_record: None,
+            session_replay: None,
+            simulate: None,
+            batch_count: None,
+            work_order: None,
             no_ui: false,
+            stdin_timeout_secs: None,
+            answer_file: None,
+            answer_env: false,
             log_buffer_len: 1000,
         }
     }
@@ -186,6 +780,17 @@ impl Config {
         self.flash_erase = true;
     }
 
+    /// Change the configuration so that it does a factory reset of an already provisioned chip,
+    /// i.e. only its data partitions are reset, while its firmware is left intact
+    pub fn data_reset(&mut self) {
+        // eFUSEs were burned already, set dry run mode
+        self.efuse_dry_run = true;
+        // Erasing the flash would erase the firmware as well, disable
+        self.flash_erase = false;
+        // Only reset the data partitions
+        self.flash_data_reset = true;
+    }
+
     /// Change the configuration so that it does the right thing
     /// if the chip already has Secure Download mode enabled
     pub fn secure_download(&mut self) {
@@ -201,6 +806,138 @@ impl Config {
         self.reset_empty_partitions = true;
         // Can't really read from eFuse when Secure Download mode is enabled
         self.efuse_ignore_failed_readouts = true;
+        // ... hence no eFuse summary snapshots either
+        self.efuse_snapshots = false;
+    }
+
+    /// Return `true` if the configuration already does the right thing
+    /// if the chip has Secure Download mode enabled (see `Config::secure_download`)
+    pub fn is_secure_download(&self) -> bool {
+        self.efuse_dry_run
+            && !self.flash_erase
+            && self.flash_no_stub
+            && self.flash_esptool
+            && self.reset_empty_partitions
+            && self.efuse_ignore_failed_readouts
+    }
+
+    /// Return whether to run the app without the interactive console UI
+    pub fn no_ui(&self) -> bool {
+        self.no_ui
+    }
+
+    /// Set whether to run the app without the interactive console UI
+    pub fn set_no_ui(&mut self, no_ui: bool) {
+        self.no_ui = no_ui;
+    }
+
+    /// Return the timeout in seconds for each prompt when running without the interactive console UI
+    pub fn stdin_timeout_secs(&self) -> Option<u32> {
+        self.stdin_timeout_secs
+    }
+
+    /// Set the timeout in seconds for each prompt when running without the interactive console UI
+    pub fn set_stdin_timeout_secs(&mut self, secs: Option<u32>) {
+        self.stdin_timeout_secs = secs;
+    }
+
+    /// Return the length of the log buffer of the interactive console UI
+    pub fn log_buffer_len(&self) -> usize {
+        self.log_buffer_len
+    }
+
+    /// Set the length of the log buffer of the interactive console UI
+    pub fn set_log_buffer_len(&mut self, len: usize) {
+        self.log_buffer_len = len;
+    }
+
+    /// Return whether the images should be compressed when flashing them with `esptool.py`
+    /// (see `Config::flash_compress`), or `None` if the `esptool.py` default applies
+    pub fn flash_compressed(&self) -> Option<bool> {
+        self.flash_compress
+            .or((!self.flash_no_stub).then_some(true))
+    }
+
+    /// Return `true` if the bundles are identified by a readout (see `bundle_identification`
+    /// and `ExtraReadout::bundle_identification`), rather than just loading the first bundle found
+    pub fn identifies_bundles(&self) -> bool {
+        !matches!(self.bundle_identification, BundleIdentification::None)
+            || self
+                .extra_readouts
+                .iter()
+                .any(|readout| readout.bundle_identification.is_some())
+    }
+
+    /// Change the configuration so that it does the right thing
+    /// when a recorded session is replayed, i.e. without a device and peripherals being connected
+    pub fn demo(&mut self) {
+        // Never touch a real device, even if one happens to be connected
+        self.flash_dry_run = true;
+        self.efuse_dry_run = true;
+        // The app run and the peripherals of the test JIG need the real hardware, disable
+        self.app_run = AppRun::Disabled;
+        self.app_run_ota_verify = None;
+        self.app_run_coredump = None;
+        self.jig = None;
+        self.footswitch = None;
+        self.label = None;
+        self.sensors.clear();
+        self.measurements.clear();
+        // Replayed PCBs are not accounted against the real work order
+        self.work_order = None;
+        self.plugins.clear();
+        // The recorded key presses are replayed in the interactive console UI only
+        self.no_ui = false;
+        self.daemon = None;
+        self.gui = false;
+    }
+
+    /// Return a human-readable summary of the settings which irreversibly change the chips being provisioned
+    ///
+    /// An empty result means that the configuration is not destructive
+    pub fn destructive_settings(&self) -> Vec<String> {
+        let mut settings = Vec::new();
+
+        if !self.efuse_dry_run {
+            settings.push("eFuses are BURNED (`efuse_dry_run = false`)".to_string());
+
+            if self.efuse_protect_keys {
+                settings.push(
+                    "Keys are read- and write-protected (`efuse_protect_keys = true`)".to_string(),
+                );
+            }
+
+            if self.efuse_protect_digests {
+                settings.push(
+                    "Key digests are write-protected (`efuse_protect_digests = true`)".to_string(),
+                );
+            }
+
+            for protection in &self.efuse_key_protection {
+                let protected = [
+                    ("read", protection.read_protect),
+                    ("write", protection.write_protect),
+                ]
+                .into_iter()
+                .filter(|(_, protect)| *protect == Some(true))
+                .map(|(kind, _)| kind)
+                .collect::<Vec<_>>();
+
+                if !protected.is_empty() {
+                    settings.push(format!(
+                        "Block `{}` is {}-protected (`efuse_key_protection`)",
+                        protection.block,
+                        protected.join("- and ")
+                    ));
+                }
+            }
+        }
+
+        if self.flash_encrypt {
+            settings.push("Flash is ENCRYPTED (`flash_encrypt = true`)".to_string());
+        }
+
+        settings
     }
 }
 
@@ -210,6 +947,79 @@ impl Default for Config {
     }
 }
 
+/// A per-bundle override of a whitelisted subset of the factory configuration
+///
+/// A bundle might ship such an override as `config-override.toml` inside its ZIP archive,
+/// so that different products provisioned on the same station can have different provisioning policies.
+/// Settings which are not present in the override retain their values from the factory configuration
+#[derive(Clone, Default, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ConfigOverride {
+    /// Overrides `Config::flash_encrypt`
+    pub flash_encrypt: Option<bool>,
+    /// Overrides `Config::flash_erase`
+    pub flash_erase: Option<bool>,
+    /// Overrides `Config::reset_empty_partitions`
+    pub reset_empty_partitions: Option<bool>,
+    /// Overrides `Config::flash_readonly`
+    pub flash_readonly: Option<bool>,
+    /// Overrides `Config::efuse_dry_run`
+    pub efuse_dry_run: Option<bool>,
+    /// Overrides `Config::efuse_protect_keys`
+    pub efuse_protect_keys: Option<bool>,
+    /// Overrides `Config::efuse_protect_digests`
+    pub efuse_protect_digests: Option<bool>,
+    /// Overrides `Config::app_run`
+    pub app_run: Option<AppRun>,
+}
+
+impl ConfigOverride {
+    /// Merge another override into this one, where the settings of the other override take precedence
+    pub fn merge(&mut self, other: &Self) {
+        fn merge_one<T: Clone>(this: &mut Option<T>, other: &Option<T>) {
+            if other.is_some() {
+                *this = other.clone();
+            }
+        }
+
+        merge_one(&mut self.flash_encrypt, &other.flash_encrypt);
+        merge_one(&mut self.flash_erase, &other.flash_erase);
+        merge_one(
+            &mut self.reset_empty_partitions,
+            &other.reset_empty_partitions,
+        );
+        merge_one(&mut self.flash_readonly, &other.flash_readonly);
+        merge_one(&mut self.efuse_dry_run, &other.efuse_dry_run);
+        merge_one(&mut self.efuse_protect_keys, &other.efuse_protect_keys);
+        merge_one(
+            &mut self.efuse_protect_digests,
+            &other.efuse_protect_digests,
+        );
+        merge_one(&mut self.app_run, &other.app_run);
+    }
+
+    /// Apply the override to the given factory configuration
+    pub fn apply(&self, conf: &mut Config) {
+        fn apply_one<T: Clone>(conf: &mut T, value: &Option<T>) {
+            if let Some(value) = value {
+                *conf = value.clone();
+            }
+        }
+
+        apply_one(&mut conf.flash_encrypt, &self.flash_encrypt);
+        apply_one(&mut conf.flash_erase, &self.flash_erase);
+        apply_one(
+            &mut conf.reset_empty_partitions,
+            &self.reset_empty_partitions,
+        );
+        apply_one(&mut conf.flash_readonly, &self.flash_readonly);
+        apply_one(&mut conf.efuse_dry_run, &self.efuse_dry_run);
+        apply_one(&mut conf.efuse_protect_keys, &self.efuse_protect_keys);
+        apply_one(&mut conf.efuse_protect_digests, &self.efuse_protect_digests);
+        apply_one(&mut conf.app_run, &self.app_run);
+    }
+}
+
 const fn default_bool<const V: bool>() -> bool {
     V
 }
@@ -218,6 +1028,14 @@ const fn default_usize<const V: usize>() -> usize {
     V
 }
 
+const fn default_u32<const V: u32>() -> u32 {
+    V
+}
+
+const fn default_char<const V: char>() -> char {
+    V
+}
+
 /// Parameters for extracing the bundle ID from the Device ID or the PCB ID
 #[derive(Clone, Default, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
 pub struct BundleIdentificationParsing {
@@ -283,6 +1101,25 @@ pub enum BundleIdentification {
     DeviceId(BundleIdentificationParsing),
     /// Extract the bundle ID from the PCB ID
     PcbId(BundleIdentificationParsing),
+    /// Look up the bundle ID with an external service (e.g. a MES deciding the firmware variant by the work order)
+    Lookup(BundleIdentificationLookup),
+}
+
+/// The external service the bundle ID is looked up with (see `BundleIdentification::Lookup`)
+///
+/// The readouts collected so far (PCB ID, Device ID, eFuse readouts like the MAC, extra readouts and the
+/// work order ID, if any) are POSTed to the URL as a JSON object with the readout names as keys.
+/// The server replies either with a plain bundle ID, or with a JSON object having a `bundle_id` field
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct BundleIdentificationLookup {
+    /// The URL of the lookup service
+    pub url: String,
+    /// Additional HTTP headers sent with the request (e.g. `Authorization`)
+    #[serde(default)]
+    pub headers: Vec<(String, String)>,
+    /// The timeout of the lookup request
+    #[serde(default = "default_u32::<10>")]
+    pub timeout_secs: u32,
 }
 
 /// The type of device app run to perform
@@ -297,88 +1134,1417 @@ pub enum AppRun {
     /// Run the app until a given pattern is matched in the app logs
     /// or until a timeout is reached which would signify an unsuccessful app run
     MatchPattern { pattern: String, timeout_secs: u32 },
+    /// Run the app until a given pattern is matched in the app logs and capture the values of its capture groups
+    /// (e.g. a device-generated CSR or public key printed on the console), or until a timeout is reached
+    /// which would signify an unsuccessful app run
+    ///
+    /// The pattern is matched against all app log lines collected so far (separated with `\n`), so that values
+    /// spanning multiple lines can be captured as well (e.g. with `(?s)`).
+    /// The captured values are recorded as readouts in the summary of the PCB logs, using the names of
+    /// the capture groups, or `Capture <N>` for the unnamed ones
+    Capture {
+        pattern: String,
+        timeout_secs: u32,
+        /// The capture groups to be additionally uploaded as separate files in the PCB logs
+        #[serde(default)]
+        artifacts: Vec<AppRunArtifact>,
+    },
+    /// Run the app until all of the given patterns are matched in the app logs (the app run is then successful),
+    /// until one of the failure patterns is matched (e.g. `panic|abort|Guru Meditation`) or until a timeout is reached
+    /// (the app run then fails)
+    ///
+    /// Useful for judging the results of self-test firmware. Each pattern is recorded as a separate test case
+    /// in the report of the PCB
+    Expect {
+        /// The patterns which all need to be matched, each in an app log line
+        patterns: Vec<String>,
+        /// The patterns which fail the app run as soon as they are matched in an app log line
+        #[serde(default)]
+        fail_patterns: Vec<String>,
+        /// Whether the patterns need to be matched in the given order, each in a line after the line
+        /// matching the previous pattern
+        #[serde(default)]
+        ordered: bool,
+        timeout_secs: u32,
+    },
+    /// Run the app while executing a script over the serial connection: each step optionally sends a command
+    /// to the app and then optionally waits for a response matching a pattern, within the timeout of the step
+    ///
+    /// The app run is successful once all steps are complete, and fails as soon as a step times out or
+    /// one of the failure patterns is matched. Each step is recorded as a separate test case in the report of the PCB
+    Script {
+        /// The steps of the script, executed in order
+        steps: Vec<AppRunScriptStep>,
+        /// The patterns which fail the app run as soon as they are matched in an app log line
+        #[serde(default)]
+        fail_patterns: Vec<String>,
+    },
 }
 
-/// Run the factory
-///
-/// # Arguments
-/// - `conf` - The configuration of the factory
-/// - `log_level` - The log level to use
-/// - `bundle_dir` - The directory where a loaded bundle is temporarily stored for processing
-/// - `bundle_base_loader` - An optional loader used to load the base bundle; the base bundle (if used)
-///   usually contains the device-independent payloads like the bootloader, the partition image
-///   and the factory app image
-/// - `bundle_loader` - The loader used to load the bundle; in case `bundle_base_loader` is used, this
-///   loader is used to load the device-specific payloads like the NVS partitions. The two bundles are then merged
-/// - `bundle_logs_uploader` - The uploader used to upload the logs from the device provisioning to the server
-pub async fn run<B, L, U>(
-    conf: &Config,
-    log_level: log::LevelFilter,
-    bundle_base_loader: Option<B>,
-    bundle_loader: L,
-    bundle_logs_uploader: U,
-) -> anyhow::Result<()>
-where
-    B: loader::BundleLoader,
-    L: loader::BundleLoader,
-    U: uploader::BundleLogsUploader,
-{
-    let mut terminal = (!conf.no_ui).then(ratatui::init);
-    let area = terminal
-        .as_mut()
-        .map(|terminal| terminal.get_frame().area());
+/// A step of an `AppRun::Script`
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AppRunScriptStep {
+    /// The command to send to the app when the step starts, followed by a newline
+    #[serde(default)]
+    pub send: Option<String>,
+    /// The pattern which needs to be matched in an app log line received after the step starts
+    ///
+    /// If not provided, the step completes right after sending its command
+    #[serde(default)]
+    pub expect: Option<String>,
+    /// The time the step is allowed to take
+    pub timeout_secs: u32,
+}
 
-    let model = Arc::new(Model::new(
-        log_level,
-        conf.no_ui,
-        if conf.no_ui {
-            0
-        } else {
-            conf.log_buffer_len.min(5000)
-        },
-        area.map(|area| area.width).unwrap_or(0),
-        area.map(|area| area.height).unwrap_or(0),
-    ));
+/// The protection of a key or a key digest eFuse block (see `Config::efuse_key_protection`)
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct EfuseKeyProtection {
+    /// The eFuse block of the key or the key digest (e.g. `BLOCK_KEY0`)
+    pub block: String,
+    /// Whether to read-protect the block; if not provided, the global setting applies
+    #[serde(default)]
+    pub read_protect: Option<bool>,
+    /// Whether to write-protect the block; if not provided, the global setting applies
+    #[serde(default)]
+    pub write_protect: Option<bool>,
+}
 
-    LOGGER.swap_model(Some(model.clone()));
-    let _guard = scopeguard::guard((), |_| {
-        LOGGER.swap_model(None);
-    });
+/// A value captured from the app logs during the device app run, to be uploaded as a separate file in the PCB logs
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AppRunArtifact {
+    /// The name (or the number) of the capture group
+    pub group: String,
+    /// The name of the file in the PCB logs (e.g. `csr.pem`)
+    pub file: String,
+}
 
-    let result = if let Some(mut terminal) = terminal {
-        let input = Input::new(&model);
+/// The verification of the OTA app slot activated during the device app run
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct OtaVerify {
+    /// The name of the OTA app partition expected to be active (e.g. `ota_1`)
+    pub slot: String,
+    /// The expected SHA-256 of the app image in the slot, i.e. the "Validation Hash" as printed by
+    /// `esptool.py image_info`
+    ///
+    /// If not provided, only the active slot is verified
+    #[serde(default)]
+    pub sha256: Option<String>,
+}
 
-        select3(
-            View::new(&model, &mut terminal).run(),
-            Task::new(
-                model.clone(),
-                conf,
-                bundle_base_loader,
+/// The retrieval of the ESP-IDF coredump when the device app run fails
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct AppRunCoredump {
+    /// An optional command decoding the coredump (e.g. `espcoredump.py` of ESP-IDF), invoked as
+    /// `<decoder> --chip <chip> info_corefile --core <coredump> --core-format raw <elf>`
+    ///
+    /// If not provided, the coredump is only attached to the PCB logs, to be decoded offline
+    #[serde(default)]
+    pub decoder: Option<String>,
+}
+
+/// The log format of the app during the device app run
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum AppLogFormat {
+    /// Plain text logs, as emitted by the ESP-IDF logging
+    #[default]
+    Serial,
+    /// `defmt` frames, decoded with the help of the ELF file of the app image
+    Defmt,
+}
+
+/// The coloring of the app logs during the device app run
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum AppLogColors {
+    /// Plain text, with the ANSI escape sequences emitted by the app stripped
+    #[default]
+    Plain,
+    /// Colored by the ESP-IDF log level of each line (`E`, `W`, `I`, `D` or `V`),
+    /// with the ANSI escape sequences emitted by the app stripped
+    Level,
+    /// Colored with the ANSI escape sequences emitted by the app (e.g. with `CONFIG_LOG_COLORS` enabled),
+    /// and by the ESP-IDF log level for the lines without any
+    Ansi,
+}
+
+/// The reset done by the native flasher when connecting to the chip (see `Config::flash_reset_before`)
+///
+/// Same as the `--before` option of `esptool.py`
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashResetBefore {
+    /// The classic reset sequence, toggling the DTR and RTS lines
+    DefaultReset,
+    /// The reset sequence of the USB-Serial-JTAG peripheral of the newer chips
+    UsbReset,
+    /// No reset; the chip is expected to already be in the ROM bootloader
+    NoReset,
+    /// No reset and no synchronization; the chip is expected to already be in the ROM bootloader and synchronized
+    NoResetNoSync,
+}
+
+/// The reset done by the native flasher once flashing is complete (see `Config::flash_reset_after`)
+///
+/// Same as the `--after` option of `esptool.py`
+#[derive(Copy, Clone, Default, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FlashResetAfter {
+    /// No reset; the chip stays in the flasher stub (or in the ROM bootloader)
+    #[default]
+    NoReset,
+    /// No reset, but exit the flasher stub, so that the chip stays in the ROM bootloader
+    NoResetNoStub,
+    /// Reset the chip via the RTS line, booting the flashed app
+    HardReset,
+}
+
+/// The app slot to be booted, as selected by the `otadata` partition (see `Config::otadata_boot`)
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(try_from = "String", into = "String")]
+pub enum OtaBoot {
+    /// Boot the factory app (`factory`); the `otadata` partition is left blank
+    Factory,
+    /// Boot the app in the OTA slot with the given index (`ota_0`, `ota_1`, ...)
+    Ota(u8),
+}
+
+impl core::str::FromStr for OtaBoot {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s == "factory" {
+            return Ok(Self::Factory);
+        }
+
+        s.strip_prefix("ota_")
+            .and_then(|index| index.parse::<u8>().ok())
+            .filter(|index| *index < 16)
+            .map(Self::Ota)
+            .with_context(|| {
+                format!("Invalid boot slot `{s}`, expected `factory` or `ota_0` to `ota_15`")
+            })
+    }
+}
+
+impl TryFrom<String> for OtaBoot {
+    type Error = anyhow::Error;
+
+    fn try_from(value: String) -> Result<Self, Self::Error> {
+        value.parse()
+    }
+}
+
+impl From<OtaBoot> for String {
+    fn from(value: OtaBoot) -> Self {
+        value.to_string()
+    }
+}
+
+impl fmt::Display for OtaBoot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Factory => write!(f, "factory"),
+            Self::Ota(index) => write!(f, "ota_{index}"),
+        }
+    }
+}
+
+/// The colors and the branding of the interactive console UI
+///
+/// The colors are either names (e.g. `blue`, `lightgreen`), 256-color palette indexes (e.g. `17`)
+/// or RGB values (e.g. `#1e3a5f`). The colors of the result banner (PASS/FAIL) and of the provisioning statuses
+/// are not themable, as they carry a meaning
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct UiTheme {
+    /// The title rendered at the top left of the UI
+    ///
+    /// If not provided, `ESP32 Factory Provisioning` is used
+    #[serde(default)]
+    pub title: Option<String>,
+    /// An optional banner line (e.g. the customer and the product) rendered at the bottom left of the UI
+    #[serde(default)]
+    pub banner: Option<String>,
+    /// The background color. If not provided, `blue` is used
+    #[serde(default)]
+    pub background: Option<String>,
+    /// The foreground (text) color. If not provided, `white` is used
+    #[serde(default)]
+    pub foreground: Option<String>,
+    /// The color of the titles. If not provided, `green` is used
+    #[serde(default)]
+    pub title_color: Option<String>,
+    /// The color of the key instructions and the input being typed. If not provided, `yellow` is used
+    #[serde(default)]
+    pub keys_color: Option<String>,
+}
+
+impl UiTheme {
+    /// The default blue/white/green theme
+    pub const DEFAULT: Self = Self {
+        title: None,
+        banner: None,
+        background: None,
+        foreground: None,
+        title_color: None,
+        keys_color: None,
+    };
+}
+
+impl Default for UiTheme {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// The locale used for formatting dates and numbers
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Locale {
+    /// The `strftime`-style format of the dates (e.g. `%d.%m.%Y %H:%M:%S`)
+    ///
+    /// If not provided, ISO 8601 is used
+    #[serde(default)]
+    pub date_format: Option<String>,
+    /// Whether the dates are in the local time zone of the station rather than in UTC
+    #[serde(default)]
+    pub local_time: bool,
+    /// The decimal separator of the numbers
+    #[serde(default = "default_char::<'.'>")]
+    pub decimal_separator: char,
+}
+
+impl Locale {
+    /// ISO 8601 UTC dates and dot decimal separators
+    pub const ISO: Self = Self {
+        date_format: None,
+        local_time: false,
+        decimal_separator: '.',
+    };
+
+    /// Format the given date
+    pub fn format_date(&self, date: chrono::DateTime<chrono::Utc>) -> String {
+        match (self.date_format.as_deref(), self.local_time) {
+            (Some(format), false) => date.format(format).to_string(),
+            (Some(format), true) => date
+                .with_timezone(&chrono::Local)
+                .format(format)
+                .to_string(),
+            (None, false) => date.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+            (None, true) => date
+                .with_timezone(&chrono::Local)
+                .to_rfc3339_opts(chrono::SecondsFormat::Secs, false),
+        }
+    }
+
+    /// Format the current date
+    pub fn format_now(&self) -> String {
+        self.format_date(chrono::Utc::now())
+    }
+
+    /// Format the given number with the given number of decimal places
+    pub fn format_decimal(&self, value: f64, precision: usize) -> String {
+        let formatted = format!("{value:.precision$}");
+
+        if self.decimal_separator == '.' {
+            formatted
+        } else {
+            formatted.replace('.', &self.decimal_separator.to_string())
+        }
+    }
+}
+
+impl Default for Locale {
+    fn default() -> Self {
+        Self::ISO
+    }
+}
+
+/// The formats in which the report of the PCB can be emitted
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ReportFormat {
+    /// JUnit XML (`report.xml`)
+    Junit,
+    /// JSON (`report.json`)
+    Json,
+}
+
+impl ReportFormat {
+    /// The name of the file in the logs ZIP, where the report in this format is stored
+    pub const fn file_name(&self) -> &'static str {
+        match self {
+            Self::Junit => "report.xml",
+            Self::Json => "report.json",
+        }
+    }
+}
+
+/// An external plugin, i.e. an executable invoked at the hook points of the provisioning cycle
+///
+/// For each hook point, the executable is spawned with the configured arguments and a single JSON object
+/// describing the hook and the provisioning context (`hook`, `readouts`, `mac`, `bundle_id`, `bundle_name`,
+/// `chip`, `port`) is written to its standard input, where `mac` is the value of the `MAC` eFuse readout under
+/// whatever alias it is configured with. The same context is also provided in the environment variables of the plugin
+/// (`ESPFACTORY_HOOK`, `ESPFACTORY_BUNDLE_ID`, `ESPFACTORY_BUNDLE_NAME`, `ESPFACTORY_CHIP`, `ESPFACTORY_PORT`,
+/// `ESPFACTORY_MAC` and `ESPFACTORY_READOUT_<NAME>` for each readout), so that plain commands (e.g. label printing
+/// or MES check-in scripts), which do not read their standard input, can be used as plugins too.
+///
+/// The plugin might reply on its standard output with a JSON object of the form
+/// `{"readouts": [["Name", "Value"], ...], "error": "..."}` (all fields optional), where `readouts`
+/// are appended to the readouts of the PCB, and a non-empty `error` (or a non-zero exit status) fails the hook
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Plugin {
+    /// The plugin executable
+    pub command: String,
+    /// The arguments to pass to the plugin executable
+    #[serde(default)]
+    pub args: Vec<String>,
+    /// The hook points the plugin should be invoked for; if empty, the plugin is invoked for all hook points
+    #[serde(default)]
+    pub hooks: Vec<PluginHook>,
+    /// The time the plugin is allowed to run for each hook point; the plugin is killed and the hook fails
+    /// if it does not exit in time
+    #[serde(default = "default_u32::<60>")]
+    pub timeout_secs: u32,
+}
+
+impl Plugin {
+    /// Return `true` if the plugin should be invoked for the given hook point
+    pub fn handles(&self, hook: PluginHook) -> bool {
+        self.hooks.is_empty() || self.hooks.contains(&hook)
+    }
+}
+
+/// The hook points of the provisioning cycle where plugins are invoked
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum PluginHook {
+    /// Before the provisioning of a PCB starts (i.e. before the readouts)
+    PreBoard,
+    /// After the manual and eFuse readouts are done
+    PostReadout,
+    /// Before the PCB is flashed
+    #[serde(alias = "pre_flash")]
+    PreFlash,
+    /// After the PCB is flashed, but before it is eFused
+    #[serde(alias = "post_flash")]
+    PostFlash,
+    /// After the PCB is eFused
+    #[serde(alias = "post_efuse")]
+    PostEfuse,
+    /// After the app of the PCB is run
+    #[serde(alias = "post_app_run")]
+    PostAppRun,
+    /// After the PCB is provisioned and its logs are uploaded
+    PostBoard,
+}
+
+impl fmt::Display for PluginHook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::PreBoard => write!(f, "pre-board"),
+            Self::PostReadout => write!(f, "post-readout"),
+            Self::PreFlash => write!(f, "pre-flash"),
+            Self::PostFlash => write!(f, "post-flash"),
+            Self::PostEfuse => write!(f, "post-efuse"),
+            Self::PostAppRun => write!(f, "post-app-run"),
+            Self::PostBoard => write!(f, "post-board"),
+        }
+    }
+}
+
+/// An additional readout, beyond the Device ID, PCB ID and Test JIG ID readouts
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ExtraReadout {
+    /// The name of the readout, as displayed and recorded in the summary of the PCB logs (e.g. `Work Order`)
+    pub name: String,
+    /// An optional prompt of the readout input; if not provided, the name of the readout is used
+    #[serde(default)]
+    pub prompt: Option<String>,
+    /// The source of the readout
+    #[serde(default)]
+    pub source: ReadoutSource,
+    /// Optional validation rules of the readout
+    #[serde(default)]
+    pub validation: Option<ReadoutValidation>,
+    /// If provided, the bundle ID is extracted from the readout
+    ///
+    /// Only used when `Config::bundle_identification` is `BundleIdentification::None`;
+    /// if several readouts are marked, the first one is used
+    #[serde(default)]
+    pub bundle_identification: Option<BundleIdentificationParsing>,
+}
+
+/// Validation rules of a readout, so that mistyped or misread values (e.g. PCB IDs) are rejected
+/// before they reach the bundle identification or the logs
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct ReadoutValidation {
+    /// A regular expression the whole value must match (e.g. `PCB-[0-9]{8}`)
+    #[serde(default)]
+    pub regex: Option<String>,
+    /// The exact length of the value, in characters
+    #[serde(default)]
+    pub length: Option<u32>,
+    /// The algorithm of the check digit the value ends with, if any
+    #[serde(default)]
+    pub check_digit: Option<CheckDigit>,
+}
+
+/// A check digit algorithm of a readout
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CheckDigit {
+    /// The Luhn (mod 10) algorithm; the value must be all digits
+    Luhn,
+    /// The Damm algorithm; the value must be all digits
+    Damm,
+    /// The ISO 7064 MOD 97-10 algorithm (as used by IBANs) over the alphanumeric characters of the value,
+    /// with letters counting as 10 to 35; the value ends with two check digits
+    #[serde(alias = "mod97")]
+    Mod97,
+}
+
+/// A label printed for each successfully provisioned PCB
+///
+/// The label template is in the language of the printer (e.g. ZPL or EPL) and might contain
+/// `${Name}` placeholders, which are replaced with the values of the readouts with that name
+/// (e.g. `${MAC}`, `${Device ID}`, `${PCB ID}`), and `${Bundle}`, which is replaced with the name (version) of the bundle
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Label {
+    /// The label template
+    #[serde(default)]
+    pub template: String,
+    /// A file to load the label template from, instead of `template`
+    #[serde(default)]
+    pub template_file: Option<String>,
+    /// The printer the label is sent to
+    pub printer: LabelPrinter,
+}
+
+/// The source of a readout (Device ID, PCB ID, Test JIG ID)
+///
+/// With a source other than `Keyboard`, the operator can still type the value into the UI instead
+/// (e.g. when a barcode is unreadable)
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum ReadoutSource {
+    /// Typed into the UI (also by barcode scanners emulating a keyboard)
+    #[default]
+    Keyboard,
+    /// A barcode scanner attached to a serial port; each scan is terminated by CR and/or LF
+    Serial {
+        port: String,
+        #[serde(default = "default_u32::<9600>")]
+        baud: u32,
+    },
+    /// A line read from stdin (e.g. piped from another program)
+    ///
+    /// Not to be used with `no_ui`, where the operator input is read from stdin as well
+    Stdin,
+    /// A file written by another program (e.g. the test JIG software); the file is removed once read
+    File { path: String },
+    /// The first non-empty output line of an external command (e.g. a QR decoder reading a camera,
+    /// or a custom badge reader); the command is killed once the value is read
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+        /// Fall back to the keyboard if no value is read within that many seconds; 0 means no timeout
+        #[serde(default)]
+        timeout_secs: u32,
+    },
+}
+
+/// A label printer
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum LabelPrinter {
+    /// A network printer accepting raw print jobs (e.g. `192.168.1.50:9100`)
+    Network { address: String },
+    /// A printer attached to a serial port
+    Serial {
+        port: String,
+        #[serde(default = "default_u32::<9600>")]
+        baud: u32,
+    },
+    /// A printer exposed as a device file (e.g. `/dev/usb/lp0`)
+    Device { path: String },
+}
+
+/// The flasher used for flashing the device over JTAG
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum FlashJtag {
+    /// Flash with the `probe-rs` CLI
+    ProbeRs {
+        /// The `probe-rs` executable; `probe-rs` if not provided
+        #[serde(default)]
+        command: Option<String>,
+        /// The `probe-rs` target name; the bundle chip (e.g. `esp32s3`) if not provided
+        #[serde(default)]
+        target: Option<String>,
+        /// The probe to use, as `VID:PID` or `VID:PID:Serial`; if not provided, the only connected probe is used
+        #[serde(default)]
+        probe: Option<String>,
+        /// The JTAG speed in kHz
+        #[serde(default)]
+        speed_khz: Option<u32>,
+    },
+    /// Flash with the Espressif fork of OpenOCD (`openocd-esp32`) and its `program_esp` command
+    OpenOcd {
+        /// The `openocd` executable; `openocd` if not provided
+        #[serde(default)]
+        command: Option<String>,
+        /// The OpenOCD configuration files; if not provided, the built-in USB-JTAG board
+        /// configuration of the bundle chip is used (e.g. `board/esp32s3-builtin.cfg`)
+        #[serde(default)]
+        configs: Vec<String>,
+    },
+}
+
+/// The test JIG control of the PCB boot mode strapping and reset
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Jig {
+    /// The serial port used for the `Dtr`, `Rts` and `Write` actions
+    ///
+    /// If not provided, the `Write` actions use the serial port used for flashing, and the `Dtr` and `Rts`
+    /// actions are rejected, as the port is closed - deasserting DTR/RTS - before the flashing tool connects
+    #[serde(default)]
+    pub port: Option<String>,
+    /// The baud rate of the serial port (only relevant for the `Write` actions)
+    #[serde(default = "default_u32::<9600>")]
+    pub baud: u32,
+    /// Whether the flashing and eFuse tools should still reset the chip into the ROM bootloader
+    /// themselves via the DTR/RTS lines of the flashing port
+    ///
+    /// Set to `false` with adapters where the auto-reset does not work, in which case
+    /// `pre_flash` should put the chip into the ROM bootloader instead
+    #[serde(default = "default_bool::<true>")]
+    pub auto_reset: bool,
+    /// The actions run before each connection to the ROM bootloader of the chip
+    /// (the eFuse readout and the flashing), e.g. pulling IO0 low and pulsing EN
+    #[serde(default)]
+    pub pre_flash: Vec<JigAction>,
+    /// The actions run after the flashing and the eFuse burning of the PCB, e.g. releasing IO0 and pulsing EN
+    /// so that the app boots for the app run
+    #[serde(default)]
+    pub post_flash: Vec<JigAction>,
+}
+
+/// The settings of the simulated device (see `Config::simulate`)
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Simulation {
+    /// The simulated flashing speed, in KB/s
+    #[serde(default = "default_u32::<100>")]
+    pub flash_kbps: u32,
+    /// The duration of each simulated tool invocation (eFuse readout and burning, chip detection, etc.), in ms
+    #[serde(default = "default_u32::<500>")]
+    pub tool_delay_ms: u32,
+    /// An optional file with the canned eFuse summary of the simulated PCBs, in the format of
+    /// `espefuse.py summary --format json`
+    ///
+    /// If not provided, a minimal summary with a distinct MAC address for each PCB is synthesized
+    #[serde(default)]
+    pub efuse_summary: Option<String>,
+    /// An optional file with the scripted logs of the app, one log line per line
+    ///
+    /// If not provided, the boot logs of a typical ESP-IDF app are simulated
+    #[serde(default)]
+    pub app_logs: Option<String>,
+    /// The delay between the scripted app log lines, in ms
+    #[serde(default = "default_u32::<100>")]
+    pub app_log_delay_ms: u32,
+}
+
+impl Simulation {
+    /// Create new simulation settings with the default values
+    pub const fn new() -> Self {
+        Self {
+            flash_kbps: 100,
+            tool_delay_ms: 500,
+            efuse_summary: None,
+            app_logs: None,
+            app_log_delay_ms: 100,
+        }
+    }
+}
+
+impl Default for Simulation {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// A footswitch (or another single-button device) answering the prompts
+///
+/// A press confirms the confirmation prompts and accepts the preselected value of the input prompts
+/// (e.g. the serial port pick). The readouts still need a keyboard or a barcode scanner
+/// (see `ReadoutSource`), and quitting still needs the keyboard
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Footswitch {
+    /// A single key of the console UI acting as the footswitch (e.g. `Space`, `F5` or a character),
+    /// for USB footswitches emulating a keyboard
+    #[serde(default)]
+    pub key: Option<String>,
+    /// A serial port with a footswitch closing its DTR line (driven high) onto `line`
+    #[serde(default)]
+    pub port: Option<String>,
+    /// The modem status line of `port` the footswitch is wired to
+    #[serde(default)]
+    pub line: FootswitchLine,
+    /// Whether a press on an error which can be ignored ignores the error rather than retrying the step
+    #[serde(default)]
+    pub ignore_errors: bool,
+}
+
+/// The modem status line of a serial port a footswitch is wired to
+#[derive(Clone, Debug, Default, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub enum FootswitchLine {
+    Cts,
+    #[default]
+    Dsr,
+    Cd,
+    Ri,
+}
+
+/// An action of a test JIG sequence
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum JigAction {
+    /// Set the DTR line of the JIG serial port (requires `Jig::port`)
+    Dtr { level: bool },
+    /// Set the RTS line of the JIG serial port (requires `Jig::port`)
+    Rts { level: bool },
+    /// Write raw bytes to the JIG serial port, given as a hex string (e.g. `A0 01 01 A2` for a serial relay board)
+    Write { data: String },
+    /// Run an external command (e.g. a USB HID relay CLI)
+    Command {
+        command: String,
+        #[serde(default)]
+        args: Vec<String>,
+    },
+    /// Wait for the given number of milliseconds
+    Delay { ms: u64 },
+}
+
+/// An eFuse field read from the chip in the eFuse readouts step
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct EfuseReadout {
+    /// The name of the eFuse field, as known to `espefuse.py` (e.g. `ADC1_INIT_CODE_ATTEN0`)
+    pub field: String,
+    /// An optional name under which the field is displayed and recorded in the summary of the PCB logs
+    ///
+    /// If not provided, the name of the eFuse field is used
+    #[serde(default)]
+    pub alias: Option<String>,
+}
+
+/// An auxiliary fixture sensor (e.g. temperature, humidity) sampled during provisioning
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Sensor {
+    /// The name of the sensor, as recorded in the summary of the PCB logs (e.g. `Fixture Temperature`)
+    pub name: String,
+    /// The source the sensor is sampled from
+    #[serde(flatten)]
+    pub source: SensorSource,
+    /// An optional regular expression to grep only a fraction of the sensor response
+    /// (the first capture group is used as the sample value)
+    #[serde(default)]
+    pub regex: Option<String>,
+}
+
+/// The source a fixture sensor is sampled from
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum SensorSource {
+    /// A sensor attached to a serial port, which replies with a single line
+    Serial {
+        /// The serial port of the sensor
+        port: String,
+        /// The baud rate of the serial port
+        #[serde(default = "default_u32::<115200>")]
+        baud: u32,
+        /// An optional query to be sent (followed by a newline) before reading the sensor response
+        #[serde(default)]
+        query: Option<String>,
+        /// The time to wait for the sensor response
+        #[serde(default = "default_u32::<1000>")]
+        timeout_ms: u32,
+    },
+    /// A sensor which is sampled with an HTTP GET request and replies with the sample in the response body
+    Http {
+        /// The URL of the sensor
+        url: String,
+        /// The time to wait for the sensor response
+        #[serde(default = "default_u32::<1000>")]
+        timeout_ms: u32,
+    },
+}
+
+/// A test-point measurement taken with an external instrument after the app run
+///
+/// The thresholds are decimal numbers in the unit of the instrument response (e.g. `0.150` for 150mA)
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Measurement {
+    /// The name of the measurement, as recorded in the summary and the report of the PCB logs (e.g. `Idle Current`)
+    pub name: String,
+    /// The instrument the measurement is taken with
+    #[serde(flatten)]
+    pub instrument: Instrument,
+    /// SCPI commands to be sent to the instrument before the query (e.g. `CONF:CURR:DC`)
+    #[serde(default)]
+    pub setup: Vec<String>,
+    /// The SCPI query returning the measured value (e.g. `MEAS:CURR?`)
+    pub query: String,
+    /// An optional unit of the measured value, as displayed in the summary of the PCB logs (e.g. `A`)
+    #[serde(default)]
+    pub unit: Option<String>,
+    /// The minimum accepted value, if any
+    #[serde(default)]
+    pub min: Option<String>,
+    /// The maximum accepted value, if any
+    #[serde(default)]
+    pub max: Option<String>,
+    /// The time to wait before taking the measurement, so that the PCB settles
+    #[serde(default)]
+    pub settle_ms: u32,
+}
+
+/// The instrument a test-point measurement is taken with
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum Instrument {
+    /// An instrument accepting SCPI commands over a raw TCP socket
+    Tcp {
+        /// The address of the instrument, as `<host>:<port>` (e.g. `192.168.1.50:5025`)
+        address: String,
+        /// The time to wait for the instrument response
+        #[serde(default = "default_u32::<2000>")]
+        timeout_ms: u32,
+    },
+    /// An instrument accepting SCPI commands over a serial port
+    Serial {
+        /// The serial port of the instrument
+        port: String,
+        /// The baud rate of the serial port
+        #[serde(default = "default_u32::<9600>")]
+        baud: u32,
+        /// The time to wait for the instrument response
+        #[serde(default = "default_u32::<2000>")]
+        timeout_ms: u32,
+    },
+}
+
+/// The provisioning record ("birth certificate") settings
+///
+/// The certificate is a NUL-terminated JSON object of the form
+/// `{"version": 1, "date": "...", "station_id": "...", "bundle": "...", "report_hash": "..."}`
+/// where `report_hash` is the SHA-256 hash of the PCB readouts (`<name>=<value>\n` lines) recorded before flashing,
+/// including the `pre-flash` sensor samples, the ones added by the `pre-flash` plugins and the hashes of the generated keys
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct BirthCertificate {
+    /// The name of the data partition where the certificate is flashed
+    pub partition: String,
+    /// The ID of the provisioning station
+    ///
+    /// If empty, the Test JIG ID is used
+    #[serde(default)]
+    pub station_id: String,
+}
+
+/// The per-PCB NVS encryption keys settings
+///
+/// The keys are flashed like any other image, so - with `flash_encrypt` - they are encrypted before flashing,
+/// as long as the `nvs_keys` partition is marked as `encrypted` in the partition table (as required by ESP-IDF)
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct NvsKeys {
+    /// The name of the partition where the keys are flashed
+    ///
+    /// If not provided, the first partition of subtype `nvs_keys` is used
+    #[serde(default)]
+    pub partition: Option<String>,
+    /// If provided, the generated keys are escrowed: encrypted with this public key and attached to the PCB logs
+    /// (`nvs-keys.age` or `nvs-keys.rsa`), as well as persisted in a local directory before they are flashed (see `dir`)
+    ///
+    /// The keys are never recorded in plain text: the summary of the PCB logs only contains their SHA-256 hash.
+    /// Requires the `escrow` feature
+    #[serde(default)]
+    pub escrow: Option<EscrowKey>,
+    /// The directory where the escrowed keys are persisted before they are flashed,
+    /// so that they are not lost if the PCB logs are not uploaded (e.g. when a later step fails)
+    ///
+    /// If not provided, `espfactory/escrow` in the per-user cache directory of the host is used
+    #[serde(default)]
+    pub dir: Option<String>,
+}
+
+/// The on-host generation of the per-PCB flash encryption keys
+///
+/// The generated `XTS_AES_128_KEY` key is added to the eFuses of the bundle, which therefore must not have
+/// a flash encryption key of its own. The key is never recorded in plain text: the PCB logs only contain
+/// its SHA-256 hash, and the key encrypted with the escrow public key (`flash-encryption-key.age`
+/// or `flash-encryption-key.rsa`), so a logs uploader is required for the keys to be recoverable.
+/// The encrypted key is also persisted in a local directory before it is burned (see `dir`)
+///
+/// The read- and write-protection of the key follow `efuse_key_protection` and `efuse_protect_keys`.
+/// Requires `flash_encrypt = true` and the `escrow` feature
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct FlashEncryptKeygen {
+    /// The eFuse block where the key is burned; `BLOCK_KEY0` if not provided
+    #[serde(default)]
+    pub block: Option<String>,
+    /// The public key the generated keys are encrypted with for the escrow
+    pub escrow: EscrowKey,
+    /// The directory where the encrypted keys are persisted before they are burned,
+    /// so that they are not lost if the PCB logs are not uploaded (e.g. when a later step fails)
+    ///
+    /// If not provided, `espfactory/escrow` in the per-user cache directory of the host is used
+    #[serde(default)]
+    pub dir: Option<String>,
+}
+
+/// The public key the escrowed keys are encrypted with
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum EscrowKey {
+    /// An RSA public key in a PEM file (`BEGIN PUBLIC KEY` or `BEGIN RSA PUBLIC KEY`);
+    /// the keys are encrypted with RSA-OAEP (SHA-256) and base64-encoded
+    Rsa { path: String },
+    /// An age X25519 recipient (`age1...`); the keys are encrypted into an ASCII-armored age file
+    Age { recipient: String },
+}
+
+/// The configuration of the station metrics
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Metrics {
+    /// The address where the Prometheus metrics endpoint (`/metrics`) listens, e.g. `0.0.0.0:9100`
+    #[serde(default)]
+    pub listen: Option<String>,
+    /// The address (`host:port`) of a StatsD server where the metrics are pushed to, as they are collected
+    #[serde(default)]
+    pub statsd: Option<String>,
+    /// The prefix of the metric names; `espfactory` if not provided
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// The value of the `station` label of the metrics
+    ///
+    /// If not provided, the configured Test JIG ID is used, or - if there is none - the host name
+    /// (as per the `HOSTNAME` or `COMPUTERNAME` environment variables)
+    #[serde(default)]
+    pub station: Option<String>,
+}
+
+/// The audible feedback on the outcome of the provisioning of a PCB
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Sound {
+    /// The sound played once a PCB is provisioned; no sound if not provided
+    #[serde(default)]
+    pub success: Option<SoundEffect>,
+    /// The sound played when a step fails; no sound if not provided
+    #[serde(default)]
+    pub failure: Option<SoundEffect>,
+}
+
+/// A sound played by the station
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+#[serde(tag = "type")]
+pub enum SoundEffect {
+    /// A pattern of terminal bells (e.g. three short beeps on a failure)
+    Bell {
+        /// The number of bells
+        #[serde(default = "default_u32::<1>")]
+        count: u32,
+        /// The pause between the bells
+        #[serde(default = "default_u32::<250>")]
+        interval_ms: u32,
+    },
+    /// A WAV file played on the default audio output; requires the `sound` feature
+    Wav { path: String },
+}
+
+/// The webhook notifications of the station
+///
+/// The notifications are POSTed as a Slack-compatible JSON object with a `text` field,
+/// which is also accepted by the Teams and Mattermost incoming webhooks
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Notifications {
+    /// The URL of the webhook
+    pub webhook: String,
+    /// Notify once that many consecutive PCBs fail (i.e. are abandoned after an error) without a PCB being provisioned;
+    /// 0 means never
+    #[serde(default = "default_u32::<3>")]
+    pub consecutive_failures: u32,
+    /// Notify when no bundle is left in the bundle pool to load a random bundle from
+    #[serde(default = "default_bool::<true>")]
+    pub pool_empty: bool,
+    /// Notify when uploading the PCB logs fails
+    #[serde(default = "default_bool::<true>")]
+    pub upload_errors: bool,
+    /// The name of the station in the notifications
+    ///
+    /// If not provided, the configured Test JIG ID is used, or - if there is none - the host name
+    /// (as per the `HOSTNAME` or `COMPUTERNAME` environment variables)
+    #[serde(default)]
+    pub station: Option<String>,
+}
+
+/// A work order the PCBs are provisioned against
+///
+/// Either `quantity` or `url` has to be provided
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct WorkOrder {
+    /// The ID of the work order (e.g. the purchase order number), recorded in the summary of the PCB logs
+    pub id: String,
+    /// The target quantity of the work order
+    #[serde(default)]
+    pub quantity: Option<u32>,
+    /// A URL of the work order on a server shared by the stations, instead of `quantity`
+    ///
+    /// A `${id}` placeholder in the URL is replaced with the ID of the work order:
+    /// - `GET` - fetch the remaining quantity when the factory starts
+    /// - `POST` - claim a unit before provisioning a PCB; the server rejects the claim with `409 Conflict` or `410 Gone`
+    ///   once the work order is exhausted
+    /// - `DELETE` - release a claimed unit which was not provisioned when the factory quits
+    ///
+    /// The server replies to `GET` and `POST` either with the remaining quantity as a plain number, or with a JSON object
+    /// having a `remaining` field
+    #[serde(default)]
+    pub url: Option<String>,
+    /// Only relevant with `quantity`:
+    /// An optional file where the number of PCBs provisioned against the work order is persisted,
+    /// so that the quota is enforced across factory restarts
+    #[serde(default)]
+    pub counter_file: Option<String>,
+}
+
+/// The configuration of the headless daemon mode
+///
+/// The REST API is served over plain HTTP:
+/// - `GET /status` - the state of the factory and the pending prompt (if any), as a JSON object
+/// - `POST /provision`, `POST /skip`, `POST /cancel`, `POST /quit` - answer the pending prompt
+/// - `POST /input` - answer the pending input prompt (e.g. the PCB ID) with the request body
+/// - `GET /logs` - stream the log lines as they are logged
+///
+/// If `token` is provided, each request must carry it as an `Authorization: Bearer <token>` header
+#[derive(Clone, Debug, Eq, PartialEq, Hash, Serialize, Deserialize)]
+pub struct Daemon {
+    /// The address where the REST API listens; `127.0.0.1:8080` by default
+    ///
+    /// Listening on a non-loopback address (e.g. `0.0.0.0:8080`) requires `token`
+    #[serde(default = "default_daemon_listen")]
+    pub listen: String,
+    /// An optional bearer token the requests must carry
+    ///
+    /// If not provided, it is taken from the `ESPFACTORY_DAEMON_TOKEN` environment variable, if set
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+impl Daemon {
+    /// The bearer token the requests must carry, if any
+    pub fn token(&self) -> Option<String> {
+        self.token
+            .clone()
+            .or_else(|| std::env::var("ESPFACTORY_DAEMON_TOKEN").ok())
+            .filter(|token| !token.is_empty())
+    }
+}
+
+fn default_daemon_listen() -> String {
+    "127.0.0.1:8080".to_string()
+}
+
+/// The reason a factory run failed, attached as a context to the error returned by `run` (see `Error::failure`)
+///
+/// Each failure has a distinct process exit code (see `exit_code`), so that scripts driving the factory
+/// in batch mode (see `Config::batch_count`) can tell the failures apart
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Failure {
+    /// Loading the bundle failed, e.g. no bundle was found for the PCB
+    BundleLoad,
+    /// Preparing the loaded bundle failed
+    Bundle,
+    /// Reading the eFuse readouts failed
+    EfuseReadout,
+    /// Flashing the chip failed
+    Flash,
+    /// Burning the eFuses failed
+    Efuse,
+    /// Provisioning the PCB failed for another reason (e.g. the chip check or a plugin during provisioning)
+    Provision,
+    /// Running the app after provisioning failed
+    AppRun,
+    /// Running a pre-board, post-readout or post-app-run plugin failed
+    Plugin,
+    /// The run was quit before all PCBs of the batch were provisioned
+    Incomplete,
+    /// Taking a test-point measurement failed, or a measured value is outside of its thresholds
+    Measurement,
+}
+
+impl Failure {
+    /// Return the process exit code of the failure
+    pub const fn exit_code(&self) -> i32 {
+        match self {
+            Self::BundleLoad => 2,
+            Self::Bundle => 3,
+            Self::EfuseReadout => 4,
+            Self::Flash => 5,
+            Self::Efuse => 6,
+            Self::Provision => 7,
+            Self::AppRun => 8,
+            Self::Plugin => 9,
+            Self::Incomplete => 10,
+            Self::Measurement => 11,
+        }
+    }
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::BundleLoad => write!(f, "Loading the bundle failed"),
+            Self::Bundle => write!(f, "Preparing the bundle failed"),
+            Self::EfuseReadout => write!(f, "Reading the eFuses failed"),
+            Self::Flash => write!(f, "Flashing failed"),
+            Self::Efuse => write!(f, "Burning the eFuses failed"),
+            Self::Provision => write!(f, "Provisioning failed"),
+            Self::AppRun => write!(f, "Running the app failed"),
+            Self::Plugin => write!(f, "Running a plugin failed"),
+            Self::Incomplete => write!(f, "Quit before the batch was complete"),
+            Self::Measurement => write!(f, "Measuring failed"),
+        }
+    }
+}
+
+/// Return the process exit code for an error returned by `run`
+///
+/// The code is the one of the `Failure` of the error (if any), or `1` otherwise
+pub fn exit_code(err: &Error) -> i32 {
+    err.failure().as_ref().map(Failure::exit_code).unwrap_or(1)
+}
+
+/// Run the factory
+///
+/// # Arguments
+/// - `conf` - The configuration of the factory
+/// - `log_level` - The log level to use
+/// - `events` - The subscribers of the provisioning events of the run (see `Events::subscribe`)
+/// - `bundle_dir` - The directory where a loaded bundle is temporarily stored for processing
+/// - `bundle_base_loaders` - The loaders used to load the layers of the base bundle, from the bottom layer upwards;
+///   the base bundle layers (if any) usually contain the device-independent payloads like the bootloader,
+///   the partition image and the factory app image (e.g. a common-platform layer and a product layer)
+/// - `bundle_loader` - The loader used to load the bundle; in case `bundle_base_loaders` are used, this
+///   loader is used to load the device-specific payloads like the NVS partitions. The layers are then merged in order,
+///   with the bundle of this loader being the top layer
+/// - `bundle_logs_uploader` - The uploader used to upload the logs from the device provisioning to the server
+///
+/// The settings of the run are kept by the run itself, so several runs can be active in the process at a time.
+/// Note however that `LOGGER` routes the factory logs to the run started last.
+pub async fn run<B, L, U>(
+    conf: &Config,
+    log_level: log::LevelFilter,
+    events: &Events,
+    bundle_base_loaders: Vec<B>,
+    bundle_loader: L,
+    bundle_logs_uploader: U,
+) -> Result<(), Error>
+where
+    B: loader::BundleLoader,
+    L: loader::BundleLoader,
+    U: uploader::BundleLogsUploader,
+{
+    if conf.session_replay.is_some() && conf.simulate.is_some() {
+        return Err(
+            anyhow::anyhow!("A session cannot be replayed against a simulated device").into(),
+        );
+    }
+
+    let demo_conf;
+    let conf = if conf.session_replay.is_some() || conf.simulate.is_some() {
+        demo_conf = {
+            let mut demo_conf = conf.clone();
+            demo_conf.demo();
+
+            if conf.simulate.is_some() {
+                // The simulated device runs the app with scripted app logs, and
+                // the simulated PCBs are provisioned by a real operator (or by CI)
+                demo_conf.app_run = conf.app_run.clone();
+                demo_conf.no_ui = conf.no_ui;
+                demo_conf.daemon = conf.daemon.clone();
+            }
+
+            demo_conf
+        };
+
+        &demo_conf
+    } else {
+        conf
+    };
+
+    let remote_conf;
+    let conf = if remote::is_remote(conf.port.as_deref())
+        && !conf.flash_esptool
+        && conf.flash_jtag.is_none()
+    {
+        log::info!("The native flasher needs a local serial port, flashing over the networked serial port with `esptool.py`");
+
+        remote_conf = {
+            let mut remote_conf = conf.clone();
+            remote_conf.flash_esptool = true;
+
+            remote_conf
+        };
+
+        &remote_conf
+    } else {
+        conf
+    };
+
+    let session = Arc::new(Session::start(
+        conf.session_record.as_deref(),
+        conf.session_replay.as_deref(),
+    )?);
+
+    let metrics = if let Some(metrics) = conf
+        .metrics
+        .as_ref()
+        .filter(|_| !session.replaying() && conf.simulate.is_none())
+    {
+        metrics::Metrics::start(Some(metrics), &station(conf, metrics.station.as_deref()))?
+    } else {
+        metrics::Metrics::start(None, "")?
+    };
+
+    if let Some(notifications) = conf
+        .notifications
+        .as_ref()
+        .filter(|_| !session.replaying() && conf.simulate.is_none())
+    {
+        notifications::start(
+            notifications,
+            &station(conf, notifications.station.as_deref()),
+            events,
+        )?;
+    }
+
+    if let Some(sound) = conf.sound.as_ref() {
+        sound::start(sound, events)?;
+    }
+
+    if conf.flash_encrypt_keygen.is_some() && !cfg!(feature = "escrow") {
+        return Err(anyhow::anyhow!("`flash_encrypt_keygen` requires the `escrow` feature").into());
+    }
+
+    if conf
+        .nvs_keys
+        .as_ref()
+        .is_some_and(|nvs_keys| nvs_keys.escrow.is_some())
+        && !cfg!(feature = "escrow")
+    {
+        return Err(
+            anyhow::anyhow!("Escrowing the `nvs_keys` requires the `escrow` feature").into(),
+        );
+    }
+
+    if conf.gui && !cfg!(feature = "gui") {
+        return Err(anyhow::anyhow!("`gui = true` requires the `gui` feature").into());
+    }
+
+    if conf.gui && cfg!(target_os = "macos") {
+        return Err(anyhow::anyhow!(
+            "`gui = true` is not supported on macOS, where the GUI cannot run outside of the main thread"
+        )
+        .into());
+    }
+
+    let no_ui = conf.no_ui || conf.daemon.is_some() || conf.gui;
+
+    let ui_theme = ui::view::Theme::resolve(&conf.ui_theme)?;
+    let ui_catalog = i18n::Catalog::load(conf.ui_language.as_deref(), conf.ui_messages.as_deref())?;
+
+    let (footswitch, ignore_errors) = if let Some(footswitch) = conf.footswitch.as_ref() {
+        (Switch::start(footswitch)?, footswitch.ignore_errors)
+    } else {
+        (Arc::new(Switch::new()), false)
+    };
+
+    let backend = Backend::new(
+        conf,
+        Arc::new(ToolRunner::new(conf)?.with_session(session.clone())),
+    )?;
+
+    let mut terminal = (!no_ui).then(ratatui::init);
+    let area = terminal
+        .as_mut()
+        .map(|terminal| terminal.get_frame().area());
+
+    let model = Arc::new(
+        Model::new(
+            log_level,
+            no_ui,
+            if no_ui && !conf.gui {
+                0
+            } else {
+                conf.log_buffer_len.min(5000)
+            },
+            area.map(|area| area.width).unwrap_or(0),
+            area.map(|area| area.height).unwrap_or(0),
+        )
+        .with_events(events.clone())
+        .with_metrics(metrics),
+    );
+
+    model.modify(|inner| {
+        inner
+            .logs
+            .file
+            .set_save_dir(conf.logs_save_dir.as_ref().map(std::path::PathBuf::from));
+        inner
+            .logs
+            .file
+            .set_max_size(conf.logs_max_size_kb as u64 * 1024);
+        inner.ui_theme = ui_theme;
+        inner.ui_catalog = ui_catalog.clone();
+    });
+
+    LOGGER.swap_model(Some(model.clone()));
+    let _guard = scopeguard::guard((), |_| {
+        LOGGER.swap_model(None);
+    });
+
+    let result = if let Some(mut terminal) = terminal {
+        let input = Input::new(&model, footswitch.clone(), session);
+
+        select3(
+            View::new(&model, &mut terminal).run(),
+            Task::new(
+                model.clone(),
+                conf,
+                backend,
+                bundle_base_loaders,
                 bundle_loader,
                 bundle_logs_uploader,
             )
-            .run(&input),
+            .run(FootswitchInput::new(&input, footswitch, ignore_errors)),
             run_log(&model, &input),
         )
         .coalesce()
         .await
+    } else if conf.daemon.is_some() || conf.gui {
+        let api = daemon::Api::new();
+
+        if let Some(daemon) = conf.daemon.as_ref() {
+            api.serve(&daemon.listen, daemon.token().as_deref(), model.clone())?;
+        }
+
+        #[cfg(feature = "gui")]
+        let gui_failed = if conf.gui {
+            Some(ui::gui::start(model.clone(), api.clone())?)
+        } else {
+            None
+        };
+
+        // Fail rather than wait forever for prompt answers if the GUI fails
+        let gui = async {
+            #[cfg(feature = "gui")]
+            if let Some(gui_failed) = gui_failed.as_ref() {
+                return Err(gui_failed.wait().await);
+            }
+
+            core::future::pending::<anyhow::Result<()>>().await
+        };
+
+        select(
+            Task::new(
+                model.clone(),
+                conf,
+                backend,
+                bundle_base_loaders,
+                bundle_loader,
+                bundle_logs_uploader,
+            )
+            .run(FootswitchInput::new(api, footswitch, ignore_errors)),
+            gui,
+        )
+        .coalesce()
+        .await
+    } else if conf.answer_file.is_some() || conf.answer_env {
+        let answers = input::Answers::new(conf.answer_file.as_deref(), conf.answer_env)?;
+
+        Task::new(
+            model.clone(),
+            conf,
+            backend,
+            bundle_base_loaders,
+            bundle_loader,
+            bundle_logs_uploader,
+        )
+        .run(FootswitchInput::new(answers, footswitch, ignore_errors))
+        .await
     } else {
         Task::new(
             model.clone(),
             conf,
-            bundle_base_loader,
+            backend,
+            bundle_base_loaders,
             bundle_loader,
             bundle_logs_uploader,
         )
-        .run(input::Stdin)
+        .run(FootswitchInput::new(
+            input::Stdin::new(
+                conf.stdin_timeout_secs
+                    .map(|secs| core::time::Duration::from_secs(secs as _)),
+                ui_catalog,
+            ),
+            footswitch,
+            ignore_errors,
+        ))
         .await
     };
 
-    if !conf.no_ui {
+    if !no_ui {
         ratatui::restore();
     }
 
-    result
+    result.map_err(Error::from)
+}
+
+/// Load and prepare the bundle(s) exactly like `run` does, and return a plan of what would be flashed and burned
+///
+/// The partition mapping, the image sizes, the eFuse operations and the tool command lines are rendered,
+/// without touching the hardware. Useful for validating bundles in CI.
+///
+/// # Arguments
+/// - `conf` - The configuration of the factory
+/// - `bundle_base_loaders` - The loaders used to load the layers of the base bundle, from the bottom layer upwards
+/// - `bundle_loader` - The loader used to load the bundle
+/// - `bundle_id` - The ID of the bundle to load, if the bundle loader requires one
+pub async fn plan<B, L>(
+    conf: &Config,
+    bundle_base_loaders: Vec<B>,
+    bundle_loader: L,
+    bundle_id: Option<&str>,
+) -> Result<String, Error>
+where
+    B: loader::BundleLoader,
+    L: loader::BundleLoader,
+{
+    let model = Arc::new(Model::new(log::LevelFilter::Info, true, 0, 0, 0));
+    let backend = Backend::new(conf, Arc::new(ToolRunner::new(conf)?))?;
+
+    Task::new(model, conf, backend, bundle_base_loaders, bundle_loader, ())
+        .plan(bundle_id)
+        .await
+        .map_err(Error::from)
+}
+
+/// Return the name of the station: the explicitly configured one, or the Test JIG ID, or the host name
+fn station(conf: &Config, station: Option<&str>) -> String {
+    station
+        .map(str::to_string)
+        .or_else(|| (!conf.test_jig_id.is_empty()).then(|| conf.test_jig_id.clone()))
+        .or_else(|| std::env::var("HOSTNAME").ok())
+        .or_else(|| std::env::var("COMPUTERNAME").ok())
+        .unwrap_or_else(|| "station".to_string())
 }
 
 /// Run the interaction with the logs view
@@ -400,6 +2566,14 @@ async fn run_log(model: &Model, mut input: impl LogInput) -> anyhow::Result<()>
                 LogInputOutcome::PgDown => log.page_scroll_y(false),
                 LogInputOutcome::Up => log.scroll_y(true),
                 LogInputOutcome::Down => log.scroll_y(false),
+                LogInputOutcome::Search => log.search_start(),
+                LogInputOutcome::SearchChar(ch) => log.search_edit(Some(ch)),
+                LogInputOutcome::SearchBackspace => log.search_edit(None),
+                LogInputOutcome::SearchDone => log.search_done(),
+                LogInputOutcome::SearchCancel => log.search_cancel(),
+                LogInputOutcome::SearchNext => log.search_next(false),
+                LogInputOutcome::SearchPrev => log.search_next(true),
+                LogInputOutcome::Filter => log.toggle_filter(),
             }
 
             ((), true)
@@ -1,14 +1,35 @@
+use core::fmt;
+
 use std::io::Write;
 use std::path::PathBuf;
 
 use url::Url;
 
+use crate::auth::HttpAuth;
+use crate::Error;
+
+pub mod cache;
 pub mod dir;
 pub mod file;
 pub mod http;
 #[cfg(feature = "s3")]
 pub mod s3;
 
+/// A loader of random bundles has no bundle left in its pool (e.g. the bundles' directory or the S3 bucket)
+///
+/// Loaders attach it to the load error (e.g. `anyhow::Error::new(BundlePoolEmpty).context(message)`),
+/// so that the factory reports the load error as `ProvisioningEvent::BundlePoolEmpty`
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub struct BundlePoolEmpty;
+
+impl fmt::Display for BundlePoolEmpty {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "No bundle is left in the bundle pool")
+    }
+}
+
+impl std::error::Error for BundlePoolEmpty {}
+
 /// Supported bundle types
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
 pub enum BundleType {
@@ -26,7 +47,17 @@ pub enum BundleType {
     ///                                   if missing, the partition will be left empty
     /// ...
     /// /efuses/<efuse_name> (optional) - a binary file with an efuse content
+    /// /efuses.toml (optional)         - a TOML manifest declaring efuses (params, keys, digests, custom MAC) in a structured way,
+    ///                                   as an alternative to encoding them in the names of the files in `/efuses`
     Complete,
+    /// A real bundle, as a gzip-compressed TAR file
+    ///
+    /// The content of the TAR file has the same layout as the one of `Complete`
+    CompleteTarGz,
+    /// A real bundle, as a zstd-compressed TAR file
+    ///
+    /// The content of the TAR file has the same layout as the one of `Complete`
+    CompleteTarZst,
     /// Binary application image
     ///
     /// This is a single binary file to be flashed to the device
@@ -49,7 +80,22 @@ pub enum BundleType {
 impl BundleType {
     /// Iterate over all supported bundle types
     pub fn iter() -> impl Iterator<Item = Self> {
-        [Self::Complete, Self::BinAppImage, Self::ElfAppImage].into_iter()
+        [
+            Self::Complete,
+            Self::CompleteTarGz,
+            Self::CompleteTarZst,
+            Self::BinAppImage,
+            Self::ElfAppImage,
+        ]
+        .into_iter()
+    }
+
+    /// Return `true` if the bundle type is a real bundle, in any of the supported containers
+    pub const fn is_complete(&self) -> bool {
+        matches!(
+            self,
+            Self::Complete | Self::CompleteTarGz | Self::CompleteTarZst
+        )
     }
 
     /// Get the file name for the bundle with the given ID
@@ -61,6 +107,8 @@ impl BundleType {
     pub const fn suffix(&self) -> &str {
         match self {
             Self::Complete => ".bundle",
+            Self::CompleteTarGz => ".bundle.tar.gz",
+            Self::CompleteTarZst => ".bundle.tar.zst",
             Self::BinAppImage => ".bin",
             Self::ElfAppImage => "",
         }
@@ -76,25 +124,98 @@ pub trait BundleLoader {
     /// - `id` - an optional ID of the bundle to load, where the ID is usually a PCB number, or a device ID number
     ///   (see `BundleIdentification`)
     ///   if provided, then the bundle with the given ID is loaded and the bundle is not removed from the source
-    ///   if not provided, then a random bundle is loaded and the bundle is removed from the source
+    ///   if not provided, then a random bundle is loaded and - with loaders which remove the random bundles from the source
+    ///   (e.g. `dird:` and `s3d:`) - the bundle is claimed, so that it is not loaded again, until it is either committed
+    ///   (see `commit`) or released (see `release`)
     ///
     /// # Returns
-    /// The name of the loaded bundle
-    async fn load<W>(&mut self, write: W, id: Option<&str>) -> anyhow::Result<String>
+    /// The name of the loaded bundle, or an `Error` (usually an `Error::Bundle` one) if loading the bundle failed
+    async fn load<W>(&mut self, write: W, id: Option<&str>) -> Result<String, Error>
     where
         W: Write;
+
+    /// Return a cheap fingerprint (e.g. an ETag, or a modification time and a size) of the bundle
+    /// which would be loaded by `load` with the same `id`, without loading the bundle itself
+    ///
+    /// Used for caching rarely changing bundles (i.e. the base bundle). Loaders which cannot fingerprint
+    /// a bundle return `None`, in which case the bundle is always loaded
+    ///
+    /// # Arguments
+    /// - `id` - an optional ID of the bundle, as in `load`
+    async fn fingerprint(&mut self, _id: Option<&str>) -> Result<Option<String>, Error> {
+        Ok(None)
+    }
+
+    /// Remove a claimed bundle from the source, once the bundle is provisioned and the logs of its provisioning are uploaded
+    ///
+    /// Does nothing if the bundle with that name is not claimed by this loader (e.g. when it was loaded by ID)
+    ///
+    /// # Arguments
+    /// - `name` - the name of the bundle, as returned by `load`
+    async fn commit(&mut self, _name: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Release the claim of a bundle which did not get provisioned, so that it can be loaded again
+    ///
+    /// Does nothing if the bundle with that name is not claimed by this loader (e.g. when it was loaded by ID)
+    ///
+    /// # Arguments
+    /// - `name` - the name of the bundle, as returned by `load`
+    async fn release(&mut self, _name: &str) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Quarantine a claimed bundle which did not get provisioned, but was (possibly partially) written to a PCB,
+    /// so that it is neither loaded again nor lost, and can be inspected
+    ///
+    /// Does nothing if the bundle with that name is not claimed by this loader (e.g. when it was loaded by ID)
+    ///
+    /// # Arguments
+    /// - `name` - the name of the bundle, as returned by `load`
+    async fn quarantine(&mut self, _name: &str) -> Result<(), Error> {
+        Ok(())
+    }
 }
 
 impl<T> BundleLoader for &mut T
 where
     T: BundleLoader,
 {
-    async fn load<W>(&mut self, write: W, id: Option<&str>) -> anyhow::Result<String>
+    async fn load<W>(&mut self, write: W, id: Option<&str>) -> Result<String, Error>
     where
         W: Write,
     {
         (*self).load(write, id).await
     }
+
+    async fn fingerprint(&mut self, id: Option<&str>) -> Result<Option<String>, Error> {
+        (*self).fingerprint(id).await
+    }
+
+    async fn commit(&mut self, name: &str) -> Result<(), Error> {
+        (*self).commit(name).await
+    }
+
+    async fn release(&mut self, name: &str) -> Result<(), Error> {
+        (*self).release(name).await
+    }
+
+    async fn quarantine(&mut self, name: &str) -> Result<(), Error> {
+        (*self).quarantine(name).await
+    }
+}
+
+/// A loader which has no bundles, for when no loader is configured (e.g. no base bundle layers)
+impl BundleLoader for () {
+    async fn load<W>(&mut self, _write: W, _id: Option<&str>) -> Result<String, Error>
+    where
+        W: Write,
+    {
+        Err(Error::bundle(anyhow::anyhow!(
+            "No bundle loader configured"
+        )))
+    }
 }
 
 /// Wrapper enum for the loaders supported OOTB
@@ -113,7 +234,27 @@ pub enum Loader {
 }
 
 impl Loader {
-    pub fn new(url: &Url, delete_after_load_allowed: bool) -> anyhow::Result<Self> {
+    /// Create a loader for the given URL
+    ///
+    /// # Arguments
+    /// - `url` - the URL of the bundles; the scheme selects the loader
+    /// - `delete_after_load_allowed` - whether loaders which delete the bundles after loading them are allowed
+    /// - `http_auth` - the authorization of the HTTP(S) requests, if any
+    /// - `logs_urls` - the URLs where the logs of the provisioned bundles are uploaded, if the loader should refuse
+    ///   to load a bundle by ID whose logs are already uploaded; the first one of the same kind as `url` is checked
+    ///   (a `dir` directory for a `dir`/`dird` loader, an HTTP(S) URL for an HTTP(S) loader, an `s3` bucket for an `s3`/`s3d` loader)
+    pub fn new(
+        url: &Url,
+        delete_after_load_allowed: bool,
+        http_auth: Option<HttpAuth>,
+        logs_urls: &[Url],
+    ) -> Result<Self, Error> {
+        let logs_url = |schemes: &[&str]| {
+            logs_urls
+                .iter()
+                .find(|logs_url| schemes.contains(&logs_url.scheme()))
+        };
+
         match url.scheme() {
             "file" => Ok(Self::File(file::FileLoader::new(PathBuf::from(
                 url.path().to_string(),
@@ -121,40 +262,70 @@ impl Loader {
             "dir" | "dird" if delete_after_load_allowed => Ok(Self::Dir(dir::DirLoader::new(
                 PathBuf::from(url.path().to_string()),
                 matches!(url.scheme(), "dird"),
-                None,
+                logs_url(&["dir"]).map(|logs_url| PathBuf::from(logs_url.path().to_string())),
             ))),
             "http" | "https" => Ok(Self::Http(http::HttpLoader::new(
                 url.as_str().to_string(),
-                None,
+                http_auth,
                 false,
                 true,
-                None,
+                logs_url(&["http", "https"]).map(|logs_url| logs_url.as_str().to_string()),
             ))),
             #[cfg(feature = "s3")]
             "s3" | "s3d" if delete_after_load_allowed => {
                 let bucket = url
                     .host_str()
-                    .ok_or_else(|| anyhow::anyhow!("No bucket provided in URL: {}", url))?
+                    .ok_or_else(|| {
+                        Error::bundle(anyhow::anyhow!("No bucket provided in URL: {}", url))
+                    })?
                     .to_string();
                 let path = url.path().trim_matches('/');
                 let path = (!path.is_empty()).then(|| path.to_string());
 
+                let (logs_bucket, logs_prefix) = logs_url(&["s3"])
+                    .and_then(|logs_url| {
+                        let logs_path = logs_url.path().trim_matches('/');
+
+                        logs_url.host_str().map(|logs_bucket| {
+                            (
+                                Some(logs_bucket.to_string()),
+                                (!logs_path.is_empty()).then(|| logs_path.to_string()),
+                            )
+                        })
+                    })
+                    .unwrap_or_default();
+
                 Ok(Self::S3(s3::S3Loader::new(
                     None,
                     bucket,
                     path,
                     matches!(url.scheme(), "s3d"),
-                    None,
-                    None,
+                    logs_bucket,
+                    logs_prefix,
                 )))
             }
-            _ => anyhow::bail!("Unsupported bundle load URL: {url}"),
+            _ => Err(Error::bundle(anyhow::anyhow!(
+                "Unsupported bundle load URL: {url}"
+            ))),
+        }
+    }
+}
+
+impl Loader {
+    /// Return the loader with the deletion of the loaded bundles disabled, so that no bundle of a `dird` or `s3d`
+    /// pool is claimed or deleted (e.g. for a dry `plan`, a replay or a simulation)
+    pub fn keep_bundles(self) -> Self {
+        match self {
+            Self::Dir(loader) => Self::Dir(loader.keep_bundles()),
+            #[cfg(feature = "s3")]
+            Self::S3(loader) => Self::S3(loader.keep_bundles()),
+            other => other,
         }
     }
 }
 
 impl BundleLoader for Loader {
-    async fn load<W>(&mut self, write: W, id: Option<&str>) -> anyhow::Result<String>
+    async fn load<W>(&mut self, write: W, id: Option<&str>) -> Result<String, Error>
     where
         W: std::io::Write,
     {
@@ -166,4 +337,44 @@ impl BundleLoader for Loader {
             Self::S3(loader) => loader.load(write, id).await,
         }
     }
+
+    async fn fingerprint(&mut self, id: Option<&str>) -> Result<Option<String>, Error> {
+        match self {
+            Self::File(loader) => loader.fingerprint(id).await,
+            Self::Dir(loader) => loader.fingerprint(id).await,
+            Self::Http(loader) => loader.fingerprint(id).await,
+            #[cfg(feature = "s3")]
+            Self::S3(loader) => loader.fingerprint(id).await,
+        }
+    }
+
+    async fn commit(&mut self, name: &str) -> Result<(), Error> {
+        match self {
+            Self::File(loader) => loader.commit(name).await,
+            Self::Dir(loader) => loader.commit(name).await,
+            Self::Http(loader) => loader.commit(name).await,
+            #[cfg(feature = "s3")]
+            Self::S3(loader) => loader.commit(name).await,
+        }
+    }
+
+    async fn release(&mut self, name: &str) -> Result<(), Error> {
+        match self {
+            Self::File(loader) => loader.release(name).await,
+            Self::Dir(loader) => loader.release(name).await,
+            Self::Http(loader) => loader.release(name).await,
+            #[cfg(feature = "s3")]
+            Self::S3(loader) => loader.release(name).await,
+        }
+    }
+
+    async fn quarantine(&mut self, name: &str) -> Result<(), Error> {
+        match self {
+            Self::File(loader) => loader.quarantine(name).await,
+            Self::Dir(loader) => loader.quarantine(name).await,
+            Self::Http(loader) => loader.quarantine(name).await,
+            #[cfg(feature = "s3")]
+            Self::S3(loader) => loader.quarantine(name).await,
+        }
+    }
 }
@@ -0,0 +1,196 @@
+use std::fs;
+use std::io::{self, Write};
+use std::path::PathBuf;
+
+use anyhow::Context;
+
+use log::{info, warn};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::hash::sha256_hex;
+use crate::utils::private_dir;
+use crate::Error;
+
+use super::BundleLoader;
+
+/// A loader which caches the bundles loaded by another loader in a local, content-addressed cache directory.
+///
+/// Meant for rarely changing bundles (i.e. the base bundle), so that they are not re-downloaded for every single PCB.
+///
+/// Before loading a bundle, the cache asks the wrapped loader for a fingerprint of the bundle (see `BundleLoader::fingerprint`).
+/// If the wrapped loader provides a fingerprint, and a bundle with that fingerprint is in the cache, and the checksum of the cached
+/// bundle content is correct, the bundle is served from the cache. Otherwise the bundle is loaded with the wrapped loader and stored in the cache.
+///
+/// Bundles of loaders which cannot fingerprint them (e.g. the directory loader) are never cached.
+/// The cache directory is accessible by the current user only (see `utils::private_dir`), and the cache is bypassed
+/// if it cannot be restricted so.
+///
+/// The cache directory has the following layout:
+/// - `<sha256-of-fingerprint>.json` - an entry mapping a bundle fingerprint to a bundle name and the SHA-256 of the bundle content
+/// - `<sha256-of-content>.blob` - the bundle content
+#[derive(Debug, Clone)]
+pub struct CachedLoader<T> {
+    loader: T,
+    dir: PathBuf,
+}
+
+impl<T> CachedLoader<T> {
+    /// Creates a new `CachedLoader`
+    ///
+    /// Arguments
+    /// - `loader`: The loader whose bundles are to be cached
+    /// - `dir`: The cache directory; created (accessible by the current user only) if it does not exist
+    pub const fn new(loader: T, dir: PathBuf) -> Self {
+        Self { loader, dir }
+    }
+
+    /// Return the default cache directory (`espfactory/bundles` in the per-user cache directory of the host)
+    pub fn default_dir() -> PathBuf {
+        private_dir::user_dir("bundles")
+    }
+
+    fn entry_path(&self, fingerprint: &str) -> PathBuf {
+        self.dir.join(format!("{}.json", sha256_hex(fingerprint)))
+    }
+
+    fn blob_path(&self, hash: &str) -> PathBuf {
+        self.dir.join(format!("{hash}.blob"))
+    }
+
+    /// Load the bundle with the given fingerprint from the cache, if present and valid
+    fn load_cached(&self, fingerprint: &str) -> anyhow::Result<Option<(String, Vec<u8>)>> {
+        let entry_path = self.entry_path(fingerprint);
+
+        if !entry_path.exists() {
+            return Ok(None);
+        }
+
+        private_dir::ensure(&self.dir).context("Securing the cache directory failed")?;
+
+        let entry: CacheEntry = serde_json::from_str(
+            &fs::read_to_string(&entry_path).context("Reading the cache entry failed")?,
+        )
+        .context("Parsing the cache entry failed")?;
+
+        if entry.fingerprint != fingerprint {
+            return Ok(None);
+        }
+
+        let data =
+            fs::read(self.blob_path(&entry.sha256)).context("Reading the cache blob failed")?;
+
+        if sha256_hex(&data) != entry.sha256 {
+            warn!(
+                "Cached bundle `{}` is corrupted, ignoring the cache",
+                entry.name
+            );
+
+            return Ok(None);
+        }
+
+        Ok(Some((entry.name, data)))
+    }
+
+    /// Store the bundle with the given fingerprint in the cache
+    fn store(&self, fingerprint: &str, name: &str, data: &[u8]) -> anyhow::Result<()> {
+        private_dir::ensure(&self.dir).context("Creating the cache directory failed")?;
+
+        let hash = sha256_hex(data);
+
+        fs::write(self.blob_path(&hash), data).context("Writing the cache blob failed")?;
+
+        let entry = CacheEntry {
+            fingerprint: fingerprint.to_string(),
+            name: name.to_string(),
+            sha256: hash,
+        };
+
+        fs::write(self.entry_path(fingerprint), serde_json::to_string(&entry)?)
+            .context("Writing the cache entry failed")?;
+
+        Ok(())
+    }
+}
+
+impl<T> BundleLoader for CachedLoader<T>
+where
+    T: BundleLoader,
+{
+    async fn load<W>(&mut self, mut write: W, id: Option<&str>) -> Result<String, Error>
+    where
+        W: Write,
+    {
+        let fingerprint = match self.loader.fingerprint(id).await {
+            Ok(fingerprint) => fingerprint,
+            Err(err) => {
+                warn!("Fingerprinting the bundle failed, bypassing the cache: {err:?}");
+                None
+            }
+        };
+
+        let Some(fingerprint) = fingerprint else {
+            return self.loader.load(write, id).await;
+        };
+
+        match self.load_cached(&fingerprint) {
+            Ok(Some((name, data))) => {
+                write
+                    .write_all(&data)
+                    .context("Loading the bundle failed")?;
+
+                info!(
+                    "Loaded bundle `{name}` from cache directory `{}`",
+                    self.dir.display()
+                );
+
+                return Ok(name);
+            }
+            Ok(None) => (),
+            Err(err) => warn!("Loading the bundle from the cache failed: {err:?}"),
+        }
+
+        let mut data = Vec::new();
+        let name = self.loader.load(&mut data, id).await?;
+
+        if let Err(err) = self.store(&fingerprint, &name, &data) {
+            warn!("Storing bundle `{name}` in the cache failed: {err:?}");
+        } else {
+            info!(
+                "Stored bundle `{name}` in cache directory `{}`",
+                self.dir.display()
+            );
+        }
+
+        io::copy(&mut data.as_slice(), &mut write).context("Loading the bundle failed")?;
+
+        Ok(name)
+    }
+
+    async fn fingerprint(&mut self, id: Option<&str>) -> Result<Option<String>, Error> {
+        self.loader.fingerprint(id).await
+    }
+
+    async fn commit(&mut self, name: &str) -> Result<(), Error> {
+        self.loader.commit(name).await
+    }
+
+    async fn release(&mut self, name: &str) -> Result<(), Error> {
+        self.loader.release(name).await
+    }
+
+    async fn quarantine(&mut self, name: &str) -> Result<(), Error> {
+        self.loader.quarantine(name).await
+    }
+}
+
+/// An entry in the cache directory
+#[derive(Serialize, Deserialize)]
+struct CacheEntry {
+    /// The fingerprint of the bundle, as returned by the wrapped loader
+    fingerprint: String,
+    /// The name of the bundle
+    name: String,
+    /// The SHA-256 of the bundle content
+    sha256: String,
+}
@@ -4,9 +4,11 @@ use std::path::PathBuf;
 
 use anyhow::Context;
 
-use log::info;
+use log::{info, warn};
 
-use super::BundleLoader;
+use crate::Error;
+
+use super::{BundleLoader, BundlePoolEmpty, BundleType};
 
 /// A loader that reads bundles from a directory.
 ///
@@ -15,23 +17,39 @@ use super::BundleLoader;
 /// `BundleType::suffix()`
 ///
 /// If the bundles are loaded by ID, then the bundle name is assumed to be the ID with the corresponding extension
-/// i.e. `<ID>.bundle`, `<ID>.bin`, or `<ID>`. Otherwise, each file in the directory is treated as a bundle as long as
-/// it has an extension matching one of the ones returned by `BundleType::suffix()`, and the loader just loads (and removes)
-/// a random file from the directory
+/// i.e. `<ID>.bundle`, `<ID>.bundle.tar.gz`, `<ID>.bundle.tar.zst`, `<ID>.bin`, or `<ID>`. Otherwise, each file in the directory is treated as a bundle as long as
+/// it has an extension matching one of the ones returned by `BundleType::suffix()`, and the loader just loads a random file from the directory
+///
+/// A random bundle which is to be deleted after loading is first claimed by renaming it to `<name>.inprogress`,
+/// and is only deleted once its provisioning is complete (see `BundleLoader::commit`); if the provisioning does not complete,
+/// the bundle is renamed back (see `BundleLoader::release`), or - if it was already written to the PCB - renamed to `<name>.failed`
+/// (see `BundleLoader::quarantine`). Claimed and quarantined bundles are skipped when looking for a random bundle
+///
+/// As the renaming is atomic, multiple stations can share the same directory (e.g. a network share), with each bundle
+/// claimed - and consumed - by exactly one station. The claims of a station which crashed while provisioning are not released
+/// automatically; such bundles have to be renamed back manually
+///
+/// If a logs directory is configured, the loader - before loading a bundle with an ID - checks whether the logs of that bundle
+/// are already uploaded to it (i.e. whether a `<bundle-name>_<timestamp>.log.zip` file exists there), and refuses to load the bundle if so
 #[derive(Debug, Clone)]
 pub struct DirLoader {
     path: PathBuf,
     delete_after_load: bool,
-    #[allow(unused)]
     logs_path: Option<PathBuf>,
+    claimed: Vec<String>,
 }
 
 impl DirLoader {
+    /// The suffix of the claimed bundles
+    pub const CLAIM_SUFFIX: &'static str = ".inprogress";
+    /// The suffix of the quarantined bundles
+    pub const QUARANTINE_SUFFIX: &'static str = ".failed";
+
     /// Creates a new `DirLoader`
     ///
     /// Arguments
     /// - `path`: The path to the directory to load the bundles from
-    /// - `delete_after_load`: A flag indicating whether the loaded bundle should be deleted from the directory after loading
+    /// - `delete_after_load`: A flag indicating whether the loaded bundle should be deleted from the directory after its provisioning
     ///   Only used when a bundle is loaded without a supplied ID (i.e. a random bundle)
     /// - `logs_path`: An optional path to the directory where the logs are uploaded;
     ///   if provided, the loader will only download a bundle if its logs are not yet uploaded, this preventing
@@ -41,12 +59,93 @@ impl DirLoader {
             path,
             delete_after_load,
             logs_path,
+            claimed: Vec::new(),
+        }
+    }
+
+    /// Return the loader with the deletion of the loaded bundles disabled, so that no bundle is claimed or deleted
+    pub fn keep_bundles(mut self) -> Self {
+        self.delete_after_load = false;
+        self
+    }
+
+    fn claim_path(&self, name: &str) -> PathBuf {
+        self.path.join(format!("{name}{}", Self::CLAIM_SUFFIX))
+    }
+
+    /// Check - if a logs directory is configured - that no logs are uploaded yet for the bundle with the given ID
+    fn check_logs(&self, id: &str) -> anyhow::Result<()> {
+        let Some(logs_path) = self.logs_path.as_ref() else {
+            return Ok(());
+        };
+
+        info!(
+            "About to check for uploaded logs of bundle ID `{id}` in directory `{}`...",
+            logs_path.display()
+        );
+
+        // A logs directory which does not exist yet has no logs either
+        let entries = match fs::read_dir(logs_path) {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+            Err(err) => Err(err).context("Cannot open the logs' directory")?,
+        };
+
+        for entry in entries {
+            let entry = entry.context("Error when reading the logs' directory")?;
+            let file_name = entry.file_name();
+
+            let Some(file_name) = file_name.to_str() else {
+                continue;
+            };
+
+            // The names of the uploaded logs are `<bundle-name>_<timestamp>.log.zip`
+            if BundleType::iter()
+                .any(|bundle_type| file_name.starts_with(&format!("{}_", bundle_type.file(id))))
+            {
+                anyhow::bail!(
+                    "The logs of bundle ID `{id}` are already uploaded (`{file_name}`), i.e. the bundle was already provisioned; refusing to load it again"
+                );
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Find the bundles in the directory matching the ID (if provided), skipping the claimed and the quarantined ones
+    fn find(&self, id: Option<&str>) -> anyhow::Result<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+
+        for entry in fs::read_dir(&self.path).context("Cannot open the bundles' directory")? {
+            let entry = entry.context("Error when reading the bundles' directory")?;
+            let path = entry.path();
+
+            if !path.is_file() {
+                continue;
+            }
+
+            let Some(file_name) = path.file_name().and_then(|file_name| file_name.to_str()) else {
+                continue;
+            };
+
+            let matches = if let Some(id) = id {
+                BundleType::iter().any(|bundle_type| file_name == bundle_type.file(id))
+            } else {
+                !file_name.ends_with(Self::CLAIM_SUFFIX)
+                    && !file_name.ends_with(Self::QUARANTINE_SUFFIX)
+            };
+
+            if matches {
+                paths.push(path);
+            }
         }
+
+        Ok(paths)
     }
 }
 
 impl BundleLoader for DirLoader {
-    async fn load<W>(&mut self, mut write: W, id: Option<&str>) -> anyhow::Result<String>
+    async fn load<W>(&mut self, mut write: W, id: Option<&str>) -> Result<String, Error>
     where
         W: Write,
     {
@@ -62,64 +161,169 @@ impl BundleLoader for DirLoader {
             );
         }
 
-        let file_name = fs::read_dir(&self.path)
-            .context("Cannot open the bundles' directory")?
-            .find_map(|entry| {
-                (move || {
-                    let entry = entry.context("Error when reading the bundles' directory")?;
-                    let path = entry.path();
-
-                    let mut matches = false;
-
-                    if path.is_file() {
-                        if let Some(file_name) =
-                            path.file_name().and_then(|file_name| file_name.to_str())
-                        {
-                            if let Some(id) = id {
-                                if file_name == format!("{id}.bin")
-                                    || file_name == format!("{id}.bundle")
-                                    || file_name == id
-                                {
-                                    matches = true;
-                                }
-                            } else {
-                                matches = true;
-                            }
-                        }
-                    }
-
-                    Ok::<_, anyhow::Error>(matches.then_some(path))
-                })()
-                .transpose()
-            })
-            .transpose()?;
-
-        if let Some(path) = file_name {
-            info!(
-                "Found bundle `{}`",
-                path.file_name().unwrap().to_str().unwrap_or("???")
-            );
+        if let Some(id) = id {
+            self.check_logs(id)?;
+        }
+
+        let claim = id.is_none() && self.delete_after_load;
+
+        for path in self.find(id)? {
+            let name = path.file_name().unwrap().to_str().unwrap().to_string();
+
+            let path = if claim {
+                let claim_path = self.claim_path(&name);
+
+                match fs::rename(&path, &claim_path) {
+                    Ok(()) => (),
+                    // Claimed by someone else in the meantime
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+                    Err(err) => Err(err).context("Claiming the random bundle failed")?,
+                }
+
+                info!("Claimed bundle `{name}`");
+
+                self.claimed.push(name.clone());
+
+                claim_path
+            } else {
+                path
+            };
+
+            info!("Found bundle `{name}`");
 
-            let mut file = fs::File::open(&path).context("Loading the bundle failed")?;
+            let result = fs::File::open(&path)
+                .and_then(|mut file| io::copy(&mut file, &mut write))
+                .context("Loading the bundle failed");
 
-            io::copy(&mut file, &mut write).context("Loading the bundle failed")?;
+            if let Err(err) = result {
+                if claim {
+                    self.release(&name).await?;
+                }
 
-            if self.delete_after_load {
-                fs::remove_file(&path)
-                    .context("Removing the random bundle from the directory failed")?;
+                return Err(err.into());
             }
 
-            info!(
-                "Loaded bundle `{}`",
-                path.file_name().unwrap().to_str().unwrap_or("???")
-            );
+            info!("Loaded bundle `{name}`");
+
+            return Ok(name);
+        }
 
-            // TODO
-            Ok(path.file_name().unwrap().to_str().unwrap().to_string())
-        } else if let Some(id) = id {
-            anyhow::bail!("No bundle found for ID `{id}`")
+        if let Some(id) = id {
+            Err(Error::bundle(anyhow::anyhow!(
+                "No bundle found for ID `{id}`"
+            )))
         } else {
-            anyhow::bail!("No files found in bundles' directory")
+            Err(Error::bundle(
+                anyhow::Error::new(BundlePoolEmpty).context("No files found in bundles' directory"),
+            ))
+        }
+    }
+
+    async fn commit(&mut self, name: &str) -> Result<(), Error> {
+        if let Some(index) = self.claimed.iter().position(|claimed| claimed == name) {
+            fs::remove_file(self.claim_path(name))
+                .context("Removing the random bundle from the directory failed")?;
+
+            self.claimed.remove(index);
+
+            info!("Removed bundle `{name}` from the directory");
+        }
+
+        Ok(())
+    }
+
+    async fn release(&mut self, name: &str) -> Result<(), Error> {
+        if let Some(index) = self.claimed.iter().position(|claimed| claimed == name) {
+            fs::rename(self.claim_path(name), self.path.join(name))
+                .context("Releasing the claim of the random bundle failed")?;
+
+            self.claimed.remove(index);
+
+            info!("Released the claim of bundle `{name}`");
+        }
+
+        Ok(())
+    }
+
+    async fn quarantine(&mut self, name: &str) -> Result<(), Error> {
+        if let Some(index) = self.claimed.iter().position(|claimed| claimed == name) {
+            fs::rename(
+                self.claim_path(name),
+                self.path.join(format!("{name}{}", Self::QUARANTINE_SUFFIX)),
+            )
+            .context("Quarantining the random bundle failed")?;
+
+            self.claimed.remove(index);
+
+            warn!(
+                "Quarantined bundle `{name}` as `{name}{}`",
+                Self::QUARANTINE_SUFFIX
+            );
         }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::future::block_on;
+
+    use super::*;
+
+    #[test]
+    fn load_refuses_bundles_with_uploaded_logs() {
+        let bundles = tempfile::tempdir().unwrap();
+        let logs = tempfile::tempdir().unwrap();
+
+        fs::write(bundles.path().join("PCB1.bundle"), b"bundle").unwrap();
+
+        let mut loader = DirLoader::new(
+            bundles.path().to_path_buf(),
+            false,
+            Some(logs.path().to_path_buf()),
+        );
+
+        let mut data = Vec::new();
+        let name = block_on(loader.load(&mut data, Some("PCB1"))).unwrap();
+
+        assert_eq!(name, "PCB1.bundle");
+        assert_eq!(data, b"bundle");
+
+        // The logs of another bundle do not matter
+        fs::write(
+            logs.path()
+                .join("PCB10.bundle_2024-01-01T00:00:00Z.log.zip"),
+            b"",
+        )
+        .unwrap();
+
+        assert!(block_on(loader.load(io::sink(), Some("PCB1"))).is_ok());
+
+        fs::write(
+            logs.path().join("PCB1.bundle_2024-01-01T00:00:00Z.log.zip"),
+            b"",
+        )
+        .unwrap();
+
+        assert!(block_on(loader.load(io::sink(), Some("PCB1"))).is_err());
+    }
+
+    #[test]
+    fn load_without_logs_path_skips_the_check() {
+        let bundles = tempfile::tempdir().unwrap();
+
+        fs::write(bundles.path().join("PCB1.bundle"), b"bundle").unwrap();
+        fs::write(
+            bundles
+                .path()
+                .join("PCB1.bundle_2024-01-01T00:00:00Z.log.zip"),
+            b"",
+        )
+        .unwrap();
+
+        let mut loader = DirLoader::new(bundles.path().to_path_buf(), false, None);
+
+        assert!(block_on(loader.load(io::sink(), Some("PCB1"))).is_ok());
     }
 }
@@ -6,6 +6,8 @@ use anyhow::Context;
 
 use log::info;
 
+use crate::Error;
+
 use super::BundleLoader;
 
 /// A loader that reads bundles from a single file.
@@ -28,22 +30,30 @@ impl FileLoader {
 }
 
 impl BundleLoader for FileLoader {
-    async fn load<W>(&mut self, mut write: W, id: Option<&str>) -> anyhow::Result<String>
+    async fn load<W>(&mut self, mut write: W, id: Option<&str>) -> Result<String, Error>
     where
         W: Write,
     {
         if id.is_some() {
-            anyhow::bail!("Loading a bundle by ID is not supported by the file loader");
+            return Err(Error::bundle(anyhow::anyhow!(
+                "Loading a bundle by ID is not supported by the file loader"
+            )));
         }
 
         info!("About to load bundle file `{}`...", self.path.display());
 
         if !self.path.exists() {
-            anyhow::bail!("Bundle file `{}` does not exist", self.path.display());
+            return Err(Error::bundle(anyhow::anyhow!(
+                "Bundle file `{}` does not exist",
+                self.path.display()
+            )));
         }
 
         if !self.path.is_file() {
-            anyhow::bail!("Bundle file `{}` is not a file", self.path.display());
+            return Err(Error::bundle(anyhow::anyhow!(
+                "Bundle file `{}` is not a file",
+                self.path.display()
+            )));
         }
 
         let mut file = fs::File::open(&self.path).context("Loading the bundle failed")?;
@@ -57,4 +67,28 @@ impl BundleLoader for FileLoader {
 
         Ok(self.path.file_name().unwrap().to_str().unwrap().to_string())
     }
+
+    async fn fingerprint(&mut self, id: Option<&str>) -> Result<Option<String>, Error> {
+        if id.is_some() {
+            return Err(Error::bundle(anyhow::anyhow!(
+                "Loading a bundle by ID is not supported by the file loader"
+            )));
+        }
+
+        let metadata = fs::metadata(&self.path)
+            .with_context(|| format!("Bundle file `{}` does not exist", self.path.display()))?;
+
+        let modified = metadata
+            .modified()
+            .ok()
+            .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+            .map(|modified| modified.as_nanos())
+            .unwrap_or_default();
+
+        Ok(Some(format!(
+            "file:{}:{}:{modified}",
+            self.path.display(),
+            metadata.len()
+        )))
+    }
 }
@@ -4,6 +4,9 @@ use anyhow::Context;
 
 use log::info;
 
+use crate::auth::HttpAuth;
+use crate::Error;
+
 use super::BundleLoader;
 
 /// A loader that reads bundles from an HTTP(S) server.
@@ -22,13 +25,20 @@ use super::BundleLoader;
 /// In both cases (bundle loading with or without a bundle ID), the server should provide the bundle data in the response body
 /// and the name of the bundle in the `Content-Disposition` header. If the `Content-Disposition` header is not present, then the name of the bundle
 /// is assumed to be the ID of the bundle with the `.bundle` extension, or a random name with the `.bundle` extension if the ID is not present
+///
+/// If a logs URL is configured, the loader - before loading a bundle with an ID - checks whether the logs of that bundle
+/// are already uploaded, and refuses to load the bundle if so:
+/// - Request: `GET <logs-url>?id=<id>`, with the same `Authorization` header as the bundle requests (if any)
+/// - Response:
+///   - 404 (Not Found): no logs are uploaded for the bundle yet, so the bundle is loaded
+///   - Any success status code (2xx; the body is ignored): logs are already uploaded, so loading the bundle fails
+///   - Any other status code: the check failed, and so does loading the bundle
 #[derive(Debug, Clone)]
 pub struct HttpLoader {
     load_url: String,
-    auth: Option<String>,
+    auth: Option<HttpAuth>,
     use_post: bool,
     id_as_bundle_file: bool,
-    #[allow(unused)]
     logs_url: Option<String>,
 }
 
@@ -37,9 +47,9 @@ impl HttpLoader {
     ///
     /// # Arguments
     /// - `load_url`: The URL of the server to load the bundles from
-    /// - `auth`: An optional authorization token to use when loading the bundles
-    ///   If present, it will be used as the value of the `Authorization` header
-    ///   in the request
+    /// - `auth`: An optional authorization to use when loading the bundles
+    ///   If present, it will be used for the value of the `Authorization` header
+    ///   in the request (either a static value, or an automatically renewed OAuth2 access token)
     /// - `use_post`: A flag indicating whether to fetch the bundle with a GET or a POST request
     /// - `id_as_bundle_file`: A flag indicating whether to fetch the bundle with:
     ///   - `true`:  A simple parameter-less GET/POST request of the form `<url>/<bundle-id>.bundle`
@@ -49,7 +59,7 @@ impl HttpLoader {
     ///   flashing a bundle multiple times
     pub const fn new(
         load_url: String,
-        auth: Option<String>,
+        auth: Option<HttpAuth>,
         use_post: bool,
         id_as_bundle_file: bool,
         logs_url: Option<String>,
@@ -64,8 +74,39 @@ impl HttpLoader {
     }
 }
 
+impl HttpLoader {
+    /// Check - if a logs URL is configured - that no logs are uploaded yet for the bundle with the given ID
+    async fn check_logs(&self, id: &str) -> anyhow::Result<()> {
+        let Some(logs_url) = self.logs_url.as_deref() else {
+            return Ok(());
+        };
+
+        info!("About to check for uploaded logs of bundle ID `{id}` at URL `{logs_url}`...");
+
+        let mut builder = reqwest::Client::new().get(logs_url).query(&[("id", id)]);
+
+        if let Some(auth) = self.auth.as_ref() {
+            builder = builder.header("Authorization", auth.header().await?);
+        }
+
+        let response = builder.send().await.context("Logs check request failed")?;
+
+        if response.status() == reqwest::StatusCode::NOT_FOUND {
+            return Ok(());
+        }
+
+        response
+            .error_for_status()
+            .context("Logs check request returned an error status")?;
+
+        anyhow::bail!(
+            "The logs of bundle ID `{id}` are already uploaded, i.e. the bundle was already provisioned; refusing to load it again"
+        )
+    }
+}
+
 impl BundleLoader for HttpLoader {
-    async fn load<W>(&mut self, mut write: W, id: Option<&str>) -> anyhow::Result<String>
+    async fn load<W>(&mut self, mut write: W, id: Option<&str>) -> Result<String, Error>
     where
         W: Write,
     {
@@ -78,6 +119,10 @@ impl BundleLoader for HttpLoader {
             info!("About to fetch a bundle from URL `{}`...", self.load_url);
         }
 
+        if let Some(id) = id {
+            self.check_logs(id).await?;
+        }
+
         let client = reqwest::Client::new();
 
         let mut builder = if let Some(id) = id {
@@ -101,8 +146,8 @@ impl BundleLoader for HttpLoader {
             client.get(&self.load_url)
         };
 
-        if let Some(auth) = self.auth.as_deref() {
-            builder = builder.header("Authorization", auth);
+        if let Some(auth) = self.auth.as_ref() {
+            builder = builder.header("Authorization", auth.header().await?);
         }
 
         let response = builder.send().await.context("Request failed")?;
@@ -138,4 +183,61 @@ impl BundleLoader for HttpLoader {
 
         Ok(bundle_name)
     }
+
+    async fn fingerprint(&mut self, id: Option<&str>) -> Result<Option<String>, Error> {
+        if self.use_post {
+            // POST requests might have side effects (i.e. deleting the bundle), so no fingerprinting
+            return Ok(None);
+        }
+
+        let client = reqwest::Client::new();
+
+        let (url, mut builder) = if let Some(id) = id {
+            if self.id_as_bundle_file {
+                let url = format!("{}/{id}.bundle", self.load_url.trim_end_matches('/'));
+
+                (url.clone(), client.head(&url))
+            } else {
+                (
+                    self.load_url.clone(),
+                    client.head(&self.load_url).query(&[("id", id)]),
+                )
+            }
+        } else {
+            (self.load_url.clone(), client.head(&self.load_url))
+        };
+
+        if let Some(auth) = self.auth.as_ref() {
+            builder = builder.header("Authorization", auth.header().await?);
+        }
+
+        let response = builder
+            .send()
+            .await
+            .context("Request failed")?
+            .error_for_status()
+            .context("Request returned an error status")?;
+
+        let header = |name| {
+            response
+                .headers()
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string)
+        };
+
+        let fingerprint = if let Some(etag) = header("ETag") {
+            Some(format!("http:{url}:{}:etag:{etag}", id.unwrap_or_default()))
+        } else {
+            header("Last-Modified").map(|last_modified| {
+                format!(
+                    "http:{url}:{}:modified:{last_modified}:{}",
+                    id.unwrap_or_default(),
+                    header("Content-Length").unwrap_or_default()
+                )
+            })
+        };
+
+        Ok(fingerprint)
+    }
 }
@@ -5,10 +5,14 @@ use std::io::Write;
 use anyhow::Context;
 use aws_sdk_s3::error::SdkError;
 use aws_sdk_s3::operation::get_object::GetObjectError;
+use aws_sdk_s3::operation::head_object::HeadObjectError;
+use aws_sdk_s3::primitives::ByteStream;
 
-use log::info;
+use log::{info, warn};
 
-use super::{BundleLoader, BundleType};
+use crate::Error;
+
+use super::{BundleLoader, BundlePoolEmpty, BundleType};
 
 /// Re-export the `aws-config` crate as a module so that the user
 /// does not have to depend on the `aws-cponfig` crate directly
@@ -27,26 +31,39 @@ pub mod aws_config {
 ///   where `<suffix>` is one of the suffixes returned by `BundleType::suffix()`, examined in order of the variants of `BundleType`
 /// - If the `id` argument is not present when calling `load`, then the loader will list the contents of the bucket and load the first bundle  
 ///   with a suffix matching one of the suffixes returned by `BundleType::suffix()`, examined in order of the variants of `BundleType`
-///   Furthermore, if the `delete_after_load` flag is set to `true`, then the loader will delete the loaded bundle from the bucket
+///   Furthermore, if the `delete_after_load` flag is set to `true`, then the loader will claim the loaded bundle by creating
+///   a `<key>.lock` lock object next to it, and will only delete the bundle (and its lock object) from the bucket once its provisioning
+///   is complete (see `BundleLoader::commit`); if the provisioning does not complete, the lock object is deleted (see `BundleLoader::release`),
+///   unless the bundle was already written to the PCB, in which case the lock object is kept (see `BundleLoader::quarantine`).
+///   The lock object is created with a conditional put (`If-None-Match: *`), so that when multiple stations share the bucket,
+///   each bundle is claimed - and consumed - by exactly one station. Bundles with a lock object are skipped when looking for a bundle;
+///   the lock objects of a station which crashed while provisioning have to be deleted manually
+///
+/// If a logs bucket is configured, the loader - before loading a bundle with an ID - checks whether the logs of that bundle
+/// are already uploaded (i.e. whether there are objects with keys [<optional-logs-prefix>/]<ID>[.<suffix>]_ in the logs bucket,
+/// as uploaded by `S3LogsUploader`), and refuses to load the bundle if so
 #[derive(Debug, Clone)]
 pub struct S3Loader {
     config: Option<aws_config::SdkConfig>,
     load_bucket: String,
     load_prefix: Option<String>,
     delete_after_load: bool,
-    #[allow(unused)]
     logs_bucket: Option<String>,
-    #[allow(unused)]
     logs_prefix: Option<String>,
+    /// The claimed bundles, as (bundle name, object key) pairs
+    claimed: Vec<(String, String)>,
 }
 
 impl S3Loader {
+    /// The key suffix of the lock objects of the claimed bundles
+    pub const LOCK_SUFFIX: &'static str = ".lock";
+
     /// Creates a new `S3Loader` instance
     ///
     /// # Arguments
     /// - `load_bucket`: The name of the S3 bucket to load the bundles from
     /// - `load_prefix`: An optional prefix key to use when loading the bundles
-    /// - `delete_after_load`: A flag indicating whether the loaded bundle should be deleted from the bucket after loading
+    /// - `delete_after_load`: A flag indicating whether the loaded bundle should be deleted from the bucket after its provisioning
     /// - `logs_bucket`: An optional name of the S3 bucket where the logs are uploaded;
     ///   if provided, the loader will only download a bundle if its logs are not yet uploaded, this preventing
     ///   flashing a bundle multiple times
@@ -66,12 +83,165 @@ impl S3Loader {
             delete_after_load,
             logs_bucket,
             logs_prefix,
+            claimed: Vec::new(),
         }
     }
 }
 
+impl S3Loader {
+    async fn client(&self) -> aws_sdk_s3::Client {
+        let config = if let Some(config) = self.config.as_ref() {
+            config.clone()
+        } else {
+            aws_config::load_from_env().await
+        };
+
+        aws_sdk_s3::Client::new(&config)
+    }
+
+    /// Return the loader with the deletion of the loaded bundles disabled, so that no bundle is claimed or deleted
+    pub fn keep_bundles(mut self) -> Self {
+        self.delete_after_load = false;
+        self
+    }
+
+    fn lock_key(key: &str) -> String {
+        format!("{key}{}", Self::LOCK_SUFFIX)
+    }
+
+    /// Claim the bundle with the given key by creating its lock object, on condition that the lock object does not exist yet
+    ///
+    /// Returns `false` if the bundle is already claimed (possibly by another station)
+    async fn claim(&self, client: &aws_sdk_s3::Client, key: &str) -> anyhow::Result<bool> {
+        let claimant = format!(
+            "pid={} date={}",
+            std::process::id(),
+            chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ")
+        );
+
+        let result = client
+            .put_object()
+            .bucket(&self.load_bucket)
+            .key(Self::lock_key(key))
+            .if_none_match("*")
+            .body(ByteStream::from(claimant.into_bytes()))
+            .send()
+            .await;
+
+        match result {
+            Ok(_) => Ok(true),
+            // 412 - the lock object exists; 409 - a concurrent conditional put of the lock object is in progress
+            Err(SdkError::ServiceError(err))
+                if matches!(err.raw().status().as_u16(), 409 | 412) =>
+            {
+                Ok(false)
+            }
+            Err(other) => Err(other).context("Claiming the bundle failed"),
+        }
+    }
+
+    /// Delete the lock object of the bundle with the given key
+    async fn unclaim(&self, client: &aws_sdk_s3::Client, key: &str) -> anyhow::Result<()> {
+        client
+            .delete_object()
+            .bucket(&self.load_bucket)
+            .key(Self::lock_key(key))
+            .send()
+            .await
+            .context("Deleting the lock object of the bundle failed")?;
+
+        Ok(())
+    }
+
+    /// Download the bundle with the given key
+    ///
+    /// Returns `false` if the bundle is gone (e.g. loaded and deleted by another station since it was listed)
+    async fn download<W>(
+        &self,
+        client: &aws_sdk_s3::Client,
+        key: &str,
+        write: &mut W,
+    ) -> anyhow::Result<bool>
+    where
+        W: Write,
+    {
+        let result = client
+            .get_object()
+            .bucket(&self.load_bucket)
+            .key(key)
+            .send()
+            .await;
+
+        let mut object_data = match result {
+            Ok(object_data) => object_data,
+            Err(SdkError::ServiceError(err))
+                if matches!(err.err(), GetObjectError::NoSuchKey(_)) =>
+            {
+                return Ok(false)
+            }
+            Err(other) => Err(other).context("Loading the bundle failed")?,
+        };
+
+        while let Some(bytes) = object_data
+            .body
+            .try_next()
+            .await
+            .context("Loading the bundle failed")?
+        {
+            write
+                .write_all(&bytes)
+                .context("Loading the bundle failed")?;
+        }
+
+        Ok(true)
+    }
+
+    /// Check - if a logs bucket is configured - that no logs are uploaded yet for the bundle with the given ID
+    async fn check_logs(&self, client: &aws_sdk_s3::Client, id: &str) -> anyhow::Result<()> {
+        let Some(logs_bucket) = self.logs_bucket.as_deref() else {
+            return Ok(());
+        };
+
+        info!(
+            "About to check for uploaded logs of bundle ID `{id}` in S3 bucket `{}`...",
+            BucketWithPrefix::new(logs_bucket, self.logs_prefix.as_deref())
+        );
+
+        for bundle_type in BundleType::iter() {
+            // The names of the uploaded logs are `<bundle-name>_<timestamp>.log.zip`
+            let log_prefix = format!("{}_", bundle_type.file(id));
+            let key_prefix = self
+                .logs_prefix
+                .as_deref()
+                .map(|prefix| format!("{prefix}/{log_prefix}"))
+                .unwrap_or(log_prefix);
+
+            let resp = client
+                .list_objects_v2()
+                .bucket(logs_bucket)
+                .prefix(&key_prefix)
+                .max_keys(1)
+                .send()
+                .await
+                .context("Checking for uploaded logs failed")?;
+
+            if let Some(key) = resp
+                .contents()
+                .iter()
+                .find_map(|object_desc| object_desc.key())
+            {
+                anyhow::bail!(
+                    "The logs of bundle ID `{id}` are already uploaded (`{key}`), i.e. the bundle was already provisioned; refusing to load it again"
+                );
+            }
+        }
+
+        Ok(())
+    }
+}
+
 impl BundleLoader for S3Loader {
-    async fn load<W>(&mut self, mut write: W, id: Option<&str>) -> anyhow::Result<String>
+    async fn load<W>(&mut self, mut write: W, id: Option<&str>) -> Result<String, Error>
     where
         W: Write,
     {
@@ -87,15 +257,11 @@ impl BundleLoader for S3Loader {
             );
         }
 
-        let config = if let Some(config) = self.config.as_ref() {
-            config.clone()
-        } else {
-            aws_config::load_from_env().await
-        };
-
-        let client = aws_sdk_s3::Client::new(&config);
+        let client = self.client().await;
 
         if let Some(id) = id {
+            self.check_logs(&client, id).await?;
+
             for bundle_type in BundleType::iter() {
                 let bundle_name = bundle_type.file(id);
                 let key = self
@@ -117,7 +283,12 @@ impl BundleLoader for S3Loader {
                             continue;
                         }
 
-                        while let Some(bytes) = object_data.body.try_next().await? {
+                        while let Some(bytes) = object_data
+                            .body
+                            .try_next()
+                            .await
+                            .context("Loading the bundle failed")?
+                        {
                             write.write_all(&bytes)?;
                         }
 
@@ -145,36 +316,47 @@ impl BundleLoader for S3Loader {
                     builder = builder.prefix(prefix);
                 }
 
-                let resp = builder.send().await?;
+                let resp = builder.send().await.context("Listing the bundles failed")?;
 
                 for object_desc in resp.contents() {
                     if let Some(key) = object_desc.key() {
+                        // The lock objects would otherwise match the empty suffix of the ELF app images
+                        if key.ends_with(Self::LOCK_SUFFIX) {
+                            continue;
+                        }
+
                         if BundleType::iter().any(|bundle_type| key.ends_with(bundle_type.suffix()))
                         {
-                            let mut object_data = client
-                                .get_object()
-                                .bucket(&self.load_bucket)
-                                .key(key)
-                                .send()
-                                .await
-                                .context("Loading the bundle failed")?;
-
-                            while let Some(bytes) = object_data.body.try_next().await? {
-                                write
-                                    .write_all(&bytes)
-                                    .context("Loading the bundle failed")?;
-                            }
-
                             let bundle_name = key.split('/').next_back().unwrap_or(key).to_string();
 
                             if self.delete_after_load {
-                                client
-                                    .delete_object()
-                                    .bucket(&self.load_bucket)
-                                    .key(key)
-                                    .send()
-                                    .await
-                                    .context("Deleting the bundle after loading failed")?;
+                                if !self.claim(&client, key).await? {
+                                    continue;
+                                }
+
+                                info!("Claimed bundle `{bundle_name}`");
+
+                                self.claimed.push((bundle_name.clone(), key.to_string()));
+                            }
+
+                            match self.download(&client, key, &mut write).await {
+                                Ok(true) => (),
+                                Ok(false) => {
+                                    warn!("Bundle `{bundle_name}` is gone, skipping it");
+
+                                    if self.delete_after_load {
+                                        self.release(&bundle_name).await?;
+                                    }
+
+                                    continue;
+                                }
+                                Err(err) => {
+                                    if self.delete_after_load {
+                                        self.release(&bundle_name).await?;
+                                    }
+
+                                    return Err(err.into());
+                                }
                             }
 
                             info!("Loaded bundle `{}`", bundle_name);
@@ -193,11 +375,140 @@ impl BundleLoader for S3Loader {
         }
 
         if let Some(id) = id {
-            anyhow::bail!("No bundle found for ID `{id}`")
+            Err(Error::bundle(anyhow::anyhow!(
+                "No bundle found for ID `{id}`"
+            )))
         } else {
-            anyhow::bail!("No bundles found in the bucket")
+            Err(Error::bundle(anyhow::Error::new(BundlePoolEmpty).context(
+                format!("No bundles found in the bucket `{}`", self.load_bucket),
+            )))
         }
     }
+
+    async fn fingerprint(&mut self, id: Option<&str>) -> Result<Option<String>, Error> {
+        if id.is_none() && self.delete_after_load {
+            // A random bundle which is deleted after loading is never loaded twice, so no fingerprinting
+            return Ok(None);
+        }
+
+        let client = self.client().await;
+
+        if let Some(id) = id {
+            for bundle_type in BundleType::iter() {
+                let bundle_name = bundle_type.file(id);
+                let key = self
+                    .load_prefix
+                    .as_deref()
+                    .map(|prefix| format!("{}/{}", prefix, bundle_name))
+                    .unwrap_or(bundle_name.clone());
+
+                let result = client
+                    .head_object()
+                    .bucket(&self.load_bucket)
+                    .key(&key)
+                    .send()
+                    .await;
+
+                match result {
+                    Ok(object_desc) => {
+                        if object_desc.delete_marker().unwrap_or(false) {
+                            continue;
+                        }
+
+                        return Ok(object_desc
+                            .e_tag()
+                            .map(|etag| format!("s3:{}/{key}:{etag}", self.load_bucket)));
+                    }
+                    Err(SdkError::ServiceError(err))
+                        if matches!(err.err(), HeadObjectError::NotFound(_)) =>
+                    {
+                        continue
+                    }
+                    Err(other) => Err(other).context("Fingerprinting the bundle failed")?,
+                }
+            }
+
+            Ok(None)
+        } else {
+            let mut builder = client.list_objects_v2().bucket(&self.load_bucket);
+
+            if let Some(prefix) = &self.load_prefix {
+                builder = builder.prefix(prefix);
+            }
+
+            let mut pages = builder.into_paginator().send();
+
+            while let Some(resp) = pages.next().await {
+                let resp = resp.context("Fingerprinting the bundle failed")?;
+
+                for object_desc in resp.contents() {
+                    if let Some(key) = object_desc.key() {
+                        if BundleType::iter().any(|bundle_type| key.ends_with(bundle_type.suffix()))
+                        {
+                            return Ok(object_desc
+                                .e_tag()
+                                .map(|etag| format!("s3:{}/{key}:{etag}", self.load_bucket)));
+                        }
+                    }
+                }
+            }
+
+            Ok(None)
+        }
+    }
+
+    async fn commit(&mut self, name: &str) -> Result<(), Error> {
+        if let Some(index) = self.claimed.iter().position(|(claimed, _)| claimed == name) {
+            let client = self.client().await;
+            let key = &self.claimed[index].1;
+
+            client
+                .delete_object()
+                .bucket(&self.load_bucket)
+                .key(key)
+                .send()
+                .await
+                .context("Deleting the bundle after its provisioning failed")?;
+
+            self.unclaim(&client, key).await?;
+
+            self.claimed.remove(index);
+
+            info!("Deleted bundle `{name}` from the bucket");
+        }
+
+        Ok(())
+    }
+
+    async fn release(&mut self, name: &str) -> Result<(), Error> {
+        if let Some(index) = self.claimed.iter().position(|(claimed, _)| claimed == name) {
+            let client = self.client().await;
+
+            self.unclaim(&client, &self.claimed[index].1)
+                .await
+                .context("Releasing the claim of the bundle failed")?;
+
+            self.claimed.remove(index);
+
+            info!("Released the claim of bundle `{name}`");
+        }
+
+        Ok(())
+    }
+
+    async fn quarantine(&mut self, name: &str) -> Result<(), Error> {
+        if let Some(index) = self.claimed.iter().position(|(claimed, _)| claimed == name) {
+            // The lock object is kept, so that the bundle is skipped by all stations until it is inspected
+            let (_, key) = self.claimed.remove(index);
+
+            warn!(
+                "Quarantined bundle `{name}`: lock object `{}` kept",
+                Self::lock_key(&key)
+            );
+        }
+
+        Ok(())
+    }
 }
 
 #[derive(Debug)]
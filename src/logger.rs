@@ -17,6 +17,7 @@ pub static LOGGER: Logger = Logger::new();
 /// - Writes all logs to a file
 /// - Keeps the last N log lines in a memory buffer (for rendering in the UI)
 /// - Signals when a log message has been written
+/// - Publishes all logs to the subscribers of the daemon API log stream (if any)
 pub struct Logger(Mutex<Option<Arc<Model>>>);
 
 impl Default for Logger {
@@ -48,6 +49,7 @@ impl Log for Logger {
         if let Some(model) = self.0.lock().unwrap().clone() {
             model.access_mut(|inner| {
                 inner.logs.file.log(record);
+                inner.logs.streamed.log(record);
 
                 ((), inner.logs.buffered.log(record))
             });
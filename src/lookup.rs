@@ -0,0 +1,68 @@
+//! Looking up the ID of the bundle to be loaded with an external service (see `BundleIdentification::Lookup`)
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use log::info;
+
+use crate::BundleIdentificationLookup;
+
+/// Look up the bundle ID by POSTing the readouts to the lookup service
+///
+/// The server replies either with a plain bundle ID, or with a JSON object having a `bundle_id` field
+pub fn lookup(
+    lookup: &BundleIdentificationLookup,
+    readouts: &[(String, String)],
+) -> anyhow::Result<String> {
+    let url = lookup.url.as_str();
+
+    let body = readouts
+        .iter()
+        .map(|(name, value)| (name.as_str(), value.as_str()))
+        .collect::<BTreeMap<_, _>>();
+
+    let mut request = reqwest::blocking::Client::new()
+        .post(url)
+        .timeout(Duration::from_secs(lookup.timeout_secs as _))
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::to_string(&body)?);
+
+    for (name, value) in &lookup.headers {
+        request = request.header(name, value);
+    }
+
+    let response = request
+        .send()
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Looking up the bundle ID with `{url}` failed"))?
+        .text()
+        .with_context(|| format!("Reading the bundle ID lookup response from `{url}` failed"))?;
+
+    let response = response.trim();
+
+    let bundle_id = if response.starts_with('{') {
+        let value: serde_json::Value = serde_json::from_str(response).with_context(|| {
+            format!("Parsing the bundle ID lookup response `{response}` failed")
+        })?;
+
+        value
+            .get("bundle_id")
+            .and_then(serde_json::Value::as_str)
+            .map(str::to_string)
+            .with_context(|| {
+                format!("Bundle ID lookup response `{response}` has no `bundle_id` field")
+            })?
+    } else {
+        response.to_string()
+    };
+
+    if bundle_id.is_empty() {
+        anyhow::bail!("Bundle ID lookup with `{url}` returned no bundle ID");
+    }
+
+    info!("Bundle ID `{bundle_id}` looked up with `{url}`");
+
+    Ok(bundle_id)
+}
@@ -1,12 +1,14 @@
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
 
 use anyhow::Context;
 
 use async_compat::CompatExt;
 
-use clap::{ColorChoice, Parser, Subcommand, ValueEnum};
+use clap::{Args, ColorChoice, Parser, Subcommand, ValueEnum};
 
+use espfactory::auth::{HttpAuth, OAuth2};
 use espfactory::loader::Loader;
+use espfactory::uploader::spool::SpoolLogsUploader;
 use espfactory::uploader::{LogsUploader, MultilogsUploader};
 use espfactory::{self, LOGGER};
 
@@ -20,6 +22,9 @@ use url::Url;
 
 extern crate alloc;
 
+/// The supported extensions of the configuration file, in order of preference
+const CONF_EXTENSIONS: &[&str] = &["toml", "json", "yaml", "yml"];
+
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None, color = ColorChoice::Auto)]
 struct Cli {
@@ -27,7 +32,8 @@ struct Cli {
     #[arg(short = 'l', long, default_value = "regular")]
     verbosity: Verbosity,
 
-    /// Configuration file
+    /// Configuration file (`.toml`, `.json` or `.yaml`).
+    /// If not provided, `espfactory.toml` (or `.json`, `.yaml`, `.yml`) next to the executable is used, if present
     #[arg(short = 'c', long)]
     conf: Option<PathBuf>,
 
@@ -41,21 +47,63 @@ struct Cli {
     #[arg(short = 's', long)]
     secure_download: bool,
 
+    /// Factory reset of returned units: only the data partitions (NVS, `otadata`, coredump, FAT, SPIFFS, ...)
+    /// are reset, while the bootloader, the partition table and the app partitions are left intact
+    #[arg(short = 'd', long, conflicts_with = "reprovision")]
+    data_reset: bool,
+
+    /// Do not cache the base bundle locally, i.e. always load it from the base bundle URL
+    #[arg(long)]
+    no_cache: bool,
+
+    /// Acknowledge upfront that the configuration might have destructive settings (eFuse burning, flash encryption),
+    /// rather than acknowledging those interactively at startup. Necessary in headless mode
+    #[arg(long)]
+    yes_i_know: bool,
+
+    /// Batch mode: provision exactly the given number of PCBs and then exit, failing on the first failed step.
+    /// The exit code tells the outcome apart (0 - success, 1 - other error, 2 - bundle load failure (e.g. not found),
+    /// 3 - bundle preparation failure, 4 - eFuse readout failure, 5 - flash failure, 6 - eFuse burn failure,
+    /// 7 - other provisioning failure, 8 - app run failure, 9 - plugin failure, 10 - quit before the batch was complete,
+    /// 11 - measurement failure)
+    #[arg(short = 'n', long, conflicts_with = "single")]
+    count: Option<u32>,
+
+    /// Batch mode with a single PCB; same as `--count 1`
+    #[arg(long)]
+    single: bool,
+
+    /// Record the operator session (key presses, bundle hashes, tool invocations and device responses) into the given file
+    #[arg(long)]
+    record: Option<PathBuf>,
+
+    /// Replay a recorded operator session from the given file against the recorded device responses (no device needed),
+    /// for operator training, documentation screencasts or for reproducing a reported failure
+    #[arg(long)]
+    replay: Option<PathBuf>,
+
+    /// Run against a simulated device rather than a real one (no device needed),
+    /// for operator training and for exercising the whole provisioning in CI
+    #[arg(long)]
+    simulate: bool,
+
     /// Base bundle URL - the URL where the factory will look for a base bundle to load.
+    /// Can be repeated to layer several base bundles, from the bottom layer upwards
+    /// (e.g. a common-platform bundle followed by a product bundle).
     /// Supported URL schemes:
     /// `file:` - load a base bundle from a file;
     /// `dir:` - load a base bundle from a directory;
     /// `http:` or `https:` - load a base bundle from an HTTP(s) server;
     /// `s3:` - load a base bundle from an S3 bucket
     #[arg(short = 'b', long)]
-    base_url: Option<Url>,
+    base_url: Vec<Url>,
 
     /// Bundle URL - the URL where the factory will look for a bundle to load.
     /// Supported URL schemes:
     /// `file:` - load a bundle from a file;
-    /// `dir:` or `dird:` - load bundles from a directory; if `dird:` is used, the bundle will be removed after loading;
+    /// `dir:` or `dird:` - load bundles from a directory; if `dird:` is used, the bundle will be removed once provisioned;
     /// `http:` or `https:` - load bundles from an HTTP(s) server;
-    /// `s3:` or `s3d:` - load bundles from an S3 bucket; if `s3d:` is used, the bundle will be removed after loading
+    /// `s3:` or `s3d:` - load bundles from an S3 bucket; if `s3d:` is used, the bundle will be removed once provisioned
     url: Option<Url>,
 
     /// Logs upload URLs - the URLs where the factory will upload the logs from the device provisioning.
@@ -65,6 +113,11 @@ struct Cli {
     /// `s3:` - upload logs to an S3 bucket
     logs_urls: Vec<Url>,
 
+    /// Refuse to load a bundle by ID if its logs are already uploaded, i.e. if it was already provisioned.
+    /// The first logs upload URL of the same kind as the bundle URL (`dir:`, `http(s):` or `s3:`) is checked
+    #[arg(long)]
+    logs_check: bool,
+
     #[command(subcommand)]
     command: Option<Command>,
 }
@@ -73,6 +126,61 @@ struct Cli {
 enum Command {
     /// Run a TTY monitor rather than doing factory provisioning
     Monitor(MonitorArgs),
+    /// Generate a udev rules file granting access to the USB serial adapters (Linux)
+    UdevRules(UdevRulesArgs),
+    /// Load and merge the bundle(s) like the factory provisioning does, and print what would be
+    /// flashed and burned, without touching the hardware
+    Plan(PlanArgs),
+    /// Read back selected partitions or the whole flash of the connected chip into files, e.g. for archiving
+    /// a failed PCB for failure analysis before rework
+    Dump(DumpArgs),
+}
+
+/// Arguments of the `dump` command
+#[derive(Args, Debug)]
+struct DumpArgs {
+    /// The chip to read from (e.g. `esp32c3`)
+    #[arg(short = 'c', long)]
+    chip: String,
+
+    /// The name of a partition to read back; can be repeated.
+    /// If not provided, the whole flash is read back
+    #[arg(short = 'p', long)]
+    partition: Vec<String>,
+
+    /// The offset of the partition table in the flash
+    #[arg(long, default_value = "0x8000", value_parser = parse_offset)]
+    part_table_offset: u32,
+
+    /// The directory where the partitions (`<partition-name>.bin`) or the whole flash (`flash.bin`) are saved
+    #[arg(short = 'o', long, default_value = ".")]
+    output: PathBuf,
+}
+
+/// Arguments of the `plan` command
+#[derive(Args, Debug)]
+struct PlanArgs {
+    /// The ID of the bundle to load, if the bundle URL requires one
+    #[arg(short = 'i', long)]
+    id: Option<String>,
+}
+
+/// Arguments of the `udev-rules` command
+#[derive(Args, Debug)]
+struct UdevRulesArgs {
+    /// The group to be granted read/write access to the adapters
+    #[arg(short = 'g', long, default_value = "dialout")]
+    group: String,
+
+    /// An optional user to become the owner of the adapters
+    /// (i.e. the user the tool subprocesses are run as)
+    #[arg(short = 'u', long)]
+    owner: Option<String>,
+
+    /// The file where the rules should be saved (e.g. `/etc/udev/rules.d/99-espfactory.rules`).
+    /// If not provided, the rules are printed to the standard output
+    #[arg(short = 'o', long)]
+    output: Option<PathBuf>,
 }
 
 /// Verbosity
@@ -105,11 +213,29 @@ impl Verbosity {
 pub struct Config {
     /// The base URL of the factory
     pub base_url: Option<Url>,
+    /// The URLs of additional base bundle layers, merged in order on top of the base bundle of `base_url` (if any)
+    /// and below the bundle of `url`
+    #[serde(default)]
+    pub bundle_layers: Vec<Url>,
     /// The source of bundles
     pub url: Option<Url>,
     /// The destinations where to upload logs
     #[serde(default)]
     pub logs_upload_urls: Vec<Url>,
+    /// Whether to refuse loading a bundle by ID if its logs are already uploaded, i.e. if it was already provisioned
+    ///
+    /// The first logs upload URL of the same kind as `url` is checked: a `dir:` directory for a `dir:`/`dird:` bundle URL
+    /// (for a `<bundle-name>_<timestamp>.log.zip` file), an `http(s):` server for an `http(s):` bundle URL (see `HttpLoader`
+    /// for the check request), and an `s3:` bucket for an `s3:`/`s3d:` bundle URL
+    #[serde(default)]
+    pub logs_check: bool,
+    /// An optional static value of the `Authorization` header of the HTTP(S) bundle and logs requests
+    #[serde(default)]
+    pub http_auth: Option<String>,
+    /// If provided, the HTTP(S) bundle and logs requests are authorized with an access token obtained
+    /// with the OAuth2 client credentials flow (and renewed automatically before it expires), instead of `http_auth`
+    #[serde(default)]
+    pub http_oauth2: Option<OAuth2>,
     /// The configuration of the factory
     #[serde(default)]
     pub config: espfactory::Config,
@@ -121,8 +247,12 @@ impl Config {
     pub const fn new() -> Self {
         Self {
             base_url: None,
+            bundle_layers: Vec::new(),
             url: None,
             logs_upload_urls: Vec::new(),
+            logs_check: false,
+            http_auth: None,
+            http_oauth2: None,
             config: espfactory::Config::new(),
         }
     }
@@ -137,36 +267,24 @@ impl Default for Config {
 fn main() {
     if let Err(err) = run() {
         eprintln!("Error: {err:#}");
-        std::process::exit(1);
+        std::process::exit(espfactory::exit_code(&err.into()));
     }
 }
 
 fn run() -> anyhow::Result<()> {
     let args = Cli::parse();
 
-    if let Some(Command::Monitor(monitor_args)) = args.command {
-        return run_monitor(monitor_args);
-    }
+    let plan_args = match args.command {
+        Some(Command::Monitor(monitor_args)) => return run_monitor(monitor_args),
+        Some(Command::UdevRules(udev_rules_args)) => return run_udev_rules(udev_rules_args),
+        Some(Command::Plan(plan_args)) => Some(plan_args),
+        Some(Command::Dump(dump_args)) => return run_dump(args.conf, dump_args),
+        None => None,
+    };
 
     log::set_max_level(LevelFilter::Debug);
 
-    let mut conf = if let Some(conf) = args.conf {
-        println!("Loading configuration from `{}`", conf.display());
-        toml::from_str(&std::fs::read_to_string(conf)?).context("Invalid configuiration format")?
-    } else if let Ok(current_exe) = std::env::current_exe() {
-        let conf = current_exe.with_file_name("espfactory.toml");
-        if conf.exists() && conf.is_file() {
-            println!("Loading configuration from `{}`", conf.display());
-            toml::from_str(&std::fs::read_to_string(conf)?)
-                .context("Invalid configuiration format")?
-        } else {
-            println!("Using default configuration");
-            Config::new()
-        }
-    } else {
-        println!("Using default configuration");
-        Config::new()
-    };
+    let mut conf = find_load_conf(args.conf)?;
 
     if args.reprovision {
         conf.config.reprovision();
@@ -176,33 +294,124 @@ fn run() -> anyhow::Result<()> {
         conf.config.secure_download();
     }
 
-    let base_loader_url = args.base_url.or_else(|| conf.base_url.clone());
+    if args.data_reset {
+        conf.config.data_reset();
+    }
+
+    if args.no_cache {
+        conf.config.base_bundle_cache = false;
+    }
+
+    if args.yes_i_know {
+        conf.config.destructive_ack = true;
+    }
+
+    if args.single {
+        conf.config.batch_count = Some(1);
+    } else if let Some(count) = args.count {
+        conf.config.batch_count = Some(count);
+    }
+
+    if let Some(record) = args.record {
+        conf.config.session_record = Some(record.display().to_string());
+    }
+
+    if let Some(replay) = args.replay {
+        conf.config.session_replay = Some(replay.display().to_string());
+    }
+
+    if args.simulate && conf.config.simulate.is_none() {
+        conf.config.simulate = Some(espfactory::Simulation::new());
+    }
 
-    let base_loader = base_loader_url
-        .as_ref()
-        .map(|url| Loader::new(url, false))
-        .transpose()?;
+    let base_loader_urls = if args.base_url.is_empty() {
+        conf.base_url
+            .iter()
+            .chain(conf.bundle_layers.iter())
+            .cloned()
+            .collect()
+    } else {
+        args.base_url
+    };
+
+    let http_auth = conf
+        .http_oauth2
+        .clone()
+        .map(HttpAuth::oauth2)
+        .or_else(|| conf.http_auth.clone().map(HttpAuth::Header));
+
+    let base_loaders = base_loader_urls
+        .iter()
+        .map(|url| Loader::new(url, false, http_auth.clone(), &[]))
+        .collect::<Result<Vec<_>, espfactory::Error>>()?;
 
     let loader_url = args.url.or_else(|| conf.url.clone());
     let Some(loader_url) = loader_url else {
         anyhow::bail!("No bundle URL provided");
     };
 
-    let loader = Loader::new(&loader_url, true)?;
-
     let mut logs_upload_urls = args.logs_urls;
 
     if logs_upload_urls.is_empty() {
         logs_upload_urls = conf.logs_upload_urls.clone();
     }
 
-    if logs_upload_urls.is_empty() {
+    let loader = Loader::new(
+        &loader_url,
+        true,
+        http_auth.clone(),
+        if args.logs_check || conf.logs_check {
+            &logs_upload_urls
+        } else {
+            &[]
+        },
+    )?;
+
+    // Neither a dry plan, nor a replay or a simulation provision real PCBs, so they never consume the bundles of a pool
+    let loader = if plan_args.is_some()
+        || conf.config.session_replay.is_some()
+        || conf.config.simulate.is_some()
+    {
+        loader.keep_bundles()
+    } else {
+        loader
+    };
+
+    if let Some(plan_args) = plan_args {
+        let plan = futures_lite::future::block_on(
+            espfactory::plan(&conf.config, base_loaders, loader, plan_args.id.as_deref()).compat(),
+        )?;
+
+        print!("{plan}");
+
+        return Ok(());
+    }
+
+    if logs_upload_urls.is_empty()
+        && conf.config.session_replay.is_none()
+        && conf.config.simulate.is_none()
+    {
         anyhow::bail!("No logs upload URLs provided");
     }
 
+    let logs_spool_dir = conf.config.logs_spool.then(|| {
+        conf.config
+            .logs_spool_dir
+            .as_ref()
+            .map(PathBuf::from)
+            .unwrap_or_else(SpoolLogsUploader::<LogsUploader>::default_dir)
+    });
+
     let mut logs_uploaders = logs_upload_urls
         .iter()
-        .map(LogsUploader::new)
+        .map(|url| {
+            Ok(SpoolLogsUploader::new(
+                LogsUploader::new(url, http_auth.clone())?,
+                logs_spool_dir
+                    .as_deref()
+                    .map(|dir| SpoolLogsUploader::<LogsUploader>::url_dir(dir, url)),
+            ))
+        })
         .collect::<anyhow::Result<Vec<_>>>()?;
 
     log::set_logger(&LOGGER).unwrap();
@@ -215,7 +424,8 @@ fn run() -> anyhow::Result<()> {
         espfactory::run(
             &conf.config,
             args.verbosity.log_level(),
-            base_loader,
+            &espfactory::events::Events::new(),
+            base_loaders,
             loader,
             MultilogsUploader(&mut logs_uploaders),
         )
@@ -225,8 +435,55 @@ fn run() -> anyhow::Result<()> {
     Ok(())
 }
 
+/// Load the configuration from the given file or - if not provided - from `espfactory.toml`
+/// (or `.json`, `.yaml`, `.yml`) next to the executable, if present
+fn find_load_conf(conf_file: Option<PathBuf>) -> anyhow::Result<Config> {
+    let conf_file = conf_file.or_else(|| {
+        let current_exe = std::env::current_exe().ok()?;
+
+        CONF_EXTENSIONS
+            .iter()
+            .map(|ext| current_exe.with_file_name(format!("espfactory.{ext}")))
+            .find(|conf| conf.exists() && conf.is_file())
+    });
+
+    if let Some(conf_file) = conf_file {
+        load_conf(&conf_file)
+    } else {
+        println!("Using default configuration");
+        Ok(Config::new())
+    }
+}
+
+/// Load the configuration from the given file
+///
+/// The format of the file (TOML, JSON or YAML) is detected by the file extension,
+/// with TOML being the default
+fn load_conf(conf_file: &Path) -> anyhow::Result<Config> {
+    println!("Loading configuration from `{}`", conf_file.display());
+
+    let content = std::fs::read_to_string(conf_file)?;
+
+    let ext = conf_file
+        .extension()
+        .and_then(|ext| ext.to_str())
+        .map(str::to_ascii_lowercase);
+
+    let conf = match ext.as_deref() {
+        Some("json") => serde_json::from_str(&content).map_err(anyhow::Error::from),
+        Some("yaml") | Some("yml") => serde_yaml::from_str(&content).map_err(anyhow::Error::from),
+        _ => toml::from_str(&content).map_err(anyhow::Error::from),
+    };
+
+    conf.context("Invalid configuration format")
+}
+
 fn run_monitor(monitor_args: MonitorArgs) -> anyhow::Result<()> {
-    match espflash::cli::serial_monitor(monitor_args, &espflash::cli::config::Config::default()) {
+    // Honor the `espflash.toml` / `espflash_ports.toml` files, as the `espflash` CLI does
+    let config = espflash::cli::config::Config::load()
+        .map_err(|err| anyhow::anyhow!("Loading the `espflash` configuration failed: {err}"))?;
+
+    match espflash::cli::serial_monitor(monitor_args, &config) {
         Ok(_) => {}
         Err(err) => {
             error!("Running serial monitor returned an error: {err}");
@@ -235,3 +492,53 @@ fn run_monitor(monitor_args: MonitorArgs) -> anyhow::Result<()> {
 
     Ok(())
 }
+
+fn run_dump(conf_file: Option<PathBuf>, args: DumpArgs) -> anyhow::Result<()> {
+    let conf = find_load_conf(conf_file)?;
+
+    env_logger::Builder::new()
+        .filter_level(LevelFilter::Info)
+        .init();
+
+    espfactory::dump(
+        &conf.config,
+        &args.chip,
+        &args.partition,
+        args.part_table_offset,
+        &args.output,
+    )
+}
+
+/// Parse a flash offset, either as a hexadecimal (`0x` prefix) or a decimal number
+fn parse_offset(offset: &str) -> Result<u32, String> {
+    let offset = offset.trim();
+
+    if let Some(hex) = offset
+        .strip_prefix("0x")
+        .or_else(|| offset.strip_prefix("0X"))
+    {
+        u32::from_str_radix(hex, 16).map_err(|err| err.to_string())
+    } else {
+        offset
+            .parse()
+            .map_err(|err: core::num::ParseIntError| err.to_string())
+    }
+}
+
+fn run_udev_rules(args: UdevRulesArgs) -> anyhow::Result<()> {
+    let rules = espfactory::udev_rules(&args.group, args.owner.as_deref())?;
+
+    if let Some(output) = args.output {
+        std::fs::write(&output, rules)
+            .with_context(|| format!("Writing udev rules to `{}` failed", output.display()))?;
+
+        println!(
+            "udev rules saved to `{}`. Reload with `sudo udevadm control --reload-rules && sudo udevadm trigger`",
+            output.display()
+        );
+    } else {
+        print!("{rules}");
+    }
+
+    Ok(())
+}
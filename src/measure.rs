@@ -0,0 +1,161 @@
+//! Test-point measurements with external instruments (e.g. the current draw of the PCB, as measured by a programmable PSU)
+//!
+//! The instruments are queried with SCPI commands, over a raw TCP socket (port 5025 on most instruments)
+//! or over a serial port
+
+use std::io::{BufRead, BufReader, Read, Write};
+use std::net::{TcpStream, ToSocketAddrs};
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use log::info;
+
+use crate::{Instrument, Measurement};
+
+/// The outcome of a single measurement
+#[derive(Clone, Debug)]
+pub struct Outcome {
+    /// The measured value
+    pub value: f64,
+    /// The time it took to take the measurement
+    pub elapsed: Duration,
+    /// `None` if the value is within the thresholds of the measurement, or a description of the violation otherwise
+    pub violation: Option<String>,
+}
+
+impl Outcome {
+    /// Return the measured value, followed by the unit of the measurement (if any)
+    pub fn display(&self, measurement: &Measurement) -> String {
+        if let Some(unit) = measurement.unit.as_deref() {
+            format!("{} {unit}", self.value)
+        } else {
+            self.value.to_string()
+        }
+    }
+}
+
+/// Take a single measurement and check it against its thresholds
+pub fn measure(measurement: &Measurement) -> anyhow::Result<Outcome> {
+    let started = Instant::now();
+
+    let min = threshold(measurement, "min", measurement.min.as_deref())?;
+    let max = threshold(measurement, "max", measurement.max.as_deref())?;
+
+    if measurement.settle_ms > 0 {
+        std::thread::sleep(Duration::from_millis(measurement.settle_ms as _));
+    }
+
+    let response = match &measurement.instrument {
+        Instrument::Tcp {
+            address,
+            timeout_ms,
+        } => {
+            let timeout = Duration::from_millis(*timeout_ms as _);
+
+            let addr = address
+                .to_socket_addrs()
+                .with_context(|| format!("Resolving instrument address `{address}` failed"))?
+                .next()
+                .ok_or_else(|| anyhow::anyhow!("Instrument address `{address}` did not resolve"))?;
+
+            let stream = TcpStream::connect_timeout(&addr, timeout)
+                .with_context(|| format!("Connecting to instrument `{address}` failed"))?;
+
+            stream.set_read_timeout(Some(timeout))?;
+            stream.set_write_timeout(Some(timeout))?;
+
+            query(stream, measurement)
+                .with_context(|| format!("Querying instrument `{address}` failed"))?
+        }
+        Instrument::Serial {
+            port,
+            baud,
+            timeout_ms,
+        } => {
+            let serial = serialport::new(port, *baud)
+                .timeout(Duration::from_millis(*timeout_ms as _))
+                .open()
+                .with_context(|| format!("Opening instrument serial port `{port}` failed"))?;
+
+            query(serial, measurement)
+                .with_context(|| format!("Querying instrument on serial port `{port}` failed"))?
+        }
+    };
+
+    let value = parse(&response)
+        .with_context(|| format!("Invalid response `{}` of the instrument", response.trim()))?;
+
+    let violation = match (min, max) {
+        (Some(min), _) if value < min => Some(format!("{value} is below the minimum of {min}")),
+        (_, Some(max)) if value > max => Some(format!("{value} is above the maximum of {max}")),
+        _ => None,
+    };
+
+    info!(
+        "Measurement `{}`: {value}{} ({})",
+        measurement.name,
+        measurement
+            .unit
+            .as_deref()
+            .map(|unit| format!(" {unit}"))
+            .unwrap_or_default(),
+        violation.as_deref().unwrap_or("within thresholds")
+    );
+
+    Ok(Outcome {
+        value,
+        elapsed: started.elapsed(),
+        violation,
+    })
+}
+
+/// Send the setup commands and the query of the measurement and return the (single line) response of the instrument
+fn query<T>(mut io: T, measurement: &Measurement) -> anyhow::Result<String>
+where
+    T: Read + Write,
+{
+    for command in measurement
+        .setup
+        .iter()
+        .chain(core::iter::once(&measurement.query))
+    {
+        io.write_all(command.as_bytes())?;
+        io.write_all(b"\n")?;
+    }
+
+    io.flush()?;
+
+    let mut line = String::new();
+
+    BufReader::new(io).read_line(&mut line)?;
+
+    Ok(line)
+}
+
+/// Parse a SCPI numeric response (e.g. `+1.23450E-01`)
+///
+/// Instruments replying with several comma-separated values (e.g. a multi-channel query) are supported
+/// by taking the first value
+fn parse(response: &str) -> anyhow::Result<f64> {
+    let value = response.trim().split(',').next().unwrap_or_default().trim();
+
+    Ok(value.parse::<f64>()?)
+}
+
+fn threshold(
+    measurement: &Measurement,
+    kind: &str,
+    value: Option<&str>,
+) -> anyhow::Result<Option<f64>> {
+    value
+        .map(|value| {
+            value.trim().parse::<f64>().with_context(|| {
+                format!(
+                    "Invalid {kind} threshold `{value}` of measurement `{}`",
+                    measurement.name
+                )
+            })
+        })
+        .transpose()
+}
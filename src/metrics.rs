@@ -0,0 +1,337 @@
+//! Line-rate metrics of the factory station, for dashboards (e.g. Grafana) across many stations
+//!
+//! The metrics are exposed in the Prometheus text format on an HTTP endpoint, and/or pushed to a StatsD server:
+//! - `<prefix>_provisioned_total` - the number of provisioned PCBs
+//! - `<prefix>_failures_total{step="..."}` - the number of failed steps, by step
+//! - `<prefix>_cycle_seconds` - a histogram of the cycle times of the provisioned PCBs
+//! - `<prefix>_flash_bytes_total` and `<prefix>_flash_seconds_total` - the flashed bytes and the time spent flashing,
+//!   so that the flash throughput can be computed as the ratio of their rates
+//!
+//! All metrics carry a `station` label, so that the metrics of many stations can be aggregated
+
+use core::fmt::Write as _;
+use core::time::Duration;
+
+use std::collections::BTreeMap;
+use std::io::ErrorKind;
+use std::io::{BufRead, BufReader, Write};
+use std::net::{TcpListener, TcpStream, UdpSocket};
+use std::sync::{Arc, Mutex, Weak};
+use std::thread;
+
+use anyhow::Context;
+
+use log::{info, warn};
+
+/// The upper bounds of the buckets of the cycle time histogram, in seconds
+const CYCLE_BUCKETS: &[f64] = &[15.0, 30.0, 45.0, 60.0, 90.0, 120.0, 180.0, 300.0, 600.0];
+
+/// The polling period of the metrics endpoint, when no connection is pending
+const POLL: Duration = Duration::from_millis(100);
+
+/// The metrics collected since the start of the factory run
+struct Counters {
+    prefix: String,
+    station: String,
+    statsd: Option<(UdpSocket, String)>,
+    provisioned: u64,
+    failures: BTreeMap<String, u64>,
+    cycle_buckets: Vec<u64>,
+    cycle_sum: f64,
+    flash_bytes: u64,
+    flash_secs: f64,
+}
+
+impl Counters {
+    /// Render the metrics in the Prometheus text exposition format
+    fn render(&self) -> String {
+        let prefix = &self.prefix;
+        let station = escape(&self.station);
+
+        let mut out = String::new();
+
+        let _ = writeln!(
+            out,
+            "# HELP {prefix}_provisioned_total The number of provisioned PCBs"
+        );
+        let _ = writeln!(out, "# TYPE {prefix}_provisioned_total counter");
+        let _ = writeln!(
+            out,
+            "{prefix}_provisioned_total{{station=\"{station}\"}} {}",
+            self.provisioned
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP {prefix}_failures_total The number of failed steps, by step"
+        );
+        let _ = writeln!(out, "# TYPE {prefix}_failures_total counter");
+        for (step, count) in &self.failures {
+            let _ = writeln!(
+                out,
+                "{prefix}_failures_total{{station=\"{station}\",step=\"{}\"}} {count}",
+                escape(step)
+            );
+        }
+
+        let _ = writeln!(
+            out,
+            "# HELP {prefix}_cycle_seconds The cycle time of the provisioned PCBs"
+        );
+        let _ = writeln!(out, "# TYPE {prefix}_cycle_seconds histogram");
+        for (bound, count) in CYCLE_BUCKETS.iter().zip(&self.cycle_buckets) {
+            let _ = writeln!(
+                out,
+                "{prefix}_cycle_seconds_bucket{{station=\"{station}\",le=\"{bound}\"}} {count}"
+            );
+        }
+        let _ = writeln!(
+            out,
+            "{prefix}_cycle_seconds_bucket{{station=\"{station}\",le=\"+Inf\"}} {}",
+            self.provisioned
+        );
+        let _ = writeln!(
+            out,
+            "{prefix}_cycle_seconds_sum{{station=\"{station}\"}} {}",
+            self.cycle_sum
+        );
+        let _ = writeln!(
+            out,
+            "{prefix}_cycle_seconds_count{{station=\"{station}\"}} {}",
+            self.provisioned
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP {prefix}_flash_bytes_total The number of flashed bytes"
+        );
+        let _ = writeln!(out, "# TYPE {prefix}_flash_bytes_total counter");
+        let _ = writeln!(
+            out,
+            "{prefix}_flash_bytes_total{{station=\"{station}\"}} {}",
+            self.flash_bytes
+        );
+
+        let _ = writeln!(
+            out,
+            "# HELP {prefix}_flash_seconds_total The time spent flashing"
+        );
+        let _ = writeln!(out, "# TYPE {prefix}_flash_seconds_total counter");
+        let _ = writeln!(
+            out,
+            "{prefix}_flash_seconds_total{{station=\"{station}\"}} {}",
+            self.flash_secs
+        );
+
+        out
+    }
+
+    /// Push a metric to the StatsD server, if configured
+    fn push(&self, name: &str, value: impl core::fmt::Display, kind: &str) {
+        if let Some((socket, addr)) = &self.statsd {
+            let line = format!(
+                "{}.{}.{name}:{value}|{kind}",
+                self.prefix,
+                statsd_name(&self.station)
+            );
+
+            if let Err(err) = socket.send_to(line.as_bytes(), addr.as_str()) {
+                warn!("Pushing metric `{name}` to StatsD server `{addr}` failed: {err}");
+            }
+        }
+    }
+}
+
+/// The metrics of a factory run, collected only if enabled (see `Config::metrics`)
+#[derive(Clone, Default)]
+pub(crate) struct Metrics(Option<Arc<Mutex<Counters>>>);
+
+impl Metrics {
+    /// Start collecting the metrics of the station, if enabled
+    ///
+    /// The metrics endpoint (if any) stops listening once the returned metrics are dropped
+    ///
+    /// # Arguments
+    /// - `conf` - the metrics configuration; if `None`, no metrics are collected
+    /// - `station` - the station label of the metrics
+    pub(crate) fn start(conf: Option<&crate::Metrics>, station: &str) -> anyhow::Result<Self> {
+        let Some(conf) = conf else {
+            return Ok(Self(None));
+        };
+
+        let statsd = conf
+            .statsd
+            .as_ref()
+            .map(|addr| {
+                UdpSocket::bind("0.0.0.0:0")
+                    .map(|socket| (socket, addr.clone()))
+                    .context("Creating the StatsD socket failed")
+            })
+            .transpose()?;
+
+        let counters = Arc::new(Mutex::new(Counters {
+            prefix: conf
+                .prefix
+                .clone()
+                .unwrap_or_else(|| "espfactory".to_string()),
+            station: station.to_string(),
+            statsd,
+            provisioned: 0,
+            failures: BTreeMap::new(),
+            cycle_buckets: vec![0; CYCLE_BUCKETS.len()],
+            cycle_sum: 0.0,
+            flash_bytes: 0,
+            flash_secs: 0.0,
+        }));
+
+        if let Some(listen) = conf.listen.as_deref() {
+            let listener = TcpListener::bind(listen)
+                .with_context(|| format!("Binding the metrics endpoint to `{listen}` failed"))?;
+
+            // Non-blocking, so that the endpoint notices that the metrics are dropped
+            listener
+                .set_nonblocking(true)
+                .context("Configuring the metrics endpoint failed")?;
+
+            info!("Metrics exposed on `http://{listen}/metrics`");
+
+            let counters = Arc::downgrade(&counters);
+
+            thread::Builder::new()
+                .name("metrics".into())
+                .spawn(move || listen_loop(listener, counters))
+                .context("Spawning the metrics endpoint thread failed")?;
+        }
+
+        Ok(Self(Some(counters)))
+    }
+
+    /// Account a provisioned PCB and its cycle time
+    pub(crate) fn provisioned(&self, cycle_time: Duration) {
+        self.with(|metrics| {
+            let secs = cycle_time.as_secs_f64();
+
+            metrics.provisioned += 1;
+            metrics.cycle_sum += secs;
+
+            for (bound, count) in CYCLE_BUCKETS.iter().zip(metrics.cycle_buckets.iter_mut()) {
+                if secs <= *bound {
+                    *count += 1;
+                }
+            }
+
+            metrics.push("provisioned", 1, "c");
+            metrics.push("cycle_time", cycle_time.as_millis(), "ms");
+        });
+    }
+
+    /// Account a failed step
+    pub(crate) fn failed(&self, step: &str) {
+        self.with(|metrics| {
+            *metrics.failures.entry(step.to_string()).or_default() += 1;
+
+            metrics.push(&format!("failures.{}", statsd_name(step)), 1, "c");
+        });
+    }
+
+    /// Account a completed flashing of the given number of bytes
+    pub(crate) fn flashed(&self, bytes: usize, duration: Duration) {
+        self.with(|metrics| {
+            let secs = duration.as_secs_f64();
+
+            metrics.flash_bytes += bytes as u64;
+            metrics.flash_secs += secs;
+
+            metrics.push("flash_bytes", bytes, "c");
+
+            if secs > 0.0 {
+                metrics.push("flash_throughput", (bytes as f64 / secs) as u64, "g");
+            }
+        });
+    }
+
+    /// Update the metrics, if enabled
+    fn with<F>(&self, f: F)
+    where
+        F: FnOnce(&mut Counters),
+    {
+        if let Some(counters) = self.0.as_ref() {
+            f(&mut counters.lock().unwrap());
+        }
+    }
+}
+
+/// Serve the metrics endpoint until the metrics are dropped
+fn listen_loop(listener: TcpListener, counters: Weak<Mutex<Counters>>) {
+    loop {
+        let Some(counters) = counters.upgrade() else {
+            break;
+        };
+
+        match listener.accept() {
+            Ok((stream, _)) => {
+                if let Err(err) = stream
+                    .set_nonblocking(false)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|_| serve(stream, &counters))
+                {
+                    warn!("Serving the metrics failed: {err}");
+                }
+            }
+            Err(err) if err.kind() == ErrorKind::WouldBlock => {
+                drop(counters);
+                thread::sleep(POLL);
+            }
+            Err(err) => warn!("Accepting a metrics connection failed: {err}"),
+        }
+    }
+}
+
+/// Serve a single HTTP request on the metrics endpoint
+fn serve(mut stream: TcpStream, counters: &Mutex<Counters>) -> anyhow::Result<()> {
+    stream.set_read_timeout(Some(Duration::from_secs(5)))?;
+
+    let mut request = String::new();
+    BufReader::new(&stream).read_line(&mut request)?;
+
+    let path = request.split_whitespace().nth(1).unwrap_or_default();
+
+    let (status, body) = if path == "/metrics" || path.starts_with("/metrics?") {
+        let body = counters.lock().unwrap().render();
+
+        ("200 OK", body)
+    } else {
+        ("404 Not Found", "Not Found\n".to_string())
+    };
+
+    write!(
+        stream,
+        "HTTP/1.1 {status}\r\nContent-Type: text/plain; version=0.0.4\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{body}",
+        body.len()
+    )?;
+
+    stream.flush()?;
+
+    Ok(())
+}
+
+/// Escape a Prometheus label value
+fn escape(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Turn a name into a StatsD-safe metric name segment
+fn statsd_name(name: &str) -> String {
+    name.chars()
+        .map(|ch| {
+            if ch.is_ascii_alphanumeric() || ch == '_' || ch == '-' {
+                ch
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
@@ -1,9 +1,15 @@
 use core::cell::RefCell;
 use core::num::Wrapping;
+use core::time::Duration;
 
 use std::collections::VecDeque;
 use std::fs::File;
 use std::io::{Read, Seek, SeekFrom, Write as _};
+use std::path::PathBuf;
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::time::Instant;
+
+use anyhow::Context;
 
 use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
 use embassy_sync::blocking_mutex::Mutex;
@@ -11,15 +17,26 @@ use embassy_sync::signal::Signal;
 
 use log::{LevelFilter, Log as _, Record};
 
+use ansi_to_tui::IntoText;
+
 use ratatui::layout::Rect;
-use ratatui::style::Stylize;
+use ratatui::style::{Color, Style, Stylize};
 use ratatui::text::{Line, Span, Text};
 use ratatui::widgets::{Paragraph, Wrap};
+
+use serde::Serialize;
 use tempfile::tempfile;
 use zip::write::FileOptions;
 use zip::ZipWriter;
 
+use crate::audit::AuditLog;
 use crate::bundle::Bundle;
+use crate::events::Events;
+use crate::i18n::Catalog;
+use crate::metrics::Metrics;
+use crate::report::Report;
+use crate::ui::view::Theme;
+use crate::{AppLogColors, Locale};
 
 extern crate alloc;
 
@@ -30,6 +47,10 @@ pub struct Model {
     /// A signal to notify that the model has changed
     /// Used to trigger redraws of the UI
     changed: Signal<CriticalSectionRawMutex, ()>,
+    /// The subscribers of the provisioning events of the run
+    events: Events,
+    /// The line-rate metrics of the station
+    metrics: Metrics,
 }
 
 impl Model {
@@ -41,7 +62,7 @@ impl Model {
     /// - `log_buffer_len`: The maximum number of log lines to keep in the on-screen logs buffer
     /// - `width`: The initial width of the screen (necessary for proper paging in the on-screen logs)
     /// - `height`: The initial height of the screen (necessary for proper paging in the on-screen logs)
-    pub const fn new(
+    pub fn new(
         log_level: LevelFilter,
         no_ui: bool,
         log_buffer_len: usize,
@@ -57,9 +78,33 @@ impl Model {
                 height,
             ))),
             changed: Signal::new(),
+            events: Events::new(),
+            metrics: Metrics::default(),
         }
     }
 
+    /// Send the provisioning events of the run to the subscribers of `events`
+    pub(crate) fn with_events(mut self, events: Events) -> Self {
+        self.events = events;
+        self
+    }
+
+    /// Collect the line-rate metrics of the run with `metrics`
+    pub(crate) fn with_metrics(mut self, metrics: Metrics) -> Self {
+        self.metrics = metrics;
+        self
+    }
+
+    /// Return the subscribers of the provisioning events of the run
+    pub(crate) fn events(&self) -> &Events {
+        &self.events
+    }
+
+    /// Return the line-rate metrics of the station
+    pub(crate) fn metrics(&self) -> &Metrics {
+        &self.metrics
+    }
+
     /// Get the current state of the model in the given closure
     pub fn access<F, R>(&self, f: F) -> R
     where
@@ -111,6 +156,25 @@ pub struct ModelInner {
     pub state: State,
     /// The logs' state of the model (i.e. whether the logs are active, the position inside the logs etc. etc.)
     pub logs: Logs,
+    /// The ID of the logged-in operator, if any
+    pub operator: Option<String>,
+    /// The statistics of the provisioning session
+    pub stats: Stats,
+    /// The timing of the provisioning steps
+    pub timing: Timing,
+    /// The progress of the provisioning cycle of the PCB being provisioned
+    pub cycle: Cycle,
+    /// The progress of the work order, if the provisioning is done against a work order (see `Config::work_order`)
+    pub work_order: Option<WorkOrderProgress>,
+    /// The identity of the PCB being provisioned, if its final result (see `Status::result`) is to be presented
+    /// as a full-screen PASS / FAIL banner (see `Config::result_banner`)
+    pub banner: Option<String>,
+    /// The locale used for formatting the numbers and dates displayed in the UI (see `Config::ui_locale`)
+    pub ui_locale: Locale,
+    /// The theme of the UI (see `Config::ui_theme`)
+    pub ui_theme: Theme,
+    /// The message catalog of the UI language (see `Config::ui_language`)
+    pub ui_catalog: Catalog,
 }
 
 impl ModelInner {
@@ -139,8 +203,257 @@ impl ModelInner {
                 width,
                 height,
             ),
+            operator: None,
+            stats: Stats::new(),
+            timing: Timing::new(),
+            cycle: Cycle::new(),
+            work_order: None,
+            banner: None,
+            ui_locale: Locale::ISO,
+            ui_theme: Theme::new(),
+            ui_catalog: Catalog::new(),
+        }
+    }
+}
+
+/// The statistics of the provisioning session
+#[derive(Debug, Clone, Default)]
+pub struct Stats {
+    /// The number of PCBs provisioned
+    pub provisioned: usize,
+    /// The number of provisioned PCBs which exceeded the cycle time budget (see `Config::cycle_time_budget_secs`)
+    pub over_budget: usize,
+}
+
+impl Stats {
+    /// Create new, empty statistics
+    pub const fn new() -> Self {
+        Self {
+            provisioned: 0,
+            over_budget: 0,
+        }
+    }
+}
+
+/// The timing of the provisioning steps: the step being executed, and the estimated duration of each step
+#[derive(Debug, Clone, Default)]
+pub struct Timing {
+    /// The step being executed, and when it was started
+    pub current: Option<(String, Instant)>,
+    /// The estimated duration of each step, i.e. the average duration of its successful executions
+    /// in the provisioning session so far, as (step, average duration, number of executions) triples
+    pub estimates: Vec<(String, Duration, u32)>,
+}
+
+impl Timing {
+    /// Create a new timing, with no step being executed and no estimates
+    pub const fn new() -> Self {
+        Self {
+            current: None,
+            estimates: Vec::new(),
         }
     }
+
+    /// Mark the step as being executed
+    pub fn start(&mut self, step: &str) {
+        self.current = Some((step.to_string(), Instant::now()));
+    }
+
+    /// Mark the step as completed, accounting its duration in its estimate if the step succeeded
+    pub fn finish(&mut self, step: &str, duration: Duration, succeeded: bool) {
+        if self
+            .current
+            .as_ref()
+            .is_some_and(|(current, _)| current == step)
+        {
+            self.current = None;
+        }
+
+        if !succeeded {
+            return;
+        }
+
+        if let Some((_, average, count)) =
+            self.estimates.iter_mut().find(|(name, _, _)| name == step)
+        {
+            *average = (*average * *count + duration) / (*count + 1);
+            *count += 1;
+        } else {
+            self.estimates.push((step.to_string(), duration, 1));
+        }
+    }
+
+    /// Return the estimated duration of the step, if the step was executed successfully before
+    pub fn estimate(&self, step: &str) -> Option<Duration> {
+        self.estimates
+            .iter()
+            .find(|(name, _, _)| name == step)
+            .map(|(_, average, _)| *average)
+    }
+}
+
+/// A step of the provisioning cycle of a PCB, in the order the steps are executed
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CycleStep {
+    /// The manual readouts (Device ID, PCB ID, Test JIG ID)
+    Readout,
+    /// The eFuse readouts
+    Efuse,
+    /// The loading and the preparation of the bundle
+    Prepare,
+    /// The flashing of the bundle images
+    Flash,
+    /// The burning of the bundle eFuses
+    EfuseBurn,
+    /// The running of the provisioned app
+    AppRun,
+    /// The upload of the PCB logs
+    Upload,
+}
+
+impl CycleStep {
+    /// All steps, in the order they are executed
+    pub const ALL: [Self; 7] = [
+        Self::Readout,
+        Self::Efuse,
+        Self::Prepare,
+        Self::Flash,
+        Self::EfuseBurn,
+        Self::AppRun,
+        Self::Upload,
+    ];
+
+    /// The name of the step, as presented to the operator
+    pub const fn name(&self) -> &'static str {
+        match self {
+            Self::Readout => "Readout",
+            Self::Efuse => "eFuse",
+            Self::Prepare => "Prepare",
+            Self::Flash => "Flash",
+            Self::EfuseBurn => "eFuse burn",
+            Self::AppRun => "App run",
+            Self::Upload => "Upload",
+        }
+    }
+}
+
+/// The status of a step of the provisioning cycle
+#[derive(Debug, Clone, Copy, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum CycleStepStatus {
+    /// The step is not executed yet
+    Pending,
+    /// The step is being executed
+    Active,
+    /// The step completed successfully
+    Done,
+    /// The step failed
+    Failed,
+    /// The step was not executed, as a later step was started
+    Skipped,
+}
+
+/// The progress of the provisioning cycle of a PCB: the status of each of its steps
+#[derive(Debug, Clone)]
+pub struct Cycle {
+    /// The steps of the cycle, in the order they are executed, with their statuses
+    pub steps: [(CycleStep, CycleStepStatus); 7],
+}
+
+impl Cycle {
+    /// Create a new cycle, with all steps pending
+    pub const fn new() -> Self {
+        Self {
+            steps: [
+                (CycleStep::Readout, CycleStepStatus::Pending),
+                (CycleStep::Efuse, CycleStepStatus::Pending),
+                (CycleStep::Prepare, CycleStepStatus::Pending),
+                (CycleStep::Flash, CycleStepStatus::Pending),
+                (CycleStep::EfuseBurn, CycleStepStatus::Pending),
+                (CycleStep::AppRun, CycleStepStatus::Pending),
+                (CycleStep::Upload, CycleStepStatus::Pending),
+            ],
+        }
+    }
+
+    /// Mark all steps as pending
+    ///
+    /// To be called when a new PCB is to be provisioned
+    pub fn reset(&mut self) {
+        *self = Self::new();
+    }
+
+    /// Return `true` if no step of the cycle was started yet
+    pub fn is_pending(&self) -> bool {
+        self.steps
+            .iter()
+            .all(|(_, status)| *status == CycleStepStatus::Pending)
+    }
+
+    /// Mark the step as being executed
+    ///
+    /// The earlier steps which were not executed are marked as skipped, and the later steps - which might have been
+    /// executed in a previous attempt, when the operator retries from an earlier step - are marked as pending again
+    pub fn start(&mut self, step: CycleStep) {
+        for (current, status) in &mut self.steps {
+            if *current == step {
+                *status = CycleStepStatus::Active;
+            } else if (*current as usize) < (step as usize) {
+                match status {
+                    CycleStepStatus::Pending => *status = CycleStepStatus::Skipped,
+                    CycleStepStatus::Active => *status = CycleStepStatus::Done,
+                    _ => (),
+                }
+            } else {
+                *status = CycleStepStatus::Pending;
+            }
+        }
+    }
+
+    /// Mark the step as completed
+    pub fn finish(&mut self, step: CycleStep) {
+        self.set(step, CycleStepStatus::Done);
+    }
+
+    /// Mark the step being executed (if any) as failed
+    pub fn fail(&mut self) {
+        for (_, status) in &mut self.steps {
+            if *status == CycleStepStatus::Active {
+                *status = CycleStepStatus::Failed;
+            }
+        }
+    }
+
+    fn set(&mut self, step: CycleStep, new_status: CycleStepStatus) {
+        if let Some((_, status)) = self.steps.iter_mut().find(|(current, _)| *current == step) {
+            *status = new_status;
+        }
+    }
+}
+
+impl Default for Cycle {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The progress of the work order the PCBs are provisioned against
+#[derive(Debug, Clone)]
+pub struct WorkOrderProgress {
+    /// The ID of the work order
+    pub id: String,
+    /// The quantity of the work order
+    pub quantity: u32,
+    /// The number of PCBs provisioned against the work order so far
+    pub provisioned: u32,
+}
+
+impl WorkOrderProgress {
+    /// Return the number of PCBs still to be provisioned
+    pub const fn remaining(&self) -> u32 {
+        self.quantity.saturating_sub(self.provisioned)
+    }
 }
 
 /// The state of the model
@@ -150,12 +463,18 @@ pub enum State {
     Readout(Readout),
     /// The model has prepared the bundle and is either waiting for user confirmation or provisioning it already
     Provision(Provision),
+    /// The model is presenting a preview of the prepared bundle and awaiting the operator to confirm its provisioning
+    Preview(Preview),
     /// The model is running the application and displaying the logs
     AppRun(AppLogs),
     /// The model is processing a task
     Processing(Processing),
     /// The model needs to present the outcome of a task (success or failure)
     Status(Status),
+    /// The model is presenting the detected serial ports and awaiting the operator to pick one
+    PortPick(PortPick),
+    /// The model is presenting the alternative partition tables of the bundle and awaiting the operator to pick one
+    PartTablePick(PartTablePick),
 }
 
 impl State {
@@ -164,14 +483,18 @@ impl State {
         Self::Processing(Processing::empty())
     }
 
-    pub fn success(&mut self, title: impl Into<String>, message: impl Into<String>) {
-        *self = Self::Status(Status::success(title, message));
-    }
-
     pub fn error(&mut self, title: impl Into<String>, message: impl Into<String>) {
         *self = Self::Status(Status::error(title, message));
     }
 
+    /// Set the state to the final result (PASS / FAIL) of the PCB provisioning (see `Status::result`)
+    pub fn result(&mut self, title: impl Into<String>, message: impl Into<String>, error: bool) {
+        let mut status = Status::new(title, message, error);
+        status.result = true;
+
+        *self = Self::Status(status);
+    }
+
     /// Get a reference to the readout from the state
     /// Panics if the state is not `Readout`
     pub fn readout(&self) -> &Readout {
@@ -232,6 +555,46 @@ impl State {
         }
     }
 
+    /// Get a reference to the port pick state
+    /// Panics if the state is not `PortPick`
+    pub fn port_pick(&self) -> &PortPick {
+        if let Self::PortPick(port_pick) = self {
+            port_pick
+        } else {
+            panic!("Unexpected state: {self:?}")
+        }
+    }
+
+    /// Get a mutable reference to the port pick state
+    /// Panics if the state is not `PortPick`
+    pub fn port_pick_mut(&mut self) -> &mut PortPick {
+        if let Self::PortPick(port_pick) = self {
+            port_pick
+        } else {
+            panic!("Unexpected state: {self:?}")
+        }
+    }
+
+    /// Get a reference to the partition table pick state
+    /// Panics if the state is not `PartTablePick`
+    pub fn part_table_pick(&self) -> &PartTablePick {
+        if let Self::PartTablePick(part_table_pick) = self {
+            part_table_pick
+        } else {
+            panic!("Unexpected state: {self:?}")
+        }
+    }
+
+    /// Get a mutable reference to the partition table pick state
+    /// Panics if the state is not `PartTablePick`
+    pub fn part_table_pick_mut(&mut self) -> &mut PartTablePick {
+        if let Self::PartTablePick(part_table_pick) = self {
+            part_table_pick
+        } else {
+            panic!("Unexpected state: {self:?}")
+        }
+    }
+
     /// Get a mutable reference to the status state
     /// Panics if the state is not `Status`
     pub fn status_mut(&mut self) -> &mut Status {
@@ -259,6 +622,8 @@ pub struct Readout {
     /// Used to indicate which readout is currently being input
     /// When all readouts are input, this is equal to the length of the `readouts` vector
     pub active: usize,
+    /// The reason the last value of the active readout was rejected by its validation rules, if it was
+    pub error: Option<String>,
 }
 
 impl Readout {
@@ -267,6 +632,7 @@ impl Readout {
         Self {
             readouts: Vec::new(),
             active: 0,
+            error: None,
         }
     }
 
@@ -282,10 +648,102 @@ impl Default for Readout {
     }
 }
 
+/// The state of the model when the operator is picking the serial port to be used for the session
+#[derive(Debug, Clone)]
+pub struct PortPick {
+    /// The detected serial ports
+    pub ports: Vec<PortDescription>,
+    /// The number of the port being picked, as input by the operator so far
+    pub selection: String,
+}
+
+impl PortPick {
+    /// Create a new `PortPick` state with the given ports
+    pub const fn new(ports: Vec<PortDescription>) -> Self {
+        Self {
+            ports,
+            selection: String::new(),
+        }
+    }
+
+    /// Return the index of the port selected by the operator so far, if the selection is valid
+    pub fn selected(&self) -> Option<usize> {
+        self.selection
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|number| (1..=self.ports.len()).contains(number))
+            .map(|number| number - 1)
+    }
+}
+
+/// The state of the model when the operator is picking an alternative partition table of the bundle,
+/// because the partition table of the bundle does not fit the flash of the chip
+#[derive(Debug, Clone)]
+pub struct PartTablePick {
+    /// Why the partition table of the bundle does not fit the flash of the chip
+    pub problem: String,
+    /// The alternative partition tables of the bundle
+    pub variants: Vec<PartTableDescription>,
+    /// The number of the partition table being picked, as input by the operator so far
+    pub selection: String,
+}
+
+impl PartTablePick {
+    /// Create a new `PartTablePick` state with the given problem and alternative partition tables
+    pub const fn new(problem: String, variants: Vec<PartTableDescription>) -> Self {
+        Self {
+            problem,
+            variants,
+            selection: String::new(),
+        }
+    }
+
+    /// Return the index of the partition table selected by the operator so far, if the selection is valid
+    pub fn selected(&self) -> Option<usize> {
+        self.selection
+            .trim()
+            .parse::<usize>()
+            .ok()
+            .filter(|number| (1..=self.variants.len()).contains(number))
+            .map(|number| number - 1)
+    }
+}
+
+/// An alternative partition table of the bundle, as presented to the operator
+#[derive(Debug, Clone)]
+pub struct PartTableDescription {
+    /// The variant of the partition table (i.e. `<variant>` in `partition-table-<variant>.csv`)
+    pub variant: String,
+    /// The names of the partitions, in partition table order
+    pub partitions: Vec<String>,
+    /// The offset at which the last partition ends
+    pub end: u32,
+    /// Whether the partition table fits the flash detected on the chip
+    pub fits: bool,
+}
+
+/// A serial port, as presented to the operator
+#[derive(Debug, Clone)]
+pub struct PortDescription {
+    /// The name of the port (e.g. `COM3` or `/dev/ttyUSB0`)
+    pub name: String,
+    /// The USB vendor ID of the adapter
+    pub vid: u16,
+    /// The USB product ID of the adapter
+    pub pid: u16,
+    /// The product string of the adapter, if any
+    pub product: Option<String>,
+    /// The serial number of the adapter, if any
+    pub serial_number: Option<String>,
+}
+
 /// The state of the model when the bundle is ready to be provisioned or in the process of being provisioned
 /// (i.e. flashed and efused)
 #[derive(Debug, Clone)]
 pub struct Provision {
+    /// The ID of the bundle, if the bundle was identified by an ID
+    pub bundle_id: Option<String>,
     /// The readouts (manual and eFuse IDs) to display
     /// Each readout is a tuple of the readout key and its stringified value
     pub readouts: Vec<(String, String)>,
@@ -295,6 +753,23 @@ pub struct Provision {
     pub provisioning: bool,
 }
 
+/// The state of the model when the operator is previewing the prepared bundle, before confirming its provisioning
+#[derive(Debug, Clone)]
+pub struct Preview {
+    /// The prepared bundle and the readouts, as they are going to be provisioned
+    pub provision: Provision,
+    /// The details of the provisioning (the parameters of the bundle, the flasher, the estimated duration, etc.),
+    /// as (name, value) pairs
+    pub details: Vec<(String, String)>,
+}
+
+impl Preview {
+    /// Create a new `Preview` state with the given provision state and details
+    pub const fn new(provision: Provision, details: Vec<(String, String)>) -> Self {
+        Self { provision, details }
+    }
+}
+
 /// The state of the model when processing a sub-task
 #[derive(Debug)]
 pub struct Processing {
@@ -332,22 +807,71 @@ pub struct AppLogs {
     pub buffer: VecDeque<Line<'static>>,
     /// The maximum number of log lines to keep in the buffer
     buffer_len: usize,
+    /// How the log lines are colored
+    colors: AppLogColors,
 }
 
 impl AppLogs {
-    pub fn new(buffer_len: usize) -> Self {
+    pub fn new(buffer_len: usize, colors: AppLogColors) -> Self {
         Self {
             buffer: VecDeque::new(),
             buffer_len,
+            colors,
         }
     }
 
-    pub fn append(&mut self, line: String) {
+    /// Append a log line of the app, as emitted by the app (i.e. with the ANSI escape sequences, if any)
+    pub fn append(&mut self, line: &str) {
         if self.buffer.len() >= self.buffer_len {
             self.buffer.pop_front();
         }
 
-        self.buffer.push_back(Line::raw(line));
+        let line = match self.colors {
+            AppLogColors::Plain => Line::raw(strip_ansi_escapes::strip_str(line)),
+            AppLogColors::Level => Self::level_colored(strip_ansi_escapes::strip_str(line)),
+            AppLogColors::Ansi => {
+                let ansi = line
+                    .as_bytes()
+                    .into_text()
+                    .ok()
+                    .and_then(|text| text.lines.into_iter().next())
+                    .filter(|ansi| ansi.spans.iter().any(|span| span.style != Style::new()));
+
+                ansi.unwrap_or_else(|| Self::level_colored(strip_ansi_escapes::strip_str(line)))
+            }
+        };
+
+        self.buffer.push_back(line);
+    }
+
+    /// Color the line by its ESP-IDF log level (e.g. `E (1234) wifi: ...`), if it has one
+    fn level_colored(line: String) -> Line<'static> {
+        let mut chars = line.chars();
+
+        let color = match chars.next() {
+            Some(level) if chars.as_str().starts_with(" (") => match level {
+                'E' => Some(Color::Red),
+                'W' => Some(Color::Yellow),
+                'I' => Some(Color::Green),
+                'D' => Some(Color::Blue),
+                'V' => Some(Color::Cyan),
+                _ => None,
+            },
+            _ => None,
+        };
+
+        let line = Line::raw(line);
+
+        if let Some(color) = color {
+            line.fg(color)
+        } else {
+            line
+        }
+    }
+
+    /// Get the buffered log lines as plain text, for frontends other than the terminal UI
+    pub fn text_lines(&self) -> impl Iterator<Item = String> + '_ {
+        self.buffer.iter().map(|line| line.to_string())
     }
 }
 
@@ -362,14 +886,23 @@ pub struct Status {
     pub message: String,
     /// Whether the status is an error
     pub error: bool,
+    /// An optional highlight of the status (e.g. when the cycle time budget is exceeded)
+    pub highlight: Option<Highlight>,
+    /// Whether the status is the final result (PASS / FAIL) of the PCB provisioning,
+    /// as presented on the result banner (see `Config::result_banner`)
+    pub result: bool,
 }
 
-impl Status {
-    /// Create a new "success" `Status` state with the given title and message
-    pub fn success(title: impl Into<String>, message: impl Into<String>) -> Self {
-        Self::new(title, message, false)
-    }
+/// The highlight of a status message
+#[derive(Debug, Copy, Clone, Eq, PartialEq, Hash)]
+pub enum Highlight {
+    /// Rendered in yellow
+    Warning,
+    /// Rendered in red
+    Critical,
+}
 
+impl Status {
     /// Create a new "error" `Status` state with the given title and message
     pub fn error(title: impl Into<String>, message: impl Into<String>) -> Self {
         Self::new(title, message, true)
@@ -381,6 +914,8 @@ impl Status {
             title: title.into(),
             message: message.into(),
             error,
+            highlight: None,
+            result: false,
         }
     }
 }
@@ -392,6 +927,14 @@ pub struct Logs {
     pub file: FileLogs,
     /// Buffered (on-screen) logs
     pub buffered: BufferedLogs,
+    /// Streamed logs (to the subscribers of the daemon API log stream)
+    pub streamed: StreamedLogs,
+    /// The audit log of the irreversible operations done on the PCB
+    pub audit: AuditLog,
+    /// The test report of the provisioning steps done on the PCB
+    pub report: Report,
+    /// Additional files (name and content) to be included in the PCB logs, like the eFuse summary snapshots
+    pub attachments: Vec<(String, String)>,
 }
 
 impl Logs {
@@ -425,6 +968,10 @@ impl Logs {
                 width,
                 height,
             ),
+            streamed: StreamedLogs::new(),
+            audit: AuditLog::new(),
+            report: Report::new(),
+            attachments: Vec::new(),
         }
     }
 
@@ -434,6 +981,9 @@ impl Logs {
     pub fn clear(&mut self) -> anyhow::Result<()> {
         self.file.start()?;
         self.buffered.clear();
+        self.audit.clear();
+        self.report.clear();
+        self.attachments.clear();
 
         Ok(())
     }
@@ -446,6 +996,51 @@ impl Logs {
     }
 }
 
+/// The streamed logs of the model, published to the subscribers of the daemon API log stream (if any)
+#[derive(Debug)]
+pub struct StreamedLogs(Vec<Sender<String>>);
+
+impl StreamedLogs {
+    /// Create a new `StreamedLogs` state without subscribers
+    pub const fn new() -> Self {
+        Self(Vec::new())
+    }
+
+    /// Subscribe to the log lines logged from now on
+    ///
+    /// The receiver is disconnected once the model is dropped
+    pub fn subscribe(&mut self) -> Receiver<String> {
+        let (sender, receiver) = mpsc::channel();
+
+        self.0.push(sender);
+
+        receiver
+    }
+
+    /// Publish a log record to the subscribers, forgetting those whose receiver is dropped
+    pub fn log(&mut self, record: &Record) {
+        if self.0.is_empty() {
+            return;
+        }
+
+        let line = format!(
+            "[{} {}] {}\n",
+            record.level(),
+            record.target(),
+            record.args()
+        );
+
+        self.0
+            .retain(|subscriber| subscriber.send(line.clone()).is_ok());
+    }
+}
+
+impl Default for StreamedLogs {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 /// The file logs of the model
 #[derive(Debug)]
 pub struct FileLogs {
@@ -457,6 +1052,17 @@ pub struct FileLogs {
     file: Option<File>,
     /// The optional console logger in case the interactive UI is disabled
     console: Option<env_logger::Logger>,
+    /// The directory where the logs are saved on demand; the current directory if not set
+    save_dir: Option<PathBuf>,
+    /// The maximum size of the log file in bytes; 0 means no limit
+    ///
+    /// The first half of the log (the head) is kept in the log file, while the second half is
+    /// a rotated tail of the log (see `LogTail`)
+    max_size: u64,
+    /// The number of bytes written to the head of the log so far
+    written: u64,
+    /// The rotated tail of the log, once the head is full
+    tail: Option<LogTail>,
 }
 
 impl FileLogs {
@@ -470,7 +1076,60 @@ impl FileLogs {
             no_ui,
             file: None,
             console: None,
+            save_dir: None,
+            max_size: 0,
+            written: 0,
+            tail: None,
+        }
+    }
+
+    /// Set the directory where the logs are saved on demand (see `FileLogs::save`)
+    pub fn set_save_dir(&mut self, save_dir: Option<PathBuf>) {
+        self.save_dir = save_dir;
+    }
+
+    /// Set the maximum size of the log file in bytes; 0 means no limit
+    ///
+    /// Once exceeded, the head and the tail of the log are kept and the middle is truncated
+    pub fn set_max_size(&mut self, max_size: u64) {
+        self.max_size = max_size;
+    }
+
+    /// Save a copy of the logs written so far into a timestamped file in the save directory,
+    /// and return the path of the file
+    ///
+    /// The logs keep being written to the log file afterwards
+    pub fn save(&mut self) -> anyhow::Result<PathBuf> {
+        let log = self.file.as_mut().context("No PCB is being provisioned")?;
+
+        let dir = self.save_dir.clone().unwrap_or_default();
+        if !dir.as_os_str().is_empty() {
+            std::fs::create_dir_all(&dir).context("Creating the logs save directory failed")?;
         }
+
+        let path = dir.join(format!(
+            "espfactory-log-{}.txt",
+            chrono::Local::now().format("%Y%m%d-%H%M%S")
+        ));
+
+        let mut file = File::create(&path)
+            .with_context(|| format!("Creating the log file `{}` failed", path.display()))?;
+
+        log.flush()?;
+        log.seek(SeekFrom::Start(0))?;
+
+        // Continue appending to the end of the log, even if the copying failed
+        let result = std::io::copy(log, &mut file);
+        log.seek(SeekFrom::End(0))?;
+
+        result.with_context(|| format!("Saving the log to `{}` failed", path.display()))?;
+
+        if let Some(tail) = self.tail.as_mut() {
+            tail.copy_to(&mut file)
+                .with_context(|| format!("Saving the log to `{}` failed", path.display()))?;
+        }
+
+        Ok(path)
     }
 
     /// Start the file logs
@@ -478,6 +1137,8 @@ impl FileLogs {
         let log = tempfile()?;
 
         self.file = Some(log);
+        self.written = 0;
+        self.tail = None;
 
         if self.no_ui && self.console.is_none() {
             self.console = Some(
@@ -493,15 +1154,31 @@ impl FileLogs {
     }
 
     /// Take the file of the file logs, if any
+    ///
+    /// If the log was truncated, the file contains the head of the log, a truncation note and the tail of the log
     pub fn grab(&mut self) -> Option<File> {
-        self.file.take()
+        let mut log = self.file.take()?;
+
+        if let Some(mut tail) = self.tail.take() {
+            // Nowhere to log the failure from within the logger, and the head of the log is still worth uploading
+            let _ = tail.copy_to(&mut log);
+        }
+
+        self.written = 0;
+
+        Some(log)
     }
 
     /// Utility to finish the file logs
     ///
     /// Finishing the file logs means flushing the logs to the file and creating
-    /// a ZIP file with the logs and a small summary csv
-    pub fn finish<'i, I, S>(mut log: File, summary: I) -> anyhow::Result<impl Read + Seek>
+    /// a ZIP file with the logs, a small summary csv and the additional files (e.g. the audit log
+    /// and the test reports), where the empty ones are skipped
+    pub fn finish<'i, I, S>(
+        mut log: File,
+        summary: I,
+        files: &[(&str, String)],
+    ) -> anyhow::Result<impl Read + Seek>
     where
         I: IntoIterator<Item = &'i (S, S)>,
         S: AsRef<str> + 'i,
@@ -533,6 +1210,14 @@ impl FileLogs {
         csv.flush()?;
 
         drop(csv);
+
+        for (name, content) in files {
+            if !content.is_empty() {
+                log_zip.start_file(*name, FileOptions::<()>::default())?;
+                log_zip.write_all(content.as_bytes())?;
+            }
+        }
+
         drop(log_zip);
 
         log_zip_file.flush()?;
@@ -546,17 +1231,16 @@ impl FileLogs {
         let mut logged = false;
 
         if self.level >= record.level() {
-            if let Some(out) = self.file.as_mut() {
+            if self.file.is_some() {
                 let message = format!(
-                    "[{} {} {}] {}",
+                    "[{} {} {}] {}\n",
                     record.level(),
-                    chrono::Utc::now().format("%Y-%m-%dT%H:%M:%SZ"),
+                    Locale::ISO.format_now(),
                     record.target(),
                     record.args()
                 );
 
-                let _ = out.write_all(message.as_bytes());
-                let _ = out.write_all(b"\n");
+                let _ = self.write(message.as_bytes());
 
                 logged |= true;
             }
@@ -576,6 +1260,116 @@ impl FileLogs {
         if let Some(out) = self.file.as_mut() {
             let _ = out.flush();
         }
+
+        if let Some(tail) = self.tail.as_mut() {
+            tail.flush();
+        }
+    }
+
+    /// Write a line to the head of the log, or - once the head is full - to the tail of the log
+    fn write(&mut self, line: &[u8]) -> std::io::Result<()> {
+        let Some(out) = self.file.as_mut() else {
+            return Ok(());
+        };
+
+        let len = line.len() as u64;
+
+        if self.max_size == 0 || (self.tail.is_none() && self.written + len <= self.max_size / 2) {
+            self.written += len;
+
+            return out.write_all(line);
+        }
+
+        let tail = match self.tail.as_mut() {
+            Some(tail) => tail,
+            None => self.tail.insert(LogTail::new()?),
+        };
+
+        // The tail is rotated between two files, each up to a quarter of the maximum size
+        tail.write(line, self.max_size / 4)
+    }
+}
+
+/// The tail of a log which exceeded its maximum size
+///
+/// Written to two files in turn, so that only the most recent lines are kept:
+/// once the current file is full, the other - older - file is truncated and becomes the current one
+#[derive(Debug)]
+struct LogTail {
+    /// The two files of the tail
+    files: [File; 2],
+    /// The index of the file being written to
+    current: usize,
+    /// The number of bytes written to the current file
+    written: u64,
+    /// The number of bytes of the log dropped by the rotation so far
+    truncated: u64,
+}
+
+impl LogTail {
+    /// Create a new, empty tail
+    fn new() -> std::io::Result<Self> {
+        Ok(Self {
+            files: [tempfile()?, tempfile()?],
+            current: 0,
+            written: 0,
+            truncated: 0,
+        })
+    }
+
+    /// Write a line to the current file, rotating the files if the current one is full
+    fn write(&mut self, line: &[u8], file_size: u64) -> std::io::Result<()> {
+        let len = line.len() as u64;
+
+        if self.written > 0 && self.written + len > file_size {
+            self.current = 1 - self.current;
+
+            let file = &mut self.files[self.current];
+
+            self.truncated += file.seek(SeekFrom::End(0))?;
+
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+
+            self.written = 0;
+        }
+
+        self.written += len;
+
+        self.files[self.current].write_all(line)
+    }
+
+    /// Copy the tail to the output, preceded by a note how much of the log was truncated, if any
+    fn copy_to<W: std::io::Write>(&mut self, out: &mut W) -> std::io::Result<()> {
+        if self.truncated > 0 {
+            writeln!(
+                out,
+                "[... {} bytes of the log truncated, as the log exceeded `logs_max_size_kb` ...]",
+                self.truncated
+            )?;
+        }
+
+        for index in [1 - self.current, self.current] {
+            let file = &mut self.files[index];
+
+            file.flush()?;
+            file.seek(SeekFrom::Start(0))?;
+
+            // Continue appending to the end of the file, even if the copying failed
+            let result = std::io::copy(file, out);
+            file.seek(SeekFrom::End(0))?;
+
+            result?;
+        }
+
+        Ok(())
+    }
+
+    /// Flush the files of the tail
+    fn flush(&mut self) {
+        for file in &mut self.files {
+            let _ = file.flush();
+        }
     }
 }
 
@@ -637,11 +1431,26 @@ pub struct BufferedLogs {
     /// The number of log lines sent to the on-screen logs buffer from the beginning of the program
     /// (used to number the log lines)
     count: usize,
-    /// The buffer of the on-screen logs. Keeps the last N log lines
+    /// The buffer of the on-screen logs, with the log level of each line. Keeps the last N log lines
     /// Uses `ratatui::Line` directly in the model for performance reasons
-    buffer: VecDeque<Line<'static>>,
+    buffer: VecDeque<(log::Level, Line<'static>)>,
     /// The maximum number of log lines to keep in the buffer
     buffer_len: usize,
+    /// The search in the on-screen logs, if any
+    search: Option<LogSearch>,
+    /// Whether only the warning and error log lines are displayed
+    filter: bool,
+}
+
+/// A search in the on-screen logs
+#[derive(Clone, Debug, Default)]
+pub struct LogSearch {
+    /// The searched (case-insensitive) text
+    pub pattern: String,
+    /// Whether the pattern is still being typed by the user
+    pub editing: bool,
+    /// The index in the logs buffer of the line with the current match, if any
+    current: Option<usize>,
 }
 
 impl BufferedLogs {
@@ -668,6 +1477,8 @@ impl BufferedLogs {
             wrap: true,
             buffer: VecDeque::new(),
             buffer_len,
+            search: None,
+            filter: false,
         }
     }
 
@@ -723,11 +1534,14 @@ impl BufferedLogs {
             let mut iter = lines.lines();
 
             if let Some(first) = iter.next() {
-                self.push(Line::from(vec![no, level, first.to_string().into()]));
+                self.push(
+                    record.level(),
+                    Line::from(vec![no, level, first.to_string().into()]),
+                );
             }
 
             for line in iter {
-                self.push(Line::from(vec![line.to_string().into()]));
+                self.push(record.level(), Line::from(vec![line.to_string().into()]));
             }
 
             !matches!(self.layout, BufferedLogsLayout::Hidden)
@@ -737,15 +1551,26 @@ impl BufferedLogs {
     }
 
     /// Push a log line to the on-screen logs buffer, removing the oldest line if necessary
-    fn push(&mut self, line: Line<'static>) {
+    fn push(&mut self, level: log::Level, line: Line<'static>) {
         if self.buffer.len() >= self.buffer_len {
             self.buffer.pop_front();
+
+            if let Some(search) = self.search.as_mut() {
+                search.current = search.current.and_then(|current| current.checked_sub(1));
+            }
         }
 
-        self.buffer.push_back(line);
+        self.buffer.push_back((level, line));
         self.count += 1;
     }
 
+    /// Get the buffered log lines as plain text with their log level, for frontends other than the terminal UI
+    pub fn text_lines(&self) -> impl Iterator<Item = (log::Level, String)> + '_ {
+        self.buffer
+            .iter()
+            .map(|(level, line)| (*level, line.to_string()))
+    }
+
     /// Clear the on-screen logs buffer
     pub fn clear(&mut self) {
         self.viewport.x = 0;
@@ -753,6 +1578,219 @@ impl BufferedLogs {
 
         self.buffer.clear();
         self.count = 0;
+
+        if let Some(search) = self.search.as_mut() {
+            search.current = None;
+        }
+    }
+
+    /// Get the search in the on-screen logs, if any
+    pub fn search(&self) -> Option<&LogSearch> {
+        self.search.as_ref()
+    }
+
+    /// Return `true` if a search pattern is being typed
+    pub fn is_search_editing(&self) -> bool {
+        self.search.as_ref().is_some_and(|search| search.editing)
+    }
+
+    /// Start typing a new search pattern
+    pub fn search_start(&mut self) {
+        self.search = Some(LogSearch {
+            editing: true,
+            ..Default::default()
+        });
+    }
+
+    /// Append a character to or - with `None` - remove the last character from the search pattern being typed
+    pub fn search_edit(&mut self, ch: Option<char>) {
+        if let Some(search) = self.search.as_mut().filter(|search| search.editing) {
+            if let Some(ch) = ch {
+                search.pattern.push(ch);
+            } else {
+                search.pattern.pop();
+            }
+        }
+    }
+
+    /// Complete typing the search pattern and move to the last match (i.e. the most recent log line)
+    ///
+    /// An empty pattern ends the search
+    pub fn search_done(&mut self) {
+        if let Some(search) = self.search.as_mut() {
+            search.editing = false;
+
+            if search.pattern.is_empty() {
+                self.search = None;
+            } else {
+                search.current = None;
+                self.search_next(false);
+            }
+        }
+    }
+
+    /// End the search
+    pub fn search_cancel(&mut self) {
+        self.search = None;
+    }
+
+    /// Move to the next match downwards or - with `up` - upwards, wrapping around at the end of the logs
+    pub fn search_next(&mut self, up: bool) {
+        let Some(search) = self.search.as_ref().filter(|search| !search.editing) else {
+            return;
+        };
+
+        let pattern = search.pattern.to_ascii_lowercase();
+
+        let matches = self
+            .visible()
+            .filter(|(_, line)| Self::line_text(line).contains(&pattern))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        let next = match (search.current, up) {
+            (Some(current), false) => matches
+                .iter()
+                .find(|index| **index > current)
+                .or(matches.first()),
+            (Some(current), true) => matches
+                .iter()
+                .rev()
+                .find(|index| **index < current)
+                .or(matches.last()),
+            (None, _) => matches.last(),
+        }
+        .copied();
+
+        if let Some(search) = self.search.as_mut() {
+            search.current = next;
+        }
+
+        if let Some(next) = next {
+            self.scroll_to(next);
+        }
+    }
+
+    /// Return the number of the current match and the total number of matches
+    pub fn search_matches(&self) -> (Option<usize>, usize) {
+        let Some(search) = self
+            .search
+            .as_ref()
+            .filter(|search| !search.pattern.is_empty())
+        else {
+            return (None, 0);
+        };
+
+        let pattern = search.pattern.to_ascii_lowercase();
+
+        let matches = self
+            .visible()
+            .filter(|(_, line)| Self::line_text(line).contains(&pattern))
+            .map(|(index, _)| index)
+            .collect::<Vec<_>>();
+
+        let current = search
+            .current
+            .and_then(|current| matches.iter().position(|index| *index == current))
+            .map(|position| position + 1);
+
+        (current, matches.len())
+    }
+
+    /// Return `true` if only the warning and error log lines are displayed
+    pub const fn is_filter(&self) -> bool {
+        self.filter
+    }
+
+    /// Toggle displaying only the warning and error log lines
+    pub fn toggle_filter(&mut self) {
+        self.filter = !self.filter;
+        self.viewport.x = 0;
+
+        if let Some(search) = self.search.as_mut() {
+            search.current = None;
+        }
+
+        self.home_end_y(false);
+    }
+
+    /// Scroll the viewport so that the line with the given index in the logs buffer is in the middle of the screen
+    fn scroll_to(&mut self, index: usize) {
+        let lines = self
+            .visible()
+            .take_while(|(visible_index, _)| *visible_index < index)
+            .map(|(_, line)| line.clone())
+            .collect::<Vec<_>>();
+
+        let mut para = Paragraph::new(Text::from_iter(lines));
+
+        if self.wrap {
+            para = para.wrap(Wrap { trim: false });
+        }
+
+        let y = para.line_count(self.viewport.width) as u32;
+        let y = y.saturating_sub(self.viewport.height as u32 / 2);
+
+        self.viewport.y = y.min(self.max_y()) as _;
+    }
+
+    /// Return the lines of the logs buffer which are displayed (as per the filter), with their indexes in the buffer
+    fn visible(&self) -> impl Iterator<Item = (usize, &Line<'static>)> {
+        self.buffer
+            .iter()
+            .enumerate()
+            .filter(|(_, (level, _))| !self.filter || *level <= log::Level::Warn)
+            .map(|(index, (_, line))| (index, line))
+    }
+
+    /// Return the lowercase text of a log line, for searching
+    fn line_text(line: &Line) -> String {
+        line.spans
+            .iter()
+            .map(|span| span.content.to_ascii_lowercase())
+            .collect()
+    }
+
+    /// Return the log line with the matches of the search pattern highlighted
+    fn highlight(line: &Line<'static>, pattern: &str, current: bool) -> Line<'static> {
+        let mut spans = Vec::new();
+
+        for span in &line.spans {
+            let content = span.content.as_ref();
+            let lower = content.to_ascii_lowercase();
+
+            let mut offset = 0;
+
+            for (start, _) in lower.match_indices(pattern) {
+                if start < offset {
+                    continue;
+                }
+
+                if start > offset {
+                    spans.push(Span::styled(content[offset..start].to_string(), span.style));
+                }
+
+                let matched = Span::styled(
+                    content[start..start + pattern.len()].to_string(),
+                    span.style,
+                )
+                .black();
+
+                spans.push(if current {
+                    matched.on_light_red()
+                } else {
+                    matched.on_yellow()
+                });
+
+                offset = start + pattern.len();
+            }
+
+            if offset < content.len() {
+                spans.push(Span::styled(content[offset..].to_string(), span.style));
+            }
+        }
+
+        Line::from(spans).style(line.style)
     }
 
     /// Move the viewport to the beginning or the end of the on-screen logs by the X axis
@@ -817,7 +1855,20 @@ impl BufferedLogs {
 
     /// Get the on-screen logs as a `Paragraph` widget, ready for rendering
     pub fn para(&self, scroll: bool, last_n_height: u16) -> Paragraph<'static> {
-        let mut para = Paragraph::new(Text::from_iter(self.buffer.iter().cloned()));
+        let pattern = self
+            .search
+            .as_ref()
+            .filter(|search| !search.pattern.is_empty())
+            .map(|search| (search.pattern.to_ascii_lowercase(), search.current));
+
+        let lines = self.visible().map(|(index, line)| match &pattern {
+            Some((pattern, current)) if Self::line_text(line).contains(pattern) => {
+                Self::highlight(line, pattern, *current == Some(index))
+            }
+            _ => line.clone(),
+        });
+
+        let mut para = Paragraph::new(Text::from_iter(lines));
 
         if self.wrap {
             para = para.wrap(Wrap { trim: false });
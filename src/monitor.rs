@@ -2,22 +2,28 @@
 
 use std::io::{ErrorKind, Read, Write};
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Arc;
+use std::sync::{mpsc, Arc};
 use std::time::Duration;
 
-use anyhow::Context;
 use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
 
 use log::{debug, error};
 //#[cfg(feature = "serialport")]
 use serialport::{FlowControl, SerialPort};
 
+use espflash::cli::monitor::parser::esp_defmt::EspDefmt;
 use espflash::cli::monitor::parser::{serial::Serial, InputParser, ResolvingPrinter};
 use espflash::cli::monitor::LogFormat;
 
 use crate::flash::get_serial_port_info;
+use crate::permissions::serial_open_error;
+use crate::remote::{self, RemotePort};
 
 /// Open a serial monitor on the given serial port.
+///
+/// If `input` is provided, the data received on it is sent to the serial port as it arrives
+/// (e.g. commands to the app)
+#[allow(clippy::too_many_arguments)]
 pub fn monitor<W>(
     port: Option<&str>,
     elf: Option<&[u8]>,
@@ -25,6 +31,7 @@ pub fn monitor<W>(
     log_format: LogFormat,
     raw: bool,
     stop: Arc<AtomicBool>,
+    input: Option<mpsc::Receiver<Vec<u8>>>,
     out: W,
 ) -> anyhow::Result<()>
 where
@@ -32,37 +39,62 @@ where
 {
     debug!("Opening serial monitor with baudrate: {}", baud);
 
-    let port_info = get_serial_port_info(port)?;
+    let mut serial: Box<dyn MonitorPort> =
+        if let Some(url) = port.filter(|port| remote::is_remote(Some(port))) {
+            let mut serial = RemotePort::open(url, baud)?;
+            serial.set_timeout(Duration::from_millis(5))?;
 
-    let mut serial = serialport::new(port_info.port_name, baud)
-        .flow_control(FlowControl::None)
-        .open_native()
-        .context("Opening serial port failed")?;
+            Box::new(serial)
+        } else {
+            let port_info = get_serial_port_info(port)?;
 
-    // Explicitly set the baud rate when starting the serial monitor, to allow using
-    // different rates for flashing.
-    serial.set_baud_rate(baud)?;
-    serial.set_timeout(Duration::from_millis(5))?;
+            let mut serial = serialport::new(&port_info.port_name, baud)
+                .flow_control(FlowControl::None)
+                .open_native()
+                .map_err(|err| serial_open_error(&port_info, err))?;
+
+            // Explicitly set the baud rate when starting the serial monitor, to allow using
+            // different rates for flashing.
+            serial.set_baud_rate(baud)?;
+            serial.set_timeout(Duration::from_millis(5))?;
+
+            Box::new(serial)
+        };
 
     // We are in raw mode until `_raw_mode` is dropped (ie. this function returns).
     let _raw_mode = RawModeGuard::new(raw)?;
 
-    let mut out = ResolvingPrinter::new(elf, out);
-
     let mut parser: Box<dyn InputParser> = match log_format {
-        LogFormat::Defmt => panic!("Defmt not supported yet"),
+        LogFormat::Defmt => {
+            if elf.is_none() {
+                anyhow::bail!("Decoding `defmt` logs requires the ELF file of the app");
+            }
+
+            Box::new(EspDefmt::new(elf, None)?)
+        }
         _ => Box::new(Serial),
     };
 
+    // With espflash 4, the addresses are resolved against any of the provided ELF files
+    let mut out = ResolvingPrinter::new(elf.into_iter().collect(), out);
+
     // let mut external_processors =
     //     ExternalProcessors::new(monitor_args.processors, monitor_args.elf)?;
 
     let mut buf = [0; 1024];
 
     while !stop.load(Ordering::SeqCst) {
+        if let Some(input) = input.as_ref() {
+            while let Ok(data) = input.try_recv() {
+                serial.write_all(&data)?;
+                serial.flush()?;
+            }
+        }
+
         let read_count = match serial.read(&mut buf) {
             Ok(count) => Ok(count),
-            Err(e) if e.kind() == ErrorKind::TimedOut => Ok(0),
+            // Networked ports report an expired read timeout as `WouldBlock` on some platforms
+            Err(e) if matches!(e.kind(), ErrorKind::TimedOut | ErrorKind::WouldBlock) => Ok(0),
             Err(e) if e.kind() == ErrorKind::Interrupted => continue,
             err => err,
         }?;
@@ -76,6 +108,11 @@ where
     Ok(())
 }
 
+/// A local or a networked serial port the monitor is talking to
+trait MonitorPort: Read + Write {}
+
+impl<T> MonitorPort for T where T: Read + Write {}
+
 /// Type that ensures that raw mode is disabled when dropped.
 struct RawModeGuard(bool);
 
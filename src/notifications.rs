@@ -0,0 +1,92 @@
+//! Webhook notifications for the production supervisors, when the station stalls
+//!
+//! The notifications are driven by the provisioning events (see `events`) and are POSTed on a separate thread,
+//! so that a slow or unreachable webhook never delays the provisioning
+
+use core::time::Duration;
+
+use std::thread;
+
+use anyhow::Context;
+
+use log::{info, warn};
+
+use crate::events::{Events, ProvisioningEvent};
+use crate::Notifications;
+
+/// The timeout of posting a notification to the webhook
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Start notifying the webhook on the configured events, until the events of the run are dropped
+pub(crate) fn start(conf: &Notifications, station: &str, events: &Events) -> anyhow::Result<()> {
+    let conf = conf.clone();
+    let station = station.to_string();
+    let events = events.subscribe();
+
+    thread::Builder::new()
+        .name("notifications".to_string())
+        .spawn(move || {
+            // The number of consecutive failed PCBs, and the last error of the current PCB, if it failed so far
+            //
+            // A PCB only counts as failed once the next one is started, as the operator might retry
+            // the failed step (possibly several times) and eventually provision it
+            let mut failures = 0;
+            let mut failed = None;
+
+            for event in events {
+                let text = match event {
+                    ProvisioningEvent::PcbStarted => {
+                        failed.take().and_then(|last_error| {
+                            failures += 1;
+
+                            (failures == conf.consecutive_failures).then(|| {
+                                format!(
+                                    "Station `{station}`: {failures} consecutive failed PCBs, the last error: {last_error}"
+                                )
+                            })
+                        })
+                    }
+                    ProvisioningEvent::Provisioned { .. } => {
+                        failures = 0;
+                        failed = None;
+                        None
+                    }
+                    ProvisioningEvent::Error { title, message } => {
+                        failed = Some(format!("{title}: {message}"));
+                        None
+                    }
+                    ProvisioningEvent::BundlePoolEmpty { message } if conf.pool_empty => {
+                        Some(format!("Station `{station}`: the bundle pool is empty: {message}"))
+                    }
+                    ProvisioningEvent::UploadFailed { message } if conf.upload_errors => Some(
+                        format!("Station `{station}`: uploading the PCB logs failed: {message}"),
+                    ),
+                    _ => None,
+                };
+
+                if let Some(text) = text {
+                    match notify(&conf.webhook, &text) {
+                        Ok(()) => info!("Notified the webhook: {text}"),
+                        Err(err) => warn!("Notifying the webhook failed: {err:#}"),
+                    }
+                }
+            }
+        })
+        .context("Starting the notifications thread failed")?;
+
+    Ok(())
+}
+
+/// POST the text to the webhook as a Slack-compatible message
+fn notify(webhook: &str, text: &str) -> anyhow::Result<()> {
+    reqwest::blocking::Client::new()
+        .post(webhook)
+        .timeout(TIMEOUT)
+        .header(reqwest::header::CONTENT_TYPE, "application/json")
+        .body(serde_json::json!({ "text": text }).to_string())
+        .send()
+        .and_then(|response| response.error_for_status())
+        .context("Posting the notification failed")?;
+
+    Ok(())
+}
@@ -0,0 +1,57 @@
+//! Per-device NVS encryption keys
+//!
+//! The keys are randomly generated for each PCB and flashed into the `nvs_keys` partition, in the format
+//! expected by the ESP-IDF NVS encryption (`nvs_flash_read_security_cfg`)
+
+use anyhow::Context;
+
+use zeroize::Zeroizing;
+
+use crate::utils::hash::crc32_le;
+use crate::utils::hex;
+
+/// The size of each of the two keys (the XTS encryption key and the XTS tweak key)
+const KEY_SIZE: usize = 32;
+
+/// The size of the `nvs_keys` partition content; the rest of the partition is left erased
+const PARTITION_SIZE: usize = 4096;
+
+/// Randomly generated NVS encryption keys, zeroized once dropped
+pub struct Keys {
+    /// The XTS encryption key followed by the XTS tweak key
+    keys: Zeroizing<[u8; KEY_SIZE * 2]>,
+}
+
+impl Keys {
+    /// Generate new random keys
+    pub fn generate() -> anyhow::Result<Self> {
+        let mut keys = Zeroizing::new([0; KEY_SIZE * 2]);
+
+        getrandom::getrandom(keys.as_mut())
+            .map_err(|err| anyhow::anyhow!("{err}"))
+            .context("Generating the NVS encryption keys failed")?;
+
+        Ok(Self { keys })
+    }
+
+    /// Return the keys (the encryption key followed by the tweak key)
+    pub fn as_bytes(&self) -> &[u8] {
+        self.keys.as_ref()
+    }
+
+    /// Return the keys (the encryption key followed by the tweak key) as a lowercase hex string
+    pub fn to_hex(&self) -> String {
+        hex::encode(&*self.keys)
+    }
+
+    /// Return the content of the `nvs_keys` partition: the two keys, followed by their CRC32
+    pub fn partition_image(&self) -> Vec<u8> {
+        let mut image = vec![0xff; PARTITION_SIZE];
+
+        image[..self.keys.len()].copy_from_slice(self.keys.as_ref());
+        image[self.keys.len()..self.keys.len() + 4]
+            .copy_from_slice(&crc32_le(u32::MAX, self.keys.as_ref()).to_le_bytes());
+
+        image
+    }
+}
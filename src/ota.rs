@@ -0,0 +1,206 @@
+//! Verification of the app slot activated by an on-device OTA update
+//!
+//! Some products receive their production firmware via an OTA update done by the factory app during the app run.
+//! The verification reads back the `otadata` partition to find out which OTA app slot the bootloader would boot,
+//! and the app image in that slot to confirm that the update completed.
+//!
+//! Conversely, the `otadata` partition can be generated upfront with a chosen boot slot (see `Config::otadata_boot`).
+
+use anyhow::Context;
+
+use crate::utils::hash::{crc32_le, sha256_hex};
+use crate::utils::hex;
+
+/// The size of one `otadata` entry (`esp_ota_select_entry_t`)
+const OTADATA_ENTRY_SIZE: usize = 32;
+/// The `otadata` partition contains two entries, one per flash sector
+const OTADATA_SECTOR_SIZE: usize = 0x1000;
+
+/// `ESP_OTA_IMG_UNDEFINED`
+const OTA_IMG_UNDEFINED: u32 = u32::MAX;
+/// `ESP_OTA_IMG_INVALID`
+const OTA_IMG_INVALID: u32 = 3;
+/// `ESP_OTA_IMG_ABORTED`
+const OTA_IMG_ABORTED: u32 = 4;
+
+/// The magic byte of an ESP-IDF app image
+const IMAGE_MAGIC: u8 = 0xe9;
+/// The size of the app image header (`esp_image_header_t`)
+const IMAGE_HEADER_SIZE: usize = 24;
+/// The size of an app image segment header (`esp_image_segment_header_t`)
+const IMAGE_SEGMENT_HEADER_SIZE: usize = 8;
+
+/// Return the index of the OTA app slot the bootloader would boot, as per the content of the `otadata` partition
+///
+/// Returns `None` if `otadata` contains no valid entry, in which case the bootloader boots the factory app
+///
+/// # Arguments
+/// - `otadata` - the content of the `otadata` partition
+/// - `slots` - the number of OTA app slots in the partition table
+pub fn active_slot(otadata: &[u8], slots: usize) -> Option<usize> {
+    if slots == 0 {
+        return None;
+    }
+
+    (0..2)
+        .filter_map(|index| {
+            let offset = index * OTADATA_SECTOR_SIZE;
+
+            otadata.get(offset..offset + OTADATA_ENTRY_SIZE)
+        })
+        .filter_map(|entry| {
+            let seq = u32::from_le_bytes(entry[0..4].try_into().unwrap());
+            let state = u32::from_le_bytes(entry[24..28].try_into().unwrap());
+            let crc = u32::from_le_bytes(entry[28..32].try_into().unwrap());
+
+            (seq != u32::MAX
+                && crc == crc32_le(u32::MAX, &entry[0..4])
+                && state != OTA_IMG_INVALID
+                && state != OTA_IMG_ABORTED)
+                .then_some(seq)
+        })
+        .max()
+        .map(|seq| (seq.wrapping_sub(1) % slots as u32) as usize)
+}
+
+/// Generate the content of the `otadata` partition, selecting the given OTA app slot for booting
+///
+/// With no slot, both entries are left blank, in which case the bootloader boots the factory app
+///
+/// # Arguments
+/// - `size` - the size of the `otadata` partition
+/// - `slot` - the index of the OTA app slot to be booted, if any
+pub fn otadata_image(size: usize, slot: Option<usize>) -> Vec<u8> {
+    let mut otadata = vec![0xff; size];
+
+    if let Some(slot) = slot {
+        // The bootloader boots slot `(seq - 1) % slots`, so the sequence number of the first OTA update
+        // to the slot is used; the second entry is left blank
+        let seq = slot as u32 + 1;
+
+        let entry = &mut otadata[..OTADATA_ENTRY_SIZE];
+        entry[0..4].copy_from_slice(&seq.to_le_bytes());
+        entry[24..28].copy_from_slice(&OTA_IMG_UNDEFINED.to_le_bytes());
+        entry[28..32].copy_from_slice(&crc32_le(u32::MAX, &seq.to_le_bytes()).to_le_bytes());
+    }
+
+    otadata
+}
+
+/// Compute the SHA-256 of the app image at the start of the given partition content,
+/// i.e. the "Validation Hash" of the image as computed by the bootloader
+///
+/// If the image has an appended hash, the computed hash is checked against it
+pub fn image_hash(data: &[u8]) -> anyhow::Result<String> {
+    let header = data
+        .get(..IMAGE_HEADER_SIZE)
+        .context("App image is truncated")?;
+
+    if header[0] != IMAGE_MAGIC {
+        anyhow::bail!(
+            "No valid app image found (magic byte 0x{:02x}); is the flash encrypted?",
+            header[0]
+        );
+    }
+
+    let segments = header[1] as usize;
+    let hash_appended = header[23] == 1;
+
+    let mut len = IMAGE_HEADER_SIZE;
+
+    for _ in 0..segments {
+        let segment_header = data
+            .get(len..len + IMAGE_SEGMENT_HEADER_SIZE)
+            .context("App image is truncated")?;
+
+        let segment_len = u32::from_le_bytes(segment_header[4..8].try_into().unwrap()) as usize;
+
+        len += IMAGE_SEGMENT_HEADER_SIZE + segment_len;
+    }
+
+    // The checksum byte is placed at the end of a 16-byte aligned block
+    len = (len + 1).next_multiple_of(16);
+
+    let image = data.get(..len).context("App image is truncated")?;
+    let hash = sha256_hex(image);
+
+    if hash_appended {
+        let appended = hex::encode(data.get(len..len + 32).context("App image is truncated")?);
+
+        if appended != hash {
+            anyhow::bail!("App image is corrupted: computed hash {hash}, appended hash {appended}");
+        }
+    }
+
+    Ok(hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OTADATA_SIZE: usize = 2 * OTADATA_SECTOR_SIZE;
+
+    /// Write an `otadata` entry with the given sequence number and state into the given sector
+    fn write_entry(otadata: &mut [u8], sector: usize, seq: u32, state: u32) {
+        let entry = &mut otadata[sector * OTADATA_SECTOR_SIZE..][..OTADATA_ENTRY_SIZE];
+
+        entry[0..4].copy_from_slice(&seq.to_le_bytes());
+        entry[24..28].copy_from_slice(&state.to_le_bytes());
+        entry[28..32].copy_from_slice(&crc32_le(u32::MAX, &seq.to_le_bytes()).to_le_bytes());
+    }
+
+    #[test]
+    fn crc32_le_vectors() {
+        assert_eq!(crc32_le(0, b"123456789"), 0xcbf4_3926);
+        assert_eq!(crc32_le(0, b""), 0);
+    }
+
+    #[test]
+    fn otadata_entry_crc() {
+        let otadata = otadata_image(OTADATA_SIZE, Some(0));
+
+        // The CRC of sequence number 1, as in the `otadata` written by `esp_ota_set_boot_partition`
+        assert_eq!(&otadata[0..4], &1_u32.to_le_bytes());
+        assert_eq!(&otadata[28..32], &0x4743_989a_u32.to_le_bytes());
+    }
+
+    #[test]
+    fn otadata_image_round_trip() {
+        for slots in 1..=4 {
+            for slot in 0..slots {
+                let otadata = otadata_image(OTADATA_SIZE, Some(slot));
+
+                assert_eq!(active_slot(&otadata, slots), Some(slot));
+            }
+        }
+
+        assert_eq!(active_slot(&otadata_image(OTADATA_SIZE, None), 2), None);
+    }
+
+    #[test]
+    fn active_slot_picks_highest_valid_sequence() {
+        let mut otadata = vec![0xff; OTADATA_SIZE];
+
+        write_entry(&mut otadata, 0, 2, OTA_IMG_UNDEFINED);
+        write_entry(&mut otadata, 1, 3, OTA_IMG_UNDEFINED);
+        assert_eq!(active_slot(&otadata, 2), Some(0));
+        assert_eq!(active_slot(&otadata, 3), Some(2));
+
+        write_entry(&mut otadata, 1, 3, OTA_IMG_ABORTED);
+        assert_eq!(active_slot(&otadata, 2), Some(1));
+
+        write_entry(&mut otadata, 1, 3, OTA_IMG_INVALID);
+        assert_eq!(active_slot(&otadata, 2), Some(1));
+    }
+
+    #[test]
+    fn active_slot_rejects_invalid_entries() {
+        let mut otadata = otadata_image(OTADATA_SIZE, Some(1));
+        otadata[28] ^= 1;
+
+        assert_eq!(active_slot(&otadata, 2), None);
+        assert_eq!(active_slot(&otadata_image(OTADATA_SIZE, Some(1)), 0), None);
+        assert_eq!(active_slot(&[0xff; OTADATA_ENTRY_SIZE - 1], 2), None);
+    }
+}
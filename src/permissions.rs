@@ -0,0 +1,578 @@
+//! Serial port permissions' diagnostics and the user under which the tool subprocesses
+//! (`esptool.py`, `espefuse.py`, `espsecure.py`) are run, as well as the handling of the serial port
+//! being busy between the tool invocations and of the connections failing at too high speeds
+
+use std::collections::HashMap;
+use std::ffi::OsStr;
+use std::io;
+use std::process::{Command, Output};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use log::{info, warn};
+
+use serialport::{SerialPortInfo, SerialPortType};
+
+use tempfile::NamedTempFile;
+
+use crate::efuse::EfuseValue;
+use crate::session::Session;
+use crate::utils::secret::{self, SecretFile};
+use crate::{Config, FlashResetAfter, FlashResetBefore};
+
+/// The (lowercase) fragments of the tool output signifying that the serial port is busy or not (yet) available,
+/// e.g. because the port of the previous tool invocation is still being closed, or the adapter is re-enumerating
+const PORT_BUSY_SIGNATURES: &[&str] = &[
+    "resource busy",
+    "errno 16",
+    "could not open port",
+    "access is denied",
+    "permissionerror(13",
+];
+
+/// The message of the tools (`Failed to connect to ESP32: ...`) and of the native flasher (`Failed to connect to the device`)
+/// signifying that connecting to the chip failed - which, with some adapters, happens at the higher speeds
+///
+/// Matched exactly, so that a timeout later on - once the command started changing the chip - is never retried
+const CONNECTION_FAILED_MESSAGE: &str = "Failed to connect to";
+
+/// The tool commands retried at the fallback speeds when connecting to the chip fails
+///
+/// Only the commands which either do not change the chip, or which are safe to be repeated as a whole
+/// (i.e. writing the flash), so that an eFuse burn is never executed twice
+const SPEED_FALLBACK_COMMANDS: &[&str] = &[
+    "chip_id",
+    "flash_id",
+    "read_mac",
+    "read_flash",
+    "write_flash",
+    "summary",
+    "dump",
+];
+
+/// The delay before retrying a tool invocation which failed because the serial port was busy,
+/// in addition to the settle delay
+const PORT_BUSY_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// USB serial adapters commonly found on ESP32 PCBs and test JIGs (VID, PID, description)
+///
+/// Always included in the generated udev rules, in addition to the currently connected adapters
+const KNOWN_ADAPTERS: &[(u16, u16, &str)] = &[
+    (0x303a, 0x1001, "Espressif USB-JTAG-Serial"),
+    (0x10c4, 0xea60, "Silicon Labs CP210x"),
+    (0x1a86, 0x7523, "WCH CH340"),
+    (0x1a86, 0x55d4, "WCH CH9102"),
+    (0x0403, 0x6001, "FTDI FT232R"),
+    (0x0403, 0x6010, "FTDI FT2232"),
+    (0x0403, 0x6015, "FTDI FT231X"),
+];
+
+/// Return `true` if the given (error) output signifies that connecting to the chip failed
+pub(crate) fn connection_failed(output: &str) -> bool {
+    output.contains(CONNECTION_FAILED_MESSAGE)
+}
+
+/// The runner of the tool subprocesses connecting to the chip over the serial port
+///
+/// Each tool invocation opens (and resets) the serial port anew, as the tools cannot take over
+/// an already opened port. With some USB hubs the port is not immediately available after the previous
+/// invocation closed it, hence the settle delay and the retries. With some adapters connecting to the chip
+/// fails at the higher speeds, hence the fallback speeds.
+///
+/// The runner also carries the settings of a factory run which apply to all connections to the chip - either
+/// by the tools or by the native flasher - like the tools' user, the resets, the eFuse summary cached
+/// for the provisioning cycle and the session being recorded or replayed
+#[derive(Debug)]
+pub struct ToolRunner {
+    /// The minimum delay between the end of a tool invocation and the start of the next one
+    settle: Duration,
+    /// The number of times a tool invocation is retried if it failed because the port was busy
+    busy_retries: u32,
+    /// The speeds tried in order when connecting to the chip fails (see `Config::speed_fallbacks`)
+    speed_fallbacks: Vec<u32>,
+    /// The user (UID and GID) under which the tool subprocesses are run, if any (see `Config::tools_user`)
+    user: Option<(u32, u32)>,
+    /// Whether the flashing and eFuse tools should reset the chip into the ROM bootloader themselves,
+    /// or rather - expect the test JIG to have already done so (see `Jig::auto_reset`)
+    auto_reset: bool,
+    /// The reset done by the native flasher before connecting to the chip; the default one if not provided
+    reset_before: Option<FlashResetBefore>,
+    /// The reset done by the native flasher once all images are flashed
+    reset_after: FlashResetAfter,
+    /// When the last tool invocation completed, if any
+    last_run: Mutex<Option<Instant>>,
+    /// The full eFuse summary of the chip being provisioned, read at most once per provisioning cycle
+    /// (see `efuse::cached_summary`)
+    efuse_summary: Mutex<Option<HashMap<String, EfuseValue>>>,
+    /// The session in which the tool invocations are recorded or from which they are replayed
+    /// (see `Config::session_record` and `Config::session_replay`)
+    session: Arc<Session>,
+}
+
+impl ToolRunner {
+    /// Create a tool runner with the serial port handling of the factory configuration
+    /// (see `Config::tool_port_settle_ms`, `Config::tool_port_busy_retries` and `Config::speed_fallbacks`),
+    /// the tools' user (see `Config::tools_user`), and the resets of the chip
+    /// (see `Jig::auto_reset`, `Config::flash_reset_before` and `Config::flash_reset_after`)
+    ///
+    /// Running the tools under another user is only supported on Unix. Switching the user requires the `espfactory`
+    /// process itself to have the privileges to do so (i.e. to run as root or with `CAP_SETUID` and `CAP_SETGID`).
+    /// Note that the tools run only with the primary group of the user, so the serial devices
+    /// should either be owned by that user or be accessible by its primary group (see `udev_rules`)
+    pub fn new(conf: &Config) -> anyhow::Result<Self> {
+        Ok(Self {
+            settle: Duration::from_millis(conf.tool_port_settle_ms as _),
+            busy_retries: conf.tool_port_busy_retries,
+            speed_fallbacks: conf.speed_fallbacks.clone(),
+            user: conf.tools_user.as_deref().map(lookup_user).transpose()?,
+            auto_reset: conf.jig.as_ref().map(|jig| jig.auto_reset).unwrap_or(true),
+            reset_before: conf.flash_reset_before,
+            reset_after: conf.flash_reset_after,
+            last_run: Mutex::new(None),
+            efuse_summary: Mutex::new(None),
+            session: Arc::new(Session::new()),
+        })
+    }
+
+    /// Record the tool invocations in the given session, or replay them from it
+    pub(crate) fn with_session(mut self, session: Arc<Session>) -> Self {
+        self.session = session;
+        self
+    }
+
+    /// Return the speeds tried in order - either with the native flasher or with the tools -
+    /// when connecting to the chip fails at the requested speed
+    pub fn speed_fallbacks(&self) -> &[u32] {
+        &self.speed_fallbacks
+    }
+
+    /// Return `true` if the flashing and eFuse tools should reset the chip into the ROM bootloader themselves
+    pub(crate) fn auto_reset(&self) -> bool {
+        self.auto_reset
+    }
+
+    /// Return the reset done by the native flasher before connecting to the chip, if configured
+    pub(crate) fn reset_before(&self) -> Option<FlashResetBefore> {
+        self.reset_before
+    }
+
+    /// Return the reset done by the native flasher once all images are flashed
+    pub(crate) fn reset_after(&self) -> FlashResetAfter {
+        self.reset_after
+    }
+
+    /// Return the eFuse summary cached for the provisioning cycle (see `efuse::cached_summary`)
+    pub(crate) fn efuse_summary(&self) -> &Mutex<Option<HashMap<String, EfuseValue>>> {
+        &self.efuse_summary
+    }
+
+    /// Return the session in which the tool invocations are recorded or from which they are replayed
+    pub(crate) fn session(&self) -> &Arc<Session> {
+        &self.session
+    }
+
+    /// Create a command for executing the given tool, under the configured tools' user (if any)
+    pub(crate) fn command(&self, tool: esptools::Tool) -> anyhow::Result<Command> {
+        let mut command = Command::new(tool.mount()?.path());
+
+        if let Some((uid, gid)) = self.user {
+            run_as(&mut command, uid, gid);
+        }
+
+        Ok(command)
+    }
+
+    /// Create a temporary file to be read or written by a tool subprocess
+    ///
+    /// If the tools run under a dedicated user, the file is handed over to that user
+    pub(crate) fn temp_file(&self) -> anyhow::Result<NamedTempFile> {
+        let file = NamedTempFile::new().context("Creating a temporary file failed")?;
+
+        if let Some((uid, gid)) = self.user {
+            hand_over(&file, uid, gid)?;
+        }
+
+        Ok(file)
+    }
+
+    /// Create a temporary file with secret data (e.g. a key) to be read by a tool subprocess (see `SecretFile`)
+    ///
+    /// If the tools run under a dedicated user, the file is handed over to that user
+    pub(crate) fn secret_file(&self, data: &[u8]) -> anyhow::Result<SecretFile> {
+        let file = SecretFile::create().context("Creating a secret file failed")?;
+
+        if let Some((uid, gid)) = self.user {
+            hand_over(&file, uid, gid)?;
+        }
+
+        SecretFile::new(file, data).context("Writing a secret file failed")
+    }
+
+    /// Execute the tool command and collect its output, waiting for the serial port to settle after the previous
+    /// tool invocation and retrying if the port turns out to be busy
+    ///
+    /// If the command connects to a chip (i.e. it has a `--chip` argument) and is one of the commands which are safe
+    /// to be repeated (see `SPEED_FALLBACK_COMMANDS`), and connecting fails, the command is retried with each
+    /// of the fallback speeds in turn
+    ///
+    /// The paths of the secret files (see `SecretFile`) are redacted from the logs and from the returned output
+    pub(crate) fn run(&self, command: &mut Command) -> io::Result<Output> {
+        self.run_fallback(command).map(redacted)
+    }
+
+    fn run_fallback(&self, command: &mut Command) -> io::Result<Output> {
+        let mut output = self.run_settled(command)?;
+
+        if !command.get_args().any(|arg| arg == "--chip")
+            || !command.get_args().any(|arg| {
+                SPEED_FALLBACK_COMMANDS
+                    .iter()
+                    .any(|fallback| arg == *fallback)
+            })
+        {
+            return Ok(output);
+        }
+
+        let mut current = tool_speed(command);
+
+        for fallback in &self.speed_fallbacks {
+            let connect_failed = [&output.stderr, &output.stdout]
+                .into_iter()
+                .any(|out| connection_failed(&String::from_utf8_lossy(out)));
+
+            if output.status.success() || !connect_failed {
+                break;
+            }
+
+            warn!(
+                "Connecting at speed {} failed when executing tool command `{}`, retrying at speed {fallback}...",
+                current.as_deref().unwrap_or("default"),
+                tool_display(command)
+            );
+
+            let mut fallback_command = with_speed(command, *fallback, self.user);
+
+            output = self.run_settled(&mut fallback_command)?;
+            current = Some(fallback.to_string());
+
+            if output.status.success() {
+                info!(
+                    "Tool command `{}` succeeded at fallback speed {fallback}",
+                    tool_display(&fallback_command)
+                );
+            }
+        }
+
+        Ok(output)
+    }
+
+    /// Execute the tool command and collect its output, waiting for the serial port to settle after the previous
+    /// tool invocation and retrying if the port turns out to be busy
+    ///
+    /// eFuse burns are never retried, even though the tools fail to open a busy port before connecting to the chip
+    fn run_settled(&self, command: &mut Command) -> io::Result<Output> {
+        let busy_retries = if burns(command) { 0 } else { self.busy_retries };
+
+        let mut attempt = 0;
+
+        loop {
+            let last_run = *self.last_run.lock().unwrap();
+
+            if let Some(wait) =
+                last_run.and_then(|last_run| self.settle.checked_sub(last_run.elapsed()))
+            {
+                thread::sleep(wait);
+            }
+
+            let result = command.output();
+
+            *self.last_run.lock().unwrap() = Some(Instant::now());
+
+            let output = result?;
+
+            if output.status.success() || attempt >= busy_retries || !port_busy(&output) {
+                break Ok(output);
+            }
+
+            attempt += 1;
+
+            warn!(
+                "Serial port busy when executing tool command `{}`, retrying ({attempt}/{busy_retries})...",
+                tool_display(command)
+            );
+
+            thread::sleep(PORT_BUSY_RETRY_DELAY + self.settle);
+        }
+    }
+}
+
+/// Execute a tool command which does not connect to the chip (e.g. `espsecure.py`) and collect its output
+///
+/// The paths of the secret files (see `SecretFile`) are redacted from the returned output
+pub(crate) fn tool_run(command: &mut Command) -> io::Result<Output> {
+    command.output().map(redacted)
+}
+
+/// Return the tool command as it should be logged or recorded, i.e. with the paths of the secret files redacted
+pub(crate) fn tool_display(command: &Command) -> String {
+    secret::redact(format!("{command:?}"))
+}
+
+/// Return `true` if the tool command burns eFuses
+fn burns(command: &Command) -> bool {
+    // Only the burns need to be confirmed, and they are always confirmed upfront (see `efuse::burn_command`)
+    command.get_args().any(|arg| arg == "--do-not-confirm")
+}
+
+fn redacted(output: Output) -> Output {
+    fn redact(output: Vec<u8>) -> Vec<u8> {
+        match String::from_utf8(output) {
+            Ok(output) => secret::redact(output).into_bytes(),
+            Err(err) => err.into_bytes(),
+        }
+    }
+
+    Output {
+        status: output.status,
+        stdout: redact(output.stdout),
+        stderr: redact(output.stderr),
+    }
+}
+
+/// Return the speed (`--baud`) of the tool command, if specified
+fn tool_speed(command: &Command) -> Option<String> {
+    let mut args = command.get_args();
+
+    while let Some(arg) = args.next() {
+        if arg == "--baud" || arg == "-b" {
+            return args
+                .next()
+                .map(|speed| speed.to_string_lossy().into_owned());
+        }
+    }
+
+    None
+}
+
+/// Create a copy of the tool command, with its speed (`--baud`) replaced - or set - to the given one,
+/// to be run under the given user (if any)
+fn with_speed(command: &Command, speed: u32, user: Option<(u32, u32)>) -> Command {
+    let mut new_command = Command::new(command.get_program());
+
+    let mut args = command
+        .get_args()
+        .map(OsStr::to_os_string)
+        .collect::<Vec<_>>();
+
+    if let Some(pos) = args.iter().position(|arg| arg == "--baud" || arg == "-b") {
+        if pos + 1 < args.len() {
+            args[pos + 1] = speed.to_string().into();
+        }
+    } else {
+        // `--baud` is a global option of the tools, hence it should precede the tool command
+        args.splice(0..0, ["--baud".into(), speed.to_string().into()]);
+    }
+
+    new_command.args(args);
+
+    for (key, value) in command.get_envs() {
+        if let Some(value) = value {
+            new_command.env(key, value);
+        } else {
+            new_command.env_remove(key);
+        }
+    }
+
+    if let Some(dir) = command.get_current_dir() {
+        new_command.current_dir(dir);
+    }
+
+    if let Some((uid, gid)) = user {
+        run_as(&mut new_command, uid, gid);
+    }
+
+    new_command
+}
+
+/// Return `true` if the output of a failed tool invocation signifies that the serial port was busy
+fn port_busy(output: &Output) -> bool {
+    [&output.stderr, &output.stdout].into_iter().any(|out| {
+        let out = String::from_utf8_lossy(out).to_ascii_lowercase();
+
+        PORT_BUSY_SIGNATURES
+            .iter()
+            .any(|signature| out.contains(signature))
+    })
+}
+
+/// Convert an error returned when opening a serial port into an error with actionable guidance
+/// in case the error is due to missing permissions (EACCES)
+pub(crate) fn serial_open_error(
+    port_info: &SerialPortInfo,
+    err: serialport::Error,
+) -> anyhow::Error {
+    if matches!(
+        err.kind(),
+        serialport::ErrorKind::Io(std::io::ErrorKind::PermissionDenied)
+    ) {
+        anyhow::Error::new(err).context(permission_guidance(port_info))
+    } else {
+        anyhow::Error::new(err).context("Opening serial port failed")
+    }
+}
+
+/// Generate the content of a udev rules file (e.g. `/etc/udev/rules.d/99-espfactory.rules`)
+/// granting access to the commonly used USB serial adapters as well as to all currently connected ones
+///
+/// # Arguments
+/// - `group` - the group to be granted read/write access to the adapters' TTY devices
+/// - `owner` - an optional user to become the owner of the adapters' TTY devices
+///   (i.e. the user the tool subprocesses are run as)
+pub fn udev_rules(group: &str, owner: Option<&str>) -> anyhow::Result<String> {
+    let mut adapters = KNOWN_ADAPTERS
+        .iter()
+        .map(|(vid, pid, desc)| (*vid, *pid, desc.to_string()))
+        .collect::<Vec<_>>();
+
+    for port in serialport::available_ports().unwrap_or_default() {
+        if let SerialPortType::UsbPort(info) = port.port_type {
+            if !adapters
+                .iter()
+                .any(|(vid, pid, _)| *vid == info.vid && *pid == info.pid)
+            {
+                adapters.push((
+                    info.vid,
+                    info.pid,
+                    info.product.unwrap_or_else(|| port.port_name.clone()),
+                ));
+            }
+        }
+    }
+
+    let mut rules = String::from("# Generated by `espfactory udev-rules`\n");
+
+    for (vid, pid, desc) in adapters {
+        rules.push_str(&format!(
+            "# {desc}\n{}\n",
+            udev_rule(vid, pid, group, owner)
+        ));
+    }
+
+    Ok(rules)
+}
+
+/// Render a single udev rule for the given USB VID/PID
+fn udev_rule(vid: u16, pid: u16, group: &str, owner: Option<&str>) -> String {
+    let mut rule = format!(
+        "SUBSYSTEM==\"tty\", ATTRS{{idVendor}}==\"{vid:04x}\", ATTRS{{idProduct}}==\"{pid:04x}\", MODE=\"0660\", GROUP=\"{group}\""
+    );
+
+    if let Some(owner) = owner {
+        rule.push_str(&format!(", OWNER=\"{owner}\""));
+    }
+
+    rule
+}
+
+/// Render a guidance message on how to fix missing permissions for the given serial port
+fn permission_guidance(port_info: &SerialPortInfo) -> String {
+    let mut guidance = format!(
+        "Permission denied when opening serial port `{}`.",
+        port_info.port_name
+    );
+
+    if let Some(group) = device_group(&port_info.port_name) {
+        guidance.push_str(&format!(
+            "\nAdd the current user to group `{group}` (`sudo usermod -aG {group} $USER`, then log out and back in)."
+        ));
+    }
+
+    if cfg!(target_os = "linux") {
+        if let SerialPortType::UsbPort(info) = &port_info.port_type {
+            guidance.push_str(&format!(
+                "\nOr install the following udev rule in `/etc/udev/rules.d/99-espfactory.rules`:\n{}",
+                udev_rule(info.vid, info.pid, "dialout", None)
+            ));
+        }
+
+        guidance.push_str(
+            "\nA udev rules file for all common adapters can be generated with `espfactory udev-rules`.",
+        );
+    }
+
+    guidance
+}
+
+/// Return the name of the group owning the given device file
+#[cfg(unix)]
+fn device_group(device: &str) -> Option<String> {
+    use std::os::unix::fs::MetadataExt;
+
+    let gid = std::fs::metadata(device).ok()?.gid();
+
+    std::fs::read_to_string("/etc/group")
+        .ok()?
+        .lines()
+        .find_map(|line| {
+            let mut fields = line.split(':');
+
+            let name = fields.next()?;
+            let line_gid = fields.nth(1)?.parse::<u32>().ok()?;
+
+            (line_gid == gid).then(|| name.to_string())
+        })
+}
+
+#[cfg(not(unix))]
+fn device_group(_device: &str) -> Option<String> {
+    None
+}
+
+/// Resolve the UID and the primary GID of the given user
+#[cfg(unix)]
+fn lookup_user(user: &str) -> anyhow::Result<(u32, u32)> {
+    std::fs::read_to_string("/etc/passwd")
+        .context("Reading `/etc/passwd` failed")?
+        .lines()
+        .find_map(|line| {
+            let mut fields = line.split(':');
+
+            let name = fields.next()?;
+            let uid = fields.nth(1)?.parse::<u32>().ok()?;
+            let gid = fields.next()?.parse::<u32>().ok()?;
+
+            (name == user).then_some((uid, gid))
+        })
+        .ok_or_else(|| anyhow::anyhow!("User `{user}` not found"))
+}
+
+#[cfg(not(unix))]
+fn lookup_user(user: &str) -> anyhow::Result<(u32, u32)> {
+    anyhow::bail!("Running the tools as user `{user}` is only supported on Unix")
+}
+
+#[cfg(unix)]
+fn run_as(command: &mut Command, uid: u32, gid: u32) {
+    use std::os::unix::process::CommandExt;
+
+    command.uid(uid).gid(gid);
+}
+
+#[cfg(not(unix))]
+fn run_as(_command: &mut Command, _uid: u32, _gid: u32) {
+    unreachable!()
+}
+
+#[cfg(unix)]
+fn hand_over(file: &NamedTempFile, uid: u32, gid: u32) -> anyhow::Result<()> {
+    std::os::unix::fs::chown(file.path(), Some(uid), Some(gid))
+        .context("Handing over a temporary file to the tools' user failed")
+}
+
+#[cfg(not(unix))]
+fn hand_over(_file: &NamedTempFile, _uid: u32, _gid: u32) -> anyhow::Result<()> {
+    unreachable!()
+}
@@ -0,0 +1,250 @@
+//! External plugins' protocol
+//!
+//! A plugin is an executable which is spawned at a given hook point of the provisioning cycle.
+//! The executable receives a single JSON object on its standard input describing the hook and the provisioning context,
+//! and might reply with a single JSON object on its standard output.
+//!
+//! The provisioning context is also passed in the environment variables of the executable.
+
+use std::io::{self, Read, Write};
+use std::process::{Child, Command, ExitStatus, Output, Stdio};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use log::info;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{Plugin, PluginHook};
+
+/// The provisioning context shared with the plugins
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct PluginContext {
+    /// The readouts (manual, eFuse and plugin-provided ones) collected so far
+    pub readouts: Vec<(String, String)>,
+    /// The MAC address of the chip, if already read from its eFuses
+    pub mac: Option<String>,
+    /// The ID of the bundle, if the bundle is already identified
+    pub bundle_id: Option<String>,
+    /// The name of the bundle, if the bundle is already loaded
+    pub bundle_name: Option<String>,
+    /// The chip of the bundle, if the bundle is already loaded
+    pub chip: Option<String>,
+    /// The serial port used for provisioning, if configured
+    pub port: Option<String>,
+}
+
+/// The request sent to the plugin on its standard input
+#[derive(Serialize)]
+struct PluginRequest<'a> {
+    hook: PluginHook,
+    #[serde(flatten)]
+    context: &'a PluginContext,
+}
+
+/// The response of the plugin on its standard output
+#[derive(Default, Deserialize)]
+struct PluginResponse {
+    /// Additional readouts to be appended to the readouts of the PCB
+    #[serde(default)]
+    readouts: Vec<(String, String)>,
+    /// An error reported by the plugin, if any
+    #[serde(default)]
+    error: Option<String>,
+}
+
+/// Return `true` if at least one of the plugins is registered for the given hook
+pub fn is_registered(plugins: &[Plugin], hook: PluginHook) -> bool {
+    plugins.iter().any(|plugin| plugin.handles(hook))
+}
+
+/// Run all plugins registered for the given hook, in order
+///
+/// # Arguments
+/// - `plugins` - the configured plugins
+/// - `hook` - the hook point
+/// - `context` - the provisioning context to share with the plugins
+///
+/// # Returns
+/// The additional readouts reported by the plugins
+pub fn run(
+    plugins: &[Plugin],
+    hook: PluginHook,
+    context: &PluginContext,
+) -> anyhow::Result<Vec<(String, String)>> {
+    let mut readouts = Vec::new();
+
+    for plugin in plugins.iter().filter(|plugin| plugin.handles(hook)) {
+        let response = run_one(plugin, hook, context)?;
+
+        for (name, value) in &response.readouts {
+            info!(
+                "Readout `{name}` (from plugin `{}`): `{value}`",
+                plugin.command
+            );
+        }
+
+        readouts.extend(response.readouts);
+    }
+
+    Ok(readouts)
+}
+
+/// Return the environment variables describing the hook and the provisioning context
+fn env(hook: PluginHook, context: &PluginContext) -> Vec<(String, String)> {
+    let mut env = vec![("ESPFACTORY_HOOK".to_string(), hook.to_string())];
+
+    let optional = [
+        ("ESPFACTORY_BUNDLE_ID", &context.bundle_id),
+        ("ESPFACTORY_BUNDLE_NAME", &context.bundle_name),
+        ("ESPFACTORY_CHIP", &context.chip),
+        ("ESPFACTORY_PORT", &context.port),
+        ("ESPFACTORY_MAC", &context.mac),
+    ];
+
+    for (name, value) in optional {
+        if let Some(value) = value {
+            env.push((name.to_string(), value.clone()));
+        }
+    }
+
+    for (name, value) in &context.readouts {
+        let name = name
+            .chars()
+            .map(|c| {
+                if c.is_ascii_alphanumeric() {
+                    c.to_ascii_uppercase()
+                } else {
+                    '_'
+                }
+            })
+            .collect::<String>();
+
+        env.push((format!("ESPFACTORY_READOUT_{name}"), value.clone()));
+    }
+
+    env
+}
+
+fn run_one(
+    plugin: &Plugin,
+    hook: PluginHook,
+    context: &PluginContext,
+) -> anyhow::Result<PluginResponse> {
+    let request = serde_json::to_string(&PluginRequest { hook, context })?;
+
+    let mut command = Command::new(&plugin.command);
+
+    command
+        .args(&plugin.args)
+        .envs(env(hook, context))
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+
+    info!("About to execute plugin `{command:?}` for hook `{hook}`...");
+
+    let mut child = command
+        .spawn()
+        .with_context(|| format!("Executing plugin `{command:?}` failed"))?;
+
+    // The request is written, and the output is drained, from separate threads, so that a plugin which
+    // does not read its standard input, or which produces a lot of output, cannot block the exchange
+    let stdin = {
+        let mut stdin = child.stdin.take().unwrap();
+
+        thread::spawn(move || {
+            let result = stdin
+                .write_all(request.as_bytes())
+                .and_then(|_| stdin.write_all(b"\n"));
+
+            match result {
+                // Plain commands are not required to read the request
+                Err(err) if err.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+                other => other,
+            }
+        })
+    };
+
+    let stdout = drain(child.stdout.take().unwrap());
+    let stderr = drain(child.stderr.take().unwrap());
+
+    let status = wait(&mut child, Duration::from_secs(plugin.timeout_secs as _))
+        .with_context(|| format!("Executing plugin `{command:?}` failed"))?;
+
+    let output = Output {
+        status,
+        stdout: stdout.join().unwrap(),
+        stderr: stderr.join().unwrap(),
+    };
+
+    stdin
+        .join()
+        .unwrap()
+        .with_context(|| format!("Sending the request to plugin `{command:?}` failed"))?;
+
+    if !output.status.success() {
+        anyhow::bail!(
+            "Plugin `{command:?}` failed with status: {}.\nStderr output:\n{}",
+            output.status,
+            core::str::from_utf8(&output.stderr).unwrap_or("???")
+        );
+    }
+
+    let stdout = core::str::from_utf8(&output.stdout)
+        .with_context(|| format!("Loading the plugin `{command:?}` output failed"))?
+        .trim();
+
+    let response = if stdout.is_empty() {
+        PluginResponse::default()
+    } else {
+        serde_json::from_str::<PluginResponse>(stdout).with_context(|| {
+            format!("Parsing the plugin `{command:?}` output===\n{stdout}\n=== failed")
+        })?
+    };
+
+    if let Some(error) = response.error.as_deref().filter(|error| !error.is_empty()) {
+        anyhow::bail!("Plugin `{command:?}` reported an error: {error}");
+    }
+
+    info!("Plugin `{command:?}` for hook `{hook}` executed");
+
+    Ok(response)
+}
+
+/// Read the given plugin output stream to its end in a separate thread
+fn drain<R>(mut reader: R) -> thread::JoinHandle<Vec<u8>>
+where
+    R: Read + Send + 'static,
+{
+    thread::spawn(move || {
+        let mut data = Vec::new();
+
+        // A failed read only truncates the output; the exit status of the plugin is what matters
+        let _ = reader.read_to_end(&mut data);
+
+        data
+    })
+}
+
+/// Wait for the plugin to exit, killing it if it does not exit within the given timeout
+fn wait(child: &mut Child, timeout: Duration) -> anyhow::Result<ExitStatus> {
+    let deadline = Instant::now() + timeout;
+
+    loop {
+        if let Some(status) = child.try_wait()? {
+            return Ok(status);
+        }
+
+        if Instant::now() >= deadline {
+            let _ = child.kill();
+            let _ = child.wait();
+
+            anyhow::bail!("Plugin timed out after {}s", timeout.as_secs());
+        }
+
+        thread::sleep(Duration::from_millis(50));
+    }
+}
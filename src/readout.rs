@@ -0,0 +1,374 @@
+//! External sources of the readouts (Device ID, PCB ID, Test JIG ID), so that e.g. a barcode scanner
+//! does not have to emulate a keyboard typing into the UI, as well as the validation of the readouts
+
+use std::fs;
+use std::io::{self, BufRead, Read};
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::mpsc::{self, Receiver, RecvTimeoutError};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use log::info;
+
+use crate::{CheckDigit, ReadoutSource, ReadoutValidation};
+
+/// How often a blocked readout checks whether it was canceled
+const POLL: Duration = Duration::from_millis(100);
+
+/// The lines read from stdin by a detached reader thread, once the first stdin readout is requested
+static STDIN_LINES: Mutex<Option<Receiver<String>>> = Mutex::new(None);
+
+/// Read a value from the given (non-keyboard) readout source
+///
+/// Blocks until a value is available, or until `cancel` is set, in which case `None` is returned
+///
+/// # Arguments
+/// - `source` - the readout source
+/// - `cancel` - a flag signalling that the readout is no longer necessary (e.g. the operator started typing the value)
+pub fn read(source: &ReadoutSource, cancel: &AtomicBool) -> anyhow::Result<Option<String>> {
+    let value = match source {
+        ReadoutSource::Keyboard => anyhow::bail!("Keyboard readouts are read by the UI"),
+        ReadoutSource::Serial { port, baud } => read_serial(port, *baud, cancel)?,
+        ReadoutSource::Stdin => read_stdin(cancel)?,
+        ReadoutSource::File { path } => read_file(Path::new(path), cancel)?,
+        ReadoutSource::Command {
+            command,
+            args,
+            timeout_secs,
+        } => read_command(command, args, *timeout_secs, cancel)?,
+    };
+
+    if let Some(value) = value.as_ref() {
+        info!("Read `{value}` from readout source {source:?}");
+    }
+
+    Ok(value)
+}
+
+/// Validate a readout value against its validation rules
+///
+/// Returns a human-readable description of the first violated rule, if any
+pub fn validate(validation: &ReadoutValidation, value: &str) -> Result<(), String> {
+    if let Some(length) = validation.length {
+        let actual = value.chars().count();
+
+        if actual != length as usize {
+            return Err(format!("Expected {length} characters, got {actual}"));
+        }
+    }
+
+    if let Some(regex) = validation.regex.as_deref() {
+        let re = regex::Regex::new(&format!("^(?:{regex})$"))
+            .map_err(|err| format!("Invalid validation regex `{regex}`: {err}"))?;
+
+        if !re.is_match(value) {
+            return Err(format!("Does not match the pattern `{regex}`"));
+        }
+    }
+
+    if let Some(check_digit) = validation.check_digit {
+        let valid = match check_digit {
+            CheckDigit::Luhn => luhn(value),
+            CheckDigit::Damm => damm(value),
+            CheckDigit::Mod97 => mod97(value),
+        };
+
+        if !valid {
+            return Err(format!("Invalid {check_digit:?} check digit"));
+        }
+    }
+
+    Ok(())
+}
+
+/// Return `true` if the (all digits) value ends with a valid Luhn check digit
+fn luhn(value: &str) -> bool {
+    if value.len() < 2 || !value.chars().all(|ch| ch.is_ascii_digit()) {
+        return false;
+    }
+
+    let sum = value
+        .bytes()
+        .rev()
+        .map(|digit| (digit - b'0') as u32)
+        .enumerate()
+        .map(|(index, digit)| {
+            if index % 2 == 1 {
+                let doubled = digit * 2;
+
+                if doubled > 9 {
+                    doubled - 9
+                } else {
+                    doubled
+                }
+            } else {
+                digit
+            }
+        })
+        .sum::<u32>();
+
+    sum % 10 == 0
+}
+
+/// Return `true` if the (all digits) value ends with a valid Damm check digit
+fn damm(value: &str) -> bool {
+    const TABLE: [[u8; 10]; 10] = [
+        [0, 3, 1, 7, 5, 9, 8, 6, 4, 2],
+        [7, 0, 9, 2, 1, 5, 4, 8, 6, 3],
+        [4, 2, 0, 6, 8, 7, 1, 3, 5, 9],
+        [1, 7, 5, 0, 9, 8, 3, 4, 2, 6],
+        [6, 1, 2, 3, 0, 4, 5, 9, 7, 8],
+        [3, 6, 7, 4, 2, 0, 9, 5, 8, 1],
+        [5, 8, 6, 9, 7, 2, 0, 1, 3, 4],
+        [8, 9, 4, 5, 3, 6, 2, 0, 1, 7],
+        [9, 4, 3, 8, 6, 1, 7, 2, 0, 5],
+        [2, 5, 8, 1, 4, 3, 6, 7, 9, 0],
+    ];
+
+    if value.len() < 2 || !value.chars().all(|ch| ch.is_ascii_digit()) {
+        return false;
+    }
+
+    value.bytes().fold(0, |interim, digit| {
+        TABLE[interim as usize][(digit - b'0') as usize]
+    }) == 0
+}
+
+/// Return `true` if the alphanumeric characters of the value end with two valid ISO 7064 MOD 97-10 check digits
+fn mod97(value: &str) -> bool {
+    let chars = value
+        .chars()
+        .filter(|ch| ch.is_ascii_alphanumeric())
+        .collect::<Vec<_>>();
+
+    if chars.len() < 3 {
+        return false;
+    }
+
+    chars.iter().fold(0_u32, |rem, ch| {
+        let digit = ch.to_digit(36).unwrap();
+
+        if digit < 10 {
+            (rem * 10 + digit) % 97
+        } else {
+            (rem * 100 + digit) % 97
+        }
+    }) == 1
+}
+
+/// Read a line (a scan) from a serial port, terminated by CR and/or LF
+fn read_serial(port: &str, baud: u32, cancel: &AtomicBool) -> anyhow::Result<Option<String>> {
+    let mut serial = serialport::new(port, baud)
+        .timeout(POLL)
+        .open()
+        .with_context(|| format!("Opening readout serial port `{port}` failed"))?;
+
+    let mut line = Vec::new();
+    let mut buf = [0; 64];
+
+    while !cancel.load(Ordering::SeqCst) {
+        let len = match serial.read(&mut buf) {
+            Ok(len) => len,
+            Err(err) if err.kind() == io::ErrorKind::TimedOut => continue,
+            Err(err) => {
+                Err(err).with_context(|| format!("Reading readout serial port `{port}` failed"))?
+            }
+        };
+
+        for byte in &buf[..len] {
+            if matches!(byte, b'\r' | b'\n') {
+                let value = String::from_utf8_lossy(&line).trim().to_string();
+
+                if !value.is_empty() {
+                    return Ok(Some(value));
+                }
+
+                line.clear();
+            } else {
+                line.push(*byte);
+            }
+        }
+    }
+
+    Ok(None)
+}
+
+/// Read a non-empty line from stdin
+fn read_stdin(cancel: &AtomicBool) -> anyhow::Result<Option<String>> {
+    let mut lines = STDIN_LINES.lock().unwrap();
+
+    let lines = lines.get_or_insert_with(|| {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::Builder::new()
+            .name("readout-stdin".into())
+            .spawn(move || {
+                for line in io::stdin().lock().lines() {
+                    let Ok(line) = line else {
+                        break;
+                    };
+
+                    if sender.send(line).is_err() {
+                        break;
+                    }
+                }
+            })
+            .expect("Spawning the stdin readout thread failed");
+
+        receiver
+    });
+
+    while !cancel.load(Ordering::SeqCst) {
+        match lines.recv_timeout(POLL) {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => return Ok(Some(line.trim().to_string())),
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => anyhow::bail!("Stdin closed"),
+        }
+    }
+
+    Ok(None)
+}
+
+/// Wait for the file to appear with a non-empty content, read the content and remove the file,
+/// so that the next readout waits for a new value
+fn read_file(path: &Path, cancel: &AtomicBool) -> anyhow::Result<Option<String>> {
+    while !cancel.load(Ordering::SeqCst) {
+        if let Ok(content) = fs::read_to_string(path) {
+            let value = content.trim();
+
+            if !value.is_empty() {
+                fs::remove_file(path).with_context(|| {
+                    format!("Removing readout file `{}` failed", path.display())
+                })?;
+
+                return Ok(Some(value.to_string()));
+            }
+        }
+
+        thread::sleep(POLL);
+    }
+
+    Ok(None)
+}
+
+/// Run the command and read the value from the first non-empty line of its output
+///
+/// The command might exit after printing the value (e.g. a one-shot QR decoder snapping a camera image)
+/// or keep running (e.g. a badge reader daemon), in which case it is killed once the value is read.
+/// The command is also killed if the readout is canceled or does not complete within `timeout_secs` (if non-zero)
+fn read_command(
+    command: &str,
+    args: &[String],
+    timeout_secs: u32,
+    cancel: &AtomicBool,
+) -> anyhow::Result<Option<String>> {
+    let mut child = Command::new(command)
+        .args(args)
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .spawn()
+        .with_context(|| format!("Starting readout command `{command}` failed"))?;
+
+    let stdout = child.stdout.take().unwrap();
+
+    let (sender, receiver) = mpsc::channel();
+
+    thread::Builder::new()
+        .name("readout-command".into())
+        .spawn(move || {
+            for line in io::BufReader::new(stdout).lines() {
+                let Ok(line) = line else {
+                    break;
+                };
+
+                if sender.send(line).is_err() {
+                    break;
+                }
+            }
+        })
+        .context("Spawning the readout command thread failed")?;
+
+    let started = Instant::now();
+    let timeout = Duration::from_secs(timeout_secs as _);
+
+    let result = loop {
+        if cancel.load(Ordering::SeqCst) {
+            break Ok(None);
+        }
+
+        if timeout_secs > 0 && started.elapsed() >= timeout {
+            break Err(anyhow::anyhow!(
+                "Readout command `{command}` did not return a value within {timeout_secs}s"
+            ));
+        }
+
+        match receiver.recv_timeout(POLL) {
+            Ok(line) if line.trim().is_empty() => continue,
+            Ok(line) => break Ok(Some(line.trim().to_string())),
+            Err(RecvTimeoutError::Timeout) => continue,
+            Err(RecvTimeoutError::Disconnected) => {
+                let status = child.wait()?;
+
+                if !status.success() {
+                    break Err(anyhow::anyhow!(
+                        "Readout command `{command}` failed with status: {status}"
+                    ));
+                }
+
+                break Err(anyhow::anyhow!(
+                    "Readout command `{command}` returned an empty value"
+                ));
+            }
+        }
+    };
+
+    // The command is no longer needed, whether it exited on its own or not
+    let _ = child.kill();
+    let _ = child.wait();
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn luhn_vectors() {
+        assert!(luhn("79927398713"));
+        assert!(luhn("4111111111111111"));
+
+        assert!(!luhn("79927398710"));
+        assert!(!luhn("79927398731"));
+        assert!(!luhn("7992739871a"));
+        assert!(!luhn("0"));
+    }
+
+    #[test]
+    fn damm_vectors() {
+        assert!(damm("5724"));
+        assert!(damm("112946"));
+
+        assert!(!damm("5727"));
+        assert!(!damm("7524"));
+        assert!(!damm("572a"));
+        assert!(!damm("4"));
+    }
+
+    #[test]
+    fn mod97_vectors() {
+        // The IBAN `GB82 WEST 1234 5698 7654 32`, with the country code and the check digits moved to the end
+        assert!(mod97("WEST12345698765432GB82"));
+        assert!(mod97("WEST 1234 5698 7654 32GB 82"));
+
+        assert!(!mod97("WEST12345698765432GB83"));
+        assert!(!mod97("WEST12345698765423GB82"));
+        assert!(!mod97("01"));
+    }
+}
@@ -0,0 +1,263 @@
+//! Networked serial ports (e.g. `ser2net` or a networked serial server), so that the station PC
+//! can be far away from the test JIG
+//!
+//! Two URL forms are supported in place of a serial port name:
+//! - `rfc2217://<host>:<port>` - Telnet with the RFC2217 Com Port Control option
+//! - `tcp://<host>:<port>` - a raw TCP socket (`socket://<host>:<port>` is accepted as an alias)
+//!
+//! The native flasher needs a local serial port, so with a networked port the images are flashed
+//! with `esptool.py`, which - as `espefuse.py` - understands these URLs natively.
+//! The serial monitor talks to the networked port directly.
+
+use std::io::{self, Read, Write};
+use std::net::TcpStream;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use log::debug;
+
+const RFC2217_PREFIX: &str = "rfc2217://";
+const TCP_PREFIXES: &[&str] = &["tcp://", "socket://"];
+
+const IAC: u8 = 255;
+const DONT: u8 = 254;
+const DO: u8 = 253;
+const WONT: u8 = 252;
+const WILL: u8 = 251;
+const SB: u8 = 250;
+const SE: u8 = 240;
+
+const BINARY: u8 = 0;
+const SGA: u8 = 3;
+const COM_PORT_OPTION: u8 = 44;
+const SET_BAUDRATE: u8 = 1;
+
+/// Return `true` if the port is a networked serial port URL rather than a local serial port
+pub(crate) fn is_remote(port: Option<&str>) -> bool {
+    port.map(|port| port.starts_with(RFC2217_PREFIX) || tcp_address(port).is_some())
+        .unwrap_or(false)
+}
+
+/// Return the port as understood by `esptool.py` and `espefuse.py` (i.e. by `pyserial`)
+///
+/// Local serial ports and `rfc2217://` URLs are passed as-is, while `tcp://` URLs are
+/// converted to their `pyserial` `socket://` equivalent
+pub(crate) fn tools_port(port: &str) -> String {
+    if let Some(address) = port.strip_prefix("tcp://") {
+        format!("socket://{address}")
+    } else {
+        port.to_string()
+    }
+}
+
+/// A connection to a networked serial port
+pub(crate) struct RemotePort {
+    stream: TcpStream,
+    rfc2217: bool,
+    telnet: Telnet,
+}
+
+impl RemotePort {
+    /// Connect to the networked serial port with the given URL
+    ///
+    /// With RFC2217, the baud rate of the remote serial port is set to `baud`;
+    /// with raw TCP, the baud rate is whatever the networked serial server is configured with
+    pub fn open(url: &str, baud: u32) -> anyhow::Result<Self> {
+        let (address, rfc2217) = if let Some(address) = url.strip_prefix(RFC2217_PREFIX) {
+            (address, true)
+        } else {
+            (
+                tcp_address(url)
+                    .with_context(|| format!("Not a networked serial port: `{url}`"))?,
+                false,
+            )
+        };
+
+        // `pyserial` URL options (e.g. `?logging=debug`) are not relevant here
+        let address = address.split('?').next().unwrap_or(address);
+
+        debug!("Connecting to networked serial port `{address}`");
+
+        let stream = TcpStream::connect(address)
+            .with_context(|| format!("Connecting to networked serial port `{url}` failed"))?;
+        stream.set_nodelay(true)?;
+
+        let mut port = Self {
+            stream,
+            rfc2217,
+            telnet: Telnet::Data,
+        };
+
+        if rfc2217 {
+            port.negotiate(baud)?;
+        }
+
+        Ok(port)
+    }
+
+    /// Set the read timeout of the connection
+    pub fn set_timeout(&mut self, timeout: Duration) -> io::Result<()> {
+        self.stream.set_read_timeout(Some(timeout))
+    }
+
+    /// Announce the Com Port Control option and set the baud rate of the remote serial port
+    fn negotiate(&mut self, baud: u32) -> io::Result<()> {
+        let mut request = vec![IAC, WILL, COM_PORT_OPTION];
+        request.extend_from_slice(&[IAC, WILL, BINARY, IAC, DO, BINARY]);
+        request.extend_from_slice(&[IAC, SB, COM_PORT_OPTION, SET_BAUDRATE]);
+
+        for byte in baud.to_be_bytes() {
+            request.push(byte);
+
+            if byte == IAC {
+                request.push(IAC);
+            }
+        }
+
+        request.extend_from_slice(&[IAC, SE]);
+
+        self.stream.write_all(&request)?;
+        self.stream.flush()
+    }
+}
+
+impl Read for RemotePort {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        if !self.rfc2217 {
+            return self.stream.read(buf);
+        }
+
+        loop {
+            let read = self.stream.read(buf)?;
+            if read == 0 {
+                return Ok(0);
+            }
+
+            // Strip the Telnet commands and answer the option negotiations in-place
+            let mut replies = Vec::new();
+            let mut len = 0;
+
+            let received = buf[..read].to_vec();
+
+            for byte in received {
+                if let Some(data) = self.telnet.feed(byte, &mut replies) {
+                    buf[len] = data;
+                    len += 1;
+                }
+            }
+
+            if !replies.is_empty() {
+                self.stream.write_all(&replies)?;
+            }
+
+            // Only Telnet commands received; keep reading so that `0` still means EOF
+            if len > 0 {
+                return Ok(len);
+            }
+        }
+    }
+}
+
+impl Write for RemotePort {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if !self.rfc2217 {
+            return self.stream.write(buf);
+        }
+
+        let mut escaped = Vec::with_capacity(buf.len());
+
+        for byte in buf {
+            escaped.push(*byte);
+
+            if *byte == IAC {
+                escaped.push(IAC);
+            }
+        }
+
+        self.stream.write_all(&escaped)?;
+
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+/// The state of the Telnet command parser
+#[derive(Copy, Clone, Debug)]
+enum Telnet {
+    Data,
+    Iac,
+    Option(u8),
+    Sub,
+    SubIac,
+}
+
+impl Telnet {
+    /// Feed a received byte, returning it if it is serial data rather than a Telnet command
+    ///
+    /// Replies to the option negotiations of the server are appended to `replies`
+    fn feed(&mut self, byte: u8, replies: &mut Vec<u8>) -> Option<u8> {
+        match *self {
+            Self::Data => {
+                if byte == IAC {
+                    *self = Self::Iac;
+                    None
+                } else {
+                    Some(byte)
+                }
+            }
+            Self::Iac => match byte {
+                IAC => {
+                    *self = Self::Data;
+                    Some(IAC)
+                }
+                DO | DONT | WILL | WONT => {
+                    *self = Self::Option(byte);
+                    None
+                }
+                SB => {
+                    *self = Self::Sub;
+                    None
+                }
+                _ => {
+                    *self = Self::Data;
+                    None
+                }
+            },
+            Self::Option(command) => {
+                let supported = matches!(byte, BINARY | SGA | COM_PORT_OPTION);
+
+                match command {
+                    DO if !supported => replies.extend_from_slice(&[IAC, WONT, byte]),
+                    WILL if !supported => replies.extend_from_slice(&[IAC, DONT, byte]),
+                    _ => (),
+                }
+
+                *self = Self::Data;
+                None
+            }
+            // The Com Port Control notifications (line and modem state) are not interesting
+            Self::Sub => {
+                if byte == IAC {
+                    *self = Self::SubIac;
+                }
+
+                None
+            }
+            Self::SubIac => {
+                *self = if byte == SE { Self::Data } else { Self::Sub };
+                None
+            }
+        }
+    }
+}
+
+/// Return the `<host>:<port>` address of a raw TCP port URL
+fn tcp_address(port: &str) -> Option<&str> {
+    TCP_PREFIXES
+        .iter()
+        .find_map(|prefix| port.strip_prefix(prefix))
+}
@@ -0,0 +1,316 @@
+//! Per-board test reports of the provisioning steps, as consumed by factory orchestration systems
+//!
+//! Each executed step (and each app-run expectation) is recorded as a test case, together with its duration
+//! and - in case of a failure - an error code and the error message. Steps retried by the operator are recorded
+//! once per attempt.
+
+use core::fmt::Write as _;
+use core::time::Duration;
+
+use serde::Serialize;
+
+use crate::{Locale, ReportFormat};
+
+/// The outcome of a step
+#[derive(Clone, Debug, Eq, PartialEq, Serialize)]
+#[serde(tag = "status", rename_all = "lowercase")]
+pub enum StepOutcome {
+    /// The step completed successfully
+    Passed,
+    /// The step failed with the given error code and message
+    Failed { code: String, message: String },
+}
+
+/// A step (test case) in the report
+#[derive(Clone, Debug, Serialize)]
+pub struct StepReport {
+    /// The name of the step; suffixed with the attempt number for retried steps
+    pub name: String,
+    /// The duration of the step, in seconds
+    pub time: f64,
+    /// The outcome of the step
+    #[serde(flatten)]
+    pub outcome: StepOutcome,
+}
+
+/// The checksums of an image flashed to the PCB
+#[derive(Clone, Debug, Serialize)]
+pub struct ImageChecksum {
+    /// The offset of the image in the flash memory
+    pub offset: u32,
+    /// The size of the image, in bytes
+    pub size: usize,
+    /// The SHA-256 hash of the image, as in the bundle
+    pub sha256: String,
+    /// The MD5 checksum of the data flashed for the image (i.e. of the encrypted image, if encrypted while flashing)
+    pub md5: String,
+    /// The MD5 checksum of the flash region of the image as computed by the chip, if read back
+    pub device_md5: Option<String>,
+}
+
+impl ImageChecksum {
+    /// Return `true` if the MD5 checksum read back from the chip (if any) matches the one of the flashed data
+    pub fn verified(&self) -> Option<bool> {
+        self.device_md5
+            .as_ref()
+            .map(|device_md5| *device_md5 == self.md5)
+    }
+}
+
+/// The report of the PCB being provisioned
+#[derive(Clone, Debug)]
+pub struct Report {
+    timestamp: Option<chrono::DateTime<chrono::Utc>>,
+    steps: Vec<StepReport>,
+    images: Vec<ImageChecksum>,
+}
+
+impl Report {
+    /// Create a new, empty report
+    pub const fn new() -> Self {
+        Self {
+            timestamp: None,
+            steps: Vec::new(),
+            images: Vec::new(),
+        }
+    }
+
+    /// Clear the report
+    ///
+    /// To be called when a new PCB is to be provisioned
+    pub fn clear(&mut self) {
+        self.timestamp = None;
+        self.steps.clear();
+        self.images.clear();
+    }
+
+    /// Record the outcome of a step
+    ///
+    /// # Arguments
+    /// - `step` - the name of the step
+    /// - `duration` - the duration of the step
+    /// - `outcome` - the outcome of the step
+    pub fn record(&mut self, step: &str, duration: Duration, outcome: StepOutcome) {
+        if self.timestamp.is_none() {
+            self.timestamp = Some(chrono::Utc::now());
+        }
+
+        let attempt = self
+            .steps
+            .iter()
+            .filter(|report| report.name == step || report.name.starts_with(&format!("{step} #")))
+            .count()
+            + 1;
+
+        let name = if attempt > 1 {
+            format!("{step} #{attempt}")
+        } else {
+            step.to_string()
+        };
+
+        self.steps.push(StepReport {
+            name,
+            time: duration.as_secs_f64(),
+            outcome,
+        });
+    }
+
+    /// Return the steps recorded so far
+    pub fn steps(&self) -> &[StepReport] {
+        &self.steps
+    }
+
+    /// Record the checksums of the flashed images, replacing the ones of a previous flashing attempt
+    pub fn record_images(&mut self, images: Vec<ImageChecksum>) {
+        self.images = images;
+    }
+
+    /// Render the checksums of the flashed images as a CSV manifest
+    pub fn images_csv(&self) -> anyhow::Result<String> {
+        let mut csv = csv::WriterBuilder::new()
+            .has_headers(true)
+            .from_writer(Vec::new());
+
+        csv.serialize(("Offset", "Size", "SHA-256", "MD5", "Device MD5", "Verified"))?;
+
+        for image in &self.images {
+            csv.serialize((
+                format!("0x{:08x}", image.offset),
+                image.size,
+                &image.sha256,
+                &image.md5,
+                image.device_md5.as_deref().unwrap_or_default(),
+                match image.verified() {
+                    Some(true) => "yes",
+                    Some(false) => "no",
+                    None => "",
+                },
+            ))?;
+        }
+
+        Ok(String::from_utf8(csv.into_inner()?)?)
+    }
+
+    /// Render the report in the given format
+    ///
+    /// # Arguments
+    /// - `format` - the format of the report
+    /// - `bundle_name` - the name of the provisioned bundle
+    /// - `summary` - the readouts of the PCB, as recorded in the summary of the PCB logs
+    /// - `locale` - the locale of the dates in the JSON report; the JUnit report always uses the formats
+    ///   mandated by the JUnit schema
+    pub fn render(
+        &self,
+        format: ReportFormat,
+        bundle_name: &str,
+        summary: &[(String, String)],
+        locale: &Locale,
+    ) -> anyhow::Result<String> {
+        match format {
+            ReportFormat::Junit => self.to_junit(bundle_name, summary),
+            ReportFormat::Json => self.to_json(bundle_name, summary, locale),
+        }
+    }
+
+    fn to_junit(&self, bundle_name: &str, summary: &[(String, String)]) -> anyhow::Result<String> {
+        let failures = self
+            .steps
+            .iter()
+            .filter(|step| matches!(step.outcome, StepOutcome::Failed { .. }))
+            .count();
+        let time = Locale::ISO.format_decimal(self.steps.iter().map(|step| step.time).sum(), 3);
+        let timestamp = self
+            .timestamp
+            .map(|timestamp| Locale::ISO.format_date(timestamp))
+            .unwrap_or_default();
+
+        let mut xml = String::new();
+
+        writeln!(&mut xml, r#"<?xml version="1.0" encoding="UTF-8"?>"#)?;
+        writeln!(
+            &mut xml,
+            r#"<testsuites name="espfactory" tests="{}" failures="{failures}" time="{time}">"#,
+            self.steps.len()
+        )?;
+        writeln!(
+            &mut xml,
+            r#"  <testsuite name="{}" tests="{}" failures="{failures}" time="{time}" timestamp="{timestamp}">"#,
+            escape(bundle_name),
+            self.steps.len(),
+        )?;
+
+        writeln!(&mut xml, "    <properties>")?;
+        for (name, value) in summary {
+            writeln!(
+                &mut xml,
+                r#"      <property name="{}" value="{}"/>"#,
+                escape(name),
+                escape(value)
+            )?;
+        }
+        for image in &self.images {
+            let prefix = format!("image.0x{:08x}", image.offset);
+
+            writeln!(
+                &mut xml,
+                r#"      <property name="{prefix}.sha256" value="{}"/>"#,
+                image.sha256
+            )?;
+            writeln!(
+                &mut xml,
+                r#"      <property name="{prefix}.md5" value="{}"/>"#,
+                image.md5
+            )?;
+
+            if let Some(device_md5) = &image.device_md5 {
+                writeln!(
+                    &mut xml,
+                    r#"      <property name="{prefix}.device_md5" value="{device_md5}"/>"#
+                )?;
+            }
+        }
+        writeln!(&mut xml, "    </properties>")?;
+
+        for step in &self.steps {
+            write!(
+                &mut xml,
+                r#"    <testcase name="{}" classname="espfactory" time="{}""#,
+                escape(&step.name),
+                Locale::ISO.format_decimal(step.time, 3)
+            )?;
+
+            match &step.outcome {
+                StepOutcome::Passed => writeln!(&mut xml, "/>")?,
+                StepOutcome::Failed { code, message } => {
+                    writeln!(&mut xml, ">")?;
+                    writeln!(
+                        &mut xml,
+                        r#"      <failure type="{}" message="{}">{}</failure>"#,
+                        escape(code),
+                        escape(message.lines().next().unwrap_or_default()),
+                        escape(message)
+                    )?;
+                    writeln!(&mut xml, "    </testcase>")?;
+                }
+            }
+        }
+
+        writeln!(&mut xml, "  </testsuite>")?;
+        writeln!(&mut xml, "</testsuites>")?;
+
+        Ok(xml)
+    }
+
+    fn to_json(
+        &self,
+        bundle_name: &str,
+        summary: &[(String, String)],
+        locale: &Locale,
+    ) -> anyhow::Result<String> {
+        #[derive(Serialize)]
+        struct JsonReport<'a> {
+            bundle: &'a str,
+            timestamp: Option<String>,
+            readouts: &'a [(String, String)],
+            steps: &'a [StepReport],
+            images: &'a [ImageChecksum],
+        }
+
+        let report = JsonReport {
+            bundle: bundle_name,
+            timestamp: self
+                .timestamp
+                .map(|timestamp| locale.format_date(timestamp)),
+            readouts: summary,
+            steps: &self.steps,
+            images: &self.images,
+        };
+
+        Ok(serde_json::to_string_pretty(&report)?)
+    }
+}
+
+impl Default for Report {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Escape a string for use in XML attributes and text
+fn escape(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+
+    for c in s.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&apos;"),
+            c if c.is_control() && c != '\n' && c != '\t' => (),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
@@ -0,0 +1,116 @@
+//! Sampling of auxiliary fixture sensors (e.g. temperature, humidity)
+
+use std::io::{BufRead, BufReader, Write};
+use std::time::Duration;
+
+use anyhow::Context;
+
+use log::{info, warn};
+
+use crate::{Locale, Sensor, SensorSource};
+
+/// Sample all sensors and return the samples as readouts
+///
+/// A sensor which fails to be sampled is recorded with a `N/A` value, as the sensors
+/// are only used for failure analysis and should not interrupt the provisioning
+///
+/// # Arguments
+/// - `sensors` - the configured sensors
+/// - `stage` - the provisioning stage the sensors are sampled at (e.g. `pre-flash`)
+/// - `locale` - the locale of the sampling time
+pub fn sample_all(sensors: &[Sensor], stage: &str, locale: &Locale) -> Vec<(String, String)> {
+    if sensors.is_empty() {
+        return Vec::new();
+    }
+
+    let mut readouts = vec![(format!("Sensors Time ({stage})"), locale.format_now())];
+
+    for sensor in sensors {
+        let value = match sample(sensor) {
+            Ok(value) => {
+                info!("Sensor `{}` ({stage}): `{value}`", sensor.name);
+                value
+            }
+            Err(err) => {
+                warn!(
+                    "Sampling sensor `{}` ({stage}) failed: {err:?}",
+                    sensor.name
+                );
+                "N/A".to_string()
+            }
+        };
+
+        readouts.push((format!("{} ({stage})", sensor.name), value));
+    }
+
+    readouts
+}
+
+/// Sample a single sensor
+pub fn sample(sensor: &Sensor) -> anyhow::Result<String> {
+    let response = match &sensor.source {
+        SensorSource::Serial {
+            port,
+            baud,
+            query,
+            timeout_ms,
+        } => sample_serial(port, *baud, query.as_deref(), *timeout_ms)?,
+        SensorSource::Http { url, timeout_ms } => sample_http(url, *timeout_ms)?,
+    };
+
+    let mut value = response.trim().to_string();
+
+    if let Some(regex) = &sensor.regex {
+        let re = regex::Regex::new(regex)
+            .with_context(|| format!("Invalid regex `{regex}` of sensor `{}`", sensor.name))?;
+
+        value = re
+            .captures(&value)
+            .and_then(|captures| captures.get(1))
+            .map(|m| m.as_str().to_string())
+            .ok_or_else(|| anyhow::anyhow!("Response `{value}` does not match regex `{regex}`"))?;
+    }
+
+    Ok(value)
+}
+
+fn sample_serial(
+    port: &str,
+    baud: u32,
+    query: Option<&str>,
+    timeout_ms: u32,
+) -> anyhow::Result<String> {
+    let mut serial = serialport::new(port, baud)
+        .timeout(Duration::from_millis(timeout_ms as _))
+        .open()
+        .with_context(|| format!("Opening sensor serial port `{port}` failed"))?;
+
+    if let Some(query) = query {
+        serial
+            .write_all(query.as_bytes())
+            .and_then(|_| serial.write_all(b"\n"))
+            .and_then(|_| serial.flush())
+            .with_context(|| format!("Sending query to sensor serial port `{port}` failed"))?;
+    }
+
+    let mut line = String::new();
+
+    BufReader::new(serial)
+        .read_line(&mut line)
+        .with_context(|| format!("Reading from sensor serial port `{port}` failed"))?;
+
+    Ok(line)
+}
+
+fn sample_http(url: &str, timeout_ms: u32) -> anyhow::Result<String> {
+    let response = reqwest::blocking::Client::new()
+        .get(url)
+        .timeout(Duration::from_millis(timeout_ms as _))
+        .send()
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Requesting sensor URL `{url}` failed"))?;
+
+    response
+        .text()
+        .with_context(|| format!("Reading the response of sensor URL `{url}` failed"))
+}
@@ -0,0 +1,387 @@
+//! Recording and replaying of operator sessions
+//!
+//! A recorded session contains the key presses of the operator with their timings, the hashes of the loaded bundles,
+//! as well as the responses of the device (the invocations of the tools with their outputs, and the flashing).
+//!
+//! Replaying a session drives the real UI and re-executes the provisioning pipeline against the recorded responses,
+//! without a device being connected. This is useful for operator training, for documentation screencasts, as well as
+//! for reproducing a reported failure deterministically on a developer machine.
+
+use std::collections::VecDeque;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::path::Path;
+use std::process::{Command, ExitStatus, Output};
+use std::sync::Mutex;
+use std::thread;
+use std::time::{Duration, Instant};
+
+use anyhow::Context;
+
+use crossterm::event::KeyEvent;
+
+use espflash::flasher::ProgressCallbacks;
+
+use log::{info, warn};
+
+use serde::{Deserialize, Serialize};
+
+use crate::bundle::FlashData;
+use crate::permissions::{self, ToolRunner};
+use crate::utils::hex;
+
+/// An event of a recorded session, stored as a JSON line in the session file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "kebab-case")]
+enum SessionEvent {
+    /// A key pressed by the operator
+    Key { at_ms: u64, key: KeyEvent },
+    /// A loaded bundle
+    Bundle {
+        at_ms: u64,
+        name: String,
+        sha256: String,
+    },
+    /// An invocation of a tool (`esptool.py`, `espefuse.py`) talking to the device
+    Tool {
+        at_ms: u64,
+        /// The command line, for diagnostics only, as it contains temporary file paths
+        command: String,
+        /// The exit code of the tool
+        code: i32,
+        stdout: String,
+        stderr: String,
+        /// The hex-encoded content of the files written by the tool
+        #[serde(default)]
+        files: Vec<String>,
+    },
+    /// The flashing of the device
+    Flash {
+        at_ms: u64,
+        duration_ms: u64,
+        #[serde(default)]
+        error: Option<String>,
+    },
+}
+
+/// Whether a session is being recorded or replayed
+#[derive(Debug)]
+enum Mode {
+    Record {
+        start: Instant,
+        out: BufWriter<File>,
+    },
+    Replay {
+        keys: Option<Vec<(u64, KeyEvent)>>,
+        device: VecDeque<SessionEvent>,
+    },
+}
+
+/// The session of a run, being recorded or replayed, if any
+#[derive(Debug)]
+pub(crate) struct Session(Mutex<Option<Mode>>);
+
+impl Session {
+    /// Create a session which is neither recorded nor replayed
+    pub(crate) const fn new() -> Self {
+        Self(Mutex::new(None))
+    }
+
+    /// Start recording or replaying a session
+    ///
+    /// # Arguments
+    /// - `record` - the file where the session is to be recorded, if any
+    /// - `replay` - the file of the session to be replayed, if any
+    pub(crate) fn start(record: Option<&str>, replay: Option<&str>) -> anyhow::Result<Self> {
+        let session = match (record, replay) {
+            (Some(_), Some(_)) => {
+                anyhow::bail!("A session cannot be recorded and replayed at the same time")
+            }
+            (Some(record), None) => Some(Mode::Record {
+                start: Instant::now(),
+                out: BufWriter::new(
+                    File::create(record)
+                        .with_context(|| format!("Creating session file `{record}` failed"))?,
+                ),
+            }),
+            (None, Some(replay)) => {
+                let file = File::open(replay)
+                    .with_context(|| format!("Opening session file `{replay}` failed"))?;
+
+                let mut keys = Vec::new();
+                let mut device = VecDeque::new();
+
+                for line in BufReader::new(file).lines() {
+                    let line =
+                        line.with_context(|| format!("Reading session file `{replay}` failed"))?;
+
+                    if line.trim().is_empty() {
+                        continue;
+                    }
+
+                    match serde_json::from_str(&line).with_context(|| {
+                        format!("Invalid event `{line}` in session file `{replay}`")
+                    })? {
+                        SessionEvent::Key { at_ms, key } => keys.push((at_ms, key)),
+                        event => device.push_back(event),
+                    }
+                }
+
+                Some(Mode::Replay {
+                    keys: Some(keys),
+                    device,
+                })
+            }
+            (None, None) => None,
+        };
+
+        Ok(Self(Mutex::new(session)))
+    }
+
+    /// Return `true` if a session is being replayed, i.e. there is no real device and no real operator
+    pub(crate) fn replaying(&self) -> bool {
+        matches!(*self.0.lock().unwrap(), Some(Mode::Replay { .. }))
+    }
+
+    /// Return `true` if a session is being recorded or replayed
+    pub(crate) fn active(&self) -> bool {
+        self.0.lock().unwrap().is_some()
+    }
+
+    /// Record a key pressed by the operator, if a session is being recorded
+    pub(crate) fn record_key(&self, key: &KeyEvent) {
+        self.record(|at_ms| SessionEvent::Key { at_ms, key: *key });
+    }
+
+    /// Record a loaded bundle if a session is being recorded, or check that the loaded bundle is the recorded one
+    /// if a session is being replayed
+    pub(crate) fn bundle(&self, name: &str, sha256: &str) -> anyhow::Result<()> {
+        let recorded = self.replay(|event| match event {
+            SessionEvent::Bundle { name, sha256, .. } => Some((name.clone(), sha256.clone())),
+            _ => None,
+        });
+
+        match recorded {
+            Some(Some((recorded_name, recorded_sha256))) => {
+                if recorded_sha256 != sha256 {
+                    anyhow::bail!(
+                        "Bundle `{name}` (SHA-256 {sha256}) differs from the recorded bundle `{recorded_name}` (SHA-256 {recorded_sha256})"
+                    );
+                }
+            }
+            Some(None) => anyhow::bail!("No more bundles in the replayed session"),
+            None => self.record(|at_ms| SessionEvent::Bundle {
+                at_ms,
+                name: name.to_string(),
+                sha256: sha256.to_string(),
+            }),
+        }
+
+        Ok(())
+    }
+
+    /// Record the flashing of the device, if a session is being recorded
+    pub(crate) fn record_flash(&self, duration: Duration, error: Option<&anyhow::Error>) {
+        self.record(|at_ms| SessionEvent::Flash {
+            at_ms,
+            duration_ms: duration.as_millis() as u64,
+            error: error.map(|err| format!("{err:#}")),
+        });
+    }
+
+    /// Take the recorded key presses (with their times in ms since the start of the session) to be replayed,
+    /// if a session is being replayed
+    ///
+    /// The key presses can only be taken once
+    pub(crate) fn take_replay_keys(&self) -> Option<Vec<(u64, KeyEvent)>> {
+        match &mut *self.0.lock().unwrap() {
+            Some(Mode::Replay { keys, .. }) => keys.take(),
+            _ => None,
+        }
+    }
+
+    /// Simulate the flashing of the device with the next recorded flashing, if a session is being replayed
+    ///
+    /// Returns `None` if no session is being replayed, or the recorded result of the flashing otherwise
+    pub(crate) fn replay_flash<P>(
+        &self,
+        flash_data: &[FlashData],
+        progress: &mut P,
+    ) -> Option<anyhow::Result<()>>
+    where
+        P: ProgressCallbacks,
+    {
+        let recorded = self.replay(|event| match event {
+            SessionEvent::Flash {
+                duration_ms, error, ..
+            } => Some((*duration_ms, error.clone())),
+            _ => None,
+        })?;
+
+        let Some((duration_ms, error)) = recorded else {
+            return Some(Err(anyhow::anyhow!(
+                "No more flashings in the replayed session"
+            )));
+        };
+
+        let total_len = flash_data
+            .iter()
+            .map(|data| data.data.len() as u64)
+            .sum::<u64>()
+            .max(1);
+
+        for data in flash_data {
+            let len = data.data.len();
+            let step = Duration::from_millis(duration_ms * len as u64 / total_len / 10);
+
+            progress.init(data.offset, len);
+
+            for tick in 1..=10 {
+                thread::sleep(step);
+                progress.update(len * tick / 10);
+            }
+
+            progress.finish(false);
+        }
+
+        Some(match error {
+            Some(error) => Err(anyhow::anyhow!("{error}")),
+            None => Ok(()),
+        })
+    }
+
+    /// Record a session event, if a session is being recorded
+    fn record<F>(&self, event: F)
+    where
+        F: FnOnce(u64) -> SessionEvent,
+    {
+        if let Some(Mode::Record { start, out }) = &mut *self.0.lock().unwrap() {
+            let event = event(start.elapsed().as_millis() as u64);
+
+            let result = serde_json::to_writer(&mut *out, &event)
+                .map_err(anyhow::Error::from)
+                .and_then(|_| writeln!(out).map_err(anyhow::Error::from))
+                .and_then(|_| out.flush().map_err(anyhow::Error::from));
+
+            if let Err(err) = result {
+                warn!("Recording the session failed: {err:?}");
+            }
+        }
+    }
+
+    /// Take the first recorded device event of the requested kind, if a session is being replayed
+    ///
+    /// Returns `None` if no session is being replayed, and `Some(None)` if no more events of that kind were recorded
+    fn replay<F, R>(&self, f: F) -> Option<Option<R>>
+    where
+        F: Fn(&SessionEvent) -> Option<R>,
+    {
+        if let Some(Mode::Replay { device, .. }) = &mut *self.0.lock().unwrap() {
+            let result = device
+                .iter()
+                .enumerate()
+                .find_map(|(index, event)| f(event).map(|result| (index, result)));
+
+            Some(result.map(|(index, result)| {
+                device.remove(index);
+                result
+            }))
+        } else {
+            None
+        }
+    }
+}
+
+/// Execute a tool talking to the device and return its output
+///
+/// If the session of the tool runner is being recorded, the invocation is recorded, together with the content
+/// of the files written by the tool. If the session is being replayed, the tool is not executed; rather,
+/// the recorded output is returned and the recorded files are written back
+///
+/// # Arguments
+/// - `tools` - the tool runner
+/// - `command` - the tool command
+/// - `files` - the files written by the tool
+pub(crate) fn tool_output(
+    tools: &ToolRunner,
+    command: &mut Command,
+    files: &[&Path],
+) -> io::Result<Output> {
+    let session = tools.session();
+
+    let recorded = session.replay(|event| match event {
+        SessionEvent::Tool {
+            code,
+            stdout,
+            stderr,
+            files,
+            ..
+        } => Some((*code, stdout.clone(), stderr.clone(), files.clone())),
+        _ => None,
+    });
+
+    match recorded {
+        Some(Some((code, stdout, stderr, recorded_files))) => {
+            info!(
+                "Replaying tool command `{}` from the session",
+                permissions::tool_display(command)
+            );
+
+            for (path, data) in files.iter().zip(recorded_files) {
+                fs::write(
+                    path,
+                    hex::decode(&data)
+                        .context("Invalid hex data in the session file")
+                        .map_err(io::Error::other)?,
+                )?;
+            }
+
+            Ok(Output {
+                status: exit_status(code),
+                stdout: stdout.into_bytes(),
+                stderr: stderr.into_bytes(),
+            })
+        }
+        Some(None) => Err(io::Error::other(
+            "No more tool invocations in the replayed session",
+        )),
+        None => {
+            let output = tools.run(command)?;
+
+            if session.active() {
+                let files = files
+                    .iter()
+                    .map(|path| fs::read(path).map(hex::encode).unwrap_or_default())
+                    .collect();
+
+                session.record(|at_ms| SessionEvent::Tool {
+                    at_ms,
+                    command: permissions::tool_display(command),
+                    code: output.status.code().unwrap_or(-1),
+                    stdout: String::from_utf8_lossy(&output.stdout).into_owned(),
+                    stderr: String::from_utf8_lossy(&output.stderr).into_owned(),
+                    files,
+                });
+            }
+
+            Ok(output)
+        }
+    }
+}
+
+/// Create an exit status with the given exit code
+fn exit_status(code: i32) -> ExitStatus {
+    #[cfg(unix)]
+    {
+        use std::os::unix::process::ExitStatusExt;
+
+        ExitStatus::from_raw((code & 0xff) << 8)
+    }
+
+    #[cfg(windows)]
+    {
+        use std::os::windows::process::ExitStatusExt;
+
+        ExitStatus::from_raw(code as u32)
+    }
+}
@@ -0,0 +1,507 @@
+//! A simulated device, for operator training and for exercising the whole provisioning in CI without hardware
+//!
+//! When simulating, the `Simulated` provisioning backend replaces the hardware backends: the tools talking to
+//! the device (`esptool.py`, `espefuse.py` and the JTAG flasher) are not executed, the flashing is replaced with
+//! deterministic delays, the eFuse summaries are canned and the app logs are scripted. Everything else - the bundles,
+//! the UI and the provisioning pipeline - is real.
+
+use std::collections::HashMap;
+use std::fs;
+use std::io::Write;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use espflash::cli::monitor::LogFormat;
+use espflash::flasher::{FlashSize, ProgressCallbacks};
+
+use log::info;
+
+use crate::backend::{Connection, ProvisioningBackend};
+use crate::bundle::{mac_str, Chip, FlashData};
+use crate::efuse::{self, EfuseBurn, EfuseValue, KeyProtection};
+use crate::flash::ChipInfo;
+use crate::{Config, Simulation};
+
+/// The state of the simulated device
+#[derive(Debug)]
+struct Device {
+    /// The canned eFuse summary, if loaded from a file
+    efuses: Option<HashMap<String, EfuseValue>>,
+    /// The scripted app log lines
+    app_logs: Vec<String>,
+    /// The number of eFuse summaries read so far; used to give each simulated PCB a distinct MAC address
+    readouts: u32,
+}
+
+/// The app log lines of the simulated device, if no scripted app logs are provided
+const DEFAULT_APP_LOGS: &[&str] = &[
+    "I (31) boot: ESP-IDF v5.3 2nd stage bootloader",
+    "I (31) boot: compile time Jan  1 2025 00:00:00",
+    "I (35) boot: Enabling RNG early entropy source...",
+    "I (112) cpu_start: Pro cpu start user code",
+    "I (135) app_init: Application information:",
+    "I (140) main_task: Started on CPU0",
+    "I (150) main_task: Calling app_main()",
+    "I (160) app: Self-test OK",
+];
+
+/// Provisioning a simulated device
+#[derive(Clone, Debug)]
+pub struct Simulated {
+    /// The serial connection to the device, as configured (see `ProvisioningBackend::connection`)
+    connection: Connection,
+    /// The simulation settings
+    simulation: Simulation,
+    /// The state of the device, shared by all PCBs of the session
+    device: Arc<Mutex<Device>>,
+}
+
+impl Simulated {
+    /// Create the simulated device
+    ///
+    /// # Arguments
+    /// - `simulation` - the simulation settings
+    /// - `connection` - the serial connection to the device
+    pub fn new(simulation: &Simulation, connection: Connection) -> anyhow::Result<Self> {
+        let efuses = simulation
+            .efuse_summary
+            .as_deref()
+            .map(|path| {
+                let summary = fs::read_to_string(path)
+                    .with_context(|| format!("Loading simulated eFuse summary `{path}` failed"))?;
+
+                serde_json::from_str::<HashMap<String, EfuseValue>>(&summary)
+                    .with_context(|| format!("Parsing simulated eFuse summary `{path}` failed"))
+            })
+            .transpose()?;
+
+        let app_logs = if let Some(path) = simulation.app_logs.as_deref() {
+            fs::read_to_string(path)
+                .with_context(|| format!("Loading simulated app logs `{path}` failed"))?
+                .lines()
+                .map(str::to_string)
+                .collect()
+        } else {
+            DEFAULT_APP_LOGS
+                .iter()
+                .map(|line| line.to_string())
+                .collect()
+        };
+
+        info!("Simulating the device");
+
+        Ok(Self {
+            connection,
+            simulation: simulation.clone(),
+            device: Arc::new(Mutex::new(Device {
+                efuses,
+                app_logs,
+                readouts: 0,
+            })),
+        })
+    }
+
+    /// Simulate a tool talking to the device
+    fn tool(&self, operation: &str) {
+        info!("Simulating {operation}");
+
+        thread::sleep(Duration::from_millis(self.simulation.tool_delay_ms as _));
+    }
+}
+
+impl ProvisioningBackend for Simulated {
+    fn name(&self) -> &'static str {
+        "simulated"
+    }
+
+    fn connection(&self) -> &Connection {
+        &self.connection
+    }
+
+    fn tools(&self) -> bool {
+        false
+    }
+
+    fn configure(&mut self, conf: &Config) {
+        self.connection = Connection::new(conf, self.connection.tools.clone());
+    }
+
+    fn connect(&self, _chip: Chip) -> anyhow::Result<Option<ChipInfo>> {
+        info!("Simulating the device, chip detection skipped");
+
+        Ok(None)
+    }
+
+    fn erase(
+        &self,
+        _chip: Chip,
+        _flash_size: Option<FlashSize>,
+        _dry_run: bool,
+    ) -> anyhow::Result<()> {
+        self.tool("the erasing of the flash");
+
+        Ok(())
+    }
+
+    fn write<P>(
+        &self,
+        _chip: Chip,
+        _flash_size: Option<FlashSize>,
+        flash_data: impl IntoIterator<Item = anyhow::Result<FlashData>>,
+        _diff: bool,
+        _dry_run: bool,
+        progress: &mut P,
+    ) -> anyhow::Result<Vec<(u32, u128)>>
+    where
+        P: ProgressCallbacks + Send + Sync + 'static,
+    {
+        let flash_kbps = self.simulation.flash_kbps.max(1);
+
+        info!("Simulating the flashing at {flash_kbps}KB/s");
+
+        for data in flash_data {
+            let data = data?;
+            let len = data.data.len();
+            let step = Duration::from_millis(len as u64 * 1000 / 1024 / flash_kbps as u64 / 10);
+
+            progress.init(data.offset, len);
+
+            for tick in 1..=10 {
+                thread::sleep(step);
+                progress.update(len * tick / 10);
+            }
+
+            progress.finish(false);
+        }
+
+        Ok(Vec::new())
+    }
+
+    /// The flash of the simulated device reads as erased
+    fn read(&self, _chip: Chip, offset: u32, size: u32) -> anyhow::Result<Vec<u8>> {
+        self.tool(&format!("the reading of the flash at 0x{offset:08x}"));
+
+        Ok(vec![0xff; size as usize])
+    }
+
+    fn read_efuses<'a, I>(
+        &self,
+        _chip: Option<Chip>,
+        values: I,
+    ) -> anyhow::Result<HashMap<String, EfuseValue>>
+    where
+        I: Iterator<Item = &'a str>,
+    {
+        efuse::cached(
+            &self.connection.tools,
+            || {
+                self.tool("the eFuse summary");
+
+                Ok(self.device.lock().unwrap().efuses())
+            },
+            values,
+        )
+    }
+
+    fn burn_efuses<'a, I>(&self, _chip: Chip, _dry_run: bool, _values: I) -> anyhow::Result<String>
+    where
+        I: Iterator<Item = (&'a str, u32)>,
+    {
+        self.tool("the burning of the eFuses");
+
+        Ok(String::new())
+    }
+
+    fn burn_keys<'a, I>(
+        &self,
+        _protection: KeyProtection,
+        _chip: Chip,
+        _dry_run: bool,
+        _values: I,
+    ) -> anyhow::Result<String>
+    where
+        I: Iterator<Item = (&'a str, &'a [u8], &'a str)>,
+    {
+        self.tool("the burning of the keys");
+
+        Ok(String::new())
+    }
+
+    fn burn_key_digests<'a, I>(
+        &self,
+        _protection: KeyProtection,
+        _chip: Chip,
+        _dry_run: bool,
+        _values: I,
+    ) -> anyhow::Result<String>
+    where
+        I: Iterator<Item = (&'a str, &'a [u8], &'a str)>,
+    {
+        self.tool("the burning of the key digests");
+
+        Ok(String::new())
+    }
+
+    fn burn_batch(
+        &self,
+        _chip: Chip,
+        _dry_run: bool,
+        _burns: &[EfuseBurn<'_, [u8]>],
+    ) -> anyhow::Result<String> {
+        self.tool("the burning of the eFuses");
+
+        Ok(String::new())
+    }
+
+    fn burn_custom_mac(
+        &self,
+        _chip: Chip,
+        _dry_run: bool,
+        mac: &[u8; 6],
+    ) -> anyhow::Result<String> {
+        self.tool(&format!("the burning of the custom MAC {}", mac_str(mac)));
+
+        Ok(String::new())
+    }
+
+    fn run_app(&self, _chip: Chip) -> anyhow::Result<()> {
+        self.tool("the reset of the device");
+
+        Ok(())
+    }
+
+    /// The scripted app log lines are written to `out` with the configured delay between them, and then
+    /// the monitor waits for `stop` like the real one
+    fn monitor<W>(
+        &self,
+        _elf: Option<&[u8]>,
+        _baud: u32,
+        _log_format: LogFormat,
+        stop: Arc<AtomicBool>,
+        _input: Option<mpsc::Receiver<Vec<u8>>>,
+        mut out: W,
+    ) -> anyhow::Result<()>
+    where
+        W: Write,
+    {
+        let lines = self.device.lock().unwrap().app_logs.clone();
+        let delay = Duration::from_millis(self.simulation.app_log_delay_ms as _);
+
+        for line in lines {
+            if stop.load(Ordering::SeqCst) {
+                break;
+            }
+
+            thread::sleep(delay);
+
+            writeln!(out, "{line}")?;
+            out.flush()?;
+        }
+
+        while !stop.load(Ordering::SeqCst) {
+            thread::sleep(Duration::from_millis(50));
+        }
+
+        Ok(())
+    }
+}
+
+impl Device {
+    /// Return the eFuse summary of the simulated PCB
+    ///
+    /// Unless a canned summary is provided, a minimal summary with a distinct MAC address per PCB is synthesized
+    fn efuses(&mut self) -> HashMap<String, EfuseValue> {
+        if let Some(efuses) = self.efuses.as_ref() {
+            return efuses.clone();
+        }
+
+        self.readouts += 1;
+
+        // A locally administered MAC address, in the format of `espefuse.py`
+        let readout = self.readouts.to_be_bytes();
+        let mac = format!(
+            "{} (OK)",
+            mac_str(&[0x02, 0x5a, 0x00, readout[1], readout[2], readout[3]])
+        );
+
+        [
+            ("MAC", "Factory MAC address", 48, serde_json::json!(mac)),
+            (
+                "WAFER_VERSION_MAJOR",
+                "WAFER version major",
+                2,
+                serde_json::json!(0),
+            ),
+            (
+                "WAFER_VERSION_MINOR",
+                "WAFER version minor",
+                4,
+                serde_json::json!(1),
+            ),
+        ]
+        .into_iter()
+        .map(|(name, description, bit_len, value)| {
+            (
+                name.to_string(),
+                EfuseValue {
+                    bit_len,
+                    block: 1,
+                    category: "identity".to_string(),
+                    description: description.to_string(),
+                    efuse_type: if value.is_string() {
+                        "bytes:6"
+                    } else {
+                        "uint:8"
+                    }
+                    .to_string(),
+                    name: name.to_string(),
+                    pos: None,
+                    readable: true,
+                    value,
+                    word: None,
+                    writeable: true,
+                },
+            )
+        })
+        .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use futures_lite::future::block_on;
+
+    use zip::write::FileOptions;
+    use zip::ZipWriter;
+
+    use crate::backend::Backend;
+    use crate::events::{Events, ProvisioningEvent};
+    use crate::input::Answers;
+    use crate::loader::dir::DirLoader;
+    use crate::model::{Model, State};
+    use crate::permissions::ToolRunner;
+    use crate::task::Task;
+    use crate::{AppRun, Label, LabelPrinter};
+
+    use super::*;
+
+    /// Write a ZIP bundle with an app image and an eFuse to be burned
+    fn write_bundle(path: &std::path::Path) {
+        let mut zip = ZipWriter::new(fs::File::create(path).unwrap());
+
+        for (name, content) in [
+            ("params.toml", b"chip = \"Esp32\"\n".as_slice()),
+            ("images/ota_0.bin", [0xaa; 1024].as_slice()),
+            ("efuses/param-JTAG_DISABLE-0x1", b"".as_slice()),
+        ] {
+            zip.start_file(name, FileOptions::<()>::default()).unwrap();
+            zip.write_all(content).unwrap();
+        }
+
+        zip.finish().unwrap();
+    }
+
+    #[test]
+    fn provision_simulated_pcb() {
+        let bundles = tempfile::tempdir().unwrap();
+        let work = tempfile::tempdir().unwrap();
+
+        write_bundle(&bundles.path().join("PCB1.bundle"));
+
+        let answer_file = work.path().join("answers.toml");
+        fs::write(&answer_file, "[inputs]\n\"PCB ID\" = \"PCB-0001\"\n").unwrap();
+
+        // The label printer device is a plain file, so that the readouts printed on the label can be checked
+        let label_file = work.path().join("label.txt");
+        fs::write(&label_file, "").unwrap();
+
+        let mut conf = Config::new();
+        conf.simulate = Some(Simulation {
+            flash_kbps: 100_000,
+            tool_delay_ms: 0,
+            app_log_delay_ms: 0,
+            ..Simulation::new()
+        });
+        conf.batch_count = Some(1);
+        conf.skip_confirmations = true;
+        conf.destructive_ack = true;
+        conf.efuse_dry_run = false;
+        conf.efuse_snapshots = false;
+        conf.pcb_id_readout = true;
+        conf.app_run = AppRun::Capture {
+            pattern: r"app: Self-test (?P<SELF_TEST>\w+)".to_string(),
+            timeout_secs: 10,
+            artifacts: Vec::new(),
+        };
+        conf.label = Some(Label {
+            template: "${Bundle};${PCB ID};${MAC};${WAFER_VERSION_MINOR};${SELF_TEST}".to_string(),
+            template_file: None,
+            printer: LabelPrinter::Device {
+                path: label_file.to_str().unwrap().to_string(),
+            },
+        });
+
+        let events = Events::new();
+        let received = events.subscribe();
+
+        let model =
+            Arc::new(Model::new(log::LevelFilter::Info, true, 0, 0, 0).with_events(events.clone()));
+
+        let backend = Backend::new(&conf, Arc::new(ToolRunner::new(&conf).unwrap())).unwrap();
+        let answers = Answers::new(answer_file.to_str(), false).unwrap();
+
+        block_on(
+            Task::new(
+                model.clone(),
+                &conf,
+                backend,
+                Vec::<DirLoader>::new(),
+                DirLoader::new(bundles.path().to_path_buf(), false, None),
+                (),
+            )
+            .run(answers),
+        )
+        .unwrap();
+
+        // The summary of the PCB
+        model.access(|inner| {
+            assert_eq!(inner.stats.provisioned, 1);
+
+            let State::Status(status) = &inner.state else {
+                panic!("Unexpected final state: {:?}", inner.state);
+            };
+
+            assert!(!status.error);
+            assert_eq!(status.title.trim(), "PCB1.bundle");
+            assert!(status.message.starts_with("Provisioning complete"));
+            assert!(status.message.contains("Cycle time"));
+        });
+
+        let events = received.try_iter().collect::<Vec<_>>();
+
+        assert!(events
+            .iter()
+            .any(|event| matches!(event, ProvisioningEvent::FlashProgress { .. })));
+        assert!(events.iter().any(
+            |event| matches!(event, ProvisioningEvent::EfuseBurned { name } if name.contains("JTAG_DISABLE"))
+        ));
+        assert!(events.iter().any(
+            |event| matches!(event, ProvisioningEvent::Provisioned { bundle } if bundle == "PCB1.bundle")
+        ));
+
+        // The user, eFuse and app run readouts of the PCB
+        let label = fs::read_to_string(&label_file).unwrap();
+        let readouts = label.split(';').collect::<Vec<_>>();
+
+        assert_eq!(readouts.len(), 5);
+        assert_eq!(readouts[0], "PCB1.bundle");
+        assert_eq!(readouts[1], "PCB-0001");
+        assert!(readouts[2].starts_with("02:5a:00:"));
+        assert_eq!(readouts[3], "1");
+        assert_eq!(readouts[4], "OK");
+    }
+}
@@ -0,0 +1,95 @@
+//! Audible feedback on the outcome of the provisioning of a PCB
+//!
+//! The sounds are played on a separate thread, driven by the provisioning events (see `events`),
+//! so that a long WAV file never delays the provisioning of the next PCB
+
+use core::time::Duration;
+
+use std::io::Write;
+use std::thread;
+
+use anyhow::Context;
+
+use log::warn;
+
+use crate::events::{Events, ProvisioningEvent};
+use crate::{Sound, SoundEffect};
+
+/// Start playing the configured sounds on the outcome of each PCB, until the events of the run are dropped
+pub(crate) fn start(conf: &Sound, events: &Events) -> anyhow::Result<()> {
+    for effect in [&conf.success, &conf.failure].into_iter().flatten() {
+        if let SoundEffect::Wav { path } = effect {
+            if !cfg!(feature = "sound") {
+                anyhow::bail!("Playing WAV files requires the `sound` feature");
+            }
+
+            std::fs::metadata(path)
+                .with_context(|| format!("Cannot access sound file `{path}`"))?;
+        }
+    }
+
+    let conf = conf.clone();
+    let events = events.subscribe();
+
+    thread::Builder::new()
+        .name("sound".to_string())
+        .spawn(move || {
+            for event in events {
+                let effect = match event {
+                    ProvisioningEvent::Provisioned { .. } => conf.success.as_ref(),
+                    ProvisioningEvent::Error { .. } => conf.failure.as_ref(),
+                    _ => None,
+                };
+
+                if let Some(effect) = effect {
+                    if let Err(err) = play(effect) {
+                        warn!("Playing sound failed: {err:#}");
+                    }
+                }
+            }
+        })
+        .context("Starting the sound thread failed")?;
+
+    Ok(())
+}
+
+/// Play the sound effect, blocking until it is over
+fn play(effect: &SoundEffect) -> anyhow::Result<()> {
+    match effect {
+        SoundEffect::Bell { count, interval_ms } => {
+            // The bell goes to stderr, so that it never ends up in the piped output with `no_ui`
+            let mut stderr = std::io::stderr();
+
+            for index in 0..*count {
+                if index > 0 {
+                    thread::sleep(Duration::from_millis(*interval_ms as _));
+                }
+
+                stderr.write_all(b"\x07")?;
+                stderr.flush()?;
+            }
+
+            Ok(())
+        }
+        SoundEffect::Wav { path } => play_wav(path),
+    }
+}
+
+#[cfg(feature = "sound")]
+fn play_wav(path: &str) -> anyhow::Result<()> {
+    let file = std::fs::File::open(path).with_context(|| format!("Opening `{path}` failed"))?;
+
+    let (_stream, handle) =
+        rodio::OutputStream::try_default().context("No audio output available")?;
+    let sink = rodio::Sink::try_new(&handle)?;
+
+    sink.append(rodio::Decoder::new_wav(std::io::BufReader::new(file))?);
+    sink.sleep_until_end();
+
+    Ok(())
+}
+
+#[cfg(not(feature = "sound"))]
+fn play_wav(_path: &str) -> anyhow::Result<()> {
+    anyhow::bail!("Playing WAV files requires the `sound` feature")
+}
@@ -1,54 +1,156 @@
 use core::fmt::{self, Display};
 use core::future::Future;
-use core::pin::pin;
+use core::pin::{pin, Pin};
 
 use std::fmt::Write as _;
-use std::io::{Seek, Write};
+use std::io::{Read, Seek, Write};
+use std::path::PathBuf;
 use std::sync::atomic::{AtomicBool, Ordering};
-use std::sync::Mutex;
+use std::sync::{mpsc, Mutex};
+use std::time::SystemTime;
 
 use alloc::sync::Arc;
 
 use anyhow::Context;
 
+use base64::Engine;
+
 use embassy_futures::select::{select, select3, Either, Either3};
 use embassy_time::{Duration, Ticker};
 
 use espflash::cli::monitor::LogFormat;
-use espflash::flasher::ProgressCallbacks;
+use espflash::flasher::{FlashSize, ProgressCallbacks};
 
 use log::{error, info, warn};
 
 use tempfile::NamedTempFile;
 
-use crate::bundle::{Bundle, Chip, Efuse, Params, ProvisioningStatus};
-use crate::flash::{self, encrypt, DEFAULT_BAUD_RATE};
+use crate::audit;
+use crate::backend::{Backend, ProvisioningBackend};
+use crate::bundle::{
+    mac_str, Bundle, Chip, Efuse, FlashData, Image, OtaLayout, Params, ProvisioningStatus,
+};
+use crate::certificate;
+use crate::coredump;
+use crate::escrow;
+use crate::events::{Events, ProvisioningEvent};
+use crate::flash::{self, EncryptPipeline, DEFAULT_BAUD_RATE};
 use crate::input::{TaskConfirmationOutcome, TaskInput, TaskInputOutcome};
-use crate::loader::BundleLoader;
-use crate::model::{AppLogs, FileLogs, Model, Processing, Provision, Readout, State};
+use crate::label;
+use crate::loader::cache::CachedLoader;
+use crate::loader::{BundleLoader, BundlePoolEmpty};
+use crate::lookup;
+use crate::measure;
+use crate::model::{
+    AppLogs, CycleStep, CycleStepStatus, FileLogs, Highlight, Model, PartTableDescription,
+    PartTablePick, PortDescription, PortPick, Preview, Processing, Provision, Readout, State,
+};
+use crate::nvs_keys;
+use crate::plugin::{self, PluginContext};
+use crate::readout;
+use crate::report::{ImageChecksum, StepOutcome};
+use crate::sensor;
+use crate::session::Session;
 use crate::uploader::BundleLogsUploader;
 use crate::utils::futures::unblock;
+use crate::utils::hash::sha256_hex;
 use crate::utils::linewrite::LineWrite;
-use crate::{efuse, monitor, AppRun};
-use crate::{BundleIdentification, Config};
+use crate::utils::secret::Secret;
+use crate::work_order;
+use crate::{efuse, jig, jtag, ota, AppLogFormat, AppRun, AppRunScriptStep, OtaVerify};
+use crate::{
+    BundleIdentification, Config, ConfigOverride, Failure, OtaBoot, PluginHook, ReadoutSource,
+};
 
 extern crate alloc;
 
 /// A task that runs the factory application and represents the lifecycle states of provisioning a bundle
 /// (readouts, preparing, provisioning, etc.)
-pub struct Task<'a, B, L, U> {
+pub struct Task<'a, B, L, U, K = Backend> {
     model: Arc<Model>,
-    conf: &'a Config,
-    bundle_base_loader: Option<B>,
-    bundle_loader: L,
+    base_conf: &'a Config,
+    /// The configuration in effect for the PCB being provisioned,
+    /// i.e. `base_conf` with the configuration override of the loaded bundle (if any) applied
+    conf: Config,
+    /// The loaders of the base bundle layers, from the bottom layer upwards
+    bundle_base_loaders: Vec<B>,
+    /// `None` only while the loader is fetching the bundle of the next PCB in the background
+    /// (see `Config::bundle_prefetch`)
+    bundle_loader: Option<L>,
+    /// The bundle of the next PCB, if it was prefetched
+    prefetched_bundle: Option<anyhow::Result<(String, NamedTempFile)>>,
+    /// The name of the bundle loaded for the PCB being provisioned; once the PCB is provisioned, the bundle is
+    /// committed with the bundle loader, otherwise it is released (see `BundleLoader::commit` and `BundleLoader::release`)
+    claimed_bundle: Option<String>,
+    /// Whether the claimed bundle was (possibly partially) written to the PCB, i.e. its flashing or its eFuse burning
+    /// was started, so that it must not be loaded again for another PCB
+    claimed_bundle_written: bool,
+    /// The bundles of the provisioned PCBs, whose commit is deferred while the bundle loader is prefetching
+    provisioned_bundles: Vec<String>,
     bundle_logs_uploader: U,
+    /// The serial port picked by the operator for the session, if any (see `Config::port_pick`)
+    port: Option<String>,
+    /// The provisioning backend talking to the chip, shared by all PCBs of the session
+    /// and configured for the PCB being provisioned (see `ProvisioningBackend::configure`)
+    backend: K,
+    /// The flash encryption key generated for the PCB being provisioned (see `Config::flash_encrypt_keygen`)
+    ///
+    /// Kept outside of the provisioning state, which is restored when a provisioning step is retried,
+    /// as the key might be burned already
+    flash_key: Option<Secret>,
+    /// The configuration override of `Config::config_override_file` (if any), with the modification time
+    /// of the file it was read from
+    config_override: Option<(SystemTime, ConfigOverride)>,
+    /// Whether a unit of the work order is claimed on the server for the PCB being provisioned (see `WorkOrder::url`)
+    work_order_claimed: bool,
+}
+
+/// The kind of a PCB readout (see `Task::step1_readout`), which selects the source and the validation rules of the readout
+#[derive(Debug, Copy, Clone, Eq, PartialEq)]
+enum ReadoutKind {
+    DeviceId,
+    PcbId,
+    TestJigId,
+    /// The extra readout with that index in `Config::extra_readouts`
+    Extra(usize),
 }
 
-impl<'a, B, L, U> Task<'a, B, L, U>
+/// The background fetching of the bundle of the next PCB (see `Config::bundle_prefetch`)
+type Prefetch<'p, L> = Pin<Box<dyn Future<Output = Prefetched<L>> + 'p>>;
+/// The outcome of the prefetching: the bundle loader, and the prefetched bundle
+type Prefetched<L> = (L, anyhow::Result<(String, NamedTempFile)>);
+
+/// The name of the operator ID readout
+const OPERATOR_ID: &str = "Operator ID";
+/// The name of the Device ID readout
+const DEVICE_ID: &str = "Device ID";
+/// The name of the PCB ID readout
+const PCB_ID: &str = "PCB ID";
+/// The name of the Test JIG ID readout
+const TEST_JIG_ID: &str = "Test JIG ID";
+
+/// The name of the readout with the ID of the work order the PCB is provisioned against
+const WORK_ORDER: &str = "Work Order";
+/// The name of the readout with the SHA-256 hash of the generated NVS keys
+const NVS_KEYS_HASH: &str = "NVS Keys Hash";
+/// The name of the readout with the SHA-256 hash of the generated flash encryption key
+const FLASH_KEY_HASH: &str = "Flash Encryption Key Hash";
+/// The name of the PCB logs file with the (base64-encoded, raw) coredump of a failed app run
+const COREDUMP_FILE_NAME: &str = "coredump.b64";
+/// The name of the PCB logs file with the decoded coredump of a failed app run
+const COREDUMP_DECODED_FILE_NAME: &str = "coredump.txt";
+/// The name of the PCB logs file with the checksums of the flashed images
+const CHECKSUMS_FILE_NAME: &str = "checksums.csv";
+/// The maximum size of the app log captured for matching a multi-line `AppRun::MatchPattern`;
+/// only the most recent app log lines are kept beyond it
+const RUN_LOG_MAX_SIZE: usize = 1024 * 1024;
+
+impl<'a, B, L, U, K> Task<'a, B, L, U, K>
 where
     B: BundleLoader,
     L: BundleLoader,
     U: BundleLogsUploader,
+    K: ProvisioningBackend + Clone + Send + 'static,
 {
     /// Create a new task
     ///
@@ -57,28 +159,46 @@ where
     ///   Shared between the task, the UI (`View`) and the input processing (`Input`), i.e.
     ///   the task modifies the model, the UI renders the model and the input processing triggers model changes on terminal resize events (MVC)
     /// - `conf` - the configuration of the task
-    /// - `bundle_base_loader` - An optional loader used to load the base bundle; the base bundle (if used)
-    ///   usually contains the device-independent payloads like the bootloader, the partition image
-    ///   and the factory app image
-    /// - `bundle_loader` - The loader used to load the bundle; in case `bundle_base_loader` is used, this
-    ///   loader is used to load the device-specific payloads like the NVS partitions. The two bundles are then merged
+    /// - `backend` - the provisioning backend talking to the chip (see `Backend::new`)
+    /// - `bundle_base_loaders` - The loaders used to load the layers of the base bundle, from the bottom layer upwards;
+    ///   the base bundle layers (if any) usually contain the device-independent payloads like the bootloader,
+    ///   the partition image and the factory app image (e.g. a common-platform layer and a product layer)
+    /// - `bundle_loader` - The loader used to load the bundle; in case `bundle_base_loaders` are used, this
+    ///   loader is used to load the device-specific payloads like the NVS partitions. The layers are then merged in order,
+    ///   with the bundle of this loader being the top layer
     /// - `bundle_logs_uploader` - The uploader used to upload the logs from the device provisioning to the server
     pub fn new(
         model: Arc<Model>,
         conf: &'a Config,
-        bundle_base_loader: Option<B>,
+        backend: K,
+        bundle_base_loaders: Vec<B>,
         bundle_loader: L,
         bundle_logs_uploader: U,
     ) -> Self {
         Self {
             model,
-            conf,
-            bundle_base_loader,
-            bundle_loader,
+            base_conf: conf,
+            conf: conf.clone(),
+            bundle_base_loaders,
+            bundle_loader: Some(bundle_loader),
+            prefetched_bundle: None,
+            claimed_bundle: None,
+            claimed_bundle_written: false,
+            provisioned_bundles: Vec::new(),
             bundle_logs_uploader,
+            port: None,
+            backend,
+            flash_key: None,
+            config_override: None,
+            work_order_claimed: false,
         }
     }
 
+    /// Return the session being recorded or replayed, if any (see `Config::session_record` and `Config::session_replay`)
+    fn session(&self) -> &Arc<Session> {
+        self.backend.connection().tools.session()
+    }
+
     /// Run the factory bundle provisioning task in a loop as follows:
     /// - Step 1: eFuse readouts (read the necessary IDs from the chip eFuse memory)
     /// - Step 2: Readouts (read the necessary IDs from the user, e.g. Device ID, PCB ID, Test JIG ID)
@@ -87,15 +207,65 @@ where
     /// - Step 4: Provision the bundle by flashing and optionally efusing the chip with the bundle content
     /// - Step 5: Save the log output to a file and upload it to the server
     ///
-    /// Repeat the above steps until the user quits
+    /// Repeat the above steps until the user quits, or - in batch mode (see `Config::batch_count`) - until the batch is complete
     ///
     /// Arguments:
     /// - `input` - the input helper to process terminal events
     ///   Necessary as some states require direct user input (e.g. readouts)
     pub async fn run(&mut self, input: impl TaskInput + Clone) -> anyhow::Result<()> {
-        let result = self.step(input).await;
+        let audit_key = self
+            .conf
+            .audit_signing_key
+            .as_deref()
+            .map(audit::load_key)
+            .transpose()?;
+
+        self.model.modify(|inner| {
+            inner.logs.audit.set_key(audit_key);
+            inner.ui_locale = self.conf.ui_locale.clone();
+        });
+
+        if !self.session().replaying() && self.conf.simulate.is_none() {
+            if let Err(err) = self.bundle_logs_uploader.upload_pending().await {
+                self.model.events().emit(ProvisioningEvent::UploadFailed {
+                    message: format!("{err:#}"),
+                });
+
+                warn!("Uploading the pending logs failed: {err:?}");
+            }
+        }
+
+        if let Some(work_order) = self.base_conf.work_order.clone() {
+            let progress = unblock("work-order", move || work_order::start(&work_order)).await?;
+
+            self.model.modify(|inner| inner.work_order = Some(progress));
+        }
+
+        let result = match self.acknowledge(input.clone()).await {
+            Ok(()) => match self.pick_port(input.clone()).await {
+                Ok(()) => self.step(input).await,
+                Err(err) => Err(err),
+            },
+            Err(err) => Err(err),
+        };
+
+        self.release_work_order().await;
+
+        let provisioned = self.model.access(|inner| inner.stats.provisioned);
 
         match result {
+            Ok(_) | Err(TaskError::Quit)
+                if self
+                    .base_conf
+                    .batch_count
+                    .is_some_and(|count| provisioned < count as usize) =>
+            {
+                Err(anyhow::anyhow!(
+                    "{provisioned} of {} PCBs provisioned",
+                    self.base_conf.batch_count.unwrap()
+                )
+                .context(Failure::Incomplete))
+            }
             Ok(_) | Err(TaskError::Quit) => {
                 info!("Quit by user request");
                 Ok(())
@@ -110,7 +280,454 @@ where
         }
     }
 
-    async fn step(&mut self, mut input: impl TaskInput + Clone) -> Result<(), TaskError> {
+    /// Load and prepare the bundle exactly like during provisioning (i.e. with merging, the configuration override
+    /// and the base bundle cache), and return a plan of what would be flashed and burned
+    ///
+    /// The hardware is not touched, so the plan can be used for validating bundles in CI
+    ///
+    /// Arguments:
+    /// - `bundle_id` - the ID of the bundle to be loaded, if the bundle loader requires one
+    pub async fn plan(&mut self, bundle_id: Option<&str>) -> anyhow::Result<String> {
+        self.prep_bundle(bundle_id).await?;
+
+        // Nothing gets provisioned, so a claimed bundle is returned to its source right away
+        self.settle_bundles().await;
+
+        let bundle = self
+            .model
+            .access(|inner| inner.state.provision().bundle.clone());
+
+        let conf = &self.conf;
+        let tools = &self.backend.connection().tools;
+        let chip = bundle.params.chip;
+        let port = conf.port.as_deref();
+        let efuse_baud = conf.efuse_speed.map(|speed| speed.to_string());
+        let efuse_baud = efuse_baud.as_deref();
+
+        let mut plan = String::new();
+
+        writeln!(&mut plan, "{bundle}")?;
+
+        if let Some(birth_certificate) = &conf.birth_certificate {
+            writeln!(
+                &mut plan,
+                "A birth certificate will be flashed to partition `{}`",
+                birth_certificate.partition
+            )?;
+        }
+
+        if let Some(nvs_keys) = &conf.nvs_keys {
+            writeln!(
+                &mut plan,
+                "Generated NVS keys will be flashed to partition `{}`{}",
+                nvs_keys
+                    .partition
+                    .as_deref()
+                    .or(bundle.nvs_keys_partition())
+                    .unwrap_or("(none)"),
+                if nvs_keys.escrow.is_some() {
+                    " and escrowed with the logs"
+                } else {
+                    ""
+                }
+            )?;
+        }
+
+        if let Some(keygen) = &conf.flash_encrypt_keygen {
+            writeln!(
+                &mut plan,
+                "A generated flash encryption key will be burned to eFuse block `{}` and escrowed with the logs",
+                keygen.block.as_deref().unwrap_or(escrow::DEFAULT_BLOCK)
+            )?;
+        }
+
+        if let Some(otadata_boot) = conf.otadata_boot {
+            writeln!(
+                &mut plan,
+                "A generated `otadata` selecting boot slot `{otadata_boot}` will be flashed to partition `{}`",
+                bundle.otadata_partition().unwrap_or("(none)")
+            )?;
+        }
+
+        writeln!(
+            &mut plan,
+            "Flash ({}{}{}{}):",
+            if conf.flash_jtag.is_some() {
+                "JTAG"
+            } else if conf.flash_esptool {
+                "esptool.py"
+            } else {
+                "native flasher, equivalent esptool.py commands"
+            },
+            if conf.flash_dry_run { ", dry run" } else { "" },
+            if conf.flash_diff
+                && !conf.flash_erase
+                && !conf.flash_esptool
+                && conf.flash_jtag.is_none()
+            {
+                ", only the images differing from the flash content"
+            } else {
+                ""
+            },
+            if conf.flash_encrypt {
+                ", encrypted partitions are encrypted on the host"
+            } else {
+                ""
+            },
+        )?;
+
+        let use_stub = !conf.flash_no_stub;
+
+        if conf.flash_erase {
+            let command = if let Some(flash_jtag) = conf.flash_jtag.as_ref() {
+                jtag::erase_command(flash_jtag, chip)
+            } else {
+                flash::erase_esptool_command(tools, port, chip, use_stub, conf.flash_speed)?
+            };
+
+            writeln!(&mut plan, "  {command:?}")?;
+        }
+
+        for flash_data in bundle.get_flash_data() {
+            let image = PathBuf::from(format!("image-0x{:08x}.bin", flash_data.offset));
+
+            let command = if let Some(flash_jtag) = conf.flash_jtag.as_ref() {
+                jtag::flash_command(flash_jtag, chip, flash_data.offset, &image)
+            } else {
+                flash::flash_esptool_command(
+                    tools,
+                    port,
+                    chip,
+                    use_stub,
+                    conf.flash_speed,
+                    bundle.params.flash_size,
+                    conf.flash_compressed(),
+                    flash_data.offset,
+                    &image,
+                )?
+            };
+
+            writeln!(&mut plan, "  {command:?}")?;
+        }
+
+        if let Some(flash_jtag) = conf.flash_jtag.as_ref() {
+            writeln!(&mut plan, "  {:?}", jtag::reset_command(flash_jtag, chip))?;
+        }
+
+        let mut keys = Vec::new();
+        let mut digests = Vec::new();
+        let mut custom_mac = None;
+        let mut params = Vec::new();
+
+        for efuse in &bundle.efuse_mapping {
+            match &efuse.efuse {
+                Efuse::Key {
+                    block,
+                    purpose,
+                    read_protect,
+                    write_protect,
+                    ..
+                } => keys.push((
+                    block.as_str(),
+                    PathBuf::from(format!("key-{block}.bin")),
+                    purpose.as_str(),
+                    efuse::KeyProtection::new(
+                        read_protect.unwrap_or(conf.efuse_protect_keys),
+                        write_protect.unwrap_or(conf.efuse_protect_keys),
+                    ),
+                )),
+                Efuse::KeyDigest {
+                    block,
+                    purpose,
+                    read_protect,
+                    write_protect,
+                    ..
+                } => digests.push((
+                    block.as_str(),
+                    PathBuf::from(format!("digest-{block}.bin")),
+                    purpose.as_str(),
+                    efuse::KeyProtection::new(
+                        read_protect.unwrap_or(conf.efuse_protect_digests),
+                        write_protect.unwrap_or(conf.efuse_protect_digests),
+                    ),
+                )),
+                Efuse::CustomMac { mac } => custom_mac = Some(*mac),
+                Efuse::Param { name, value } => params.push((name.as_str(), *value)),
+            }
+        }
+
+        writeln!(
+            &mut plan,
+            "eFuse{}:",
+            if conf.efuse_dry_run { " (dry run)" } else { "" }
+        )?;
+
+        if conf.efuse_batch {
+            let mut burns = Vec::new();
+
+            for protection in efuse::KeyProtection::ALL {
+                let keys = keys
+                    .iter()
+                    .filter(|key| key.3 == protection)
+                    .map(|(block, path, purpose, _)| (*block, path.as_path(), *purpose))
+                    .collect::<Vec<_>>();
+
+                if !keys.is_empty() {
+                    burns.push(efuse::EfuseBurn::Keys(protection, keys));
+                }
+            }
+
+            for protection in efuse::KeyProtection::ALL {
+                let digests = digests
+                    .iter()
+                    .filter(|digest| digest.3 == protection)
+                    .map(|(block, path, purpose, _)| (*block, path.as_path(), *purpose))
+                    .collect::<Vec<_>>();
+
+                if !digests.is_empty() {
+                    burns.push(efuse::EfuseBurn::KeyDigests(protection, digests));
+                }
+            }
+
+            if let Some(mac) = custom_mac.as_ref() {
+                burns.push(efuse::EfuseBurn::CustomMac(mac));
+            }
+
+            if !params.is_empty() {
+                burns.push(efuse::EfuseBurn::Params(params.clone()));
+            }
+
+            if !burns.is_empty() {
+                let command = efuse::burn_batch_command(tools, chip, port, efuse_baud, &burns)?;
+
+                writeln!(&mut plan, "  {command:?}")?;
+            }
+
+            return Ok(plan);
+        }
+
+        // Keys and key digests with a different protection are burned with separate commands
+        for protection in efuse::KeyProtection::ALL {
+            if keys.iter().any(|key| key.3 == protection) {
+                let command = efuse::burn_keys_command(
+                    tools,
+                    protection,
+                    chip,
+                    port,
+                    efuse_baud,
+                    keys.iter()
+                        .filter(|key| key.3 == protection)
+                        .map(|(block, path, purpose, _)| (*block, path.as_path(), *purpose)),
+                )?;
+
+                writeln!(&mut plan, "  {command:?}")?;
+            }
+        }
+
+        for protection in efuse::KeyProtection::ALL {
+            if digests.iter().any(|digest| digest.3 == protection) {
+                let command = efuse::burn_key_digests_command(
+                    tools,
+                    protection,
+                    chip,
+                    port,
+                    efuse_baud,
+                    digests
+                        .iter()
+                        .filter(|digest| digest.3 == protection)
+                        .map(|(block, path, purpose, _)| (*block, path.as_path(), *purpose)),
+                )?;
+
+                writeln!(&mut plan, "  {command:?}")?;
+            }
+        }
+
+        if let Some(mac) = custom_mac.as_ref() {
+            let command = efuse::burn_custom_mac_command(tools, chip, port, efuse_baud, mac)?;
+
+            writeln!(&mut plan, "  {command:?}")?;
+        }
+
+        if !params.is_empty() {
+            let command =
+                efuse::burn_efuses_command(tools, chip, port, efuse_baud, params.iter().copied())?;
+
+            writeln!(&mut plan, "  {command:?}")?;
+        }
+
+        Ok(plan)
+    }
+
+    /// Request an explicit acknowledgment from the operator if the configuration has destructive settings,
+    /// so that an engineering configuration left on a station does not accidentally burn production PCBs
+    async fn acknowledge(&mut self, mut input: impl TaskInput) -> Result<(), TaskError> {
+        let settings = self.conf.destructive_settings();
+
+        if settings.is_empty() || self.conf.destructive_ack {
+            return Ok(());
+        }
+
+        let summary = settings
+            .iter()
+            .map(|setting| format!("- {setting}"))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        warn!("The configuration has destructive settings:\n{summary}");
+
+        if self.conf.skip_confirmations {
+            Err(anyhow::anyhow!(
+                "The configuration has destructive settings:\n{summary}\nRefusing to start without an acknowledgment; use `--yes-i-know`"
+            ))?;
+        }
+
+        self.model.modify(|inner| {
+            inner.state.error(
+                " Destructive settings ",
+                format!("The configuration irreversibly changes the PCBs:\n{summary}"),
+            );
+        });
+
+        match input.confirm("Acknowledge? <[Y]es/ENTER, [Q]uit>").await {
+            TaskConfirmationOutcome::Confirmed => {
+                info!("Destructive settings acknowledged");
+                Ok(())
+            }
+            _ => Err(TaskError::Quit),
+        }
+    }
+
+    /// Let the operator pick the serial port to be used for the session, if no port is configured
+    /// and more than one USB serial port is detected
+    async fn pick_port(&mut self, mut input: impl TaskInput) -> Result<(), TaskError> {
+        if !self.conf.port_pick
+            || self.conf.port.is_some()
+            || self.conf.skip_confirmations
+            || self.conf.flash_jtag.is_some()
+            || self.session().active()
+            || self.conf.simulate.is_some()
+        {
+            return Ok(());
+        }
+
+        let ports = flash::usb_serial_ports()?
+            .into_iter()
+            .map(|(name, info)| PortDescription {
+                name,
+                vid: info.vid,
+                pid: info.pid,
+                product: info.product,
+                serial_number: info.serial_number,
+            })
+            .collect::<Vec<_>>();
+
+        if ports.len() < 2 {
+            return Ok(());
+        }
+
+        info!("=== => Serial port pick");
+
+        self.model
+            .modify(|inner| inner.state = State::PortPick(PortPick::new(ports)));
+
+        loop {
+            let selection = self
+                .model
+                .access(|inner| inner.state.port_pick().selection.clone());
+
+            match input.input("Port number", &selection).await {
+                TaskInputOutcome::Modified(value) => {
+                    self.model.modify(|inner| {
+                        inner.state.port_pick_mut().selection = value;
+                    });
+                }
+                TaskInputOutcome::Done(value) => {
+                    let port = self.model.modify(|inner| {
+                        let port_pick = inner.state.port_pick_mut();
+                        port_pick.selection = value;
+
+                        port_pick
+                            .selected()
+                            .map(|index| port_pick.ports[index].name.clone())
+                    });
+
+                    if let Some(port) = port {
+                        info!("Serial port `{port}` picked for the session");
+
+                        self.port = Some(port);
+
+                        break Ok(());
+                    }
+
+                    warn!("Invalid port number");
+
+                    self.model.modify(|inner| {
+                        inner.state.port_pick_mut().selection.clear();
+                    });
+                }
+                TaskInputOutcome::StartOver => {
+                    self.model.modify(|inner| {
+                        inner.state.port_pick_mut().selection.clear();
+                    });
+                }
+                TaskInputOutcome::Quit => break Err(TaskError::Quit),
+            }
+        }
+    }
+
+    async fn step(&mut self, input: impl TaskInput + Clone) -> Result<(), TaskError> {
+        // The background fetching of the bundle of the next PCB, and its outcome
+        let mut prefetch = None;
+        let mut prefetched = None;
+
+        let result = self.steps(input, &mut prefetch, &mut prefetched).await;
+
+        // Take back the loader from the prefetching, so that the claimed bundles can be released
+        if let Some(pending) = prefetch.take() {
+            info!("Waiting for the prefetching of the bundle to complete");
+
+            prefetched = Some(pending.await);
+        }
+
+        if let Some((loader, bundle)) = prefetched.take() {
+            self.bundle_loader = Some(loader);
+            self.prefetched_bundle = Some(bundle);
+        }
+
+        if let Some(Ok((bundle_name, _))) = self.prefetched_bundle.take() {
+            self.release_bundle(&bundle_name).await;
+        }
+
+        // The PCB being provisioned when quitting (or when failing in batch mode) did not get provisioned
+        self.upload_failed_logs().await;
+
+        self.settle_bundles().await;
+
+        result
+    }
+
+    async fn steps<'p>(
+        &mut self,
+        mut input: impl TaskInput + Clone,
+        prefetch: &mut Option<Prefetch<'p, L>>,
+        prefetched: &mut Option<Prefetched<L>>,
+    ) -> Result<(), TaskError>
+    where
+        'a: 'p,
+        B: 'p,
+        L: 'p,
+        U: 'p,
+    {
+        // In batch mode, a failed step fails the run rather than offering a retry
+        let batch = self.base_conf.batch_count.is_some();
+        let propagate = |failure| {
+            if batch {
+                ErrPolicy::Fail(failure)
+            } else {
+                ErrPolicy::Propagate
+            }
+        };
+
         loop {
             {
                 self.model.modify(|inner| {
@@ -118,8 +735,14 @@ where
                 });
             }
 
+            if !self.claim_work_order().await? {
+                break;
+            }
+
             info!("========== Starting PCB provisioning ==========");
 
+            let started = std::time::Instant::now();
+
             let _guard = {
                 let model = self.model.clone();
 
@@ -131,7 +754,53 @@ where
                 })
             };
 
-            let (bundle_id, bundle_name, summary) = 'steps: loop {
+            let (bundle_id, bundle_name, chip, summary, artifacts) = 'steps: loop {
+                self.conf = self.base_conf.clone();
+                self.apply_config_override_file().await;
+
+                // The previous attempt (if any) was abandoned, so its PCB did not get provisioned
+                Self::prefetching(self.upload_failed_logs(), prefetch, prefetched).await;
+
+                // A new attempt might be on a different PCB
+                efuse::clear_summary_cache(&self.backend.connection().tools);
+                self.flash_key = None;
+
+                if self.port.is_some() {
+                    self.conf.port = self.port.clone();
+                }
+
+                self.backend.configure(&self.conf);
+
+                self.model.modify(|inner| {
+                    inner.banner = None;
+                    inner.cycle.reset();
+                });
+
+                self.model.events().emit(ProvisioningEvent::PcbStarted);
+
+                loop {
+                    let context = self.plugin_context(&[], None, None);
+
+                    let result = Self::handle(
+                        &self.model.clone(),
+                        self.step_hook(input.clone(), PluginHook::PreBoard, context),
+                        "Running pre-board plugins failed",
+                        propagate(Failure::Plugin),
+                        &mut input,
+                    )
+                    .await;
+
+                    match result {
+                        Ok(_) => break,
+                        Err(TaskError::Retry) | Err(TaskError::Canceled) => continue,
+                        Err(other) => Err(other)?,
+                    }
+                }
+
+                self.step0_login(&mut input).await?;
+
+                let operator = self.model.access(|inner| inner.operator.clone());
+
                 let mut readouts = Vec::new();
 
                 let bundle_id = loop {
@@ -146,14 +815,28 @@ where
                             && !self.conf.test_jig_id_readout
                             && !self.conf.test_jig_id.is_empty()
                         {
-                            readouts
-                                .push(("Test JIG ID".to_string(), self.conf.test_jig_id.clone()));
+                            readouts.push((TEST_JIG_ID.to_string(), self.conf.test_jig_id.clone()));
+                        }
+
+                        if fill_test_jig {
+                            if let Some(operator) = operator.as_ref() {
+                                readouts.push((OPERATOR_ID.to_string(), operator.clone()));
+                            }
+
+                            if let Some(work_order) = self.base_conf.work_order.as_ref() {
+                                readouts.push((WORK_ORDER.to_string(), work_order.id.clone()));
+                            }
                         }
                     };
 
                     info!("=== => STEP 1: manual readouts");
 
-                    let result = self.step1_readout(&mut input).await;
+                    self.model
+                        .modify(|inner| inner.cycle.start(CycleStep::Readout));
+
+                    let result =
+                        Self::prefetching(self.step1_readout(&mut input), prefetch, prefetched)
+                            .await;
 
                     match result {
                         Ok(_) => (),
@@ -162,14 +845,27 @@ where
                         Err(other) => Err(other)?,
                     }
 
-                    self.model.access(|inner| {
+                    self.model.access_mut(|inner| {
                         add_readouts(&inner.state.readout().readouts, true);
+                        inner.cycle.finish(CycleStep::Readout);
+
+                        ((), true)
                     });
 
+                    if self.conf.result_banner {
+                        let identity = banner_identity(&readouts, "");
+                        self.model.modify(|inner| inner.banner = Some(identity));
+                    }
+
                     info!("=== => STEP 2: eFuse readouts");
 
+                    self.model
+                        .modify(|inner| inner.cycle.start(CycleStep::Efuse));
+
                     let err_policy = if self.conf.efuse_ignore_failed_readouts {
                         ErrPolicy::Ignore
+                    } else if batch {
+                        ErrPolicy::Fail(Failure::EfuseReadout)
                     } else {
                         ErrPolicy::ExplicitIgnore
                     };
@@ -177,7 +873,12 @@ where
                     let efuse_values = loop {
                         let result = Self::handle(
                             &self.model.clone(),
-                            self.step2_prepare_efuse_readout(input.clone()),
+                            Self::reported(
+                                &self.model.clone(),
+                                "efuse-readout",
+                                "EFUSE_READOUT_FAILED",
+                                self.step2_prepare_efuse_readout(input.clone()),
+                            ),
                             "Preparing eFuse readouts failed",
                             err_policy,
                             &mut input,
@@ -197,20 +898,82 @@ where
 
                     add_readouts(&efuse_values, false);
 
+                    self.model
+                        .modify(|inner| inner.cycle.finish(CycleStep::Efuse));
+
+                    let plugin_readouts = loop {
+                        let context = self.plugin_context(&readouts, None, None);
+
+                        let result = Self::handle(
+                            &self.model.clone(),
+                            self.step_hook(input.clone(), PluginHook::PostReadout, context),
+                            "Running post-readout plugins failed",
+                            propagate(Failure::Plugin),
+                            &mut input,
+                        )
+                        .await;
+
+                        break match result {
+                            Ok(new_readouts) => new_readouts,
+                            Err(TaskError::Retry) => continue,
+                            Err(TaskError::Canceled) => continue 'steps,
+                            Err(other) => Err(other)?,
+                        };
+                    };
+
+                    readouts.extend(plugin_readouts);
+
+                    if let Some(pending) = prefetch.take() {
+                        info!("Waiting for the prefetching of the bundle to complete");
+
+                        *prefetched = Some(pending.await);
+                    }
+
+                    if let Some((loader, bundle)) = prefetched.take() {
+                        self.bundle_loader = Some(loader);
+                        self.prefetched_bundle = Some(bundle);
+                    }
+
                     break loop {
                         info!("=== => STEP 3: Bundle preparation");
 
+                        self.model
+                            .modify(|inner| inner.cycle.start(CycleStep::Prepare));
+
                         let result = Self::handle(
                             &self.model.clone(),
-                            self.step3_prepare(input.clone(), &readouts),
+                            Self::reported(
+                                &self.model.clone(),
+                                "bundle-prepare",
+                                "BUNDLE_PREPARE_FAILED",
+                                self.step3_prepare(input.clone(), &readouts),
+                            ),
                             "Preparing a bundle failed",
-                            ErrPolicy::Propagate,
+                            propagate(Failure::Bundle),
                             &mut input,
                         )
                         .await;
 
                         match result {
-                            Ok(bundle_id) => break bundle_id,
+                            Ok(bundle_id) => {
+                                self.model
+                                    .modify(|inner| inner.cycle.finish(CycleStep::Prepare));
+
+                                if self.conf.bundle_prefetch
+                                    && !self.conf.identifies_bundles()
+                                    && !self.session().active()
+                                {
+                                    info!("Prefetching the bundle of the next PCB");
+
+                                    let loader = self.bundle_loader.take().unwrap();
+                                    *prefetch = Some(Box::pin(Self::prefetch_bundle(
+                                        loader,
+                                        self.model.events().clone(),
+                                    )));
+                                }
+
+                                break bundle_id;
+                            }
                             Err(TaskError::Canceled) => continue 'steps,
                             Err(TaskError::Retry) => continue,
                             Err(other) => Err(other)?,
@@ -219,7 +982,12 @@ where
                 };
 
                 self.model.access_mut(|inner| {
-                    inner.state.provision_mut().readouts = readouts.clone();
+                    let provision = inner.state.provision_mut();
+                    provision.readouts = readouts.clone();
+
+                    if self.conf.result_banner {
+                        inner.banner = Some(banner_identity(&readouts, &provision.bundle.name));
+                    }
 
                     ((), true)
                 });
@@ -228,11 +996,29 @@ where
                     info!("=== => STEP 4: PCB provisioning");
 
                     if !self.conf.skip_confirmations {
-                        match input
-                            .confirm("Provision? <[Y]es/ENTER, [N]o/[C]ancel, [Q]uit>")
-                            .await
-                            .into()
-                        {
+                        if self.conf.provision_preview {
+                            self.model.modify(|inner| {
+                                let provision = inner.state.provision().clone();
+                                let details = self.preview_details(&provision.bundle);
+
+                                inner.state = State::Preview(Preview::new(provision, details));
+                            });
+                        }
+
+                        let result = Self::prefetching(
+                            input.confirm("Provision? <[Y]es/ENTER, [N]o/[C]ancel, [Q]uit>"),
+                            prefetch,
+                            prefetched,
+                        )
+                        .await;
+
+                        self.model.modify(|inner| {
+                            if let State::Preview(preview) = &inner.state {
+                                inner.state = State::Provision(preview.provision.clone());
+                            }
+                        });
+
+                        match result.into() {
                             Ok(_) => (),
                             Err(TaskError::Canceled) => continue 'steps,
                             Err(TaskError::Retry) => unreachable!(),
@@ -243,12 +1029,32 @@ where
                     // TODO: Not very efficient
                     let provision = self.model.access(|inner| inner.state.provision().clone());
 
-                    let result = Self::handle(
-                        &self.model.clone(),
-                        self.step4_provision(input.clone()),
-                        &format!("Provisioning bundle `{}` failed", provision.bundle.name),
-                        ErrPolicy::Propagate,
-                        &mut input,
+                    // Recorded with the readouts right away, so that they are covered by the birth certificate
+                    let pre_flash_samples = self.sample_sensors("pre-flash").await;
+
+                    self.model.modify(|inner| {
+                        inner
+                            .state
+                            .provision_mut()
+                            .readouts
+                            .extend(pre_flash_samples)
+                    });
+
+                    let result = Self::prefetching(
+                        Self::handle(
+                            &self.model.clone(),
+                            Self::reported(
+                                &self.model.clone(),
+                                "provision",
+                                "PROVISION_FAILED",
+                                self.step4_provision(input.clone()),
+                            ),
+                            &format!("Provisioning bundle `{}` failed", provision.bundle.name),
+                            propagate(Failure::Provision),
+                            &mut input,
+                        ),
+                        prefetch,
+                        prefetched,
                     )
                     .await;
 
@@ -264,17 +1070,56 @@ where
                         Err(other) => Err(other)?,
                     };
 
-                    let result = Self::handle(
-                        &self.model.clone(),
-                        self.step5_run_app(bundle_name.clone(), chip, input.clone()),
-                        &format!("Running app from bundle `{}` failed", bundle_name),
-                        ErrPolicy::Propagate,
-                        &mut input,
+                    let post_flash_samples = self.sample_sensors("post-flash").await;
+
+                    self.model.modify(|inner| {
+                        inner
+                            .state
+                            .provision_mut()
+                            .readouts
+                            .extend(post_flash_samples)
+                    });
+
+                    // Pick the readouts reported by the plugins during the provisioning, and the sensor samples
+                    readouts = self
+                        .model
+                        .access(|inner| inner.state.provision().readouts.clone());
+
+                    self.model
+                        .modify(|inner| inner.cycle.start(CycleStep::AppRun));
+
+                    let result = Self::prefetching(
+                        Self::handle(
+                            &self.model.clone(),
+                            Self::reported(
+                                &self.model.clone(),
+                                "app-run",
+                                "APP_RUN_FAILED",
+                                self.step5_run_app(
+                                    bundle_name.clone(),
+                                    chip,
+                                    provision.bundle.app_elf(),
+                                    provision.bundle.ota_layout(),
+                                    provision.bundle.coredump_partition(),
+                                    input.clone(),
+                                ),
+                            ),
+                            &format!("Running app from bundle `{}` failed", bundle_name),
+                            propagate(Failure::AppRun),
+                            &mut input,
+                        ),
+                        prefetch,
+                        prefetched,
                     )
                     .await;
 
-                    match result {
-                        Ok(_) => (),
+                    let capture = match result {
+                        Ok(capture) => {
+                            self.model
+                                .modify(|inner| inner.cycle.finish(CycleStep::AppRun));
+
+                            capture
+                        }
                         Err(TaskError::Canceled) => continue 'steps,
                         Err(TaskError::Retry) => {
                             self.model
@@ -285,26 +1130,183 @@ where
                         Err(other) => Err(other)?,
                     };
 
-                    break (bundle_id, bundle_name, readouts);
+                    readouts.extend(capture.readouts);
+
+                    readouts.extend(self.sample_sensors("post-app-run").await);
+
+                    loop {
+                        let result = Self::prefetching(
+                            Self::handle(
+                                &self.model.clone(),
+                                Self::reported(
+                                    &self.model.clone(),
+                                    "measurements",
+                                    "MEASUREMENTS_FAILED",
+                                    self.step_measure(input.clone()),
+                                ),
+                                "Taking the measurements failed",
+                                propagate(Failure::Measurement),
+                                &mut input,
+                            ),
+                            prefetch,
+                            prefetched,
+                        )
+                        .await;
+
+                        match result {
+                            Ok(measurements) => {
+                                readouts.extend(measurements);
+                                break;
+                            }
+                            Err(TaskError::Retry) => continue,
+                            Err(TaskError::Canceled) => continue 'steps,
+                            Err(other) => Err(other)?,
+                        }
+                    }
+
+                    loop {
+                        let context = self.plugin_context(
+                            &readouts,
+                            bundle_id.as_deref(),
+                            Some((&bundle_name, chip)),
+                        );
+
+                        let result = Self::handle(
+                            &self.model.clone(),
+                            self.step_hook(input.clone(), PluginHook::PostAppRun, context),
+                            "Running post-app-run plugins failed",
+                            propagate(Failure::Plugin),
+                            &mut input,
+                        )
+                        .await;
+
+                        match result {
+                            Ok(new_readouts) => {
+                                readouts.extend(new_readouts);
+                                break;
+                            }
+                            Err(TaskError::Retry) => continue,
+                            Err(TaskError::Canceled) => continue 'steps,
+                            Err(other) => Err(other)?,
+                        }
+                    }
+
+                    break (bundle_id, bundle_name, chip, readouts, capture.artifacts);
                 };
             };
 
+            self.check_cycle_time(started.elapsed());
+            self.account_work_order().await;
+
+            if let Some(label) = self.conf.label.clone() {
+                let label_bundle_name = bundle_name.clone();
+                let label_readouts = summary.clone();
+
+                let result = unblock("label", move || {
+                    label::print(&label, &label_bundle_name, &label_readouts)
+                })
+                .await;
+
+                if let Err(err) = result {
+                    error!("Printing the label failed: {err:?}");
+                }
+            }
+
             info!("========== PCB provisioning complete, uploading logs ==========");
 
-            let log_file = self
-                .model
-                .access_mut(|inner| (inner.logs.file.grab(), true));
+            self.model
+                .modify(|inner| inner.cycle.start(CycleStep::Upload));
 
-            if let Some(log_file) = log_file {
-                let log = FileLogs::finish(log_file, &summary)?;
-                self.bundle_logs_uploader
-                    .upload_logs(log, bundle_id.as_deref(), &bundle_name)
-                    .await?;
+            let (log_file, audit, audit_head, reports, attachments) =
+                self.model.access_mut(|inner| {
+                    let reports = self
+                        .conf
+                        .report_formats
+                        .iter()
+                        .map(|format| {
+                            inner
+                                .logs
+                                .report
+                                .render(*format, &bundle_name, &summary, &self.conf.summary_locale)
+                                .map(|report| (format.file_name(), report))
+                        })
+                        .collect::<anyhow::Result<Vec<_>>>();
+
+                    (
+                        (
+                            inner.logs.file.grab(),
+                            inner.logs.audit.to_json_lines(),
+                            inner.logs.audit.head(),
+                            reports,
+                            core::mem::take(&mut inner.logs.attachments),
+                        ),
+                        true,
+                    )
+                });
+
+            if self.session().replaying() {
+                info!("Replaying a session, logs upload skipped");
+            } else if self.conf.simulate.is_some() {
+                info!("Simulating the device, logs upload skipped");
+            } else if let Some(log_file) = log_file {
+                let mut files = vec![("audit.jsonl", audit?)];
+                files.extend(reports?);
+                files.extend(
+                    attachments
+                        .iter()
+                        .map(|(file, content)| (file.as_str(), content.clone())),
+                );
+                files.extend(
+                    artifacts
+                        .iter()
+                        .map(|(file, content)| (file.as_str(), content.clone())),
+                );
+
+                let log = FileLogs::finish(log_file, summary.iter().chain(&audit_head), &files)?;
+                Self::prefetching(
+                    self.bundle_logs_uploader
+                        .upload_logs(log, bundle_id.as_deref(), &bundle_name),
+                    prefetch,
+                    prefetched,
+                )
+                .await
+                .inspect_err(|err| {
+                    self.model.events().emit(ProvisioningEvent::UploadFailed {
+                        message: format!("{err:#}"),
+                    })
+                })?;
+            }
+
+            self.model
+                .modify(|inner| inner.cycle.finish(CycleStep::Upload));
+
+            // Only now the bundle is removed from its source, so that it is not lost if the provisioning fails
+            self.provisioned_bundles.extend(self.claimed_bundle.take());
+            self.claimed_bundle_written = false;
+            self.settle_bundles().await;
+
+            let context =
+                self.plugin_context(&summary, bundle_id.as_deref(), Some((&bundle_name, chip)));
+
+            if let Err(err) = self.run_plugins(PluginHook::PostBoard, context).await {
+                error!("Running post-board plugins failed: {err:?}");
+            }
+
+            if let Some(count) = self.base_conf.batch_count {
+                let provisioned = self.model.access(|inner| inner.stats.provisioned);
+
+                if provisioned >= count as usize {
+                    info!("========== Batch of {count} PCBs complete ==========");
+                    break;
+                }
+
+                info!("{provisioned} of {count} PCBs provisioned");
             }
 
             if !self.conf.skip_confirmations
                 && matches!(
-                    input.confirm("Continue? <Any key, [Q]uit>").await,
+                    Self::prefetching(self.confirm_continue(&mut input), prefetch, prefetched,)
+                        .await,
                     TaskConfirmationOutcome::Quit
                 )
             {
@@ -315,613 +1317,2923 @@ where
         Ok(())
     }
 
-    /// Step 1:
-    /// Process the readouts state by visualizing the eFuse readouts (if any) and
-    /// reading the necessary IDs from the user (if any)
-    async fn step1_readout(&mut self, mut input: impl TaskInput) -> Result<(), TaskError> {
-        if !self.conf.test_jig_id.is_empty() {
-            if self.conf.test_jig_id_readout {
-                warn!(
-                    "Ignoring the fixed value `{}` for the Test JIG ID from the configuration",
-                    self.conf.test_jig_id
-                );
-            } else {
-                info!("Readout `Test JIG ID`: `{}`", self.conf.test_jig_id);
-            }
+    /// Upload the logs of a PCB which did not get provisioned, together with its audit log, test reports and attachments
+    /// (e.g. the coredump or the eFuse summary snapshots), so that the failures can be analyzed
+    ///
+    /// Does nothing if no provisioning step of the PCB was started, or if its logs are uploaded already.
+    /// The logs are uploaded under the bundle name with a `.failed` suffix, so that they are not mistaken for the logs
+    /// of a provisioned bundle. Failing to upload the logs is not an error
+    async fn upload_failed_logs(&mut self) {
+        let uploaded = self.model.access(|inner| {
+            inner.cycle.is_pending()
+                || inner.cycle.steps.iter().any(|(step, status)| {
+                    *step == CycleStep::Upload && *status == CycleStepStatus::Done
+                })
+        });
+
+        if uploaded {
+            return;
         }
 
-        let init = |readouts: &mut Readout| {
-            readouts.readouts.clear();
-            readouts.active = 0;
+        let bundle_name = format!(
+            "{}.failed",
+            self.claimed_bundle.as_deref().unwrap_or("unknown")
+        );
 
-            if self.conf.device_id_readout {
-                readouts
-                    .readouts
-                    .push(("Device ID".to_string(), "".to_string()));
-            }
+        let summary = self.model.access(|inner| {
+            core::iter::once(("Result".to_string(), "Failed".to_string()))
+                .chain(
+                    inner
+                        .cycle
+                        .steps
+                        .iter()
+                        .map(|(step, status)| (step.name().to_string(), format!("{status:?}"))),
+                )
+                .collect::<Vec<_>>()
+        });
 
-            if self.conf.pcb_id_readout {
-                readouts
-                    .readouts
-                    .push(("PCB ID".to_string(), "".to_string()));
-            }
+        let (log_file, audit, audit_head, reports, attachments) = self.model.access_mut(|inner| {
+            let reports = self
+                .conf
+                .report_formats
+                .iter()
+                .map(|format| {
+                    inner
+                        .logs
+                        .report
+                        .render(*format, &bundle_name, &summary, &self.conf.summary_locale)
+                        .map(|report| (format.file_name(), report))
+                })
+                .collect::<anyhow::Result<Vec<_>>>();
+
+            let logs = (
+                inner.logs.file.grab(),
+                inner.logs.audit.to_json_lines(),
+                inner.logs.audit.head(),
+                reports,
+                core::mem::take(&mut inner.logs.attachments),
+            );
 
-            if self.conf.test_jig_id_readout {
-                readouts
-                    .readouts
-                    .push(("Test JIG ID".to_string(), "".to_string()));
+            // The next attempt starts with fresh logs
+            if let Err(err) = inner.logs.clear() {
+                error!("Restarting the logs failed: {err:?}");
             }
-        };
 
-        self.model.modify(|inner| {
-            inner.state = State::Readout(Readout::new());
-            let readouts = inner.state.readout_mut();
-            init(readouts);
+            (logs, true)
         });
 
-        let mut result = Ok(());
+        if self.session().replaying() || self.conf.simulate.is_some() {
+            return;
+        }
 
-        while result.is_ok() && !self.model.access(|inner| inner.state.readout().is_ready()) {
-            let (label, value) = self.model.access(|inner| {
-                let readouts = inner.state.readout();
+        let Some(log_file) = log_file else {
+            return;
+        };
+
+        info!("Uploading the logs of the PCB which did not get provisioned");
+
+        let result = async {
+            let mut files = vec![("audit.jsonl", audit?)];
+            files.extend(reports?);
+            files.extend(
+                attachments
+                    .iter()
+                    .map(|(file, content)| (file.as_str(), content.clone())),
+            );
+
+            let log = FileLogs::finish(log_file, summary.iter().chain(&audit_head), &files)?;
+
+            self.bundle_logs_uploader
+                .upload_logs(log, None, &bundle_name)
+                .await?;
 
-                readouts.readouts[readouts.active].clone()
+            Ok::<_, anyhow::Error>(())
+        }
+        .await;
+
+        if let Err(err) = result {
+            self.model.events().emit(ProvisioningEvent::UploadFailed {
+                message: format!("{err:#}"),
             });
 
-            match input.input(&label, &value).await {
-                TaskInputOutcome::Modified(value) => {
-                    self.model.modify(|inner| {
-                        let readouts = inner.state.readout_mut();
-                        readouts.readouts[readouts.active].1 = value;
-                    });
-                }
-                TaskInputOutcome::Done(value) => {
-                    self.model.modify(|inner| {
-                        let readouts = inner.state.readout_mut();
-                        readouts.readouts[readouts.active].1 = value.clone();
-                        readouts.active += 1;
-                    });
+            error!("Uploading the logs of the PCB which did not get provisioned failed: {err:?}");
+        }
+    }
 
-                    info!("Readout `{label}`: `{value}`");
-                }
-                TaskInputOutcome::StartOver => {
-                    let reset = self.model.access_mut(|inner| {
-                        let readouts = inner.state.readout_mut();
+    /// Check - before flashing - that the connected chip matches the bundle: its type, its silicon revision
+    /// (if the bundle requires a minimum one) and its flash size (see `Bundle::check_flash_size`)
+    ///
+    /// The check is skipped if the backend cannot detect the chip (e.g. when flashing over JTAG)
+    async fn check_chip(&self, chip: Chip) -> anyhow::Result<()> {
+        let backend = self.backend.clone();
 
-                        if readouts.active == 0 {
-                            let readouts = inner.state.readout_mut();
-                            init(readouts);
+        let Some(info) = unblock("chip-detect", move || backend.connect(chip)).await? else {
+            return Ok(());
+        };
 
-                            (true, true)
-                        } else {
-                            readouts.active -= 1;
-                            readouts.readouts[readouts.active].1.clear();
+        let (min_revision, result) = self.model.access(|inner| {
+            let bundle = &inner.state.provision().bundle;
 
-                            (false, true)
-                        }
-                    });
+            (
+                bundle.params.min_chip_revision,
+                info.flash_size
+                    .map(|flash_size| bundle.check_flash_size(flash_size)),
+            )
+        });
 
-                    if reset {
-                        info!("All readouts reset");
-                    } else {
-                        info!("Readout `{label}` reset");
-                    }
-                }
-                TaskInputOutcome::Quit => {
-                    result = Err(TaskError::Quit);
-                }
+        if let Some(min_revision) = min_revision {
+            match info.revision {
+                Some(revision) if revision < min_revision => anyhow::bail!(
+                    "Wrong chip revision: expected {} {min_revision} or later, found {revision}. Refusing to write to the chip",
+                    chip.as_tools_str()
+                ),
+                Some(revision) => info!("Chip revision {revision} matches the bundle minimum revision {min_revision}"),
+                None => warn!("Chip revision not detected, checking it against the bundle minimum revision {min_revision} skipped"),
             }
         }
 
-        result
-    }
+        let (Some(result), Some(flash_size)) = (result, info.flash_size) else {
+            warn!("Flash size not detected, checking the bundle against the flash size skipped");
+            return Ok(());
+        };
 
-    /// Step 2:
-    /// Prepare the eFuse readouts by reading those from the chip eFuse memory
-    ///
-    /// Displays a progress info while reading the eFuse values
-    async fn step2_prepare_efuse_readout(
-        &mut self,
-        input: impl TaskInput,
-    ) -> anyhow::Result<Vec<(String, String)>, TaskError> {
-        self.model
-            .modify(|inner| inner.state = State::Processing(Processing::new(" Read eFuse IDs ")));
+        if let Err(err) = result {
+            if !self.conf.flash_size_mismatch_ignore {
+                return Err(err.context(FlashSizeMismatch(flash_size)));
+            }
 
-        Self::process(&self.model.clone(), self.prep_efuse_readouts(), input).await
-    }
+            warn!("Ignoring the flash size mismatch: {err}");
+        }
 
-    /// Step 3:
-    /// Prepare the bundle to be provisioned by loading it from the storage
-    async fn step3_prepare(
-        &mut self,
-        input: impl TaskInput,
-        readouts: &[(String, String)],
-    ) -> anyhow::Result<Option<String>, TaskError> {
-        let (device_id, pcb_id, _test_jig_id) = {
-            let mut offset = 0;
+        Ok(())
+    }
 
-            let device_id = if self.conf.device_id_readout {
-                let readout = readouts[offset].1.clone();
+    /// Return the details of the provisioning of the bundle, as presented in the bundle preview
+    ///
+    /// The flashing duration is estimated from the size of the images and the flash speed, as if
+    /// the images were not compressed, and does not include the erasing of the flash
+    fn preview_details(&self, bundle: &Bundle) -> Vec<(String, String)> {
+        let conf = &self.conf;
+        let params = &bundle.params;
+
+        let (images, size) = bundle
+            .get_flash_data()
+            .fold((0, 0), |(images, size), flash_data| {
+                (images + 1, size + flash_data.data.len())
+            });
 
-                offset += 1;
+        let efuses = bundle.efuse_mapping.len();
+        let speed = conf.flash_speed.unwrap_or(DEFAULT_BAUD_RATE);
 
-                Some(readout)
-            } else {
-                None
-            };
+        let mut details = vec![
+            ("Chip".to_string(), format!("{:?}", params.chip)),
+            (
+                "Flash size".to_string(),
+                params
+                    .flash_size
+                    .map(|flash_size| format!("{flash_size:?}"))
+                    .unwrap_or_else(|| "4MB (default)".to_string()),
+            ),
+        ];
 
-            let pcb_id = if self.conf.pcb_id_readout {
-                let readout = readouts[offset].1.clone();
+        if let Some(min_chip_revision) = params.min_chip_revision {
+            details.push((
+                "Min chip revision".to_string(),
+                min_chip_revision.to_string(),
+            ));
+        }
 
-                offset += 1;
+        details.push((
+            "Images".to_string(),
+            format!("{images} images, {size} bytes"),
+        ));
+
+        details.push((
+            "Flasher".to_string(),
+            format!(
+                "{}{}{}",
+                if conf.flash_jtag.is_some() {
+                    "JTAG"
+                } else if conf.flash_esptool {
+                    "esptool.py"
+                } else {
+                    "native"
+                },
+                if conf.flash_erase { ", erase" } else { "" },
+                if conf.flash_dry_run { ", dry run" } else { "" },
+            ),
+        ));
+
+        details.push((
+            "eFuses".to_string(),
+            format!(
+                "{efuses} eFuse operations{}",
+                if conf.efuse_dry_run && efuses > 0 {
+                    ", dry run"
+                } else {
+                    ""
+                }
+            ),
+        ));
 
-                Some(readout)
+        details.push((
+            "Estimated duration".to_string(),
+            if conf.flash_jtag.is_some() {
+                "n/a (JTAG)".to_string()
             } else {
-                None
-            };
+                // 10 bits per byte on the wire (start, 8 data and stop bits)
+                let secs = (size as u64 * 10).div_ceil(speed as u64);
 
-            let test_jig_id = if self.conf.test_jig_id_readout {
-                let readout = readouts[offset].1.clone();
+                format!("~{secs}s at {speed} baud")
+            },
+        ));
 
-                Some(readout)
-            } else {
-                None
-            };
+        details
+    }
 
-            (device_id, pcb_id, test_jig_id)
-        };
+    /// Ask the operator whether to continue with the next PCB
+    ///
+    /// With the result banner enabled (see `Config::result_banner`), the confirmation is implied
+    /// once the banner dismiss delay elapses or once the next PCB is inserted
+    async fn confirm_continue(&self, mut input: impl TaskInput) -> TaskConfirmationOutcome {
+        let confirm = input.confirm("Continue? <Any key, [Q]uit>");
 
-        self.model
-            .modify(|inner| inner.state = State::Processing(Processing::new(" Preparing bundle ")));
+        if !self.conf.result_banner {
+            return confirm.await;
+        }
 
-        let bundle_id_source = match &self.conf.bundle_identification {
-            BundleIdentification::None => None,
-            BundleIdentification::DeviceId(parsing) => {
-                device_id.map(|device_id| (device_id, parsing))
+        let delay = async {
+            if let Some(secs) = self.conf.result_banner_dismiss_secs {
+                embassy_time::Timer::after(Duration::from_secs(secs as _)).await;
+                info!("Result banner dismissed after {secs}s");
+            } else {
+                core::future::pending::<()>().await;
             }
-            BundleIdentification::PcbId(parsing) => pcb_id.map(|pcb_id| (pcb_id, parsing)),
         };
 
-        let bundle_id = bundle_id_source
-            .as_ref()
-            .map(|(id, parsing)| parsing.parse(id))
-            .transpose()
-            .map_err(TaskError::Other)?;
+        let cancel = Arc::new(AtomicBool::new(false));
 
-        Self::process(
-            &self.model.clone(),
-            self.prep_bundle(bundle_id.as_deref()),
-            input,
-        )
-        .await?;
+        let mut insertion = pin!({
+            let cancel = cancel.clone();
+            let enabled = self.conf.result_banner_dismiss_on_insert;
 
-        Ok(bundle_id)
-    }
+            async move {
+                if !enabled {
+                    core::future::pending::<()>().await;
+                }
 
-    /// Step 4:
-    /// Provision the bundle by flashing and optionally efusing the chip with the bundle content
-    async fn step4_provision(
-        &mut self,
-        mut input: impl TaskInput,
-    ) -> anyhow::Result<(String, Chip), TaskError> {
-        match select(self.prov_bundle(), input.swallow()).await {
-            Either::First(result) => result.map_err(TaskError::Other),
-        }
-    }
+                match unblock("insertion", move || {
+                    flash::wait_usb_serial_port_arrival(&cancel)
+                })
+                .await
+                {
+                    Ok(true) => info!("Result banner dismissed, next PCB inserted"),
+                    Ok(false) => core::future::pending().await,
+                    Err(err) => {
+                        error!("Detecting the insertion of the next PCB failed: {err:?}");
+                        core::future::pending().await
+                    }
+                }
+            }
+        });
 
-    /// Step 5:
-    /// Optionally, run the app
-    async fn step5_run_app(
-        &mut self,
-        bundle_name: String,
-        chip: Chip,
-        mut input: impl TaskInput,
-    ) -> anyhow::Result<(), TaskError> {
-        match select(self.run_app(bundle_name, chip), input.swallow()).await {
-            Either::First(result) => result.map_err(TaskError::Other),
+        let result = select3(confirm, delay, insertion.as_mut()).await;
+
+        // Let the insertion detection thread (if still running) complete before it is joined
+        cancel.store(true, Ordering::SeqCst);
+
+        match result {
+            Either3::First(outcome) => outcome,
+            _ => TaskConfirmationOutcome::Confirmed,
         }
     }
 
-    //
-    // Helper methods
-    //
+    /// Account the cycle time of the provisioned PCB in the session statistics, add it - with a breakdown of
+    /// the step durations - to the PCB summary, and - if it exceeds the cycle time budget - warn and highlight
+    /// the PCB summary
+    fn check_cycle_time(&self, cycle_time: core::time::Duration) {
+        let budget = self.conf.cycle_time_budget_secs;
+        let locale = &self.conf.ui_locale;
 
-    /// Prepare the eFuse readouts by reading those from the chip eFuse memory
-    async fn prep_efuse_readouts(&mut self) -> anyhow::Result<Vec<(String, String)>> {
-        static EFUSE_VALUES: &[&str] = &[
-            "MAC",
-            "WAFER_VERSION_MAJOR",
-            "WAFER_VERSION_MINOR",
-            // Not available on all chips
-            "OPTIONAL_UNIQUE_ID",
-            "FLASH_TYPE",
-            "FLASH_VENDOR",
-            "FLASH_CAP",
-            "PSRAM_CAP",
-            "PSRAM_TYPE",
-            "PSRAMP_VENDOR",
-        ];
+        self.model.metrics().provisioned(cycle_time);
 
         self.model.modify(|inner| {
-            inner.state.processing_mut().status = "Reading Chip IDs from eFuse".to_string();
-        });
+            let stats = &mut inner.stats;
+            stats.provisioned += 1;
 
-        info!("About to read Chip IDs from eFuse");
+            let secs = cycle_time.as_secs_f64();
 
-        let efuse_port = self.conf.port.clone();
-        let efuse_baud = self.conf.efuse_speed.map(|speed| speed.to_string());
+            let breakdown = inner
+                .logs
+                .report
+                .steps()
+                .iter()
+                .map(|step| format!("{}: {}s", step.name, locale.format_decimal(step.time, 1)))
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            if let State::Status(status) = &mut inner.state {
+                status.message = format!(
+                    "{}\n\nCycle time: {}s\n{breakdown}",
+                    status.message,
+                    locale.format_decimal(secs, 1),
+                );
+            }
 
-        let efuse_values = unblock("efuse-summary", move || {
-            let efuse_values = efuse::summary(
-                None,
-                efuse_port.as_deref(),
-                efuse_baud.as_deref(),
-                EFUSE_VALUES.iter().copied(),
-            )?;
+            let Some(budget) = budget.filter(|budget| secs > *budget as f64) else {
+                info!(
+                    "Cycle time: {}s; steps: {breakdown}",
+                    locale.format_decimal(secs, 1)
+                );
+                return;
+            };
 
-            let efuse_values = efuse_values
-                .iter()
-                .filter_map(|(k, v)| {
-                    v.value.as_str().and_then(|v| {
-                        EFUSE_VALUES
-                            .iter()
-                            .find(|&x| x == k)
-                            .map(|&x| (x.to_string(), v.to_string()))
-                    })
-                })
-                .collect::<Vec<_>>();
+            stats.over_budget += 1;
 
-            Ok(efuse_values)
-        })
-        .await?;
+            warn!(
+                "Cycle time budget exceeded: {}s > {budget}s ({} of {} PCBs over budget); steps: {breakdown}",
+                locale.format_decimal(secs, 1),
+                stats.over_budget,
+                stats.provisioned
+            );
 
-        for (key, value) in efuse_values.iter() {
-            info!("Chip {key}: {value}");
+            if let State::Status(status) = &mut inner.state {
+                status.message = format!(
+                    "{}\n\nCycle time budget exceeded: {budget}s\n({} of {} PCBs over budget)",
+                    status.message, stats.over_budget, stats.provisioned
+                );
+
+                status.highlight = Some(if secs > budget as f64 * 1.5 {
+                    Highlight::Critical
+                } else {
+                    Highlight::Warning
+                });
+            }
+        });
+    }
+
+    /// Apply the configuration override of `Config::config_override_file` (if any) to the configuration of the next PCB
+    ///
+    /// The file is re-read only if it changed since it was last read. If it cannot be read or parsed,
+    /// the override read previously is kept
+    async fn apply_config_override_file(&mut self) {
+        let Some(path) = self.base_conf.config_override_file.clone() else {
+            return;
+        };
+
+        let modified = std::fs::metadata(&path).and_then(|metadata| metadata.modified());
+
+        match modified {
+            Ok(modified)
+                if self
+                    .config_override
+                    .as_ref()
+                    .is_some_and(|(read, _)| *read == modified) => {}
+            Ok(modified) => {
+                let result = std::fs::read_to_string(&path)
+                    .map_err(anyhow::Error::from)
+                    .and_then(|content| Ok(toml::from_str::<ConfigOverride>(&content)?));
+
+                match result {
+                    Ok(config_override) => {
+                        info!("Loaded configuration override from `{path}`: {config_override:?}");
+
+                        self.config_override = Some((modified, config_override));
+                    }
+                    Err(err) => error!(
+                        "Loading the configuration override from `{path}` failed, keeping the previous one: {err:?}"
+                    ),
+                }
+            }
+            Err(err) => error!(
+                "Reading the configuration override file `{path}` failed, keeping the previous override: {err}"
+            ),
         }
 
-        Ok(efuse_values)
+        if let Some((_, config_override)) = &self.config_override {
+            config_override.apply(&mut self.conf);
+        }
     }
 
-    /// Prepare the bundle to be provisioned by creating a `Bundle` instance from the loaded bundle content
-    /// in the bundle workspace directory
-    async fn prep_bundle(&mut self, bundle_id: Option<&str>) -> anyhow::Result<()> {
-        let bundle = Self::prep_one_bundle(
-            &self.model,
-            bundle_id,
-            &mut self.bundle_loader,
-            self.bundle_base_loader.is_none() && self.conf.supply_default_partition_table,
-            self.bundle_base_loader.is_none() && self.conf.supply_default_bootloader,
-        )
-        .await?;
+    /// Claim a unit of the work order (if any) for the next PCB, unless a unit is claimed already
+    /// (i.e. the previous PCB was not provisioned)
+    ///
+    /// Returns `false` if the work order is complete
+    async fn claim_work_order(&mut self) -> anyhow::Result<bool> {
+        let Some(work_order) = self.base_conf.work_order.clone() else {
+            return Ok(true);
+        };
 
-        let mut bundle = if let Some(base_loader) = self.bundle_base_loader.as_mut() {
-            info!("About to load base bundle");
+        let Some(progress) = self.model.access(|inner| inner.work_order.clone()) else {
+            return Ok(true);
+        };
 
-            let mut base_bundle = Self::prep_one_bundle(
-                &self.model,
-                None,
-                base_loader,
-                self.conf.supply_default_partition_table,
-                self.conf.supply_default_bootloader,
-            )
-            .await?;
+        let available = match work_order.url.clone() {
+            Some(_) if self.work_order_claimed => true,
+            Some(url) => {
+                let id = work_order.id.clone();
 
-            info!("Loaded base bundle `{}`", base_bundle.name);
+                match unblock("work-order", move || work_order::claim(&id, &url)).await? {
+                    Some(remaining) => {
+                        self.work_order_claimed = true;
 
-            self.model.modify(|inner| {
-                inner.state.processing_mut().status =
-                    format!("Merging `{}` and `{}`", base_bundle.name, bundle.name);
-            });
+                        // The other stations might have provisioned units in the meantime
+                        self.model.modify(|inner| {
+                            if let Some(progress) = inner.work_order.as_mut() {
+                                progress.quantity = progress.provisioned + remaining + 1;
+                            }
+                        });
+
+                        true
+                    }
+                    None => false,
+                }
+            }
+            None => progress.remaining() > 0,
+        };
 
+        if !available {
             info!(
-                "Merging base bundle `{}` with bundle `{}`, override `{}`",
-                base_bundle.name, bundle.name, self.conf.overwrite_on_merge
+                "========== Work order `{}` complete, {} units provisioned ==========",
+                progress.id, progress.provisioned
             );
+        }
 
-            base_bundle.add(bundle, self.conf.overwrite_on_merge)?;
+        Ok(available)
+    }
 
-            info!("Bundles merged");
+    /// Release the unit of the work order claimed on the server (if any) for a PCB which was not provisioned
+    async fn release_work_order(&mut self) {
+        let Some(work_order) = self.base_conf.work_order.clone() else {
+            return;
+        };
 
-            base_bundle
-        } else {
-            bundle
+        let Some(url) = work_order.url.clone() else {
+            return;
         };
 
-        if self.conf.reset_empty_partitions {
-            info!("Adding 0xff images for empty partitions");
+        if !core::mem::take(&mut self.work_order_claimed) {
+            return;
+        }
 
-            bundle.add_empty();
+        if let Err(err) = unblock("work-order", move || {
+            work_order::release(&work_order.id, &url)
+        })
+        .await
+        {
+            error!("Releasing the unit claimed on the work order failed: {err:?}");
         }
+    }
 
-        self.model.modify(move |inner| {
-            inner.state = State::Provision(Provision {
-                readouts: Vec::new(),
-                bundle,
-                provisioning: false,
-            })
-        });
+    /// Account the provisioned PCB against the work order (if any)
+    async fn account_work_order(&mut self) {
+        let Some(work_order) = self.base_conf.work_order.clone() else {
+            return;
+        };
 
-        Ok(())
-    }
+        // The claimed unit is consumed by the provisioned PCB
+        self.work_order_claimed = false;
 
-    /// Provision the bundle by flashing and optionally efusing the chip with the bundle content
-    async fn prov_bundle(&mut self) -> anyhow::Result<(String, Chip)> {
-        let bundle_name = self.model.modify(|inner| {
-            let ps = inner.state.provision_mut();
-            ps.provisioning = true;
+        let Some(progress) = self.model.access_mut(|inner| {
+            let progress = inner.work_order.as_mut().map(|progress| {
+                progress.provisioned += 1;
+                progress.clone()
+            });
 
-            ps.bundle.set_status_all(ProvisioningStatus::Pending);
+            (progress, true)
+        }) else {
+            return;
+        };
 
-            ps.bundle.name.clone()
-        });
+        if let Err(err) = unblock("work-order", move || {
+            work_order::account(&work_order, &progress)
+        })
+        .await
+        {
+            error!("Accounting the PCB against the work order failed: {err:?}");
+        }
+    }
 
-        info!("About to provision bundle `{bundle_name}`");
+    /// Step 0:
+    /// Log in the operator by reading the operator ID (e.g. a badge scan),
+    /// if the operator login is enabled and no operator is logged in yet
+    async fn step0_login(&mut self, mut input: impl TaskInput) -> Result<(), TaskError> {
+        if !self.conf.operator_id_readout || self.model.access(|inner| inner.operator.is_some()) {
+            return Ok(());
+        }
 
-        let flash_erase_all = self.conf.flash_erase;
+        info!("=== => STEP 0: operator login");
 
-        let (chip, flash_size, keys, mut flash_data) = self.model.access(|inner| {
-            let ps = inner.state.provision();
+        self.model.modify(|inner| {
+            let mut readout = Readout::new();
+            readout
+                .readouts
+                .push((OPERATOR_ID.to_string(), "".to_string()));
 
-            (
-                ps.bundle.params.chip,
-                ps.bundle.params.flash_size,
-                ps.bundle
-                    .get_flash_encrypt_keys()
-                    .map(|key| key.to_vec())
-                    .collect::<Vec<_>>(),
-                ps.bundle.get_flash_data().collect::<Vec<_>>(),
-            )
+            inner.state = State::Readout(readout);
         });
 
-        if self.conf.flash_encrypt && flash_data.iter().any(|fd| fd.encrypted_partition) {
-            let key = if keys.is_empty() {
-                anyhow::bail!("No encryption keys provided for flash data");
-            } else if keys.len() > 1 {
-                anyhow::bail!("Multiple encryption keys provided for flash data");
-            } else {
-                &keys[0]
-            };
+        loop {
+            let value = self
+                .model
+                .access(|inner| inner.state.readout().readouts[0].1.clone());
 
-            info!(
-                "About to ENCRYPT flash data: Chip={chip:?}, Flash Size={flash_size:?}, Images N={}",
-                flash_data.len()
-            );
+            match input.input(OPERATOR_ID, &value).await {
+                TaskInputOutcome::Modified(value) => {
+                    self.model.modify(|inner| {
+                        inner.state.readout_mut().readouts[0].1 = value;
+                    });
+                }
+                TaskInputOutcome::Done(value) => {
+                    let value = value.trim().to_string();
 
-            for flash_data in &mut flash_data {
-                if flash_data.encrypted_partition {
-                    info!(
-                        "Encrypting image for addr `0x{:08x}`, {}KB",
-                        flash_data.offset,
-                        flash_data.data.len() / 1024
-                    );
+                    if value.is_empty() {
+                        continue;
+                    }
 
-                    let encrypted_data = {
-                        let offset = flash_data.offset;
-                        let raw_data = flash_data.data.clone();
-                        let key = key.clone();
+                    info!("Operator `{value}` logged in");
 
-                        unblock("encrypt-flash-data", move || {
-                            encrypt(offset as _, &raw_data, &key)
-                        })
-                        .await?
-                    };
+                    self.model.modify(|inner| {
+                        let readout = inner.state.readout_mut();
+                        readout.readouts[0].1 = value.clone();
+                        readout.active = 1;
+
+                        inner.operator = Some(value);
+                    });
 
-                    flash_data.data = Arc::new(encrypted_data);
+                    break Ok(());
+                }
+                TaskInputOutcome::StartOver => {
+                    self.model.modify(|inner| {
+                        inner.state.readout_mut().readouts[0].1.clear();
+                    });
                 }
+                TaskInputOutcome::Quit => break Err(TaskError::Quit),
             }
         }
+    }
 
-        if flash_erase_all {
-            info!("About to erase all flash using the standard `Flash Erase` command: Chip={chip:?}, Flash Size={flash_size:?}");
+    /// Step 1:
+    /// Process the readouts state by visualizing the eFuse readouts (if any) and
+    /// reading the necessary IDs from the user (if any)
+    async fn step1_readout(&mut self, mut input: impl TaskInput) -> Result<(), TaskError> {
+        if !self.conf.test_jig_id.is_empty() {
+            if self.conf.test_jig_id_readout {
+                warn!(
+                    "Ignoring the fixed value `{}` for the Test JIG ID from the configuration",
+                    self.conf.test_jig_id
+                );
+            } else {
+                info!("Readout `Test JIG ID`: `{}`", self.conf.test_jig_id);
+            }
         }
 
-        info!(
-            "About to flash data: Chip={chip:?}, Flash Size={flash_size:?}, Images N={}",
-            flash_data.len()
-        );
+        // The readouts are told apart by their kinds rather than by their names, as the names of the extra readouts
+        // might clash with the names of the built-in ones
+        let kinds = [
+            (self.conf.device_id_readout, ReadoutKind::DeviceId),
+            (self.conf.pcb_id_readout, ReadoutKind::PcbId),
+            (self.conf.test_jig_id_readout, ReadoutKind::TestJigId),
+        ]
+        .into_iter()
+        .filter_map(|(enabled, kind)| enabled.then_some(kind))
+        .chain((0..self.conf.extra_readouts.len()).map(ReadoutKind::Extra))
+        .collect::<Vec<_>>();
 
-        let flash_use_stub = !self.conf.flash_no_stub;
-        let flash_esptool = self.conf.flash_esptool;
-        let flash_port = self.conf.port.clone();
-        let flash_speed = self.conf.flash_speed;
-        let flash_model = self.model.clone();
-        let flash_dry_run = self.conf.flash_dry_run;
+        let init = |readouts: &mut Readout| {
+            readouts.readouts.clear();
+            readouts.active = 0;
+            readouts.error = None;
+
+            for kind in &kinds {
+                let name = match kind {
+                    ReadoutKind::DeviceId => DEVICE_ID,
+                    ReadoutKind::PcbId => PCB_ID,
+                    ReadoutKind::TestJigId => TEST_JIG_ID,
+                    ReadoutKind::Extra(index) => &self.conf.extra_readouts[*index].name,
+                };
 
-        unblock("flash", move || {
-            let mut progress = FlashProgress::new(flash_model);
+                readouts.readouts.push((name.to_string(), "".to_string()));
+            }
+        };
 
-            if flash_esptool {
-                if flash_erase_all {
-                    flash::erase_esptool(
-                        flash_port.as_deref(),
-                        chip,
-                        flash_use_stub,
-                        flash_speed,
-                        flash_size,
-                        flash_dry_run,
-                    )?;
-                }
+        self.model.modify(|inner| {
+            inner.state = State::Readout(Readout::new());
+            let readouts = inner.state.readout_mut();
+            init(readouts);
+        });
 
-                flash::flash_esptool(
-                    flash_port.as_deref(),
-                    chip,
-                    flash_use_stub,
-                    flash_speed,
-                    flash_size,
-                    flash_data,
-                    flash_dry_run,
-                    &mut progress,
-                )
-            } else {
-                if flash_erase_all {
-                    flash::erase(
-                        flash_port.as_deref(),
-                        chip,
-                        flash_use_stub,
-                        flash_speed,
-                        flash_size,
-                        flash_dry_run,
-                    )?;
-                }
+        let mut result = Ok(());
 
-                flash::flash(
-                    flash_port.as_deref(),
-                    chip,
-                    flash_use_stub,
-                    flash_speed,
-                    flash_size,
-                    flash_data,
-                    flash_dry_run,
-                    &mut progress,
-                )
-            }
-        })
-        .await?;
+        // The readouts whose source failed, and which are therefore read from the keyboard
+        let mut failed_sources = Vec::new();
 
-        info!("Flash complete");
+        while result.is_ok() && !self.model.access(|inner| inner.state.readout().is_ready()) {
+            let (kind, (label, value)) = self.model.access(|inner| {
+                let readouts = inner.state.readout();
 
-        info!("About to burn eFuses");
+                (
+                    kinds[readouts.active],
+                    readouts.readouts[readouts.active].clone(),
+                )
+            });
 
-        let model = self.model.clone();
+            let extra = match kind {
+                ReadoutKind::Extra(index) => Some(&self.conf.extra_readouts[index]),
+                _ => None,
+            };
 
-        let efuse_protect_keys = self.conf.efuse_protect_keys;
-        let efuse_protect_digests = self.conf.efuse_protect_digests;
-        let efuse_port = self.conf.port.clone();
-        let efuse_baud = self.conf.efuse_speed.map(|speed| speed.to_string());
-        let efuse_dry_run = self.conf.efuse_dry_run;
+            let source = match kind {
+                ReadoutKind::DeviceId => &self.conf.device_id_source,
+                ReadoutKind::PcbId => &self.conf.pcb_id_source,
+                ReadoutKind::TestJigId => &self.conf.test_jig_id_source,
+                ReadoutKind::Extra(_) => extra
+                    .map(|extra| &extra.source)
+                    .unwrap_or(&ReadoutSource::Keyboard),
+            };
 
-        unblock("efuse-burn", move || {
-            Self::burn(
-                &model,
-                efuse_protect_keys,
-                efuse_protect_digests,
-                chip,
-                efuse_port.as_deref(),
-                efuse_baud.as_deref(),
-                efuse_dry_run,
-            )
-        })
-        .await?;
+            let prompt = extra
+                .and_then(|extra| extra.prompt.clone())
+                .unwrap_or_else(|| label.clone());
 
-        info!("Burn complete");
+            // Race the readout source with the keyboard input, unless the operator already started typing
+            let outcome = if !matches!(source, ReadoutSource::Keyboard)
+                && value.is_empty()
+                && !failed_sources.contains(&kind)
+            {
+                let cancel = Arc::new(AtomicBool::new(false));
 
-        info!("Provisioning bundle `{bundle_name}` complete");
+                let mut read = pin!({
+                    let source = source.clone();
+                    let cancel = cancel.clone();
 
-        Ok((bundle_name, chip))
-    }
+                    unblock("readout", move || readout::read(&source, &cancel))
+                });
 
-    async fn run_app(&mut self, bundle_name: String, chip: Chip) -> anyhow::Result<()> {
-        if !matches!(self.conf.app_run, AppRun::Disabled) {
-            info!("Running app to finish provisioning");
+                let result = select(read.as_mut(), input.input(&prompt, &value)).await;
 
-            self.model.modify(|inner| {
-                inner.state = State::AppRun(AppLogs::new(100));
-            });
+                // Let the readout thread (if still running) complete before it is joined
+                cancel.store(true, Ordering::SeqCst);
 
-            let run_use_stub = !self.conf.flash_no_stub;
-            let run_port = self.conf.port.clone();
-            let run_speed = self.conf.flash_speed;
-            let run_model = Arc::new(Mutex::new(Some(self.model.clone())));
-            let run_model_inner = run_model.clone();
-            let run_stop = Arc::new(AtomicBool::new(false));
+                match result {
+                    Either::First(Ok(Some(value))) => TaskInputOutcome::Done(value),
+                    Either::First(Ok(None)) => continue,
+                    Either::First(Err(err)) => {
+                        error!("Reading `{label}` from its source failed, falling back to the keyboard: {err:?}");
+
+                        failed_sources.push(kind);
+                        continue;
+                    }
+                    Either::Second(outcome) => outcome,
+                }
+            } else {
+                input.input(&prompt, &value).await
+            };
+
+            match outcome {
+                TaskInputOutcome::Modified(value) => {
+                    self.model.modify(|inner| {
+                        let readouts = inner.state.readout_mut();
+                        readouts.readouts[readouts.active].1 = value;
+                        readouts.error = None;
+                    });
+                }
+                TaskInputOutcome::Done(value) => {
+                    let validation = match kind {
+                        ReadoutKind::DeviceId => self.conf.device_id_validation.as_ref(),
+                        ReadoutKind::PcbId => self.conf.pcb_id_validation.as_ref(),
+                        ReadoutKind::TestJigId => self.conf.test_jig_id_validation.as_ref(),
+                        ReadoutKind::Extra(_) => extra.and_then(|extra| extra.validation.as_ref()),
+                    };
+
+                    if let Some(Err(err)) =
+                        validation.map(|validation| readout::validate(validation, &value))
+                    {
+                        // The rejected value is not logged, as it might be sensitive (e.g. a misread of another barcode)
+                        warn!("Readout `{label}`: value rejected: {err}");
+
+                        self.model.modify(|inner| {
+                            let readouts = inner.state.readout_mut();
+                            readouts.readouts[readouts.active].1.clear();
+                            readouts.error = Some(format!("`{value}` rejected: {err}"));
+                        });
+
+                        continue;
+                    }
+
+                    self.model.modify(|inner| {
+                        let readouts = inner.state.readout_mut();
+                        readouts.readouts[readouts.active].1 = value.clone();
+                        readouts.active += 1;
+                        readouts.error = None;
+                    });
+
+                    info!("Readout `{label}`: `{value}`");
+                }
+                TaskInputOutcome::StartOver => {
+                    let reset = self.model.access_mut(|inner| {
+                        let readouts = inner.state.readout_mut();
+
+                        if readouts.active == 0 {
+                            let readouts = inner.state.readout_mut();
+                            init(readouts);
+
+                            (true, true)
+                        } else {
+                            readouts.active -= 1;
+                            readouts.readouts[readouts.active].1.clear();
+                            readouts.error = None;
+
+                            (false, true)
+                        }
+                    });
+
+                    if reset {
+                        info!("All readouts reset");
+                    } else {
+                        info!("Readout `{label}` reset");
+                    }
+                }
+                TaskInputOutcome::Quit => {
+                    result = Err(TaskError::Quit);
+                }
+            }
+        }
+
+        result
+    }
+
+    /// Step 2:
+    /// Prepare the eFuse readouts by reading those from the chip eFuse memory
+    ///
+    /// Displays a progress info while reading the eFuse values
+    async fn step2_prepare_efuse_readout(
+        &mut self,
+        mut input: impl TaskInput,
+    ) -> anyhow::Result<Vec<(String, String)>, TaskError> {
+        self.model
+            .modify(|inner| inner.state = State::Processing(Processing::new(" Read eFuse IDs ")));
+
+        let result =
+            Self::process(&self.model.clone(), self.prep_efuse_readouts(), &mut input).await;
+
+        if self.offer_secure_download(&result, &mut input).await? {
+            // eFuses cannot be read in Secure Download mode
+            return Err(TaskError::Skipped);
+        }
+
+        result
+    }
+
+    /// Step 3:
+    /// Prepare the bundle to be provisioned by loading it from the storage
+    async fn step3_prepare(
+        &mut self,
+        mut input: impl TaskInput,
+        readouts: &[(String, String)],
+    ) -> anyhow::Result<Option<String>, TaskError> {
+        let (device_id, pcb_id, _test_jig_id) = {
+            let mut offset = 0;
+
+            let device_id = if self.conf.device_id_readout {
+                let readout = readouts[offset].1.clone();
+
+                offset += 1;
+
+                Some(readout)
+            } else {
+                None
+            };
+
+            let pcb_id = if self.conf.pcb_id_readout {
+                let readout = readouts[offset].1.clone();
+
+                offset += 1;
+
+                Some(readout)
+            } else {
+                None
+            };
+
+            let test_jig_id = if self.conf.test_jig_id_readout {
+                let readout = readouts[offset].1.clone();
+
+                Some(readout)
+            } else {
+                None
+            };
+
+            (device_id, pcb_id, test_jig_id)
+        };
+
+        self.model
+            .modify(|inner| inner.state = State::Processing(Processing::new(" Preparing bundle ")));
+
+        let bundle_id_source = match &self.conf.bundle_identification {
+            BundleIdentification::None => self.conf.extra_readouts.iter().find_map(|extra| {
+                let parsing = extra.bundle_identification.as_ref()?;
+
+                readouts
+                    .iter()
+                    .find(|(name, _)| *name == extra.name)
+                    .map(|(_, value)| (value.clone(), parsing))
+            }),
+            BundleIdentification::DeviceId(parsing) => {
+                device_id.map(|device_id| (device_id, parsing))
+            }
+            BundleIdentification::PcbId(parsing) => pcb_id.map(|pcb_id| (pcb_id, parsing)),
+            BundleIdentification::Lookup(_) => None,
+        };
+
+        let bundle_id =
+            if let BundleIdentification::Lookup(bundle_lookup) = &self.conf.bundle_identification {
+                let bundle_lookup = bundle_lookup.clone();
+                let readouts = readouts.to_vec();
+
+                Self::process(
+                    &self.model.clone(),
+                    unblock("bundle-lookup", move || {
+                        lookup::lookup(&bundle_lookup, &readouts)
+                    }),
+                    &mut input,
+                )
+                .await
+                .map(Some)?
+            } else {
+                bundle_id_source
+                    .as_ref()
+                    .map(|(id, parsing)| parsing.parse(id))
+                    .transpose()
+                    .map_err(TaskError::Other)?
+            };
+
+        Self::process(
+            &self.model.clone(),
+            self.prep_bundle(bundle_id.as_deref()),
+            input,
+        )
+        .await?;
+
+        Ok(bundle_id)
+    }
+
+    /// Step 4:
+    /// Provision the bundle by flashing and optionally efusing the chip with the bundle content
+    async fn step4_provision(
+        &mut self,
+        mut input: impl TaskInput,
+    ) -> anyhow::Result<(String, Chip), TaskError> {
+        let mut provision = self.model.access(|inner| inner.state.provision().clone());
+        let mut efuses_only = false;
+
+        loop {
+            let prov = async {
+                if efuses_only {
+                    self.prov_efuses().await
+                } else {
+                    self.prov_bundle().await
+                }
+            };
+
+            let result = match select(prov, input.swallow()).await {
+                Either::First(result) => result.map_err(TaskError::Other),
+            };
+
+            efuses_only = false;
+
+            if let Some(failed) = self.offer_efuse_reconnect(&result, &mut input).await? {
+                // Restore the provisioning state - with the images flashed - before retrying the eFuse burning
+                self.model
+                    .modify(|inner| inner.state = State::Provision(failed));
+
+                efuses_only = true;
+            } else if self.offer_secure_download(&result, &mut input).await? {
+                // Restore the provisioning state before retrying with the Secure Download profile
+                self.model
+                    .modify(|inner| inner.state = State::Provision(provision.clone()));
+            } else if let Some(bundle) = self
+                .offer_part_table_variant(&result, &provision.bundle, &mut input)
+                .await?
+            {
+                // Restore the provisioning state - with the picked partition table - before retrying
+                provision.bundle = bundle;
+
+                self.model
+                    .modify(|inner| inner.state = State::Provision(provision.clone()));
+            } else {
+                break result;
+            }
+        }
+    }
+
+    /// If the eFuse burning failed because the eFuse tool could not connect to the chip (e.g. the chip left the download
+    /// mode after flashing, and the adapter needs a manual boot-strap), offer the operator to put the chip in the download
+    /// mode again and to retry burning the eFuses - without flashing the images again
+    ///
+    /// Returns the provisioning state to be restored before retrying, if the operator chose to retry
+    async fn offer_efuse_reconnect<R>(
+        &mut self,
+        result: &anyhow::Result<R, TaskError>,
+        mut input: impl TaskInput,
+    ) -> Result<Option<Provision>, TaskError> {
+        let Err(TaskError::Other(err)) = result else {
+            return Ok(None);
+        };
+
+        if self.conf.skip_confirmations || err.downcast_ref::<efuse::ConnectFailed>().is_none() {
+            return Ok(None);
+        }
+
+        let Some(failed) = self.model.access(|inner| match &inner.state {
+            State::Provision(provision) => Some(provision.clone()),
+            _ => None,
+        }) else {
+            return Ok(None);
+        };
+
+        warn!("Connecting to the chip for burning the eFuses failed: {err:?}");
+
+        self.model.modify(|inner| {
+            inner.state.error(
+                " eFuse connection failed ".to_string(),
+                format!(
+                    "Connecting to the chip for burning the eFuses failed:\n{err:#}\n\nPut the chip in the download mode again (e.g. hold BOOT and tap RESET) and retry burning the eFuses?\n(the images are not flashed again, and the eFuses burned already are not burned again)"
+                ),
+            )
+        });
+
+        match input
+            .confirm("Retry? <[Y]es/ENTER, [N]o/[C]ancel, [Q]uit>")
+            .await
+        {
+            TaskConfirmationOutcome::Confirmed => {
+                info!("Retrying the eFuse burning");
+
+                Ok(Some(failed))
+            }
+            TaskConfirmationOutcome::Quit => Err(TaskError::Quit),
+            _ => Ok(None),
+        }
+    }
+
+    /// If a step failed because the bundle does not fit the flash of the chip, offer the operator to pick
+    /// one of the alternative partition tables shipped in the bundle (see `Config::part_table_variant_pick`)
+    ///
+    /// Returns the bundle with the picked partition table, if the operator picked one
+    async fn offer_part_table_variant<R>(
+        &mut self,
+        result: &anyhow::Result<R, TaskError>,
+        bundle: &Bundle,
+        mut input: impl TaskInput,
+    ) -> Result<Option<Bundle>, TaskError> {
+        let Err(TaskError::Other(err)) = result else {
+            return Ok(None);
+        };
+
+        if !self.conf.part_table_variant_pick
+            || self.conf.skip_confirmations
+            || bundle.part_table_variants.is_empty()
+        {
+            return Ok(None);
+        }
+
+        let Some(FlashSizeMismatch(flash_size)) = err.downcast_ref::<FlashSizeMismatch>() else {
+            return Ok(None);
+        };
+
+        let mut variants = Vec::new();
+        let mut bundles = Vec::new();
+
+        for (variant, _) in &bundle.part_table_variants {
+            let variant_bundle = match bundle.with_part_table_variant(variant) {
+                Ok(variant_bundle) => variant_bundle,
+                Err(err) => {
+                    warn!("Partition table variant `{variant}` not applicable: {err:#}");
+                    continue;
+                }
+            };
+
+            let partitions = variant_bundle
+                .parts_mapping
+                .iter()
+                .filter_map(|mapping| mapping.partition.as_ref())
+                .filter(|partition| {
+                    partition.name() != Bundle::BOOTLOADER_NAME
+                        && partition.name() != Bundle::PART_TABLE_NAME
+                });
+
+            variants.push(PartTableDescription {
+                variant: variant.clone(),
+                partitions: partitions
+                    .clone()
+                    .map(|partition| partition.name())
+                    .collect(),
+                end: partitions
+                    .map(|partition| partition.offset() + partition.size())
+                    .max()
+                    .unwrap_or(0),
+                fits: variant_bundle.check_flash_size(*flash_size).is_ok(),
+            });
+
+            bundles.push(variant_bundle);
+        }
+
+        if variants.is_empty() {
+            return Ok(None);
+        }
+
+        warn!("The bundle does not fit the flash of the chip: {err:#}");
+
+        info!("=== => Partition table pick");
+
+        let problem = format!("{err:#}");
+
+        self.model.modify(|inner| {
+            inner.state = State::PartTablePick(PartTablePick::new(problem, variants))
+        });
+
+        loop {
+            let selection = self
+                .model
+                .access(|inner| inner.state.part_table_pick().selection.clone());
+
+            match input.input("Partition table number", &selection).await {
+                TaskInputOutcome::Modified(value) => {
+                    self.model.modify(|inner| {
+                        inner.state.part_table_pick_mut().selection = value;
+                    });
+                }
+                TaskInputOutcome::Done(value) => {
+                    let selected = self.model.modify(|inner| {
+                        let part_table_pick = inner.state.part_table_pick_mut();
+                        part_table_pick.selection = value;
+
+                        part_table_pick
+                            .selected()
+                            .map(|index| (index, part_table_pick.variants[index].variant.clone()))
+                    });
+
+                    if let Some((index, variant)) = selected {
+                        warn!("Partition table variant `{variant}` picked by the operator");
+
+                        break Ok(Some(bundles.swap_remove(index)));
+                    }
+
+                    warn!("Invalid partition table number");
+
+                    self.model.modify(|inner| {
+                        inner.state.part_table_pick_mut().selection.clear();
+                    });
+                }
+                TaskInputOutcome::StartOver => break Ok(None),
+                TaskInputOutcome::Quit => break Err(TaskError::Quit),
+            }
+        }
+    }
+
+    /// If a step failed because the chip turned out to be in Secure Download mode (i.e. it was most likely
+    /// provisioned already), offer the operator to switch the configuration to the Secure Download profile
+    /// (see `Config::secure_download`) for the current PCB only
+    ///
+    /// Returns `true` if the operator accepted the switch
+    async fn offer_secure_download<R>(
+        &mut self,
+        result: &anyhow::Result<R, TaskError>,
+        mut input: impl TaskInput,
+    ) -> Result<bool, TaskError> {
+        let Err(TaskError::Other(err)) = result else {
+            return Ok(false);
+        };
+
+        if self.conf.is_secure_download() || !is_secure_download_error(err) {
+            return Ok(false);
+        }
+
+        warn!("The chip is in Secure Download mode: {err:?}");
+
+        self.model.modify(|inner| {
+            inner.state.error(
+                " Secure Download mode ".to_string(),
+                format!(
+                    "The chip is in Secure Download mode, i.e. it was most likely provisioned already:\n{err:#}\n\nSwitch to the Secure Download profile for this PCB?\n(eFuses are not burned, flash is not erased, `esptool.py` is used for flashing)"
+                ),
+            )
+        });
+
+        match input
+            .confirm("Switch? <[Y]es/ENTER, [N]o/[C]ancel, [Q]uit>")
+            .await
+        {
+            TaskConfirmationOutcome::Confirmed => {
+                warn!("Switching to the Secure Download profile for this PCB");
+
+                self.conf.secure_download();
+
+                Ok(true)
+            }
+            TaskConfirmationOutcome::Quit => Err(TaskError::Quit),
+            _ => Ok(false),
+        }
+    }
+
+    /// Step 5:
+    /// Optionally, run the app
+    async fn step5_run_app(
+        &mut self,
+        bundle_name: String,
+        chip: Chip,
+        elf: Option<Arc<Vec<u8>>>,
+        ota_layout: Option<OtaLayout>,
+        coredump_partition: Option<(u32, u32)>,
+        mut input: impl TaskInput,
+    ) -> anyhow::Result<AppRunCapture, TaskError> {
+        let result = match select(
+            self.run_app(bundle_name, chip, elf.clone(), ota_layout),
+            input.swallow(),
+        )
+        .await
+        {
+            Either::First(result) => result,
+        };
+
+        if result.is_err() {
+            self.coredump(chip, coredump_partition, elf).await;
+        }
+
+        result.map_err(TaskError::Other)
+    }
+
+    /// Retrieve the ESP-IDF coredump of the app after a failed app run, and attach it - as well as its decoded
+    /// form, if a decoder is configured - to the PCB logs (see `Config::app_run_coredump`)
+    ///
+    /// Failing to retrieve or decode the coredump is not an error
+    async fn coredump(
+        &self,
+        chip: Chip,
+        coredump_partition: Option<(u32, u32)>,
+        elf: Option<Arc<Vec<u8>>>,
+    ) {
+        let Some(coredump_conf) = self.conf.app_run_coredump.clone() else {
+            return;
+        };
+
+        let Some((offset, size)) = coredump_partition else {
+            warn!(
+                "The partition table has no `coredump` partition, retrieving the coredump skipped"
+            );
+            return;
+        };
+
+        self.model.modify(|inner| {
+            let mut processing = Processing::new(" Coredump ");
+            processing.status = "Retrieving the coredump of the app".to_string();
+
+            inner.state = State::Processing(processing);
+        });
+
+        let backend = self.backend.clone();
+
+        let result = unblock("coredump", move || {
+            let Some(coredump) = coredump::read(&backend, chip, offset, size)? else {
+                return Ok(None);
+            };
+
+            let decoded = match (coredump_conf.decoder.as_deref(), elf.as_ref()) {
+                (Some(decoder), Some(elf)) => match coredump::decode(&backend.connection().tools, decoder, chip, &coredump, elf)
+                {
+                    Ok(decoded) => Some(decoded),
+                    Err(err) => {
+                        warn!("Decoding the coredump failed: {err:?}");
+                        None
+                    }
+                },
+                (Some(_), None) => {
+                    warn!("The app image of the bundle is not an ELF file, decoding the coredump skipped");
+                    None
+                }
+                _ => None,
+            };
+
+            Ok::<_, anyhow::Error>(Some((coredump, decoded)))
+        })
+        .await;
+
+        let (coredump, decoded) = match result {
+            Ok(Some(coredump)) => coredump,
+            Ok(None) => return,
+            Err(err) => {
+                warn!("Retrieving the coredump failed: {err:?}");
+                return;
+            }
+        };
+
+        if let Some(decoded) = decoded.as_deref() {
+            error!("App coredump:\n{decoded}");
+        }
+
+        self.model.access_mut(|inner| {
+            let attachments = &mut inner.logs.attachments;
+
+            // Replace the coredump of a previous app run attempt, if any
+            attachments.retain(|(attachment, _)| {
+                attachment != COREDUMP_FILE_NAME && attachment != COREDUMP_DECODED_FILE_NAME
+            });
+
+            attachments.push((
+                COREDUMP_FILE_NAME.to_string(),
+                base64::engine::general_purpose::STANDARD.encode(&coredump),
+            ));
+
+            if let Some(decoded) = decoded {
+                attachments.push((COREDUMP_DECODED_FILE_NAME.to_string(), decoded));
+            }
+
+            ((), false)
+        });
+
+        info!("Coredump attached to the PCB logs");
+    }
+
+    /// Hook:
+    /// Run the external plugins registered for the given hook point
+    ///
+    /// Displays a progress info while the plugins are running
+    async fn step_hook(
+        &mut self,
+        input: impl TaskInput,
+        hook: PluginHook,
+        context: PluginContext,
+    ) -> anyhow::Result<Vec<(String, String)>, TaskError> {
+        if !plugin::is_registered(&self.conf.plugins, hook) {
+            return Ok(Vec::new());
+        }
+
+        self.model.modify(|inner| {
+            let mut processing = Processing::new(" Running plugins ");
+            processing.status = format!("Running `{hook}` plugins");
+
+            inner.state = State::Processing(processing);
+        });
+
+        Self::process(&self.model.clone(), self.run_plugins(hook, context), input).await
+    }
+
+    /// A step that takes the configured test-point measurements with the external instruments
+    ///
+    /// Each measurement is recorded as a separate case in the report of the PCB logs;
+    /// returns the measured values as readouts
+    async fn step_measure(
+        &mut self,
+        input: impl TaskInput,
+    ) -> anyhow::Result<Vec<(String, String)>, TaskError> {
+        if self.conf.measurements.is_empty() {
+            return Ok(Vec::new());
+        }
+
+        self.model.modify(|inner| {
+            let mut processing = Processing::new(" Measuring ");
+            processing.status = "Taking the test-point measurements".to_string();
+
+            inner.state = State::Processing(processing);
+        });
+
+        Self::process(&self.model.clone(), self.take_measurements(), input).await
+    }
+
+    //
+    // Helper methods
+    //
+
+    /// Take the configured test-point measurements and record each of them in the report of the PCB logs
+    ///
+    /// Fails if any of the measurements cannot be taken or is outside of its thresholds
+    async fn take_measurements(&self) -> anyhow::Result<Vec<(String, String)>> {
+        let mut readouts = Vec::new();
+        let mut failed = Vec::new();
+
+        for measurement in &self.conf.measurements {
+            let name = measurement.name.clone();
+
+            let result = {
+                let measurement = measurement.clone();
+
+                unblock("measure", move || measure::measure(&measurement)).await
+            };
+
+            let (value, elapsed, outcome) = match result {
+                Ok(outcome) => {
+                    let value = outcome.display(measurement);
+
+                    let report = match &outcome.violation {
+                        None => StepOutcome::Passed,
+                        Some(violation) => {
+                            failed.push(format!("`{name}`: {violation}"));
+
+                            StepOutcome::Failed {
+                                code: "MEASUREMENT_OUT_OF_RANGE".to_string(),
+                                message: violation.clone(),
+                            }
+                        }
+                    };
+
+                    (value, outcome.elapsed, report)
+                }
+                Err(err) => {
+                    failed.push(format!("`{name}`: {err:#}"));
+
+                    (
+                        "N/A".to_string(),
+                        core::time::Duration::ZERO,
+                        StepOutcome::Failed {
+                            code: "MEASUREMENT_FAILED".to_string(),
+                            message: format!("{err:#}"),
+                        },
+                    )
+                }
+            };
+
+            self.model.access_mut(|inner| {
+                inner
+                    .logs
+                    .report
+                    .record(&format!("measurement-{name}"), elapsed, outcome);
+
+                ((), false)
+            });
+
+            readouts.push((name, value));
+        }
+
+        if !failed.is_empty() {
+            anyhow::bail!("Measurements failed: {}", failed.join(", "));
+        }
+
+        Ok(readouts)
+    }
+
+    /// Run the external plugins registered for the given hook point
+    ///
+    /// Returns the additional readouts reported by the plugins
+    async fn run_plugins(
+        &self,
+        hook: PluginHook,
+        context: PluginContext,
+    ) -> anyhow::Result<Vec<(String, String)>> {
+        if !plugin::is_registered(&self.conf.plugins, hook) {
+            return Ok(Vec::new());
+        }
+
+        info!("About to run `{hook}` plugins");
+
+        let plugins = self.conf.plugins.clone();
+
+        unblock("plugins", move || plugin::run(&plugins, hook, &context)).await
+    }
+
+    /// Sample the fixture sensors (if any) at the given provisioning stage
+    ///
+    /// Returns the samples as readouts to be recorded in the summary of the PCB logs
+    async fn sample_sensors(&self, stage: &'static str) -> Vec<(String, String)> {
+        if self.conf.sensors.is_empty() {
+            return Vec::new();
+        }
+
+        let sensors = self.conf.sensors.clone();
+        let locale = self.conf.summary_locale.clone();
+
+        unblock("sensors", move || {
+            Ok(sensor::sample_all(&sensors, stage, &locale))
+        })
+        .await
+        .unwrap_or_else(|err| {
+            warn!("Sampling sensors ({stage}) failed: {err:?}");
+            Vec::new()
+        })
+    }
+
+    /// Run the external plugins registered for the given hook point during the provisioning of the bundle
+    ///
+    /// The plugins get the readouts and the bundle of the provisioning state, and the readouts reported
+    /// by the plugins are appended to the readouts of the provisioning state
+    async fn prov_hook(&self, hook: PluginHook, chip: Chip) -> anyhow::Result<()> {
+        if !plugin::is_registered(&self.conf.plugins, hook) {
+            return Ok(());
+        }
+
+        let context = self.model.access(|inner| {
+            let ps = inner.state.provision();
+
+            self.plugin_context(
+                &ps.readouts,
+                ps.bundle_id.as_deref(),
+                Some((&ps.bundle.name, chip)),
+            )
+        });
+
+        let readouts = self.run_plugins(hook, context).await?;
+
+        self.model.modify(|inner| {
+            inner.state.provision_mut().readouts.extend(readouts);
+        });
+
+        Ok(())
+    }
+
+    /// Run the `pre-flash` or `post-flash` sequence of the test JIG, if a test JIG is configured
+    async fn run_jig(&self, sequence: jig::Sequence) -> anyhow::Result<()> {
+        let Some(jig) = self.conf.jig.clone() else {
+            return Ok(());
+        };
+
+        let port = self.conf.port.clone();
+
+        unblock("jig", move || jig::run(&jig, port.as_deref(), sequence)).await
+    }
+
+    /// Create the provisioning context shared with the external plugins
+    fn plugin_context(
+        &self,
+        readouts: &[(String, String)],
+        bundle_id: Option<&str>,
+        bundle: Option<(&str, Chip)>,
+    ) -> PluginContext {
+        // The MAC readout might be aliased in `efuse_readouts`
+        let mac = self
+            .efuse_readout_fields()
+            .into_iter()
+            .find(|(field, _)| field == "MAC")
+            .and_then(|(_, name)| {
+                readouts
+                    .iter()
+                    .find(|(readout, _)| *readout == name)
+                    .map(|(_, value)| value.clone())
+            });
+
+        PluginContext {
+            readouts: readouts.to_vec(),
+            mac,
+            bundle_id: bundle_id.map(str::to_string),
+            bundle_name: bundle.map(|(name, _)| name.to_string()),
+            chip: bundle.map(|(_, chip)| chip.to_string()),
+            port: self.conf.port.clone(),
+        }
+    }
+
+    /// Return the eFuse fields read in the eFuse readouts step, as `(eFuse field, readout name)`
+    fn efuse_readout_fields(&self) -> Vec<(String, String)> {
+        static EFUSE_VALUES: &[&str] = &[
+            "MAC",
+            "WAFER_VERSION_MAJOR",
+            "WAFER_VERSION_MINOR",
+            // Not available on all chips
+            "OPTIONAL_UNIQUE_ID",
+            "FLASH_TYPE",
+            "FLASH_VENDOR",
+            "FLASH_CAP",
+            "PSRAM_CAP",
+            "PSRAM_TYPE",
+            "PSRAMP_VENDOR",
+        ];
+
+        if self.conf.efuse_readouts.is_empty() {
+            EFUSE_VALUES
+                .iter()
+                .map(|field| (field.to_string(), field.to_string()))
+                .collect()
+        } else {
+            self.conf
+                .efuse_readouts
+                .iter()
+                .map(|readout| {
+                    (
+                        readout.field.clone(),
+                        readout
+                            .alias
+                            .clone()
+                            .unwrap_or_else(|| readout.field.clone()),
+                    )
+                })
+                .collect()
+        }
+    }
+
+    /// Prepare the eFuse readouts by reading those from the chip eFuse memory
+    async fn prep_efuse_readouts(&mut self) -> anyhow::Result<Vec<(String, String)>> {
+        let fields = self.efuse_readout_fields();
+
+        self.model.modify(|inner| {
+            inner.state.processing_mut().status = "Reading Chip IDs from eFuse".to_string();
+        });
+
+        self.run_jig(jig::Sequence::PreFlash).await?;
+
+        info!("About to read Chip IDs from eFuse");
+
+        let backend = self.backend.clone();
+
+        let efuse_values = unblock("efuse-summary", move || {
+            let efuse_values =
+                backend.read_efuses(None, fields.iter().map(|(field, _)| field.as_str()))?;
+
+            // Fields not available on the chip are not reported
+            let efuse_values = fields
+                .iter()
+                .filter_map(|(field, name)| {
+                    let value = match &efuse_values.get(field)?.value {
+                        serde_json::Value::Null => None,
+                        serde_json::Value::String(value) => Some(value.clone()),
+                        // E.g. the ADC calibration fields are numbers
+                        value => Some(value.to_string()),
+                    }?;
+
+                    Some((name.clone(), value))
+                })
+                .collect::<Vec<_>>();
+
+            Ok(efuse_values)
+        })
+        .await?;
+
+        for (key, value) in efuse_values.iter() {
+            info!("Chip {key}: {value}");
+        }
+
+        Ok(efuse_values)
+    }
+
+    /// Prepare the bundle to be provisioned by creating a `Bundle` instance from the loaded bundle content
+    /// in the bundle workspace directory
+    async fn prep_bundle(&mut self, bundle_id: Option<&str>) -> anyhow::Result<()> {
+        let supply_default_partition_table =
+            self.bundle_base_loaders.is_empty() && self.conf.supply_default_partition_table;
+        let supply_default_bootloader =
+            self.bundle_base_loaders.is_empty() && self.conf.supply_default_bootloader;
+
+        // The bundle of a previous PCB which did not get provisioned is returned to its source
+        self.settle_bundles().await;
+
+        let session = self.session().clone();
+
+        let bundle = if let Some(prefetched) = self.prefetched_bundle.take() {
+            let (bundle_name, bundle_file) = prefetched.context("Prefetching the bundle failed")?;
+
+            info!("Using the prefetched bundle `{bundle_name}`");
+
+            Self::create_one_bundle(
+                &self.model,
+                &session,
+                bundle_name,
+                bundle_file,
+                supply_default_partition_table,
+                supply_default_bootloader,
+            )?
+        } else {
+            Self::prep_one_bundle(
+                &self.model,
+                &session,
+                bundle_id,
+                self.bundle_loader.as_mut().unwrap(),
+                supply_default_partition_table,
+                supply_default_bootloader,
+            )
+            .await?
+        };
+
+        self.claimed_bundle = Some(bundle.name.clone());
+
+        let layers = self.bundle_base_loaders.len();
+
+        let mut bundle = if layers > 0 {
+            let mut base_bundle: Option<Bundle> = None;
+
+            for (index, base_loader) in self.bundle_base_loaders.iter_mut().enumerate() {
+                info!("About to load base bundle layer {}/{layers}", index + 1);
+
+                // Only the bottom layer is supplied with the default partition table and bootloader
+                let bottom = base_bundle.is_none();
+
+                let layer_bundle = if self.conf.base_bundle_cache {
+                    let cache_dir = self
+                        .conf
+                        .base_bundle_cache_dir
+                        .as_ref()
+                        .map(PathBuf::from)
+                        .unwrap_or_else(CachedLoader::<B>::default_dir);
+
+                    Self::prep_one_bundle(
+                        &self.model,
+                        &session,
+                        None,
+                        CachedLoader::new(base_loader, cache_dir),
+                        bottom && self.conf.supply_default_partition_table,
+                        bottom && self.conf.supply_default_bootloader,
+                    )
+                    .await?
+                } else {
+                    Self::prep_one_bundle(
+                        &self.model,
+                        &session,
+                        None,
+                        base_loader,
+                        bottom && self.conf.supply_default_partition_table,
+                        bottom && self.conf.supply_default_bootloader,
+                    )
+                    .await?
+                };
+
+                info!(
+                    "Loaded base bundle layer {}/{layers} `{}`",
+                    index + 1,
+                    layer_bundle.name
+                );
+
+                base_bundle = Some(if let Some(mut base_bundle) = base_bundle {
+                    Self::merge_bundles(
+                        &self.model,
+                        &mut base_bundle,
+                        layer_bundle,
+                        self.conf.overwrite_on_merge,
+                    )?;
+
+                    base_bundle
+                } else {
+                    layer_bundle
+                });
+            }
+
+            let mut base_bundle = base_bundle.unwrap();
+
+            Self::merge_bundles(
+                &self.model,
+                &mut base_bundle,
+                bundle,
+                self.conf.overwrite_on_merge,
+            )?;
+
+            base_bundle
+        } else {
+            bundle
+        };
+
+        if let Some(config_override) = &bundle.config_override {
+            info!(
+                "Applying configuration override from bundle `{}`: {config_override:?}",
+                bundle.name
+            );
+
+            config_override.apply(&mut self.conf);
+            self.backend.configure(&self.conf);
+        }
+
+        // Only the destructive settings of the factory configuration were acknowledged at startup
+        if !self.conf.destructive_ack {
+            let acknowledged = self.base_conf.destructive_settings();
+            let unacknowledged = self
+                .conf
+                .destructive_settings()
+                .into_iter()
+                .filter(|setting| !acknowledged.contains(setting))
+                .map(|setting| format!("- {setting}"))
+                .collect::<Vec<_>>();
+
+            if !unacknowledged.is_empty() {
+                anyhow::bail!(
+                    "The configuration override for bundle `{}` has destructive settings which were not acknowledged:\n{}\nUse `--yes-i-know` to acknowledge them upfront",
+                    bundle.name,
+                    unacknowledged.join("\n")
+                );
+            }
+        }
+
+        for protection in &self.conf.efuse_key_protection {
+            bundle.protect_efuse(
+                &protection.block,
+                protection.read_protect,
+                protection.write_protect,
+            );
+        }
+
+        if let Some(efuse_plan) = &self.conf.efuse_plan {
+            info!("Adding the eFuses of eFuse plan `{efuse_plan}`");
+
+            let efuse_plan_str = std::fs::read_to_string(efuse_plan)
+                .with_context(|| format!("Loading eFuse plan `{efuse_plan}` failed"))?;
+
+            bundle.add_efuses(
+                Efuse::from_plan(&efuse_plan_str)
+                    .with_context(|| format!("Parsing eFuse plan `{efuse_plan}` failed"))?,
+            )?;
+        }
+
+        let chip = bundle.params.chip;
+        let fields = if let Some(tables) = &self.conf.efuse_native_tables {
+            efuse::field_names(tables, chip)?
+        } else {
+            efuse::known_fields(chip)
+                .map(|field| field.name.to_string())
+                .collect()
+        };
+
+        let unknown = bundle
+            .efuse_mapping
+            .iter()
+            .filter_map(|mapping| match &mapping.efuse {
+                Efuse::Param { name, .. } if !fields.contains(name) => Some(name.as_str()),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        if !unknown.is_empty() {
+            if self.conf.efuse_native_tables.is_some() || !self.conf.efuse_allow_unknown {
+                anyhow::bail!(
+                    "Unknown eFuses for chip {chip}: {}\nCheck the eFuse names against {}",
+                    unknown.join(", "),
+                    efuse::docs_url(chip)
+                );
+            }
+
+            warn!(
+                "eFuses not in the built-in table for chip {chip}: {}\nCheck the eFuse names against {}",
+                unknown.join(", "),
+                efuse::docs_url(chip)
+            );
+        }
+
+        if !self.conf.flash_readonly {
+            bundle.check_readonly()?;
+        }
+
+        if self.conf.flash_data_reset {
+            info!("Data reset: adding 0xff images for the data partitions, leaving the app partitions intact");
+
+            bundle.data_reset(self.conf.flash_readonly);
+        } else if self.conf.reset_empty_partitions {
+            info!("Adding 0xff images for empty partitions");
+
+            bundle.add_empty(self.conf.flash_readonly);
+        }
+
+        self.model.modify(move |inner| {
+            inner.state = State::Provision(Provision {
+                bundle_id: bundle_id.map(str::to_string),
+                readouts: Vec::new(),
+                bundle,
+                provisioning: false,
+            })
+        });
+
+        Ok(())
+    }
+
+    /// Provision the bundle by flashing and optionally efusing the chip with the bundle content
+    async fn prov_bundle(&mut self) -> anyhow::Result<(String, Chip)> {
+        let chip = self
+            .model
+            .access(|inner| inner.state.provision().bundle.params.chip);
+
+        self.prov_hook(PluginHook::PreFlash, chip).await?;
+
+        if let Some(nvs_keys) = self.conf.nvs_keys.clone() {
+            let flash_encrypt = self.conf.flash_encrypt;
+
+            // A new pair of keys on each provisioning attempt
+            let keys = nvs_keys::Keys::generate()?;
+
+            // The keys are escrowed before they are flashed, so that keys which cannot be recovered never end up on a PCB
+            let escrowed = if let Some(escrow_key) = nvs_keys.escrow.as_ref() {
+                let (escrow_file, escrowed) =
+                    escrow::escrow(escrow_key, escrow::NVS_KEYS_FILE, keys.as_bytes())?;
+
+                if !self.session().replaying() && self.conf.simulate.is_none() {
+                    let dir = nvs_keys
+                        .dir
+                        .as_ref()
+                        .map(PathBuf::from)
+                        .unwrap_or_else(escrow::default_dir);
+                    let bundle_name = self
+                        .model
+                        .access(|inner| inner.state.provision().bundle.name.clone());
+                    let file = escrow_file.clone();
+                    let content = escrowed.clone();
+
+                    let path = unblock("escrow", move || {
+                        escrow::persist(&dir, &bundle_name, &file, &content)
+                    })
+                    .await?;
+
+                    info!("Escrowed NVS keys persisted as `{}`", path.display());
+                }
+
+                Some((escrow_file, escrowed))
+            } else {
+                None
+            };
+
+            self.model.modify(|inner| {
+                let ps = inner.state.provision_mut();
+
+                let partition = match nvs_keys.partition.as_deref() {
+                    Some(partition) => partition.to_string(),
+                    None => ps
+                        .bundle
+                        .nvs_keys_partition()
+                        .context("No `nvs_keys` partition in the partition table")?
+                        .to_string(),
+                };
+
+                let encrypted = ps.bundle.parts_mapping.iter().any(|mapping| {
+                    mapping.partition.as_ref().is_some_and(|part| {
+                        part.name() == partition && part.encrypted()
+                    })
+                });
+
+                if flash_encrypt && !encrypted {
+                    warn!("Partition `{partition}` is not marked as encrypted, the NVS keys will be flashed in plain text");
+                }
+
+                ps.readouts.retain(|(name, _)| name != NVS_KEYS_HASH);
+                ps.readouts
+                    .push((NVS_KEYS_HASH.to_string(), sha256_hex(keys.to_hex())));
+
+                info!("Adding generated NVS keys for partition `{partition}`");
+
+                ps.bundle.set_image(
+                    &partition,
+                    Image::new("(nvs-keys)".to_string(), keys.partition_image()),
+                )?;
+
+                if let Some((escrow_file, escrowed)) = escrowed {
+                    let attachments = &mut inner.logs.attachments;
+
+                    attachments.retain(|(attachment, _)| *attachment != escrow_file);
+                    attachments.push((escrow_file, escrowed));
+                }
+
+                Ok::<_, anyhow::Error>(())
+            })?;
+        }
+
+        if let Some(keygen) = self.conf.flash_encrypt_keygen.clone() {
+            if !self.conf.flash_encrypt {
+                anyhow::bail!("`flash_encrypt_keygen` requires `flash_encrypt = true`");
+            }
+
+            let block = keygen.block.as_deref().unwrap_or(escrow::DEFAULT_BLOCK);
+
+            // The key is escrowed before anything is burned, so that a key which cannot be recovered never ends up on a PCB
+            let (key, escrow_file, escrowed) = match self.flash_key.clone() {
+                // The key of a previous provisioning attempt of the PCB is kept, as it might be burned already
+                Some(key) => {
+                    let (escrow_file, escrowed) =
+                        escrow::escrow(&keygen.escrow, escrow::FLASH_KEY_FILE, &key)?;
+
+                    (key, escrow_file, escrowed)
+                }
+                None => {
+                    let key = escrow::generate()?;
+                    let (escrow_file, escrowed) =
+                        escrow::escrow(&keygen.escrow, escrow::FLASH_KEY_FILE, &key)?;
+
+                    if !self.session().replaying() && self.conf.simulate.is_none() {
+                        let dir = keygen
+                            .dir
+                            .as_ref()
+                            .map(PathBuf::from)
+                            .unwrap_or_else(escrow::default_dir);
+                        let bundle_name = self
+                            .model
+                            .access(|inner| inner.state.provision().bundle.name.clone());
+                        let file = escrow_file.clone();
+                        let content = escrowed.clone();
+
+                        let path = unblock("escrow", move || {
+                            escrow::persist(&dir, &bundle_name, &file, &content)
+                        })
+                        .await?;
+
+                        info!(
+                            "Escrowed flash encryption key persisted as `{}`",
+                            path.display()
+                        );
+                    }
+
+                    self.flash_key = Some(key.clone());
+
+                    (key, escrow_file, escrowed)
+                }
+            };
+
+            self.model.modify(|inner| {
+                let ps = inner.state.provision_mut();
+
+                if ps.bundle.get_flash_encrypt_keys().any(|existing| existing != key.as_slice()) {
+                    anyhow::bail!(
+                        "Bundle `{}` has a flash encryption key of its own, which conflicts with `flash_encrypt_keygen`",
+                        ps.bundle.name
+                    );
+                }
+
+                if ps.bundle.get_flash_encrypt_keys().next().is_none() {
+                    info!("Adding a generated flash encryption key for eFuse block `{block}`");
+
+                    ps.bundle.add_efuses([Efuse::Key {
+                        block: block.to_string(),
+                        key_value: Arc::new(key.clone()),
+                        purpose: escrow::PURPOSE.to_string(),
+                        read_protect: None,
+                        write_protect: None,
+                    }])?;
+
+                    ps.readouts.retain(|(name, _)| name != FLASH_KEY_HASH);
+                    ps.readouts
+                        .push((FLASH_KEY_HASH.to_string(), sha256_hex(&key)));
+                }
+
+                let attachments = &mut inner.logs.attachments;
+
+                attachments.retain(|(attachment, _)| *attachment != escrow_file);
+                attachments.push((escrow_file, escrowed));
+
+                Ok(())
+            })?;
+        }
+
+        if let Some(otadata_boot) = self.conf.otadata_boot {
+            self.model.modify(|inner| {
+                let ps = inner.state.provision_mut();
+
+                let (partition, layout) = ps
+                    .bundle
+                    .otadata_partition()
+                    .zip(ps.bundle.ota_layout())
+                    .context("No `otadata` partition in the partition table")?;
+                let partition = partition.to_string();
+
+                let slot = match otadata_boot {
+                    OtaBoot::Factory => {
+                        if !ps.bundle.has_factory_partition() {
+                            warn!("No `factory` partition in the partition table, the bootloader will boot the first OTA app slot");
+                        }
+
+                        None
+                    }
+                    OtaBoot::Ota(index) => {
+                        if index as usize >= layout.slots.len() {
+                            anyhow::bail!(
+                                "Boot slot `{otadata_boot}` not found, the partition table has {} OTA app slots",
+                                layout.slots.len()
+                            );
+                        }
+
+                        Some(index as usize)
+                    }
+                };
+
+                info!("Adding generated `otadata` selecting boot slot `{otadata_boot}` for partition `{partition}`");
+
+                ps.bundle.set_image(
+                    &partition,
+                    Image::new(
+                        format!("(otadata {otadata_boot})"),
+                        ota::otadata_image(layout.otadata.1 as _, slot),
+                    ),
+                )
+            })?;
+        }
+
+        // Created last, so that the report hash covers all readouts recorded so far (e.g. the hashes of the generated keys)
+        if let Some(birth_certificate) = &self.conf.birth_certificate {
+            self.model.modify(|inner| {
+                let ps = inner.state.provision_mut();
+
+                let station_id = if birth_certificate.station_id.is_empty() {
+                    ps.readouts
+                        .iter()
+                        .find(|(name, _)| name == TEST_JIG_ID)
+                        .map(|(_, value)| value.as_str())
+                        .unwrap_or_default()
+                } else {
+                    birth_certificate.station_id.as_str()
+                };
+
+                let blob = certificate::create(station_id, &ps.bundle.name, &ps.readouts)?;
+
+                info!(
+                    "Adding birth certificate ({}B) for partition `{}`",
+                    blob.len(),
+                    birth_certificate.partition
+                );
+
+                ps.bundle.set_image(
+                    &birth_certificate.partition,
+                    Image::new("(birth-certificate)".to_string(), blob),
+                )
+            })?;
+        }
+
+        let bundle_name = self.model.modify(|inner| {
+            let ps = inner.state.provision_mut();
+            ps.provisioning = true;
+
+            ps.bundle.set_status_all(ProvisioningStatus::Pending);
+
+            ps.bundle.name.clone()
+        });
+
+        info!("About to provision bundle `{bundle_name}`");
+
+        self.run_jig(jig::Sequence::PreFlash).await?;
+
+        let flash_erase_all = self.conf.flash_erase;
+
+        let (chip, flash_size, keys, flash_data) = self.model.access(|inner| {
+            let ps = inner.state.provision();
+
+            (
+                ps.bundle.params.chip,
+                ps.bundle.params.flash_size,
+                ps.bundle
+                    .get_flash_encrypt_keys()
+                    .map(|key| Secret::new(key.to_vec()))
+                    .collect::<Vec<_>>(),
+                ps.bundle.get_flash_data().collect::<Vec<_>>(),
+            )
+        });
+
+        let flash_encrypt_key = if self.conf.flash_encrypt
+            && flash_data.iter().any(|fd| fd.encrypted_partition)
+        {
+            let key = if keys.is_empty() {
+                anyhow::bail!("No encryption keys provided for flash data");
+            } else if keys.len() > 1 {
+                anyhow::bail!("Multiple encryption keys provided for flash data");
+            } else {
+                &keys[0]
+            };
+
+            info!(
+                    "About to ENCRYPT flash data while flashing: Chip={chip:?}, Flash Size={flash_size:?}, Images N={}",
+                    flash_data.len()
+                );
+
+            Some(key.clone())
+        } else {
+            None
+        };
+
+        if flash_erase_all {
+            info!("About to erase all flash using the standard `Flash Erase` command: Chip={chip:?}, Flash Size={flash_size:?}");
+        }
+
+        info!(
+            "About to flash data: Chip={chip:?}, Flash Size={flash_size:?}, Images N={}",
+            flash_data.len()
+        );
+
+        let flash_backend = self.backend.clone();
+        // With `esptool.py` and the JTAG tools, the tool invocations themselves are recorded in the session
+        let flash_tools = flash_backend.tools();
+        let flash_model = self.model.clone();
+        let flash_dry_run = self.conf.flash_dry_run;
+        let flash_encrypt_threads = self.conf.flash_encrypt_threads;
+
+        if !flash_tools && self.conf.flash_compress == Some(false) {
+            warn!(
+                "The native flasher always compresses the images, `flash_compress = false` ignored"
+            );
+        }
+
+        let flash_diff = if !self.conf.flash_diff {
+            false
+        } else if flash_tools {
+            warn!("Diff flashing is only supported with the native flasher, flashing all images");
+            false
+        } else if flash_erase_all {
+            info!("Flash is erased, diff flashing skipped");
+            false
+        } else {
+            info!("Diff flashing: only the images which differ from the flash content will be flashed");
+            true
+        };
+
+        info!("Flashing with the {} backend", flash_backend.name());
+
+        self.model
+            .modify(|inner| inner.cycle.start(CycleStep::Flash));
+
+        self.claimed_bundle_written = self.claimed_bundle.is_some();
+
+        if !flash_tools && self.session().replaying() {
+            info!("Replaying a session, chip detection skipped");
+        } else {
+            self.check_chip(chip).await?;
+        }
+
+        let flash_bytes = flash_data.iter().map(|fd| fd.data.len()).sum::<usize>();
+        let flash_checksums = flash_data
+            .iter()
+            .map(|fd| (fd.offset, fd.data.len(), sha256_hex(fd.data.as_slice())))
+            .collect::<Vec<_>>();
+        let flash_start = std::time::Instant::now();
+        let flash_session = self.session().clone();
+
+        let result = unblock("flash", move || {
+            let audit_model = flash_model.clone();
+            let mut progress = FlashProgress::new(flash_model);
+
+            let flash_data = EncryptPipeline::new(
+                flash_data,
+                flash_encrypt_key,
+                flash_encrypt_threads,
+                flash_backend.connection().tools.clone(),
+            );
+
+            if !flash_tools && flash_session.replaying() {
+                let flash_data = flash_data.collect::<anyhow::Result<Vec<_>>>()?;
+
+                info!("Replaying the flashing from the session");
+
+                return flash_session
+                    .replay_flash(&flash_data, &mut progress)
+                    .unwrap_or(Ok(()))
+                    .map(|_| (Self::flashed_md5s(&flash_data), Vec::new()));
+            }
+
+            let erase_params =
+                format!("chip={chip};flash_size={flash_size:?};dry_run={flash_dry_run}");
+
+            // The hashes of the images are collected while flashing, as the images
+            // might still be in the process of being encrypted
+            let mut flash_params = format!("{erase_params};diff={flash_diff}");
+            let mut flashed_md5s = Vec::new();
+            let flash_data = flash_data.inspect(|flash_data| {
+                if let Ok(flash_data) = flash_data {
+                    let _ = write!(
+                        &mut flash_params,
+                        ";0x{:08x}={}",
+                        flash_data.offset,
+                        sha256_hex(flash_data.data.as_slice())
+                    );
+
+                    flashed_md5s.push((flash_data.offset, flash::md5(flash_data.data.as_slice())));
+                }
+            });
+
+            if flash_erase_all {
+                Self::audit(
+                    &audit_model,
+                    "erase-flash",
+                    &erase_params,
+                    flash_backend.erase(chip, flash_size, flash_dry_run),
+                )?;
+            }
+
+            let result = flash_backend.write(
+                chip,
+                flash_size,
+                flash_data,
+                flash_diff,
+                flash_dry_run,
+                &mut progress,
+            );
+
+            let device_md5s = Self::audit(&audit_model, "flash", &flash_params, result)?;
+
+            Ok((flashed_md5s, device_md5s))
+        })
+        .await;
+
+        if !flash_tools {
+            self.session()
+                .record_flash(flash_start.elapsed(), result.as_ref().err());
+        }
+
+        let (flashed_md5s, device_md5s) = result.context(Failure::Flash)?;
+
+        if self.conf.flash_verify && flash_tools {
+            warn!("Flash verification is only supported with the native flasher, verification skipped");
+        }
+
+        self.record_checksums(flash_checksums, flashed_md5s, device_md5s)
+            .context(Failure::Flash)?;
+
+        if !flash_tools && self.session().replaying() {
+            info!("Flash complete");
+        } else {
+            let flash_time = flash_start.elapsed();
+
+            info!(
+                "Flash complete: {flash_bytes}B in {:.1}s",
+                flash_time.as_secs_f64()
+            );
+
+            self.model.metrics().flashed(flash_bytes, flash_time);
+        }
+
+        record_timed(&self.model, "flash", flash_start.elapsed());
+
+        self.model
+            .modify(|inner| inner.cycle.finish(CycleStep::Flash));
+
+        self.prov_hook(PluginHook::PostFlash, chip).await?;
+
+        self.prov_efuses().await
+    }
+
+    /// Burn the eFuses of the bundle, once its images are flashed
+    ///
+    /// Also the entry point when retrying just the eFuse burning (see `Task::offer_efuse_reconnect`)
+    async fn prov_efuses(&mut self) -> anyhow::Result<(String, Chip)> {
+        let (bundle_name, chip) = self.model.access(|inner| {
+            let ps = inner.state.provision();
+
+            (ps.bundle.name.clone(), ps.bundle.params.chip)
+        });
+
+        info!("About to burn eFuses");
+
+        self.model
+            .modify(|inner| inner.cycle.start(CycleStep::EfuseBurn));
+
+        self.claimed_bundle_written = self.claimed_bundle.is_some();
+
+        let model = self.model.clone();
+
+        let efuse_protect_keys = self.conf.efuse_protect_keys;
+        let efuse_protect_digests = self.conf.efuse_protect_digests;
+        let efuse_backend = self.backend.clone();
+        let efuse_dry_run = self.conf.efuse_dry_run;
+        let efuse_batch = self.conf.efuse_batch;
+
+        self.efuse_snapshot(chip, "efuse-before.json").await;
+
+        let efuse_start = std::time::Instant::now();
+
+        unblock("efuse-burn", move || {
+            Self::burn(
+                &model,
+                &efuse_backend,
+                efuse_protect_keys,
+                efuse_protect_digests,
+                chip,
+                efuse_dry_run,
+                efuse_batch,
+            )
+        })
+        .await
+        .context(Failure::Efuse)?;
+
+        info!("Burn complete");
+
+        self.model
+            .modify(|inner| inner.cycle.finish(CycleStep::EfuseBurn));
+
+        record_timed(&self.model, "efuse", efuse_start.elapsed());
+
+        self.efuse_snapshot(chip, "efuse-after.json").await;
+
+        self.prov_hook(PluginHook::PostEfuse, chip).await?;
+
+        self.run_jig(jig::Sequence::PostFlash).await?;
+
+        info!("Provisioning bundle `{bundle_name}` complete");
+
+        Ok((bundle_name, chip))
+    }
+
+    /// Return the MD5 checksums of the flashed data, by image offset
+    fn flashed_md5s(flash_data: &[FlashData]) -> Vec<(u32, u128)> {
+        flash_data
+            .iter()
+            .map(|fd| (fd.offset, flash::md5(fd.data.as_slice())))
+            .collect()
+    }
+
+    /// Record the checksums of the flashed images in the test report and in the `checksums.csv` file
+    /// of the PCB logs, and fail if an MD5 checksum read back from the chip does not match the one of the flashed data
+    ///
+    /// # Arguments
+    /// - `images` - the (offset, size, SHA-256 hash) of each image of the bundle
+    /// - `flashed` - the MD5 checksums of the flashed data, by image offset
+    /// - `device` - the MD5 checksums read back from the chip, by image offset (empty if the flashing was not verified)
+    fn record_checksums(
+        &self,
+        images: Vec<(u32, usize, String)>,
+        flashed: Vec<(u32, u128)>,
+        device: Vec<(u32, u128)>,
+    ) -> anyhow::Result<()> {
+        let find = |digests: &[(u32, u128)], offset| {
+            digests
+                .iter()
+                .find(|(digest_offset, _)| *digest_offset == offset)
+                .map(|(_, digest)| format!("{digest:032x}"))
+        };
+
+        let images = images
+            .into_iter()
+            .map(|(offset, size, sha256)| ImageChecksum {
+                offset,
+                size,
+                sha256,
+                md5: find(&flashed, offset).unwrap_or_default(),
+                device_md5: find(&device, offset),
+            })
+            .collect::<Vec<_>>();
+
+        let mismatched = images
+            .iter()
+            .filter(|image| image.verified() == Some(false))
+            .map(|image| format!("0x{:08x}", image.offset))
+            .collect::<Vec<_>>();
+
+        if !device.is_empty() && mismatched.is_empty() {
+            info!("Flash verified: the MD5 checksums of all images match");
+        }
+
+        let csv = self.model.access_mut(|inner| {
+            inner.logs.report.record_images(images);
+
+            let csv = inner.logs.report.images_csv();
+
+            if let Ok(csv) = csv.as_ref() {
+                let attachments = &mut inner.logs.attachments;
+
+                // Replace the manifest of a previous flashing attempt, if any
+                attachments.retain(|(attachment, _)| attachment != CHECKSUMS_FILE_NAME);
+                attachments.push((CHECKSUMS_FILE_NAME.to_string(), csv.clone()));
+            }
+
+            (csv, false)
+        });
+
+        if let Err(err) = csv {
+            warn!("Rendering the `{CHECKSUMS_FILE_NAME}` manifest failed: {err:?}");
+        }
+
+        if !mismatched.is_empty() {
+            anyhow::bail!(
+                "Flash verification failed: the MD5 checksums read back from the chip do not match for the images at {}",
+                mismatched.join(", ")
+            );
+        }
+
+        Ok(())
+    }
+
+    /// Take a snapshot of the full eFuse summary of the chip and attach it to the PCB logs with the given file name
+    /// (see `Config::efuse_snapshots`)
+    ///
+    /// Failing to take the snapshot (e.g. because the chip is in Secure Download mode) is not an error
+    async fn efuse_snapshot(&self, chip: Chip, name: &str) {
+        if !self.conf.efuse_snapshots {
+            return;
+        }
+
+        let backend = self.backend.clone();
+
+        let result = unblock("efuse-snapshot", move || backend.efuse_summary_json(chip)).await;
+
+        match result {
+            Ok(summary) => {
+                info!("eFuse summary snapshot `{name}` taken");
+
+                self.model.access_mut(|inner| {
+                    let attachments = &mut inner.logs.attachments;
+
+                    // Replace the snapshot of a previous provisioning attempt, if any
+                    attachments.retain(|(attachment, _)| attachment != name);
+                    attachments.push((name.to_string(), summary));
+
+                    ((), false)
+                });
+            }
+            Err(err) => warn!("Taking the eFuse summary snapshot `{name}` failed: {err:?}"),
+        }
+    }
+
+    async fn run_app(
+        &mut self,
+        bundle_name: String,
+        chip: Chip,
+        elf: Option<Arc<Vec<u8>>>,
+        ota_layout: Option<OtaLayout>,
+    ) -> anyhow::Result<AppRunCapture> {
+        let mut capture = AppRunCapture::default();
+
+        if !matches!(self.conf.app_run, AppRun::Disabled) {
+            info!("Running app to finish provisioning");
+
+            self.model.modify(|inner| {
+                inner.state = State::AppRun(AppLogs::new(100, self.conf.app_run_log_colors));
+            });
+
+            let run_backend = self.backend.clone();
+            let run_monitor_speed = self.conf.monitor_speed.unwrap_or(DEFAULT_BAUD_RATE);
+            let run_model = Arc::new(Mutex::new(Some(self.model.clone())));
+            let run_model_inner = run_model.clone();
+            let run_stop = Arc::new(AtomicBool::new(false));
             let run_stop_inner = run_stop.clone();
-            let (run_end_regex, run_timeout_secs) = match &self.conf.app_run {
+            let (run_end_regex, run_capture, run_timeout_secs) = match &self.conf.app_run {
                 AppRun::MatchPattern {
                     pattern,
                     timeout_secs,
                 } => (
                     Some(regex::Regex::new(pattern).context("Invalid regex pattern")?),
+                    false,
+                    *timeout_secs,
+                ),
+                AppRun::Capture {
+                    pattern,
+                    timeout_secs,
+                    ..
+                } => (
+                    Some(regex::Regex::new(pattern).context("Invalid regex pattern")?),
+                    true,
                     *timeout_secs,
                 ),
-                AppRun::ForSecs { secs } => (None, *secs),
+                AppRun::Expect { timeout_secs, .. } => (None, false, *timeout_secs),
+                AppRun::Script { steps, .. } => (
+                    None,
+                    false,
+                    steps.iter().map(|step| step.timeout_secs).sum(),
+                ),
+                AppRun::ForSecs { secs } => (None, false, *secs),
                 _ => unreachable!(),
             };
-            let run_end_regex_present = run_end_regex.is_some();
+            let run_expect = match &self.conf.app_run {
+                AppRun::Expect {
+                    patterns,
+                    fail_patterns,
+                    ordered,
+                    ..
+                } => Some(AppRunExpect::new(patterns, fail_patterns, *ordered)?),
+                _ => None,
+            };
+            let (run_script, run_input) = match &self.conf.app_run {
+                AppRun::Script {
+                    steps,
+                    fail_patterns,
+                } => {
+                    let (input, input_recv) = mpsc::channel();
+
+                    (
+                        Some(AppRunScript::new(steps, fail_patterns, input)?),
+                        Some(input_recv),
+                    )
+                }
+                _ => (None, None),
+            };
+            let run_end_regex_present =
+                run_end_regex.is_some() || run_expect.is_some() || run_script.is_some();
+            let run_expect = Arc::new(Mutex::new(run_expect));
+            let run_expect_inner = run_expect.clone();
+            let run_script = Arc::new(Mutex::new(run_script));
+            let run_script_inner = run_script.clone();
+            let run_script_timeout = run_script.clone();
+            let run_captures = Arc::new(Mutex::new(None::<Vec<(String, String)>>));
+            let run_captures_inner = run_captures.clone();
+            let mut run_log = String::new();
+            let run_log_format = match self.conf.app_run_log_format {
+                AppLogFormat::Serial => LogFormat::Serial,
+                AppLogFormat::Defmt => LogFormat::Defmt,
+            };
+
+            let mut log_task = pin!(unblock("run-app", move || {
+                run_backend.run_app(chip)?;
+
+                info!("APP LOG START >>>>>>>>>>>>>>>>>>>>>>>>>>");
+
+                if let Some(script) = run_script_inner.lock().unwrap().as_mut() {
+                    if script.start() {
+                        run_stop_inner.store(true, Ordering::SeqCst);
+                    }
+                }
+
+                run_backend.monitor(
+                    elf.as_ref().map(|elf| elf.as_slice()),
+                    run_monitor_speed,
+                    run_log_format,
+                    run_stop_inner.clone(),
+                    run_input,
+                    LineWrite::new(move |line| {
+                        let model = run_model_inner.lock().unwrap();
+
+                        if let Some(model) = model.as_ref() {
+                            // The app logs view renders the ANSI escape sequences as configured,
+                            // while the PCB logs and the pattern matching need them stripped
+                            model.modify(|inner| {
+                                inner.state.app_logs_mut().append(line);
+                            });
+
+                            let line = strip_ansi_escapes::strip_str(line);
+
+                            info!("[APP LOG] {line}");
+
+                            if let Some(regex) = run_end_regex.as_ref() {
+                                if run_capture {
+                                    let mut captures = run_captures_inner.lock().unwrap();
+
+                                    if captures.is_none() {
+                                        run_log.push_str(&line);
+                                        run_log.push('\n');
+
+                                        if run_log.len() > RUN_LOG_MAX_SIZE {
+                                            let mut start = run_log.len() - RUN_LOG_MAX_SIZE / 2;
+                                            while !run_log.is_char_boundary(start) {
+                                                start += 1;
+                                            }
+
+                                            run_log.drain(..start);
+                                        }
+
+                                        if let Some(found) = regex.captures(&run_log) {
+                                            *captures = Some(
+                                                found
+                                                    .iter()
+                                                    .zip(regex.capture_names())
+                                                    .enumerate()
+                                                    .skip(1)
+                                                    .filter_map(|(index, (value, name))| {
+                                                        Some((
+                                                            name.map(str::to_string)
+                                                                .unwrap_or_else(|| {
+                                                                    format!("Capture {index}")
+                                                                }),
+                                                            value?.as_str().to_string(),
+                                                        ))
+                                                    })
+                                                    .collect(),
+                                            );
+
+                                            run_stop_inner.store(true, Ordering::SeqCst);
+                                            info!("[App run finishing, captured pattern ending on this line ^^^]");
+                                        }
+                                    }
+                                } else if regex.is_match(&line) {
+                                    run_stop_inner.store(true, Ordering::SeqCst);
+                                    info!("[App run finishing, detected pattern on this line ^^^]");
+                                }
+                            }
+
+                            if let Some(expect) = run_expect_inner.lock().unwrap().as_mut() {
+                                if expect.feed(&line) {
+                                    run_stop_inner.store(true, Ordering::SeqCst);
+                                }
+                            }
+
+                            if let Some(script) = run_script_inner.lock().unwrap().as_mut() {
+                                if script.feed(&line) {
+                                    run_stop_inner.store(true, Ordering::SeqCst);
+                                }
+                            }
+                        }
+                    }),
+                )?;
+
+                info!("APP LOG END <<<<<<<<<<<<<<<<<<<<<<<<<<<<");
+
+                Ok(())
+            }));
+
+            let mut timeout_task = pin!(async move {
+                if run_script_timeout.lock().unwrap().is_some() {
+                    // Each step of the script has its own timeout
+                    let mut tick = Ticker::every(Duration::from_millis(100));
+
+                    loop {
+                        tick.next().await;
+
+                        if run_script_timeout
+                            .lock()
+                            .unwrap()
+                            .as_mut()
+                            .is_some_and(AppRunScript::poll)
+                        {
+                            break;
+                        }
+                    }
+                } else {
+                    embassy_time::Timer::after(Duration::from_secs(run_timeout_secs as _)).await;
+                }
+            });
+
+            let run_started = std::time::Instant::now();
+
+            let result = select(&mut log_task, &mut timeout_task).await;
+
+            run_stop.store(true, Ordering::SeqCst);
+            *run_model.lock().unwrap() = None;
+
+            if let AppRun::MatchPattern { pattern, .. } | AppRun::Capture { pattern, .. } =
+                &self.conf.app_run
+            {
+                let outcome = if matches!(result, Either::First(Ok(_))) {
+                    StepOutcome::Passed
+                } else {
+                    StepOutcome::Failed {
+                        code: "APP_RUN_PATTERN_NOT_MATCHED".to_string(),
+                        message: format!(
+                            "Pattern `{pattern}` not matched in the app logs within {run_timeout_secs} seconds"
+                        ),
+                    }
+                };
+
+                self.model.access_mut(|inner| {
+                    inner.logs.report.record(
+                        if run_capture {
+                            "app-run-capture"
+                        } else {
+                            "app-run-pattern"
+                        },
+                        run_started.elapsed(),
+                        outcome,
+                    );
+
+                    ((), false)
+                });
+            }
+
+            if let Some(expect) = run_expect.lock().unwrap().as_ref() {
+                let elapsed = run_started.elapsed();
+
+                self.model.access_mut(|inner| {
+                    for (step, outcome) in expect.outcomes(run_timeout_secs) {
+                        inner.logs.report.record(&step, elapsed, outcome);
+                    }
+
+                    ((), false)
+                });
+
+                if let Some(failure) = expect.failure.as_ref() {
+                    error!("App run failed: {failure}");
+                    anyhow::bail!("App run failed: {failure}");
+                }
+
+                if matches!(result, Either::First(Ok(_))) && !expect.is_done() {
+                    anyhow::bail!("App run ended before all patterns were matched");
+                }
+            }
+
+            if let Some(script) = run_script.lock().unwrap().as_ref() {
+                let elapsed = run_started.elapsed();
+
+                self.model.access_mut(|inner| {
+                    for (step, outcome) in script.outcomes() {
+                        inner.logs.report.record(&step, elapsed, outcome);
+                    }
+
+                    ((), false)
+                });
+
+                if let Some(failure) = script.failure.as_ref() {
+                    error!("App run failed: {failure}");
+                    anyhow::bail!("App run failed: {failure}");
+                }
+
+                if matches!(result, Either::First(Ok(_))) && !script.is_done() {
+                    anyhow::bail!("App run ended before all script steps were complete");
+                }
+            }
+
+            match result {
+                Either::First(result) => {
+                    result?;
+
+                    info!("App run auccessful, match pattern detected");
+                }
+                Either::Second(_) => {
+                    if run_end_regex_present {
+                        error!("App run timeout after {run_timeout_secs} seconds");
+                        anyhow::bail!("App run timeout after {run_timeout_secs} seconds");
+                    } else {
+                        info!("App run auccessful, timeout reached");
+                    }
+                }
+            }
+
+            if let Some(captured) = run_captures.lock().unwrap().take() {
+                if let AppRun::Capture { artifacts, .. } = &self.conf.app_run {
+                    for artifact in artifacts {
+                        let value = captured
+                            .iter()
+                            .find(|(name, _)| {
+                                *name == artifact.group
+                                    || *name == format!("Capture {}", artifact.group)
+                            })
+                            .map(|(_, value)| value.clone())
+                            .with_context(|| {
+                                format!(
+                                    "Capture group `{}` of artifact `{}` not captured",
+                                    artifact.group, artifact.file
+                                )
+                            })?;
+
+                        capture.artifacts.push((artifact.file.clone(), value));
+                    }
+                }
+
+                for (name, value) in &captured {
+                    info!("Captured {name}: {value}");
+                }
+
+                capture.readouts = captured;
+            }
+        } else {
+            info!("App run disabled");
+        }
+
+        if let Some(ota_verify) = self.conf.app_run_ota_verify.clone() {
+            self.verify_ota(chip, ota_layout, ota_verify).await?;
+        }
+
+        self.model.events().emit(ProvisioningEvent::Provisioned {
+            bundle: bundle_name.to_string(),
+        });
+
+        self.model.modify(|inner| {
+            inner.state.result(
+                format!(" {bundle_name} "),
+                format!(
+                    "Provisioning complete at {}.",
+                    self.conf.ui_locale.format_now()
+                ),
+                false,
+            );
+        });
+
+        Ok(capture)
+    }
+
+    /// Verify that the OTA app slot activated during the app run is the expected one,
+    /// and - optionally - that it contains the expected app image
+    async fn verify_ota(
+        &self,
+        chip: Chip,
+        ota_layout: Option<OtaLayout>,
+        ota_verify: OtaVerify,
+    ) -> anyhow::Result<()> {
+        let Some(ota_layout) = ota_layout else {
+            anyhow::bail!(
+                "Cannot verify the OTA app slot: the partition table has no `otadata` partition"
+            );
+        };
+
+        let Some(expected_slot) = ota_layout
+            .slots
+            .iter()
+            .position(|(name, _, _)| *name == ota_verify.slot)
+        else {
+            anyhow::bail!(
+                "Cannot verify the OTA app slot: partition `{}` is not an OTA app partition",
+                ota_verify.slot
+            );
+        };
+
+        info!(
+            "Verifying that OTA app slot `{}` is active",
+            ota_verify.slot
+        );
+
+        let backend = self.backend.clone();
+
+        unblock("verify-ota", move || {
+            let (otadata_offset, otadata_size) = ota_layout.otadata;
+
+            let otadata = backend.read(chip, otadata_offset, otadata_size)?;
+
+            let Some(active_slot) = ota::active_slot(&otadata, ota_layout.slots.len()) else {
+                anyhow::bail!(
+                    "No OTA app slot is active (expected `{}`), the OTA update did not complete",
+                    ota_verify.slot
+                );
+            };
+
+            let (name, offset, size) = &ota_layout.slots[active_slot];
+
+            if active_slot != expected_slot {
+                anyhow::bail!(
+                    "OTA app slot `{name}` is active, expected `{}`",
+                    ota_verify.slot
+                );
+            }
+
+            info!("OTA app slot `{name}` is active");
+
+            if let Some(expected_hash) = &ota_verify.sha256 {
+                let data = backend.read(chip, *offset, *size)?;
+
+                let hash = ota::image_hash(&data)
+                    .with_context(|| format!("Verifying the app image in OTA app slot `{name}` failed"))?;
+
+                if !hash.eq_ignore_ascii_case(expected_hash.trim()) {
+                    anyhow::bail!(
+                        "The app image in OTA app slot `{name}` has hash {hash}, expected {expected_hash}"
+                    );
+                }
+
+                info!("The app image in OTA app slot `{name}` has the expected hash {hash}");
+            }
 
-            let mut log_task = pin!(unblock("run-app", move || {
-                flash::run_app_esptool(run_port.as_deref(), chip, run_use_stub, run_speed)?;
+            Ok(())
+        })
+        .await
+    }
 
-                info!("APP LOG START >>>>>>>>>>>>>>>>>>>>>>>>>>");
+    /// Commit the bundles of the provisioned PCBs, and settle the bundle of the PCB being provisioned (if any),
+    /// as it did not get provisioned:
+    /// - if the bundle was not written to the PCB yet, it is released, so that it can be loaded again
+    /// - otherwise it is quarantined, as its identity (e.g. its keys or its certificate) might be on the PCB already
+    ///
+    /// Deferred while the bundle loader is prefetching the bundle of the next PCB
+    async fn settle_bundles(&mut self) {
+        let Some(loader) = self.bundle_loader.as_mut() else {
+            return;
+        };
 
-                monitor::monitor(
-                    run_port.as_deref(),
-                    None,
-                    DEFAULT_BAUD_RATE,
-                    LogFormat::Serial,
-                    false,
-                    run_stop_inner.clone(),
-                    LineWrite::new(move |line| {
-                        let model = run_model_inner.lock().unwrap();
+        for bundle_name in self.provisioned_bundles.drain(..) {
+            if let Err(err) = loader.commit(&bundle_name).await {
+                error!("Removing bundle `{bundle_name}` from its source failed: {err:?}");
+            }
+        }
 
-                        if let Some(model) = model.as_ref() {
-                            // Strip the ANSI escape sequences
-                            // TODO: Do something more intelligent in the future
-                            //let line = line.chars().filter(|c| *c >= ' ').collect::<String>();
-                            let line = strip_ansi_escapes::strip_str(line);
+        let written = core::mem::take(&mut self.claimed_bundle_written);
 
-                            info!("[APP LOG] {line}");
+        if let Some(bundle_name) = self.claimed_bundle.take() {
+            if written {
+                warn!("Bundle `{bundle_name}` was written to a PCB which was not provisioned, quarantining it");
 
-                            model.modify(|inner| {
-                                inner.state.app_logs_mut().append(line.clone());
-                            });
+                if let Err(err) = loader.quarantine(&bundle_name).await {
+                    error!("Quarantining bundle `{bundle_name}` failed: {err:?}");
+                }
+            } else {
+                self.release_bundle(&bundle_name).await;
+            }
+        }
+    }
 
-                            if let Some(regex) = run_end_regex.as_ref() {
-                                if regex.is_match(&line) {
-                                    run_stop_inner.store(true, Ordering::SeqCst);
-                                    info!("[App run finishing, detected pattern on this line ^^^]");
-                                }
-                            }
-                        }
-                    }),
-                )?;
+    /// Release a bundle which did not get provisioned, so that it can be loaded again
+    async fn release_bundle(&mut self, bundle_name: &str) {
+        let Some(loader) = self.bundle_loader.as_mut() else {
+            return;
+        };
 
-                info!("APP LOG END <<<<<<<<<<<<<<<<<<<<<<<<<<<<");
+        if let Err(err) = loader.release(bundle_name).await {
+            error!("Returning bundle `{bundle_name}` to its source failed: {err:?}");
+        }
+    }
 
-                Ok(())
-            }));
+    /// Fetch the bundle of the next PCB in the background (see `Config::bundle_prefetch`)
+    ///
+    /// The loader is returned back together with the outcome of the fetching
+    async fn prefetch_bundle(
+        mut loader: L,
+        events: Events,
+    ) -> (L, anyhow::Result<(String, NamedTempFile)>) {
+        let result = Self::fetch_one_bundle(&events, None, &mut loader).await;
+
+        if let Err(err) = &result {
+            warn!("Prefetching the bundle of the next PCB failed: {err:?}");
+        }
 
-            let mut timeout_task = pin!(embassy_time::Timer::after(Duration::from_secs(
-                run_timeout_secs as _
-            )));
+        (loader, result)
+    }
 
-            let result = select(&mut log_task, &mut timeout_task).await;
+    /// Run the future while driving the prefetching of the bundle of the next PCB (if any) in the background
+    ///
+    /// If the prefetching completes first, its outcome is stored in `prefetched`
+    async fn prefetching<F, P>(
+        fut: F,
+        prefetch: &mut Option<P>,
+        prefetched: &mut Option<P::Output>,
+    ) -> F::Output
+    where
+        F: Future,
+        P: Future + Unpin,
+    {
+        let mut fut = pin!(fut);
 
-            run_stop.store(true, Ordering::SeqCst);
-            *run_model.lock().unwrap() = None;
+        if let Some(pending) = prefetch.as_mut() {
+            let result = select(fut.as_mut(), pending).await;
 
             match result {
-                Either::First(result) => {
-                    result?;
+                Either::First(output) => return output,
+                Either::Second(output) => {
+                    info!("Bundle of the next PCB prefetched");
 
-                    info!("App run auccessful, match pattern detected");
-                }
-                Either::Second(_) => {
-                    if run_end_regex_present {
-                        error!("App run timeout after {run_timeout_secs} seconds");
-                        anyhow::bail!("App run timeout after {run_timeout_secs} seconds");
-                    } else {
-                        info!("App run auccessful, timeout reached");
-                    }
+                    *prefetch = None;
+                    *prefetched = Some(output);
                 }
             }
-        } else {
-            info!("App run disabled");
         }
 
-        self.model.modify(|inner| {
-            inner
-                .state
-                .success(format!(" {bundle_name} "), "Provisioning complete.");
-        });
-
-        Ok(())
+        fut.await
     }
 
     /// Load a bundle from the storage of the bundle loader into the bundle workspace directory
     async fn load_one_bundle<T>(
         model: &Model,
         bundle_id: Option<&str>,
-        mut loader: T,
+        loader: T,
     ) -> anyhow::Result<(String, NamedTempFile)>
     where
         T: BundleLoader,
@@ -930,8 +4242,37 @@ where
             inner.state.processing_mut().status = "Fetching".into();
         });
 
+        Self::fetch_one_bundle(model.events(), bundle_id, loader).await
+    }
+
+    /// Fetch a bundle with the loader into a temporary file, without updating the model
+    ///
+    /// Emits `ProvisioningEvent::BundlePoolEmpty` if the loader has no bundle left in its pool
+    async fn fetch_one_bundle<T>(
+        events: &Events,
+        bundle_id: Option<&str>,
+        mut loader: T,
+    ) -> anyhow::Result<(String, NamedTempFile)>
+    where
+        T: BundleLoader,
+    {
         let mut bundle_file = NamedTempFile::new().context("Creating temp bundle file failed")?;
-        let bundle_name = loader.load(&mut bundle_file, bundle_id).await?;
+        let bundle_name = match loader.load(&mut bundle_file, bundle_id).await {
+            Ok(bundle_name) => bundle_name,
+            Err(err) => {
+                if err
+                    .cause()
+                    .and_then(|source| source.downcast_ref::<BundlePoolEmpty>())
+                    .is_some()
+                {
+                    events.emit(ProvisioningEvent::BundlePoolEmpty {
+                        message: err.to_string(),
+                    });
+                }
+
+                return Err(err).context(Failure::BundleLoad);
+            }
+        };
 
         bundle_file
             .flush()
@@ -945,10 +4286,35 @@ where
         Ok((bundle_name, bundle_file))
     }
 
+    /// Merge a bundle layer on top of the bundle merged so far
+    fn merge_bundles(
+        model: &Model,
+        base_bundle: &mut Bundle,
+        bundle: Bundle,
+        overwrite: bool,
+    ) -> anyhow::Result<()> {
+        model.modify(|inner| {
+            inner.state.processing_mut().status =
+                format!("Merging `{}` and `{}`", base_bundle.name, bundle.name);
+        });
+
+        info!(
+            "Merging base bundle `{}` with bundle `{}`, override `{overwrite}`",
+            base_bundle.name, bundle.name
+        );
+
+        base_bundle.add(bundle, overwrite)?;
+
+        info!("Bundles merged");
+
+        Ok(())
+    }
+
     /// Prepare a bundle to be provisioned by creating a `Bundle` instance from the loaded bundle content
     /// in the bundle workspace directory
     async fn prep_one_bundle<T>(
         model: &Model,
+        session: &Session,
         bundle_id: Option<&str>,
         loader: T,
         supply_default_partition_table: bool,
@@ -957,9 +4323,27 @@ where
     where
         T: BundleLoader,
     {
-        let (bundle_name, mut bundle_file) =
-            Self::load_one_bundle(model, bundle_id, loader).await?;
+        let (bundle_name, bundle_file) = Self::load_one_bundle(model, bundle_id, loader).await?;
+
+        Self::create_one_bundle(
+            model,
+            session,
+            bundle_name,
+            bundle_file,
+            supply_default_partition_table,
+            supply_default_bootloader,
+        )
+    }
 
+    /// Create a `Bundle` instance from an already loaded bundle content
+    fn create_one_bundle(
+        model: &Model,
+        session: &Session,
+        bundle_name: String,
+        mut bundle_file: NamedTempFile,
+        supply_default_partition_table: bool,
+        supply_default_bootloader: bool,
+    ) -> anyhow::Result<Bundle> {
         model.modify(|inner| {
             inner.state.processing_mut().status = format!("Processing {bundle_name}");
         });
@@ -969,6 +4353,17 @@ where
             bundle_file.path().display()
         );
 
+        if session.active() {
+            let mut content = Vec::new();
+
+            bundle_file
+                .seek(std::io::SeekFrom::Start(0))
+                .and_then(|_| bundle_file.read_to_end(&mut content))
+                .context("Reading the loaded bundle file failed")?;
+
+            session.bundle(&bundle_name, &sha256_hex(&content))?;
+        }
+
         bundle_file
             .seek(std::io::SeekFrom::Start(0))
             .context("Seeking the loaded bundle file failed")?;
@@ -988,23 +4383,35 @@ where
 
     fn burn(
         model: &Model,
+        backend: &impl ProvisioningBackend,
         protect_keys: bool,
         protect_digests: bool,
         chip: Chip,
-        port: Option<&str>,
-        baud: Option<&str>,
         dry_run: bool,
+        batch: bool,
     ) -> anyhow::Result<String> {
         let mut output = String::new();
 
         model.modify(|inner| {
             let efuses = &mut inner.state.provision_mut().bundle.efuse_mapping;
 
+            // The eFuses burned already by a previous attempt are not burned again
             for efuse in efuses {
-                efuse.status = ProvisioningStatus::Pending;
+                if !matches!(efuse.status, ProvisioningStatus::Done) {
+                    efuse.status = ProvisioningStatus::Pending;
+                }
             }
         });
 
+        if batch {
+            match Self::burn_batch(model, backend, protect_keys, protect_digests, chip, dry_run) {
+                Err(err) if err.downcast_ref::<efuse::BatchUnsupported>().is_some() => {
+                    warn!("{err:#}, burning the eFuses with one eFuse tool invocation per kind");
+                }
+                result => return result,
+            }
+        }
+
         // Step 1: Burn keys first
 
         let keys = model.access_mut(|inner| {
@@ -1013,14 +4420,27 @@ where
             let mut notify = false;
 
             let mut keys = Vec::new();
-            for efuse in efuses {
+            for efuse in efuses
+                .iter_mut()
+                .filter(|efuse| !matches!(efuse.status, ProvisioningStatus::Done))
+            {
                 if let Efuse::Key {
                     block,
                     key_value,
                     purpose,
+                    read_protect,
+                    write_protect,
                 } = &efuse.efuse
                 {
-                    keys.push((block.clone(), key_value.clone(), purpose.clone()));
+                    keys.push((
+                        block.clone(),
+                        key_value.clone(),
+                        purpose.clone(),
+                        efuse::KeyProtection::new(
+                            read_protect.unwrap_or(protect_keys),
+                            write_protect.unwrap_or(protect_keys),
+                        ),
+                    ));
                 }
 
                 efuse.status = ProvisioningStatus::Pending;
@@ -1033,17 +4453,43 @@ where
         if !keys.is_empty() {
             info!("Initiating burn of {} keys", keys.len());
 
-            let keys_output = efuse::burn_keys(
-                protect_keys,
-                chip,
-                port,
-                baud,
-                dry_run,
-                keys.iter().map(|(block, key, purpose)| {
-                    (block.as_str(), key.as_slice(), purpose.as_str())
-                }),
-            )
-            .context("Burning keys failed")?;
+            // Keys with a different protection are burned with separate commands
+            for protection in efuse::KeyProtection::ALL {
+                let keys = keys
+                    .iter()
+                    .filter(|key| key.3 == protection)
+                    .collect::<Vec<_>>();
+
+                if keys.is_empty() {
+                    continue;
+                }
+
+                let mut keys_params = format!("chip={chip};{protection};dry_run={dry_run}");
+                for (block, key, purpose, _) in &keys {
+                    write!(
+                        &mut keys_params,
+                        ";{block}:{purpose}={}",
+                        sha256_hex(key.as_slice())
+                    )?;
+                }
+
+                let keys_output = Self::audit(
+                    model,
+                    "burn-keys",
+                    &keys_params,
+                    backend.burn_keys(
+                        protection,
+                        chip,
+                        dry_run,
+                        keys.iter().map(|(block, key, purpose, _)| {
+                            (block.as_str(), key.as_slice(), purpose.as_str())
+                        }),
+                    ),
+                )
+                .context("Burning keys failed")?;
+
+                write!(&mut output, "{keys_output}\n\n")?;
+            }
 
             model.modify(|inner| {
                 let efuses = &mut inner.state.provision_mut().bundle.efuse_mapping;
@@ -1051,12 +4497,14 @@ where
                 for efuse in efuses {
                     if let Efuse::Key { .. } = &efuse.efuse {
                         efuse.status = ProvisioningStatus::Done;
+
+                        model.events().emit(ProvisioningEvent::EfuseBurned {
+                            name: efuse.efuse.to_string(),
+                        });
                     }
                 }
             });
 
-            write!(&mut output, "{keys_output}\n\n")?;
-
             info!("Burn of keys complete");
         }
 
@@ -1067,104 +4515,446 @@ where
 
             let mut notify = false;
 
-            let mut digests = Vec::new();
+            let mut digests = Vec::new();
+
+            for efuse in efuses
+                .iter_mut()
+                .filter(|efuse| !matches!(efuse.status, ProvisioningStatus::Done))
+            {
+                if let Efuse::KeyDigest {
+                    block,
+                    digest_value,
+                    purpose,
+                    read_protect,
+                    write_protect,
+                } = &efuse.efuse
+                {
+                    digests.push((
+                        block.clone(),
+                        digest_value.clone(),
+                        purpose.clone(),
+                        efuse::KeyProtection::new(
+                            read_protect.unwrap_or(protect_digests),
+                            write_protect.unwrap_or(protect_digests),
+                        ),
+                    ));
+                }
+
+                efuse.status = ProvisioningStatus::Pending;
+                notify = true;
+            }
+
+            (digests, notify)
+        });
+
+        if !digests.is_empty() {
+            info!("Initiating burn of {} key digests", digests.len());
+
+            // Key digests with a different protection are burned with separate commands
+            for protection in efuse::KeyProtection::ALL {
+                let digests = digests
+                    .iter()
+                    .filter(|digest| digest.3 == protection)
+                    .collect::<Vec<_>>();
+
+                if digests.is_empty() {
+                    continue;
+                }
+
+                let mut digests_params = format!("chip={chip};{protection};dry_run={dry_run}");
+                for (block, digest, purpose, _) in &digests {
+                    write!(
+                        &mut digests_params,
+                        ";{block}:{purpose}={}",
+                        sha256_hex(digest)
+                    )?;
+                }
+
+                let digests_output = Self::audit(
+                    model,
+                    "burn-key-digests",
+                    &digests_params,
+                    backend.burn_key_digests(
+                        protection,
+                        chip,
+                        dry_run,
+                        digests.iter().map(|(block, digest, purpose, _)| {
+                            (block.as_str(), digest.as_slice(), purpose.as_str())
+                        }),
+                    ),
+                )
+                .context("Burning key digests failed")?;
+
+                write!(&mut output, "{digests_output}\n\n")?;
+            }
+
+            model.modify(|inner| {
+                let efuses = &mut inner.state.provision_mut().bundle.efuse_mapping;
+
+                for efuse in efuses {
+                    if let Efuse::KeyDigest { .. } = &efuse.efuse {
+                        efuse.status = ProvisioningStatus::Done;
+
+                        model.events().emit(ProvisioningEvent::EfuseBurned {
+                            name: efuse.efuse.to_string(),
+                        });
+                    }
+                }
+            });
+
+            info!("Burn of key digests complete");
+        }
+
+        // Step 3: Burn the custom MAC
+
+        let custom_mac = model.access(|inner| {
+            inner
+                .state
+                .provision()
+                .bundle
+                .efuse_mapping
+                .iter()
+                .filter(|efuse| !matches!(efuse.status, ProvisioningStatus::Done))
+                .find_map(|efuse| match &efuse.efuse {
+                    Efuse::CustomMac { mac } => Some(*mac),
+                    _ => None,
+                })
+        });
+
+        if let Some(mac) = custom_mac {
+            info!("Initiating burn of the custom MAC");
+
+            let mac_output = Self::audit(
+                model,
+                "burn-custom-mac",
+                &format!("chip={chip};dry_run={dry_run};mac={}", mac_str(&mac)),
+                backend.burn_custom_mac(chip, dry_run, &mac),
+            )
+            .context("Burning the custom MAC failed")?;
+
+            model.modify(|inner| {
+                let efuses = &mut inner.state.provision_mut().bundle.efuse_mapping;
+
+                for efuse in efuses {
+                    if let Efuse::CustomMac { .. } = &efuse.efuse {
+                        efuse.status = ProvisioningStatus::Done;
+
+                        model.events().emit(ProvisioningEvent::EfuseBurned {
+                            name: efuse.efuse.to_string(),
+                        });
+                    }
+                }
+            });
+
+            write!(&mut output, "{mac_output}\n\n")?;
+
+            info!("Burn of the custom MAC complete");
+        }
+
+        // Step 4: Finally, burn all params
+
+        let params = model.access_mut(|inner| {
+            let efuses = &mut inner.state.provision_mut().bundle.efuse_mapping;
+
+            let mut notify = false;
+
+            let mut params = Vec::new();
+
+            for efuse in efuses
+                .iter_mut()
+                .filter(|efuse| !matches!(efuse.status, ProvisioningStatus::Done))
+            {
+                if let Efuse::Param { name, value } = &efuse.efuse {
+                    params.push((name.clone(), *value));
+                }
+
+                efuse.status = ProvisioningStatus::Pending;
+                notify = true;
+            }
+
+            (params, notify)
+        });
+
+        if !params.is_empty() {
+            info!("Initiating burn of {} params", params.len());
+
+            let mut efuses_params = format!("chip={chip};dry_run={dry_run}");
+            for (name, value) in &params {
+                write!(&mut efuses_params, ";{name}={value}")?;
+            }
+
+            let params_output = Self::audit(
+                model,
+                "burn-efuses",
+                &efuses_params,
+                backend.burn_efuses(
+                    chip,
+                    dry_run,
+                    params.iter().map(|(name, value)| (name.as_str(), *value)),
+                ),
+            )
+            .context("Burning params failed")?;
+
+            model.modify(|inner| {
+                let efuses = &mut inner.state.provision_mut().bundle.efuse_mapping;
+
+                for efuse in efuses {
+                    if let Efuse::Param { .. } = &efuse.efuse {
+                        efuse.status = ProvisioningStatus::Done;
+
+                        model.events().emit(ProvisioningEvent::EfuseBurned {
+                            name: efuse.efuse.to_string(),
+                        });
+                    }
+                }
+            });
+
+            write!(&mut output, "{params_output}\n\n")?;
+
+            info!("Burn of params complete");
+        }
+
+        Ok(output)
+    }
+
+    /// Burn all eFuses of the bundle with a single eFuse tool invocation (see `Config::efuse_batch`)
+    ///
+    /// The eFuses are burned in the same order as by `burn`
+    fn burn_batch(
+        model: &Model,
+        backend: &impl ProvisioningBackend,
+        protect_keys: bool,
+        protect_digests: bool,
+        chip: Chip,
+        dry_run: bool,
+    ) -> anyhow::Result<String> {
+        let efuses = model.access(|inner| {
+            inner
+                .state
+                .provision()
+                .bundle
+                .efuse_mapping
+                .iter()
+                .filter(|efuse| !matches!(efuse.status, ProvisioningStatus::Done))
+                .map(|efuse| efuse.efuse.clone())
+                .collect::<Vec<_>>()
+        });
+
+        let mut keys = Vec::new();
+        let mut digests = Vec::new();
+        let mut custom_mac = None;
+        let mut params = Vec::new();
 
-            for efuse in efuses {
-                if let Efuse::KeyDigest {
+        for efuse in &efuses {
+            match efuse {
+                Efuse::Key {
+                    block,
+                    key_value,
+                    purpose,
+                    read_protect,
+                    write_protect,
+                } => keys.push((
+                    block.as_str(),
+                    key_value.as_slice(),
+                    purpose.as_str(),
+                    efuse::KeyProtection::new(
+                        read_protect.unwrap_or(protect_keys),
+                        write_protect.unwrap_or(protect_keys),
+                    ),
+                )),
+                Efuse::KeyDigest {
                     block,
                     digest_value,
                     purpose,
-                } = &efuse.efuse
-                {
-                    digests.push((block.clone(), digest_value.clone(), purpose.clone()));
+                    read_protect,
+                    write_protect,
+                } => digests.push((
+                    block.as_str(),
+                    digest_value.as_slice(),
+                    purpose.as_str(),
+                    efuse::KeyProtection::new(
+                        read_protect.unwrap_or(protect_digests),
+                        write_protect.unwrap_or(protect_digests),
+                    ),
+                )),
+                Efuse::CustomMac { mac } => custom_mac = Some(mac),
+                Efuse::Param { name, value } => params.push((name.as_str(), *value)),
+            }
+        }
+
+        let mut burns = Vec::new();
+        let mut burn_params = format!("chip={chip};dry_run={dry_run}");
+
+        // Keys and key digests with a different protection are still burned with separate chained commands
+        for protection in efuse::KeyProtection::ALL {
+            let keys = keys
+                .iter()
+                .filter(|key| key.3 == protection)
+                .map(|(block, key, purpose, _)| (*block, *key, *purpose))
+                .collect::<Vec<_>>();
+
+            if !keys.is_empty() {
+                write!(&mut burn_params, ";burn_key;{protection}")?;
+                for (block, key, purpose) in &keys {
+                    write!(&mut burn_params, ";{block}:{purpose}={}", sha256_hex(key))?;
                 }
 
-                efuse.status = ProvisioningStatus::Pending;
-                notify = true;
+                burns.push(efuse::EfuseBurn::Keys(protection, keys));
             }
+        }
 
-            (digests, notify)
-        });
+        for protection in efuse::KeyProtection::ALL {
+            let digests = digests
+                .iter()
+                .filter(|digest| digest.3 == protection)
+                .map(|(block, digest, purpose, _)| (*block, *digest, *purpose))
+                .collect::<Vec<_>>();
 
-        if !digests.is_empty() {
-            info!("Initiating burn of {} key digests", digests.len());
+            if !digests.is_empty() {
+                write!(&mut burn_params, ";burn_key_digest;{protection}")?;
+                for (block, digest, purpose) in &digests {
+                    write!(
+                        &mut burn_params,
+                        ";{block}:{purpose}={}",
+                        sha256_hex(digest)
+                    )?;
+                }
 
-            let digests_output = efuse::burn_key_digests(
-                protect_digests,
-                chip,
-                port,
-                baud,
-                dry_run,
-                digests.iter().map(|(block, digest, purpose)| {
-                    (block.as_str(), digest.as_slice(), purpose.as_str())
-                }),
-            )
-            .context("Burning key digests failed")?;
+                burns.push(efuse::EfuseBurn::KeyDigests(protection, digests));
+            }
+        }
 
-            model.modify(|inner| {
-                let efuses = &mut inner.state.provision_mut().bundle.efuse_mapping;
+        if let Some(mac) = custom_mac {
+            write!(&mut burn_params, ";burn_custom_mac;mac={}", mac_str(mac))?;
 
-                for efuse in efuses {
-                    if let Efuse::KeyDigest { .. } = &efuse.efuse {
-                        efuse.status = ProvisioningStatus::Done;
-                    }
-                }
-            });
+            burns.push(efuse::EfuseBurn::CustomMac(mac));
+        }
 
-            write!(&mut output, "{digests_output}\n\n")?;
+        if !params.is_empty() {
+            write!(&mut burn_params, ";burn_efuse")?;
+            for (name, value) in &params {
+                write!(&mut burn_params, ";{name}={value}")?;
+            }
 
-            info!("Burn of key digests complete");
+            burns.push(efuse::EfuseBurn::Params(params));
         }
 
-        // Step 3: Finally, burn all params
+        if burns.is_empty() {
+            return Ok(String::new());
+        }
 
-        let params = model.access_mut(|inner| {
-            let efuses = &mut inner.state.provision_mut().bundle.efuse_mapping;
+        info!(
+            "Initiating burn of {} eFuses with a single eFuse tool invocation",
+            efuses.len()
+        );
 
-            let mut notify = false;
+        let output = Self::audit(
+            model,
+            "burn-efuses-batch",
+            &burn_params,
+            backend.burn_batch(chip, dry_run, &burns),
+        )
+        .context("Burning eFuses failed")?;
 
-            let mut params = Vec::new();
+        model.modify(|inner| {
+            let efuses = &mut inner.state.provision_mut().bundle.efuse_mapping;
 
             for efuse in efuses {
-                if let Efuse::Param { name, value } = &efuse.efuse {
-                    params.push((name.clone(), *value));
-                }
+                efuse.status = ProvisioningStatus::Done;
 
-                efuse.status = ProvisioningStatus::Pending;
-                notify = true;
+                model.events().emit(ProvisioningEvent::EfuseBurned {
+                    name: efuse.efuse.to_string(),
+                });
             }
+        });
 
-            (params, notify)
+        info!("Burn of eFuses complete");
+
+        Ok(format!("{output}\n\n"))
+    }
+
+    /// Run a step and record its outcome and duration as a test case in the report of the PCB
+    ///
+    /// Only successes and failures are recorded, i.e. steps canceled by the user are not
+    async fn reported<F, R>(
+        model: &Model,
+        step: &str,
+        code: &str,
+        fut: F,
+    ) -> anyhow::Result<R, TaskError>
+    where
+        F: Future<Output = anyhow::Result<R, TaskError>>,
+    {
+        let started = std::time::Instant::now();
+
+        model.modify(|inner| inner.timing.start(step));
+
+        model.events().emit(ProvisioningEvent::StepStarted {
+            step: step.to_string(),
         });
 
-        if !params.is_empty() {
-            info!("Initiating burn of {} params", params.len());
+        let result = fut.await;
 
-            let params_output = efuse::burn_efuses(
-                chip,
-                port,
-                baud,
-                dry_run,
-                params.iter().map(|(name, value)| (name.as_str(), *value)),
-            )
-            .context("Burning params failed")?;
+        let outcome = match &result {
+            Ok(_) => Some(StepOutcome::Passed),
+            Err(TaskError::Other(err)) => Some(StepOutcome::Failed {
+                code: code.to_string(),
+                message: format!("{err:#}"),
+            }),
+            Err(_) => None,
+        };
 
-            model.modify(|inner| {
-                let efuses = &mut inner.state.provision_mut().bundle.efuse_mapping;
+        if let Some(outcome) = outcome {
+            if matches!(outcome, StepOutcome::Failed { .. }) {
+                model.metrics().failed(step);
+            }
 
-                for efuse in efuses {
-                    if let Efuse::Param { .. } = &efuse.efuse {
-                        efuse.status = ProvisioningStatus::Done;
-                    }
+            model.access_mut(|inner| {
+                let duration = started.elapsed();
+
+                inner
+                    .timing
+                    .finish(step, duration, matches!(outcome, StepOutcome::Passed));
+
+                if matches!(outcome, StepOutcome::Failed { .. }) {
+                    inner.cycle.fail();
                 }
-            });
 
-            write!(&mut output, "{params_output}\n\n")?;
+                inner.logs.report.record(step, duration, outcome);
 
-            info!("Burn of params complete");
+                ((), true)
+            });
+        } else {
+            model.modify(|inner| inner.timing.finish(step, started.elapsed(), false));
         }
 
-        Ok(output)
+        model.events().emit(ProvisioningEvent::StepFinished {
+            step: step.to_string(),
+            duration: started.elapsed(),
+            error: match &result {
+                Ok(_) => None,
+                Err(TaskError::Other(err)) => Some(format!("{err:#}")),
+                Err(err) => Some(err.to_string()),
+            },
+        });
+
+        result
+    }
+
+    /// Record the result of an irreversible operation in the audit log of the PCB
+    fn audit<T>(
+        model: &Model,
+        operation: &str,
+        params: &str,
+        result: anyhow::Result<T>,
+    ) -> anyhow::Result<T> {
+        model.access_mut(|inner| {
+            inner.logs.audit.record(operation, params, &result);
+
+            ((), false)
+        });
+
+        result
     }
 
     /// Handle a future failure by displaying an error message and waiting for a confirmation
@@ -1183,13 +4973,38 @@ where
         if let Err(TaskError::Other(err)) = result {
             error!("{err_msg}: {err:?}");
 
+            model.events().emit(ProvisioningEvent::Error {
+                title: err_msg.to_string(),
+                message: format!("{err:#}"),
+            });
+
             model.modify(|inner| {
-                inner
-                    .state
-                    .error(format!(" {err_msg} "), format!("{err_msg}: {err:?}"))
+                // Once the PCB is being written, a failure is the result of its provisioning
+                let result = inner.cycle.steps.iter().any(|(step, status)| {
+                    matches!(
+                        step,
+                        CycleStep::Flash | CycleStep::EfuseBurn | CycleStep::AppRun
+                    ) && *status == CycleStepStatus::Failed
+                });
+
+                let title = format!(" {err_msg} ");
+                let message = format!("{err_msg}: {err:?}");
+
+                if result {
+                    inner.state.result(title, message, true);
+                } else {
+                    inner.state.error(title, message);
+                }
             });
 
             match err_policy {
+                ErrPolicy::Fail(failure) => {
+                    if err.downcast_ref::<Failure>().is_some() {
+                        Err(TaskError::Other(err))
+                    } else {
+                        Err(TaskError::Other(err.context(failure)))
+                    }
+                }
                 ErrPolicy::Ignore => {
                     info!("Ignoring the error");
 
@@ -1271,10 +5086,372 @@ where
     }
 }
 
+/// Record the duration of a successfully completed sub-step of a reported step
+/// (e.g. the flashing of a partition) in the report and in the step timing estimates
+fn record_timed(model: &Model, step: &str, duration: core::time::Duration) {
+    info!("Step `{step}` took {:.1}s", duration.as_secs_f64());
+
+    model.events().emit(ProvisioningEvent::StepFinished {
+        step: step.to_string(),
+        duration,
+        error: None,
+    });
+
+    model.access_mut(|inner| {
+        inner.timing.finish(step, duration, true);
+        inner
+            .logs
+            .report
+            .record(step, duration, StepOutcome::Passed);
+
+        ((), false)
+    });
+}
+
+/// Return `true` if the error signifies that the chip is in Secure Download mode
+///
+/// Both `esptool.py`/`espefuse.py` as well as `espflash` mention the Secure Download mode in their errors
+/// when an operation is not supported in that mode
+fn is_secure_download_error(err: &anyhow::Error) -> bool {
+    let err = format!("{err:#}").to_ascii_lowercase();
+
+    err.contains("secure download mode")
+}
+
+/// Marks the error of a bundle not fitting the flash detected on the chip, so that the operator can be offered
+/// to pick an alternative partition table (see `Config::part_table_variant_pick`)
+#[derive(Debug)]
+struct FlashSizeMismatch(FlashSize);
+
+impl Display for FlashSizeMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "The bundle does not fit the {} flash of the chip",
+            self.0
+        )
+    }
+}
+
+/// The values captured from the app logs during the device app run
+#[derive(Default)]
+struct AppRunCapture {
+    /// The captured values, to be recorded as readouts
+    readouts: Vec<(String, String)>,
+    /// The captured values to be uploaded as separate files in the PCB logs, as (file name, content)
+    artifacts: Vec<(String, String)>,
+}
+
+/// The judging of the app logs during the device app run, as per `AppRun::Expect`
+struct AppRunExpect {
+    /// The patterns which all need to be matched, with the number of the line matching each (if matched already)
+    patterns: Vec<(regex::Regex, Option<usize>)>,
+    /// The patterns failing the app run
+    fail_patterns: Vec<regex::Regex>,
+    /// Whether the patterns need to be matched in order
+    ordered: bool,
+    /// The number of app log lines so far
+    lines: usize,
+    /// The reason of the failure of the app run, once a failure pattern is matched
+    failure: Option<String>,
+}
+
+impl AppRunExpect {
+    fn new(patterns: &[String], fail_patterns: &[String], ordered: bool) -> anyhow::Result<Self> {
+        let compile = |pattern: &String| {
+            regex::Regex::new(pattern).with_context(|| format!("Invalid regex pattern `{pattern}`"))
+        };
+
+        Ok(Self {
+            patterns: patterns
+                .iter()
+                .map(|pattern| compile(pattern).map(|regex| (regex, None)))
+                .collect::<anyhow::Result<_>>()?,
+            fail_patterns: fail_patterns
+                .iter()
+                .map(compile)
+                .collect::<anyhow::Result<_>>()?,
+            ordered,
+            lines: 0,
+            failure: None,
+        })
+    }
+
+    /// Judge an app log line and return `true` if the app run is complete,
+    /// i.e. all patterns are matched or a failure pattern is matched
+    fn feed(&mut self, line: &str) -> bool {
+        if self.is_done() || self.failure.is_some() {
+            return true;
+        }
+
+        self.lines += 1;
+
+        if let Some(fail_pattern) = self.fail_patterns.iter().find(|fp| fp.is_match(line)) {
+            self.failure = Some(format!(
+                "Failure pattern `{fail_pattern}` matched in line `{line}`"
+            ));
+            info!("[App run failing, detected failure pattern on this line ^^^]");
+
+            return true;
+        }
+
+        for (pattern, matched) in &mut self.patterns {
+            if matched.is_none() {
+                if pattern.is_match(line) {
+                    *matched = Some(self.lines);
+                    info!("[Pattern `{pattern}` detected on this line ^^^]");
+                }
+
+                if self.ordered {
+                    // The next patterns can only match in the lines after the line matching this one
+                    break;
+                }
+            }
+        }
+
+        if self.is_done() {
+            info!("[App run finishing, all patterns detected]");
+        }
+
+        self.is_done()
+    }
+
+    /// Return `true` if all patterns are matched
+    fn is_done(&self) -> bool {
+        self.patterns.iter().all(|(_, matched)| matched.is_some())
+    }
+
+    /// Return the outcome of each pattern, as test cases for the report of the PCB
+    fn outcomes(&self, timeout_secs: u32) -> Vec<(String, StepOutcome)> {
+        let mut outcomes = self
+            .patterns
+            .iter()
+            .enumerate()
+            .map(|(index, (pattern, matched))| {
+                let outcome = if matched.is_some() {
+                    StepOutcome::Passed
+                } else {
+                    StepOutcome::Failed {
+                        code: "APP_RUN_PATTERN_NOT_MATCHED".to_string(),
+                        message: format!(
+                            "Pattern `{pattern}` not matched in the app logs{}",
+                            if self.failure.is_some() {
+                                " before the failure".to_string()
+                            } else {
+                                format!(" within {timeout_secs} seconds")
+                            }
+                        ),
+                    }
+                };
+
+                (format!("app-run-expect-{}", index + 1), outcome)
+            })
+            .collect::<Vec<_>>();
+
+        if let Some(failure) = self.failure.as_ref() {
+            outcomes.push((
+                "app-run-fail-pattern".to_string(),
+                StepOutcome::Failed {
+                    code: "APP_RUN_FAILURE_PATTERN_MATCHED".to_string(),
+                    message: failure.clone(),
+                },
+            ));
+        }
+
+        outcomes
+    }
+}
+
+/// The execution of the script of the device app run, as per `AppRun::Script`
+struct AppRunScript {
+    /// The steps of the script, with their compiled patterns
+    steps: Vec<(AppRunScriptStep, Option<regex::Regex>)>,
+    /// The patterns failing the app run
+    fail_patterns: Vec<regex::Regex>,
+    /// The index of the current step and the time it started at, once the script is started
+    current: Option<(usize, std::time::Instant)>,
+    /// The reason of the failure of the app run, once a step times out or a failure pattern is matched
+    failure: Option<String>,
+    /// The channel for sending the commands of the steps to the app
+    input: mpsc::Sender<Vec<u8>>,
+}
+
+impl AppRunScript {
+    fn new(
+        steps: &[AppRunScriptStep],
+        fail_patterns: &[String],
+        input: mpsc::Sender<Vec<u8>>,
+    ) -> anyhow::Result<Self> {
+        let compile = |pattern: &String| {
+            regex::Regex::new(pattern).with_context(|| format!("Invalid regex pattern `{pattern}`"))
+        };
+
+        Ok(Self {
+            steps: steps
+                .iter()
+                .map(|step| {
+                    step.expect
+                        .as_ref()
+                        .map(compile)
+                        .transpose()
+                        .map(|regex| (step.clone(), regex))
+                })
+                .collect::<anyhow::Result<_>>()?,
+            fail_patterns: fail_patterns
+                .iter()
+                .map(compile)
+                .collect::<anyhow::Result<_>>()?,
+            current: None,
+            failure: None,
+            input,
+        })
+    }
+
+    /// Start the script with its first step and return `true` if the script is already complete
+    fn start(&mut self) -> bool {
+        self.enter(0)
+    }
+
+    /// Judge an app log line and return `true` if the script is complete,
+    /// i.e. all steps are complete or the script failed
+    fn feed(&mut self, line: &str) -> bool {
+        if self.is_done() || self.failure.is_some() {
+            return true;
+        }
+
+        if let Some(fail_pattern) = self.fail_patterns.iter().find(|fp| fp.is_match(line)) {
+            self.failure = Some(format!(
+                "Failure pattern `{fail_pattern}` matched in line `{line}`"
+            ));
+            info!("[App run failing, detected failure pattern on this line ^^^]");
+
+            return true;
+        }
+
+        let Some((index, _)) = self.current else {
+            return false;
+        };
+
+        if let Some(pattern) = self.steps[index].1.as_ref() {
+            if pattern.is_match(line) {
+                info!(
+                    "[Script step {} complete, pattern `{pattern}` detected on this line ^^^]",
+                    index + 1
+                );
+
+                return self.enter(index + 1);
+            }
+        }
+
+        false
+    }
+
+    /// Check the timeout of the current step and return `true` if the script failed because of it
+    fn poll(&mut self) -> bool {
+        if self.failure.is_some() {
+            return true;
+        }
+
+        if self.is_done() {
+            return false;
+        }
+
+        let Some((index, started)) = self.current else {
+            return false;
+        };
+
+        let timeout_secs = self.steps[index].0.timeout_secs;
+
+        if started.elapsed() >= std::time::Duration::from_secs(timeout_secs as _) {
+            self.failure = Some(format!(
+                "Script step {} not complete within {timeout_secs} seconds",
+                index + 1
+            ));
+
+            return true;
+        }
+
+        false
+    }
+
+    /// Return `true` if all steps are complete
+    fn is_done(&self) -> bool {
+        self.current
+            .is_some_and(|(index, _)| index >= self.steps.len())
+    }
+
+    /// Start the step with the given index, completing right away the steps without a pattern,
+    /// and return `true` if the script is complete
+    fn enter(&mut self, mut index: usize) -> bool {
+        while index < self.steps.len() {
+            self.current = Some((index, std::time::Instant::now()));
+
+            let (step, pattern) = &self.steps[index];
+
+            if let Some(send) = step.send.as_ref() {
+                info!("[Script step {}, sending `{send}`]", index + 1);
+
+                if self.input.send(format!("{send}\n").into_bytes()).is_err() {
+                    self.failure = Some(format!(
+                        "Sending the command of script step {} failed",
+                        index + 1
+                    ));
+
+                    return true;
+                }
+            }
+
+            if pattern.is_some() {
+                return false;
+            }
+
+            index += 1;
+        }
+
+        self.current = Some((index, std::time::Instant::now()));
+
+        info!("[App run finishing, all script steps complete]");
+
+        true
+    }
+
+    /// Return the outcome of each step started so far, as test cases for the report of the PCB
+    fn outcomes(&self) -> Vec<(String, StepOutcome)> {
+        let Some((current, _)) = self.current else {
+            return Vec::new();
+        };
+
+        self.steps
+            .iter()
+            .enumerate()
+            .take(current + 1)
+            .map(|(index, (step, pattern))| {
+                let outcome = if index < current {
+                    StepOutcome::Passed
+                } else {
+                    StepOutcome::Failed {
+                        code: "APP_RUN_SCRIPT_STEP_FAILED".to_string(),
+                        message: match (self.failure.as_ref(), pattern) {
+                            (Some(failure), _) => failure.clone(),
+                            (None, Some(pattern)) => format!(
+                                "Pattern `{pattern}` not matched in the app logs within {} seconds",
+                                step.timeout_secs
+                            ),
+                            (None, None) => "Step not complete".to_string(),
+                        },
+                    }
+                };
+
+                (format!("app-run-script-{}", index + 1), outcome)
+            })
+            .collect()
+    }
+}
+
 /// A progress callback for flashing the bundle
 struct FlashProgress {
     model: Arc<Model>,
-    image: Mutex<Option<(u32, usize)>>,
+    image: Mutex<Option<(u32, usize, std::time::Instant)>>,
 }
 
 impl FlashProgress {
@@ -1288,7 +5465,7 @@ impl FlashProgress {
 
 impl ProgressCallbacks for FlashProgress {
     fn init(&mut self, addr: u32, total: usize) {
-        *self.image.lock().unwrap() = Some((addr, total));
+        *self.image.lock().unwrap() = Some((addr, total, std::time::Instant::now()));
 
         self.model.access_mut(|inner| {
             let notify = inner
@@ -1307,7 +5484,13 @@ impl ProgressCallbacks for FlashProgress {
     }
 
     fn update(&mut self, current: usize) {
-        if let Some((addr, total)) = *self.image.lock().unwrap() {
+        if let Some((addr, total, _)) = *self.image.lock().unwrap() {
+            self.model.events().emit(ProvisioningEvent::FlashProgress {
+                addr,
+                current,
+                total,
+            });
+
             self.model.access_mut(|inner| {
                 let notify = inner.state.provision_mut().bundle.set_status(
                     addr,
@@ -1319,19 +5502,38 @@ impl ProgressCallbacks for FlashProgress {
         }
     }
 
-    fn finish(&mut self) {
-        if let Some((addr, _)) = self.image.lock().unwrap().take() {
-            self.model.access_mut(|inner| {
-                let notify = inner
-                    .state
-                    .provision_mut()
-                    .bundle
-                    .set_status(addr, ProvisioningStatus::Done);
+    fn verifying(&mut self) {
+        if let Some((addr, _, _)) = *self.image.lock().unwrap() {
+            info!("Verifying flash for addr `0x{addr:08x}`");
+        }
+    }
 
-                ((), notify)
+    fn finish(&mut self, skipped: bool) {
+        if let Some((addr, _, started)) = self.image.lock().unwrap().take() {
+            let partition = self.model.access_mut(|inner| {
+                let bundle = &mut inner.state.provision_mut().bundle;
+                let notify = bundle.set_status(addr, ProvisioningStatus::Done);
+
+                (
+                    bundle
+                        .partition_name(addr)
+                        .map(str::to_string)
+                        .unwrap_or_else(|| format!("0x{addr:08x}")),
+                    notify,
+                )
             });
 
-            info!("Flash for addr `0x{addr:08x}` completed");
+            record_timed(
+                &self.model,
+                &format!("flash-{partition}"),
+                started.elapsed(),
+            );
+
+            if skipped {
+                info!("Flash for addr `0x{addr:08x}` skipped");
+            } else {
+                info!("Flash for addr `0x{addr:08x}` completed");
+            }
         }
     }
 }
@@ -1387,4 +5589,23 @@ enum ErrPolicy {
     Propagate,
     ExplicitIgnore,
     Ignore,
+    /// Fail without offering a retry, attaching the failure to the error unless it already has one
+    Fail(Failure),
+}
+
+/// The identity of a PCB as presented on the result banner (see `Config::result_banner`),
+/// i.e. its Device ID and PCB ID readouts, or - if none - the given fallback
+fn banner_identity(readouts: &[(String, String)], fallback: &str) -> String {
+    let identity = readouts
+        .iter()
+        .filter(|(name, value)| (name == DEVICE_ID || name == PCB_ID) && !value.is_empty())
+        .map(|(_, value)| value.as_str())
+        .collect::<Vec<_>>()
+        .join(" / ");
+
+    if identity.is_empty() {
+        fallback.to_string()
+    } else {
+        identity
+    }
 }
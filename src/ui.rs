@@ -1,2 +1,6 @@
+pub mod font;
+#[cfg(feature = "gui")]
+pub mod gui;
 pub mod input;
+pub mod present;
 pub mod view;
@@ -0,0 +1,108 @@
+//! A tiny 3x5 block font for rendering large text (e.g. the PASS / FAIL result banner) in the terminal
+
+/// The glyphs of the font; characters not in the font are rendered as `?`
+const GLYPHS: &[(char, [&str; 5])] = &[
+    (' ', ["...", "...", "...", "...", "..."]),
+    ('0', ["###", "#.#", "#.#", "#.#", "###"]),
+    ('1', [".#.", "##.", ".#.", ".#.", "###"]),
+    ('2', ["###", "..#", "###", "#..", "###"]),
+    ('3', ["###", "..#", "###", "..#", "###"]),
+    ('4', ["#.#", "#.#", "###", "..#", "..#"]),
+    ('5', ["###", "#..", "###", "..#", "###"]),
+    ('6', ["###", "#..", "###", "#.#", "###"]),
+    ('7', ["###", "..#", "..#", "..#", "..#"]),
+    ('8', ["###", "#.#", "###", "#.#", "###"]),
+    ('9', ["###", "#.#", "###", "..#", "###"]),
+    ('A', ["###", "#.#", "###", "#.#", "#.#"]),
+    ('B', ["##.", "#.#", "##.", "#.#", "##."]),
+    ('C', ["###", "#..", "#..", "#..", "###"]),
+    ('D', ["##.", "#.#", "#.#", "#.#", "##."]),
+    ('E', ["###", "#..", "##.", "#..", "###"]),
+    ('F', ["###", "#..", "##.", "#..", "#.."]),
+    ('G', ["###", "#..", "#.#", "#.#", "###"]),
+    ('H', ["#.#", "#.#", "###", "#.#", "#.#"]),
+    ('I', ["###", ".#.", ".#.", ".#.", "###"]),
+    ('J', ["..#", "..#", "..#", "#.#", "###"]),
+    ('K', ["#.#", "#.#", "##.", "#.#", "#.#"]),
+    ('L', ["#..", "#..", "#..", "#..", "###"]),
+    ('M', ["#.#", "###", "###", "#.#", "#.#"]),
+    ('N', ["##.", "#.#", "#.#", "#.#", "#.#"]),
+    ('O', [".#.", "#.#", "#.#", "#.#", ".#."]),
+    ('P', ["###", "#.#", "###", "#..", "#.."]),
+    ('Q', ["###", "#.#", "#.#", "###", "..#"]),
+    ('R', ["###", "#.#", "##.", "#.#", "#.#"]),
+    ('S', ["###", "#..", "###", "..#", "###"]),
+    ('T', ["###", ".#.", ".#.", ".#.", ".#."]),
+    ('U', ["#.#", "#.#", "#.#", "#.#", "###"]),
+    ('V', ["#.#", "#.#", "#.#", "#.#", ".#."]),
+    ('W', ["#.#", "#.#", "###", "###", "#.#"]),
+    ('X', ["#.#", "#.#", ".#.", "#.#", "#.#"]),
+    ('Y', ["#.#", "#.#", ".#.", ".#.", ".#."]),
+    ('Z', ["###", "..#", ".#.", "#..", "###"]),
+    ('-', ["...", "...", "###", "...", "..."]),
+    ('_', ["...", "...", "...", "...", "###"]),
+    (':', ["...", ".#.", "...", ".#.", "..."]),
+    ('.', ["...", "...", "...", "...", ".#."]),
+    ('/', ["..#", "..#", ".#.", "#..", "#.."]),
+    ('?', ["###", "..#", ".##", "...", ".#."]),
+];
+
+/// The height of a glyph, in pixels
+pub const HEIGHT: u16 = 5;
+
+/// Return the size (width, height) in terminal cells of the given text, when rendered by `lines` with the given scale
+pub fn size(text: &str, scale: u16) -> (u16, u16) {
+    let chars = text.chars().count() as u16;
+
+    ((chars * 4).saturating_sub(1) * scale * 2, HEIGHT * scale)
+}
+
+/// Render the given text in large letters, as lines of block characters
+///
+/// Each pixel of a glyph is rendered as `scale * 2` cells horizontally and `scale` cells vertically,
+/// as terminal cells are roughly twice as tall as they are wide
+///
+/// # Arguments
+/// - `text` - the text to render; lowercase letters are rendered as uppercase
+/// - `scale` - the scale of the text (1 and upwards)
+pub fn lines(text: &str, scale: u16) -> Vec<String> {
+    let scale = scale.max(1) as usize;
+
+    let glyphs = text
+        .chars()
+        .map(|ch| glyph(ch.to_ascii_uppercase()))
+        .collect::<Vec<_>>();
+
+    let mut lines = Vec::new();
+
+    for row in 0..HEIGHT as usize {
+        let mut line = String::new();
+
+        for (index, glyph) in glyphs.iter().enumerate() {
+            if index > 0 {
+                line.push_str(&" ".repeat(scale * 2));
+            }
+
+            for pixel in glyph[row].chars() {
+                let cell = if pixel == '#' { "█" } else { " " };
+
+                line.push_str(&cell.repeat(scale * 2));
+            }
+        }
+
+        for _ in 0..scale {
+            lines.push(line.clone());
+        }
+    }
+
+    lines
+}
+
+fn glyph(ch: char) -> &'static [&'static str; 5] {
+    GLYPHS
+        .iter()
+        .find(|(glyph_ch, _)| *glyph_ch == ch)
+        .or_else(|| GLYPHS.iter().find(|(glyph_ch, _)| *glyph_ch == '?'))
+        .map(|(_, glyph)| glyph)
+        .unwrap()
+}
@@ -0,0 +1,488 @@
+//! A desktop GUI frontend with big, touch-friendly buttons, for factories which refuse terminal UIs
+//!
+//! Renders the same model as the terminal UI (via the presentation layer in `present`) and answers
+//! the prompts of the task through the same task input as the daemon mode (see `daemon::Api`)
+
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use eframe::egui::{self, Color32, RichText};
+
+use embassy_sync::blocking_mutex::raw::CriticalSectionRawMutex;
+use embassy_sync::signal::Signal;
+
+use crate::bundle::ProvisioningStatus;
+use crate::daemon::{Api, Command, Prompt};
+use crate::i18n::Catalog;
+use crate::model::{CycleStepStatus, Model, State};
+
+use super::present::{Align, Emphasis, TableView};
+
+/// The size of the prompt buttons, big enough for touch screens
+const BUTTON_SIZE: [f32; 2] = [200.0, 80.0];
+/// The text size of the prompt buttons
+const BUTTON_TEXT_SIZE: f32 = 28.0;
+/// How often the GUI is repainted, so that the model changes done by the task are picked up
+const REPAINT_INTERVAL: Duration = Duration::from_millis(100);
+
+/// Start the GUI on its own thread
+///
+/// Closing the window quits the factory
+///
+/// # Arguments
+/// - `model` - the model of the factory, for rendering its state
+/// - `api` - the task input, whose prompts are answered with the GUI buttons
+///
+/// # Returns
+/// A signal raised with the error if the GUI fails or its thread panics, as the task would otherwise wait forever
+/// for the answers of its prompts
+pub(crate) fn start(model: Arc<Model>, api: Api) -> anyhow::Result<Arc<GuiFailed>> {
+    let failed = Arc::new(GuiFailed::new());
+
+    {
+        let failed = failed.clone();
+
+        thread::Builder::new()
+            .name("gui".into())
+            .spawn(move || {
+                let options = eframe::NativeOptions {
+                    viewport: egui::ViewportBuilder::default()
+                        .with_title("ESP Factory")
+                        .with_inner_size([1280.0, 800.0]),
+                    event_loop_builder: Some(Box::new(|builder| {
+                        // The event loop does not run on the main thread, which is busy with the task
+                        // (hence the GUI is not supported on macOS, where the event loop has to run on the main thread)
+                        #[cfg(all(unix, not(target_os = "macos")))]
+                        {
+                            winit::platform::x11::EventLoopBuilderExtX11::with_any_thread(
+                                builder, true,
+                            );
+                            winit::platform::wayland::EventLoopBuilderExtWayland::with_any_thread(
+                                builder, true,
+                            );
+                        }
+
+                        #[cfg(windows)]
+                        winit::platform::windows::EventLoopBuilderExtWindows::with_any_thread(
+                            builder, true,
+                        );
+                    })),
+                    ..Default::default()
+                };
+
+                let app_model = model.clone();
+                let app_api = api.clone();
+
+                let result = panic::catch_unwind(AssertUnwindSafe(|| {
+                    eframe::run_native(
+                        "ESP Factory",
+                        options,
+                        Box::new(move |cc| {
+                            cc.egui_ctx.set_zoom_factor(1.5);
+
+                            Ok(Box::new(GuiApp::new(app_model, app_api)))
+                        }),
+                    )
+                }));
+
+                match result {
+                    Ok(Ok(())) => {
+                        // The window is closed; quit as soon as the task prompts for anything
+                        while !api.send(Command::Quit) {
+                            thread::sleep(REPAINT_INTERVAL);
+                        }
+                    }
+                    Ok(Err(err)) => failed.signal(anyhow::anyhow!("Running the GUI failed: {err}")),
+                    Err(panic) => {
+                        let message = panic
+                            .downcast_ref::<&str>()
+                            .copied()
+                            .or_else(|| panic.downcast_ref::<String>().map(String::as_str))
+                            .unwrap_or("unknown panic");
+
+                        failed.signal(anyhow::anyhow!("The GUI panicked: {message}"));
+                    }
+                }
+            })
+            .context("Spawning the GUI thread failed")?;
+    }
+
+    Ok(failed)
+}
+
+/// A signal raised with the error the GUI failed with
+pub(crate) type GuiFailed = Signal<CriticalSectionRawMutex, anyhow::Error>;
+
+/// The GUI application
+struct GuiApp {
+    model: Arc<Model>,
+    api: Api,
+    /// The message catalog of the UI language
+    catalog: Catalog,
+    /// The value being input by the operator for the pending input prompt
+    input: String,
+    /// The input prompt the value is being input for, so that the value is reset when the prompt changes
+    input_prompt: Option<Prompt>,
+}
+
+impl GuiApp {
+    fn new(model: Arc<Model>, api: Api) -> Self {
+        let catalog = model.access(|inner| inner.ui_catalog.clone());
+
+        Self {
+            model,
+            api,
+            catalog,
+            input: String::new(),
+            input_prompt: None,
+        }
+    }
+
+    /// Render the title bar with the session statistics
+    fn header(&self, ui: &mut egui::Ui) {
+        self.model.access(|inner| {
+            ui.horizontal(|ui| {
+                ui.heading(RichText::new("ESP Factory").strong());
+
+                ui.separator();
+                ui.label(format!(
+                    "{}: {}",
+                    self.catalog.tr("Provisioned"),
+                    inner.stats.provisioned
+                ));
+
+                if let Some(work_order) = inner.work_order.as_ref() {
+                    ui.separator();
+                    ui.label(format!(
+                        "{} {}: {}/{}",
+                        self.catalog.tr("Work order"),
+                        work_order.id,
+                        work_order.remaining(),
+                        work_order.quantity
+                    ));
+                }
+
+                if let Some(operator) = inner.operator.as_ref() {
+                    ui.separator();
+                    ui.label(format!("{}: {}", self.catalog.tr("Operator"), operator));
+                }
+            });
+
+            if !inner.cycle.is_pending() {
+                ui.horizontal(|ui| {
+                    for (index, (step, status)) in inner.cycle.steps.iter().enumerate() {
+                        if index > 0 {
+                            ui.label(">");
+                        }
+
+                        let text = RichText::new(self.catalog.tr(step.name()));
+
+                        ui.label(match status {
+                            CycleStepStatus::Pending => text,
+                            CycleStepStatus::Active => text.strong().color(Color32::YELLOW),
+                            CycleStepStatus::Done => text.color(Color32::GREEN),
+                            CycleStepStatus::Failed => text.strong().color(Color32::RED),
+                            CycleStepStatus::Skipped => text.color(Color32::DARK_GRAY),
+                        });
+                    }
+                });
+            }
+        });
+    }
+
+    /// Render the state of the model
+    fn state(&self, ui: &mut egui::Ui) {
+        self.model.access(|inner| match &inner.state {
+            State::Readout(readout) => {
+                ui.heading(self.catalog.tr("Readouts"));
+                table(ui, &self.catalog, &TableView::input_readouts(readout));
+
+                if let Some(error) = readout.error.as_ref() {
+                    ui.colored_label(Color32::RED, RichText::new(error).size(20.0));
+                }
+            }
+            State::Provision(provision) => {
+                ui.heading(format!(
+                    "{}{}",
+                    self.catalog.tr("Bundle "),
+                    provision.bundle.name
+                ));
+                table(ui, &self.catalog, &TableView::partitions(&provision.bundle));
+
+                if !provision.bundle.efuse_mapping.is_empty() {
+                    table(ui, &self.catalog, &TableView::efuses(&provision.bundle));
+                }
+
+                table(ui, &self.catalog, &TableView::readouts(&provision.readouts));
+            }
+            State::Preview(preview) => {
+                let bundle = &preview.provision.bundle;
+
+                ui.heading(format!(
+                    "{}{}",
+                    self.catalog.tr("Preview of bundle "),
+                    bundle.name
+                ));
+                table(ui, &self.catalog, &TableView::details(&preview.details));
+                table(ui, &self.catalog, &TableView::partitions(bundle));
+
+                if !bundle.efuse_mapping.is_empty() {
+                    table(ui, &self.catalog, &TableView::efuses(bundle));
+                }
+
+                table(
+                    ui,
+                    &self.catalog,
+                    &TableView::readouts(&preview.provision.readouts),
+                );
+            }
+            State::AppRun(app_logs) => {
+                ui.heading(self.catalog.tr("App logs"));
+
+                egui::ScrollArea::vertical()
+                    .stick_to_bottom(true)
+                    .auto_shrink(false)
+                    .show(ui, |ui| {
+                        for line in app_logs.text_lines() {
+                            ui.monospace(line);
+                        }
+                    });
+            }
+            State::Processing(processing) => {
+                ui.vertical_centered(|ui| {
+                    ui.add_space(80.0);
+                    ui.heading(
+                        RichText::new(self.catalog.tr(processing.title.trim()))
+                            .size(36.0)
+                            .strong(),
+                    );
+                    ui.add_space(20.0);
+                    ui.spinner();
+                    ui.label(RichText::new(self.catalog.tr(&processing.status)).size(24.0));
+                });
+            }
+            State::Status(status) => {
+                let color = if status.error {
+                    Color32::RED
+                } else {
+                    Color32::GREEN
+                };
+
+                ui.vertical_centered(|ui| {
+                    ui.add_space(80.0);
+                    ui.heading(
+                        RichText::new(status.title.trim())
+                            .size(48.0)
+                            .strong()
+                            .color(color),
+                    );
+                    ui.add_space(20.0);
+                    ui.label(RichText::new(&status.message).size(24.0));
+                });
+            }
+            State::PortPick(port_pick) => {
+                ui.heading(self.catalog.tr("Pick the serial port"));
+                table(ui, &self.catalog, &TableView::ports(port_pick));
+                self.picks(ui, port_pick.ports.iter().map(|port| port.name.clone()));
+            }
+            State::PartTablePick(part_table_pick) => {
+                ui.heading(self.catalog.tr("Pick the partition table"));
+                ui.colored_label(Color32::YELLOW, &part_table_pick.problem);
+                table(ui, &self.catalog, &TableView::part_tables(part_table_pick));
+                self.picks(
+                    ui,
+                    part_table_pick
+                        .variants
+                        .iter()
+                        .map(|variant| variant.variant.clone()),
+                );
+            }
+        });
+    }
+
+    /// Render one big button per pickable entry, answering the input prompt with the 1-based number of the entry
+    fn picks(&self, ui: &mut egui::Ui, entries: impl Iterator<Item = String>) {
+        ui.add_space(20.0);
+
+        ui.horizontal_wrapped(|ui| {
+            for (index, entry) in entries.enumerate() {
+                if button(ui, &entry) {
+                    self.api.send(Command::Input((index + 1).to_string()));
+                }
+            }
+        });
+    }
+
+    /// Render the buttons (and the input field) answering the pending prompt, if any
+    fn prompt(&mut self, ui: &mut egui::Ui) {
+        let prompt = self.api.pending();
+
+        if !matches!(prompt, Some(Prompt::Input { .. })) {
+            self.input_prompt = None;
+        }
+
+        ui.horizontal(|ui| {
+            match prompt.clone() {
+                None => (),
+                Some(Prompt::Cancelable) => {
+                    if button(ui, &self.catalog.tr("Cancel")) {
+                        self.api.send(Command::Cancel);
+                    }
+                }
+                Some(Prompt::Confirm { label, skip }) => {
+                    if button(ui, &label) {
+                        self.api.send(Command::Confirm);
+                    }
+
+                    if skip && button(ui, &self.catalog.tr("Skip")) {
+                        self.api.send(Command::Skip);
+                    }
+
+                    if button(ui, &self.catalog.tr("Back")) {
+                        self.api.send(Command::Cancel);
+                    }
+                }
+                Some(Prompt::Input { label, current }) => {
+                    if self.input_prompt != prompt {
+                        self.input = current;
+                        self.input_prompt = prompt.clone();
+                    }
+
+                    ui.label(RichText::new(label).size(BUTTON_TEXT_SIZE));
+
+                    let response = ui.add_sized(
+                        [400.0, BUTTON_SIZE[1]],
+                        egui::TextEdit::singleline(&mut self.input)
+                            .font(egui::FontId::proportional(BUTTON_TEXT_SIZE)),
+                    );
+
+                    let entered =
+                        response.lost_focus() && ui.input(|i| i.key_pressed(egui::Key::Enter));
+
+                    if button(ui, &self.catalog.tr("OK")) || entered {
+                        self.api.send(Command::Input(self.input.trim().to_string()));
+                    }
+
+                    if button(ui, &self.catalog.tr("Back")) {
+                        self.api.send(Command::Cancel);
+                    }
+                }
+            }
+
+            if prompt.is_some() && button(ui, &self.catalog.tr("Quit")) {
+                self.api.send(Command::Quit);
+            }
+        });
+    }
+
+    /// Render the on-screen logs
+    fn logs(&self, ui: &mut egui::Ui) {
+        egui::CollapsingHeader::new(self.catalog.tr("Logs")).show(ui, |ui| {
+            egui::ScrollArea::vertical()
+                .max_height(200.0)
+                .stick_to_bottom(true)
+                .auto_shrink(false)
+                .show(ui, |ui| {
+                    self.model.access(|inner| {
+                        for (level, line) in inner.logs.buffered.text_lines() {
+                            let color = match level {
+                                log::Level::Error => Color32::RED,
+                                log::Level::Warn => Color32::YELLOW,
+                                _ => ui.visuals().text_color(),
+                            };
+
+                            ui.label(RichText::new(line).monospace().color(color));
+                        }
+                    });
+                });
+        });
+    }
+}
+
+impl eframe::App for GuiApp {
+    fn update(&mut self, ctx: &egui::Context, _frame: &mut eframe::Frame) {
+        egui::TopBottomPanel::top("header").show(ctx, |ui| self.header(ui));
+
+        egui::TopBottomPanel::bottom("prompt")
+            .min_height(BUTTON_SIZE[1] + 20.0)
+            .show(ctx, |ui| {
+                ui.add_space(10.0);
+                self.prompt(ui);
+                ui.add_space(10.0);
+                self.logs(ui);
+            });
+
+        egui::CentralPanel::default().show(ctx, |ui| {
+            egui::ScrollArea::vertical().show(ui, |ui| self.state(ui));
+        });
+
+        ctx.request_repaint_after(REPAINT_INTERVAL);
+    }
+}
+
+/// Render a big, touch-friendly button and return `true` if it was clicked
+fn button(ui: &mut egui::Ui, text: &str) -> bool {
+    ui.add_sized(
+        BUTTON_SIZE,
+        egui::Button::new(RichText::new(text).size(BUTTON_TEXT_SIZE).strong()),
+    )
+    .clicked()
+}
+
+/// Render a table of the presentation layer, translated to the UI language
+fn table(ui: &mut egui::Ui, catalog: &Catalog, table: &TableView) {
+    ui.add_space(10.0);
+    ui.label(RichText::new(catalog.tr(table.title)).strong());
+
+    egui::Grid::new(table.title)
+        .striped(true)
+        .spacing([20.0, 6.0])
+        .show(ui, |ui| {
+            for column in &table.columns {
+                ui.label(RichText::new(catalog.tr(column.title)).strong());
+            }
+
+            ui.end_row();
+
+            for row in &table.rows {
+                let color = match row.emphasis {
+                    Emphasis::Normal => None,
+                    Emphasis::Active => Some(Color32::WHITE),
+                    Emphasis::Status(
+                        ProvisioningStatus::NotStarted | ProvisioningStatus::Pending,
+                    ) => Some(Color32::WHITE),
+                    Emphasis::Status(ProvisioningStatus::InProgress(_)) => Some(Color32::YELLOW),
+                    Emphasis::Status(ProvisioningStatus::Done) => Some(Color32::GREEN),
+                    Emphasis::Unavailable => Some(Color32::GRAY),
+                };
+
+                for (text, column) in row.cells.iter().zip(&table.columns) {
+                    let mut text = RichText::new(text);
+
+                    if let Some(color) = color {
+                        text = text.color(color);
+                    }
+
+                    if matches!(row.emphasis, Emphasis::Active | Emphasis::Status(_)) {
+                        text = text.strong();
+                    }
+
+                    match column.align {
+                        Align::Left => ui.label(text),
+                        Align::Right => {
+                            ui.with_layout(egui::Layout::right_to_left(egui::Align::Center), |ui| {
+                                ui.label(text)
+                            })
+                            .inner
+                        }
+                    };
+                }
+
+                ui.end_row();
+            }
+        });
+}
@@ -1,5 +1,9 @@
 use alloc::sync::Arc;
 use core::sync::atomic::{AtomicBool, Ordering};
+use core::time::Duration;
+
+use std::time::Instant;
+
 use embassy_futures::select::{select, Either};
 use embassy_sync::signal::Signal;
 
@@ -8,16 +12,22 @@ use crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifier
 use embassy_sync::blocking_mutex::raw::{CriticalSectionRawMutex, RawMutex};
 use embassy_sync::channel::Channel;
 
+use log::{error, info};
+
+use crate::footswitch::Switch;
 use crate::input::{
     LogInput, LogInputOutcome, TaskConfirmationOutcome, TaskInput, TaskInputOutcome,
 };
 use crate::model::{BufferedLogsLayout, Model};
+use crate::session::Session;
 
 extern crate alloc;
 
 /// A helper for procressing input events from the terminal
 pub struct Input<'a> {
     model: &'a Model,
+    footswitch: Arc<Switch>,
+    session: Arc<Session>,
     input_changed_main: Signal<CriticalSectionRawMutex, ()>,
     input_changed_log: Signal<CriticalSectionRawMutex, ()>,
     pump: EventsPump,
@@ -42,11 +52,20 @@ impl<'a> Input<'a> {
 
     const TOGGLE_LOG: (KeyModifiers, KeyCode) = (KeyModifiers::ALT, KeyCode::Char('l'));
     const WRAP_LOG: (KeyModifiers, KeyCode) = (KeyModifiers::ALT, KeyCode::Char('w'));
-
-    /// Creates a new `Input` instance with the given model
-    pub fn new(model: &'a Model) -> Self {
+    const SAVE_LOG: (KeyModifiers, KeyCode) = (KeyModifiers::ALT, KeyCode::Char('s'));
+    const FILTER_LOG: (KeyModifiers, KeyCode) = (KeyModifiers::empty(), KeyCode::Char('f'));
+    const SEARCH: (KeyModifiers, KeyCode) = (KeyModifiers::empty(), KeyCode::Char('/'));
+    const SEARCH_NEXT: (KeyModifiers, KeyCode) = (KeyModifiers::empty(), KeyCode::Char('n'));
+    const SEARCH_PREV: (KeyModifiers, KeyCode) = (KeyModifiers::SHIFT, KeyCode::Char('N'));
+
+    /// Creates a new `Input` instance with the given model, footswitch and session
+    ///
+    /// The key presses are recorded in the session, or replayed from it
+    pub(crate) fn new(model: &'a Model, footswitch: Arc<Switch>, session: Arc<Session>) -> Self {
         Self {
             model,
+            footswitch,
+            session,
             input_changed_main: Signal::new(),
             input_changed_log: Signal::new(),
             pump: EventsPump::new(),
@@ -83,14 +102,19 @@ impl<'a> Input<'a> {
 
     /// Gets the next key press event
     async fn get_any(&self) -> KeyEvent {
-        self.pump.start();
+        self.pump.start(&self.session);
 
         loop {
             match self.pump.state.event.receive().await {
                 // It's important to check that the event is a key press event as
                 // crossterm also emits key release and repeat events on Windows.
                 Event::Key(key) if key.kind == KeyEventKind::Press => {
-                    if Self::key_m(&key) == Self::TOGGLE_LOG || Self::key_m(&key) == Self::WRAP_LOG
+                    self.session.record_key(&key);
+
+                    if self.footswitch.is_key(&key) {
+                        self.footswitch.press();
+                    } else if Self::key_m(&key) == Self::TOGGLE_LOG
+                        || Self::key_m(&key) == Self::WRAP_LOG
                     {
                         self.model.modify(|inner| {
                             let buffered = &mut inner.logs.buffered;
@@ -104,6 +128,15 @@ impl<'a> Input<'a> {
                             self.input_changed_main.signal(());
                             self.input_changed_log.signal(());
                         });
+                    } else if Self::key_m(&key) == Self::SAVE_LOG {
+                        let result = self
+                            .model
+                            .access_mut(|inner| (inner.logs.file.save(), false));
+
+                        match result {
+                            Ok(path) => info!("Log saved to `{}`", path.display()),
+                            Err(err) => error!("Saving the log failed: {err:#}"),
+                        }
                     } else {
                         return key;
                     }
@@ -208,7 +241,33 @@ impl TaskInput for &Input<'_> {
 impl LogInput for &Input<'_> {
     async fn get(&mut self) -> LogInputOutcome {
         loop {
-            match Input::key_m(&self.get_log_input().await) {
+            let key = self.get_log_input().await;
+
+            if self
+                .model
+                .access(|inner| inner.logs.buffered.is_search_editing())
+            {
+                match Input::key_m(&key) {
+                    Input::NEXT => break LogInputOutcome::SearchDone,
+                    Input::PREV => break LogInputOutcome::SearchCancel,
+                    (modifiers, KeyCode::Backspace) if modifiers.is_empty() => {
+                        break LogInputOutcome::SearchBackspace
+                    }
+                    (modifiers, KeyCode::Char(ch))
+                        if modifiers.is_empty() || modifiers == KeyModifiers::SHIFT =>
+                    {
+                        break LogInputOutcome::SearchChar(ch)
+                    }
+                    _ => continue,
+                }
+            }
+
+            match Input::key_m(&key) {
+                Input::SEARCH => break LogInputOutcome::Search,
+                Input::SEARCH_NEXT => break LogInputOutcome::SearchNext,
+                Input::SEARCH_PREV => break LogInputOutcome::SearchPrev,
+                Input::PREV => break LogInputOutcome::SearchCancel,
+                Input::FILTER_LOG => break LogInputOutcome::Filter,
                 Input::CTL_HOME => break LogInputOutcome::LogHome,
                 Input::CTL_END => break LogInputOutcome::LogEnd,
                 Input::UP => break LogInputOutcome::Up,
@@ -241,14 +300,15 @@ impl EventsPump {
         }
     }
 
-    /// Starts the event pump thread if not started yet
-    fn start(&self) {
+    /// Starts the event pump thread if not started yet, replaying the key presses of the session (if any)
+    fn start(&self, session: &Session) {
         let mut thread_join = self.thread_join.lock().unwrap();
 
         if thread_join.is_none() {
             let state = self.state.clone();
+            let replay = session.take_replay_keys().unwrap_or_default();
 
-            *thread_join = Some(std::thread::spawn(move || state.pump_loop()));
+            *thread_join = Some(std::thread::spawn(move || state.pump_loop(replay)));
         }
     }
 }
@@ -283,9 +343,32 @@ impl EventsPumpState {
     }
 
     /// The main event pump loop (to be called from the event pump thread)
-    fn pump_loop(&self) -> anyhow::Result<()> {
+    ///
+    /// The key presses of a replayed session (if any) are pumped at their recorded times,
+    /// in addition to the events from the terminal
+    fn pump_loop(&self, replay: Vec<(u64, KeyEvent)>) -> anyhow::Result<()> {
+        const POLL_TIMEOUT: Duration = Duration::from_millis(100);
+
+        let start = Instant::now();
+        let mut replay = replay.into_iter().peekable();
+
         while !self.quit.load(Ordering::SeqCst) {
-            if event::poll(core::time::Duration::from_millis(100))? {
+            let timeout = if let Some((at_ms, key)) = replay.peek() {
+                let due = Duration::from_millis(*at_ms).saturating_sub(start.elapsed());
+
+                if due.is_zero() {
+                    futures_lite::future::block_on(self.event.send(Event::Key(*key)));
+                    replay.next();
+
+                    continue;
+                }
+
+                due.min(POLL_TIMEOUT)
+            } else {
+                POLL_TIMEOUT
+            };
+
+            if event::poll(timeout)? {
                 futures_lite::future::block_on(self.event.send(event::read()?));
             }
         }
@@ -0,0 +1,753 @@
+//! The presentation layer of the UI
+//!
+//! Turns the model into tables of formatted cells, independent of the frontend rendering them
+//! (the terminal UI, or other frontends and snapshot tests)
+//!
+//! The table and column titles are in English; the frontends translate them to the UI language
+
+use core::cmp::Ordering;
+
+use crate::bundle::{mac_str, Bundle, Efuse, ImageType, ProvisioningStatus};
+use crate::efuse;
+use crate::model::{PartTablePick, PortPick, Readout};
+
+/// The alignment of a table column
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Align {
+    Left,
+    Right,
+}
+
+/// The emphasis of a table row
+///
+/// Mapped by the frontends to their own styles and colors
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Emphasis {
+    /// A regular row
+    Normal,
+    /// The row the operator is currently working on
+    Active,
+    /// A row with a provisioning status (not started, pending, in progress or done)
+    Status(ProvisioningStatus),
+    /// A row which is not going to be provisioned
+    Unavailable,
+}
+
+/// A table column
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Column {
+    /// The title of the column
+    pub title: &'static str,
+    /// The alignment of the column cells
+    pub align: Align,
+}
+
+impl Column {
+    const fn left(title: &'static str) -> Self {
+        Self {
+            title,
+            align: Align::Left,
+        }
+    }
+
+    const fn right(title: &'static str) -> Self {
+        Self {
+            title,
+            align: Align::Right,
+        }
+    }
+}
+
+/// A table row
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TableRow {
+    /// The formatted cells of the row, one per column
+    pub cells: Vec<String>,
+    /// The emphasis of the row
+    pub emphasis: Emphasis,
+}
+
+/// A table, as presented to the operator
+#[derive(Clone, Debug, Eq, PartialEq, Hash)]
+pub struct TableView {
+    /// The title of the table
+    pub title: &'static str,
+    /// The columns of the table
+    pub columns: Vec<Column>,
+    /// The rows of the table
+    pub rows: Vec<TableRow>,
+}
+
+impl TableView {
+    /// The table of the readouts being input by the operator
+    pub fn input_readouts(readout: &Readout) -> Self {
+        Self {
+            title: "Input Readouts",
+            columns: vec![
+                Column::left(""),
+                Column::left("Name"),
+                Column::left("Value"),
+            ],
+            rows: readout
+                .readouts
+                .iter()
+                .enumerate()
+                .map(|(index, (name, value))| TableRow {
+                    cells: vec![
+                        if index == readout.active { ">" } else { "" }.into(),
+                        name.clone(),
+                        match readout.active.cmp(&index) {
+                            Ordering::Less => "(empty)".into(),
+                            Ordering::Equal => format!("{value}_"),
+                            Ordering::Greater => value.clone(),
+                        },
+                    ],
+                    emphasis: if index == readout.active {
+                        Emphasis::Active
+                    } else {
+                        Emphasis::Normal
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    /// The table of the serial ports the operator is picking from
+    pub fn ports(port_pick: &PortPick) -> Self {
+        let selected = port_pick.selected();
+
+        Self {
+            title: "Serial Ports",
+            columns: vec![
+                Column::right("#"),
+                Column::left("Port"),
+                Column::left("VID:PID"),
+                Column::left("Product"),
+                Column::left("Serial Number"),
+            ],
+            rows: port_pick
+                .ports
+                .iter()
+                .enumerate()
+                .map(|(index, port)| TableRow {
+                    cells: vec![
+                        (index + 1).to_string(),
+                        port.name.clone(),
+                        format!("{:04x}:{:04x}", port.vid, port.pid),
+                        port.product.clone().unwrap_or_default(),
+                        port.serial_number.clone().unwrap_or_default(),
+                    ],
+                    emphasis: if selected == Some(index) {
+                        Emphasis::Active
+                    } else {
+                        Emphasis::Normal
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    /// The table of the alternative partition tables the operator is picking from
+    pub fn part_tables(part_table_pick: &PartTablePick) -> Self {
+        let selected = part_table_pick.selected();
+
+        Self {
+            title: "Partition Tables",
+            columns: vec![
+                Column::right("#"),
+                Column::left("Variant"),
+                Column::right("End"),
+                Column::left("Fits"),
+                Column::left("Partitions"),
+            ],
+            rows: part_table_pick
+                .variants
+                .iter()
+                .enumerate()
+                .map(|(index, variant)| TableRow {
+                    cells: vec![
+                        (index + 1).to_string(),
+                        variant.variant.clone(),
+                        format!("0x{:06x}", variant.end),
+                        if variant.fits { "Yes" } else { "No" }.to_string(),
+                        variant.partitions.join(", "),
+                    ],
+                    emphasis: if selected == Some(index) {
+                        Emphasis::Active
+                    } else {
+                        Emphasis::Normal
+                    },
+                })
+                .collect(),
+        }
+    }
+
+    /// The table of the partitions of the bundle being provisioned
+    pub fn partitions(bundle: &Bundle) -> Self {
+        Self {
+            title: "Partitions",
+            columns: vec![
+                Column::left(""),
+                Column::left("Name"),
+                Column::left("Type"),
+                Column::left("Subtype"),
+                Column::right("Offset"),
+                Column::right("Size"),
+                Column::left("Flags"),
+                Column::right("Image"),
+                Column::right("Provision"),
+            ],
+            rows: bundle
+                .parts_mapping
+                .iter()
+                .map(|mapping| {
+                    let status = mapping.status();
+
+                    let cells = if let Some(partition) = mapping.partition.as_ref() {
+                        let special = matches!(
+                            partition.name().as_str(),
+                            Bundle::BOOTLOADER_NAME | Bundle::PART_TABLE_NAME
+                        );
+
+                        let flags = partition
+                            .encrypted()
+                            .then_some("Encr")
+                            .into_iter()
+                            .chain(mapping.flags.readonly.then_some("RO"))
+                            .chain(mapping.flags.custom.iter().map(String::as_str))
+                            .chain(
+                                mapping
+                                    .image
+                                    .as_ref()
+                                    .map(|image| matches!(image.ty, ImageType::Empty))
+                                    .unwrap_or(false)
+                                    .then_some("Empty"),
+                            )
+                            .collect::<Vec<_>>()
+                            .join(", ");
+
+                        vec![
+                            active_string(status),
+                            partition.name(),
+                            if special {
+                                "".into()
+                            } else {
+                                partition.ty().to_string()
+                            },
+                            if special {
+                                "".into()
+                            } else {
+                                partition.subtype().to_string()
+                            },
+                            format!("0x{:06x}", partition.offset()),
+                            size_string(partition.size() as usize),
+                            if flags.is_empty() { "-".into() } else { flags },
+                            mapping
+                                .image
+                                .as_ref()
+                                .map(|image| size_string(image.data.len()))
+                                .unwrap_or("-".to_string()),
+                            status_string(status),
+                        ]
+                    } else {
+                        let image = mapping.image.as_ref().unwrap();
+
+                        vec![
+                            active_string(status),
+                            format!("???{}", image.name),
+                            "".into(),
+                            "".into(),
+                            "".into(),
+                            "".into(),
+                            "".into(),
+                            size_string(image.data.len()),
+                            status_string(status),
+                        ]
+                    };
+
+                    TableRow {
+                        cells,
+                        emphasis: status_emphasis(status),
+                    }
+                })
+                .collect(),
+        }
+    }
+
+    /// The table of the eFuses of the bundle being provisioned
+    pub fn efuses(bundle: &Bundle) -> Self {
+        Self {
+            title: "EFUSE",
+            columns: vec![
+                Column::left(""),
+                Column::left("Name"),
+                Column::left("Type"),
+                Column::left("Purpose"),
+                Column::right("Value"),
+                Column::left("Description"),
+                Column::right("Provision"),
+            ],
+            rows: bundle
+                .efuse_mapping
+                .iter()
+                .map(|mapping| TableRow {
+                    cells: vec![
+                        active_string(Some(mapping.status)),
+                        mapping.efuse.name().to_string(),
+                        match &mapping.efuse {
+                            Efuse::Param { .. } => "Param".into(),
+                            Efuse::Key { .. } => "Key".into(),
+                            Efuse::KeyDigest { .. } => "Digest".into(),
+                            Efuse::CustomMac { .. } => "MAC".into(),
+                        },
+                        match &mapping.efuse {
+                            Efuse::Param { .. } | Efuse::CustomMac { .. } => "-".into(),
+                            Efuse::Key { purpose, .. } | Efuse::KeyDigest { purpose, .. } => {
+                                purpose.clone()
+                            }
+                        },
+                        match &mapping.efuse {
+                            Efuse::Param { value, .. } => format!("0x{:08x}", value),
+                            // Not even the size of the keys is shown
+                            Efuse::Key { .. } => "(secret)".into(),
+                            Efuse::KeyDigest {
+                                digest_value: value,
+                                ..
+                            } => format!("({}B)", value.len()),
+                            Efuse::CustomMac { mac } => mac_str(mac),
+                        },
+                        match &mapping.efuse {
+                            Efuse::Param { name, .. } => {
+                                efuse::known_field(bundle.params.chip, name)
+                                    .map(|field| field.description.to_string())
+                                    .unwrap_or_else(|| "?".into())
+                            }
+                            _ => "-".into(),
+                        },
+                        status_string(Some(mapping.status)),
+                    ],
+                    emphasis: status_emphasis(Some(mapping.status)),
+                })
+                .collect(),
+        }
+    }
+
+    /// The table of the details of the provisioning of a previewed bundle
+    pub fn details(details: &[(String, String)]) -> Self {
+        Self {
+            title: "Details",
+            columns: vec![
+                Column::left(""),
+                Column::left("Name"),
+                Column::left("Value"),
+            ],
+            rows: details
+                .iter()
+                .map(|(name, value)| TableRow {
+                    cells: vec!["".into(), name.clone(), value.clone()],
+                    emphasis: Emphasis::Normal,
+                })
+                .collect(),
+        }
+    }
+
+    /// The table of the readouts (manual and eFuse) of the PCB being provisioned
+    pub fn readouts(readouts: &[(String, String)]) -> Self {
+        Self {
+            title: "Readouts (manual and eFuse)",
+            columns: vec![
+                Column::left(""),
+                Column::left("Name"),
+                Column::left("Value"),
+            ],
+            rows: readouts
+                .iter()
+                .map(|(name, value)| TableRow {
+                    cells: vec!["".into(), name.clone(), value.clone()],
+                    emphasis: Emphasis::Normal,
+                })
+                .collect(),
+        }
+    }
+}
+
+/// The emphasis of a row with the given provisioning status
+fn status_emphasis(status: Option<ProvisioningStatus>) -> Emphasis {
+    status
+        .map(Emphasis::Status)
+        .unwrap_or(Emphasis::Unavailable)
+}
+
+/// The marker of the row being provisioned
+fn active_string(status: Option<ProvisioningStatus>) -> String {
+    if status
+        .map(|status| matches!(status, ProvisioningStatus::InProgress(_)))
+        .unwrap_or(false)
+    {
+        ">"
+    } else {
+        ""
+    }
+    .into()
+}
+
+/// The provisioning status, as displayed to the operator
+fn status_string(status: Option<ProvisioningStatus>) -> String {
+    match status {
+        Some(ProvisioningStatus::NotStarted) => "Not Started".into(),
+        Some(ProvisioningStatus::Pending) => "Pending".into(),
+        Some(ProvisioningStatus::InProgress(progress)) => {
+            if let Some(progress) = progress {
+                format!("{}%", progress)
+            } else {
+                "In Progress".into()
+            }
+        }
+        Some(ProvisioningStatus::Done) => "Done".into(),
+        None => "-".into(),
+    }
+}
+
+/// A size in KB (rounded up) and in bytes
+fn size_string(size: usize) -> String {
+    format!("{}KB (0x{:06x})", size.div_ceil(1024), size)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use esp_idf_part::{AppType, DataType, Partition, SubType, Type};
+
+    use crate::bundle::{EfuseMapping, Image, Params, PartitionFlags, PartitionMapping};
+    use crate::model::{PartTableDescription, PortDescription};
+    use crate::utils::secret::Secret;
+
+    use super::*;
+
+    /// Render the table as text, one line for the title, one for the columns and one per row
+    ///
+    /// The left-aligned column titles are suffixed with `<` and the right-aligned ones are prefixed with `>`;
+    /// the emphasis of each row follows its cells
+    fn render(table: &TableView) -> Vec<String> {
+        let columns = table
+            .columns
+            .iter()
+            .map(|column| match column.align {
+                Align::Left => format!("{}<", column.title),
+                Align::Right => format!(">{}", column.title),
+            })
+            .collect::<Vec<_>>()
+            .join(" | ");
+
+        [table.title.to_string(), columns]
+            .into_iter()
+            .chain(
+                table
+                    .rows
+                    .iter()
+                    .map(|row| format!("{} [{:?}]", row.cells.join(" | "), row.emphasis)),
+            )
+            .collect()
+    }
+
+    fn image(name: &str, len: usize, status: ProvisioningStatus) -> Image {
+        let mut image = Image::new(name.to_string(), vec![0; len]);
+        image.status = status;
+
+        image
+    }
+
+    #[test]
+    fn input_readouts() {
+        let readout = Readout {
+            readouts: vec![
+                ("Device ID".into(), "D-1".into()),
+                ("PCB ID".into(), "P-".into()),
+                ("Test JIG ID".into(), "".into()),
+            ],
+            active: 1,
+            error: None,
+        };
+
+        assert_eq!(
+            render(&TableView::input_readouts(&readout)),
+            [
+                "Input Readouts",
+                "< | Name< | Value<",
+                " | Device ID | D-1 [Normal]",
+                "> | PCB ID | P-_ [Active]",
+                " | Test JIG ID | (empty) [Normal]",
+            ]
+        );
+    }
+
+    #[test]
+    fn ports() {
+        let mut port_pick = PortPick::new(vec![
+            PortDescription {
+                name: "/dev/ttyUSB0".into(),
+                vid: 0x10c4,
+                pid: 0xea60,
+                product: Some("CP2102 USB to UART".into()),
+                serial_number: Some("0001".into()),
+            },
+            PortDescription {
+                name: "/dev/ttyACM0".into(),
+                vid: 0x303a,
+                pid: 0x1001,
+                product: None,
+                serial_number: None,
+            },
+        ]);
+        port_pick.selection = "2".into();
+
+        assert_eq!(
+            render(&TableView::ports(&port_pick)),
+            [
+                "Serial Ports",
+                "># | Port< | VID:PID< | Product< | Serial Number<",
+                "1 | /dev/ttyUSB0 | 10c4:ea60 | CP2102 USB to UART | 0001 [Normal]",
+                "2 | /dev/ttyACM0 | 303a:1001 |  |  [Active]",
+            ]
+        );
+    }
+
+    #[test]
+    fn part_tables() {
+        let mut part_table_pick = PartTablePick::new(
+            "The partition table ends beyond the 4MB flash".into(),
+            vec![
+                PartTableDescription {
+                    variant: "8mb".into(),
+                    partitions: vec!["nvs".into(), "ota_0".into(), "ota_1".into()],
+                    end: 0x7f0000,
+                    fits: false,
+                },
+                PartTableDescription {
+                    variant: "4mb".into(),
+                    partitions: vec!["nvs".into(), "factory".into()],
+                    end: 0x3f0000,
+                    fits: true,
+                },
+            ],
+        );
+        part_table_pick.selection = "1".into();
+
+        assert_eq!(
+            render(&TableView::part_tables(&part_table_pick)),
+            [
+                "Partition Tables",
+                "># | Variant< | >End | Fits< | Partitions<",
+                "1 | 8mb | 0x7f0000 | No | nvs, ota_0, ota_1 [Active]",
+                "2 | 4mb | 0x3f0000 | Yes | nvs, factory [Normal]",
+            ]
+        );
+    }
+
+    #[test]
+    fn partitions() {
+        let bundle = Bundle {
+            name: "PCB1.bundle".into(),
+            params: Params::new(),
+            parts_mapping: vec![
+                PartitionMapping {
+                    partition: Some(Partition::new(
+                        Bundle::BOOTLOADER_NAME,
+                        Type::Custom(0),
+                        SubType::Custom(0),
+                        0x1000,
+                        0x7000,
+                        false,
+                    )),
+                    flags: PartitionFlags::default(),
+                    image: Some(image(
+                        Bundle::BOOTLOADER_NAME,
+                        0x4321,
+                        ProvisioningStatus::Done,
+                    )),
+                },
+                PartitionMapping {
+                    partition: Some(Partition::new(
+                        Bundle::PART_TABLE_NAME,
+                        Type::Custom(0),
+                        SubType::Custom(0),
+                        0x8000,
+                        0x1000,
+                        false,
+                    )),
+                    flags: PartitionFlags::default(),
+                    image: Some(image(
+                        Bundle::PART_TABLE_NAME,
+                        0xc00,
+                        ProvisioningStatus::InProgress(Some(50)),
+                    )),
+                },
+                PartitionMapping {
+                    partition: Some(Partition::new(
+                        "nvs",
+                        Type::Data,
+                        SubType::Data(DataType::Nvs),
+                        0x9000,
+                        0x6000,
+                        false,
+                    )),
+                    flags: PartitionFlags {
+                        readonly: true,
+                        custom: vec!["keep".into()],
+                    },
+                    image: None,
+                },
+                PartitionMapping {
+                    partition: Some(Partition::new(
+                        "ota_0",
+                        Type::App,
+                        SubType::App(AppType::Ota_0),
+                        0x10000,
+                        0x100000,
+                        true,
+                    )),
+                    flags: PartitionFlags::default(),
+                    image: Some(image("ota_0", 1025, ProvisioningStatus::Pending)),
+                },
+                PartitionMapping {
+                    partition: Some(Partition::new(
+                        "ota_1",
+                        Type::App,
+                        SubType::App(AppType::Ota_1),
+                        0x110000,
+                        0x100000,
+                        false,
+                    )),
+                    flags: PartitionFlags::default(),
+                    image: Some(Image::new_empty(0x2000)),
+                },
+                PartitionMapping {
+                    partition: None,
+                    flags: PartitionFlags::default(),
+                    image: Some(image("extra.bin", 10, ProvisioningStatus::NotStarted)),
+                },
+            ],
+            efuse_mapping: Vec::new(),
+            config_override: None,
+            part_table_variants: Vec::new(),
+        };
+
+        assert_eq!(
+            render(&TableView::partitions(&bundle)),
+            [
+                "Partitions",
+                "< | Name< | Type< | Subtype< | >Offset | >Size | Flags< | >Image | >Provision",
+                " | (bootloader) |  |  | 0x001000 | 28KB (0x007000) | - | 17KB (0x004321) | Done [Status(Done)]",
+                "> | (part-table) |  |  | 0x008000 | 4KB (0x001000) | - | 3KB (0x000c00) | 50% [Status(InProgress(Some(50)))]",
+                " | nvs | data | nvs | 0x009000 | 24KB (0x006000) | RO, keep | - | - [Unavailable]",
+                " | ota_0 | app | ota_0 | 0x010000 | 1024KB (0x100000) | Encr | 2KB (0x000401) | Pending [Status(Pending)]",
+                " | ota_1 | app | ota_1 | 0x110000 | 1024KB (0x100000) | Empty | 8KB (0x002000) | Not Started [Status(NotStarted)]",
+                " | ???extra.bin |  |  |  |  |  | 1KB (0x00000a) | - [Unavailable]",
+            ]
+        );
+    }
+
+    #[test]
+    fn efuses() {
+        let mapping = |efuse, status| EfuseMapping { efuse, status };
+
+        let bundle = Bundle {
+            name: "PCB1.bundle".into(),
+            params: Params::new(),
+            parts_mapping: Vec::new(),
+            efuse_mapping: vec![
+                mapping(
+                    Efuse::Param {
+                        name: "DISABLE_BT".into(),
+                        value: 1,
+                    },
+                    ProvisioningStatus::Done,
+                ),
+                mapping(
+                    Efuse::Param {
+                        name: "FOO_BAR".into(),
+                        value: 0x10,
+                    },
+                    ProvisioningStatus::NotStarted,
+                ),
+                mapping(
+                    Efuse::Key {
+                        block: "BLOCK1".into(),
+                        key_value: Arc::new(Secret::new(vec![0x55; 32])),
+                        purpose: "flash_encryption".into(),
+                        read_protect: None,
+                        write_protect: None,
+                    },
+                    ProvisioningStatus::InProgress(None),
+                ),
+                mapping(
+                    Efuse::KeyDigest {
+                        block: "BLOCK2".into(),
+                        digest_value: Arc::new(vec![0xaa; 32]),
+                        purpose: "secure_boot_v2".into(),
+                        read_protect: None,
+                        write_protect: None,
+                    },
+                    ProvisioningStatus::Pending,
+                ),
+                mapping(
+                    Efuse::CustomMac {
+                        mac: [0x02, 0x5a, 0x00, 0x00, 0x00, 0x01],
+                    },
+                    ProvisioningStatus::NotStarted,
+                ),
+            ],
+            config_override: None,
+            part_table_variants: Vec::new(),
+        };
+
+        assert_eq!(
+            render(&TableView::efuses(&bundle)),
+            [
+                "EFUSE",
+                "< | Name< | Type< | Purpose< | >Value | Description< | >Provision",
+                " | DISABLE_BT | Param | - | 0x00000001 | Disable Bluetooth | Done [Status(Done)]",
+                " | FOO_BAR | Param | - | 0x00000010 | ? | Not Started [Status(NotStarted)]",
+                "> | BLOCK1 | Key | flash_encryption | (secret) | - | In Progress [Status(InProgress(None))]",
+                " | BLOCK2 | Digest | secure_boot_v2 | (32B) | - | Pending [Status(Pending)]",
+                " | CUSTOM_MAC | MAC | - | 02:5a:00:00:00:01 | - | Not Started [Status(NotStarted)]",
+            ]
+        );
+    }
+
+    #[test]
+    fn details_and_readouts() {
+        let values = [
+            ("Chip".to_string(), "ESP32".to_string()),
+            ("MAC".to_string(), "02:5a:00:00:00:01".to_string()),
+        ];
+
+        assert_eq!(
+            render(&TableView::details(&values)),
+            [
+                "Details",
+                "< | Name< | Value<",
+                " | Chip | ESP32 [Normal]",
+                " | MAC | 02:5a:00:00:00:01 [Normal]",
+            ]
+        );
+
+        assert_eq!(
+            render(&TableView::readouts(&values)),
+            [
+                "Readouts (manual and eFuse)",
+                "< | Name< | Value<",
+                " | Chip | ESP32 [Normal]",
+                " | MAC | 02:5a:00:00:00:01 [Normal]",
+            ]
+        );
+    }
+
+    #[test]
+    fn sizes() {
+        assert_eq!(size_string(0), "0KB (0x000000)");
+        assert_eq!(size_string(1024), "1KB (0x000400)");
+        assert_eq!(size_string(1025), "2KB (0x000401)");
+    }
+}
@@ -1,19 +1,107 @@
-use core::cmp::Ordering;
+use core::str::FromStr;
+
+use std::borrow::Cow;
+
+use anyhow::Context;
 
 use bitflags::bitflags;
 
 use ratatui::buffer::Buffer;
 use ratatui::layout::{Constraint, Direction, Layout, Margin, Rect};
-use ratatui::style::Stylize;
+use ratatui::style::{Color, Stylize};
 use ratatui::text::{Line, Text};
 use ratatui::widgets::{Block, Cell, Paragraph, Row, Table, Widget, Wrap};
 use ratatui::DefaultTerminal;
 
-use crate::bundle::{Bundle, Efuse, ImageType, ProvisioningStatus};
+use crate::bundle::ProvisioningStatus;
+use crate::i18n::Catalog;
 use crate::model::{
-    AppLogs, BufferedLogs, BufferedLogsLayout, Logs, Model, ModelInner, Processing, Provision,
-    Readout, State, Status,
+    AppLogs, BufferedLogs, BufferedLogsLayout, Cycle, CycleStepStatus, Highlight, Logs, Model,
+    ModelInner, PartTablePick, PortPick, Preview, Processing, Provision, Readout, State, Status,
 };
+use crate::UiTheme;
+
+use super::font;
+use super::present::{Align, Emphasis, TableView};
+
+/// The default title rendered at the top left of the UI
+const DEFAULT_TITLE: &str = "ESP32 Factory Provisioning";
+
+/// The resolved theme of the UI (see `Config::ui_theme`)
+#[derive(Debug, Clone)]
+pub(crate) struct Theme {
+    title: Option<String>,
+    banner: Option<String>,
+    background: Color,
+    foreground: Color,
+    title_color: Color,
+    keys_color: Color,
+}
+
+impl Theme {
+    /// Create the default theme of the UI
+    pub(crate) const fn new() -> Self {
+        Self {
+            title: None,
+            banner: None,
+            background: Color::Blue,
+            foreground: Color::White,
+            title_color: Color::Green,
+            keys_color: Color::Yellow,
+        }
+    }
+
+    /// Resolve the theme of the UI from its configuration
+    ///
+    /// Fails if any of the configured colors is invalid
+    pub(crate) fn resolve(conf: &UiTheme) -> anyhow::Result<Self> {
+        let default = Self::new();
+
+        let color = |color: Option<&str>, default| {
+            color
+                .map(|color| {
+                    Color::from_str(color.trim())
+                        .with_context(|| format!("Invalid UI color `{color}`"))
+                })
+                .transpose()
+                .map(|color| color.unwrap_or(default))
+        };
+
+        Ok(Self {
+            title: conf.title.clone(),
+            banner: conf.banner.clone(),
+            background: color(conf.background.as_deref(), default.background)?,
+            foreground: color(conf.foreground.as_deref(), default.foreground)?,
+            title_color: color(conf.title_color.as_deref(), default.title_color)?,
+            keys_color: color(conf.keys_color.as_deref(), default.keys_color)?,
+        })
+    }
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// The theme and the message catalog the UI is rendered with
+#[derive(Copy, Clone)]
+struct Ui<'a> {
+    theme: &'a Theme,
+    catalog: &'a Catalog,
+}
+
+impl Ui<'_> {
+    /// Translate an operator-facing UI string to the UI language
+    fn tr(&self, text: &str) -> String {
+        self.catalog.tr(text)
+    }
+}
+
+/// A part of the model, rendered with the theme and in the language of the UI
+trait Draw {
+    fn draw(&self, ui: Ui, area: Rect, buf: &mut Buffer);
+}
 
 /// The view (UI) of the application
 ///
@@ -46,10 +134,92 @@ impl<'a, 'b> View<'a, 'b> {
 
 impl Widget for &ModelInner {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        let ui = Ui {
+            theme: &self.ui_theme,
+            catalog: &self.ui_catalog,
+        };
+
         let (main_area, logs_area) = self.logs.buffered.layout().split(area);
 
         if main_area.width > 0 && main_area.height > 0 {
-            self.state.render(main_area, buf);
+            if let (State::Status(status @ Status { result: true, .. }), Some(identity)) =
+                (&self.state, self.banner.as_deref())
+            {
+                ResultBanner {
+                    status,
+                    identity,
+                    ui,
+                }
+                .render(main_area, buf);
+            } else {
+                self.state.draw(ui, main_area, buf);
+
+                // The breadcrumb goes to the empty line right below the top border
+                if !self.cycle.is_pending() && main_area.height > 2 {
+                    self.cycle.draw(
+                        ui,
+                        Rect::new(
+                            main_area.x + 1,
+                            main_area.y + 1,
+                            main_area.width.saturating_sub(2),
+                            1,
+                        ),
+                        buf,
+                    );
+                }
+            }
+
+            if let Some((step, started)) = self.timing.current.as_ref() {
+                let elapsed = started.elapsed().as_secs_f64();
+
+                let elapsed = self.ui_locale.format_decimal(elapsed, 1);
+
+                let timing = if let Some(estimate) = self.timing.estimate(step) {
+                    format!(
+                        " {step}: {elapsed}s / ~{}s ",
+                        self.ui_locale.format_decimal(estimate.as_secs_f64(), 1)
+                    )
+                } else {
+                    format!(" {step}: {elapsed}s ")
+                };
+
+                Line::from(timing).centered().bold().render(
+                    Rect::new(
+                        main_area.x + 1,
+                        main_area.y + main_area.height - 1,
+                        main_area.width.saturating_sub(2),
+                        1,
+                    ),
+                    buf,
+                );
+            }
+
+            if let Some(work_order) = self.work_order.as_ref() {
+                let remaining = Line::from(format!(
+                    " Work Order {}: {} of {} remaining ",
+                    work_order.id,
+                    work_order.remaining(),
+                    work_order.quantity
+                ))
+                .right_aligned()
+                .bold();
+
+                let remaining = if work_order.remaining() == 0 {
+                    remaining.red()
+                } else {
+                    remaining.yellow()
+                };
+
+                remaining.render(
+                    Rect::new(
+                        main_area.x + 1,
+                        main_area.y,
+                        main_area.width.saturating_sub(2),
+                        1,
+                    ),
+                    buf,
+                );
+            }
         }
 
         if logs_area.width > 0 && logs_area.height > 0 {
@@ -58,22 +228,53 @@ impl Widget for &ModelInner {
     }
 }
 
-impl Widget for &State {
-    fn render(self, area: Rect, buf: &mut Buffer) {
+/// The breadcrumb of the steps of the provisioning cycle, with the status of each step
+impl Draw for Cycle {
+    fn draw(&self, ui: Ui, area: Rect, buf: &mut Buffer) {
+        let theme = ui.theme;
+
+        let mut spans = Vec::new();
+
+        for (index, (step, status)) in self.steps.iter().enumerate() {
+            if index > 0 {
+                spans.push(" > ".into());
+            }
+
+            let name = format!(" {} ", ui.tr(step.name()));
+
+            spans.push(match status {
+                CycleStepStatus::Pending => name.into(),
+                CycleStepStatus::Active => name.fg(theme.keys_color).bold().reversed(),
+                CycleStepStatus::Done => name.green(),
+                CycleStepStatus::Failed => name.red().bold(),
+                CycleStepStatus::Skipped => name.dark_gray(),
+            });
+        }
+
+        Line::from(spans).centered().render(area, buf);
+    }
+}
+
+impl Draw for State {
+    fn draw(&self, ui: Ui, area: Rect, buf: &mut Buffer) {
         match self {
-            State::Readout(readouts) => readouts.render(area, buf),
-            State::Provision(loaded) => loaded.render(area, buf),
-            State::Processing(processing) => processing.render(area, buf),
-            State::AppRun(logs) => logs.render(area, buf),
-            State::Status(status) => status.render(area, buf),
+            State::Readout(readouts) => readouts.draw(ui, area, buf),
+            State::Provision(loaded) => loaded.draw(ui, area, buf),
+            State::Preview(preview) => preview.draw(ui, area, buf),
+            State::Processing(processing) => processing.draw(ui, area, buf),
+            State::AppRun(logs) => logs.draw(ui, area, buf),
+            State::Status(status) => status.draw(ui, area, buf),
+            State::PortPick(port_pick) => port_pick.draw(ui, area, buf),
+            State::PartTablePick(part_table_pick) => part_table_pick.draw(ui, area, buf),
         }
     }
 }
 
-impl Widget for &Readout {
-    fn render(self, area: Rect, buf: &mut Buffer) {
+impl Draw for Readout {
+    fn draw(&self, ui: Ui, area: Rect, buf: &mut Buffer) {
         render_main(
-            Some(" Readouts ".bold()),
+            ui,
+            Some(ui.tr(" Readouts ").bold()),
             Keys::INPUT | Keys::RESET | Keys::QUIT,
             area,
             buf,
@@ -89,95 +290,133 @@ impl Widget for &Readout {
         )
         .split(area.inner(Margin::new(2, 2)));
 
-        Paragraph::new("== Input Readouts")
-            .bold()
-            .render(layout[1], buf);
-
-        Table::new(
-            self.readouts
-                .iter()
-                .enumerate()
-                .map(|(index, (name, value))| {
-                    let mut row = Row::new::<Vec<Cell>>(vec![
-                        if index == self.active { ">" } else { "" }.into(),
-                        name.as_str().into(),
-                        match self.active.cmp(&index) {
-                            Ordering::Less => "(empty)".into(),
-                            Ordering::Equal => format!("{}_", value.as_str()).into(),
-                            Ordering::Greater => value.as_str().into(),
-                        },
-                    ]);
-
-                    if index == self.active {
-                        row = row.bold();
-                    }
+        if let Some(error) = self.error.as_deref() {
+            Paragraph::new(error).bold().red().render(layout[0], buf);
+        }
 
-                    row
-                })
-                .collect::<Vec<_>>(),
+        render_table(
+            ui,
+            &TableView::input_readouts(self),
             vec![
                 Constraint::Length(1),
                 Constraint::Percentage(20),
                 Constraint::Percentage(80),
             ],
-        )
-        .header(Row::new::<Vec<Cell>>(vec!["".into(), "Name".into(), "Value".into()]).gray())
-        .render(layout[2], buf);
+            layout[1],
+            layout[2],
+            buf,
+        );
     }
 }
 
-impl Provision {
-    fn mark_available(mut row: Row<'_>, status: Option<ProvisioningStatus>) -> Row<'_> {
-        if let Some(status) = status {
-            row = row.bold();
+impl Draw for PortPick {
+    fn draw(&self, ui: Ui, area: Rect, buf: &mut Buffer) {
+        render_main(
+            ui,
+            Some(ui.tr(" Serial Port ").bold()),
+            Keys::INPUT | Keys::QUIT,
+            area,
+            buf,
+        );
 
-            row = match status {
-                ProvisioningStatus::NotStarted | ProvisioningStatus::Pending => row.white(),
-                ProvisioningStatus::InProgress(_) => row.yellow(),
-                ProvisioningStatus::Done => row.green(),
-            };
-        } else {
-            row = row.italic().black();
-        }
+        let layout = Layout::new(
+            Direction::Vertical,
+            [
+                Constraint::Min(1),
+                Constraint::Min(1),
+                Constraint::Min((self.ports.len() + 1) as _),
+                Constraint::Min(1),
+                Constraint::Min(1),
+                Constraint::Percentage(100),
+            ],
+        )
+        .split(area.inner(Margin::new(2, 2)));
 
-        row
-    }
+        render_table(
+            ui,
+            &TableView::ports(self),
+            vec![
+                Constraint::Length(3),
+                Constraint::Percentage(20),
+                Constraint::Length(10),
+                Constraint::Percentage(40),
+                Constraint::Percentage(40),
+            ],
+            layout[1],
+            layout[2],
+            buf,
+        );
 
-    fn active_string(status: Option<ProvisioningStatus>) -> String {
-        if status
-            .map(|status| matches!(status, ProvisioningStatus::InProgress(_)))
-            .unwrap_or(false)
-        {
-            ">"
-        } else {
-            ""
-        }
-        .into()
+        Line::from(vec![
+            ui.tr("Port number: ").bold(),
+            format!("{}_", self.selection)
+                .fg(ui.theme.keys_color)
+                .bold(),
+        ])
+        .render(layout[4], buf);
     }
+}
 
-    fn status_string(status: Option<ProvisioningStatus>) -> String {
-        match status {
-            Some(ProvisioningStatus::NotStarted) => "Not Started".into(),
-            Some(ProvisioningStatus::Pending) => "Pending".into(),
-            Some(ProvisioningStatus::InProgress(progress)) => {
-                if let Some(progress) = progress {
-                    format!("{}%", progress)
-                } else {
-                    "In Progress".into()
-                }
-            }
-            Some(ProvisioningStatus::Done) => "Done".into(),
-            None => "-".into(),
-        }
+impl Draw for PartTablePick {
+    fn draw(&self, ui: Ui, area: Rect, buf: &mut Buffer) {
+        render_main(
+            ui,
+            Some(ui.tr(" Partition Table ").bold()),
+            Keys::INPUT | Keys::QUIT,
+            area,
+            buf,
+        );
+
+        let layout = Layout::new(
+            Direction::Vertical,
+            [
+                Constraint::Min(1),
+                Constraint::Min(1),
+                Constraint::Min((self.variants.len() + 1) as _),
+                Constraint::Min(1),
+                Constraint::Min(1),
+                Constraint::Percentage(100),
+            ],
+        )
+        .split(area.inner(Margin::new(2, 2)));
+
+        Paragraph::new(self.problem.as_str())
+            .bold()
+            .red()
+            .render(layout[0], buf);
+
+        render_table(
+            ui,
+            &TableView::part_tables(self),
+            vec![
+                Constraint::Length(3),
+                Constraint::Percentage(20),
+                Constraint::Length(10),
+                Constraint::Length(5),
+                Constraint::Percentage(80),
+            ],
+            layout[1],
+            layout[2],
+            buf,
+        );
+
+        Line::from(vec![
+            ui.tr("Partition table number: ").bold(),
+            format!("{}_", self.selection)
+                .fg(ui.theme.keys_color)
+                .bold(),
+        ])
+        .render(layout[4], buf);
     }
 }
 
-impl Widget for &Provision {
-    fn render(self, area: Rect, buf: &mut Buffer) {
+impl Draw for Provision {
+    fn draw(&self, ui: Ui, area: Rect, buf: &mut Buffer) {
         render_main(
+            ui,
             Some(Line::from(vec![
                 " ".into(),
-                "Bundle ".bold(),
+                ui.tr("Bundle ").bold(),
                 self.bundle.name.as_str().bold(),
                 " ".into(),
             ])),
@@ -202,109 +441,9 @@ impl Widget for &Provision {
         )
         .split(area.inner(Margin::new(2, 2)));
 
-        Paragraph::new("== Partitions")
-            .bold()
-            .render(layout[0], buf);
-
-        Table::new(
-            self.bundle.parts_mapping.iter().map(|mapping| {
-                let row = if let Some(partition) = mapping.partition.as_ref() {
-                    Row::new::<Vec<Cell>>(vec![
-                        Provision::active_string(mapping.status()).into(),
-                        partition.name().into(),
-                        if matches!(
-                            partition.name().as_str(),
-                            Bundle::BOOTLOADER_NAME | Bundle::PART_TABLE_NAME
-                        ) {
-                            "".into()
-                        } else {
-                            partition.ty().to_string().into()
-                        },
-                        if matches!(
-                            partition.name().as_str(),
-                            Bundle::BOOTLOADER_NAME | Bundle::PART_TABLE_NAME
-                        ) {
-                            "".into()
-                        } else {
-                            partition.subtype().to_string().into()
-                        },
-                        Text::raw(format!("0x{:06x}", partition.offset()))
-                            .right_aligned()
-                            .into(),
-                        Text::raw(format!(
-                            "{}KB (0x{:06x})",
-                            partition.size() / 1024
-                                + if partition.size() % 1024 > 0 { 1 } else { 0 },
-                            partition.size()
-                        ))
-                        .right_aligned()
-                        .into(),
-                        {
-                            let str = partition
-                                .encrypted()
-                                .then_some("Encr")
-                                .into_iter()
-                                .chain(
-                                    mapping
-                                        .image
-                                        .as_ref()
-                                        .map(|image| matches!(image.ty, ImageType::Empty))
-                                        .unwrap_or(false)
-                                        .then_some("Empty")
-                                        .into_iter(),
-                                )
-                                .collect::<Vec<_>>()
-                                .join(", ");
-
-                            if str.is_empty() { "-".into() } else { str }.into()
-                        },
-                        Text::raw(
-                            mapping
-                                .image
-                                .as_ref()
-                                .map(|image| {
-                                    format!(
-                                        "{}KB (0x{:06x})",
-                                        image.data.len() / 1024
-                                            + if image.data.len() % 1024 > 0 { 1 } else { 0 },
-                                        image.data.len()
-                                    )
-                                })
-                                .unwrap_or("-".to_string()),
-                        )
-                        .right_aligned()
-                        .into(),
-                        Text::raw(Provision::status_string(mapping.status()))
-                            .right_aligned()
-                            .into(),
-                    ])
-                } else {
-                    let image = mapping.image.as_ref().unwrap();
-
-                    Row::new::<Vec<Cell>>(vec![
-                        Provision::active_string(mapping.status()).into(),
-                        format!("???{}", image.name).into(),
-                        "".into(),
-                        "".into(),
-                        "".into(),
-                        "".into(),
-                        "".into(),
-                        Text::raw(format!(
-                            "{}KB (0x{:06x})",
-                            image.data.len() / 1024
-                                + if image.data.len() % 1024 > 0 { 1 } else { 0 },
-                            image.data.len()
-                        ))
-                        .right_aligned()
-                        .into(),
-                        Text::raw(Provision::status_string(mapping.status()))
-                            .right_aligned()
-                            .into(),
-                    ])
-                };
-
-                Provision::mark_available(row, mapping.status())
-            }),
+        render_table(
+            ui,
+            &TableView::partitions(&self.bundle),
             vec![
                 Constraint::Length(1),
                 Constraint::Length(15),
@@ -316,114 +455,152 @@ impl Widget for &Provision {
                 Constraint::Length(17),
                 Constraint::Length(11),
             ],
-        )
-        .header(
-            Row::new::<Vec<Cell>>(vec![
-                "".into(),
-                "Name".into(),
-                "Type".into(),
-                "Subtype".into(),
-                Text::raw("Offset").right_aligned().into(),
-                Text::raw("Size").right_aligned().into(),
-                "Flags".into(),
-                Text::raw("Image").right_aligned().into(),
-                Text::raw("Provision").right_aligned().into(),
-            ])
-            .gray(),
-        )
-        .render(layout[1], buf);
+            layout[0],
+            layout[1],
+            buf,
+        );
 
         if !self.bundle.efuse_mapping.is_empty() {
-            Paragraph::new("== EFUSE").bold().render(layout[3], buf);
-
-            Table::new(
-                self.bundle.efuse_mapping.iter().map(|mapping| {
-                    let row = Row::new::<Vec<Cell>>(vec![
-                        Provision::active_string(Some(mapping.status)).into(),
-                        mapping.efuse.name().into(),
-                        match &mapping.efuse {
-                            Efuse::Param { .. } => "Param".into(),
-                            Efuse::Key { .. } => "Key".into(),
-                            Efuse::KeyDigest { .. } => "Digest".into(),
-                        },
-                        match &mapping.efuse {
-                            Efuse::Param { .. } => "-".into(),
-                            Efuse::Key { purpose, .. } | Efuse::KeyDigest { purpose, .. } => {
-                                purpose.clone().into()
-                            }
-                        },
-                        Text::raw(match &mapping.efuse {
-                            Efuse::Param { value, .. } => format!("0x{:08x}", value),
-                            Efuse::Key {
-                                key_value: value, ..
-                            }
-                            | Efuse::KeyDigest {
-                                digest_value: value,
-                                ..
-                            } => format!("({}B)", value.len()),
-                        })
-                        .right_aligned()
-                        .into(),
-                        Text::raw(Provision::status_string(Some(mapping.status)))
-                            .right_aligned()
-                            .into(),
-                    ]);
-
-                    Provision::mark_available(row, Some(mapping.status))
-                }),
+            render_table(
+                ui,
+                &TableView::efuses(&self.bundle),
                 vec![
                     Constraint::Length(1),
                     Constraint::Min(20),
                     Constraint::Length(7),
                     Constraint::Min(20),
                     Constraint::Min(20),
+                    Constraint::Min(30),
                     Constraint::Length(11),
                 ],
-            )
-            .header(
-                Row::new::<Vec<Cell>>(vec![
-                    "".into(),
-                    "Name".into(),
-                    "Type".into(),
-                    "Purpose".into(),
-                    Text::raw("Value").right_aligned().into(),
-                    Text::raw("Provision").right_aligned().into(),
-                ])
-                .gray(),
-            )
-            .render(layout[4], buf);
-
-            Paragraph::new("== Readouts (manual and eFuse)")
-                .bold()
-                .render(layout[6], buf);
-
-            Table::new(
-                self.readouts
-                    .iter()
-                    .map(|(name, value)| {
-                        Row::new::<Vec<Cell>>(vec![
-                            "".into(),
-                            name.as_str().into(),
-                            value.as_str().into(),
-                        ])
-                    })
-                    .collect::<Vec<_>>(),
+                layout[3],
+                layout[4],
+                buf,
+            );
+
+            render_table(
+                ui,
+                &TableView::readouts(&self.readouts),
                 vec![
                     Constraint::Length(1),
                     Constraint::Percentage(20),
                     Constraint::Percentage(80),
                 ],
-            )
-            .header(Row::new::<Vec<Cell>>(vec!["".into(), "Name".into(), "Value".into()]).gray())
-            .render(layout[7], buf);
+                layout[6],
+                layout[7],
+                buf,
+            );
         }
     }
 }
 
-impl Widget for &Processing {
-    fn render(self, area: Rect, buf: &mut Buffer) {
+impl Draw for Preview {
+    fn draw(&self, ui: Ui, area: Rect, buf: &mut Buffer) {
+        let bundle = &self.provision.bundle;
+
+        render_main(
+            ui,
+            Some(Line::from(vec![
+                " ".into(),
+                ui.tr("Preview of bundle ").bold(),
+                bundle.name.as_str().bold(),
+                " ".into(),
+            ])),
+            Keys::CONFIRM | Keys::BACK | Keys::QUIT,
+            area,
+            buf,
+        );
+
+        let layout = Layout::new(
+            Direction::Vertical,
+            [
+                Constraint::Min(1),
+                Constraint::Min((self.details.len() + 1) as _),
+                Constraint::Min(1),
+                Constraint::Min(1),
+                Constraint::Min((bundle.parts_mapping.len() + 1) as _),
+                Constraint::Min(1),
+                Constraint::Min(1),
+                Constraint::Min((bundle.efuse_mapping.len() + 1) as _),
+                Constraint::Min(1),
+                Constraint::Min(1),
+                Constraint::Min((self.provision.readouts.len() + 1) as _),
+                Constraint::Percentage(100),
+            ],
+        )
+        .split(area.inner(Margin::new(2, 2)));
+
+        render_table(
+            ui,
+            &TableView::details(&self.details),
+            vec![
+                Constraint::Length(1),
+                Constraint::Percentage(20),
+                Constraint::Percentage(80),
+            ],
+            layout[0],
+            layout[1],
+            buf,
+        );
+
+        render_table(
+            ui,
+            &TableView::partitions(bundle),
+            vec![
+                Constraint::Length(1),
+                Constraint::Length(15),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(10),
+                Constraint::Length(17),
+                Constraint::Length(18),
+                Constraint::Length(17),
+                Constraint::Length(11),
+            ],
+            layout[3],
+            layout[4],
+            buf,
+        );
+
+        if !bundle.efuse_mapping.is_empty() {
+            render_table(
+                ui,
+                &TableView::efuses(bundle),
+                vec![
+                    Constraint::Length(1),
+                    Constraint::Min(20),
+                    Constraint::Length(7),
+                    Constraint::Min(20),
+                    Constraint::Min(20),
+                    Constraint::Min(30),
+                    Constraint::Length(11),
+                ],
+                layout[6],
+                layout[7],
+                buf,
+            );
+        }
+
+        render_table(
+            ui,
+            &TableView::readouts(&self.provision.readouts),
+            vec![
+                Constraint::Length(1),
+                Constraint::Percentage(20),
+                Constraint::Percentage(80),
+            ],
+            layout[9],
+            layout[10],
+            buf,
+        );
+    }
+}
+
+impl Draw for Processing {
+    fn draw(&self, ui: Ui, area: Rect, buf: &mut Buffer) {
         render_main(
-            Some(self.title.clone().bold()),
+            ui,
+            Some(ui.tr(&self.title).bold()),
             Keys::BACK | Keys::QUIT,
             area,
             buf,
@@ -434,9 +611,9 @@ impl Widget for &Processing {
         let counter_text = Text::from(format!(
             "{}... {}",
             if self.status.is_empty() {
-                "Preparing".into()
+                ui.tr("Preparing")
             } else {
-                self.status.clone()
+                ui.tr(&self.status)
             },
             PROGRESS[self.counter.0 % 4]
         ))
@@ -448,10 +625,11 @@ impl Widget for &Processing {
     }
 }
 
-impl Widget for &AppLogs {
-    fn render(self, area: Rect, buf: &mut Buffer) {
+impl Draw for AppLogs {
+    fn draw(&self, ui: Ui, area: Rect, buf: &mut Buffer) {
         render_main(
-            Some(" Run App ".to_owned().bold()),
+            ui,
+            Some(ui.tr(" Run App ").bold()),
             Keys::empty(),
             area,
             buf,
@@ -472,10 +650,11 @@ impl Widget for &AppLogs {
     }
 }
 
-impl Widget for &Status {
-    fn render(self, area: Rect, buf: &mut Buffer) {
+impl Draw for Status {
+    fn draw(&self, ui: Ui, area: Rect, buf: &mut Buffer) {
         render_main(
-            Some(self.title.clone().bold()),
+            ui,
+            Some(ui.tr(&self.title).bold()),
             if self.error {
                 Keys::RETRY | Keys::BACK | Keys::QUIT
             } else {
@@ -493,7 +672,110 @@ impl Widget for &Status {
             para = para.yellow();
         }
 
-        para.render(area.inner(Margin::new(1, 1)), buf);
+        match self.highlight {
+            Some(Highlight::Warning) => para = para.yellow(),
+            Some(Highlight::Critical) => para = para.red(),
+            None => (),
+        }
+
+        // The line right below the top border is left for the provisioning cycle breadcrumb
+        let area = area.inner(Margin::new(1, 1));
+
+        para.render(
+            Rect {
+                y: area.y + 1,
+                height: area.height.saturating_sub(1),
+                ..area
+            },
+            buf,
+        );
+    }
+}
+
+/// A full-screen PASS (green) / FAIL (red) banner presenting a status together with the identity of the PCB
+struct ResultBanner<'a> {
+    status: &'a Status,
+    identity: &'a str,
+    ui: Ui<'a>,
+}
+
+impl Widget for ResultBanner<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let (result, keys) = if self.status.error {
+            ("FAIL", Keys::RETRY | Keys::BACK | Keys::QUIT)
+        } else {
+            ("PASS", Keys::CONFIRM | Keys::QUIT)
+        };
+
+        let mut block = Block::bordered().title_top(self.status.title.clone().bold().centered());
+
+        if let Some(instructions) = keys.instructions(self.ui) {
+            block = block.title_bottom(instructions.right_aligned());
+        }
+
+        let block = if self.status.error {
+            block.on_red().white()
+        } else {
+            block.on_green().black()
+        };
+
+        let inner = block.inner(area);
+        block.render(area, buf);
+
+        let fits = |text: &str, scale, height| {
+            let (text_width, text_height) = font::size(text, scale);
+            text_width <= inner.width && text_height <= height
+        };
+
+        let mut lines = Vec::new();
+
+        // The result takes up to half of the screen, the identity is rendered in large text only if it fits
+        if let Some(scale) = (1..=4)
+            .rev()
+            .find(|scale| fits(result, *scale, inner.height / 2))
+        {
+            lines.extend(font::lines(result, scale).into_iter().map(Line::raw));
+        } else {
+            lines.push(Line::raw(result));
+        }
+
+        lines.push(Line::raw(""));
+
+        if fits(self.identity, 1, inner.height / 4) {
+            lines.extend(font::lines(self.identity, 1).into_iter().map(Line::raw));
+        } else {
+            lines.push(Line::raw(self.identity));
+        }
+
+        lines.push(Line::raw(""));
+
+        let layout = Layout::new(
+            Direction::Vertical,
+            [
+                Constraint::Length(lines.len() as _),
+                Constraint::Percentage(100),
+            ],
+        )
+        .split(inner);
+
+        Paragraph::new(Text::from(lines))
+            .bold()
+            .centered()
+            .render(layout[0], buf);
+
+        let mut para = Paragraph::new(self.status.message.clone())
+            .bold()
+            .wrap(Wrap { trim: false });
+
+        // The critical highlight must stand out on the red FAIL banner as well
+        match self.status.highlight {
+            Some(Highlight::Warning) => para = para.black().on_yellow(),
+            Some(Highlight::Critical) if self.status.error => para = para.black().on_white(),
+            Some(Highlight::Critical) => para = para.white().on_red(),
+            None => (),
+        }
+
+        para.render(layout[1].inner(Margin::new(1, 0)), buf);
     }
 }
 
@@ -515,8 +797,14 @@ impl Widget for &BufferedLogs {
             Line::from(vec![
                 "Navigate ".into(),
                 "<Arrows/Page/Home/End Keys>".yellow().bold(),
+                " Search ".into(),
+                "</ N Shift-N>".yellow().bold(),
+                " Warnings ".into(),
+                "<F>".yellow().bold(),
                 " Wrap ".into(),
                 "<Alt-W>".yellow().bold(),
+                " Save ".into(),
+                "<Alt-S>".yellow().bold(),
                 " Main ".into(),
                 "<Alt-L> ".yellow().bold(),
             ])
@@ -525,6 +813,37 @@ impl Widget for &BufferedLogs {
             .right_aligned()
             .render(layout[0], buf);
 
+            let mut status = Vec::new();
+
+            if self.is_filter() {
+                status.push(" WARN/ERROR only ".white().on_red().bold());
+            }
+
+            if let Some(search) = self.search() {
+                if search.editing {
+                    status.push(format!(" /{}_ ", search.pattern).white().on_blue().bold());
+                } else {
+                    let (current, total) = self.search_matches();
+
+                    status.push(
+                        format!(
+                            " /{} [{}/{total}] ",
+                            search.pattern,
+                            current
+                                .map(|current| current.to_string())
+                                .unwrap_or("-".into())
+                        )
+                        .white()
+                        .on_blue()
+                        .bold(),
+                    );
+                }
+            }
+
+            if !status.is_empty() {
+                Line::from(status).render(layout[0], buf);
+            }
+
             self.para(true, layout[1].height).render(layout[1], buf);
         } else {
             self.para(true, area.height).render(area, buf);
@@ -532,23 +851,105 @@ impl Widget for &BufferedLogs {
     }
 }
 
-fn render_main<'a>(title: Option<impl Into<Line<'a>>>, keys: Keys, area: Rect, buf: &mut Buffer) {
+/// Render a presentation table with its title, translated to the UI language
+fn render_table(
+    ui: Ui,
+    table: &TableView,
+    widths: Vec<Constraint>,
+    title_area: Rect,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    fn cell<'a>(text: impl Into<Cow<'a, str>>, align: Align) -> Cell<'a> {
+        match align {
+            Align::Left => Text::raw(text).into(),
+            Align::Right => Text::raw(text).right_aligned().into(),
+        }
+    }
+
+    Paragraph::new(format!("== {}", ui.tr(table.title)))
+        .bold()
+        .render(title_area, buf);
+
+    Table::new(
+        table.rows.iter().map(|table_row| {
+            let cells = table_row
+                .cells
+                .iter()
+                .zip(&table.columns)
+                .map(|(text, column)| cell(text.as_str(), column.align))
+                .collect::<Vec<_>>();
+
+            let row = Row::new(cells);
+
+            match table_row.emphasis {
+                Emphasis::Normal => row,
+                Emphasis::Active => row.bold(),
+                Emphasis::Status(status) => match status {
+                    ProvisioningStatus::NotStarted | ProvisioningStatus::Pending => {
+                        row.bold().white()
+                    }
+                    ProvisioningStatus::InProgress(_) => row.bold().yellow(),
+                    ProvisioningStatus::Done => row.bold().green(),
+                },
+                Emphasis::Unavailable => row.italic().black(),
+            }
+        }),
+        widths,
+    )
+    .header(
+        Row::new(
+            table
+                .columns
+                .iter()
+                .map(|column| cell(ui.tr(column.title), column.align))
+                .collect::<Vec<_>>(),
+        )
+        .gray(),
+    )
+    .render(area, buf);
+}
+
+fn render_main<'a>(
+    ui: Ui,
+    title: Option<impl Into<Line<'a>>>,
+    keys: Keys,
+    area: Rect,
+    buf: &mut Buffer,
+) {
+    let theme = ui.theme;
+
     let mut block = Block::bordered().title_top(
-        Line::from(" ESP32 Factory Provisioning ")
-            .bold()
-            .left_aligned()
-            .green(),
+        Line::from(format!(
+            " {} ",
+            theme.title.as_deref().unwrap_or(DEFAULT_TITLE)
+        ))
+        .bold()
+        .left_aligned()
+        .fg(theme.title_color),
     );
 
     if let Some(title) = title {
-        block = block.title_top(title.into().bold().centered().green());
+        block = block.title_top(title.into().bold().centered().fg(theme.title_color));
+    }
+
+    if let Some(banner) = theme.banner.as_deref() {
+        block = block.title_bottom(
+            Line::from(format!(" {banner} "))
+                .bold()
+                .left_aligned()
+                .fg(theme.title_color),
+        );
     }
 
-    if let Some(instructions) = keys.instructions() {
-        block = block.title_bottom(instructions.right_aligned().yellow());
+    if let Some(instructions) = keys.instructions(ui) {
+        block = block.title_bottom(instructions.right_aligned().fg(theme.keys_color));
     }
 
-    block.on_blue().white().render(area, buf);
+    block
+        .bg(theme.background)
+        .fg(theme.foreground)
+        .render(area, buf);
 }
 
 bitflags! {
@@ -564,41 +965,45 @@ bitflags! {
 
 impl Keys {
     /// Render the instructions for the keys to be displayed
-    fn instructions(&self) -> Option<Line<'static>> {
+    fn instructions(&self, ui: Ui) -> Option<Line<'static>> {
+        let keys_color = ui.theme.keys_color;
+
         (!self.is_empty()).then(|| {
             let mut instructions = Vec::new();
 
             if self.contains(Self::INPUT) {
-                instructions.push(" Readout ".into());
-                instructions.push("<chars> + <Enter>".yellow().bold());
+                instructions.push(ui.tr(" Readout ").into());
+                instructions.push("<chars> + <Enter>".fg(keys_color).bold());
             }
 
             if self.contains(Self::CONFIRM) {
-                instructions.push(" Continue ".into());
-                instructions.push("<Enter>".yellow().bold());
+                instructions.push(ui.tr(" Continue ").into());
+                instructions.push("<Enter>".fg(keys_color).bold());
             }
 
             if self.contains(Self::RETRY) {
-                instructions.push(" Re-try ".into());
-                instructions.push("<Enter>".yellow().bold());
+                instructions.push(ui.tr(" Re-try ").into());
+                instructions.push("<Enter>".fg(keys_color).bold());
             }
 
             if self.contains(Self::BACK) {
-                instructions.push(" Back ".into());
-                instructions.push("<Esc>".yellow().bold());
+                instructions.push(ui.tr(" Back ").into());
+                instructions.push("<Esc>".fg(keys_color).bold());
             }
 
             if self.contains(Self::RESET) {
-                instructions.push(" Reset ".into());
-                instructions.push("<Esc>".yellow().bold());
+                instructions.push(ui.tr(" Reset ").into());
+                instructions.push("<Esc>".fg(keys_color).bold());
             }
 
-            instructions.push(" Logs ".into());
-            instructions.push("<Alt-L>".yellow().bold());
+            instructions.push(ui.tr(" Logs ").into());
+            instructions.push("<Alt-L>".fg(keys_color).bold());
+            instructions.push(ui.tr(" Save Log ").into());
+            instructions.push("<Alt-S>".fg(keys_color).bold());
 
             if self.contains(Self::QUIT) {
-                instructions.push(" Quit ".into());
-                instructions.push("<Alt-Q>".yellow().bold());
+                instructions.push(ui.tr(" Quit ").into());
+                instructions.push("<Alt-Q>".fg(keys_color).bold());
             }
 
             instructions.push(" ".into());
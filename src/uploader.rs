@@ -5,10 +5,14 @@ use chrono::{SecondsFormat, Utc};
 
 use url::Url;
 
+use crate::auth::HttpAuth;
+use crate::Error;
+
 pub mod dir;
 pub mod http;
 #[cfg(feature = "s3")]
 pub mod s3;
+pub mod spool;
 
 /// A trait that uploads a bundle processing logs to a location
 pub trait BundleLogsUploader {
@@ -17,13 +21,19 @@ pub trait BundleLogsUploader {
         _read: R,
         _bundle_id: Option<&str>,
         _bundle_name: &str,
-    ) -> anyhow::Result<()>
+    ) -> Result<(), Error>
     where
         R: Read + Seek,
     {
         // Do nothing by default
         Ok(())
     }
+
+    /// Upload the logs whose upload failed previously (possibly in a previous run), if the uploader keeps such
+    async fn upload_pending(&mut self) -> Result<(), Error> {
+        // Do nothing by default
+        Ok(())
+    }
 }
 
 impl<T> BundleLogsUploader for &mut T
@@ -35,14 +45,21 @@ where
         read: R,
         bundle_id: Option<&str>,
         bundle_name: &str,
-    ) -> anyhow::Result<()>
+    ) -> Result<(), Error>
     where
         R: Read + Seek,
     {
         (*self).upload_logs(read, bundle_id, bundle_name).await
     }
+
+    async fn upload_pending(&mut self) -> Result<(), Error> {
+        (*self).upload_pending().await
+    }
 }
 
+/// An uploader which discards the logs
+impl BundleLogsUploader for () {}
+
 /// Wrapper enum for the loaders supported OOTB
 #[allow(clippy::large_enum_variant)]
 #[derive(Debug)]
@@ -57,14 +74,19 @@ pub enum LogsUploader {
 }
 
 impl LogsUploader {
-    pub fn new(url: &Url) -> anyhow::Result<Self> {
+    /// Create a logs uploader for the given URL
+    ///
+    /// # Arguments
+    /// - `url` - the URL of the logs destination; the scheme selects the uploader
+    /// - `http_auth` - the authorization of the HTTP(S) requests, if any
+    pub fn new(url: &Url, http_auth: Option<HttpAuth>) -> Result<Self, Error> {
         match url.scheme() {
             "dir" => Ok(Self::Dir(dir::DirLogsUploader::new(PathBuf::from(
                 url.path().to_string(),
             )))),
             "http" | "https" => Ok(Self::Http(http::HttpLogsUploader::new(
                 url.as_str().to_string(),
-                None,
+                http_auth,
                 true,
             ))),
             #[cfg(feature = "s3")]
@@ -78,7 +100,7 @@ impl LogsUploader {
 
                 Ok(Self::S3(s3::S3LogsUploader::new(None, bucket, path)))
             }
-            _ => anyhow::bail!("Unsupported logs upload URL: {url}"),
+            _ => Err(anyhow::anyhow!("Unsupported logs upload URL: {url}").into()),
         }
     }
 }
@@ -89,7 +111,7 @@ impl BundleLogsUploader for LogsUploader {
         read: R,
         bundle_id: Option<&str>,
         bundle_name: &str,
-    ) -> anyhow::Result<()>
+    ) -> Result<(), Error>
     where
         R: std::io::Read + std::io::Seek,
     {
@@ -114,20 +136,43 @@ where
         mut read: R,
         bundle_id: Option<&str>,
         bundle_name: &str,
-    ) -> anyhow::Result<()>
+    ) -> Result<(), Error>
     where
         R: std::io::Read + std::io::Seek,
     {
+        let mut result = Ok(());
+
+        // Upload to all destinations even if some fail, and report the first failure
         for uploader in self.0.iter_mut() {
             if let Err(err) = uploader
                 .upload_logs(&mut read, bundle_id, bundle_name)
                 .await
             {
                 log::error!("Error when uploading logs: {err}");
+
+                if result.is_ok() {
+                    result = Err(err);
+                }
             }
         }
 
-        Ok(())
+        result
+    }
+
+    async fn upload_pending(&mut self) -> Result<(), Error> {
+        let mut result = Ok(());
+
+        for uploader in self.0.iter_mut() {
+            if let Err(err) = uploader.upload_pending().await {
+                log::error!("Error when uploading pending logs: {err}");
+
+                if result.is_ok() {
+                    result = Err(err);
+                }
+            }
+        }
+
+        result
     }
 }
 
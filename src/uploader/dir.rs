@@ -7,6 +7,7 @@ use anyhow::Context;
 use log::info;
 
 use crate::uploader::log_name;
+use crate::Error;
 
 use super::BundleLogsUploader;
 
@@ -32,7 +33,7 @@ impl BundleLogsUploader for DirLogsUploader {
         mut read: R,
         bundle_id: Option<&str>,
         bundle_name: &str,
-    ) -> anyhow::Result<()>
+    ) -> Result<(), Error>
     where
         R: Read + Seek,
     {
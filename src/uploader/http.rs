@@ -4,7 +4,10 @@ use anyhow::Context;
 
 use log::info;
 
+use crate::auth::HttpAuth;
+
 use crate::uploader::log_name;
+use crate::Error;
 
 use super::BundleLogsUploader;
 
@@ -19,7 +22,7 @@ use super::BundleLogsUploader;
 pub struct HttpLogsUploader {
     logs_upload_url: String,
     name_as_log_file: bool,
-    auth: Option<String>,
+    auth: Option<HttpAuth>,
 }
 
 impl HttpLogsUploader {
@@ -27,13 +30,14 @@ impl HttpLogsUploader {
     ///
     /// # Arguments
     /// - `logs_upload_url`: The URL of the server to upload the logs to
-    /// - `auth`: An optional authorization token to use when uploading the logs
+    /// - `auth`: An optional authorization to use when uploading the logs
+    ///   (either a static `Authorization` header value, or an automatically renewed OAuth2 access token)
     /// - `name_as_log_file`: A flag indicating whether to upload the bundle logs with:
     ///   - `true`:  A simple parameter-less POST request of the form `<url>/<bundle-name>.log.zip`
     ///   - `false`: With a POST request with a parameter `<url>?id=<bundle-id>`
     pub const fn new(
         logs_upload_url: String,
-        auth: Option<String>,
+        auth: Option<HttpAuth>,
         name_as_log_file: bool,
     ) -> Self {
         Self {
@@ -50,7 +54,7 @@ impl BundleLogsUploader for HttpLogsUploader {
         mut read: R,
         bundle_id: Option<&str>,
         bundle_name: &str,
-    ) -> anyhow::Result<()>
+    ) -> Result<(), Error>
     where
         R: Read + Seek,
     {
@@ -86,8 +90,8 @@ impl BundleLogsUploader for HttpLogsUploader {
             format!("attachment; filename=\"{log_name}\""),
         );
 
-        if let Some(auth) = self.auth.as_deref() {
-            builder = builder.header("Authorization", auth);
+        if let Some(auth) = self.auth.as_ref() {
+            builder = builder.header("Authorization", auth.header().await?);
         }
 
         read.seek(io::SeekFrom::Start(0))
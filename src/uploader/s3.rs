@@ -10,6 +10,7 @@ use log::info;
 use tempfile::tempfile;
 
 use crate::uploader::log_name;
+use crate::Error;
 
 use super::BundleLogsUploader;
 
@@ -53,7 +54,7 @@ impl BundleLogsUploader for S3LogsUploader {
         mut read: R,
         bundle_id: Option<&str>,
         bundle_name: &str,
-    ) -> anyhow::Result<()>
+    ) -> Result<(), Error>
     where
         R: Read + Seek,
     {
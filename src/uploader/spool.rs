@@ -0,0 +1,274 @@
+use std::fs::{self, File};
+use std::io::{self, Read, Seek};
+use std::path::{Path, PathBuf};
+
+use anyhow::Context;
+
+use chrono::Utc;
+
+use log::{info, warn};
+
+use serde::{Deserialize, Serialize};
+
+use url::Url;
+
+use crate::utils::hash::sha256_hex;
+use crate::utils::private_dir;
+use crate::Error;
+
+use super::BundleLogsUploader;
+
+/// A logs uploader which persists the logs in a local spool directory before uploading them with another uploader.
+///
+/// Meant for flaky networks: if the upload fails, the logs are kept in the spool directory and the upload is retried
+/// with the next upload, as well as when the factory is started again (see `BundleLogsUploader::upload_pending`),
+/// so that no logs are lost.
+///
+/// The spool directory has the following layout:
+/// - `<timestamp>.json` - an entry with the Bundle ID and the bundle name of the logs
+/// - `<timestamp>.zip` - the logs
+///
+/// The pending logs are uploaded in the order they were spooled. Spooled logs which cannot be uploaded because their entry
+/// is corrupt or missing are quarantined under a `.corrupt` suffix, so that they do not block the upload of the other logs.
+///
+/// The spool directory is accessible by the current user only (see `utils::private_dir`).
+#[derive(Debug, Clone)]
+pub struct SpoolLogsUploader<T> {
+    uploader: T,
+    dir: Option<PathBuf>,
+}
+
+impl<T> SpoolLogsUploader<T> {
+    /// Creates a new `SpoolLogsUploader`
+    ///
+    /// Arguments
+    /// - `uploader`: The uploader used to upload the spooled logs
+    /// - `dir`: The spool directory; created (accessible by the current user only) if it does not exist. If not provided, the logs are not spooled
+    ///   and are uploaded directly with `uploader`
+    pub const fn new(uploader: T, dir: Option<PathBuf>) -> Self {
+        Self { uploader, dir }
+    }
+
+    /// Return the default root spool directory (`espfactory/spool` in the per-user cache directory of the host)
+    pub fn default_dir() -> PathBuf {
+        private_dir::user_dir("spool")
+    }
+
+    /// Return the spool directory of the logs destined to the given upload URL, inside the given root spool directory
+    ///
+    /// Each upload URL has its own spool directory, so that the logs are only re-uploaded to the destinations
+    /// where their upload failed
+    pub fn url_dir(root: &Path, url: &Url) -> PathBuf {
+        root.join(sha256_hex(url.as_str()))
+    }
+
+    /// Persist the logs in the spool directory
+    fn spool<R>(
+        dir: &Path,
+        mut read: R,
+        bundle_id: Option<&str>,
+        bundle_name: &str,
+    ) -> anyhow::Result<()>
+    where
+        R: Read + Seek,
+    {
+        private_dir::ensure(dir).context("Creating the logs spool directory failed")?;
+
+        let stem = Utc::now().format("%Y%m%dT%H%M%S%.6fZ").to_string();
+
+        read.seek(io::SeekFrom::Start(0))
+            .context("Spooling the bundle log failed")?;
+
+        // Write the logs under a temporary name first, so that a partially written log is never uploaded
+        let zip_path = dir.join(format!("{stem}.zip"));
+        let tmp_path = dir.join(format!("{stem}.zip.tmp"));
+
+        let mut file = File::create(&tmp_path).context("Spooling the bundle log failed")?;
+        io::copy(&mut read, &mut file).context("Spooling the bundle log failed")?;
+        file.sync_all().context("Spooling the bundle log failed")?;
+        drop(file);
+
+        fs::rename(&tmp_path, &zip_path).context("Spooling the bundle log failed")?;
+
+        let entry = SpoolEntry {
+            bundle_id: bundle_id.map(str::to_string),
+            bundle_name: bundle_name.to_string(),
+        };
+
+        fs::write(
+            dir.join(format!("{stem}.json")),
+            serde_json::to_string(&entry)?,
+        )
+        .context("Writing the logs spool entry failed")?;
+
+        info!("Logs spooled as `{}`", zip_path.display());
+
+        Ok(())
+    }
+
+    /// Return the stems of the spooled logs, oldest first
+    ///
+    /// Removes the partially written logs and quarantines the logs without an entry (i.e. the factory
+    /// was interrupted while spooling them)
+    fn pending(dir: &Path) -> anyhow::Result<Vec<String>> {
+        if !dir.exists() {
+            return Ok(Vec::new());
+        }
+
+        private_dir::ensure(dir).context("Securing the logs spool directory failed")?;
+
+        let names = fs::read_dir(dir)
+            .context("Reading the logs spool directory failed")?
+            .filter_map(|entry| entry.ok())
+            .filter_map(|entry| entry.file_name().to_str().map(str::to_string))
+            .collect::<Vec<_>>();
+
+        for name in &names {
+            if name.ends_with(".zip.tmp") {
+                let _ = fs::remove_file(dir.join(name));
+            } else if let Some(stem) = name.strip_suffix(".zip") {
+                if !dir.join(format!("{stem}.json")).exists() {
+                    Self::quarantine(dir, stem);
+                }
+            }
+        }
+
+        let mut stems = names
+            .iter()
+            .filter_map(|name| name.strip_suffix(".json"))
+            .map(str::to_string)
+            .collect::<Vec<_>>();
+
+        stems.sort();
+
+        Ok(stems)
+    }
+
+    /// Load the entry of the spooled logs and open the logs
+    fn open(dir: &Path, stem: &str) -> anyhow::Result<(SpoolEntry, File)> {
+        let entry_path = dir.join(format!("{stem}.json"));
+        let zip_path = dir.join(format!("{stem}.zip"));
+
+        let entry: SpoolEntry = serde_json::from_str(
+            &fs::read_to_string(&entry_path).context("Reading the logs spool entry failed")?,
+        )
+        .context("Parsing the logs spool entry failed")?;
+
+        let file = File::open(&zip_path)
+            .with_context(|| format!("Opening the spooled logs `{}` failed", zip_path.display()))?;
+
+        Ok((entry, file))
+    }
+
+    /// Move the spooled logs and their entry (whichever exist) out of the way, under a `.corrupt` suffix
+    fn quarantine(dir: &Path, stem: &str) {
+        for extension in ["json", "zip"] {
+            let path = dir.join(format!("{stem}.{extension}"));
+
+            if path.exists() {
+                let quarantined = dir.join(format!("{stem}.{extension}.corrupt"));
+
+                if let Err(err) = fs::rename(&path, &quarantined) {
+                    warn!(
+                        "Quarantining the spooled `{}` failed: {err}",
+                        path.display()
+                    );
+                } else {
+                    warn!("Quarantined the spooled `{}`", quarantined.display());
+                }
+            }
+        }
+    }
+}
+
+impl<T> BundleLogsUploader for SpoolLogsUploader<T>
+where
+    T: BundleLogsUploader,
+{
+    async fn upload_logs<R>(
+        &mut self,
+        read: R,
+        bundle_id: Option<&str>,
+        bundle_name: &str,
+    ) -> Result<(), Error>
+    where
+        R: Read + Seek,
+    {
+        let Some(dir) = self.dir.clone() else {
+            return self
+                .uploader
+                .upload_logs(read, bundle_id, bundle_name)
+                .await;
+        };
+
+        Self::spool(&dir, read, bundle_id, bundle_name)?;
+
+        self.upload_pending().await.with_context(|| {
+            format!(
+                "Uploading the logs failed, the logs are kept in the spool directory `{}` for a later retry",
+                dir.display()
+            )
+        })?;
+
+        Ok(())
+    }
+
+    async fn upload_pending(&mut self) -> Result<(), Error> {
+        let Some(dir) = self.dir.as_ref() else {
+            return Ok(());
+        };
+
+        let pending = Self::pending(dir)?;
+
+        if pending.is_empty() {
+            return Ok(());
+        }
+
+        info!(
+            "About to upload {} spooled logs from `{}`...",
+            pending.len(),
+            dir.display()
+        );
+
+        for (index, stem) in pending.iter().enumerate() {
+            let entry_path = dir.join(format!("{stem}.json"));
+            let zip_path = dir.join(format!("{stem}.zip"));
+
+            let (entry, file) = match Self::open(dir, stem) {
+                Ok(opened) => opened,
+                Err(err) => {
+                    warn!("Spooled logs `{stem}` are corrupt: {err:?}");
+                    Self::quarantine(dir, stem);
+                    continue;
+                }
+            };
+
+            self.uploader
+                .upload_logs(file, entry.bundle_id.as_deref(), &entry.bundle_name)
+                .await
+                .with_context(|| {
+                    format!(
+                        "Uploading the spooled logs `{}` failed, {} spooled logs pending",
+                        zip_path.display(),
+                        pending.len() - index
+                    )
+                })?;
+
+            fs::remove_file(&zip_path).context("Removing the spooled logs failed")?;
+            fs::remove_file(&entry_path).context("Removing the logs spool entry failed")?;
+        }
+
+        info!("All spooled logs uploaded");
+
+        Ok(())
+    }
+}
+
+/// An entry in the logs spool directory
+#[derive(Debug, Clone, Serialize, Deserialize)]
+struct SpoolEntry {
+    /// The Bundle ID of the logs, if any
+    bundle_id: Option<String>,
+    /// The name of the bundle of the logs
+    bundle_name: String,
+}
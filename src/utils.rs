@@ -3,4 +3,8 @@
 //! If you put code here, make sure to follow this convention.
 
 pub mod futures;
+pub mod hash;
+pub mod hex;
 pub mod linewrite;
+pub mod private_dir;
+pub mod secret;
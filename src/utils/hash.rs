@@ -0,0 +1,29 @@
+//! Hashing utilities
+
+use sha2::{Digest, Sha256};
+
+use super::hex;
+
+/// Return the SHA-256 hash of the given data, as a lowercase hex string
+pub fn sha256_hex(data: impl AsRef<[u8]>) -> String {
+    hex::encode(Sha256::digest(data.as_ref()))
+}
+
+/// The CRC32 variant used by the ESP ROM (`esp_rom_crc32_le`)
+pub fn crc32_le(init: u32, data: &[u8]) -> u32 {
+    let mut crc = !init;
+
+    for byte in data {
+        crc ^= *byte as u32;
+
+        for _ in 0..8 {
+            crc = if crc & 1 != 0 {
+                (crc >> 1) ^ 0xedb8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+
+    !crc
+}
@@ -0,0 +1,73 @@
+//! Hex encoding utilities
+
+use anyhow::Context;
+
+/// Encode the data as a lowercase hex string
+pub fn encode(data: impl AsRef<[u8]>) -> String {
+    encode_separated(data, "")
+}
+
+/// Encode the data as lowercase hex bytes separated with the given separator (e.g. `:` for MAC addresses)
+pub fn encode_separated(data: impl AsRef<[u8]>, separator: &str) -> String {
+    data.as_ref()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<Vec<_>>()
+        .join(separator)
+}
+
+/// Decode a hex string (e.g. `a001` or `A0 01`), ignoring any whitespace
+pub fn decode(hex: &str) -> anyhow::Result<Vec<u8>> {
+    let digits = hex
+        .chars()
+        .filter(|c| !c.is_whitespace())
+        .map(|c| c.to_digit(16))
+        .collect::<Option<Vec<_>>>()
+        .filter(|digits| digits.len() % 2 == 0)
+        .context("Invalid hex string")?;
+
+    Ok(digits
+        .chunks(2)
+        .map(|pair| ((pair[0] << 4) | pair[1]) as u8)
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn encode_vectors() {
+        assert_eq!(encode(b""), "");
+        assert_eq!(encode([0x00, 0x0f, 0xa0, 0xff]), "000fa0ff");
+        assert_eq!(
+            encode_separated([0x24, 0x0a, 0xc4, 0x00, 0x01, 0xfe], ":"),
+            "24:0a:c4:00:01:fe"
+        );
+    }
+
+    #[test]
+    fn decode_vectors() {
+        assert_eq!(decode("").unwrap(), Vec::<u8>::new());
+        assert_eq!(decode("000fa0ff").unwrap(), [0x00, 0x0f, 0xa0, 0xff]);
+        assert_eq!(decode("A0 01 01 A2").unwrap(), [0xa0, 0x01, 0x01, 0xa2]);
+        assert_eq!(decode(" a0\n01\t").unwrap(), [0xa0, 0x01]);
+    }
+
+    #[test]
+    fn decode_rejects_invalid() {
+        assert!(decode("abc").is_err());
+        assert!(decode("a 0 1").is_err());
+        assert!(decode("zz").is_err());
+        assert!(decode("+f").is_err());
+        assert!(decode("0x01").is_err());
+        assert!(decode("éé").is_err());
+    }
+
+    #[test]
+    fn round_trip() {
+        let data = (0..=255).collect::<Vec<u8>>();
+
+        assert_eq!(decode(&encode(&data)).unwrap(), data);
+    }
+}
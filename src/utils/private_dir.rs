@@ -0,0 +1,54 @@
+//! Per-user directories for the local state kept across factory runs (e.g. the bundle cache or the logs spool)
+//!
+//! The directories are accessible by the current user only, so that other users of the host
+//! cannot tamper with their content
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Return the directory `name` in the per-user cache directory of the host (e.g. `~/.cache/espfactory/<name>` on Linux),
+/// or in the temporary directory of the host if the host has no per-user cache directory
+pub fn user_dir(name: &str) -> PathBuf {
+    dirs::cache_dir()
+        .unwrap_or_else(std::env::temp_dir)
+        .join("espfactory")
+        .join(name)
+}
+
+/// Create the directory if it does not exist, so that it is accessible by the current user only
+///
+/// An existing directory which is accessible by other users is restricted to the current user,
+/// which fails if the directory is owned by another user
+pub fn ensure(path: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::{DirBuilderExt, PermissionsExt};
+
+        fs::DirBuilder::new()
+            .recursive(true)
+            .mode(0o700)
+            .create(path)?;
+
+        let metadata = fs::symlink_metadata(path)?;
+
+        if !metadata.is_dir() {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!("`{}` is not a directory", path.display()),
+            ));
+        }
+
+        if metadata.permissions().mode() & 0o077 != 0 {
+            fs::set_permissions(path, fs::Permissions::from_mode(0o700))?;
+        }
+
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    {
+        // The per-user directories on other hosts (e.g. `%LOCALAPPDATA%` on Windows) are private already
+        fs::create_dir_all(path)
+    }
+}
@@ -0,0 +1,134 @@
+//! Handling of secret key material (e.g. the flash encryption keys)
+//!
+//! Secrets are zeroized once dropped and are never printed. When they have to be handed over to a tool subprocess,
+//! they are written to files in RAM-backed storage where available (`/dev/shm` on Linux), which are only accessible
+//! by the owner, are overwritten before being removed, and have their paths redacted from the logs (see `redact`)
+
+use core::fmt::{self, Debug};
+use core::ops::Deref;
+
+use std::fs;
+use std::io::{Seek, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+
+use tempfile::NamedTempFile;
+
+use zeroize::Zeroizing;
+
+/// The paths of the existing secret files, redacted from the logs
+static FILES: Mutex<Vec<PathBuf>> = Mutex::new(Vec::new());
+
+/// Secret bytes, zeroized once dropped
+#[derive(Clone, Default, Eq, PartialEq)]
+pub struct Secret(Zeroizing<Vec<u8>>);
+
+impl Secret {
+    /// Create a secret, taking over the data
+    pub fn new(data: Vec<u8>) -> Self {
+        Self(Zeroizing::new(data))
+    }
+
+    /// Return the secret bytes
+    pub fn as_slice(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl Deref for Secret {
+    type Target = [u8];
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl AsRef<[u8]> for Secret {
+    fn as_ref(&self) -> &[u8] {
+        &self.0
+    }
+}
+
+impl From<Vec<u8>> for Secret {
+    fn from(data: Vec<u8>) -> Self {
+        Self::new(data)
+    }
+}
+
+impl Debug for Secret {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Secret(<redacted>)")
+    }
+}
+
+/// A temporary file with secret data, overwritten with zeros and removed once dropped
+pub struct SecretFile {
+    file: NamedTempFile,
+    len: usize,
+}
+
+impl SecretFile {
+    /// Create a temporary file for secret data in `dir()`, only accessible by the owner
+    pub fn create() -> std::io::Result<NamedTempFile> {
+        tempfile::Builder::new()
+            .prefix(".secret-")
+            .tempfile_in(dir())
+    }
+
+    /// Write the secret data into the temporary file (as created by `create`)
+    pub fn new(file: NamedTempFile, data: &[u8]) -> std::io::Result<Self> {
+        FILES.lock().unwrap().push(file.path().to_path_buf());
+
+        // Wrapped first, so that the file is overwritten and its path is forgotten even if writing fails
+        let mut secret_file = Self {
+            file,
+            len: data.len(),
+        };
+
+        let file = secret_file.file.as_file_mut();
+
+        file.write_all(data)?;
+        file.flush()?;
+
+        Ok(secret_file)
+    }
+
+    /// Return the path of the file
+    pub fn path(&self) -> &Path {
+        self.file.path()
+    }
+}
+
+impl Drop for SecretFile {
+    fn drop(&mut self) {
+        let file = self.file.as_file_mut();
+
+        // Best effort; the file is removed anyway
+        let _ = file
+            .rewind()
+            .and_then(|_| file.write_all(&vec![0; self.len]))
+            .and_then(|_| file.sync_all());
+
+        let path = self.file.path();
+        FILES.lock().unwrap().retain(|file| file != path);
+    }
+}
+
+/// Return the directory where the secret files are created: RAM-backed storage if available,
+/// so that the secrets never hit the disk, or else the temporary directory
+pub fn dir() -> PathBuf {
+    let shm = Path::new("/dev/shm");
+
+    if cfg!(target_os = "linux") && fs::metadata(shm).is_ok_and(|meta| meta.is_dir()) {
+        shm.to_path_buf()
+    } else {
+        std::env::temp_dir()
+    }
+}
+
+/// Replace the paths of the existing secret files in the text (e.g. a logged tool command) with `<secret>`
+pub fn redact(text: String) -> String {
+    FILES.lock().unwrap().iter().fold(text, |text, path| {
+        text.replace(path.to_string_lossy().as_ref(), "<secret>")
+    })
+}
@@ -0,0 +1,190 @@
+//! Work-order quota enforcement, so that no more PCBs are provisioned than ordered (e.g. by a purchase order)
+
+use std::fs;
+use std::time::Duration;
+
+use anyhow::Context;
+
+use log::info;
+
+use reqwest::StatusCode;
+
+use serde::{Deserialize, Serialize};
+
+use crate::model::WorkOrderProgress;
+use crate::WorkOrder;
+
+/// The content of the work-order counter file
+#[derive(Clone, Debug, Serialize, Deserialize)]
+struct Counter {
+    /// The ID of the work order the counter belongs to
+    id: String,
+    /// The number of PCBs provisioned against the work order so far
+    provisioned: u32,
+}
+
+/// Return the progress of the work order when the factory starts
+///
+/// The quantity is either the configured one (with the units provisioned in earlier sessions restored
+/// from the counter file, if any), or the remaining quantity as fetched from the server
+pub fn start(work_order: &WorkOrder) -> anyhow::Result<WorkOrderProgress> {
+    let (quantity, provisioned) = if let Some(url) = work_order.url.as_deref() {
+        (fetch_remaining(&work_order.id, url)?, 0)
+    } else {
+        let quantity = work_order.quantity.with_context(|| {
+            format!(
+                "Work order `{}` has neither a quantity nor a URL",
+                work_order.id
+            )
+        })?;
+
+        let provisioned = work_order
+            .counter_file
+            .as_deref()
+            .map(|counter_file| load(counter_file, &work_order.id))
+            .transpose()?
+            .unwrap_or(0);
+
+        (quantity, provisioned)
+    };
+
+    let progress = WorkOrderProgress {
+        id: work_order.id.clone(),
+        quantity,
+        provisioned,
+    };
+
+    info!(
+        "Work order `{}`: {} of {} units remaining",
+        progress.id,
+        progress.remaining(),
+        progress.quantity
+    );
+
+    Ok(progress)
+}
+
+/// Claim a unit of the work order on the server, before provisioning a PCB against it
+///
+/// Only relevant with `WorkOrder::url`, so that the stations sharing the work order cannot provision more PCBs
+/// than ordered. The server replies to the `POST` request either with the remaining quantity after the claim
+/// (as a plain number, or as a JSON object having a `remaining` field), or with `409 Conflict` or `410 Gone`
+/// if the work order is exhausted
+///
+/// Returns the remaining quantity after the claim, or `None` if the work order is exhausted
+pub fn claim(id: &str, url: &str) -> anyhow::Result<Option<u32>> {
+    let url = url.replace("${id}", id);
+
+    let response = reqwest::blocking::Client::new()
+        .post(&url)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .with_context(|| format!("Claiming a unit of work order `{id}` at `{url}` failed"))?;
+
+    if matches!(response.status(), StatusCode::CONFLICT | StatusCode::GONE) {
+        return Ok(None);
+    }
+
+    let response = response
+        .error_for_status()
+        .and_then(|response| response.text())
+        .with_context(|| format!("Claiming a unit of work order `{id}` at `{url}` failed"))?;
+
+    parse_remaining(id, &response).map(Some)
+}
+
+/// Release a unit of the work order claimed on the server (see `claim`) but not provisioned, with a `DELETE` request
+pub fn release(id: &str, url: &str) -> anyhow::Result<()> {
+    let url = url.replace("${id}", id);
+
+    reqwest::blocking::Client::new()
+        .delete(&url)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Releasing a unit of work order `{id}` at `{url}` failed"))?;
+
+    Ok(())
+}
+
+/// Persist the counter of the work order (if configured) after a successfully provisioned PCB was accounted
+/// in its progress
+pub fn account(work_order: &WorkOrder, progress: &WorkOrderProgress) -> anyhow::Result<()> {
+    if work_order.url.is_none() {
+        if let Some(counter_file) = work_order.counter_file.as_deref() {
+            let counter = Counter {
+                id: progress.id.clone(),
+                provisioned: progress.provisioned,
+            };
+
+            fs::write(counter_file, serde_json::to_string(&counter)?).with_context(|| {
+                format!("Writing the work order counter file `{counter_file}` failed")
+            })?;
+        }
+    }
+
+    info!(
+        "Work order `{}`: {} of {} units remaining",
+        progress.id,
+        progress.remaining(),
+        progress.quantity
+    );
+
+    Ok(())
+}
+
+/// Load the number of PCBs provisioned against the work order from the counter file
+///
+/// A missing counter file, or a counter file of another work order, means that nothing was provisioned yet
+fn load(counter_file: &str, id: &str) -> anyhow::Result<u32> {
+    let Ok(content) = fs::read_to_string(counter_file) else {
+        return Ok(0);
+    };
+
+    let counter: Counter = serde_json::from_str(&content)
+        .with_context(|| format!("Parsing the work order counter file `{counter_file}` failed"))?;
+
+    Ok(if counter.id == id {
+        counter.provisioned
+    } else {
+        0
+    })
+}
+
+/// Fetch the remaining quantity of the work order from the server
+///
+/// The server replies either with a plain number, or with a JSON object having a `remaining` field
+fn fetch_remaining(id: &str, url: &str) -> anyhow::Result<u32> {
+    let url = url.replace("${id}", id);
+
+    let response = reqwest::blocking::Client::new()
+        .get(&url)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .and_then(|response| response.error_for_status())
+        .with_context(|| format!("Fetching work order `{id}` from `{url}` failed"))?
+        .text()
+        .with_context(|| format!("Reading work order `{id}` from `{url}` failed"))?;
+
+    parse_remaining(id, &response)
+}
+
+/// Parse the remaining quantity of the work order from a plain number or a JSON object having a `remaining` field
+fn parse_remaining(id: &str, response: &str) -> anyhow::Result<u32> {
+    let response = response.trim();
+
+    if let Ok(remaining) = response.parse::<u32>() {
+        return Ok(remaining);
+    }
+
+    let value: serde_json::Value = serde_json::from_str(response)
+        .with_context(|| format!("Parsing work order `{id}` response `{response}` failed"))?;
+
+    value
+        .get("remaining")
+        .and_then(serde_json::Value::as_u64)
+        .map(|remaining| remaining as u32)
+        .with_context(|| {
+            format!("Work order `{id}` response `{response}` has no `remaining` field")
+        })
+}